@@ -19,6 +19,7 @@ from contextlib import asynccontextmanager
 
 from fastapi import FastAPI, HTTPException, BackgroundTasks, status, UploadFile, File, Form, Depends
 from fastapi.middleware.cors import CORSMiddleware
+from fastapi.middleware.gzip import GZipMiddleware
 from pydantic import BaseModel, Field, validator
 import uvicorn
 import logging
@@ -28,13 +29,29 @@ from runners.clickhouse_runner import ClickHouseRunner
 from schema_registry import SchemaRegistry, SchemaRegistryError, ProtocExecutionError
 from schema_translator import SchemaTranslator, SchemaValidationError
 from protobuf_ingester import ProtobufIngester, ProtobufDecodingError, ProtobufIngestionError
+from bigquery_sync import BigQueryMetadataSync, BigQuerySyncError
 
 # Configure logging
 logging.basicConfig(level=logging.INFO)
 logger = logging.getLogger(__name__)
 
-# Database for job history
-DB_PATH = "job_history.db"
+# Database for job history and the durable job queue
+DB_PATH = os.environ.get("JOB_HISTORY_DB", "job_history.db")
+
+# Times a job is started before an interrupted run is no longer retried
+MAX_JOB_ATTEMPTS = 3
+
+# Responses at least this large are gzip-compressed for clients sending
+# Accept-Encoding: gzip, which wide query results benefit from most
+GZIP_MIN_RESPONSE_BYTES = int(os.environ.get("GZIP_MIN_RESPONSE_BYTES", "1024"))
+
+# job_history columns beyond the original schema, added by init_database.
+# Formatted into ALTER TABLE statements, so keep this a fixed constant.
+JOB_QUEUE_COLUMNS = {
+    "estimated_slots": "INTEGER",
+    "max_execution_time": "INTEGER",
+    "attempts": "INTEGER DEFAULT 0",
+}
 
 # =============================================================================
 # Pydantic Models
@@ -238,6 +255,31 @@ class ProtobufIngestionResponse(BaseModel):
     errors: List[str] = Field(default_factory=list, description="List of processing errors")
     message: str = Field(..., description="Summary message")
 
+# BigQuery Metadata Sync Models
+class BigQuerySyncRequest(BaseModel):
+    """Request model for mirroring a BigQuery project's schemas locally"""
+    project_id: str = Field(..., description="BigQuery project to read")
+    datasets: Optional[List[str]] = Field(None, description="Datasets to mirror, all when omitted")
+    location: Optional[str] = Field(None, description="Location of the datasets, e.g. US")
+    engines: List[str] = Field(default=["duckdb"], description="Engines to create stub tables in")
+
+    @validator('engines')
+    def validate_engines(cls, v):
+        valid_engines = {'duckdb', 'clickhouse'}
+        for engine in v:
+            if engine not in valid_engines:
+                raise ValueError(f'Invalid engine: {engine}. Valid engines: {valid_engines}')
+        return v
+
+class BigQuerySyncResponse(BaseModel):
+    """Response model for a BigQuery metadata sync"""
+    project_id: str = Field(..., description="BigQuery project read")
+    datasets: List[str] = Field(..., description="Datasets mirrored")
+    tables_synced: int = Field(..., description="Tables registered and created in every engine")
+    tables: List[Dict[str, Any]] = Field(default_factory=list, description="Results per table")
+    errors: Dict[str, str] = Field(default_factory=dict, description="Errors per dataset")
+    message: str = Field(..., description="Summary message")
+
 # =============================================================================
 # Global State and Dependency Injection
 # =============================================================================
@@ -323,6 +365,14 @@ def init_database():
         )
     ''')
     
+    # Columns added for the durable queue, missing from older databases.
+    # SQLite can't bind identifiers as parameters, so the statement is
+    # formatted; names and types only ever come from JOB_QUEUE_COLUMNS.
+    existing = {row[1] for row in cursor.execute("PRAGMA table_info(job_history)")}
+    for column, column_type in JOB_QUEUE_COLUMNS.items():
+        if column not in existing:
+            cursor.execute(f"ALTER TABLE job_history ADD COLUMN {column} {column_type}")
+    
     conn.commit()
     conn.close()
 
@@ -333,8 +383,9 @@ def save_job_to_db(job_data):
     
     cursor.execute('''
         INSERT OR REPLACE INTO job_history 
-        (job_id, sql, engine, status, priority, created_at, started_at, completed_at, execution_time, error, result_summary)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        (job_id, sql, engine, status, priority, created_at, started_at, completed_at, execution_time, error, result_summary,
+         estimated_slots, max_execution_time, attempts)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
     ''', (
         job_data['job_id'],
         job_data['sql'],
@@ -346,30 +397,84 @@ def save_job_to_db(job_data):
         job_data.get('completed_at'),
         job_data.get('execution_time'),
         job_data.get('error'),
-        json.dumps(job_data.get('result_summary'))
+        json.dumps(job_data.get('result_summary')),
+        job_data.get('estimated_slots'),
+        job_data.get('max_execution_time'),
+        job_data.get('attempts', 0)
     ))
     
     conn.commit()
     conn.close()
 
-def get_job_history(limit=50):
+def _job_from_row(row):
+    """Turn a job_history row into a job dict"""
+    job = dict(row)
+    job['result_summary'] = json.loads(job['result_summary']) if job.get('result_summary') else None
+    job['attempts'] = job.get('attempts') or 0
+    return job
+
+def load_job_from_db(job_id):
+    """Get one job from the database, or None"""
+    conn = sqlite3.connect(DB_PATH)
+    conn.row_factory = sqlite3.Row
+    row = conn.execute("SELECT * FROM job_history WHERE job_id = ?", (job_id,)).fetchone()
+    conn.close()
+    return _job_from_row(row) if row else None
+
+def get_job_history(limit=50, status=None):
     """Get job history from database"""
     conn = sqlite3.connect(DB_PATH)
-    cursor = conn.cursor()
+    conn.row_factory = sqlite3.Row
     
-    cursor.execute('''
-        SELECT * FROM job_history 
-        ORDER BY created_at DESC 
-        LIMIT ?
-    ''', (limit,))
+    if status is None:
+        rows = conn.execute('''
+            SELECT * FROM job_history 
+            ORDER BY created_at DESC 
+            LIMIT ?
+        ''', (limit,)).fetchall()
+    else:
+        rows = conn.execute('''
+            SELECT * FROM job_history 
+            WHERE status = ?
+            ORDER BY created_at DESC 
+            LIMIT ?
+        ''', (status, limit)).fetchall()
+    conn.close()
+    
+    return [_job_from_row(row) for row in rows]
+
+def restore_job_queue():
+    """Requeue the jobs a previous run of the backend left unfinished
     
-    rows = cursor.fetchall()
+    Queued jobs go back in the queue as they were. Jobs that were running
+    when the backend stopped can't be resumed mid-query, so they run again
+    from the start, unless they were already started MAX_JOB_ATTEMPTS times,
+    which usually means the query itself brings the backend down.
+    """
+    conn = sqlite3.connect(DB_PATH)
+    conn.row_factory = sqlite3.Row
+    rows = conn.execute(
+        "SELECT * FROM job_history WHERE status IN ('queued', 'running') ORDER BY created_at"
+    ).fetchall()
     conn.close()
     
-    columns = ['job_id', 'sql', 'engine', 'status', 'priority', 'created_at', 
-               'started_at', 'completed_at', 'execution_time', 'error', 'result_summary']
+    requeued = 0
+    for job in map(_job_from_row, rows):
+        if job['status'] == 'running':
+            if job['attempts'] >= MAX_JOB_ATTEMPTS:
+                job['status'] = 'failed'
+                job['completed_at'] = datetime.now().isoformat()
+                job['error'] = f"Interrupted by a backend restart {job['attempts']} times, not retried"
+                save_job_to_db(job)
+                continue
+            job['status'] = 'queued'
+            job['started_at'] = None
+            save_job_to_db(job)
+        job_queue.append(job)
+        requeued += 1
     
-    return [dict(zip(columns, row)) for row in rows]
+    job_queue.sort(key=lambda x: (x['priority'], x['created_at']))
+    return requeued
 
 @asynccontextmanager
 async def lifespan(app: FastAPI):
@@ -382,6 +487,11 @@ async def lifespan(app: FastAPI):
     # Initialize database for job history
     init_database()
     
+    # Pick up jobs left unfinished by the previous run
+    requeued = restore_job_queue()
+    if requeued:
+        print(f"✅ Restored {requeued} unfinished job(s) from {DB_PATH}")
+    
     # Initialize schema management components
     try:
         schema_registry = SchemaRegistry()
@@ -435,6 +545,9 @@ app.add_middleware(
     allow_headers=["*"],
 )
 
+# Compress large responses such as query results
+app.add_middleware(GZipMiddleware, minimum_size=GZIP_MIN_RESPONSE_BYTES)
+
 async def process_job_queue():
     """Background task to process queued jobs"""
     while True:
@@ -449,6 +562,8 @@ async def process_job_queue():
             running_jobs[job['job_id']] = job
             job['status'] = 'running'
             job['started_at'] = datetime.now().isoformat()
+            job['attempts'] = job.get('attempts', 0) + 1
+            save_job_to_db(job)
             
             # Execute job in background
             asyncio.create_task(execute_job(job))
@@ -471,6 +586,10 @@ async def execute_job(job):
         job['status'] = 'completed'
         job['completed_at'] = datetime.now().isoformat()
         job['result'] = result
+        job['result_summary'] = {
+            "rows": result.get('rows', len(result.get('data', []))),
+            "columns": list(result['data'][0].keys()) if result.get('data') else []
+        }
         
         # Calculate execution time
         start_time = datetime.fromisoformat(job['started_at'])
@@ -545,9 +664,12 @@ async def submit_query(query_request: QueryRequest):
         'max_execution_time': query_request.max_execution_time
     }
     
+    # Persist before queueing so the job survives a restart
+    save_job_to_db(job)
+    
     # Add to queue (sort by priority)
     job_queue.append(job)
-    job_queue.sort(key=lambda x: x['priority'])
+    job_queue.sort(key=lambda x: (x['priority'], x['created_at']))
     
     return QueryResponse(
         job_id=job_id,
@@ -565,9 +687,9 @@ async def get_job_status(job_id: str):
     # Check completed jobs
     elif job_id in completed_jobs:
         job = completed_jobs[job_id]
-    # Check queued jobs
+    # Check queued jobs, then jobs of earlier runs
     else:
-        job = next((j for j in job_queue if j['job_id'] == job_id), None)
+        job = next((j for j in job_queue if j['job_id'] == job_id), None) or load_job_from_db(job_id)
         if not job:
             raise HTTPException(status_code=404, detail=f"Job {job_id} not found")
     
@@ -596,8 +718,24 @@ async def get_job_result(job_id: str):
                 status_code=202, 
                 detail=f"Job {job_id} is not yet completed"
             )
-        else:
+        
+        # Jobs finished before a restart keep only their result summary
+        job = load_job_from_db(job_id)
+        if not job or job['status'] not in ('completed', 'failed', 'cancelled'):
             raise HTTPException(status_code=404, detail=f"Job {job_id} not found")
+        
+        return QueryResult(
+            job_id=job['job_id'],
+            status=job['status'],
+            result=None,
+            error=job.get('error'),
+            execution_stats={
+                "execution_time": job.get('execution_time'),
+                "engine": job['engine'],
+                "rows_processed": (job.get('result_summary') or {}).get('rows', 0),
+                "result_available": False
+            }
+        )
     
     job = completed_jobs[job_id]
     
@@ -633,6 +771,13 @@ async def list_jobs(status: Optional[str] = None, limit: int = 50):
     if status is None or status in ["completed", "failed"]:
         jobs.extend([{**j, 'source': 'completed'} for j in completed_jobs.values()])
     
+    # Get finished jobs of earlier runs
+    if status is None or status in ["completed", "failed", "cancelled"]:
+        known = {j['job_id'] for j in jobs}
+        for j in get_job_history(limit, status):
+            if j['job_id'] not in known and j['status'] in ["completed", "failed", "cancelled"]:
+                jobs.append({**j, 'source': 'history'})
+    
     # Sort by creation time and limit
     jobs.sort(key=lambda x: x['created_at'], reverse=True)
     jobs = jobs[:limit]
@@ -678,6 +823,9 @@ async def cancel_job(job_id: str):
     for i, job in enumerate(job_queue):
         if job['job_id'] == job_id:
             job_queue.pop(i)
+            job['status'] = 'cancelled'
+            job['completed_at'] = datetime.now().isoformat()
+            save_job_to_db(job)
             return {"message": f"Job {job_id} cancelled"}
     
     # Running jobs can't be easily cancelled in this simple implementation
@@ -1604,6 +1752,101 @@ async def ingest_protobuf_data(
         )
 
 
+# =============================================================================
+# BigQuery Metadata Sync Endpoints
+# =============================================================================
+
+@app.post("/bigquery/sync", response_model=BigQuerySyncResponse)
+async def sync_bigquery_metadata(
+    request: BigQuerySyncRequest,
+    registry: SchemaRegistry = Depends(get_schema_registry),
+    translator: SchemaTranslator = Depends(get_schema_translator),
+    runners: Dict[str, Any] = Depends(get_runners)
+):
+    """
+    Mirror the datasets and tables of a real BigQuery project locally
+
+    This endpoint:
+    1. Reads the tables and columns of each dataset from INFORMATION_SCHEMA
+    2. Registers every table's schema, with the dataset as its database
+    3. Creates each dataset as a schema and each table as an empty stub
+       table in the requested engines
+
+    Credentials come from BIGQUERY_ACCESS_TOKEN or the gcloud CLI.
+    """
+    sync = BigQueryMetadataSync(request.project_id, location=request.location)
+    try:
+        remote_tables, errors = await asyncio.to_thread(sync.fetch_project, request.datasets)
+    except BigQuerySyncError as e:
+        raise HTTPException(
+            status_code=status.HTTP_502_BAD_GATEWAY,
+            detail=f"Reading BigQuery metadata failed: {e}"
+        )
+
+    datasets = sorted({table.dataset for table in remote_tables})
+    created_databases = set()
+    results = []
+    tables_synced = 0
+
+    for table in remote_tables:
+        table_result = {
+            "dataset": table.dataset,
+            "table_name": table.table_name,
+            "table_type": table.table_type,
+            "schema_id": None,
+            "engines": {}
+        }
+        try:
+            table_result["schema_id"] = await registry.register_schema_from_json(
+                table.schema_json, table.table_name, table.dataset
+            )
+        except SchemaRegistryError as e:
+            table_result["error"] = str(e)
+            results.append(table_result)
+            continue
+
+        for engine in request.engines:
+            if engine not in runners:
+                table_result["engines"][engine] = f"Engine {engine} not available"
+                continue
+            runner = runners[engine]
+            try:
+                if (engine, table.dataset) not in created_databases:
+                    keyword = "DATABASE" if engine == "clickhouse" else "SCHEMA"
+                    await runner.execute_query(f"CREATE {keyword} IF NOT EXISTS {table.dataset}")
+                    created_databases.add((engine, table.dataset))
+
+                create_sql = translator.generate_create_table_sql(
+                    table.schema_json, table.table_name, engine, table.dataset
+                )
+                execution_result = await runner.execute_query(create_sql)
+                if "error" in execution_result:
+                    table_result["engines"][engine] = execution_result["error"]
+                else:
+                    table_result["engines"][engine] = "created"
+                    await registry.mark_table_created(table_result["schema_id"], engine)
+            except Exception as e:
+                logger.error(f"Error creating {table.dataset}.{table.table_name} in {engine}: {e}")
+                table_result["engines"][engine] = str(e)
+
+        if all(outcome == "created" for outcome in table_result["engines"].values()):
+            tables_synced += 1
+        results.append(table_result)
+
+    message = f"Mirrored {tables_synced} of {len(remote_tables)} tables from {len(datasets)} datasets"
+    if errors:
+        message += f"; {len(errors)} datasets could not be read"
+
+    return BigQuerySyncResponse(
+        project_id=request.project_id,
+        datasets=datasets,
+        tables_synced=tables_synced,
+        tables=results,
+        errors=errors,
+        message=message
+    )
+
+
 def main():
     """Run the backend server"""
     uvicorn.run(
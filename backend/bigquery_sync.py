@@ -0,0 +1,300 @@
+#!/usr/bin/env python3
+"""
+BigQuery Metadata Sync for BigQuery-Lite
+
+Reads the datasets, tables and columns of a real BigQuery project through the
+BigQuery REST API and INFORMATION_SCHEMA, and turns every table and view into
+a BigQuery schema JSON that the schema registry can store and the schema
+translator can create as an empty stub table in DuckDB or ClickHouse.
+
+Only metadata is read: one INFORMATION_SCHEMA query per dataset, which
+BigQuery bills at its minimum for metadata queries. Queries developed against
+the stubs run locally for free until they are promoted to the real project.
+
+Authentication uses, in order, the access token passed in, the
+BIGQUERY_ACCESS_TOKEN environment variable, and `gcloud auth
+print-access-token`.
+"""
+
+import json
+import os
+import subprocess
+import time
+import urllib.error
+import urllib.parse
+import urllib.request
+from dataclasses import dataclass, field
+from typing import Any, Callable, Dict, List, Optional, Tuple
+import logging
+
+logger = logging.getLogger(__name__)
+
+BIGQUERY_API_BASE = "https://bigquery.googleapis.com/bigquery/v2"
+
+# How long one jobs.query call waits before results are polled for
+QUERY_TIMEOUT_MS = 30000
+
+# Gives up on a metadata query that has not finished after this many seconds
+MAX_QUERY_WAIT_SECONDS = 120
+
+# Simple INFORMATION_SCHEMA type names and their schema JSON types
+SCALAR_TYPES = {
+    "INT64": "INTEGER",
+    "FLOAT64": "FLOAT",
+    "BOOL": "BOOLEAN",
+    "STRING": "STRING",
+    "BYTES": "BYTES",
+    "NUMERIC": "NUMERIC",
+    "BIGNUMERIC": "BIGNUMERIC",
+    "DATE": "DATE",
+    "TIME": "TIME",
+    "DATETIME": "DATETIME",
+    "TIMESTAMP": "TIMESTAMP",
+    "GEOGRAPHY": "GEOGRAPHY",
+    "JSON": "JSON",
+    "INTERVAL": "STRING",
+    "RANGE": "STRING",
+}
+
+# Transport: (method, url, headers, body) -> parsed JSON response
+Transport = Callable[[str, str, Dict[str, str], Optional[bytes]], Dict[str, Any]]
+
+
+class BigQuerySyncError(Exception):
+    """Raised when BigQuery metadata cannot be read"""
+    pass
+
+
+@dataclass
+class RemoteTable:
+    """A table or view of the remote project with its schema"""
+    dataset: str
+    table_name: str
+    table_type: str
+    schema_json: List[Dict[str, Any]] = field(default_factory=list)
+
+
+def parse_bigquery_type(data_type: str) -> Dict[str, Any]:
+    """
+    Convert an INFORMATION_SCHEMA data type into a schema JSON field without
+    its name, e.g. `ARRAY<STRUCT<id INT64, tags ARRAY<STRING>>>` into a
+    REPEATED RECORD with nested fields
+
+    Args:
+        data_type: Type as in INFORMATION_SCHEMA.COLUMNS.data_type
+
+    Returns:
+        Dict with `type`, `mode` and, for records, `fields`
+
+    Raises:
+        BigQuerySyncError: If the type cannot be parsed
+    """
+    data_type = data_type.strip()
+    upper = data_type.upper()
+
+    if upper.startswith("ARRAY<") and upper.endswith(">"):
+        element = parse_bigquery_type(data_type[len("ARRAY<"):-1])
+        element["mode"] = "REPEATED"
+        return element
+
+    if upper.startswith("STRUCT<") and upper.endswith(">"):
+        fields = []
+        for member in _split_top_level(data_type[len("STRUCT<"):-1]):
+            name, _, member_type = member.strip().partition(" ")
+            if not member_type:
+                raise BigQuerySyncError(f"Cannot parse STRUCT member '{member}' of {data_type}")
+            nested = parse_bigquery_type(member_type)
+            fields.append({"name": name.strip("`"), **nested})
+        return {"type": "RECORD", "mode": "NULLABLE", "fields": fields}
+
+    # Parameterized types such as STRING(10) or NUMERIC(10, 2)
+    base = upper.split("(", 1)[0].split("<", 1)[0].strip()
+    if base not in SCALAR_TYPES:
+        raise BigQuerySyncError(f"Unsupported BigQuery type: {data_type}")
+    return {"type": SCALAR_TYPES[base], "mode": "NULLABLE"}
+
+
+def column_to_field(name: str, data_type: str, is_nullable: str) -> Dict[str, Any]:
+    """Convert one INFORMATION_SCHEMA.COLUMNS row into a schema JSON field"""
+    parsed = parse_bigquery_type(data_type)
+    if parsed["mode"] != "REPEATED" and str(is_nullable).upper() == "NO":
+        parsed["mode"] = "REQUIRED"
+    return {"name": name, **parsed}
+
+
+def _split_top_level(members: str) -> List[str]:
+    """Split STRUCT members on commas outside nested <> and ()"""
+    parts, depth, current = [], 0, []
+    for char in members:
+        if char in "<(":
+            depth += 1
+        elif char in ">)":
+            depth -= 1
+        if char == "," and depth == 0:
+            parts.append("".join(current))
+            current = []
+        else:
+            current.append(char)
+    if "".join(current).strip():
+        parts.append("".join(current))
+    return parts
+
+
+def _urllib_transport(method: str, url: str, headers: Dict[str, str], body: Optional[bytes]) -> Dict[str, Any]:
+    request = urllib.request.Request(url, data=body, headers=headers, method=method)
+    try:
+        with urllib.request.urlopen(request, timeout=60) as response:
+            return json.loads(response.read().decode("utf-8"))
+    except urllib.error.HTTPError as e:
+        detail = e.read().decode("utf-8", errors="replace")
+        try:
+            detail = json.loads(detail)["error"]["message"]
+        except (ValueError, KeyError, TypeError):
+            pass
+        raise BigQuerySyncError(f"BigQuery API returned {e.code}: {detail}")
+    except urllib.error.URLError as e:
+        raise BigQuerySyncError(f"Cannot reach the BigQuery API: {e.reason}")
+
+
+class BigQueryMetadataSync:
+    """
+    Reads the schemas of a BigQuery project's tables
+
+    Mirrors datasets as schemas and tables as empty stub tables through the
+    schema registry, so queries can be developed offline against production
+    schemas.
+    """
+
+    def __init__(self,
+                 project_id: str,
+                 access_token: Optional[str] = None,
+                 location: Optional[str] = None,
+                 transport: Optional[Transport] = None):
+        """
+        Initialize the sync
+
+        Args:
+            project_id: BigQuery project to read
+            access_token: OAuth access token; found automatically when omitted
+            location: Location of the datasets, e.g. "US" or "europe-west1"
+            transport: HTTP transport, replaceable for testing
+        """
+        self.project_id = project_id
+        self.location = location
+        self._access_token = access_token
+        self._transport = transport or _urllib_transport
+
+    def _token(self) -> str:
+        if self._access_token:
+            return self._access_token
+        token = os.environ.get("BIGQUERY_ACCESS_TOKEN")
+        if not token:
+            try:
+                token = subprocess.run(
+                    ["gcloud", "auth", "print-access-token"],
+                    capture_output=True, text=True, check=True, timeout=30
+                ).stdout.strip()
+            except (OSError, subprocess.SubprocessError) as e:
+                raise BigQuerySyncError(
+                    "No BigQuery credentials: pass an access token, set BIGQUERY_ACCESS_TOKEN "
+                    f"or log in with gcloud ({e})"
+                )
+        self._access_token = token
+        return token
+
+    def _request(self, method: str, path: str,
+                 params: Optional[Dict[str, Any]] = None,
+                 body: Optional[Dict[str, Any]] = None) -> Dict[str, Any]:
+        url = f"{BIGQUERY_API_BASE}/projects/{urllib.parse.quote(self.project_id)}{path}"
+        params = {k: v for k, v in (params or {}).items() if v is not None}
+        if params:
+            url += "?" + urllib.parse.urlencode(params)
+        headers = {"Authorization": f"Bearer {self._token()}", "Accept": "application/json"}
+        data = None
+        if body is not None:
+            headers["Content-Type"] = "application/json"
+            data = json.dumps(body).encode("utf-8")
+        return self._transport(method, url, headers, data)
+
+    def list_datasets(self) -> List[str]:
+        """Names of the project's datasets"""
+        datasets, page_token = [], None
+        while True:
+            response = self._request("GET", "/datasets", params={"pageToken": page_token, "all": "false"})
+            for dataset in response.get("datasets", []):
+                datasets.append(dataset["datasetReference"]["datasetId"])
+            page_token = response.get("nextPageToken")
+            if not page_token:
+                return sorted(datasets)
+
+    def _run_query(self, sql: str) -> List[List[Any]]:
+        """Run a query and return every row as a list of values"""
+        response = self._request("POST", "/queries", body={
+            "query": sql,
+            "useLegacySql": False,
+            "timeoutMs": QUERY_TIMEOUT_MS,
+            "location": self.location,
+        })
+        job = response.get("jobReference", {})
+        deadline = time.monotonic() + MAX_QUERY_WAIT_SECONDS
+        rows = []
+        while True:
+            if response.get("jobComplete", True):
+                rows.extend([cell.get("v") for cell in row["f"]] for row in response.get("rows", []))
+                page_token = response.get("pageToken")
+                if not page_token:
+                    return rows
+            else:
+                if time.monotonic() > deadline:
+                    raise BigQuerySyncError(f"Metadata query did not finish in {MAX_QUERY_WAIT_SECONDS}s")
+                page_token = None
+            response = self._request("GET", f"/queries/{job['jobId']}", params={
+                "location": job.get("location", self.location),
+                "pageToken": page_token,
+                "timeoutMs": QUERY_TIMEOUT_MS,
+            })
+
+    def fetch_tables(self, dataset: str) -> List[RemoteTable]:
+        """
+        Schemas of the tables and views in a dataset
+
+        Args:
+            dataset: Dataset to read
+
+        Returns:
+            Tables in name order, each with its columns in order
+        """
+        qualified = f"`{self.project_id}.{dataset}`"
+        rows = self._run_query(f"""
+            SELECT c.table_name, t.table_type, c.column_name, c.data_type, c.is_nullable
+            FROM {qualified}.INFORMATION_SCHEMA.COLUMNS AS c
+            JOIN {qualified}.INFORMATION_SCHEMA.TABLES AS t USING (table_name)
+            WHERE c.is_hidden = 'NO'
+            ORDER BY c.table_name, c.ordinal_position
+        """)
+
+        tables: Dict[str, RemoteTable] = {}
+        for table_name, table_type, column_name, data_type, is_nullable in rows:
+            table = tables.setdefault(table_name, RemoteTable(dataset, table_name, table_type))
+            try:
+                table.schema_json.append(column_to_field(column_name, data_type, is_nullable))
+            except BigQuerySyncError as e:
+                logger.warning(f"Storing {dataset}.{table_name}.{column_name} as STRING: {e}")
+                table.schema_json.append({"name": column_name, "type": "STRING", "mode": "NULLABLE"})
+        return list(tables.values())
+
+    def fetch_project(self, datasets: Optional[List[str]] = None) -> Tuple[List[RemoteTable], Dict[str, str]]:
+        """
+        Schemas of the tables in the given datasets, or in all of them
+
+        Returns:
+            The tables read, and an error message per dataset that failed
+        """
+        tables, errors = [], {}
+        for dataset in datasets or self.list_datasets():
+            try:
+                tables.extend(self.fetch_tables(dataset))
+            except BigQuerySyncError as e:
+                logger.error(f"Failed to read dataset {dataset}: {e}")
+                errors[dataset] = str(e)
+        return tables, errors
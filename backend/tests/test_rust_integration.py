@@ -9,6 +9,9 @@ import pytest
 import time
 import threading
 from concurrent.futures import ThreadPoolExecutor, as_completed
+from dataclasses import dataclass
+from datetime import datetime
+from typing import Dict, List, Optional
 
 # Try to import Rust engine
 try:
@@ -114,6 +117,43 @@ class TestRustEngineBasics:
         if len(result.data) > 1:
             values = [row["value"] for row in result.data]
             assert values == sorted(values, reverse=True)
+    
+    def test_synthetic_data_generation(self):
+        """Test generating configurable synthetic tables and CSV files"""
+        engine = bigquery_lite_engine.BlazeQueryEngine()
+        engine.register_synthetic_data(
+            "synthetic",
+            5000,
+            [
+                {"name": "id", "type": "sequence"},
+                {"name": "user", "type": "string", "cardinality": 50, "distribution": "zipf", "skew": 1.5},
+                "score:float:min=0,max=10,null_fraction=0.2",
+            ],
+            seed=42,
+        )
+        
+        result = engine.execute_query_sync(
+            "SELECT COUNT(*) AS n, COUNT(score) AS scored, COUNT(DISTINCT user) AS users FROM synthetic"
+        )
+        row = result.data[0]
+        assert row["n"] == 5000
+        assert 3700 < row["scored"] < 4300
+        assert row["users"] <= 50
+        
+        with pytest.raises(ValueError):
+            engine.register_synthetic_data("bad", 10, ["score:float:null_fraction=2"])
+    
+    def test_generate_csv(self, tmp_path):
+        """Test writing synthetic data to CSV"""
+        path = tmp_path / "events.csv"
+        written = bigquery_lite_engine.generate_csv(
+            str(path), 100, ["id:sequence", "kind:string:cardinality=3,distribution=cyclic"], seed=1
+        )
+        
+        assert written == 100
+        lines = path.read_text().splitlines()
+        assert lines[0] == "id,kind"
+        assert lines[1:4] == ["0,kind_0", "1,kind_1", "2,kind_2"]
 
 
 @pytest.mark.skipif(not RUST_ENGINE_AVAILABLE, reason="Rust engine not available")
@@ -308,6 +348,72 @@ class TestRustEngineDataTypes:
             assert row["category"].startswith("category_")
 
 
+@dataclass
+class Account:
+    id: int
+    nickname: Optional[str]
+    created_at: datetime
+    tags: List[str]
+    address: Dict[str, str]
+    score: float = 0.0
+
+
+ACCOUNTS_SQL = (
+    "SELECT * FROM (VALUES "
+    "(1, 'alice', TIMESTAMP '2024-01-02 03:04:05', make_array('a', 'b'), named_struct('city', 'Oslo', 'zip', '0150')), "
+    "(2, CAST(NULL AS VARCHAR), TIMESTAMP '2024-02-03 04:05:06', make_array('c'), named_struct('city', 'Bergen', 'zip', '5003'))"
+    ") AS t(id, nickname, created_at, tags, address) ORDER BY id"
+)
+
+
+@pytest.mark.skipif(not RUST_ENGINE_AVAILABLE, reason="Rust engine not available")
+class TestRustEngineTypedRows:
+    """Test mapping result rows onto dataclasses with as_objects"""
+
+    def test_nullable_timestamp_and_nested_columns(self):
+        """Test that NULLs, timestamps and nested values reach the fields"""
+        engine = bigquery_lite_engine.BlazeQueryEngine()
+
+        accounts = engine.execute_query_sync(ACCOUNTS_SQL).as_objects(Account)
+
+        assert accounts == [
+            Account(1, "alice", datetime(2024, 1, 2, 3, 4, 5), ["a", "b"], {"city": "Oslo", "zip": "0150"}),
+            Account(2, None, datetime(2024, 2, 3, 4, 5, 6), ["c"], {"city": "Bergen", "zip": "5003"}),
+        ]
+
+    def test_null_in_required_field(self):
+        """Test that NULL in a non-Optional field names the row and column"""
+        engine = bigquery_lite_engine.BlazeQueryEngine()
+        sql = ACCOUNTS_SQL.replace("TIMESTAMP '2024-02-03 04:05:06'", "CAST(NULL AS TIMESTAMP)")
+
+        with pytest.raises(ValueError, match="Row 1, column 'created_at': NULL is not allowed"):
+            engine.execute_query_sync(sql).as_objects(Account)
+
+    def test_missing_field(self):
+        """Test that a required field without a column is an error"""
+        engine = bigquery_lite_engine.BlazeQueryEngine()
+        result = engine.execute_query_sync("SELECT id, nickname, created_at, tags FROM (" + ACCOUNTS_SQL + ")")
+
+        with pytest.raises(ValueError, match="Field 'address' of Account has no matching column"):
+            result.as_objects(Account)
+
+    def test_extra_column(self):
+        """Test that a column without a field is an error"""
+        engine = bigquery_lite_engine.BlazeQueryEngine()
+        result = engine.execute_query_sync("SELECT *, 'x' AS referrer FROM (" + ACCOUNTS_SQL + ")")
+
+        with pytest.raises(ValueError, match="Account has no field for column\\(s\\) referrer"):
+            result.as_objects(Account)
+
+    def test_not_a_dataclass(self):
+        """Test that as_objects only accepts dataclass types"""
+        engine = bigquery_lite_engine.BlazeQueryEngine()
+        result = engine.execute_query_sync(ACCOUNTS_SQL)
+
+        with pytest.raises(TypeError, match="expects a dataclass type"):
+            result.as_objects(dict)
+
+
 @pytest.mark.skipif(not RUST_ENGINE_AVAILABLE, reason="Rust engine not available")
 class TestRustEngineIntegration:
     """Test integration with existing backend components"""
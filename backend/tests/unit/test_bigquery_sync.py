@@ -0,0 +1,125 @@
+"""
+Unit tests for BigQueryMetadataSync
+
+Tests reading a BigQuery project's schemas including:
+- Parsing INFORMATION_SCHEMA data types into schema JSON
+- Listing datasets across pages
+- Polling metadata queries and reading paged results
+- Reporting datasets that cannot be read
+"""
+
+import json
+
+import pytest
+
+from bigquery_sync import BigQueryMetadataSync, BigQuerySyncError, column_to_field, parse_bigquery_type
+
+
+def _rows(*rows):
+    return [{"f": [{"v": value} for value in row]} for row in rows]
+
+
+class FakeBigQuery:
+    """Answers REST calls from canned responses, recording each request"""
+
+    def __init__(self, responses):
+        self.responses = responses
+        self.requests = []
+
+    def __call__(self, method, url, headers, body):
+        self.requests.append((method, url, headers, json.loads(body) if body else None))
+        response = self.responses.pop(0)
+        if isinstance(response, Exception):
+            raise response
+        return response
+
+
+class TestParseBigQueryType:
+    """Test cases for INFORMATION_SCHEMA type parsing"""
+
+    @pytest.mark.unit
+    def test_scalar_types(self):
+        assert parse_bigquery_type("INT64") == {"type": "INTEGER", "mode": "NULLABLE"}
+        assert parse_bigquery_type("STRING(10)") == {"type": "STRING", "mode": "NULLABLE"}
+        assert parse_bigquery_type("NUMERIC(10, 2)") == {"type": "NUMERIC", "mode": "NULLABLE"}
+
+    @pytest.mark.unit
+    def test_nested_types(self):
+        parsed = parse_bigquery_type("ARRAY<STRUCT<id INT64, tags ARRAY<STRING>, price NUMERIC(10, 2)>>")
+        assert parsed["type"] == "RECORD"
+        assert parsed["mode"] == "REPEATED"
+        assert parsed["fields"] == [
+            {"name": "id", "type": "INTEGER", "mode": "NULLABLE"},
+            {"name": "tags", "type": "STRING", "mode": "REPEATED"},
+            {"name": "price", "type": "NUMERIC", "mode": "NULLABLE"},
+        ]
+
+    @pytest.mark.unit
+    def test_columns_and_unknown_types(self):
+        assert column_to_field("id", "INT64", "NO")["mode"] == "REQUIRED"
+        assert column_to_field("ids", "ARRAY<INT64>", "NO")["mode"] == "REPEATED"
+        with pytest.raises(BigQuerySyncError):
+            parse_bigquery_type("MYSTERY")
+
+
+class TestBigQueryMetadataSync:
+    """Test cases for BigQueryMetadataSync class"""
+
+    @pytest.mark.unit
+    def test_list_datasets_pages(self):
+        fake = FakeBigQuery([
+            {"datasets": [{"datasetReference": {"datasetId": "sales"}}], "nextPageToken": "p2"},
+            {"datasets": [{"datasetReference": {"datasetId": "analytics"}}]},
+        ])
+        sync = BigQueryMetadataSync("my-project", access_token="token", transport=fake)
+
+        assert sync.list_datasets() == ["analytics", "sales"]
+        assert "pageToken=p2" in fake.requests[1][1]
+        assert fake.requests[0][2]["Authorization"] == "Bearer token"
+
+    @pytest.mark.unit
+    def test_fetch_tables_polls_and_pages(self):
+        fake = FakeBigQuery([
+            {"jobComplete": False, "jobReference": {"jobId": "job1", "location": "US"}},
+            {
+                "jobComplete": True,
+                "jobReference": {"jobId": "job1", "location": "US"},
+                "rows": _rows(["orders", "BASE TABLE", "id", "INT64", "NO"]),
+                "pageToken": "next",
+            },
+            {
+                "jobComplete": True,
+                "rows": _rows(
+                    ["orders", "BASE TABLE", "items", "ARRAY<STRUCT<sku STRING>>", "NO"],
+                    ["recent_orders", "VIEW", "id", "INT64", "YES"],
+                    ["recent_orders", "VIEW", "area", "MYSTERY", "YES"],
+                ),
+            },
+        ])
+        sync = BigQueryMetadataSync("my-project", access_token="token", location="US", transport=fake)
+
+        tables = sync.fetch_tables("sales")
+        assert [table.table_name for table in tables] == ["orders", "recent_orders"]
+        assert tables[0].schema_json[0] == {"name": "id", "type": "INTEGER", "mode": "REQUIRED"}
+        assert tables[0].schema_json[1]["fields"] == [{"name": "sku", "type": "STRING", "mode": "NULLABLE"}]
+        assert tables[1].table_type == "VIEW"
+        # Types that cannot be parsed are kept as strings
+        assert tables[1].schema_json[1] == {"name": "area", "type": "STRING", "mode": "NULLABLE"}
+
+        method, url, _, body = fake.requests[0]
+        assert method == "POST" and url.endswith("/projects/my-project/queries")
+        assert "`my-project.sales`.INFORMATION_SCHEMA.COLUMNS" in body["query"]
+        assert body["location"] == "US"
+        assert "/queries/job1" in fake.requests[2][1] and "pageToken=next" in fake.requests[2][1]
+
+    @pytest.mark.unit
+    def test_fetch_project_reports_failed_datasets(self):
+        fake = FakeBigQuery([
+            {"jobComplete": True, "rows": _rows(["events", "BASE TABLE", "ts", "TIMESTAMP", "YES"])},
+            BigQuerySyncError("BigQuery API returned 403: Access Denied"),
+        ])
+        sync = BigQueryMetadataSync("my-project", access_token="token", transport=fake)
+
+        tables, errors = sync.fetch_project(["analytics", "restricted"])
+        assert [table.table_name for table in tables] == ["events"]
+        assert errors == {"restricted": "BigQuery API returned 403: Access Denied"}
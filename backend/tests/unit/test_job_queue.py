@@ -0,0 +1,119 @@
+"""
+Unit tests for the durable job queue
+
+Tests restoring jobs from the job history database including:
+- Requeueing jobs that were queued when the backend stopped
+- Rerunning jobs that were running, counting the extra attempt
+- Failing jobs that were interrupted MAX_JOB_ATTEMPTS times
+"""
+
+import asyncio
+import sqlite3
+
+import pytest
+
+pytest.importorskip("fastapi")
+
+import app
+
+
+def _job(job_id, status, attempts=0, priority=1, created_at="2024-03-01T00:00:00"):
+    return {
+        "job_id": job_id,
+        "sql": "SELECT 1",
+        "engine": "duckdb",
+        "status": status,
+        "priority": priority,
+        "created_at": created_at,
+        "started_at": created_at if status == "running" else None,
+        "attempts": attempts,
+    }
+
+
+@pytest.fixture
+def job_db(temp_db_path, monkeypatch):
+    """An initialized job history database and an empty in-memory queue"""
+    monkeypatch.setattr(app, "DB_PATH", temp_db_path)
+    monkeypatch.setattr(app, "job_queue", [])
+    monkeypatch.setattr(app, "running_jobs", {})
+    app.init_database()
+    return temp_db_path
+
+
+class TestInitDatabase:
+    """Test cases for the job history schema"""
+
+    @pytest.mark.unit
+    def test_adds_queue_columns_to_older_databases(self, temp_db_path, monkeypatch):
+        """Test that a database from before the durable queue gains its columns"""
+        conn = sqlite3.connect(temp_db_path)
+        conn.execute("CREATE TABLE job_history (job_id TEXT PRIMARY KEY, sql TEXT NOT NULL, engine TEXT NOT NULL, status TEXT NOT NULL, priority INTEGER, created_at TEXT, started_at TEXT, completed_at TEXT, execution_time REAL, error TEXT, result_summary TEXT)")
+        conn.commit()
+        conn.close()
+        monkeypatch.setattr(app, "DB_PATH", temp_db_path)
+
+        app.init_database()
+        app.init_database()
+
+        conn = sqlite3.connect(temp_db_path)
+        columns = {row[1] for row in conn.execute("PRAGMA table_info(job_history)")}
+        conn.close()
+        assert set(app.JOB_QUEUE_COLUMNS) <= columns
+
+
+class TestRestoreJobQueue:
+    """Test cases for restoring unfinished jobs after a restart"""
+
+    @pytest.mark.unit
+    def test_restores_queued_jobs(self, job_db):
+        """Test that queued jobs go back in the queue in priority order"""
+        app.save_job_to_db(_job("low", "queued", priority=2, created_at="2024-03-01T00:00:00"))
+        app.save_job_to_db(_job("high", "queued", priority=1, created_at="2024-03-01T00:00:01"))
+        app.save_job_to_db(_job("done", "completed"))
+
+        assert app.restore_job_queue() == 2
+        assert [job["job_id"] for job in app.job_queue] == ["high", "low"]
+        assert app.load_job_from_db("high")["status"] == "queued"
+
+    @pytest.mark.unit
+    async def test_requeues_running_jobs_and_counts_the_attempt(self, job_db, monkeypatch):
+        """Test that an interrupted job runs again as its next attempt"""
+        app.save_job_to_db(_job("interrupted", "running", attempts=1))
+
+        assert app.restore_job_queue() == 1
+        restored = app.load_job_from_db("interrupted")
+        assert restored["status"] == "queued"
+        assert restored["started_at"] is None
+
+        # Start the requeued job with one pass of the queue processor
+        async def execute_job(job):
+            pass
+
+        async def stop(_seconds):
+            raise asyncio.CancelledError
+
+        monkeypatch.setattr(app, "execute_job", execute_job)
+        monkeypatch.setitem(app.system_config, "available_slots", 1)
+        monkeypatch.setattr(app.asyncio, "sleep", stop)
+        with pytest.raises(asyncio.CancelledError):
+            await app.process_job_queue()
+
+        started = app.load_job_from_db("interrupted")
+        assert started["status"] == "running"
+        assert started["attempts"] == 2
+        assert app.job_queue == []
+
+    @pytest.mark.unit
+    def test_fails_jobs_interrupted_max_attempts_times(self, job_db):
+        """Test that a job interrupted on every attempt is not retried again"""
+        app.save_job_to_db(_job("crashes", "running", attempts=app.MAX_JOB_ATTEMPTS))
+        app.save_job_to_db(_job("retried", "running", attempts=app.MAX_JOB_ATTEMPTS - 1))
+
+        assert app.restore_job_queue() == 1
+        assert [job["job_id"] for job in app.job_queue] == ["retried"]
+
+        failed = app.load_job_from_db("crashes")
+        assert failed["status"] == "failed"
+        assert failed["attempts"] == app.MAX_JOB_ATTEMPTS
+        assert failed["completed_at"] is not None
+        assert "not retried" in failed["error"]
@@ -173,10 +173,10 @@ async fn create_test_data(
         
         let amount_array = Decimal128Array::from_iter_values(
             (0..batch_rows).map(|_| rng.gen_range(100..10000))
-        );
+        ).with_precision_and_scale(10, 2)?;
         
-        let flag_array = BooleanArray::from_iter_values(
-            (0..batch_rows).map(|_| rng.gen_bool(0.5))
+        let flag_array = BooleanArray::from(
+            (0..batch_rows).map(|_| rng.gen_bool(0.5)).collect::<Vec<_>>()
         );
 
         let batch = datafusion::arrow::record_batch::RecordBatch::try_new(
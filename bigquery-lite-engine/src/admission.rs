@@ -0,0 +1,330 @@
+//! Admission control for concurrent queries
+//!
+//! Every query draws from the engine's one memory pool, so many queries
+//! started at once can each fail for want of memory none of them would run
+//! short of alone. With `max_concurrent_queries` set, queries beyond that
+//! many wait in a queue and start as running ones finish.
+//!
+//! The queue holds at most `max_queued_queries`; a query arriving at a full
+//! queue fails at once with `BlazeError::Overloaded`, as does one that waited
+//! `queue_timeout_ms` without starting, so callers can back off and retry.
+//! Time spent queued does not count against a query's own timeout, and a
+//! queued query can be cancelled with `cancel_query` like a running one.
+//!
+//! Like BigQuery jobs, queries have a priority, `QueryOptions::priority`.
+//! Interactive queries, the default, are queued ahead of every waiting batch
+//! query, so a notebook stays responsive while bulk jobs queue up behind it;
+//! batch queries start only when no interactive one is waiting. Queries of
+//! the same priority start in the order they arrived. Running queries are
+//! never interrupted.
+//!
+//! Admission applies to queries run with `execute_query` and
+//! `execute_prepared`. Resource groups limit their own queries on top.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// How urgently a query should be admitted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryPriority {
+    /// Queued ahead of batch queries
+    #[default]
+    Interactive,
+    /// Admitted when no interactive query is waiting
+    Batch,
+}
+
+impl QueryPriority {
+    /// Parse a priority name, `interactive` or `batch`
+    pub fn parse(name: &str) -> BlazeResult<Self> {
+        match name.to_lowercase().as_str() {
+            "interactive" => Ok(Self::Interactive),
+            "batch" => Ok(Self::Batch),
+            _ => Err(invalid_input!("Unsupported query priority '{}'; expected interactive or batch", name)),
+        }
+    }
+}
+
+/// Limits and activity of admission control
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdmissionStats {
+    /// Queries run at once; 0 when admission control is off
+    pub max_concurrent_queries: usize,
+    /// Queries that may wait for admission
+    pub max_queued_queries: usize,
+    /// Queries admitted and not finished
+    pub running_queries: usize,
+    /// Queries waiting for admission
+    pub queued_queries: usize,
+    /// Batch queries among the queued ones
+    #[serde(default)]
+    pub queued_batch_queries: usize,
+    /// Queries admitted, at once or after queueing
+    pub admitted_queries: u64,
+    /// Queries that had to wait before they were admitted
+    pub delayed_queries: u64,
+    /// Queries rejected because the queue was full
+    pub rejected_queries: u64,
+    /// Queries that gave up after `queue_timeout_ms`
+    pub timed_out_queries: u64,
+    /// Average time admitted queries spent queued, including those admitted
+    /// at once
+    pub avg_queue_time_ms: f64,
+}
+
+struct Waiter {
+    id: u64,
+    priority: QueryPriority,
+    admit: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct AdmissionState {
+    max_concurrent: usize,
+    max_queued: usize,
+    running: usize,
+    queue: VecDeque<Waiter>,
+    next_waiter: u64,
+    admitted: u64,
+    delayed: u64,
+    rejected: u64,
+    timed_out: u64,
+    total_queue_time_ms: u64,
+}
+
+impl AdmissionState {
+    fn has_room(&self) -> bool {
+        self.max_concurrent == 0 || self.running < self.max_concurrent
+    }
+
+    /// Admit queued queries while there is room. Waiters whose query was
+    /// dropped meanwhile are skipped.
+    fn admit_queued(&mut self) {
+        while self.has_room() {
+            let Some(waiter) = self.queue.pop_front() else {
+                break;
+            };
+            if waiter.admit.send(()).is_ok() {
+                self.running += 1;
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        self.running -= 1;
+        self.admit_queued();
+    }
+}
+
+/// Running and queued queries of the engine
+#[derive(Default)]
+pub(crate) struct AdmissionControl {
+    state: Mutex<AdmissionState>,
+}
+
+/// A query's admission, released when dropped
+pub(crate) struct AdmissionSlot<'a> {
+    control: &'a AdmissionControl,
+}
+
+impl Drop for AdmissionSlot<'_> {
+    fn drop(&mut self) {
+        self.control.state.lock().release();
+    }
+}
+
+/// A place in the queue, given up when dropped before admission, e.g. when
+/// the query is cancelled
+struct QueuedQuery<'a> {
+    control: &'a AdmissionControl,
+    id: u64,
+    admitted: bool,
+}
+
+impl Drop for QueuedQuery<'_> {
+    fn drop(&mut self) {
+        if self.admitted {
+            return;
+        }
+        let mut state = self.control.state.lock();
+        match state.queue.iter().position(|waiter| waiter.id == self.id) {
+            Some(position) => {
+                state.queue.remove(position);
+            }
+            // Admitted just as it was dropped
+            None => state.release(),
+        }
+    }
+}
+
+impl AdmissionControl {
+    pub(crate) fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        let control = Self::default();
+        control.set_limits(max_concurrent, max_queued);
+        control
+    }
+
+    /// Change the limits, admitting queued queries if there is more room.
+    /// Queries already running or queued beyond the new limits stay.
+    pub(crate) fn set_limits(&self, max_concurrent: usize, max_queued: usize) {
+        let mut state = self.state.lock();
+        state.max_concurrent = max_concurrent;
+        state.max_queued = max_queued;
+        state.admit_queued();
+    }
+
+    /// Wait until a query may run, for at most `timeout_ms` when it is not 0
+    pub(crate) async fn admit(&self, priority: QueryPriority, timeout_ms: u64) -> BlazeResult<AdmissionSlot<'_>> {
+        let start = Instant::now();
+        let (admitted, id) = {
+            let mut state = self.state.lock();
+            if state.has_room() && state.queue.is_empty() {
+                state.running += 1;
+                state.admitted += 1;
+                return Ok(AdmissionSlot { control: self });
+            }
+            if state.queue.len() >= state.max_queued {
+                state.rejected += 1;
+                return Err(BlazeError::Overloaded(format!(
+                    "{} queries are running and {} are queued; retry later",
+                    state.running,
+                    state.queue.len()
+                )));
+            }
+            let (admit, admitted) = oneshot::channel();
+            let id = state.next_waiter;
+            state.next_waiter += 1;
+            // Interactive queries go after the other interactive ones but
+            // ahead of every batch query
+            let position = match priority {
+                QueryPriority::Interactive => state
+                    .queue
+                    .iter()
+                    .position(|waiter| waiter.priority == QueryPriority::Batch)
+                    .unwrap_or(state.queue.len()),
+                QueryPriority::Batch => state.queue.len(),
+            };
+            state.queue.insert(position, Waiter { id, priority, admit });
+            (admitted, id)
+        };
+
+        let mut queued = QueuedQuery { control: self, id, admitted: false };
+        let outcome = match timeout_ms {
+            0 => Ok(admitted.await),
+            _ => tokio::time::timeout(Duration::from_millis(timeout_ms), admitted).await,
+        };
+        let mut state = self.state.lock();
+        if outcome.is_err() {
+            if let Some(position) = state.queue.iter().position(|waiter| waiter.id == id) {
+                state.queue.remove(position);
+                state.timed_out += 1;
+                queued.admitted = true;
+                return Err(BlazeError::Overloaded(format!(
+                    "Query waited {}ms for one of {} running queries to finish",
+                    timeout_ms, state.running
+                )));
+            }
+            // Admitted just as the timeout ran out
+        }
+        queued.admitted = true;
+        state.admitted += 1;
+        state.delayed += 1;
+        state.total_queue_time_ms += start.elapsed().as_millis() as u64;
+        Ok(AdmissionSlot { control: self })
+    }
+
+    pub(crate) fn stats(&self) -> AdmissionStats {
+        let state = self.state.lock();
+        AdmissionStats {
+            max_concurrent_queries: state.max_concurrent,
+            max_queued_queries: state.max_queued,
+            running_queries: state.running,
+            queued_queries: state.queue.len(),
+            queued_batch_queries: state.queue.iter().filter(|waiter| waiter.priority == QueryPriority::Batch).count(),
+            admitted_queries: state.admitted,
+            delayed_queries: state.delayed,
+            rejected_queries: state.rejected,
+            timed_out_queries: state.timed_out,
+            avg_queue_time_ms: if state.admitted > 0 {
+                state.total_queue_time_ms as f64 / state.admitted as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_and_limits() {
+        let control = AdmissionControl::new(1, 1);
+        let first = control.admit(QueryPriority::Interactive, 0).await.unwrap();
+
+        // The second query waits, the third finds the queue full
+        let second = control.admit(QueryPriority::Interactive, 0);
+        tokio::pin!(second);
+        assert!(futures::poll!(second.as_mut()).is_pending());
+        assert!(matches!(control.admit(QueryPriority::Interactive, 0).await, Err(BlazeError::Overloaded(_))));
+        assert_eq!(control.stats().queued_queries, 1);
+
+        drop(first);
+        let second = second.await.unwrap();
+        assert!(matches!(control.admit(QueryPriority::Interactive, 10).await, Err(BlazeError::Overloaded(_))));
+
+        // Raising the limit admits at once
+        control.set_limits(2, 1);
+        let third = control.admit(QueryPriority::Interactive, 0).await.unwrap();
+        drop((second, third));
+
+        let stats = control.stats();
+        assert_eq!((stats.running_queries, stats.queued_queries), (0, 0));
+        assert_eq!((stats.admitted_queries, stats.delayed_queries), (3, 1));
+        assert_eq!((stats.rejected_queries, stats.timed_out_queries), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiters_leave_the_queue() {
+        let control = AdmissionControl::new(1, 10);
+        let first = control.admit(QueryPriority::Interactive, 0).await.unwrap();
+        {
+            let waiting = control.admit(QueryPriority::Interactive, 0);
+            tokio::pin!(waiting);
+            assert!(futures::poll!(waiting.as_mut()).is_pending());
+        }
+        assert_eq!(control.stats().queued_queries, 0);
+        drop(first);
+        assert_eq!(control.stats().running_queries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_queries_go_first() {
+        let control = AdmissionControl::new(1, 10);
+        let first = control.admit(QueryPriority::Batch, 0).await.unwrap();
+
+        let batch = control.admit(QueryPriority::Batch, 0);
+        let interactive = control.admit(QueryPriority::Interactive, 0);
+        tokio::pin!(batch, interactive);
+        assert!(futures::poll!(batch.as_mut()).is_pending());
+        assert!(futures::poll!(interactive.as_mut()).is_pending());
+        let stats = control.stats();
+        assert_eq!((stats.queued_queries, stats.queued_batch_queries), (2, 1));
+
+        // The interactive query arrived later but is admitted first
+        drop(first);
+        let interactive = interactive.await.unwrap();
+        assert!(futures::poll!(batch.as_mut()).is_pending());
+        drop(interactive);
+        drop(batch.await.unwrap());
+    }
+}
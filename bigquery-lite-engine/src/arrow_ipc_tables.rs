@@ -0,0 +1,253 @@
+//! Tables over memory-mapped Arrow IPC files
+//!
+//! `register_arrow_ipc("events", "exports/events.arrow")` maps an Arrow IPC
+//! file, as written by pyarrow, Polars or `FileWriter` (Feather V2 files are
+//! the same format), and registers its record batches without copying them:
+//! the column buffers point into the mapping, so registering reads only the
+//! footer and the operating system pages data in as scans touch it. A
+//! directory is registered as all its `.arrow`, `.feather` and `.ipc` files,
+//! one scan partition per file.
+//!
+//! The mapping lives as long as the table, or a query still holding its
+//! batches. Files must not be truncated or rewritten while they are
+//! registered; write a new file and register the table again instead.
+//! Buffers that are not aligned for their type are copied when read, which
+//! files from the standard writers never need. Arrow IPC streams and
+//! Feather V1 files have no footer to map batches from and are rejected.
+
+use std::any::Any;
+use std::fs::File;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::arrow::buffer::Buffer;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::convert::fb_to_schema;
+use datafusion::arrow::ipc::reader::{read_footer_length, FileDecoder};
+use datafusion::arrow::ipc::{root_as_footer, Block};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::Session;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Extensions of the files read from a directory
+const EXTENSIONS: [&str; 3] = ["arrow", "feather", "ipc"];
+
+/// Magic bytes an Arrow IPC file starts and ends with
+const MAGIC: &[u8] = b"ARROW1";
+
+/// Files behind a table registered from Arrow IPC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArrowIpcTableInfo {
+    pub table_name: String,
+    /// File or directory the table was registered from
+    pub path: String,
+    /// Files mapped, sorted
+    pub files: Vec<String>,
+    pub rows: usize,
+    /// Record batches across the files
+    pub batches: usize,
+    /// Combined size of the mapped files in bytes
+    pub mapped_bytes: u64,
+    /// Column names with their Arrow types
+    pub columns: Vec<(String, String)>,
+}
+
+/// A table whose batches point into mapped Arrow IPC files. It is not an
+/// in-memory table: it takes no appends and is never evicted.
+#[derive(Debug)]
+pub(crate) struct ArrowIpcTable {
+    table: MemTable,
+}
+
+/// Map the Arrow IPC file or the IPC files in the directory at `path`,
+/// check that their schemas agree and build a table over them
+pub(crate) fn map_table(name: &str, path: &Path) -> BlazeResult<(Arc<ArrowIpcTable>, ArrowIpcTableInfo)> {
+    let files = if path.is_dir() {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let file = entry?.path();
+            let is_ipc = file
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| EXTENSIONS.iter().any(|e| extension.eq_ignore_ascii_case(e)));
+            if file.is_file() && is_ipc {
+                files.push(file);
+            }
+        }
+        if files.is_empty() {
+            return Err(invalid_input!("No .arrow, .feather or .ipc files in '{}'", path.display()));
+        }
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut schema: Option<SchemaRef> = None;
+    let mut partitions = Vec::with_capacity(files.len());
+    let mut mapped_bytes = 0;
+    for file in &files {
+        let (file_schema, batches, bytes) = map_file(file)?;
+        match &schema {
+            None => schema = Some(file_schema),
+            Some(expected) if *expected != file_schema => {
+                return Err(BlazeError::SchemaMismatch(format!(
+                    "'{}' has schema {} but '{}' has {}",
+                    file.display(),
+                    file_schema,
+                    files[0].display(),
+                    expected
+                )))
+            }
+            Some(_) => {}
+        }
+        partitions.push(batches);
+        mapped_bytes += bytes;
+    }
+    let schema = schema.expect("at least one file");
+
+    let info = ArrowIpcTableInfo {
+        table_name: name.to_string(),
+        path: path.display().to_string(),
+        files: files.iter().map(|file| file.display().to_string()).collect(),
+        rows: partitions.iter().flatten().map(RecordBatch::num_rows).sum(),
+        batches: partitions.iter().map(Vec::len).sum(),
+        mapped_bytes,
+        columns: schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+    };
+    let table = MemTable::try_new(schema, partitions)?;
+    Ok((Arc::new(ArrowIpcTable { table }), info))
+}
+
+/// Map one file and decode its batches in place, returning them with the
+/// file's schema and size
+fn map_file(file: &Path) -> BlazeResult<(SchemaRef, Vec<RecordBatch>, u64)> {
+    let not_ipc = |reason: &str| invalid_input!("'{}' is not an Arrow IPC file: {}", file.display(), reason);
+
+    // SAFETY: registered files must not change while mapped, see above
+    let mmap = unsafe { Mmap::map(&File::open(file)?)? };
+    let len = mmap.len();
+    if len < 2 * MAGIC.len() + 4 || !mmap.starts_with(MAGIC) || !mmap.ends_with(MAGIC) {
+        return Err(not_ipc("missing the ARROW1 magic bytes of the file format"));
+    }
+    let ptr = NonNull::new(mmap.as_ptr() as *mut u8).expect("mapped memory is not null");
+    // SAFETY: the buffer owns the mapping, which covers `len` bytes and
+    // stays valid until the last slice of the buffer is dropped
+    let buffer = unsafe { Buffer::from_custom_allocation(ptr, len, Arc::new(mmap)) };
+
+    let trailer_start = len - 10;
+    let trailer: [u8; 10] = buffer[trailer_start..].try_into().expect("trailer is 10 bytes");
+    let footer_len = read_footer_length(trailer)?;
+    if footer_len > trailer_start {
+        return Err(not_ipc("footer is longer than the file"));
+    }
+    let footer = root_as_footer(&buffer[trailer_start - footer_len..trailer_start])
+        .map_err(|e| not_ipc(&format!("unreadable footer: {}", e)))?;
+    let schema = Arc::new(fb_to_schema(footer.schema().ok_or_else(|| not_ipc("footer has no schema"))?));
+
+    // Blocks are checked against the file, as slicing past it would panic
+    let block_data = |block: &Block| {
+        let (offset, block_len) = (block.offset() as usize, block.bodyLength() as usize + block.metaDataLength() as usize);
+        match offset.checked_add(block_len) {
+            Some(end) if end <= trailer_start => Ok(buffer.slice_with_length(offset, block_len)),
+            _ => Err(not_ipc("footer points past the end of the file")),
+        }
+    };
+    let mut decoder = FileDecoder::new(schema.clone(), footer.version());
+    for block in footer.dictionaries().iter().flatten() {
+        decoder.read_dictionary(block, &block_data(block)?)?;
+    }
+    let mut batches = Vec::new();
+    for block in footer.recordBatches().iter().flatten() {
+        if let Some(batch) = decoder.read_record_batch(block, &block_data(block)?)? {
+            batches.push(batch);
+        }
+    }
+    Ok((schema, batches, len as u64))
+}
+
+#[async_trait]
+impl TableProvider for ArrowIpcTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.table.schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        self.table.scan(state, projection, filters, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Array, DictionaryArray, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Int32Type, Schema};
+    use datafusion::arrow::ipc::writer::{FileWriter, StreamWriter};
+
+    fn batch(start: i64) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("tag", DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)), true),
+        ]));
+        let tags: DictionaryArray<Int32Type> = vec!["a", "b", "a"].into_iter().collect();
+        RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from_iter_values(start..start + 3)), Arc::new(tags)]).unwrap()
+    }
+
+    #[test]
+    fn test_map_file_batches_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.arrow");
+        let mut writer = FileWriter::try_new(File::create(&path).unwrap(), &batch(0).schema()).unwrap();
+        writer.write(&batch(0)).unwrap();
+        writer.write(&batch(3)).unwrap();
+        writer.finish().unwrap();
+
+        let (schema, batches, bytes) = map_file(&path).unwrap();
+        assert_eq!(schema, batch(0).schema());
+        assert_eq!(batches.len(), 2);
+        assert_eq!(bytes, std::fs::metadata(&path).unwrap().len());
+        let ids = batches[1].column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.values(), &[3, 4, 5]);
+        let tags = batches[1].column(1).as_any().downcast_ref::<DictionaryArray<Int32Type>>().unwrap();
+        let values = tags.values().as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(values.value(tags.keys().value(1) as usize), "b");
+
+        // Both batches' columns point into the one mapping of the file
+        let first = batches[0].column(0).to_data().buffers()[0].as_ptr() as usize;
+        let second = ids.values().inner().as_ptr() as usize;
+        assert!(second > first && second - first < bytes as usize);
+
+        let stream_path = dir.path().join("events.ipc");
+        let mut writer = StreamWriter::try_new(File::create(&stream_path).unwrap(), &batch(0).schema()).unwrap();
+        writer.write(&batch(0)).unwrap();
+        writer.finish().unwrap();
+        let error = map_file(&stream_path).unwrap_err().to_string();
+        assert!(error.contains("not an Arrow IPC file"), "{}", error);
+        std::fs::write(dir.path().join("empty.arrow"), "").unwrap();
+        assert!(map_file(&dir.path().join("empty.arrow")).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Data quality assertions
+//!
+//! Supports BigQuery's `ASSERT <condition> [AS 'message']` statement, which
+//! fails the query when the condition is not TRUE, and a programmatic API
+//! that evaluates a list of checks and reports each one's outcome. A check
+//! with a `table` asserts that every row of the table satisfies the
+//! expression and reports how many rows do not; a check without one is a
+//! single boolean expression, typically over scalar subqueries.
+
+use std::sync::LazyLock;
+
+use datafusion::arrow::array::{Array, BooleanArray, Int64Array};
+use datafusion::arrow::record_batch::RecordBatch;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// A data quality check
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Assertion {
+    /// Label reported with the result
+    pub name: Option<String>,
+    /// Table whose rows must all satisfy `expression`
+    pub table: Option<String>,
+    /// Boolean SQL expression
+    pub expression: String,
+    /// Message reported when the check fails
+    pub message: Option<String>,
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub name: Option<String>,
+    pub table: Option<String>,
+    pub expression: String,
+    /// True only if the check evaluated and held
+    pub passed: bool,
+    /// Rows violating a table check
+    pub failing_rows: Option<u64>,
+    /// Failure message, if the check failed
+    pub message: Option<String>,
+    /// Evaluation error, if the check could not run
+    pub error: Option<String>,
+}
+
+impl Assertion {
+    /// Check that a single boolean expression holds
+    pub fn new(expression: impl Into<String>) -> Self {
+        Self {
+            expression: expression.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Check that every row of `table` satisfies the expression
+    pub fn for_table(table: impl Into<String>, expression: impl Into<String>) -> Self {
+        Self {
+            table: Some(table.into()),
+            ..Self::new(expression)
+        }
+    }
+
+    /// Query computing this check. Table checks count violating rows, where
+    /// a NULL result counts as a violation; scalar checks select the value.
+    pub fn to_sql(&self) -> String {
+        match &self.table {
+            Some(table) => format!(
+                "SELECT COUNT(*) FROM {} WHERE NOT COALESCE(CAST(({}) AS BOOLEAN), FALSE)",
+                table, self.expression
+            ),
+            None => format!("SELECT CAST(({}) AS BOOLEAN)", self.expression),
+        }
+    }
+
+    /// Interpret the batches produced by [`Self::to_sql`]
+    pub fn evaluate(&self, batches: &[RecordBatch]) -> BlazeResult<AssertionResult> {
+        let first = batches
+            .iter()
+            .find(|b| b.num_rows() > 0)
+            .ok_or_else(|| invalid_input!("Assertion '{}' produced no rows", self.expression))?;
+
+        let (passed, failing_rows) = match &self.table {
+            Some(_) => {
+                let counts = first.column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+                let failing = counts.value(0) as u64;
+                (failing == 0, Some(failing))
+            }
+            None => {
+                let values = first.column(0).as_any().downcast_ref::<BooleanArray>().unwrap();
+                (!values.is_null(0) && values.value(0), None)
+            }
+        };
+
+        Ok(AssertionResult {
+            name: self.name.clone(),
+            table: self.table.clone(),
+            expression: self.expression.clone(),
+            passed,
+            failing_rows,
+            message: (!passed).then(|| self.failure_message(failing_rows)),
+            error: None,
+        })
+    }
+
+    /// Result for a check that could not be evaluated
+    pub fn errored(&self, error: &BlazeError) -> AssertionResult {
+        AssertionResult {
+            name: self.name.clone(),
+            table: self.table.clone(),
+            expression: self.expression.clone(),
+            passed: false,
+            failing_rows: None,
+            message: Some(self.failure_message(None)),
+            error: Some(error.to_string()),
+        }
+    }
+
+    fn failure_message(&self, failing_rows: Option<u64>) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        match (&self.table, failing_rows) {
+            (Some(table), Some(rows)) => format!("{} rows of {} violate {}", rows, table, self.expression),
+            _ => format!("Assertion failed: {}", self.expression),
+        }
+    }
+}
+
+/// Parse `ASSERT <condition> [AS 'message']`
+pub fn parse_assert(sql: &str) -> Option<Assertion> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r#"(?is)^\s*ASSERT\s+(.+?)(?:\s+AS\s+('(?:[^'\\]|\\.)*'|"(?:[^"\\]|\\.)*"))?\s*;?\s*$"#,
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    let message = captures.get(2).map(|m| {
+        let quoted = m.as_str();
+        quoted[1..quoted.len() - 1].replace("\\'", "'").replace("\\\"", "\"")
+    });
+
+    Some(Assertion {
+        message,
+        ..Assertion::new(captures[1].trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_assert() {
+        let assertion = parse_assert("ASSERT (SELECT COUNT(*) FROM t) > 0 AS 'table t is empty';").unwrap();
+        assert_eq!(assertion.expression, "(SELECT COUNT(*) FROM t) > 0");
+        assert_eq!(assertion.message.as_deref(), Some("table t is empty"));
+
+        let assertion = parse_assert("assert CAST(x AS STRING) = 'a'").unwrap();
+        assert_eq!(assertion.expression, "CAST(x AS STRING) = 'a'");
+        assert!(assertion.message.is_none());
+
+        assert!(parse_assert("SELECT 1").is_none());
+    }
+}
@@ -0,0 +1,444 @@
+//! Loading Avro object container files
+//!
+//! `register_avro` reads Avro files, such as archived Kafka topics, into an
+//! in-memory table. The writer schema stored in each file is mapped to
+//! Arrow types:
+//!
+//! - `boolean`, `int`, `long`, `float`, `double`, `bytes` and `string` to
+//!   the Arrow types of the same width; `enum` and `uuid` to strings and
+//!   `fixed` to fixed-size binary
+//! - `record` to a struct, `array` to a list and `map` to a map with string
+//!   keys, sorted
+//! - a union of `null` and one other type to a nullable column of that
+//!   type; other unions are not supported
+//! - the logical types `date`, `time-millis`, `time-micros`,
+//!   `timestamp-*` (UTC), `local-timestamp-*` (no time zone) and `decimal`
+//!   up to precision 38
+//!
+//! Files compressed with deflate, snappy or zstandard are read. A directory
+//! is read as all the `.avro` files in it, which must map to the same
+//! schema.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use apache_avro::schema::{Name, NamesRef, ResolvedSchema, Schema as AvroSchema, SchemaKind};
+use apache_avro::types::Value;
+use datafusion::arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Date32Array, Decimal128Array, FixedSizeBinaryArray, Float32Array, Float64Array,
+    Int32Array, Int64Array, ListArray, MapArray, NullArray, StringArray, StructArray, Time32MillisecondArray,
+    Time64MicrosecondArray, TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
+};
+use datafusion::arrow::buffer::{NullBuffer, OffsetBuffer};
+use datafusion::arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Rows per loaded batch
+const BATCH_ROWS: usize = 8192;
+
+/// Outcome of loading Avro files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvroLoadReport {
+    /// Table the rows were loaded into
+    pub table_name: String,
+    /// Files read, sorted
+    pub files: Vec<String>,
+    pub rows_loaded: usize,
+    /// Column names with the types they were loaded as
+    pub columns: Vec<(String, String)>,
+}
+
+/// The files behind `path`: the file itself, or the `.avro` files in a
+/// directory
+pub(crate) fn avro_files(path: &Path) -> BlazeResult<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.is_file() && file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("avro")) {
+            files.push(file);
+        }
+    }
+    if files.is_empty() {
+        return Err(invalid_input!("No .avro files in '{}'", path.display()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read Avro files that map to the same Arrow schema into batches
+pub(crate) fn read_avro(files: &[PathBuf]) -> BlazeResult<(SchemaRef, Vec<RecordBatch>)> {
+    let mut schema: Option<(SchemaRef, &PathBuf)> = None;
+    let mut batches = Vec::new();
+    for file in files {
+        let in_file = |e: BlazeError| invalid_input!("Cannot read Avro file '{}': {}", file.display(), e);
+        let reader = apache_avro::Reader::new(BufReader::new(File::open(file)?))
+            .map_err(|e| invalid_input!("Cannot read Avro file '{}': {}", file.display(), e))?;
+        let file_schema = arrow_schema(reader.writer_schema()).map_err(in_file)?;
+        match &schema {
+            None => schema = Some((file_schema.clone(), file)),
+            Some((expected, first)) if *expected != file_schema => {
+                return Err(BlazeError::SchemaMismatch(format!(
+                    "'{}' has columns {} but '{}' has {}",
+                    file.display(),
+                    describe(&file_schema),
+                    first.display(),
+                    describe(expected)
+                )))
+            }
+            Some(_) => {}
+        }
+
+        let mut rows = Vec::with_capacity(BATCH_ROWS);
+        for value in reader {
+            rows.push(value.map_err(|e| invalid_input!("Cannot read Avro file '{}': {}", file.display(), e))?);
+            if rows.len() == BATCH_ROWS {
+                batches.push(to_batch(&file_schema, &rows).map_err(in_file)?);
+                rows.clear();
+            }
+        }
+        if !rows.is_empty() {
+            batches.push(to_batch(&file_schema, &rows).map_err(in_file)?);
+        }
+    }
+    let (schema, _) = schema.ok_or_else(|| invalid_input!("No Avro files to read"))?;
+    Ok((schema, batches))
+}
+
+fn describe(schema: &Schema) -> String {
+    let columns: Vec<_> = schema.fields().iter().map(|f| format!("{} {}", f.name(), f.data_type())).collect();
+    format!("({})", columns.join(", "))
+}
+
+/// The Arrow schema of a file whose records have `schema`
+fn arrow_schema(schema: &AvroSchema) -> BlazeResult<SchemaRef> {
+    let resolved = ResolvedSchema::try_from(schema).map_err(|e| invalid_input!("Invalid Avro schema: {}", e))?;
+    let AvroSchema::Record(record) = schema else {
+        return Err(invalid_input!("Avro files must hold records, got {:?}", SchemaKind::from(schema)));
+    };
+    let mut mapper = TypeMapper { names: resolved.get_names(), records: vec![record.name.clone()] };
+    let fields = record
+        .fields
+        .iter()
+        .map(|field| mapper.field(&field.name, &field.schema))
+        .collect::<BlazeResult<Vec<_>>>()?;
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+struct TypeMapper<'a> {
+    /// Named types, for resolving references to them
+    names: &'a NamesRef<'a>,
+    /// Records being mapped, to reject recursive types
+    records: Vec<Name>,
+}
+
+impl TypeMapper<'_> {
+    fn field(&mut self, name: &str, schema: &AvroSchema) -> BlazeResult<Field> {
+        let (schema, nullable) = match schema {
+            AvroSchema::Union(union) => match union.variants() {
+                [AvroSchema::Null, other] | [other, AvroSchema::Null] => (other, true),
+                [AvroSchema::Null] => (&AvroSchema::Null, true),
+                variants => {
+                    let kinds: Vec<_> = variants.iter().map(SchemaKind::from).collect();
+                    return Err(invalid_input!(
+                        "Avro field '{}' is a union of {:?}; only unions of null and one other type are supported",
+                        name,
+                        kinds
+                    ));
+                }
+            },
+            AvroSchema::Null => (schema, true),
+            other => (other, false),
+        };
+        Ok(Field::new(name, self.data_type(name, schema)?, nullable))
+    }
+
+    fn data_type(&mut self, name: &str, schema: &AvroSchema) -> BlazeResult<DataType> {
+        Ok(match schema {
+            AvroSchema::Null => DataType::Null,
+            AvroSchema::Boolean => DataType::Boolean,
+            AvroSchema::Int => DataType::Int32,
+            AvroSchema::Long => DataType::Int64,
+            AvroSchema::Float => DataType::Float32,
+            AvroSchema::Double => DataType::Float64,
+            AvroSchema::Bytes => DataType::Binary,
+            AvroSchema::String | AvroSchema::Uuid | AvroSchema::Enum(_) => DataType::Utf8,
+            AvroSchema::Fixed(fixed) => DataType::FixedSizeBinary(fixed.size as i32),
+            AvroSchema::Decimal(decimal) if decimal.precision <= 38 => {
+                DataType::Decimal128(decimal.precision as u8, decimal.scale as i8)
+            }
+            AvroSchema::Date => DataType::Date32,
+            AvroSchema::TimeMillis => DataType::Time32(TimeUnit::Millisecond),
+            AvroSchema::TimeMicros => DataType::Time64(TimeUnit::Microsecond),
+            AvroSchema::TimestampMillis => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            AvroSchema::TimestampMicros => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            AvroSchema::TimestampNanos => DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+            AvroSchema::LocalTimestampMillis => DataType::Timestamp(TimeUnit::Millisecond, None),
+            AvroSchema::LocalTimestampMicros => DataType::Timestamp(TimeUnit::Microsecond, None),
+            AvroSchema::LocalTimestampNanos => DataType::Timestamp(TimeUnit::Nanosecond, None),
+            AvroSchema::Array(array) => DataType::List(Arc::new(self.field("item", &array.items)?)),
+            AvroSchema::Map(map) => {
+                let entries = Fields::from(vec![Field::new("key", DataType::Utf8, false), self.field("value", &map.types)?]);
+                DataType::Map(Arc::new(Field::new("entries", DataType::Struct(entries), false)), false)
+            }
+            AvroSchema::Record(record) => {
+                if self.records.contains(&record.name) {
+                    return Err(invalid_input!("Avro field '{}' has recursive type '{}'", name, record.name));
+                }
+                self.records.push(record.name.clone());
+                let fields = record
+                    .fields
+                    .iter()
+                    .map(|field| self.field(&field.name, &field.schema))
+                    .collect::<BlazeResult<Vec<_>>>();
+                self.records.pop();
+                DataType::Struct(Fields::from(fields?))
+            }
+            AvroSchema::Ref { name: type_name } => {
+                let schema = *self
+                    .names
+                    .get(type_name)
+                    .ok_or_else(|| invalid_input!("Avro field '{}' refers to unknown type '{}'", name, type_name))?;
+                self.data_type(name, schema)?
+            }
+            other => {
+                return Err(invalid_input!("Avro field '{}' has unsupported type {:?}", name, SchemaKind::from(other)))
+            }
+        })
+    }
+}
+
+fn to_batch(schema: &SchemaRef, rows: &[Value]) -> BlazeResult<RecordBatch> {
+    let rows: Vec<_> = rows.iter().map(present).collect();
+    let columns = record_columns(schema.fields(), &rows)?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// A value with unions unwrapped; `None` for null
+fn present(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Null => None,
+        Value::Union(_, inner) => present(inner),
+        value => Some(value),
+    }
+}
+
+fn unexpected(data_type: &DataType, value: &Value) -> BlazeError {
+    BlazeError::SchemaMismatch(format!("expected a value for {}, got {:?}", data_type, value))
+}
+
+/// The columns of records' fields; null records have null fields
+fn record_columns(fields: &Fields, records: &[Option<&Value>]) -> BlazeResult<Vec<ArrayRef>> {
+    let mut columns = vec![Vec::with_capacity(records.len()); fields.len()];
+    for record in records {
+        match record {
+            Some(Value::Record(values)) if values.len() == fields.len() => {
+                for (column, (_, value)) in columns.iter_mut().zip(values) {
+                    column.push(present(value));
+                }
+            }
+            None => columns.iter_mut().for_each(|column| column.push(None)),
+            Some(value) => return Err(unexpected(&DataType::Struct(fields.clone()), value)),
+        }
+    }
+    fields.iter().zip(&columns).map(|(field, values)| build_array(field.data_type(), values)).collect()
+}
+
+/// Convert each present value with `convert`, which returns `None` for a
+/// value of the wrong kind
+fn convert<'a, T>(
+    data_type: &DataType,
+    values: &[Option<&'a Value>],
+    convert: impl Fn(&'a Value) -> Option<T>,
+) -> BlazeResult<Vec<Option<T>>> {
+    values
+        .iter()
+        .map(|value| value.map(|value| convert(value).ok_or_else(|| unexpected(data_type, value))).transpose())
+        .collect()
+}
+
+fn validity(values: &[Option<&Value>]) -> NullBuffer {
+    NullBuffer::from(values.iter().map(Option::is_some).collect::<Vec<_>>())
+}
+
+/// Two's complement big-endian bytes as an `i128`
+fn decimal_value(bytes: &[u8]) -> Option<i128> {
+    if bytes.len() > 16 {
+        return None;
+    }
+    let fill = if bytes.first().is_some_and(|byte| byte & 0x80 != 0) { 0xFF } else { 0 };
+    let mut value = [fill; 16];
+    value[16 - bytes.len()..].copy_from_slice(bytes);
+    Some(i128::from_be_bytes(value))
+}
+
+fn build_array(data_type: &DataType, values: &[Option<&Value>]) -> BlazeResult<ArrayRef> {
+    let timestamp = |value: &Value| match value {
+        Value::TimestampMillis(t)
+        | Value::TimestampMicros(t)
+        | Value::TimestampNanos(t)
+        | Value::LocalTimestampMillis(t)
+        | Value::LocalTimestampMicros(t)
+        | Value::LocalTimestampNanos(t) => Some(*t),
+        _ => None,
+    };
+    Ok(match data_type {
+        DataType::Null => Arc::new(NullArray::new(values.len())),
+        DataType::Boolean => Arc::new(BooleanArray::from(convert(data_type, values, |value| match value {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        })?)),
+        DataType::Int32 => Arc::new(Int32Array::from(convert(data_type, values, |value| match value {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        })?)),
+        DataType::Int64 => Arc::new(Int64Array::from(convert(data_type, values, |value| match value {
+            Value::Long(i) => Some(*i),
+            _ => None,
+        })?)),
+        DataType::Float32 => Arc::new(Float32Array::from(convert(data_type, values, |value| match value {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        })?)),
+        DataType::Float64 => Arc::new(Float64Array::from(convert(data_type, values, |value| match value {
+            Value::Double(f) => Some(*f),
+            _ => None,
+        })?)),
+        DataType::Utf8 => Arc::new(StringArray::from(convert(data_type, values, |value| match value {
+            Value::String(s) | Value::Enum(_, s) => Some(s.clone()),
+            Value::Uuid(uuid) => Some(uuid.to_string()),
+            _ => None,
+        })?)),
+        DataType::Binary => Arc::new(BinaryArray::from_iter(convert(data_type, values, |value| match value {
+            Value::Bytes(bytes) => Some(bytes.as_slice()),
+            _ => None,
+        })?)),
+        DataType::FixedSizeBinary(size) => {
+            let bytes = convert(data_type, values, |value| match value {
+                Value::Fixed(_, bytes) => Some(bytes.as_slice()),
+                _ => None,
+            })?;
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter_with_size(bytes.into_iter(), *size)?)
+        }
+        DataType::Decimal128(precision, scale) => {
+            let decimals = convert(data_type, values, |value| match value {
+                Value::Decimal(decimal) => Vec::<u8>::try_from(decimal).ok().and_then(|bytes| decimal_value(&bytes)),
+                _ => None,
+            })?;
+            Arc::new(Decimal128Array::from(decimals).with_precision_and_scale(*precision, *scale)?)
+        }
+        DataType::Date32 => Arc::new(Date32Array::from(convert(data_type, values, |value| match value {
+            Value::Date(days) => Some(*days),
+            _ => None,
+        })?)),
+        DataType::Time32(_) => Arc::new(Time32MillisecondArray::from(convert(data_type, values, |value| match value {
+            Value::TimeMillis(t) => Some(*t),
+            _ => None,
+        })?)),
+        DataType::Time64(_) => Arc::new(Time64MicrosecondArray::from(convert(data_type, values, |value| match value {
+            Value::TimeMicros(t) => Some(*t),
+            _ => None,
+        })?)),
+        DataType::Timestamp(unit, time_zone) => {
+            let times = convert(data_type, values, timestamp)?;
+            match unit {
+                TimeUnit::Millisecond => Arc::new(TimestampMillisecondArray::from(times).with_timezone_opt(time_zone.clone())),
+                TimeUnit::Microsecond => Arc::new(TimestampMicrosecondArray::from(times).with_timezone_opt(time_zone.clone())),
+                _ => Arc::new(TimestampNanosecondArray::from(times).with_timezone_opt(time_zone.clone())),
+            }
+        }
+        DataType::List(item) => {
+            let arrays = convert(data_type, values, |value| match value {
+                Value::Array(items) => Some(items),
+                _ => None,
+            })?;
+            let items: Vec<_> = arrays.iter().flatten().flat_map(|items| items.iter().map(present)).collect();
+            let lengths = arrays.iter().map(|items| items.map_or(0, |items| items.len()));
+            Arc::new(ListArray::try_new(
+                item.clone(),
+                OffsetBuffer::from_lengths(lengths),
+                build_array(item.data_type(), &items)?,
+                Some(validity(values)),
+            )?)
+        }
+        DataType::Map(entries, sorted) => {
+            let DataType::Struct(entry_fields) = entries.data_type() else {
+                return Err(BlazeError::Internal(format!("Map entries must be a struct, got {}", entries.data_type())));
+            };
+            let maps = convert(data_type, values, |value| match value {
+                Value::Map(map) => {
+                    let mut map: Vec<_> = map.iter().collect();
+                    map.sort_by_key(|(key, _)| *key);
+                    Some(map)
+                }
+                _ => None,
+            })?;
+            let keys = StringArray::from_iter_values(maps.iter().flatten().flatten().map(|(key, _)| key.as_str()));
+            let map_values: Vec<_> = maps.iter().flatten().flatten().map(|(_, value)| present(value)).collect();
+            let entries_array = StructArray::try_new(
+                entry_fields.clone(),
+                vec![Arc::new(keys), build_array(entry_fields[1].data_type(), &map_values)?],
+                None,
+            )?;
+            let lengths = maps.iter().map(|map| map.as_ref().map_or(0, Vec::len));
+            Arc::new(MapArray::try_new(
+                entries.clone(),
+                OffsetBuffer::from_lengths(lengths),
+                entries_array,
+                Some(validity(values)),
+                *sorted,
+            )?)
+        }
+        DataType::Struct(fields) => {
+            Arc::new(StructArray::try_new(fields.clone(), record_columns(fields, values)?, Some(validity(values)))?)
+        }
+        other => return Err(BlazeError::Internal(format!("Cannot load Avro values as {}", other))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_schema() {
+        let schema = AvroSchema::parse_str(
+            r#"{"type": "record", "name": "event", "namespace": "app", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "at", "type": {"type": "long", "logicalType": "timestamp-micros"}},
+                {"name": "price", "type": ["null", {"type": "bytes", "logicalType": "decimal", "precision": 10, "scale": 2}]},
+                {"name": "origin", "type": {"type": "record", "name": "point", "fields": [{"name": "x", "type": "double"}]}},
+                {"name": "target", "type": ["null", "point"]},
+                {"name": "labels", "type": {"type": "map", "values": "string"}}
+            ]}"#,
+        )
+        .unwrap();
+        let schema = arrow_schema(&schema).unwrap();
+        let types: Vec<_> = schema.fields().iter().map(|f| (f.data_type().to_string(), f.is_nullable())).collect();
+        assert_eq!(types[1], ("Timestamp(Microsecond, Some(\"UTC\"))".to_string(), false));
+        assert_eq!(types[2], ("Decimal128(10, 2)".to_string(), true));
+        assert_eq!(schema.field(3).data_type(), schema.field(4).data_type());
+        assert!(schema.field(4).is_nullable());
+        assert!(matches!(schema.field(5).data_type(), DataType::Map(_, _)));
+
+        let recursive = AvroSchema::parse_str(
+            r#"{"type": "record", "name": "node", "fields": [{"name": "next", "type": ["null", "node"]}]}"#,
+        )
+        .unwrap();
+        assert!(arrow_schema(&recursive).unwrap_err().to_string().contains("recursive"));
+        let union = AvroSchema::parse_str(r#"{"type": "record", "name": "r", "fields": [{"name": "v", "type": ["int", "string"]}]}"#)
+            .unwrap();
+        assert!(arrow_schema(&union).unwrap_err().to_string().contains("union"));
+
+        assert_eq!(decimal_value(&[0xFF, 0x38]), Some(-200));
+        assert_eq!(decimal_value(&[0x01, 0x00]), Some(256));
+    }
+}
@@ -0,0 +1,490 @@
+//! Baseline engines for benchmark comparisons
+//!
+//! A `BaselineRunner` loads the benchmark dataset into another engine and
+//! times the same queries there. DuckDB and Polars run in a Python worker
+//! process that times each query itself, so process start-up and I/O are not
+//! counted; ClickHouse is driven over its HTTP(S) interface and reports its
+//! own server-side elapsed time.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::{DataType, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+use crate::benchmarks::QueryPerformance;
+use crate::error::{BlazeError, BlazeResult};
+use crate::{config_error, invalid_input};
+
+/// An engine the benchmark suite compares against
+#[async_trait]
+pub trait BaselineRunner: Send + Sync {
+    /// Name reported in logs and results
+    fn name(&self) -> &str;
+
+    /// Load `batches` as table `table`, replacing any previous contents
+    async fn prepare(&self, table: &str, batches: &[RecordBatch]) -> BlazeResult<()>;
+
+    /// Run a query once and measure it
+    async fn run_query(&self, sql: &str) -> BlazeResult<QueryPerformance>;
+}
+
+/// Baseline selection in `BenchmarkConfig`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum BaselineConfig {
+    /// DuckDB through its Python package
+    DuckDb {
+        #[serde(default = "default_python")]
+        python: String,
+    },
+    /// Polars' SQL context through its Python package
+    Polars {
+        #[serde(default = "default_python")]
+        python: String,
+    },
+    /// A ClickHouse server's HTTP interface, e.g. `http://localhost:8123` or
+    /// `https://clickhouse.example.com:8443`
+    ClickHouse {
+        url: String,
+        #[serde(default = "default_clickhouse_database")]
+        database: String,
+        user: Option<String>,
+        password: Option<String>,
+    },
+}
+
+fn default_python() -> String {
+    "python3".to_string()
+}
+
+fn default_clickhouse_database() -> String {
+    "default".to_string()
+}
+
+impl BaselineConfig {
+    /// Start the configured baseline
+    pub async fn runner(&self) -> BlazeResult<Box<dyn BaselineRunner>> {
+        Ok(match self {
+            BaselineConfig::DuckDb { python } => Box::new(PythonBaseline::start(python, PythonBackend::DuckDb).await?),
+            BaselineConfig::Polars { python } => Box::new(PythonBaseline::start(python, PythonBackend::Polars).await?),
+            BaselineConfig::ClickHouse { url, database, user, password } => Box::new(ClickHouseBaseline::new(
+                url,
+                database,
+                user.clone(),
+                password.clone(),
+            )?),
+        })
+    }
+}
+
+/// Measurement for a query timed by the baseline itself; baselines do not
+/// report memory use
+fn performance(elapsed_ms: f64, rows: usize) -> QueryPerformance {
+    QueryPerformance {
+        execution_time_ms: elapsed_ms.round() as u64,
+        memory_used_bytes: 0,
+        rows_processed: rows,
+        rows_per_second: if elapsed_ms > 0.0 { rows as f64 * 1000.0 / elapsed_ms } else { 0.0 },
+        rows_per_mb: 0.0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PythonBackend {
+    DuckDb,
+    Polars,
+}
+
+impl PythonBackend {
+    fn name(&self) -> &'static str {
+        match self {
+            PythonBackend::DuckDb => "duckdb",
+            PythonBackend::Polars => "polars",
+        }
+    }
+}
+
+/// Worker loop: one JSON request per stdin line, one JSON reply per stdout line
+const PYTHON_WORKER: &str = r#"
+import json, sys, time
+backend = sys.argv[1]
+try:
+    if backend == "duckdb":
+        import duckdb
+        con = duckdb.connect()
+        def load(table, path):
+            con.execute(f'CREATE OR REPLACE TABLE "{table}" AS SELECT * FROM read_parquet(?)', [path])
+        def query(sql):
+            return len(con.execute(sql).fetchall())
+    else:
+        import polars as pl
+        ctx = pl.SQLContext()
+        def load(table, path):
+            ctx.register(table, pl.read_parquet(path))
+        def query(sql):
+            return ctx.execute(sql, eager=True).height
+except Exception as e:
+    print(json.dumps({"error": f"cannot start {backend}: {e}"}), flush=True)
+    sys.exit(1)
+print(json.dumps({"ready": True}), flush=True)
+for line in sys.stdin:
+    request = json.loads(line)
+    try:
+        start = time.perf_counter()
+        if request["op"] == "load":
+            load(request["table"], request["path"])
+            rows = 0
+        else:
+            rows = query(request["sql"])
+        print(json.dumps({"elapsed_ms": (time.perf_counter() - start) * 1000, "rows": rows}), flush=True)
+    except Exception as e:
+        print(json.dumps({"error": str(e)}), flush=True)
+"#;
+
+#[derive(Debug, Deserialize)]
+struct WorkerReply {
+    elapsed_ms: Option<f64>,
+    rows: Option<usize>,
+    error: Option<String>,
+}
+
+/// DuckDB or Polars running in a Python worker process
+pub struct PythonBaseline {
+    backend: PythonBackend,
+    worker: Mutex<(Child, ChildStdin, BufReader<ChildStdout>)>,
+    /// Parquet files handed to the worker, removed on drop
+    files: std::sync::Mutex<Vec<PathBuf>>,
+}
+
+impl PythonBaseline {
+    /// Start a worker; fails if Python or the backend's package is missing
+    pub async fn start(python: &str, backend: PythonBackend) -> BlazeResult<Self> {
+        let mut child = Command::new(python)
+            .args(["-c", PYTHON_WORKER, backend.name()])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| config_error!("Cannot start {} for the {} baseline: {}", python, backend.name(), e))?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+
+        let baseline = Self {
+            backend,
+            worker: Mutex::new((child, stdin, stdout)),
+            files: std::sync::Mutex::new(Vec::new()),
+        };
+        let mut worker = baseline.worker.lock().await;
+        let ready = Self::read_reply(backend, &mut worker.2).await?;
+        if let Some(error) = ready.error {
+            return Err(config_error!("{}", error));
+        }
+        drop(worker);
+        Ok(baseline)
+    }
+
+    async fn request(&self, request: serde_json::Value) -> BlazeResult<WorkerReply> {
+        let mut worker = self.worker.lock().await;
+        let (_, stdin, stdout) = &mut *worker;
+        stdin.write_all(format!("{}\n", request).as_bytes()).await?;
+        stdin.flush().await?;
+
+        let reply = Self::read_reply(self.backend, stdout).await?;
+        match reply.error {
+            Some(error) => Err(invalid_input!("{} baseline: {}", self.backend.name(), error)),
+            None => Ok(reply),
+        }
+    }
+
+    async fn read_reply(backend: PythonBackend, stdout: &mut BufReader<ChildStdout>) -> BlazeResult<WorkerReply> {
+        let mut line = String::new();
+        if stdout.read_line(&mut line).await? == 0 {
+            return Err(BlazeError::Internal(format!("The {} baseline worker exited", backend.name())));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+#[async_trait]
+impl BaselineRunner for PythonBaseline {
+    fn name(&self) -> &str {
+        self.backend.name()
+    }
+
+    async fn prepare(&self, table: &str, batches: &[RecordBatch]) -> BlazeResult<()> {
+        let path = std::env::temp_dir().join(format!(
+            "bqlite_{}_{}_{}.parquet",
+            self.backend.name(),
+            std::process::id(),
+            table
+        ));
+        write_parquet(&path, batches)?;
+        self.files.lock().unwrap().push(path.clone());
+
+        self.request(serde_json::json!({ "op": "load", "table": table, "path": path })).await?;
+        Ok(())
+    }
+
+    async fn run_query(&self, sql: &str) -> BlazeResult<QueryPerformance> {
+        let reply = self.request(serde_json::json!({ "op": "query", "sql": sql })).await?;
+        Ok(performance(reply.elapsed_ms.unwrap_or_default(), reply.rows.unwrap_or_default()))
+    }
+}
+
+impl Drop for PythonBaseline {
+    fn drop(&mut self) {
+        for path in self.files.lock().unwrap().drain(..) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn write_parquet(path: &std::path::Path, batches: &[RecordBatch]) -> BlazeResult<()> {
+    let schema = batches
+        .first()
+        .map(|b| b.schema())
+        .ok_or_else(|| invalid_input!("Cannot load an empty dataset into a baseline"))?;
+    let file = std::fs::File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, schema, None).map_err(parquet_error)?;
+    for batch in batches {
+        writer.write(batch).map_err(parquet_error)?;
+    }
+    writer.close().map_err(parquet_error)?;
+    Ok(())
+}
+
+fn parquet_error(e: datafusion::parquet::errors::ParquetError) -> BlazeError {
+    BlazeError::QueryExecution(e.into())
+}
+
+/// ClickHouse over HTTP(S), timed by the server's reported elapsed time
+pub struct ClickHouseBaseline {
+    client: reqwest::Client,
+    url: reqwest::Url,
+    database: String,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClickHouseStatistics {
+    elapsed: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClickHouseResult {
+    rows: usize,
+    statistics: ClickHouseStatistics,
+}
+
+impl ClickHouseBaseline {
+    /// Use the server at `http[s]://host[:port]`, port 8123 or 8443 when
+    /// not given; nothing is sent until the dataset is prepared
+    pub fn new(url: &str, database: &str, user: Option<String>, password: Option<String>) -> BlazeResult<Self> {
+        let mut parsed = reqwest::Url::parse(url).map_err(|e| invalid_input!("Invalid ClickHouse URL '{}': {}", url, e))?;
+        let default_port = match parsed.scheme() {
+            "http" => 8123,
+            "https" => 8443,
+            _ => return Err(invalid_input!("ClickHouse URL '{}' must start with http:// or https://", url)),
+        };
+        if parsed.port().is_none() {
+            parsed.set_port(Some(default_port)).map_err(|_| invalid_input!("Invalid ClickHouse URL '{}'", url))?;
+        }
+        let client = reqwest::Client::builder().build().map_err(clickhouse_error)?;
+        Ok(Self {
+            client,
+            url: parsed,
+            database: database.to_string(),
+            user,
+            password,
+        })
+    }
+
+    /// POST `body` with `query` as the statement, returning the response
+    /// body. Credentials go in headers rather than the URL, which servers
+    /// and proxies log.
+    async fn post(&self, query: &str, body: Vec<u8>) -> BlazeResult<Vec<u8>> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .query(&[("database", self.database.as_str()), ("query", query)])
+            .body(body);
+        if let Some(user) = &self.user {
+            request = request.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+
+        let response = request.send().await.map_err(clickhouse_error)?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(clickhouse_error)?;
+        if !status.is_success() {
+            return Err(BlazeError::External(format!(
+                "ClickHouse returned HTTP {}: {}",
+                status.as_u16(),
+                String::from_utf8_lossy(&body).trim()
+            )));
+        }
+        Ok(body.to_vec())
+    }
+}
+
+fn clickhouse_error(e: reqwest::Error) -> BlazeError {
+    BlazeError::External(format!("ClickHouse request failed: {}", e))
+}
+
+#[async_trait]
+impl BaselineRunner for ClickHouseBaseline {
+    fn name(&self) -> &str {
+        "clickhouse"
+    }
+
+    async fn prepare(&self, table: &str, batches: &[RecordBatch]) -> BlazeResult<()> {
+        let schema = batches
+            .first()
+            .map(|b| b.schema())
+            .ok_or_else(|| invalid_input!("Cannot load an empty dataset into a baseline"))?;
+        self.post(&format!("DROP TABLE IF EXISTS `{}`", table), Vec::new()).await?;
+        self.post(&clickhouse_create_table(table, &schema)?, Vec::new()).await?;
+
+        let mut parquet = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut parquet, schema, None).map_err(parquet_error)?;
+        for batch in batches {
+            writer.write(batch).map_err(parquet_error)?;
+        }
+        writer.close().map_err(parquet_error)?;
+        self.post(&format!("INSERT INTO `{}` FORMAT Parquet", table), parquet).await?;
+        Ok(())
+    }
+
+    async fn run_query(&self, sql: &str) -> BlazeResult<QueryPerformance> {
+        let body = self.post(&format!("{} FORMAT JSON", sql.trim().trim_end_matches(';')), Vec::new()).await?;
+        let result: ClickHouseResult = serde_json::from_slice(&body)?;
+        Ok(performance(result.statistics.elapsed * 1000.0, result.rows))
+    }
+}
+
+/// `CREATE TABLE` for an Arrow schema, using the Memory engine like the
+/// in-memory tables it is compared with
+fn clickhouse_create_table(table: &str, schema: &Schema) -> BlazeResult<String> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let column_type = match field.data_type() {
+                DataType::Boolean => "Bool".to_string(),
+                DataType::Int8 => "Int8".to_string(),
+                DataType::Int16 => "Int16".to_string(),
+                DataType::Int32 => "Int32".to_string(),
+                DataType::Int64 => "Int64".to_string(),
+                DataType::UInt8 => "UInt8".to_string(),
+                DataType::UInt16 => "UInt16".to_string(),
+                DataType::UInt32 => "UInt32".to_string(),
+                DataType::UInt64 => "UInt64".to_string(),
+                DataType::Float32 => "Float32".to_string(),
+                DataType::Float64 => "Float64".to_string(),
+                DataType::Utf8 | DataType::LargeUtf8 => "String".to_string(),
+                DataType::Date32 => "Date32".to_string(),
+                DataType::Decimal128(precision, scale) => format!("Decimal({}, {})", precision, scale),
+                DataType::Timestamp(unit, _) => {
+                    let digits = match unit {
+                        TimeUnit::Second => 0,
+                        TimeUnit::Millisecond => 3,
+                        TimeUnit::Microsecond => 6,
+                        TimeUnit::Nanosecond => 9,
+                    };
+                    format!("DateTime64({})", digits)
+                }
+                other => {
+                    return Err(invalid_input!(
+                        "Column '{}' has type {} that the ClickHouse baseline cannot load",
+                        field.name(),
+                        other
+                    ))
+                }
+            };
+            Ok(if field.is_nullable() {
+                format!("`{}` Nullable({})", field.name(), column_type)
+            } else {
+                format!("`{}` {}", field.name(), column_type)
+            })
+        })
+        .collect::<BlazeResult<Vec<_>>>()?;
+    Ok(format!("CREATE TABLE `{}` ({}) ENGINE = Memory", table, columns.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::Field;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_clickhouse_create_table() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("amount", DataType::Decimal128(10, 2), true),
+            Field::new("at", DataType::Timestamp(TimeUnit::Millisecond, None), false),
+        ]);
+        assert_eq!(
+            clickhouse_create_table("t", &schema).unwrap(),
+            "CREATE TABLE `t` (`id` Int64, `amount` Nullable(Decimal(10, 2)), `at` DateTime64(3)) ENGINE = Memory"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clickhouse_query_timing() {
+        // A stand-in server answering like ClickHouse's JSON format
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let n = socket.read(&mut request).await.unwrap();
+            let body = r#"{"meta":[],"data":[],"rows":3,"statistics":{"elapsed":0.0125,"rows_read":3}}"#;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request[..n]).to_string()
+        });
+
+        let url = format!("http://127.0.0.1:{}", port);
+        let baseline = ClickHouseBaseline::new(&url, "bench", Some("bench_user".into()), Some("s3cret&x".into())).unwrap();
+        let performance = baseline.run_query("SELECT 1;").await.unwrap();
+        assert_eq!(performance.rows_processed, 3);
+        assert_eq!(performance.execution_time_ms, 13);
+
+        // Credentials are sent as headers, never in the URL
+        let request = server.await.unwrap().to_ascii_lowercase();
+        assert!(request.starts_with("post /?database=bench&query=select+1+format+json http/1.1"), "{}", request);
+        assert!(request.contains("\r\nx-clickhouse-user: bench_user\r\n"), "{}", request);
+        assert!(request.contains("\r\nx-clickhouse-key: s3cret&x\r\n"), "{}", request);
+        assert!(!request.contains("password"), "{}", request);
+    }
+
+    #[test]
+    fn test_clickhouse_urls() {
+        let baseline = ClickHouseBaseline::new("https://clickhouse.example.com", "default", None, None).unwrap();
+        assert_eq!(baseline.url.as_str(), "https://clickhouse.example.com:8443/");
+        let baseline = ClickHouseBaseline::new("http://localhost", "default", None, None).unwrap();
+        assert_eq!(baseline.url.as_str(), "http://localhost:8123/");
+        let baseline = ClickHouseBaseline::new("http://localhost:9000/", "default", None, None).unwrap();
+        assert_eq!(baseline.url.port(), Some(9000));
+        assert!(ClickHouseBaseline::new("ftp://localhost", "default", None, None).is_err());
+        assert!(ClickHouseBaseline::new("localhost:8123", "default", None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_python_backend() {
+        let result = PythonBaseline::start("python3-does-not-exist", PythonBackend::DuckDb).await;
+        assert!(matches!(result, Err(BlazeError::Config(_))));
+    }
+}
@@ -1,14 +1,16 @@
-//! Benchmarking utilities for comparing performance with DuckDB baseline
+//! Benchmarking utilities for comparing performance with a baseline engine
 
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
-use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
-use crate::engine::{BlazeQueryEngine, QueryResult};
+use crate::baselines::{BaselineConfig, BaselineRunner};
+use crate::engine::{BlazeQueryEngine, EngineConfig};
 use crate::error::{BlazeError, BlazeResult};
-use crate::utils::{format_bytes, format_duration, PerformanceTracker};
 
 /// Benchmark configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +25,13 @@ pub struct BenchmarkConfig {
     pub memory_limit_bytes: usize,
     /// Maximum execution time allowed (milliseconds)
     pub time_limit_ms: u64,
+    /// Engine the queries are compared against; without one the speedup is
+    /// estimated from the query's performance tier
+    #[serde(default)]
+    pub baseline: Option<BaselineConfig>,
+    /// Concurrent load and failure injection used by `run_stress_test`
+    #[serde(default)]
+    pub stress: StressConfig,
 }
 
 /// Individual benchmark query
@@ -58,7 +67,7 @@ pub struct BenchmarkResult {
     pub dataset_size: usize,
     /// Blaze engine results
     pub blaze_results: Vec<QueryPerformance>,
-    /// Baseline (DuckDB) results for comparison
+    /// Baseline engine results for comparison
     pub baseline_results: Option<Vec<QueryPerformance>>,
     /// Performance improvement metrics
     pub performance_metrics: PerformanceMetrics,
@@ -98,16 +107,33 @@ pub struct PerformanceMetrics {
 pub struct BenchmarkSuite {
     config: BenchmarkConfig,
     engine: BlazeQueryEngine,
+    baseline: Option<Box<dyn BaselineRunner>>,
 }
 
 impl BenchmarkSuite {
     /// Create a new benchmark suite
     pub async fn new(config: BenchmarkConfig) -> BlazeResult<Self> {
         let engine = BlazeQueryEngine::new().await?;
+        let baseline = match &config.baseline {
+            Some(baseline) => Some(baseline.runner().await?),
+            None => None,
+        };
         
         Ok(Self {
             config,
             engine,
+            baseline,
+        })
+    }
+
+    /// Create a benchmark suite comparing against a custom baseline runner
+    pub async fn with_baseline(config: BenchmarkConfig, baseline: Box<dyn BaselineRunner>) -> BlazeResult<Self> {
+        let engine = BlazeQueryEngine::new().await?;
+
+        Ok(Self {
+            config,
+            engine,
+            baseline: Some(baseline),
         })
     }
 
@@ -160,11 +186,28 @@ impl BenchmarkSuite {
                 datafusion::error::DataFusionError::Plan("All benchmark iterations failed".to_string())
             ));
         }
+
+        // Run the same iterations on the baseline engine
+        let baseline_results = match &self.baseline {
+            Some(baseline) => {
+                let mut results = Vec::new();
+                for iteration in 0..self.config.iterations {
+                    match baseline.run_query(&query.sql).await {
+                        Ok(performance) => results.push(performance),
+                        Err(e) => {
+                            warn!("Baseline {} query {} iteration {} failed: {}", baseline.name(), query.name, iteration, e);
+                        }
+                    }
+                }
+                (!results.is_empty()).then_some(results)
+            }
+            None => None,
+        };
         
         // Calculate performance metrics
         let performance_metrics = self.calculate_performance_metrics(
             &blaze_results,
-            None, // No baseline comparison for now
+            baseline_results.as_deref(),
             &query,
             successful_runs as f64 / self.config.iterations as f64,
         );
@@ -173,15 +216,13 @@ impl BenchmarkSuite {
             query,
             dataset_size,
             blaze_results,
-            baseline_results: None,
+            baseline_results,
             performance_metrics,
         })
     }
 
     /// Run a single query and collect performance metrics
     async fn run_single_query(&self, sql: &str) -> BlazeResult<QueryPerformance> {
-        let start_time = Instant::now();
-        
         // Execute query
         let result = self.engine.execute_query(sql).await?;
         
@@ -254,7 +295,7 @@ impl BenchmarkSuite {
             
             let amount_array = Decimal128Array::from_iter_values(
                 (0..batch_rows).map(|_| rng.gen_range(100..10000))
-            );
+            ).with_precision_and_scale(10, 2)?;
             
             let timestamp_array = TimestampMillisecondArray::from_iter_values(
                 (0..batch_rows).map(|_| {
@@ -262,8 +303,8 @@ impl BenchmarkSuite {
                 })
             );
             
-            let flag_array = BooleanArray::from_iter_values(
-                (0..batch_rows).map(|_| rng.gen_bool(0.5))
+            let flag_array = BooleanArray::from(
+                (0..batch_rows).map(|_| rng.gen_bool(0.5)).collect::<Vec<_>>()
             );
 
             let batch = datafusion::arrow::record_batch::RecordBatch::try_new(
@@ -281,6 +322,12 @@ impl BenchmarkSuite {
             batches.push(batch);
         }
 
+        // Load the same data into the baseline
+        if let Some(baseline) = &self.baseline {
+            info!("Loading benchmark dataset into the {} baseline", baseline.name());
+            baseline.prepare("benchmark_data", &batches).await?;
+        }
+
         // Register the table
         self.engine.register_table("benchmark_data", batches).await?;
         info!("Benchmark dataset prepared successfully");
@@ -292,7 +339,7 @@ impl BenchmarkSuite {
     fn calculate_performance_metrics(
         &self,
         blaze_results: &[QueryPerformance],
-        _baseline_results: Option<&[QueryPerformance]>,
+        baseline_results: Option<&[QueryPerformance]>,
         query: &BenchmarkQuery,
         success_rate: f64,
     ) -> PerformanceMetrics {
@@ -305,20 +352,24 @@ impl BenchmarkSuite {
             .map(|r| r.memory_used_bytes as f64)
             .sum::<f64>() / blaze_results.len() as f64;
 
-        let avg_blaze_throughput = blaze_results.iter()
-            .map(|r| r.rows_per_second)
-            .sum::<f64>() / blaze_results.len() as f64;
-
-        // For now, assume baseline values since we don't have actual DuckDB comparison
-        // In a real implementation, you would run the same queries on DuckDB
-        let estimated_baseline_time = match query.expected_tier {
-            PerformanceTier::Simple => avg_blaze_time * 5.0,   // Assume 5x slower baseline
-            PerformanceTier::Medium => avg_blaze_time * 8.0,   // Assume 8x slower baseline
-            PerformanceTier::Complex => avg_blaze_time * 12.0, // Assume 12x slower baseline
+        // Without a baseline engine, estimate its time from the query's tier
+        let baseline_time = match baseline_results {
+            Some(results) => results.iter()
+                .map(|r| r.execution_time_ms as f64)
+                .sum::<f64>() / results.len() as f64,
+            None => match query.expected_tier {
+                PerformanceTier::Simple => avg_blaze_time * 5.0,   // Assume 5x slower baseline
+                PerformanceTier::Medium => avg_blaze_time * 8.0,   // Assume 8x slower baseline
+                PerformanceTier::Complex => avg_blaze_time * 12.0, // Assume 12x slower baseline
+            },
         };
 
-        let avg_speedup = estimated_baseline_time / avg_blaze_time;
-        let memory_efficiency = 1.0; // Placeholder
+        // Measured sub-millisecond runs are rounded up so the ratio stays finite
+        let avg_speedup = match baseline_results {
+            Some(_) => baseline_time.max(1.0) / avg_blaze_time.max(1.0),
+            None => baseline_time / avg_blaze_time,
+        };
+        let memory_efficiency = 1.0; // Placeholder: baselines do not report memory
         let throughput_improvement = avg_speedup; // Simplified
 
         // Check if requirements are met
@@ -365,6 +416,602 @@ impl Default for BenchmarkConfig {
             ],
             memory_limit_bytes: 2 * 1024 * 1024 * 1024, // 2GB
             time_limit_ms: 100, // 100ms for 1M+ row aggregations
+            baseline: None,
+            stress: StressConfig::default(),
         }
     }
-}
\ No newline at end of file
+}
+/// Stress test configuration: concurrent heavy queries alongside deliberate
+/// memory-limit breaches, mid-flight cancellations and table registrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StressConfig {
+    /// Concurrent workers running the heavy queries
+    pub concurrency: usize,
+    /// Passes each worker makes over the heavy queries
+    pub rounds: usize,
+    /// Rows in the `stress_data` table
+    pub dataset_rows: usize,
+    /// Memory pool size of the stressed engine, small enough for the
+    /// breach queries to exceed
+    pub memory_limit_bytes: usize,
+    /// Queries expected to succeed, though they may hit the memory limit
+    /// when many run at once
+    pub heavy_queries: Vec<String>,
+    /// Queries expected to exceed the memory limit, run once per round
+    pub breach_queries: Vec<String>,
+    /// Heavy queries started and then cancelled
+    pub cancellations: usize,
+    /// Delay before a started query is cancelled, in milliseconds
+    pub cancel_after_ms: u64,
+    /// Tables registered while the load runs
+    pub registrations: usize,
+    /// Slowest acceptable response to a trivial probe query during the
+    /// load, in milliseconds
+    pub probe_timeout_ms: u64,
+}
+
+/// Stress test outcome
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StressReport {
+    /// Statements that completed successfully, including probes
+    pub completed_queries: u64,
+    /// Statements rejected by the memory limit
+    pub memory_limit_errors: u64,
+    /// Statements that failed for any other reason
+    pub failed_queries: u64,
+    /// Statements cancelled before completing
+    pub cancelled_queries: u64,
+    /// Tables registered during the load
+    pub registered_tables: usize,
+    /// Probe queries run during the load
+    pub probes: u64,
+    /// Slowest probe response in milliseconds
+    pub max_probe_ms: u64,
+    /// One description per violated expectation
+    pub problems: Vec<String>,
+    /// The engine stayed responsive and consistent
+    pub passed: bool,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            rounds: 5,
+            dataset_rows: 200_000,
+            memory_limit_bytes: 4 * 1024 * 1024,
+            heavy_queries: vec![
+                "SELECT category, COUNT(*) AS n, AVG(value) AS avg_value FROM stress_data GROUP BY category".to_string(),
+                "SELECT COUNT(*) AS n FROM (SELECT SUM(value) OVER (PARTITION BY category ORDER BY id) AS running \
+                 FROM stress_data)".to_string(),
+                "SELECT COUNT(*) AS n FROM stress_data a JOIN stress_data b ON a.id % 500 = b.id % 500 \
+                 WHERE a.value < 5".to_string(),
+                "SELECT COUNT(*) AS n FROM (SELECT id, ARRAY_AGG(value) AS v FROM stress_data GROUP BY id)".to_string(),
+            ],
+            breach_queries: vec![
+                "SELECT COUNT(*) AS n FROM stress_data a JOIN stress_data b ON a.id = b.id".to_string(),
+            ],
+            cancellations: 10,
+            cancel_after_ms: 20,
+            registrations: 10,
+            probe_timeout_ms: 5_000,
+        }
+    }
+}
+
+/// How a stress test statement ended
+enum Outcome {
+    Completed,
+    MemoryLimit,
+    Failed,
+}
+
+impl BenchmarkSuite {
+    /// Run the stress configuration against a fresh engine limited to
+    /// `stress.memory_limit_bytes`, then check that the engine still answers
+    /// correctly and that its stats and memory pool are consistent
+    pub async fn run_stress_test(&self) -> BlazeResult<StressReport> {
+        let stress = &self.config.stress;
+        let engine = Arc::new(
+            BlazeQueryEngine::with_config(EngineConfig {
+                memory_limit_bytes: stress.memory_limit_bytes,
+                ..Default::default()
+            })
+            .await?,
+        );
+        let data = crate::datagen::generate(&crate::datagen::DatasetSpec::test_data(stress.dataset_rows))?;
+        engine.register_table("stress_data", data).await?;
+        info!(
+            "Starting stress test: {} workers x {} rounds, {} cancellations, {} registrations",
+            stress.concurrency, stress.rounds, stress.cancellations, stress.registrations
+        );
+
+        let completed = Arc::new(AtomicU64::new(0));
+        let memory_limit_errors = Arc::new(AtomicU64::new(0));
+        let failed = Arc::new(AtomicU64::new(0));
+        let record = {
+            let (completed, memory_limit_errors, failed) =
+                (completed.clone(), memory_limit_errors.clone(), failed.clone());
+            move |outcome: Outcome| match outcome {
+                Outcome::Completed => completed.fetch_add(1, Ordering::Relaxed),
+                Outcome::MemoryLimit => memory_limit_errors.fetch_add(1, Ordering::Relaxed),
+                Outcome::Failed => failed.fetch_add(1, Ordering::Relaxed),
+            }
+        };
+
+        let mut tasks = Vec::new();
+        for worker in 0..stress.concurrency {
+            let (engine, record, stress) = (engine.clone(), record.clone(), stress.clone());
+            tasks.push(tokio::spawn(async move {
+                for round in 0..stress.rounds {
+                    for sql in &stress.heavy_queries {
+                        record(run_stress_statement(&engine, sql).await);
+                    }
+                    if let Some(sql) = stress.breach_queries.get((worker + round) % stress.breach_queries.len().max(1)) {
+                        record(run_stress_statement(&engine, sql).await);
+                    }
+                }
+            }));
+        }
+
+        let cancelled = {
+            let (engine, record, stress) = (engine.clone(), record.clone(), stress.clone());
+            tokio::spawn(async move {
+                let mut cancelled = 0;
+                for i in 0..stress.cancellations {
+                    let Some(sql) = stress.heavy_queries.get(i % stress.heavy_queries.len().max(1)).cloned() else {
+                        break;
+                    };
+                    let engine = engine.clone();
+                    let query = tokio::spawn(async move { run_stress_statement(&engine, &sql).await });
+                    tokio::time::sleep(Duration::from_millis(stress.cancel_after_ms)).await;
+                    query.abort();
+                    match query.await {
+                        Ok(outcome) => {
+                            record(outcome);
+                        }
+                        Err(e) if e.is_cancelled() => cancelled += 1,
+                        Err(_) => {
+                            record(Outcome::Failed);
+                        }
+                    }
+                }
+                cancelled
+            })
+        };
+
+        let registrar = {
+            let (engine, record, registrations) = (engine.clone(), record.clone(), stress.registrations);
+            tokio::spawn(async move {
+                let mut registered = 0;
+                for i in 0..registrations {
+                    let name = format!("stress_table_{}", i);
+                    let batches = crate::datagen::generate(&crate::datagen::DatasetSpec::test_data(1_000))?;
+                    engine.register_table(&name, batches).await?;
+                    registered += 1;
+                    record(run_stress_statement(&engine, &format!("SELECT COUNT(*) AS n FROM {}", name)).await);
+                }
+                Ok::<_, BlazeError>(registered)
+            })
+        };
+
+        // Probe responsiveness until the load finishes
+        let load_done = Arc::new(AtomicBool::new(false));
+        let prober = {
+            let (engine, record, load_done) = (engine.clone(), record.clone(), load_done.clone());
+            tokio::spawn(async move {
+                let (mut probes, mut max_probe_ms) = (0u64, 0u64);
+                while !load_done.load(Ordering::Relaxed) {
+                    let start = Instant::now();
+                    record(run_stress_statement(&engine, "SELECT 1 AS probe").await);
+                    max_probe_ms = max_probe_ms.max(start.elapsed().as_millis() as u64);
+                    probes += 1;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                (probes, max_probe_ms)
+            })
+        };
+
+        let mut problems = Vec::new();
+        for task in tasks {
+            if let Err(e) = task.await {
+                problems.push(format!("Worker task failed: {}", e));
+            }
+        }
+        let cancelled_queries = cancelled.await.unwrap_or_else(|e| {
+            problems.push(format!("Cancellation task failed: {}", e));
+            0
+        });
+        let registered_tables = match registrar.await {
+            Ok(Ok(registered)) => registered,
+            Ok(Err(e)) => {
+                problems.push(format!("Registration during load failed: {}", e));
+                0
+            }
+            Err(e) => {
+                problems.push(format!("Registration task failed: {}", e));
+                0
+            }
+        };
+        load_done.store(true, Ordering::Relaxed);
+        let (probes, max_probe_ms) = prober.await.unwrap_or_else(|e| {
+            problems.push(format!("Probe task failed: {}", e));
+            (0, 0)
+        });
+
+        let mut report = StressReport {
+            completed_queries: completed.load(Ordering::Relaxed),
+            memory_limit_errors: memory_limit_errors.load(Ordering::Relaxed),
+            failed_queries: failed.load(Ordering::Relaxed),
+            cancelled_queries,
+            registered_tables,
+            probes,
+            max_probe_ms,
+            problems,
+            passed: false,
+        };
+        self.check_stress_invariants(&engine, &mut report).await;
+
+        for problem in &report.problems {
+            warn!("Stress test problem: {}", problem);
+        }
+        report.passed = report.problems.is_empty();
+        info!(
+            "Stress test finished: {} completed, {} over memory limit, {} cancelled, passed={}",
+            report.completed_queries, report.memory_limit_errors, report.cancelled_queries, report.passed
+        );
+        Ok(report)
+    }
+
+    /// Check the engine's state after a stress run
+    async fn check_stress_invariants(&self, engine: &BlazeQueryEngine, report: &mut StressReport) {
+        let stress = &self.config.stress;
+        let problems = &mut report.problems;
+
+        if report.failed_queries > 0 {
+            problems.push(format!("{} statements failed for reasons other than the memory limit", report.failed_queries));
+        }
+        if !stress.breach_queries.is_empty() && report.memory_limit_errors == 0 {
+            problems.push("No statement hit the memory limit; lower memory_limit_bytes".to_string());
+        }
+        if report.max_probe_ms > stress.probe_timeout_ms {
+            problems.push(format!(
+                "Engine was unresponsive: a probe took {}ms (limit {}ms)",
+                report.max_probe_ms, stress.probe_timeout_ms
+            ));
+        }
+
+        // Breached, cancelled and failed statements must release their memory
+        let reserved = engine.reserved_memory_bytes();
+        if reserved != 0 {
+            problems.push(format!("{} bytes remain reserved from the memory pool", reserved));
+        }
+
+        // Only statements that completed count as queries
+        let stats = engine.get_stats().await;
+        if stats.total_queries != report.completed_queries {
+            problems.push(format!(
+                "Stats report {} queries but {} completed",
+                stats.total_queries, report.completed_queries
+            ));
+        }
+        let tables = engine.list_tables().await.map(|t| t.len()).unwrap_or_default();
+        if stats.registered_tables != report.registered_tables + 1 || tables != stats.registered_tables {
+            problems.push(format!(
+                "Stats report {} registered tables, the catalog has {}, expected {}",
+                stats.registered_tables,
+                tables,
+                report.registered_tables + 1
+            ));
+        }
+
+        // The engine still answers correctly after the load
+        match engine.execute_query("SELECT COUNT(*) AS n FROM stress_data").await {
+            Ok(result) if result.data.first().and_then(|row| row.get("n")) == Some(&serde_json::json!(stress.dataset_rows)) => {}
+            Ok(result) => problems.push(format!("Wrong row count after the load: {:?}", result.data)),
+            Err(e) => problems.push(format!("Engine failed after the load: {}", e)),
+        }
+    }
+}
+
+async fn run_stress_statement(engine: &BlazeQueryEngine, sql: &str) -> Outcome {
+    match engine.execute_query(sql).await {
+        Ok(_) => Outcome::Completed,
+        Err(e) if e.to_string().contains("Resources exhausted") => Outcome::MemoryLimit,
+        Err(e) => {
+            warn!("Stress statement failed: {}: {}", sql, e);
+            Outcome::Failed
+        }
+    }
+}
+
+/// Soak test configuration: a mixed workload run for a long time while
+/// resource usage is sampled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoakConfig {
+    /// Total run time in milliseconds
+    pub duration_ms: u64,
+    /// Time between resource samples in milliseconds
+    pub sample_interval_ms: u64,
+    /// Rows in the `soak_data` table the workload queries
+    pub dataset_rows: usize,
+    /// Statements run round-robin for the whole test
+    pub workload: Vec<String>,
+    /// Samples ignored while caches and allocator arenas settle
+    pub warmup_samples: usize,
+    /// Relative RSS growth tolerated over the measured samples
+    pub rss_growth_tolerance: f64,
+    /// Absolute RSS growth always tolerated, in bytes
+    pub rss_growth_floor_bytes: u64,
+}
+
+/// Resource usage at one point of a soak test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoakSample {
+    /// Time since the workload started
+    pub elapsed_ms: u64,
+    /// Statements executed so far
+    pub statements: u64,
+    /// Resident set size of the process, where the platform reports it
+    pub rss_bytes: Option<u64>,
+    /// Bytes reserved from the engine's memory pool between statements
+    pub reserved_memory_bytes: u64,
+    /// Tables registered in the session catalog
+    pub catalog_tables: usize,
+}
+
+/// Soak test outcome
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoakReport {
+    pub samples: Vec<SoakSample>,
+    pub statements: u64,
+    pub failed_statements: u64,
+    /// One description per resource that grew steadily
+    pub leaks: Vec<String>,
+    /// No statement failed and no resource grew steadily
+    pub passed: bool,
+}
+
+/// Number of windows the measured samples are split into; a resource leaks
+/// when the mean of every window exceeds the one before it
+const SOAK_WINDOWS: usize = 4;
+
+/// Long-running leak detector
+pub struct SoakTest {
+    config: SoakConfig,
+    engine: BlazeQueryEngine,
+}
+
+impl SoakTest {
+    /// Create a soak test with a fresh engine
+    pub async fn new(config: SoakConfig) -> BlazeResult<Self> {
+        let samples = config.duration_ms / config.sample_interval_ms.max(1);
+        if config.sample_interval_ms == 0 || samples < (config.warmup_samples + 2 * SOAK_WINDOWS) as u64 {
+            return Err(BlazeError::Config(format!(
+                "A soak test needs at least {} samples after warmup; increase duration_ms or lower sample_interval_ms",
+                2 * SOAK_WINDOWS
+            )));
+        }
+        if config.workload.is_empty() {
+            return Err(BlazeError::Config("A soak test needs a workload".to_string()));
+        }
+
+        let engine = BlazeQueryEngine::new().await?;
+        Ok(Self { config, engine })
+    }
+
+    /// Run the workload for the configured duration and check the samples
+    /// for steady growth
+    pub async fn run(&self) -> BlazeResult<SoakReport> {
+        let data = crate::datagen::generate(&crate::datagen::DatasetSpec::test_data(self.config.dataset_rows))?;
+        self.engine.register_table("soak_data", data).await?;
+        info!(
+            "Starting {}s soak test with {} workload statements",
+            self.config.duration_ms / 1000,
+            self.config.workload.len()
+        );
+
+        let interval = Duration::from_millis(self.config.sample_interval_ms);
+        let duration = Duration::from_millis(self.config.duration_ms);
+        let start = Instant::now();
+        let mut next_sample = start;
+        let mut samples = Vec::new();
+        let mut statements = 0u64;
+        let mut failed_statements = 0u64;
+
+        for sql in self.config.workload.iter().cycle() {
+            let now = Instant::now();
+            if now >= next_sample {
+                let sample = self.sample(now - start, statements).await;
+                if samples.len() % 100 == 0 {
+                    info!(
+                        "Soak sample {}: {} statements, rss={:?} reserved={} tables={}",
+                        samples.len(),
+                        statements,
+                        sample.rss_bytes,
+                        sample.reserved_memory_bytes,
+                        sample.catalog_tables
+                    );
+                }
+                samples.push(sample);
+                next_sample += interval;
+                if now - start >= duration {
+                    break;
+                }
+            }
+
+            statements += 1;
+            if let Err(e) = self.engine.execute_query(sql).await {
+                failed_statements += 1;
+                warn!("Soak statement failed: {}: {}", sql, e);
+            }
+        }
+
+        let measured = &samples[self.config.warmup_samples.min(samples.len())..];
+        let mut leaks = Vec::new();
+        let rss: Vec<f64> = measured.iter().filter_map(|s| s.rss_bytes).map(|b| b as f64).collect();
+        if rss.len() == measured.len() {
+            let first = rss.first().copied().unwrap_or_default();
+            let tolerance = (first * self.config.rss_growth_tolerance).max(self.config.rss_growth_floor_bytes as f64);
+            if let Some(growth) = steady_growth(&rss, tolerance) {
+                leaks.push(format!("RSS grew steadily by {:.1} MiB", growth / (1024.0 * 1024.0)));
+            }
+        }
+        let reserved: Vec<f64> = measured.iter().map(|s| s.reserved_memory_bytes as f64).collect();
+        if let Some(growth) = steady_growth(&reserved, 0.0) {
+            leaks.push(format!("Memory pool reservations grew steadily by {} bytes", growth as u64));
+        }
+        let tables: Vec<f64> = measured.iter().map(|s| s.catalog_tables as f64).collect();
+        if let Some(growth) = steady_growth(&tables, 0.0) {
+            leaks.push(format!("Catalog grew steadily by {} tables", growth as u64));
+        }
+
+        for leak in &leaks {
+            warn!("Soak test detected a leak: {}", leak);
+        }
+        let passed = leaks.is_empty() && failed_statements == 0;
+        info!("Soak test finished after {} statements: passed={}", statements, passed);
+
+        Ok(SoakReport {
+            samples,
+            statements,
+            failed_statements,
+            leaks,
+            passed,
+        })
+    }
+
+    async fn sample(&self, elapsed: Duration, statements: u64) -> SoakSample {
+        SoakSample {
+            elapsed_ms: elapsed.as_millis() as u64,
+            statements,
+            rss_bytes: resident_set_bytes(),
+            reserved_memory_bytes: self.engine.reserved_memory_bytes() as u64,
+            catalog_tables: self.engine.catalog_size().await,
+        }
+    }
+}
+
+/// Default soak configuration: one hour of aggregations, wide result
+/// conversions, joins, windows and scratch table churn
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            duration_ms: 60 * 60 * 1000,
+            sample_interval_ms: 5_000,
+            dataset_rows: 100_000,
+            workload: vec![
+                "SELECT category, COUNT(*) AS n, AVG(value) AS avg_value FROM soak_data GROUP BY category".to_string(),
+                "SELECT * FROM soak_data WHERE value > 990 ORDER BY value DESC LIMIT 1000".to_string(),
+                "SELECT a.category, COUNT(*) AS n FROM soak_data a JOIN soak_data b ON a.id = b.id \
+                 WHERE a.value < 50 GROUP BY a.category".to_string(),
+                "SELECT id, SUM(value) OVER (PARTITION BY category ORDER BY id ROWS 10 PRECEDING) AS running \
+                 FROM soak_data WHERE id < 5000".to_string(),
+                "CREATE TABLE soak_scratch AS SELECT * FROM soak_data WHERE value < 100".to_string(),
+                "SELECT category, MAX(value) AS max_value FROM soak_scratch GROUP BY category".to_string(),
+                "DROP TABLE soak_scratch".to_string(),
+            ],
+            warmup_samples: 12,
+            rss_growth_tolerance: 0.1,
+            rss_growth_floor_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+/// Total growth of `values` if the mean of each window exceeds the previous
+/// one and the overall growth is more than `tolerance`
+fn steady_growth(values: &[f64], tolerance: f64) -> Option<f64> {
+    let window = values.len() / SOAK_WINDOWS;
+    if window == 0 {
+        return None;
+    }
+
+    let means: Vec<f64> = values
+        .chunks(window)
+        .take(SOAK_WINDOWS)
+        .map(|chunk| chunk.iter().sum::<f64>() / chunk.len() as f64)
+        .collect();
+    let growth = means[SOAK_WINDOWS - 1] - means[0];
+    let monotonic = means.windows(2).all(|pair| pair[1] > pair[0]);
+    (monotonic && growth > tolerance).then_some(growth)
+}
+
+/// Resident set size of the current process, read from `/proc` on Linux
+fn resident_set_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    /// Baseline that takes 50ms for every query and records what it loaded
+    struct FixedBaseline {
+        loaded: Arc<std::sync::Mutex<Vec<(String, usize)>>>,
+    }
+
+    #[async_trait]
+    impl BaselineRunner for FixedBaseline {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn prepare(&self, table: &str, batches: &[RecordBatch]) -> BlazeResult<()> {
+            let rows = batches.iter().map(|b| b.num_rows()).sum();
+            self.loaded.lock().unwrap().push((table.to_string(), rows));
+            Ok(())
+        }
+
+        async fn run_query(&self, _sql: &str) -> BlazeResult<QueryPerformance> {
+            Ok(QueryPerformance {
+                execution_time_ms: 50,
+                memory_used_bytes: 0,
+                rows_processed: 1,
+                rows_per_second: 20.0,
+                rows_per_mb: 0.0,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_baseline_runner() {
+        let loaded = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let config = BenchmarkConfig {
+            iterations: 2,
+            dataset_sizes: vec![1_000],
+            queries: BenchmarkConfig::default().queries.into_iter().take(1).collect(),
+            ..Default::default()
+        };
+        let suite = BenchmarkSuite::with_baseline(config, Box::new(FixedBaseline { loaded: loaded.clone() }))
+            .await
+            .unwrap();
+        let results = suite.run_benchmarks().await.unwrap();
+
+        assert_eq!(*loaded.lock().unwrap(), vec![("benchmark_data".to_string(), 1_000)]);
+        let result = &results[0];
+        assert_eq!(result.baseline_results.as_ref().map(Vec::len), Some(2));
+        let blaze_ms = result.blaze_results.iter().map(|r| r.execution_time_ms as f64).sum::<f64>() / 2.0;
+        assert_eq!(result.performance_metrics.avg_speedup, 50.0 / blaze_ms.max(1.0));
+    }
+
+    #[test]
+    fn test_steady_growth() {
+        // A slow climb is a leak; a plateau or noise is not
+        let climbing: Vec<f64> = (0..40).map(|i| 100.0 + i as f64).collect();
+        assert_eq!(steady_growth(&climbing, 10.0), Some(30.0));
+        assert_eq!(steady_growth(&climbing, 50.0), None);
+
+        let plateau: Vec<f64> = (0..40).map(|i| if i < 5 { i as f64 } else { 5.0 }).collect();
+        assert_eq!(steady_growth(&plateau, 0.0), None);
+
+        let noisy: Vec<f64> = (0..40).map(|i| 100.0 + (i % 7) as f64).collect();
+        assert_eq!(steady_growth(&noisy, 0.0), None);
+
+        assert_eq!(steady_growth(&[1.0, 2.0], 0.0), None);
+    }
+}
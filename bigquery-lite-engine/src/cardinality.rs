@@ -0,0 +1,150 @@
+//! Estimating how many rows a query produces before running it
+//!
+//! `BlazeQueryEngine::estimate_cardinality` plans a query and reports the
+//! rows each stage of the physical plan is expected to produce. Estimates
+//! come from the planner, which works from the statistics of the tables
+//! read: row counts of in-memory tables are exact, Parquet files contribute
+//! the counts in their footers, and filters apply a default selectivity.
+//!
+//! Where the planner gives up, as it does for most aggregates, a stage that
+//! cannot produce more rows than it reads is bounded by its largest input,
+//! so an estimate is only missing below joins and other stages that can
+//! multiply rows. Bounds and estimates through filters are marked inexact.
+
+use serde::{Deserialize, Serialize};
+
+use crate::plan_graph::{PlanGraph, PlanNode};
+
+/// The estimate for one operator of the plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageEstimate {
+    /// Position in a depth-first walk from the root, as in `PlanNode::id`
+    pub id: usize,
+    /// The stage this one feeds, None for the root
+    pub parent: Option<usize>,
+    /// Operator name, e.g. `HashJoinExec`
+    pub name: String,
+    /// The operator with its settings, as in `EXPLAIN`
+    pub description: String,
+    /// Estimated output rows, when they can be estimated
+    pub estimated_rows: Option<usize>,
+    /// Estimated output size in bytes, when the planner knows
+    pub estimated_bytes: Option<usize>,
+    /// Whether `estimated_rows` is exact
+    pub exact: bool,
+    /// Whether `estimated_rows` is an upper bound taken from the inputs
+    /// rather than the planner's own estimate
+    pub bounded_by_inputs: bool,
+}
+
+/// Estimated rows of a query and of every stage of its plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardinalityEstimate {
+    /// Estimated rows of the result
+    pub estimated_rows: Option<usize>,
+    /// Whether `estimated_rows` is exact
+    pub exact: bool,
+    /// The most rows any stage is estimated to produce, which tells how
+    /// much work the query is even when its result is small
+    pub max_stage_rows: Option<usize>,
+    /// Every stage, root first
+    pub stages: Vec<StageEstimate>,
+}
+
+impl CardinalityEstimate {
+    pub(crate) fn from_graph(graph: &PlanGraph) -> Self {
+        let mut stages = Vec::with_capacity(graph.node_count());
+        estimate(&graph.root, None, &mut stages);
+        stages.sort_by_key(|stage| stage.id);
+
+        let root = &stages[0];
+        Self {
+            estimated_rows: root.estimated_rows,
+            exact: root.exact,
+            max_stage_rows: stages.iter().filter_map(|stage| stage.estimated_rows).max(),
+            stages,
+        }
+    }
+}
+
+/// Estimate `node` after its inputs, returning its rows and exactness
+fn estimate(node: &PlanNode, parent: Option<usize>, stages: &mut Vec<StageEstimate>) -> (Option<usize>, bool) {
+    let inputs: Vec<_> = node.children.iter().map(|child| estimate(child, Some(node.id), stages)).collect();
+
+    let (estimated_rows, exact, bounded_by_inputs) = match node.estimated_rows {
+        Some(rows) => (Some(rows), node.stats_exact, false),
+        None if !node.children.is_empty() && !can_multiply_rows(&node.name) => {
+            let bound = inputs.iter().map(|(rows, _)| *rows).collect::<Option<Vec<_>>>();
+            let bound = bound.map(|rows| match node.name.as_str() {
+                // A union produces all of its inputs
+                "UnionExec" | "InterleaveExec" => rows.iter().sum(),
+                _ => rows.into_iter().max().unwrap_or(0),
+            });
+            (bound, false, bound.is_some())
+        }
+        None => (None, false, false),
+    };
+
+    stages.push(StageEstimate {
+        id: node.id,
+        parent,
+        name: node.name.clone(),
+        description: node.description.clone(),
+        estimated_rows,
+        estimated_bytes: node.estimated_bytes,
+        exact,
+        bounded_by_inputs,
+    });
+    (estimated_rows, exact)
+}
+
+/// Whether an operator can produce more rows than its largest input
+fn can_multiply_rows(name: &str) -> bool {
+    name.contains("Join") || name == "UnnestExec" || name.ends_with("TableFunctionExec")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: usize, name: &str, rows: Option<usize>, children: Vec<PlanNode>) -> PlanNode {
+        PlanNode {
+            id,
+            name: name.to_string(),
+            description: name.to_string(),
+            output_partitions: 1,
+            estimated_rows: rows,
+            estimated_bytes: None,
+            stats_exact: rows.is_some(),
+            metrics: None,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_bounds_from_inputs() {
+        let root = node(0, "AggregateExec", None, vec![
+            node(1, "HashJoinExec", None, vec![
+                node(2, "MemoryExec", Some(10), vec![]),
+                node(3, "MemoryExec", Some(20), vec![]),
+            ]),
+        ]);
+        let graph = PlanGraph { dot: String::new(), root, hints: Vec::new(), execution_time_ms: None };
+        let estimate = CardinalityEstimate::from_graph(&graph);
+        assert_eq!(estimate.estimated_rows, None);
+        assert_eq!(estimate.max_stage_rows, Some(20));
+        assert_eq!(estimate.stages.iter().map(|stage| stage.parent).collect::<Vec<_>>(), vec![None, Some(0), Some(1), Some(1)]);
+
+        let root = node(0, "AggregateExec", None, vec![
+            node(1, "UnionExec", None, vec![
+                node(2, "MemoryExec", Some(10), vec![]),
+                node(3, "MemoryExec", Some(20), vec![]),
+            ]),
+        ]);
+        let estimate = CardinalityEstimate::from_graph(&PlanGraph { dot: String::new(), root, hints: Vec::new(), execution_time_ms: None });
+        assert_eq!(estimate.estimated_rows, Some(30));
+        assert!(!estimate.exact);
+        assert!(estimate.stages[0].bounded_by_inputs);
+        assert!(estimate.stages[2].exact);
+    }
+}
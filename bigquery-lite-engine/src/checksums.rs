@@ -0,0 +1,144 @@
+//! Result checksums
+//!
+//! `QueryOptions::checksum` adds a checksum of the result set to
+//! `QueryResult::checksum`, so a regression harness can compare the results
+//! of engine versions, or of this engine and another one, without keeping
+//! the rows. The checksum is computed over a canonical form that is easy to
+//! reproduce elsewhere:
+//!
+//! - each row is a JSON object from column name to the value as text, or
+//!   `null`, with the keys sorted, written without whitespace and followed
+//!   by `\n`, e.g. `{"id":"7","name":"ada"}`
+//! - `ordered` hashes the rows in result order with 128-bit FNV-1a
+//! - `unordered` hashes each row on its own, sorts the hashes as lowercase
+//!   hex and hashes those, each followed by `\n`, so results that differ
+//!   only in row order (e.g. without `ORDER BY`) match
+//!
+//! The checksum is the 32-digit lowercase hex of the final hash. Values are
+//! compared as Arrow displays them: a column read as `BIGINT` by one engine
+//! and `INTEGER` by another gives the same checksum, while zoned timestamps
+//! are shown in the session time zone, which needs to match on both sides.
+
+use std::collections::BTreeMap;
+
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::time_zone;
+
+pub(crate) const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+/// How rows are combined into a result checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumMode {
+    /// Row order is part of the checksum
+    Ordered,
+    /// Results with the same rows in any order match
+    Unordered,
+}
+
+impl ChecksumMode {
+    /// Parse a mode name, `ordered` or `unordered`
+    pub fn parse(name: &str) -> BlazeResult<Self> {
+        match name.to_lowercase().as_str() {
+            "ordered" => Ok(Self::Ordered),
+            "unordered" => Ok(Self::Unordered),
+            _ => Err(invalid_input!("Unsupported checksum mode '{}'; expected ordered or unordered", name)),
+        }
+    }
+}
+
+/// Checksum of the rows of `batches` as described in the module
+/// documentation, zoned timestamps shown in `time_zone`
+pub(crate) fn checksum(batches: &[RecordBatch], time_zone: &str, mode: ChecksumMode) -> BlazeResult<String> {
+    let mut canonical = Vec::new();
+    for batch in batches {
+        let schema = batch.schema();
+        let mut columns = Vec::with_capacity(batch.num_columns());
+        for column in batch.columns() {
+            columns.push(match time_zone::render_temporal(column, time_zone)? {
+                Some(text) => text.iter().map(|value| value.map(str::to_string)).collect(),
+                None => {
+                    let formatter = ArrayFormatter::try_new(column.as_ref(), &FormatOptions::default())?;
+                    (0..column.len())
+                        .map(|row| (!column.is_null(row)).then(|| formatter.value(row).to_string()))
+                        .collect::<Vec<_>>()
+                }
+            });
+        }
+        for row in 0..batch.num_rows() {
+            let sorted: BTreeMap<_, _> = schema
+                .fields()
+                .iter()
+                .map(|field| field.name())
+                .zip(columns.iter().map(|values| &values[row]))
+                .collect();
+            let mut line = serde_json::to_vec(&sorted)?;
+            line.push(b'\n');
+            canonical.push(line);
+        }
+    }
+
+    let hash = match mode {
+        ChecksumMode::Ordered => canonical.iter().fold(OFFSET_BASIS, |hash, line| fnv1a(hash, line)),
+        ChecksumMode::Unordered => {
+            let mut hashes: Vec<_> = canonical.iter().map(|line| format!("{:032x}\n", fnv1a(OFFSET_BASIS, line))).collect();
+            hashes.sort_unstable();
+            hashes.iter().fold(OFFSET_BASIS, |hash, line| fnv1a(hash, line.as_bytes()))
+        }
+    };
+    Ok(format!("{:032x}", hash))
+}
+
+pub(crate) fn fnv1a(hash: u128, bytes: &[u8]) -> u128 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u128).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use datafusion::arrow::array::{Int32Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    fn batch(ids: Vec<i64>, names: Vec<Option<&str>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("id", DataType::Int64, false),
+        ]));
+        RecordBatch::try_new(schema, vec![Arc::new(StringArray::from(names)), Arc::new(Int64Array::from(ids))]).unwrap()
+    }
+
+    #[test]
+    fn test_checksum_modes() {
+        let rows = [batch(vec![1, 2], vec![Some("a"), None])];
+        let reversed = [batch(vec![2], vec![None]), batch(vec![1], vec![Some("a")])];
+
+        let ordered = checksum(&rows, "UTC", ChecksumMode::Ordered).unwrap();
+        let expected = fnv1a(OFFSET_BASIS, b"{\"id\":\"1\",\"name\":\"a\"}\n{\"id\":\"2\",\"name\":null}\n");
+        assert_eq!(ordered, format!("{:032x}", expected));
+        assert_ne!(ordered, checksum(&reversed, "UTC", ChecksumMode::Ordered).unwrap());
+
+        let unordered = checksum(&rows, "UTC", ChecksumMode::Unordered).unwrap();
+        assert_eq!(unordered, checksum(&reversed, "UTC", ChecksumMode::Unordered).unwrap());
+        assert_eq!(unordered.len(), 32);
+
+        // Narrower integer types give the same text
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let narrow = RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![5]))]).unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let wide = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![5]))]).unwrap();
+        assert_eq!(
+            checksum(&[narrow], "UTC", ChecksumMode::Ordered).unwrap(),
+            checksum(&[wide], "UTC", ChecksumMode::Ordered).unwrap()
+        );
+
+        assert_eq!(ChecksumMode::parse("Unordered").unwrap(), ChecksumMode::Unordered);
+        assert!(ChecksumMode::parse("sorted").is_err());
+    }
+}
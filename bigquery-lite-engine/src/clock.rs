@@ -0,0 +1,79 @@
+//! The engine clock
+//!
+//! `CURRENT_TIMESTAMP`/`NOW()`, `CURRENT_DATE`, `CURRENT_TIME`, the expiry
+//! of result tables and source freshness checks read the time from the
+//! engine clock instead of the system clock. By default it is the system
+//! clock; `BlazeQueryEngine::set_clock` can pin it to a fixed instant, so
+//! golden-file tests of time-dependent queries give the same results on
+//! every run, or shift it by an offset to replay a workload as of another
+//! time. A fixed clock does not advance until it is set again.
+
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Where the engine reads the current time from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clock {
+    /// The system clock
+    #[default]
+    System,
+    /// Always this instant
+    Fixed(DateTime<Utc>),
+    /// The system clock shifted by this much; negative offsets go back
+    Offset(Duration),
+}
+
+impl Clock {
+    /// The time this clock shows when the system clock shows `instant`
+    pub fn adjust(&self, instant: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Clock::System => instant,
+            Clock::Fixed(at) => *at,
+            Clock::Offset(offset) => instant + *offset,
+        }
+    }
+
+    /// The current time on this clock
+    pub fn now(&self) -> DateTime<Utc> {
+        self.adjust(Utc::now())
+    }
+}
+
+/// The clock of an engine, shared with the functions reading it
+#[derive(Debug, Default)]
+pub(crate) struct SharedClock(RwLock<Clock>);
+
+impl SharedClock {
+    pub(crate) fn get(&self) -> Clock {
+        *self.0.read().unwrap()
+    }
+
+    pub(crate) fn set(&self, clock: Clock) {
+        *self.0.write().unwrap() = clock;
+    }
+
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.get().now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_fixed_and_offset_clocks() {
+        let instant = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let fixed = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(Clock::System.adjust(instant), instant);
+        assert_eq!(Clock::Fixed(fixed).adjust(instant), fixed);
+        assert_eq!(Clock::Offset(Duration::hours(-36)).adjust(instant).to_rfc3339(), "2024-02-29T00:00:00+00:00");
+
+        let shared = SharedClock::default();
+        assert_eq!(shared.get(), Clock::System);
+        shared.set(Clock::Fixed(fixed));
+        assert_eq!(shared.now(), fixed);
+    }
+}
@@ -0,0 +1,167 @@
+//! Loading `EngineConfig` from TOML files and environment variables
+//!
+//! Settings are resolved in increasing order of precedence:
+//!
+//! 1. built-in defaults
+//! 2. a TOML file, given explicitly or through `BQLITE_ENGINE_CONFIG`
+//! 3. `BQLITE_ENGINE_<SETTING>` environment variables, e.g.
+//!    `BQLITE_ENGINE_MEMORY_LIMIT_BYTES=4294967296`
+//!
+//! Settings changed in code afterwards, including through `update_config`,
+//! win over all of these. The file uses the field names of `EngineConfig`
+//! as top-level keys; every key is optional.
+
+use std::path::Path;
+
+use crate::config_error;
+use crate::engine::EngineConfig;
+use crate::error::{BlazeError, BlazeResult};
+use crate::time_zone;
+
+/// Prefix of environment variables overriding individual settings
+pub const ENV_PREFIX: &str = "BQLITE_ENGINE_";
+
+/// Environment variable naming a TOML file for `EngineConfig::load`
+pub const CONFIG_PATH_ENV: &str = "BQLITE_ENGINE_CONFIG";
+
+impl EngineConfig {
+    /// Read a TOML file over the defaults
+    pub fn from_file(path: impl AsRef<Path>) -> BlazeResult<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| config_error!("Cannot read engine config file {}: {}", path.display(), e))?;
+        Self::from_toml(&text).map_err(|e| config_error!("{}: {}", path.display(), config_message(e)))
+    }
+
+    /// Parse TOML settings over the defaults
+    pub fn from_toml(text: &str) -> BlazeResult<Self> {
+        let config: Self = toml::from_str(text).map_err(|e| config_error!("{}", e.to_string().trim_end()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The defaults with `BQLITE_ENGINE_*` environment variables applied
+    pub fn from_env() -> BlazeResult<Self> {
+        Self::default().with_env_overrides()
+    }
+
+    /// Defaults, then the TOML file at `path` (or `BQLITE_ENGINE_CONFIG` when
+    /// `path` is `None`), then environment variables
+    pub fn load(path: Option<&Path>) -> BlazeResult<Self> {
+        let env_path = std::env::var_os(CONFIG_PATH_ENV);
+        let config = match path.or(env_path.as_deref().map(Path::new)) {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.with_env_overrides()
+    }
+
+    /// Apply `BQLITE_ENGINE_*` environment variables over these settings
+    pub fn with_env_overrides(self) -> BlazeResult<Self> {
+        self.with_overrides(|name| std::env::var(name).ok())
+    }
+
+    /// Apply overrides looked up by environment variable name
+    fn with_overrides(self, lookup: impl Fn(&str) -> Option<String>) -> BlazeResult<Self> {
+        let mut settings = serde_json::to_value(&self)?;
+        let fields = settings.as_object_mut().expect("EngineConfig serializes to an object");
+
+        for (name, value) in fields.iter_mut() {
+            let var = format!("{}{}", ENV_PREFIX, name.to_uppercase());
+            let Some(text) = lookup(&var) else { continue };
+            let text = text.trim();
+            *value = match value {
+                // Unset optional settings are paths
+                serde_json::Value::String(_) | serde_json::Value::Null => text.into(),
+                serde_json::Value::Bool(_) => match text.to_lowercase().as_str() {
+                    "true" | "1" | "yes" | "on" => true.into(),
+                    "false" | "0" | "no" | "off" => false.into(),
+                    _ => return Err(config_error!("{} must be true or false, got '{}'", var, text)),
+                },
+                _ => text
+                    .parse::<u64>()
+                    .map_err(|_| config_error!("{} must be a non-negative integer, got '{}'", var, text))?
+                    .into(),
+            };
+        }
+
+        let config: Self = serde_json::from_value(settings)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check settings the engine cannot run with
+    pub fn validate(&self) -> BlazeResult<()> {
+        for (name, value) in [
+            ("batch_size", self.batch_size),
+            ("memory_limit_bytes", self.memory_limit_bytes),
+            ("cpu_cores", self.cpu_cores),
+            ("max_snapshots_per_table", self.max_snapshots_per_table),
+            ("result_table_ttl_secs", self.result_table_ttl_secs as usize),
+            ("object_cache_max_bytes", self.object_cache_max_bytes as usize),
+            ("result_cache_ttl_secs", self.result_cache_ttl_secs as usize),
+        ] {
+            if value == 0 {
+                return Err(config_error!("{} must be greater than 0", name));
+            }
+        }
+        if !(1..=100).contains(&self.table_memory_percent) {
+            return Err(config_error!("table_memory_percent must be between 1 and 100"));
+        }
+        if self.azure_access_key.is_some() && self.azure_sas_token.is_some() {
+            return Err(config_error!("Set at most one of azure_access_key and azure_sas_token"));
+        }
+        time_zone::validate_time_zone(&self.time_zone)
+    }
+}
+
+/// The message of a configuration error without its "Configuration error" prefix
+fn config_message(error: BlazeError) -> String {
+    match error {
+        BlazeError::Config(message) => message,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_toml_over_defaults() {
+        let config = EngineConfig::from_toml("cpu_cores = 3\nenable_snapshots = false\n").unwrap();
+        assert_eq!(config.cpu_cores, 3);
+        assert!(!config.enable_snapshots);
+        assert_eq!(config.batch_size, EngineConfig::default().batch_size);
+
+        let typo = EngineConfig::from_toml("cpu_core = 3").unwrap_err().to_string();
+        assert!(typo.contains("unknown field `cpu_core`"), "{}", typo);
+        let invalid = EngineConfig::from_toml("batch_size = 0").unwrap_err().to_string();
+        assert!(invalid.contains("batch_size must be greater than 0"), "{}", invalid);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("engine.toml");
+        std::fs::write(&path, "memory_limit_bytes = \"lots\"\n").unwrap();
+        let error = EngineConfig::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("engine.toml") && error.contains("line 1"), "{}", error);
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let base = EngineConfig::from_toml("cpu_cores = 3\nbatch_size = 1024\n").unwrap();
+        let env: HashMap<_, _> = [
+            ("BQLITE_ENGINE_CPU_CORES", "6"),
+            ("BQLITE_ENGINE_ENABLE_OPTIMIZATION", "off"),
+        ]
+        .into();
+        let config = base.clone().with_overrides(|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!((config.cpu_cores, config.batch_size), (6, 1024));
+        assert!(!config.enable_optimization);
+
+        let error = base
+            .with_overrides(|name| (name == "BQLITE_ENGINE_BATCH_SIZE").then(|| "big".to_string()))
+            .unwrap_err();
+        assert!(error.to_string().contains("BQLITE_ENGINE_BATCH_SIZE must be a non-negative integer, got 'big'"));
+    }
+}
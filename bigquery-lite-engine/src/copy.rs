@@ -0,0 +1,292 @@
+//! `COPY` statements for bulk export and import
+//!
+//! ```sql
+//! COPY trips TO '/data/trips.parquet' (FORMAT PARQUET);
+//! COPY (SELECT * FROM trips WHERE fare > 10) TO '/data/big.csv' (FORMAT CSV, HEADER true);
+//! COPY trips FROM '/data/more_trips.csv' (FORMAT CSV, DELIMITER ';');
+//! ```
+//!
+//! `COPY ... TO` is translated into DataFusion's own `COPY ... STORED AS`,
+//! which is also accepted as written. `COPY table FROM` appends the file's
+//! rows to an existing table, casting columns by position to the table's
+//! types, or creates the table if it does not exist. When `FORMAT` is
+//! omitted it is taken from the file extension; CSV and JSON files named
+//! with a `.gz` or `.zst` suffix are compressed or decompressed to match,
+//! unless `COMPRESSION` says otherwise. Both return the number of
+//! rows copied as `count`; `COPY ... TO` also returns the `bytes` written,
+//! and `QueryResult` carries both as `rows_written` and `bytes_written`.
+
+use std::sync::LazyLock;
+
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::logical_expr::{cast, col};
+use datafusion::prelude::{CsvReadOptions, DataFrame, NdJsonReadOptions, ParquetReadOptions, SessionContext};
+use regex::Regex;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::file_tables::{compression_type, split_compression, DataFormat};
+use crate::invalid_input;
+use crate::utils::{matching_paren, split_top_level};
+
+/// What is copied to a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CopySource {
+    Table(String),
+    Query(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyDirection {
+    To,
+    From,
+}
+
+/// Parsed `COPY` statement
+#[derive(Debug, Clone)]
+pub struct CopyStatement {
+    pub source: CopySource,
+    pub direction: CopyDirection,
+    pub path: String,
+    pub format: DataFormat,
+    /// Whether CSV files have a header row (default: true)
+    pub header: bool,
+    /// CSV field delimiter (default: `,`)
+    pub delimiter: u8,
+    /// Compression codec for written files, e.g. `zstd(3)` or `gzip`
+    pub compression: Option<String>,
+}
+
+/// Parse `COPY <table | (query)> TO | FROM 'path' [[WITH] (option [value], ...)]`.
+/// Returns `None` for statements left to DataFusion, such as `COPY ... TO`
+/// with `STORED AS`.
+pub fn parse_copy(sql: &str) -> BlazeResult<Option<CopyStatement>> {
+    static HEADER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^\s*COPY\s+").unwrap());
+    let Some(header) = HEADER.find(sql) else {
+        return Ok(None);
+    };
+    let rest = &sql[header.end()..];
+
+    let (source, rest) = if rest.starts_with('(') {
+        let close = matching_paren(rest, 0).ok_or_else(|| invalid_input!("Unbalanced parentheses in COPY"))?;
+        (CopySource::Query(rest[1..close].trim().to_string()), &rest[close + 1..])
+    } else {
+        static NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z_][\w.]*").unwrap());
+        let Some(name) = NAME.find(rest) else {
+            return Ok(None);
+        };
+        (CopySource::Table(name.as_str().to_string()), &rest[name.end()..])
+    };
+
+    static TAIL: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)^\s+(TO|FROM)\s+'((?:[^']|'')*)'\s*(.*?)\s*;?\s*$").unwrap());
+    let Some(captures) = TAIL.captures(rest) else {
+        return Ok(None);
+    };
+    let direction = if captures[1].eq_ignore_ascii_case("TO") {
+        CopyDirection::To
+    } else {
+        CopyDirection::From
+    };
+    let path = captures[2].replace("''", "'");
+
+    static OPTION_LIST: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^(?:WITH\s*)?\((.*)\)$").unwrap());
+    let options = match captures[3].trim() {
+        "" => Vec::new(),
+        text => match OPTION_LIST.captures(text) {
+            Some(list) => parse_options(&list[1])?,
+            None if direction == CopyDirection::To => return Ok(None),
+            None => return Err(invalid_input!("Expected (option value, ...) after COPY FROM path, got '{}'", text)),
+        },
+    };
+
+    if direction == CopyDirection::From && matches!(source, CopySource::Query(_)) {
+        return Err(invalid_input!("COPY FROM needs a table name, not a query"));
+    }
+
+    let mut statement = CopyStatement {
+        source,
+        direction,
+        format: DataFormat::from_path(&path).unwrap_or(DataFormat::Parquet),
+        path,
+        header: true,
+        delimiter: b',',
+        compression: None,
+    };
+    let mut format_given = false;
+    for (key, value) in options {
+        let value = value.as_deref();
+        match (key.as_str(), value) {
+            ("format", Some(name)) => {
+                statement.format = DataFormat::parse(name)?;
+                format_given = true;
+            }
+            ("header", None) => statement.header = true,
+            ("header", Some(flag)) => statement.header = parse_bool(flag)?,
+            ("delimiter", Some(text)) if text.len() == 1 => statement.delimiter = text.as_bytes()[0],
+            ("delimiter", _) => return Err(invalid_input!("COPY DELIMITER must be a single character")),
+            ("compression", Some(codec)) if direction == CopyDirection::To => {
+                statement.compression = Some(codec.to_string())
+            }
+            (key, _) => return Err(invalid_input!("Unsupported COPY option '{}'", key.to_uppercase())),
+        }
+    }
+    if !format_given && DataFormat::from_path(&statement.path).is_none() {
+        return Err(invalid_input!(
+            "Cannot infer the format of '{}'; add (FORMAT PARQUET | CSV | JSON)",
+            statement.path
+        ));
+    }
+    if direction == CopyDirection::To && statement.compression.is_none() && statement.format != DataFormat::Parquet {
+        statement.compression = split_compression(&statement.path).1.map(|compression| compression.name().to_string());
+    }
+
+    Ok(Some(statement))
+}
+
+/// Lowercased option names with their unquoted values
+fn parse_options(text: &str) -> BlazeResult<Vec<(String, Option<String>)>> {
+    split_top_level(text, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|option| !option.is_empty())
+        .map(|option| {
+            let (key, value) = match option.split_once(char::is_whitespace) {
+                Some((key, value)) => (key, Some(value.trim())),
+                None => (option, None),
+            };
+            let value = value.map(|v| match v.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+                Some(quoted) => quoted.replace("''", "'"),
+                None => v.to_string(),
+            });
+            if key.is_empty() {
+                return Err(invalid_input!("Invalid COPY option '{}'", option));
+            }
+            Ok((key.to_lowercase(), value))
+        })
+        .collect()
+}
+
+fn parse_bool(text: &str) -> BlazeResult<bool> {
+    match text.to_lowercase().as_str() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(invalid_input!("Expected true or false, got '{}'", text)),
+    }
+}
+
+impl CopyStatement {
+    /// The equivalent DataFusion `COPY ... TO` statement
+    pub fn to_datafusion_sql(&self) -> String {
+        let source = match &self.source {
+            CopySource::Table(name) => name.clone(),
+            CopySource::Query(query) => format!("({})", query),
+        };
+        let mut options = Vec::new();
+        if self.format == DataFormat::Csv {
+            options.push(format!("'format.has_header' '{}'", self.header));
+            options.push(format!("'format.delimiter' '{}'", (self.delimiter as char).to_string().replace('\'', "''")));
+        }
+        if let Some(codec) = &self.compression {
+            options.push(format!("'format.compression' '{}'", codec.replace('\'', "''")));
+        }
+
+        let mut sql = format!(
+            "COPY {} TO '{}' STORED AS {}",
+            source,
+            self.path.replace('\'', "''"),
+            self.format.stored_as()
+        );
+        if !options.is_empty() {
+            sql.push_str(&format!(" OPTIONS ({})", options.join(", ")));
+        }
+        sql
+    }
+
+    /// Read the rows of a `COPY FROM` file, cast by position to `target`
+    /// when copying into an existing table
+    pub async fn read(&self, ctx: &SessionContext, target: Option<SchemaRef>) -> BlazeResult<Vec<RecordBatch>> {
+        // Files are read whatever their extension, not only `.csv` etc.
+        let extension = match self.path.rsplit_once('.') {
+            Some((_, extension)) if !extension.contains('/') => format!(".{}", extension),
+            _ => String::new(),
+        };
+        let compression = compression_type(split_compression(&self.path).1);
+        let df = match self.format {
+            DataFormat::Parquet => {
+                let options = ParquetReadOptions::default().file_extension(&extension);
+                ctx.read_parquet(&self.path, options).await?
+            }
+            DataFormat::Csv => {
+                let options = CsvReadOptions::new()
+                    .has_header(self.header)
+                    .delimiter(self.delimiter)
+                    .file_extension(&extension)
+                    .file_compression_type(compression);
+                ctx.read_csv(&self.path, options).await?
+            }
+            DataFormat::Json => {
+                let options = NdJsonReadOptions::default().file_extension(&extension).file_compression_type(compression);
+                ctx.read_json(&self.path, options).await?
+            }
+        };
+
+        let Some(target) = target else {
+            return Ok(df.collect().await?);
+        };
+        Ok(cast_to_schema(df, &target, &self.path)?.collect().await?)
+    }
+}
+
+fn cast_to_schema(df: DataFrame, target: &SchemaRef, path: &str) -> BlazeResult<DataFrame> {
+    let columns = df.schema().columns();
+    if columns.len() != target.fields().len() {
+        return Err(BlazeError::SchemaMismatch(format!(
+            "'{}' has {} columns but the table has {}",
+            path,
+            columns.len(),
+            target.fields().len()
+        )));
+    }
+
+    let exprs = columns
+        .into_iter()
+        .zip(target.fields())
+        .map(|(column, field)| cast(col(column), field.data_type().clone()).alias(field.name()))
+        .collect::<Vec<_>>();
+    Ok(df.select(exprs)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_copy() {
+        let to = parse_copy("COPY (SELECT a FROM t WHERE b = ')') TO '/tmp/o''k.csv' WITH (FORMAT csv, HEADER false, DELIMITER '|');")
+            .unwrap()
+            .unwrap();
+        assert_eq!(to.source, CopySource::Query("SELECT a FROM t WHERE b = ')'".to_string()));
+        assert_eq!((to.direction, to.format, to.header, to.delimiter), (CopyDirection::To, DataFormat::Csv, false, b'|'));
+        assert_eq!(
+            to.to_datafusion_sql(),
+            "COPY (SELECT a FROM t WHERE b = ')') TO '/tmp/o''k.csv' STORED AS CSV OPTIONS ('format.has_header' 'false', 'format.delimiter' '|')"
+        );
+
+        let from = parse_copy("copy events from 'data/events.parquet'").unwrap().unwrap();
+        assert_eq!((from.source, from.direction, from.format), (CopySource::Table("events".into()), CopyDirection::From, DataFormat::Parquet));
+
+        let compressed = parse_copy("COPY t TO 'out/events.json.zst'").unwrap().unwrap();
+        assert_eq!((compressed.format, compressed.compression.as_deref()), (DataFormat::Json, Some("zstd")));
+        assert!(parse_copy("COPY t TO 'out.parquet.gz'").unwrap_err().to_string().contains("Cannot infer the format"));
+
+        // DataFusion's own syntax is passed through
+        assert!(parse_copy("COPY t TO 'out' STORED AS PARQUET").unwrap().is_none());
+        assert!(parse_copy("SELECT 1").unwrap().is_none());
+
+        assert!(parse_copy("COPY t TO 'out/dir'").unwrap_err().to_string().contains("Cannot infer the format"));
+        assert!(parse_copy("COPY t FROM 'x.csv' (FORMAT xml)").unwrap_err().to_string().contains("Unsupported file format"));
+        assert!(parse_copy("COPY t FROM 'x.csv' (FREEZE)").unwrap_err().to_string().contains("Unsupported COPY option 'FREEZE'"));
+        assert!(parse_copy("COPY (SELECT 1) FROM 'x.csv'").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! CSV loading that tolerates messy files
+//!
+//! Column types are inferred from the values, can be overridden per column,
+//! and fall back to wider types when later values do not fit (an integer
+//! column containing `n/a` becomes a string column) instead of failing the
+//! load. Rows that still cannot be loaded, such as rows with the wrong
+//! number of fields or values that do not parse as an overridden type, are
+//! handled by a [`BadRowPolicy`].
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{Array, ArrayRef, BooleanArray, Int64Array, StringArray};
+use datafusion::arrow::compute::{cast_with_options, filter_record_batch, CastOptions};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// What to do with rows that cannot be loaded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BadRowPolicy {
+    /// Fail the load at the first bad row
+    #[default]
+    Fail,
+    /// Leave bad rows out and count them
+    Skip,
+    /// Leave bad rows out and store them, with the reason, in the named table
+    Reject(String),
+}
+
+/// Options for [`crate::BlazeQueryEngine::load_csv`]
+#[derive(Debug, Clone)]
+pub struct CsvIngestOptions {
+    /// Whether the first row holds column names (default: true)
+    pub has_header: bool,
+    /// Field delimiter (default: `,`)
+    pub delimiter: u8,
+    /// Types for specific columns, bypassing inference. Values that do not
+    /// parse as the given type make their row bad.
+    pub column_types: HashMap<String, DataType>,
+    /// Widen inferred types until every value fits (default: true). When
+    /// off, types are inferred from the first `infer_rows` rows and later
+    /// values that do not fit make their row bad.
+    pub coerce_types: bool,
+    /// Rows sampled for inference when `coerce_types` is off (default: 1000)
+    pub infer_rows: usize,
+    /// Handling of rows that cannot be loaded (default: fail)
+    pub bad_rows: BadRowPolicy,
+}
+
+impl Default for CsvIngestOptions {
+    fn default() -> Self {
+        Self {
+            has_header: true,
+            delimiter: b',',
+            column_types: HashMap::new(),
+            coerce_types: true,
+            infer_rows: 1000,
+            bad_rows: BadRowPolicy::Fail,
+        }
+    }
+}
+
+/// Outcome of a CSV load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvLoadReport {
+    /// Table the rows were loaded into
+    pub table_name: String,
+    /// Rows loaded
+    pub rows_loaded: usize,
+    /// Bad rows skipped or rejected
+    pub rows_rejected: usize,
+    /// Table holding the rejected rows, when any were routed to one
+    pub rejects_table: Option<String>,
+    /// Column names with the types they were loaded as
+    pub columns: Vec<(String, String)>,
+}
+
+/// A CSV file split into loadable rows and rejected ones
+pub(crate) struct CsvIngest {
+    pub batch: RecordBatch,
+    /// Line number, raw fields and reason of each bad row
+    pub rejected: Vec<(u64, String, String)>,
+}
+
+impl CsvIngest {
+    /// Rejected rows as a `(line, record, error)` table
+    pub fn rejects_batch(&self) -> BlazeResult<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("line", DataType::Int64, false),
+            Field::new("record", DataType::Utf8, false),
+            Field::new("error", DataType::Utf8, false),
+        ]));
+        let lines = Int64Array::from_iter_values(self.rejected.iter().map(|(line, _, _)| *line as i64));
+        let records = StringArray::from_iter_values(self.rejected.iter().map(|(_, record, _)| record));
+        let errors = StringArray::from_iter_values(self.rejected.iter().map(|(_, _, error)| error));
+        Ok(RecordBatch::try_new(schema, vec![Arc::new(lines), Arc::new(records), Arc::new(errors)])?)
+    }
+}
+
+/// Candidate types for inference, narrowest first
+const INFERRED_TYPES: [DataType; 5] = [
+    DataType::Int64,
+    DataType::Float64,
+    DataType::Boolean,
+    DataType::Date32,
+    DataType::Timestamp(TimeUnit::Microsecond, None),
+];
+
+/// Map a SQL or BigQuery type name to an Arrow type
+pub fn parse_type_name(name: &str) -> BlazeResult<DataType> {
+    match name.trim().to_uppercase().as_str() {
+        "INT64" | "INT" | "INTEGER" | "BIGINT" => Ok(DataType::Int64),
+        "FLOAT64" | "FLOAT" | "DOUBLE" | "REAL" => Ok(DataType::Float64),
+        "BOOL" | "BOOLEAN" => Ok(DataType::Boolean),
+        "STRING" | "VARCHAR" | "TEXT" => Ok(DataType::Utf8),
+        "DATE" => Ok(DataType::Date32),
+        "TIMESTAMP" | "DATETIME" => Ok(DataType::Timestamp(TimeUnit::Microsecond, None)),
+        other => Err(invalid_input!(
+            "Unsupported column type '{}'; expected INT64, FLOAT64, BOOL, STRING, DATE or TIMESTAMP",
+            other
+        )),
+    }
+}
+
+/// Read and type a CSV file according to `options`
+pub(crate) fn read_csv(path: &Path, options: &CsvIngestOptions) -> BlazeResult<CsvIngest> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(options.has_header)
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .from_path(path)
+        .map_err(std::io::Error::from)?;
+    let names: Option<Vec<String>> = if options.has_header {
+        Some(reader.headers().map_err(std::io::Error::from)?.iter().map(str::to_string).collect())
+    } else {
+        None
+    };
+
+    let delimiter = (options.delimiter as char).to_string();
+    let mut rejected = Vec::new();
+    let mut rows: Vec<(u64, csv::StringRecord)> = Vec::new();
+    let mut width = names.as_ref().map(Vec::len);
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                match e.kind() {
+                    csv::ErrorKind::Utf8 { .. } => {
+                        reject(options, &mut rejected, line, String::new(), "invalid UTF-8".to_string())?;
+                        continue;
+                    }
+                    _ => return Err(std::io::Error::from(e).into()),
+                }
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let expected = *width.get_or_insert(record.len());
+        if record.len() != expected {
+            let raw = record.iter().collect::<Vec<_>>().join(&delimiter);
+            let reason = format!("expected {} fields, found {}", expected, record.len());
+            reject(options, &mut rejected, line, raw, reason)?;
+            continue;
+        }
+        rows.push((line, record));
+    }
+
+    let width = width.unwrap_or(0);
+    let names = names.unwrap_or_else(|| (1..=width).map(|i| format!("column_{}", i)).collect());
+    if let Some(unknown) = options.column_types.keys().find(|name| !names.contains(name)) {
+        return Err(invalid_input!("Column type given for '{}', which is not in the file", unknown));
+    }
+
+    // Empty fields are null, except in string columns
+    let raw_columns: Vec<StringArray> = (0..width)
+        .map(|i| rows.iter().map(|(_, record)| Some(&record[i]).filter(|v| !v.is_empty())).collect())
+        .collect();
+
+    let safe = CastOptions { safe: true, ..Default::default() };
+    let mut bad: Vec<Option<String>> = vec![None; rows.len()];
+    let mut fields = Vec::with_capacity(width);
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(width);
+    for (i, (name, raw)) in names.iter().zip(&raw_columns).enumerate() {
+        let data_type = match options.column_types.get(name) {
+            Some(data_type) => data_type.clone(),
+            None if options.coerce_types => infer_type(raw)?,
+            None => infer_type(&raw.slice(0, raw.len().min(options.infer_rows)))?,
+        };
+
+        let column: ArrayRef = if data_type == DataType::Utf8 {
+            let values: StringArray = rows.iter().map(|(_, record)| Some(&record[i])).collect();
+            Arc::new(values)
+        } else {
+            let column = cast_with_options(raw, &data_type, &safe)?;
+            // A value that turned into null did not parse
+            for (row, reason) in bad.iter_mut().enumerate() {
+                if reason.is_none() && raw.is_valid(row) && column.is_null(row) {
+                    *reason = Some(format!("column '{}': cannot parse '{}' as {}", name, raw.value(row), data_type));
+                }
+            }
+            column
+        };
+        fields.push(Field::new(name, data_type, true));
+        columns.push(column);
+    }
+
+    for ((line, record), reason) in rows.iter().zip(&bad) {
+        if let Some(reason) = reason {
+            let raw = record.iter().collect::<Vec<_>>().join(&delimiter);
+            reject(options, &mut rejected, *line, raw, reason.clone())?;
+        }
+    }
+    rejected.sort_by_key(|(line, _, _)| *line);
+
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+    let keep: BooleanArray = bad.iter().map(|reason| Some(reason.is_none())).collect();
+    let batch = filter_record_batch(&batch, &keep)?;
+    Ok(CsvIngest { batch, rejected })
+}
+
+/// Record a bad row, or fail the load when bad rows are not tolerated
+fn reject(
+    options: &CsvIngestOptions,
+    rejected: &mut Vec<(u64, String, String)>,
+    line: u64,
+    raw: String,
+    reason: String,
+) -> BlazeResult<()> {
+    if options.bad_rows == BadRowPolicy::Fail {
+        return Err(BlazeError::SchemaMismatch(format!("line {}: {}", line, reason)));
+    }
+    rejected.push((line, raw, reason));
+    Ok(())
+}
+
+/// The narrowest type every non-null value converts to, else string
+fn infer_type(values: &StringArray) -> BlazeResult<DataType> {
+    let safe = CastOptions { safe: true, ..Default::default() };
+    if values.null_count() == values.len() {
+        return Ok(DataType::Utf8);
+    }
+    for data_type in INFERRED_TYPES {
+        let converted = cast_with_options(values, &data_type, &safe)?;
+        if converted.null_count() == values.null_count() {
+            return Ok(data_type);
+        }
+    }
+    Ok(DataType::Utf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::AsArray;
+    use datafusion::arrow::datatypes::Int64Type;
+
+    fn write(text: &str) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), text).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_inference_and_coercion() {
+        let file = write("id,code,price,day\n1,10,2.5,2024-01-01\n2,n/a,3,2024-01-02\n3,30,,2024-01-03\n");
+        let ingest = read_csv(file.path(), &CsvIngestOptions::default()).unwrap();
+        let types: Vec<_> = ingest.batch.schema().fields().iter().map(|f| f.data_type().clone()).collect();
+        assert_eq!(types, vec![DataType::Int64, DataType::Utf8, DataType::Float64, DataType::Date32]);
+        assert_eq!(ingest.batch.num_rows(), 3);
+        assert!(ingest.rejected.is_empty());
+
+        // Without coercion the sampled type sticks and later rows are bad
+        let options = CsvIngestOptions {
+            coerce_types: false,
+            infer_rows: 1,
+            bad_rows: BadRowPolicy::Skip,
+            ..Default::default()
+        };
+        let ingest = read_csv(file.path(), &options).unwrap();
+        assert_eq!(ingest.batch.num_rows(), 2);
+        assert_eq!(ingest.rejected.len(), 1);
+        assert_eq!(ingest.rejected[0].0, 3);
+        assert!(ingest.rejected[0].2.contains("column 'code': cannot parse 'n/a' as Int64"), "{:?}", ingest.rejected);
+    }
+
+    #[test]
+    fn test_bad_row_policies() {
+        let file = write("a;b\n1;x\n2\n3;y;extra\nfour;z\n");
+        let mut options = CsvIngestOptions {
+            delimiter: b';',
+            column_types: [("a".to_string(), DataType::Int64)].into(),
+            ..Default::default()
+        };
+        let error = read_csv(file.path(), &options).err().unwrap().to_string();
+        assert!(error.contains("line 3: expected 2 fields, found 1"), "{}", error);
+
+        options.bad_rows = BadRowPolicy::Reject("bad".to_string());
+        let ingest = read_csv(file.path(), &options).unwrap();
+        assert_eq!(ingest.batch.column(0).as_primitive::<Int64Type>().values(), &[1]);
+        let reasons: Vec<_> = ingest.rejected.iter().map(|(line, raw, _)| (*line, raw.as_str())).collect();
+        assert_eq!(reasons, vec![(3, "2"), (4, "3;y;extra"), (5, "four;z")]);
+        assert_eq!(ingest.rejects_batch().unwrap().num_rows(), 3);
+
+        options.column_types = [("missing".to_string(), DataType::Int64)].into();
+        assert!(read_csv(file.path(), &options).is_err());
+        assert!(parse_type_name("geography").is_err());
+        assert_eq!(parse_type_name("bigint").unwrap(), DataType::Int64);
+    }
+}
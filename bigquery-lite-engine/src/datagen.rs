@@ -0,0 +1,580 @@
+//! Synthetic dataset generation
+//!
+//! Builds tables of fake data from a list of column specs. Every column picks
+//! a point in its value range according to a distribution (uniform, normal,
+//! zipf or cyclic), optionally limited to a fixed number of distinct values,
+//! and can be made NULL for a fraction of rows. Generation is deterministic
+//! when the spec has a seed.
+//!
+//! Specs deserialize from JSON-like dicts and also parse from the compact
+//! `name:type[:key=value,...]` form used on the command line, e.g.
+//! `user:string:cardinality=5000,distribution=zipf,skew=1.2`.
+
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use datafusion::arrow::array::{
+    ArrayRef, BooleanBuilder, Date32Builder, Float64Builder, Int64Builder, StringBuilder,
+    TimestampMicrosecondBuilder,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Rows per generated batch
+const BATCH_SIZE: usize = 10_000;
+
+/// Largest number of distinct values a zipf column can draw from
+const MAX_ZIPF_VALUES: usize = 1_000_000;
+
+/// Distinct values used by zipf and cyclic columns without a cardinality
+const DEFAULT_CARDINALITY: usize = 100;
+
+/// Column value type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    /// Consecutive integers starting at `min`
+    Sequence,
+    Int,
+    Float,
+    /// `prefix` followed by the value's index
+    String,
+    Bool,
+    Date,
+    Timestamp,
+}
+
+/// How values are spread over a column's range
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Distribution {
+    #[default]
+    Uniform,
+    /// Centered on the middle of the range, with the range spanning six
+    /// standard deviations
+    Normal,
+    /// The k-th value is drawn with weight 1/k^skew, so low values dominate
+    Zipf,
+    /// Rows walk through the distinct values in order
+    Cyclic,
+}
+
+/// Range bound: a number, or a date/timestamp string for temporal columns
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Bound {
+    Number(f64),
+    Text(String),
+}
+
+/// One generated column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    #[serde(default)]
+    pub distribution: Distribution,
+    /// Lowest value (inclusive)
+    pub min: Option<Bound>,
+    /// Highest value (inclusive, exclusive for continuous columns)
+    pub max: Option<Bound>,
+    /// Number of distinct values, spread evenly over the range
+    pub cardinality: Option<usize>,
+    /// Zipf exponent; higher values concentrate rows on fewer values
+    #[serde(default = "default_skew")]
+    pub skew: f64,
+    /// Fraction of rows that are NULL
+    #[serde(default)]
+    pub null_fraction: f64,
+    /// Prefix for string values, `<name>_` by default
+    pub prefix: Option<String>,
+}
+
+fn default_skew() -> f64 {
+    1.0
+}
+
+/// A table to generate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetSpec {
+    pub rows: usize,
+    pub columns: Vec<ColumnSpec>,
+    /// Seed for reproducible output; random when absent
+    pub seed: Option<u64>,
+}
+
+impl ColumnSpec {
+    pub fn new(name: impl Into<String>, column_type: ColumnType) -> Self {
+        Self {
+            name: name.into(),
+            column_type,
+            distribution: Distribution::Uniform,
+            min: None,
+            max: None,
+            cardinality: None,
+            skew: default_skew(),
+            null_fraction: 0.0,
+            prefix: None,
+        }
+    }
+
+    pub fn with_distribution(mut self, distribution: Distribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    pub fn with_range(mut self, min: Bound, max: Bound) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    pub fn with_cardinality(mut self, cardinality: usize) -> Self {
+        self.cardinality = Some(cardinality);
+        self
+    }
+
+    pub fn with_skew(mut self, skew: f64) -> Self {
+        self.skew = skew;
+        self
+    }
+
+    pub fn with_null_fraction(mut self, null_fraction: f64) -> Self {
+        self.null_fraction = null_fraction;
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    fn data_type(&self) -> DataType {
+        match self.column_type {
+            ColumnType::Sequence | ColumnType::Int => DataType::Int64,
+            ColumnType::Float => DataType::Float64,
+            ColumnType::String => DataType::Utf8,
+            ColumnType::Bool => DataType::Boolean,
+            ColumnType::Date => DataType::Date32,
+            ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, None),
+        }
+    }
+}
+
+/// Parses `name:type[:key=value,...]`
+impl FromStr for ColumnSpec {
+    type Err = BlazeError;
+
+    fn from_str(text: &str) -> BlazeResult<Self> {
+        let mut parts = text.splitn(3, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        let column_type = parts.next().map(str::trim).unwrap_or_default();
+        if name.is_empty() || column_type.is_empty() {
+            return Err(invalid_input!(
+                "Column spec '{}' must look like name:type[:key=value,...]",
+                text
+            ));
+        }
+
+        let mut spec = serde_json::Map::new();
+        spec.insert("name".into(), name.into());
+        spec.insert("type".into(), column_type.into());
+        for option in parts.next().into_iter().flat_map(|options| options.split(',')) {
+            let (key, value) = option.split_once('=').ok_or_else(|| {
+                invalid_input!("Column option '{}' in '{}' must look like key=value", option, text)
+            })?;
+            let value = value.trim();
+            let value = match (value.parse::<i64>(), value.parse::<f64>()) {
+                _ if key.trim() == "prefix" => value.into(),
+                (Ok(integer), _) => serde_json::json!(integer),
+                (_, Ok(number)) => serde_json::json!(number),
+                _ => value.into(),
+            };
+            spec.insert(key.trim().to_string(), value);
+        }
+        serde_json::from_value(spec.into())
+            .map_err(|e| invalid_input!("Invalid column spec '{}': {}", text, e))
+    }
+}
+
+impl DatasetSpec {
+    pub fn new(rows: usize, columns: Vec<ColumnSpec>) -> Self {
+        Self { rows, columns, seed: None }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The default test table: a sequential `id`, a uniform `value` in
+    /// [0, 1000) and ten `category` values assigned round-robin
+    pub fn test_data(rows: usize) -> Self {
+        Self::new(
+            rows,
+            vec![
+                ColumnSpec::new("id", ColumnType::Sequence),
+                ColumnSpec::new("value", ColumnType::Float)
+                    .with_range(Bound::Number(0.0), Bound::Number(1000.0)),
+                ColumnSpec::new("category", ColumnType::String)
+                    .with_distribution(Distribution::Cyclic)
+                    .with_cardinality(10),
+            ],
+        )
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(
+            self.columns
+                .iter()
+                .map(|column| Field::new(&column.name, column.data_type(), column.null_fraction > 0.0))
+                .collect::<Vec<_>>(),
+        ))
+    }
+}
+
+/// Generate the rows described by `spec`
+pub fn generate(spec: &DatasetSpec) -> BlazeResult<Vec<RecordBatch>> {
+    if spec.columns.is_empty() {
+        return Err(invalid_input!("A dataset needs at least one column"));
+    }
+    let mut names = std::collections::HashSet::new();
+    if let Some(duplicate) = spec.columns.iter().find(|c| !names.insert(c.name.to_lowercase())) {
+        return Err(invalid_input!("Duplicate column '{}'", duplicate.name));
+    }
+
+    let generators = spec
+        .columns
+        .iter()
+        .map(ColumnGenerator::new)
+        .collect::<BlazeResult<Vec<_>>>()?;
+    let mut rng = match spec.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let schema = spec.schema();
+    let mut batches = Vec::new();
+    for batch_start in (0..spec.rows).step_by(BATCH_SIZE) {
+        let rows = batch_start..(batch_start + BATCH_SIZE).min(spec.rows);
+        let columns = generators
+            .iter()
+            .map(|generator| generator.generate(rows.clone(), &mut rng))
+            .collect();
+        batches.push(RecordBatch::try_new(schema.clone(), columns)?);
+    }
+    Ok(batches)
+}
+
+/// Write batches as CSV with a header row, returning the rows written
+pub fn write_csv(path: impl AsRef<Path>, batches: &[RecordBatch]) -> BlazeResult<usize> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = datafusion::arrow::csv::WriterBuilder::new()
+        .with_header(true)
+        .build(file);
+    let mut rows = 0;
+    for batch in batches {
+        writer.write(batch)?;
+        rows += batch.num_rows();
+    }
+    Ok(rows)
+}
+
+/// Validated column spec with its range resolved to numbers
+struct ColumnGenerator<'a> {
+    spec: &'a ColumnSpec,
+    /// Range in the column's numeric representation (days for dates,
+    /// microseconds for timestamps)
+    low: f64,
+    high: f64,
+    /// Number of distinct values, or `None` for a continuous range
+    levels: Option<usize>,
+    /// Cumulative zipf weights, one per distinct value
+    zipf: Vec<f64>,
+}
+
+impl<'a> ColumnGenerator<'a> {
+    fn new(spec: &'a ColumnSpec) -> BlazeResult<Self> {
+        let name = &spec.name;
+        if !(0.0..=1.0).contains(&spec.null_fraction) {
+            return Err(invalid_input!("null_fraction of column '{}' must be between 0 and 1", name));
+        }
+        if spec.cardinality == Some(0) {
+            return Err(invalid_input!("cardinality of column '{}' must be positive", name));
+        }
+        if !spec.skew.is_finite() || spec.skew < 0.0 {
+            return Err(invalid_input!("skew of column '{}' must be a non-negative number", name));
+        }
+
+        let (default_low, default_high) = match spec.column_type {
+            ColumnType::Sequence => (0.0, 0.0),
+            ColumnType::Int => (0.0, 1000.0),
+            ColumnType::Float => (0.0, 1.0),
+            ColumnType::String => (0.0, 0.0),
+            ColumnType::Bool => (0.0, 1.0),
+            ColumnType::Date => (date_days(2024, 1, 1), date_days(2024, 12, 31)),
+            ColumnType::Timestamp => (date_days(2024, 1, 1) * MICROS_PER_DAY, date_days(2025, 1, 1) * MICROS_PER_DAY),
+        };
+        let low = spec.min.as_ref().map(|b| bound_value(spec, b)).transpose()?.unwrap_or(default_low);
+        let high = spec.max.as_ref().map(|b| bound_value(spec, b)).transpose()?.unwrap_or(default_high);
+        if low > high {
+            return Err(invalid_input!("min of column '{}' is greater than its max", name));
+        }
+
+        let levels = match spec.column_type {
+            ColumnType::Sequence => None,
+            ColumnType::Bool => Some(2),
+            ColumnType::String => Some(spec.cardinality.unwrap_or(DEFAULT_CARDINALITY)),
+            ColumnType::Int | ColumnType::Date => {
+                let span = (high - low + 1.0).min(usize::MAX as f64) as usize;
+                Some(spec.cardinality.map_or(span, |c| c.min(span)))
+            }
+            ColumnType::Float | ColumnType::Timestamp => match spec.distribution {
+                Distribution::Zipf | Distribution::Cyclic => {
+                    Some(spec.cardinality.unwrap_or(DEFAULT_CARDINALITY))
+                }
+                _ => spec.cardinality,
+            },
+        };
+
+        let zipf = match (spec.distribution, levels) {
+            (Distribution::Zipf, Some(levels)) if spec.column_type != ColumnType::Sequence => {
+                if levels > MAX_ZIPF_VALUES {
+                    return Err(invalid_input!(
+                        "Zipf column '{}' has {} distinct values; set a cardinality of at most {}",
+                        name,
+                        levels,
+                        MAX_ZIPF_VALUES
+                    ));
+                }
+                let mut total = 0.0;
+                let mut cumulative: Vec<f64> = (1..=levels)
+                    .map(|k| {
+                        total += 1.0 / (k as f64).powf(spec.skew);
+                        total
+                    })
+                    .collect();
+                cumulative.iter_mut().for_each(|w| *w /= total);
+                cumulative
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(Self { spec, low, high, levels, zipf })
+    }
+
+    fn generate(&self, rows: std::ops::Range<usize>, rng: &mut StdRng) -> ArrayRef {
+        let capacity = rows.len();
+        let null_fraction = self.spec.null_fraction;
+        let values = rows.map(|row| {
+            let null = null_fraction > 0.0 && rng.gen_bool(null_fraction);
+            let value = self.value(row, rng);
+            (!null).then_some(value)
+        });
+
+        match self.spec.column_type {
+            ColumnType::Sequence | ColumnType::Int => {
+                let mut builder = Int64Builder::with_capacity(capacity);
+                values.for_each(|v| builder.append_option(v.map(|v| v as i64)));
+                Arc::new(builder.finish())
+            }
+            ColumnType::Float => {
+                let mut builder = Float64Builder::with_capacity(capacity);
+                values.for_each(|v| builder.append_option(v));
+                Arc::new(builder.finish())
+            }
+            ColumnType::String => {
+                let prefix = self.spec.prefix.clone().unwrap_or_else(|| format!("{}_", self.spec.name));
+                let mut builder = StringBuilder::with_capacity(capacity, capacity * (prefix.len() + 4));
+                values.for_each(|v| builder.append_option(v.map(|v| format!("{}{}", prefix, v as u64))));
+                Arc::new(builder.finish())
+            }
+            ColumnType::Bool => {
+                let mut builder = BooleanBuilder::with_capacity(capacity);
+                values.for_each(|v| builder.append_option(v.map(|v| v >= 1.0)));
+                Arc::new(builder.finish())
+            }
+            ColumnType::Date => {
+                let mut builder = Date32Builder::with_capacity(capacity);
+                values.for_each(|v| builder.append_option(v.map(|v| v as i32)));
+                Arc::new(builder.finish())
+            }
+            ColumnType::Timestamp => {
+                let mut builder = TimestampMicrosecondBuilder::with_capacity(capacity);
+                values.for_each(|v| builder.append_option(v.map(|v| v as i64)));
+                Arc::new(builder.finish())
+            }
+        }
+    }
+
+    /// Value for one row, in the column's numeric representation. String
+    /// columns produce the index of the value.
+    fn value(&self, row: usize, rng: &mut StdRng) -> f64 {
+        if self.spec.column_type == ColumnType::Sequence {
+            return self.low + row as f64;
+        }
+
+        // Position within the range, in [0, 1)
+        let position = match self.spec.distribution {
+            Distribution::Uniform => rng.gen::<f64>(),
+            Distribution::Normal => (0.5 + standard_normal(rng) / 6.0).clamp(0.0, 1.0 - f64::EPSILON),
+            Distribution::Zipf => {
+                let draw = rng.gen::<f64>();
+                let level = self.zipf.partition_point(|&w| w < draw).min(self.zipf.len() - 1);
+                return self.level_value(level);
+            }
+            Distribution::Cyclic => {
+                let levels = self.levels.unwrap_or(DEFAULT_CARDINALITY);
+                return self.level_value(row % levels);
+            }
+        };
+
+        match self.levels {
+            Some(levels) => self.level_value(((position * levels as f64) as usize).min(levels - 1)),
+            None => self.low + position * (self.high - self.low),
+        }
+    }
+
+    /// The `level`-th of the column's evenly spaced distinct values
+    fn level_value(&self, level: usize) -> f64 {
+        let levels = self.levels.unwrap_or(DEFAULT_CARDINALITY);
+        match self.spec.column_type {
+            ColumnType::String | ColumnType::Bool => level as f64,
+            _ if levels <= 1 => self.low,
+            ColumnType::Int | ColumnType::Date => {
+                (self.low + level as f64 * (self.high - self.low) / (levels - 1) as f64).round()
+            }
+            _ => self.low + level as f64 * (self.high - self.low) / (levels - 1) as f64,
+        }
+    }
+}
+
+const MICROS_PER_DAY: f64 = 86_400_000_000.0;
+
+fn date_days(year: i32, month: u32, day: u32) -> f64 {
+    epoch_days(NaiveDate::from_ymd_opt(year, month, day).expect("valid date"))
+}
+
+fn epoch_days(date: NaiveDate) -> f64 {
+    (date - DateTime::UNIX_EPOCH.date_naive()).num_days() as f64
+}
+
+/// Resolve a bound to the column's numeric representation
+fn bound_value(spec: &ColumnSpec, bound: &Bound) -> BlazeResult<f64> {
+    let invalid = || invalid_input!("Invalid bound {:?} for {:?} column '{}'", bound, spec.column_type, spec.name);
+    match (spec.column_type, bound) {
+        (ColumnType::Date, Bound::Text(text)) => {
+            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| invalid())?;
+            Ok(epoch_days(date))
+        }
+        (ColumnType::Timestamp, Bound::Text(text)) => {
+            let timestamp = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S"))
+                .or_else(|_| NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap()))
+                .map_err(|_| invalid())?;
+            Ok(timestamp.and_utc().timestamp_micros() as f64)
+        }
+        (ColumnType::Date | ColumnType::Timestamp | ColumnType::String | ColumnType::Bool, _) => Err(invalid()),
+        (_, Bound::Number(number)) if number.is_finite() => Ok(*number),
+        _ => Err(invalid()),
+    }
+}
+
+/// Box-Muller transform
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Array, StringArray};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_column_spec() {
+        let spec: ColumnSpec = "user:string:cardinality=50,distribution=zipf,skew=1.5,null_fraction=0.1"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.column_type, ColumnType::String);
+        assert_eq!(spec.distribution, Distribution::Zipf);
+        assert_eq!(spec.cardinality, Some(50));
+        assert_eq!(spec.skew, 1.5);
+        assert_eq!(spec.null_fraction, 0.1);
+
+        let spec: ColumnSpec = "day:date:min=2024-01-01,max=2024-01-31".parse().unwrap();
+        assert_eq!(spec.min, Some(Bound::Text("2024-01-01".into())));
+
+        assert!("user".parse::<ColumnSpec>().is_err());
+        assert!("user:uuid".parse::<ColumnSpec>().is_err());
+        assert!("user:int:colour=red".parse::<ColumnSpec>().is_err());
+    }
+
+    #[test]
+    fn test_zipf_skew_and_nulls() {
+        let spec = DatasetSpec::new(
+            20_000,
+            vec![ColumnSpec::new("user", ColumnType::String)
+                .with_distribution(Distribution::Zipf)
+                .with_cardinality(100)
+                .with_skew(1.2)
+                .with_null_fraction(0.25)],
+        )
+        .with_seed(42);
+        let batches = generate(&spec).unwrap();
+        assert_eq!(batches.len(), 2);
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut nulls = 0;
+        for batch in &batches {
+            let users = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+            nulls += users.null_count();
+            for user in users.iter().flatten() {
+                *counts.entry(user.to_string()).or_default() += 1;
+            }
+        }
+        assert!((4_500..5_500).contains(&nulls), "{} nulls", nulls);
+        assert!(counts.len() <= 100);
+        // The most common value dominates the tail
+        assert!(counts["user_0"] > 20 * counts.get("user_99").copied().unwrap_or(1));
+
+        // Same seed, same data
+        assert_eq!(generate(&spec).unwrap(), batches);
+    }
+
+    #[test]
+    fn test_ranges_and_cardinality() {
+        let spec = DatasetSpec::new(
+            1_000,
+            vec![
+                ColumnSpec::new("score", ColumnType::Int)
+                    .with_distribution(Distribution::Normal)
+                    .with_range(Bound::Number(-5.0), Bound::Number(5.0)),
+                ColumnSpec::new("price", ColumnType::Float)
+                    .with_range(Bound::Number(10.0), Bound::Number(20.0))
+                    .with_cardinality(3),
+            ],
+        )
+        .with_seed(1);
+        let batch = &generate(&spec).unwrap()[0];
+        let scores = batch.column(0).as_any().downcast_ref::<datafusion::arrow::array::Int64Array>().unwrap();
+        assert!(scores.values().iter().all(|s| (-5..=5).contains(s)));
+        let prices = batch.column(1).as_any().downcast_ref::<datafusion::arrow::array::Float64Array>().unwrap();
+        assert!(prices.values().iter().all(|p| [10.0, 15.0, 20.0].contains(p)));
+
+        let bad = DatasetSpec::new(1, vec![ColumnSpec::new("x", ColumnType::Int).with_null_fraction(2.0)]);
+        assert!(generate(&bad).is_err());
+    }
+}
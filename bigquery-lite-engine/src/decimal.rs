@@ -0,0 +1,572 @@
+//! Decimal arithmetic semantics
+//!
+//! Two settings decide how `DECIMAL`/`NUMERIC` values behave:
+//!
+//! - `decimal_rules` picks the result type of arithmetic and aggregates.
+//!   `derived` (the default) keeps DataFusion's rules, which derive precision
+//!   and scale from the operands, e.g. `DECIMAL(10, 2) * DECIMAL(10, 2)` is
+//!   `DECIMAL(21, 4)`, capped at 38 digits. `bigquery` follows BigQuery:
+//!   `NUMERIC` results are always `DECIMAL(38, 9)` and `BIGNUMERIC` results
+//!   `DECIMAL(76, 38)`, rounded half away from zero, and a bare `NUMERIC` or
+//!   `DECIMAL` type means `DECIMAL(38, 9)`.
+//! - `decimal_overflow` decides what happens when a value does not fit its
+//!   type. `error` (the default) fails the query, `saturate` clamps the value
+//!   to the largest one of that precision, and `promote` returns results
+//!   that could exceed 38 digits as 256-bit decimals instead, which only
+//!   fail beyond 76 digits. Explicit casts to a too narrow type still fail
+//!   under `promote`.
+//!
+//! Arithmetic, casts, `SUM` and `AVG` on decimals are computed with 256-bit
+//! intermediates so an overflow is caught instead of silently producing a
+//! value with more digits than its type allows. `BIGNUMERIC` is accepted as
+//! a type name under either rules.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{Array, ArrayRef, AsArray, Decimal256Array, StringArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{i256, DataType, Decimal128Type, Decimal256Type, DecimalType, DECIMAL256_MAX_PRECISION};
+use datafusion::common::config::{ConfigExtension, ConfigOptions};
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{extensions_options, Column, DFSchema, ScalarValue};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::expr::Cast;
+use datafusion::logical_expr::expr_rewriter::NamePreserver;
+use datafusion::logical_expr::type_coercion::binary::get_result_type;
+use datafusion::logical_expr::utils::merge_schema;
+use datafusion::logical_expr::{
+    Aggregate, BinaryExpr, ColumnarValue, Expr, ExprSchemable, LogicalPlan, Operator, Projection, ScalarFunctionArgs,
+    ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::optimizer::analyzer::type_coercion::TypeCoercion;
+use datafusion::optimizer::AnalyzerRule;
+use datafusion::prelude::SessionContext;
+use datafusion::sql::parser::Statement as DFStatement;
+use datafusion::sql::sqlparser::ast::{
+    visit_expressions_mut, DataType as SqlDataType, ExactNumberInfo, Expr as SqlExpr, Statement,
+};
+use serde::{Deserialize, Serialize};
+
+/// How the result type of decimal arithmetic and aggregates is chosen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecimalRules {
+    /// DataFusion's rules, deriving precision and scale from the operands
+    #[default]
+    Derived,
+    /// BigQuery's fixed `NUMERIC` and `BIGNUMERIC` types
+    BigQuery,
+}
+
+/// What happens when a decimal value does not fit its type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecimalOverflow {
+    /// Fail the query
+    #[default]
+    Error,
+    /// Clamp to the largest value of the type's precision
+    Saturate,
+    /// Return results that could exceed 38 digits as 256-bit decimals
+    Promote,
+}
+
+impl fmt::Display for DecimalRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Derived => "derived",
+            Self::BigQuery => "bigquery",
+        })
+    }
+}
+
+impl FromStr for DecimalRules {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "derived" => Ok(Self::Derived),
+            "bigquery" => Ok(Self::BigQuery),
+            _ => Err(DataFusionError::Configuration(format!(
+                "Unknown decimal rules '{}'; expected 'derived' or 'bigquery'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for DecimalOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Saturate => "saturate",
+            Self::Promote => "promote",
+        })
+    }
+}
+
+impl FromStr for DecimalOverflow {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "saturate" => Ok(Self::Saturate),
+            "promote" => Ok(Self::Promote),
+            _ => Err(DataFusionError::Configuration(format!(
+                "Unknown decimal overflow behavior '{}'; expected 'error', 'saturate' or 'promote'",
+                s
+            ))),
+        }
+    }
+}
+
+extensions_options! {
+    /// The decimal settings of a session, read while planning
+    pub(crate) struct DecimalOptions {
+        /// Result type rules: derived or bigquery
+        pub decimal_rules: DecimalRules, default = DecimalRules::Derived
+        /// Overflow behavior: error, saturate or promote
+        pub decimal_overflow: DecimalOverflow, default = DecimalOverflow::Error
+    }
+}
+
+impl ConfigExtension for DecimalOptions {
+    const PREFIX: &'static str = "bqlite";
+}
+
+/// Install the decimal rewrite into a new session
+pub(crate) fn register(ctx: &SessionContext) {
+    ctx.add_analyzer_rule(Arc::new(DecimalSemantics));
+}
+
+/// Use `rules` and `overflow` for queries planned from now on
+pub(crate) fn apply(ctx: &SessionContext, rules: DecimalRules, overflow: DecimalOverflow) {
+    let state = ctx.state_ref();
+    let mut state = state.write();
+    let options = DecimalOptions { decimal_rules: rules, decimal_overflow: overflow };
+    state.config_mut().options_mut().extensions.insert(options);
+}
+
+/// The session's decimal settings
+pub(crate) fn options(ctx: &SessionContext) -> DecimalOptions {
+    options_from(ctx.state().config_options())
+}
+
+fn options_from(config: &ConfigOptions) -> DecimalOptions {
+    config.extensions.get::<DecimalOptions>().cloned().unwrap_or_default()
+}
+
+/// Replace `BIGNUMERIC` with `DECIMAL(76, 38)` and, under BigQuery rules, a
+/// bare `NUMERIC` or `DECIMAL` with `DECIMAL(38, 9)` in casts, typed literals
+/// and column definitions
+pub(crate) fn apply_type_defaults(statement: &mut DFStatement, rules: DecimalRules) {
+    match statement {
+        DFStatement::Statement(statement) => {
+            if let Statement::CreateTable(create) = statement.as_mut() {
+                for column in &mut create.columns {
+                    apply_type_default(&mut column.data_type, rules);
+                }
+            }
+            let _ = visit_expressions_mut(statement.as_mut(), |expr| {
+                match expr {
+                    SqlExpr::Cast { data_type, .. } | SqlExpr::TypedString { data_type, .. } => {
+                        apply_type_default(data_type, rules)
+                    }
+                    _ => {}
+                }
+                ControlFlow::<()>::Continue(())
+            });
+        }
+        DFStatement::Explain(explain) => apply_type_defaults(&mut explain.statement, rules),
+        _ => {}
+    }
+}
+
+fn apply_type_default(data_type: &mut SqlDataType, rules: DecimalRules) {
+    *data_type = match data_type {
+        SqlDataType::BigNumeric(ExactNumberInfo::None) | SqlDataType::BigDecimal(ExactNumberInfo::None) => {
+            SqlDataType::Decimal(ExactNumberInfo::PrecisionAndScale(76, 38))
+        }
+        SqlDataType::BigNumeric(info) | SqlDataType::BigDecimal(info) => SqlDataType::Decimal(*info),
+        SqlDataType::Numeric(ExactNumberInfo::None)
+        | SqlDataType::Decimal(ExactNumberInfo::None)
+        | SqlDataType::Dec(ExactNumberInfo::None)
+            if rules == DecimalRules::BigQuery =>
+        {
+            SqlDataType::Decimal(ExactNumberInfo::PrecisionAndScale(38, 9))
+        }
+        _ => return,
+    };
+}
+
+/// Render a decimal column as exact text; `None` for other types
+pub(crate) fn render_decimal(column: &ArrayRef) -> Result<Option<StringArray>> {
+    if !matches!(column.data_type(), DataType::Decimal128(_, _) | DataType::Decimal256(_, _)) {
+        return Ok(None);
+    }
+    let text = cast(column, &DataType::Utf8)?;
+    Ok(text.as_any().downcast_ref::<StringArray>().cloned())
+}
+
+/// Rewrites decimal arithmetic, casts and aggregates to follow the session's
+/// `DecimalOptions`. Runs after DataFusion's type coercion, so operand types
+/// are final.
+#[derive(Debug)]
+struct DecimalSemantics;
+
+impl AnalyzerRule for DecimalSemantics {
+    fn analyze(&self, plan: LogicalPlan, config: &ConfigOptions) -> Result<LogicalPlan> {
+        let options = options_from(config);
+        let rewritten = plan.transform_up_with_subqueries(|plan| rewrite_plan(plan, &options))?;
+
+        // Other rules or promotion may give results a new type, which
+        // expressions over them have to be coerced to again
+        let retyped = options.decimal_rules == DecimalRules::BigQuery
+            || options.decimal_overflow == DecimalOverflow::Promote;
+        if rewritten.transformed && retyped {
+            TypeCoercion::new().analyze(rewritten.data, config)
+        } else {
+            Ok(rewritten.data)
+        }
+    }
+
+    fn name(&self) -> &str {
+        "decimal_semantics"
+    }
+}
+
+fn rewrite_plan(plan: LogicalPlan, options: &DecimalOptions) -> Result<Transformed<LogicalPlan>> {
+    let schema = match plan.inputs().as_slice() {
+        [] => plan.schema().as_ref().clone(),
+        inputs => merge_schema(inputs),
+    };
+    let name_preserver = NamePreserver::new(&plan);
+    let rewritten = plan
+        .map_expressions(|expr| {
+            let saved = name_preserver.save(&expr);
+            let rewritten = expr.transform_up(|expr| rewrite_expr(expr, &schema, options))?;
+            Ok(rewritten.update_data(|expr| saved.restore(expr)))
+        })?
+        .map_data(|plan| plan.recompute_schema())?;
+    match rewritten.data {
+        LogicalPlan::Aggregate(aggregate) => {
+            let transformed = rewritten.transformed;
+            let mut rewritten = rewrite_aggregate(aggregate, options)?;
+            rewritten.transformed |= transformed;
+            Ok(rewritten)
+        }
+        _ => Ok(rewritten),
+    }
+}
+
+fn rewrite_expr(expr: Expr, schema: &DFSchema, options: &DecimalOptions) -> Result<Transformed<Expr>> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right })
+            if matches!(op, Operator::Plus | Operator::Minus | Operator::Multiply | Operator::Divide | Operator::Modulo) =>
+        {
+            let (left_type, right_type) = (left.get_type(schema)?, right.get_type(schema)?);
+            let (Some(left_wide), Some(right_wide)) = (widened(&left_type), widened(&right_type)) else {
+                return Ok(Transformed::no(Expr::BinaryExpr(BinaryExpr { left, op, right })));
+            };
+            let derived = get_result_type(&left_type, &op, &right_type)?;
+            let wide = get_result_type(&left_wide, &op, &right_wide)?;
+            let target = options.result_type(derived, &wide);
+            let computed = Expr::BinaryExpr(BinaryExpr::new(
+                Box::new(cast_to(*left, &left_type, left_wide)),
+                op,
+                Box::new(cast_to(*right, &right_type, right_wide)),
+            ));
+            Ok(Transformed::yes(fit(computed, wide, target, options.decimal_overflow)))
+        }
+        Expr::Cast(Cast { expr, data_type })
+            if options.decimal_overflow == DecimalOverflow::Saturate && is_decimal(&data_type) =>
+        {
+            let (DataType::Decimal128(_, scale) | DataType::Decimal256(_, scale)) = data_type else { unreachable!() };
+            let wide = DataType::Decimal256(DECIMAL256_MAX_PRECISION, scale);
+            let computed = Expr::Cast(Cast::new(expr, wide.clone()));
+            Ok(Transformed::yes(fit(computed, wide, data_type, DecimalOverflow::Saturate)))
+        }
+        expr => Ok(Transformed::no(expr)),
+    }
+}
+
+/// Accumulate `SUM` and `AVG` of 128-bit decimals in 256 bits, adding a
+/// projection that brings the results back to their type when needed
+fn rewrite_aggregate(aggregate: Aggregate, options: &DecimalOptions) -> Result<Transformed<LogicalPlan>> {
+    let schema = aggregate.input.schema().clone();
+    let name_preserver = NamePreserver::new_for_projection();
+    let first_aggregate_field = aggregate.schema.fields().len() - aggregate.aggr_expr.len();
+
+    let mut fits = HashMap::new();
+    let mut aggr_expr = Vec::with_capacity(aggregate.aggr_expr.len());
+    for (i, expr) in aggregate.aggr_expr.iter().enumerate() {
+        let derived = expr.get_type(&schema)?;
+        let saved = name_preserver.save(expr);
+        let mut target = None;
+        let rewritten = expr.clone().transform_up(|expr| match expr {
+            Expr::AggregateFunction(mut function)
+                if matches!(function.func.name(), "sum" | "avg") && function.args.len() == 1 =>
+            {
+                let arg_type = function.args[0].get_type(&schema)?;
+                let (DataType::Decimal128(_, _), Some(wide)) = (&arg_type, widened(&arg_type)) else {
+                    return Ok(Transformed::no(Expr::AggregateFunction(function)));
+                };
+                let result_type = options.result_type(derived.clone(), &function.func.return_type(std::slice::from_ref(&wide))?);
+                // Accumulate with at least the result's scale, so an average
+                // is rounded to it rather than truncated
+                let wide = match (&wide, &result_type) {
+                    (
+                        DataType::Decimal256(precision, scale),
+                        DataType::Decimal128(_, result_scale) | DataType::Decimal256(_, result_scale),
+                    ) if result_scale > scale => DataType::Decimal256(
+                        (*precision + (result_scale - scale) as u8).min(DECIMAL256_MAX_PRECISION),
+                        *result_scale,
+                    ),
+                    _ => wide,
+                };
+                target = Some(result_type);
+                function.args = vec![cast_to(function.args.remove(0), &arg_type, wide)];
+                Ok(Transformed::yes(Expr::AggregateFunction(function)))
+            }
+            expr => Ok(Transformed::no(expr)),
+        })?;
+        if let Some(target) = target {
+            let wide = rewritten.data.get_type(&schema)?;
+            if target != wide {
+                fits.insert(first_aggregate_field + i, (wide, target));
+            }
+        }
+        aggr_expr.push(saved.restore(rewritten.data));
+    }
+    if aggr_expr == aggregate.aggr_expr {
+        return Ok(Transformed::no(LogicalPlan::Aggregate(aggregate)));
+    }
+
+    let rewritten = Aggregate::try_new(aggregate.input, aggregate.group_expr, aggr_expr)?;
+    if fits.is_empty() {
+        return Ok(Transformed::yes(LogicalPlan::Aggregate(rewritten)));
+    }
+    let exprs = rewritten
+        .schema
+        .iter()
+        .enumerate()
+        .map(|(i, (qualifier, field))| {
+            let column = Expr::Column(Column::from((qualifier, field)));
+            match fits.remove(&i) {
+                Some((wide, target)) => fit(column, wide, target, options.decimal_overflow)
+                    .alias_qualified(qualifier.cloned(), field.name()),
+                None => column,
+            }
+        })
+        .collect();
+    let projection = Projection::try_new(exprs, Arc::new(LogicalPlan::Aggregate(rewritten)))?;
+    Ok(Transformed::yes(LogicalPlan::Projection(projection)))
+}
+
+impl DecimalOptions {
+    /// The type of a result DataFusion would type `derived`, computed as `wide`
+    fn result_type(&self, derived: DataType, wide: &DataType) -> DataType {
+        let narrow = matches!(derived, DataType::Decimal128(_, _));
+        match (self.decimal_rules, self.decimal_overflow) {
+            (DecimalRules::Derived, DecimalOverflow::Promote) => match (&derived, wide) {
+                (DataType::Decimal128(_, _), DataType::Decimal256(precision, _)) if *precision > 38 => wide.clone(),
+                _ => derived,
+            },
+            (DecimalRules::Derived, _) => derived,
+            (DecimalRules::BigQuery, overflow) if narrow && overflow != DecimalOverflow::Promote => {
+                DataType::Decimal128(38, 9)
+            }
+            (DecimalRules::BigQuery, _) => DataType::Decimal256(76, 38),
+        }
+    }
+}
+
+fn is_decimal(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Decimal128(_, _) | DataType::Decimal256(_, _))
+}
+
+/// The 256-bit decimal type holding every value of `data_type`
+fn widened(data_type: &DataType) -> Option<DataType> {
+    match data_type {
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            Some(DataType::Decimal256(*precision, *scale))
+        }
+        _ => None,
+    }
+}
+
+fn cast_to(expr: Expr, from: &DataType, to: DataType) -> Expr {
+    if *from == to {
+        expr
+    } else {
+        Expr::Cast(Cast::new(Box::new(expr), to))
+    }
+}
+
+/// `expr`, a 256-bit decimal of type `from`, rounded and checked to fit `to`
+fn fit(expr: Expr, from: DataType, to: DataType, overflow: DecimalOverflow) -> Expr {
+    ScalarUDF::from(DecimalFit::new(from, to, overflow)).call(vec![expr])
+}
+
+/// Rounds a 256-bit decimal to the scale of its target type and checks its
+/// precision, failing or saturating on overflow
+#[derive(Debug)]
+struct DecimalFit {
+    from: DataType,
+    to: DataType,
+    overflow: DecimalOverflow,
+    signature: Signature,
+}
+
+impl DecimalFit {
+    fn new(from: DataType, to: DataType, overflow: DecimalOverflow) -> Self {
+        Self { from, to, overflow, signature: Signature::any(1, Volatility::Immutable) }
+    }
+}
+
+impl ScalarUDFImpl for DecimalFit {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "decimal_fit"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.to.clone())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let scalar = matches!(args.args[0], ColumnarValue::Scalar(_));
+        let input = args.args[0].clone().into_array(args.number_rows)?;
+        let input = if input.data_type() == &self.from { input } else { cast(&input, &self.from)? };
+        let fitted = fit_array(input.as_primitive::<Decimal256Type>(), &self.to, self.overflow)?;
+        if scalar {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(&fitted, 0)?))
+        } else {
+            Ok(ColumnarValue::Array(fitted))
+        }
+    }
+
+    fn equals(&self, other: &dyn ScalarUDFImpl) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other| {
+            (&self.from, &self.to, self.overflow) == (&other.from, &other.to, other.overflow)
+        })
+    }
+
+    fn hash_value(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.name(), &self.from, &self.to, self.overflow.to_string()).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+fn fit_array(input: &Decimal256Array, to: &DataType, overflow: DecimalOverflow) -> Result<ArrayRef> {
+    let DataType::Decimal256(from_precision, from_scale) = *input.data_type() else {
+        unreachable!("decimal_fit is only planned over 256-bit decimals")
+    };
+    let (DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale)) = *to else {
+        unreachable!("decimal_fit only targets decimals")
+    };
+    let ten = i256::from_i128(10);
+    let largest = ten.wrapping_pow(precision as u32).wrapping_sub(i256::ONE);
+    let out_of_range = |value: i256| match overflow {
+        DecimalOverflow::Saturate if value.is_negative() => Ok(largest.wrapping_neg()),
+        DecimalOverflow::Saturate => Ok(largest),
+        _ => Err(DataFusionError::Execution(format!(
+            "Decimal overflow: {} does not fit {}",
+            Decimal256Type::format_decimal(value, from_precision, from_scale),
+            to
+        ))),
+    };
+
+    let fitted: Decimal256Array = input.try_unary(|value| {
+        let rescaled = match scale.cmp(&from_scale) {
+            std::cmp::Ordering::Equal => Some(value),
+            std::cmp::Ordering::Greater => {
+                ten.checked_pow((scale - from_scale) as u32).and_then(|factor| value.checked_mul(factor))
+            }
+            std::cmp::Ordering::Less => {
+                // Round half away from zero
+                let divisor = ten.wrapping_pow((from_scale - scale) as u32);
+                let quotient = value.wrapping_div(divisor);
+                let remainder = value.wrapping_rem(divisor).wrapping_abs();
+                if remainder.wrapping_mul(i256::from_i128(2)) >= divisor {
+                    Some(quotient.wrapping_add(value.signum()))
+                } else {
+                    Some(quotient)
+                }
+            }
+        };
+        match rescaled {
+            Some(rescaled) if rescaled.wrapping_abs() <= largest => Ok(rescaled),
+            _ => out_of_range(value),
+        }
+    })?;
+
+    Ok(match to {
+        DataType::Decimal128(_, _) => Arc::new(
+            fitted
+                .unary::<_, Decimal128Type>(|value| value.as_i128())
+                .with_precision_and_scale(precision, scale)?,
+        ) as ArrayRef,
+        _ => Arc::new(fitted.with_precision_and_scale(precision, scale)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Decimal128Array;
+
+    fn decimals(values: &[i128], precision: u8, scale: i8) -> Decimal256Array {
+        values
+            .iter()
+            .map(|v| Some(i256::from_i128(*v)))
+            .collect::<Decimal256Array>()
+            .with_precision_and_scale(precision, scale)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fit_rounds_and_checks_precision() {
+        // 1.2345 and -1.2355 to two places, half away from zero
+        let input = decimals(&[12345, -12355, 99999], 10, 4);
+        let fitted = fit_array(&input, &DataType::Decimal128(5, 2), DecimalOverflow::Error).unwrap();
+        let fitted = fitted.as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(fitted.values().to_vec(), vec![123, -124, 1000]);
+
+        let input = decimals(&[123456, -123456], 10, 2);
+        let error = fit_array(&input, &DataType::Decimal128(5, 2), DecimalOverflow::Error).unwrap_err();
+        assert!(error.to_string().contains("1234.56 does not fit Decimal128(5, 2)"), "{}", error);
+        let saturated = fit_array(&input, &DataType::Decimal128(5, 2), DecimalOverflow::Saturate).unwrap();
+        let saturated = saturated.as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(saturated.values().to_vec(), vec![99999, -99999]);
+    }
+
+    #[test]
+    fn test_bigquery_type_defaults() {
+        let sql = "SELECT CAST(a AS NUMERIC), CAST(b AS BIGNUMERIC), CAST(c AS DECIMAL(10, 2)) FROM t";
+        let mut statement = datafusion::sql::parser::DFParser::parse_sql(sql).unwrap().pop_front().unwrap();
+        apply_type_defaults(&mut statement, DecimalRules::Derived);
+        assert!(statement.to_string().contains("CAST(a AS NUMERIC), CAST(b AS DECIMAL(76,38))"), "{}", statement);
+        apply_type_defaults(&mut statement, DecimalRules::BigQuery);
+        assert!(statement.to_string().contains("CAST(a AS DECIMAL(38,9))"), "{}", statement);
+        assert!(statement.to_string().contains("CAST(c AS DECIMAL(10,2))"), "{}", statement);
+
+        assert_eq!("BigQuery".parse::<DecimalRules>().unwrap(), DecimalRules::BigQuery);
+        assert!("wrap".parse::<DecimalOverflow>().is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Tables a SQL statement reads and writes
+//!
+//! References are resolved the way the planner resolves them: unqualified
+//! names get the session's default catalog and schema, CTE names and table
+//! functions are left out, and views are reported by their own name rather
+//! than the tables behind them. The engine's own statements and table
+//! functions (`COPY`, `CREATE MODEL`, `ASSERT`, `VECTOR_SEARCH(...)`, ...) are
+//! unwrapped to the queries they run.
+
+use std::collections::BTreeSet;
+
+use datafusion::execution::session_state::SessionState;
+use datafusion::sql::parser::{CopyToSource, Statement as DFStatement};
+use datafusion::sql::planner::object_name_to_table_reference;
+use datafusion::sql::sqlparser::ast::{FromTable, Ident, ObjectName, ObjectType, Query, Statement, TableFactor};
+use datafusion::sql::TableReference;
+use serde::{Deserialize, Serialize};
+
+use crate::copy::{self, CopyDirection, CopySource};
+use crate::error::BlazeResult;
+use crate::{assertions, ml, search, sessionize, snapshots, time_series, vector};
+
+/// Fully-qualified names (`catalog.schema.table`) of the tables and views a
+/// statement depends on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableReferences {
+    /// Tables and views read, sorted
+    pub reads: Vec<String>,
+    /// Tables and views created, modified or dropped, sorted
+    pub writes: Vec<String>,
+}
+
+/// Collects references while a statement and the queries nested in the
+/// engine's extensions are walked
+pub(crate) struct ReferenceCollector<'a> {
+    state: &'a SessionState,
+    reads: BTreeSet<String>,
+    writes: BTreeSet<String>,
+}
+
+impl<'a> ReferenceCollector<'a> {
+    pub(crate) fn new(state: &'a SessionState) -> Self {
+        Self {
+            state,
+            reads: BTreeSet::new(),
+            writes: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn finish(self) -> TableReferences {
+        TableReferences {
+            reads: self.reads.into_iter().collect(),
+            writes: self.writes.into_iter().collect(),
+        }
+    }
+
+    /// Record the references of one statement. `ML.PREDICT` calls must
+    /// already be rewritten, as the engine does before planning.
+    pub(crate) fn add_sql(&mut self, sql: &str) -> BlazeResult<()> {
+        if let Some(check) = assertions::parse_assert(sql) {
+            return self.add_sql(&check.to_sql());
+        }
+        if let Some(copy) = copy::parse_copy(sql)? {
+            return match (copy.direction, copy.source) {
+                (CopyDirection::To, CopySource::Table(table)) => self.add_sql(&format!("SELECT * FROM {}", table)),
+                (CopyDirection::To, CopySource::Query(query)) => self.add_sql(&query),
+                (CopyDirection::From, CopySource::Table(table)) => {
+                    self.add_write(&ObjectName(table.split('.').map(Ident::new).collect()))
+                }
+                (CopyDirection::From, CopySource::Query(_)) => Ok(()),
+            };
+        }
+        if let Some(model) = ml::parse_create_model(sql)? {
+            return self.add_sql(&model.query);
+        }
+        if let Some(index) = search::parse_create_search_index(sql)? {
+            return self.add_sql(&format!("SELECT * FROM {}", index.table_name));
+        }
+        if let Some(index) = vector::parse_create_vector_index(sql)? {
+            return self.add_sql(&format!("SELECT * FROM {}", index.table_name));
+        }
+        if search::parse_drop_search_index(sql).is_some()
+            || vector::parse_drop_vector_index(sql).is_some()
+            || ml::parse_drop_model(sql).is_some()
+        {
+            return Ok(());
+        }
+
+        let sql = self.strip_table_functions(sql)?;
+        let dialect = if snapshots::contains_time_travel(&sql) {
+            "BigQuery"
+        } else {
+            self.state.config_options().sql_parser.dialect.as_str()
+        };
+        let statement = self.state.sql_to_statement(&sql, dialect)?;
+        self.add_statement(statement)
+    }
+
+    /// Record what `VECTOR_SEARCH`, `SESSIONIZE` and `GAP_FILL` calls read
+    /// and replace each with a placeholder subquery, in the order the engine
+    /// resolves them
+    fn strip_table_functions(&mut self, sql: &str) -> BlazeResult<String> {
+        let mut rewritten = sql.to_string();
+
+        if vector::contains_vector_search(&rewritten) {
+            for call in vector::find_vector_search_calls(&rewritten.clone())?.iter().rev() {
+                self.add_sql(&format!("SELECT * FROM {}", call.base_table))?;
+                self.add_sql(&call.query_sql)?;
+                rewritten.replace_range(call.span.0..call.span.1, "(SELECT 1)");
+            }
+        }
+        if sessionize::contains_sessionize(&rewritten) {
+            for call in sessionize::find_sessionize_calls(&rewritten.clone())?.iter().rev() {
+                self.add_sql(&call.source_sql)?;
+                rewritten.replace_range(call.span.0..call.span.1, "(SELECT 1)");
+            }
+        }
+        if time_series::contains_gap_fill(&rewritten) {
+            for call in time_series::find_gap_fill_calls(&rewritten.clone())?.iter().rev() {
+                self.add_sql(&call.source_sql)?;
+                rewritten.replace_range(call.span.0..call.span.1, "(SELECT 1)");
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    fn add_statement(&mut self, statement: DFStatement) -> BlazeResult<()> {
+        let statement = match statement {
+            DFStatement::Statement(statement) => *statement,
+            DFStatement::CreateExternalTable(create) => return self.add_write(&create.name),
+            DFStatement::CopyTo(copy) => {
+                return match copy.source {
+                    CopyToSource::Relation(name) => self.add_read(&name),
+                    CopyToSource::Query(query) => self.add_query(*query),
+                };
+            }
+            DFStatement::Explain(explain) => return self.add_statement(*explain.statement),
+        };
+
+        match statement {
+            Statement::Explain { statement, .. } => self.add_statement(DFStatement::Statement(statement)),
+            Statement::Insert(insert) => {
+                self.add_write(&insert.table_name)?;
+                match insert.source {
+                    Some(source) => self.add_query(*source),
+                    None => Ok(()),
+                }
+            }
+            Statement::CreateTable(create) => {
+                self.add_write(&create.name)?;
+                match create.query {
+                    Some(query) => self.add_query(*query),
+                    None => Ok(()),
+                }
+            }
+            Statement::CreateView { name, query, .. } => {
+                self.add_write(&name)?;
+                self.add_query(*query)
+            }
+            Statement::Drop { object_type: ObjectType::Table | ObjectType::View, names, .. } => {
+                names.iter().try_for_each(|name| self.add_write(name))
+            }
+            Statement::Truncate { table_names, .. } => {
+                table_names.iter().try_for_each(|target| self.add_write(&target.name))
+            }
+            // The modified table is also read: its rows are filtered and rewritten
+            Statement::Update { ref table, .. } => {
+                if let TableFactor::Table { name, .. } = &table.relation {
+                    self.add_write(name)?;
+                }
+                self.add_reads(DFStatement::Statement(Box::new(statement)))
+            }
+            Statement::Delete(ref delete) => {
+                let from = match &delete.from {
+                    FromTable::WithFromKeyword(from) | FromTable::WithoutKeyword(from) => from,
+                };
+                let targets: Vec<ObjectName> = if delete.tables.is_empty() {
+                    from.iter()
+                        .filter_map(|table| match &table.relation {
+                            TableFactor::Table { name, .. } => Some(name.clone()),
+                            _ => None,
+                        })
+                        .take(1)
+                        .collect()
+                } else {
+                    delete.tables.clone()
+                };
+                targets.iter().try_for_each(|name| self.add_write(name))?;
+                self.add_reads(DFStatement::Statement(Box::new(statement)))
+            }
+            statement => self.add_reads(DFStatement::Statement(Box::new(statement))),
+        }
+    }
+
+    fn add_query(&mut self, query: Query) -> BlazeResult<()> {
+        self.add_reads(DFStatement::Statement(Box::new(Statement::Query(Box::new(query)))))
+    }
+
+    /// Record every relation the planner would look up for `statement`
+    fn add_reads(&mut self, statement: DFStatement) -> BlazeResult<()> {
+        for reference in self.state.resolve_table_references(&statement)? {
+            if !self.state.table_functions().contains_key(reference.table()) {
+                self.reads.insert(self.qualify(reference));
+            }
+        }
+        Ok(())
+    }
+
+    fn add_read(&mut self, name: &ObjectName) -> BlazeResult<()> {
+        let reference = self.table_reference(name)?;
+        self.reads.insert(self.qualify(reference));
+        Ok(())
+    }
+
+    fn add_write(&mut self, name: &ObjectName) -> BlazeResult<()> {
+        let reference = self.table_reference(name)?;
+        self.writes.insert(self.qualify(reference));
+        Ok(())
+    }
+
+    fn table_reference(&self, name: &ObjectName) -> BlazeResult<TableReference> {
+        let normalize = self.state.config_options().sql_parser.enable_ident_normalization;
+        Ok(object_name_to_table_reference(name.clone(), normalize)?)
+    }
+
+    fn qualify(&self, reference: TableReference) -> String {
+        let catalog = &self.state.config_options().catalog;
+        reference.resolve(&catalog.default_catalog, &catalog.default_schema).to_string()
+    }
+}
@@ -0,0 +1,140 @@
+//! `UPDATE` and `DELETE` on in-memory tables
+//!
+//! DataFusion plans both statements but its in-memory tables cannot run
+//! them. The engine rewrites the planned statement into queries over the
+//! table instead and stores their result as the table's next version:
+//!
+//! - the rows the statement leaves alone, those where the `WHERE` clause is
+//!   not true
+//! - the rows it affects, as they were, for the affected-row count and the
+//!   change feed
+//! - for `UPDATE`, the affected rows with the `SET` expressions applied
+//!
+//! `WHERE` clauses with subqueries, e.g. `DELETE FROM events WHERE user_id
+//! IN (SELECT id FROM erasure_requests)`, keep the rows that are not among
+//! the affected ones. `UPDATE ... FROM` is not supported.
+
+use datafusion::common::tree_node::TreeNode;
+use datafusion::common::{not_impl_err, plan_err};
+use datafusion::error::Result;
+use datafusion::logical_expr::{DmlStatement, Expr, LogicalPlan, LogicalPlanBuilder, WriteOp};
+
+/// Target table of an `UPDATE` or `DELETE` plan
+pub(crate) fn mutation_target(plan: &LogicalPlan) -> Option<String> {
+    match plan {
+        LogicalPlan::Dml(DmlStatement { table_name, op: WriteOp::Update | WriteOp::Delete, .. }) => {
+            Some(table_name.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Queries computing the new contents of a table an `UPDATE` or `DELETE`
+/// writes
+#[derive(Debug)]
+pub(crate) struct Rewrite {
+    /// Rows left as they are
+    pub kept: LogicalPlan,
+    /// Rows updated or deleted, as they were
+    pub affected: LogicalPlan,
+    /// New values of the updated rows; `None` for `DELETE`
+    pub updated: Option<LogicalPlan>,
+}
+
+/// Rewrite a planned `UPDATE` or `DELETE`
+pub(crate) fn rewrite(dml: &DmlStatement) -> Result<Rewrite> {
+    let (assignments, source) = match (&dml.op, dml.input.as_ref()) {
+        (WriteOp::Delete, input) => (None, input),
+        (WriteOp::Update, LogicalPlan::Projection(projection)) => (Some(projection.expr.clone()), projection.input.as_ref()),
+        (op, _) => return plan_err!("Cannot rewrite {} statements", op),
+    };
+    let (predicate, scan) = match source {
+        LogicalPlan::Filter(filter) => (Some(filter.predicate.clone()), filter.input.as_ref()),
+        other => (None, other),
+    };
+    if !is_table_scan(scan) {
+        return not_impl_err!("UPDATE ... FROM is not supported; update from a subquery in the WHERE clause instead");
+    }
+
+    let (kept, affected) = match predicate {
+        None => (LogicalPlanBuilder::from(scan.clone()).limit(0, Some(0))?.build()?, scan.clone()),
+        Some(predicate) => {
+            let affected = LogicalPlanBuilder::from(scan.clone()).filter(predicate.clone())?.build()?;
+            // Subqueries can only be planned as joins at the top of a filter,
+            // so rows equal to affected ones are taken out with an anti join
+            let kept = if has_subquery(&predicate)? {
+                LogicalPlanBuilder::except(scan.clone(), affected.clone(), true)?
+            } else {
+                LogicalPlanBuilder::from(scan.clone())
+                    .filter(Expr::IsNotTrue(Box::new(predicate)))?
+                    .build()?
+            };
+            (kept, affected)
+        }
+    };
+    let updated = match assignments {
+        Some(assignments) => Some(LogicalPlanBuilder::from(affected.clone()).project(assignments)?.build()?),
+        None => None,
+    };
+    Ok(Rewrite { kept, affected, updated })
+}
+
+fn is_table_scan(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::TableScan(_) => true,
+        LogicalPlan::SubqueryAlias(alias) => is_table_scan(&alias.input),
+        _ => false,
+    }
+}
+
+fn has_subquery(expr: &Expr) -> Result<bool> {
+    expr.exists(|e| Ok(matches!(e, Expr::ScalarSubquery(_) | Expr::InSubquery(_) | Expr::Exists(_))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::prelude::SessionContext;
+
+    async fn rewrite_sql(ctx: &SessionContext, sql: &str) -> Result<Rewrite> {
+        let plan = ctx.state().create_logical_plan(sql).await?;
+        let LogicalPlan::Dml(dml) = &plan else { panic!("not a DML plan: {}", plan) };
+        assert_eq!(mutation_target(&plan).as_deref(), Some("t"));
+        rewrite(dml)
+    }
+
+    async fn count(ctx: &SessionContext, plan: LogicalPlan) -> usize {
+        ctx.execute_logical_plan(plan).await.unwrap().count().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_update_and_delete() {
+        let ctx = SessionContext::new();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, true)]));
+        let ids = Int64Array::from(vec![Some(1), Some(2), Some(3), None]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(ids)]).unwrap();
+        ctx.register_batch("t", batch).unwrap();
+
+        // Rows where the predicate is NULL are kept
+        let delete = rewrite_sql(&ctx, "DELETE FROM t WHERE id > 1").await.unwrap();
+        assert_eq!((count(&ctx, delete.kept).await, count(&ctx, delete.affected).await), (2, 2));
+        assert!(delete.updated.is_none());
+
+        let update = rewrite_sql(&ctx, "UPDATE t SET id = id * 10").await.unwrap();
+        assert_eq!((count(&ctx, update.kept).await, count(&ctx, update.affected).await), (0, 4));
+        let updated = ctx.execute_logical_plan(update.updated.unwrap()).await.unwrap().collect().await.unwrap();
+        let ids = updated[0].column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.iter().collect::<Vec<_>>(), vec![Some(10), Some(20), Some(30), None]);
+
+        let subquery = rewrite_sql(&ctx, "DELETE FROM t WHERE id IN (SELECT 2 UNION ALL SELECT 3)").await.unwrap();
+        assert_eq!((count(&ctx, subquery.kept).await, count(&ctx, subquery.affected).await), (2, 2));
+
+        ctx.register_batch("u", RecordBatch::new_empty(ctx.table_provider("t").await.unwrap().schema())).unwrap();
+        let from = rewrite_sql(&ctx, "UPDATE t SET id = u.id FROM u WHERE t.id = u.id").await;
+        assert!(from.unwrap_err().to_string().contains("UPDATE ... FROM is not supported"));
+    }
+}
@@ -0,0 +1,83 @@
+//! Dry runs: what a query would read, without running it
+//!
+//! `BlazeQueryEngine::dry_run` plans a query like BigQuery's `dryRun` flag
+//! and reports the bytes it is expected to process, so a frontend can warn
+//! "This query will process 1.2 GB" before it runs. Like BigQuery, only the
+//! columns a query reads count: the estimate adds up what the scans of the
+//! optimized physical plan are expected to produce, after projections are
+//! pushed into them.
+//!
+//! Scans of in-memory tables know their size exactly. File scans report what
+//! their metadata tells the planner, which is marked inexact, and scans
+//! without any estimate, such as remote tables, make `bytes_exact` false
+//! without adding to the total.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use datafusion::common::stats::Precision;
+use datafusion::common::tree_node::TreeNodeRecursion;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::ExecutionPlan;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlazeResult;
+
+/// A table a dry-run query reads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScannedTable {
+    /// Table name as the query refers to it
+    pub table: String,
+    /// Columns read from the table, sorted
+    pub columns: Vec<String>,
+}
+
+/// What a query would read, from `dry_run`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunReport {
+    /// Estimated bytes the query processes
+    pub total_bytes_processed: u64,
+    /// Whether `total_bytes_processed` is exact, rather than an estimate or
+    /// missing scans the planner cannot size
+    pub bytes_exact: bool,
+    /// Tables read, sorted by name
+    pub tables: Vec<ScannedTable>,
+    /// The optimized logical plan, as in `EXPLAIN`
+    pub optimized_plan: String,
+}
+
+/// Tables and columns read by the scans of an optimized logical plan,
+/// including in subqueries
+pub(crate) fn scanned_tables(plan: &LogicalPlan) -> BlazeResult<Vec<ScannedTable>> {
+    let mut tables: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    plan.apply_with_subqueries(|plan| {
+        if let LogicalPlan::TableScan(scan) = plan {
+            let columns = tables.entry(scan.table_name.to_string()).or_default();
+            columns.extend(scan.projected_schema.fields().iter().map(|field| field.name().clone()));
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })?;
+    Ok(tables
+        .into_iter()
+        .map(|(table, columns)| ScannedTable { table, columns: columns.into_iter().collect() })
+        .collect())
+}
+
+/// Bytes produced by the scans at the leaves of `plan`, and whether every
+/// scan knows them exactly
+pub(crate) fn scanned_bytes(plan: &Arc<dyn ExecutionPlan>) -> BlazeResult<(u64, bool)> {
+    let children = plan.children();
+    if children.is_empty() {
+        return Ok(match plan.statistics()?.total_byte_size {
+            Precision::Exact(bytes) => (bytes as u64, true),
+            Precision::Inexact(bytes) => (bytes as u64, false),
+            Precision::Absent => (0, false),
+        });
+    }
+    let mut total = (0, true);
+    for child in children {
+        let (bytes, exact) = scanned_bytes(child)?;
+        total = (total.0 + bytes, total.1 && exact);
+    }
+    Ok(total)
+}
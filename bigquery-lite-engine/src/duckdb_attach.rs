@@ -0,0 +1,357 @@
+//! Read-only access to DuckDB database files
+//!
+//! `attach_duckdb("analytics.duckdb", "warehouse")` opens the file read-only
+//! and registers every table and view in it, so they can be joined with the
+//! engine's own tables:
+//!
+//! ```sql
+//! ATTACH 'analytics.duckdb' AS warehouse;
+//! SELECT e.*, u.plan FROM events e JOIN warehouse.users u ON e.user_id = u.id;
+//! SELECT * FROM warehouse.staging.orders;
+//! DETACH warehouse;
+//! ```
+//!
+//! Tables of DuckDB's `main` schema are reachable as `alias.table`, tables of
+//! any schema as `alias.schema.table`. Without `AS alias` the file name is
+//! the alias, as in DuckDB: `ATTACH 'analytics.duckdb'` attaches `analytics`. Nothing is copied at attach time: each
+//! scan runs a query against the file with the projected columns, the simple
+//! comparisons of the WHERE clause and the LIMIT pushed down, so DuckDB only
+//! returns the rows and columns the query needs.
+
+use std::any::Any;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use datafusion::catalog::{CatalogProvider, SchemaProvider, Session};
+use datafusion::catalog_common::{MemoryCatalogProvider, MemorySchemaProvider};
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use duckdb::{AccessMode, Config, Connection};
+use parking_lot::Mutex;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::sql_pushdown::{self, quote_identifier};
+
+/// DuckDB's default schema, whose tables are also reachable as `alias.table`
+const MAIN_SCHEMA: &str = "main";
+
+/// A DuckDB file attached with `attach_duckdb` or `ATTACH`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachedDatabaseInfo {
+    /// Name the database's tables are qualified with
+    pub alias: String,
+    /// Path of the database file
+    pub path: String,
+    /// Registered tables and views as `alias.schema.table`, sorted
+    pub tables: Vec<String>,
+}
+
+/// Parse `ATTACH [DATABASE] 'path' [AS alias] [(TYPE duckdb, READ_ONLY)]`,
+/// returning the path and alias. Only read-only DuckDB attachments are
+/// supported, so the options may be given but cannot ask for anything else.
+pub(crate) fn parse_attach(sql: &str) -> BlazeResult<Option<(String, String)>> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*ATTACH\s+(?:DATABASE\s+)?'((?:[^']|'')*)'\s*(?:AS\s+([A-Za-z_]\w*)\s*)?(?:\(([^)]*)\))?\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let Some(captures) = PATTERN.captures(sql) else {
+        return Ok(None);
+    };
+    if let Some(options) = captures.get(3) {
+        for option in options.as_str().split(',').map(|o| o.split_whitespace().collect::<Vec<_>>().join(" ")) {
+            match option.to_uppercase().as_str() {
+                "READ_ONLY" | "READ_ONLY TRUE" | "TYPE DUCKDB" | "" => {}
+                _ => return Err(invalid_input!("Unsupported ATTACH option '{}'; databases are attached read-only", option)),
+            }
+        }
+    }
+    let path = captures[1].replace("''", "'");
+    let alias = match captures.get(2) {
+        Some(alias) => alias.as_str().to_string(),
+        None => default_alias(Path::new(&path))?,
+    };
+    Ok(Some((path, alias)))
+}
+
+/// The alias of a file attached without one: its name without the extension
+pub(crate) fn default_alias(path: &Path) -> BlazeResult<String> {
+    static IDENTIFIER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z_]\w*$").unwrap());
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if IDENTIFIER.is_match(stem) => Ok(stem.to_string()),
+        _ => Err(invalid_input!(
+            "Cannot derive an alias from '{}'; attach it with AS alias",
+            path.display()
+        )),
+    }
+}
+
+/// Parse `DETACH [DATABASE] [IF EXISTS] alias`, returning the alias and
+/// whether IF EXISTS was given
+pub(crate) fn parse_detach(sql: &str) -> Option<(String, bool)> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*DETACH\s+(?:DATABASE\s+)?(IF\s+EXISTS\s+)?([A-Za-z_]\w*)\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    Some((captures[2].to_string(), captures.get(1).is_some()))
+}
+
+/// Providers of one DuckDB schema, by table name
+type SchemaTables = Vec<(String, Arc<DuckDbTable>)>;
+
+/// An opened DuckDB file with its tables wrapped as providers
+pub(crate) struct AttachedDatabase {
+    pub(crate) info: AttachedDatabaseInfo,
+    /// Providers by DuckDB schema
+    schemas: Vec<(String, SchemaTables)>,
+}
+
+impl AttachedDatabase {
+    /// Open `path` read-only and read the schema of every table and view
+    pub(crate) fn open(path: &Path, alias: &str) -> BlazeResult<Self> {
+        if !path.is_file() {
+            return Err(invalid_input!("DuckDB database '{}' does not exist", path.display()));
+        }
+        let config = Config::default().access_mode(AccessMode::ReadOnly).map_err(duckdb_error)?;
+        let connection = Connection::open_with_flags(path, config).map_err(duckdb_error)?;
+
+        let mut statement = connection
+            .prepare(
+                "SELECT table_schema, table_name FROM information_schema.tables \
+                 WHERE table_catalog = current_database() ORDER BY table_schema, table_name",
+            )
+            .map_err(duckdb_error)?;
+        let names = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(duckdb_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(duckdb_error)?;
+        drop(statement);
+
+        let connection = Arc::new(Mutex::new(connection));
+        let mut schemas: Vec<(String, SchemaTables)> = Vec::new();
+        let mut tables = Vec::new();
+        for (schema_name, table_name) in names {
+            let table = DuckDbTable::try_new(connection.clone(), &schema_name, &table_name)?;
+            tables.push(format!("{}.{}.{}", alias, schema_name, table_name));
+            match schemas.last_mut() {
+                Some((name, providers)) if *name == schema_name => providers.push((table_name, Arc::new(table))),
+                _ => schemas.push((schema_name, vec![(table_name, Arc::new(table))])),
+            }
+        }
+
+        Ok(Self {
+            info: AttachedDatabaseInfo {
+                alias: alias.to_string(),
+                path: path.display().to_string(),
+                tables,
+            },
+            schemas,
+        })
+    }
+
+    /// Catalog holding one schema per DuckDB schema, for `alias.schema.table`
+    pub(crate) fn catalog(&self) -> BlazeResult<Arc<dyn CatalogProvider>> {
+        let catalog = MemoryCatalogProvider::new();
+        for (schema_name, _) in &self.schemas {
+            catalog.register_schema(schema_name, self.schema(schema_name)?)?;
+        }
+        Ok(Arc::new(catalog))
+    }
+
+    /// The tables of `main`, for `alias.table`
+    pub(crate) fn main_schema(&self) -> BlazeResult<Arc<dyn SchemaProvider>> {
+        self.schema(MAIN_SCHEMA)
+    }
+
+    fn schema(&self, schema_name: &str) -> BlazeResult<Arc<dyn SchemaProvider>> {
+        let schema = MemorySchemaProvider::new();
+        if let Some((_, providers)) = self.schemas.iter().find(|(name, _)| name == schema_name) {
+            for (table_name, provider) in providers {
+                schema.register_table(table_name.clone(), provider.clone())?;
+            }
+        }
+        Ok(Arc::new(schema))
+    }
+}
+
+/// A table or view of an attached DuckDB file, queried on every scan
+#[derive(Debug)]
+pub(crate) struct DuckDbTable {
+    connection: Arc<Mutex<Connection>>,
+    /// Quoted `"schema"."table"` reference
+    reference: String,
+    schema: SchemaRef,
+}
+
+impl DuckDbTable {
+    fn try_new(connection: Arc<Mutex<Connection>>, schema_name: &str, table_name: &str) -> BlazeResult<Self> {
+        let reference = format!("{}.{}", quote_identifier(schema_name), quote_identifier(table_name));
+        let schema = {
+            let connection = connection.lock();
+            let mut statement = connection
+                .prepare(&format!("SELECT * FROM {} LIMIT 0", reference))
+                .map_err(duckdb_error)?;
+            let arrow = statement.query_arrow([]).map_err(duckdb_error)?;
+            arrow.get_schema()
+        };
+        Ok(Self { connection, reference, schema })
+    }
+
+    /// The SQL DuckDB runs for a scan
+    fn scan_sql(&self, projection: Option<&Vec<usize>>, filters: &[Expr], limit: Option<usize>) -> Result<String> {
+        sql_pushdown::scan_sql(&self.reference, &self.schema, projection, filters, limit)
+    }
+}
+
+#[async_trait]
+impl TableProvider for DuckDbTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(indices) => Arc::new(self.schema.project(indices)?),
+            None => self.schema.clone(),
+        };
+        let sql = self.scan_sql(projection, filters, limit)?;
+        let connection = self.connection.lock().try_clone().map_err(|e| external(duckdb_error(e)))?;
+
+        let batch_schema = schema.clone();
+        let batches = tokio::task::spawn_blocking(move || -> BlazeResult<Vec<RecordBatch>> {
+            let mut statement = connection.prepare(&sql).map_err(duckdb_error)?;
+            let batches = statement
+                .query_arrow([])
+                .map_err(duckdb_error)?
+                .map(|batch| conform(batch, &batch_schema))
+                .collect::<BlazeResult<Vec<_>>>()?;
+            Ok(batches)
+        })
+        .await
+        .map_err(|e| DataFusionError::External(Box::new(e)))?
+        .map_err(external)?;
+
+        MemTable::try_new(schema, vec![batches])?.scan(state, None, &[], None).await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(sql_pushdown::filter_pushdown(filters))
+    }
+}
+
+/// Put a batch returned by DuckDB under the schema DataFusion planned with
+fn conform(batch: RecordBatch, schema: &SchemaRef) -> BlazeResult<RecordBatch> {
+    if schema.fields().is_empty() {
+        let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+        return Ok(RecordBatch::try_new_with_options(schema.clone(), vec![], &options)?);
+    }
+    Ok(RecordBatch::try_new(schema.clone(), batch.columns().to_vec())?)
+}
+
+fn duckdb_error(e: duckdb::Error) -> BlazeError {
+    BlazeError::External(format!("DuckDB: {}", e))
+}
+
+fn external(e: BlazeError) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::prelude::{col, lit};
+
+    fn create_database(path: &Path) {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE users (id INTEGER, name VARCHAR, plan VARCHAR);
+                 INSERT INTO users VALUES (1, 'ada', 'pro'), (2, 'bob', 'free'), (3, 'cy', NULL);
+                 CREATE SCHEMA staging;
+                 CREATE TABLE staging.orders (id INTEGER, user_id INTEGER, amount DOUBLE);
+                 INSERT INTO staging.orders VALUES (10, 1, 9.5), (11, 1, 20.0), (12, 2, 3.25);
+                 CREATE VIEW paying AS SELECT * FROM users WHERE plan = 'pro';",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_parse_attach_and_detach() {
+        assert_eq!(
+            parse_attach("ATTACH 'db/it''s.duckdb' AS wh (TYPE duckdb, READ_ONLY);").unwrap(),
+            Some(("db/it's.duckdb".to_string(), "wh".to_string()))
+        );
+        assert_eq!(
+            parse_attach("attach database 'a.duckdb' as a").unwrap(),
+            Some(("a.duckdb".to_string(), "a".to_string()))
+        );
+        assert_eq!(
+            parse_attach("ATTACH 'data/analytics.duckdb' (READ_ONLY)").unwrap(),
+            Some(("data/analytics.duckdb".to_string(), "analytics".to_string()))
+        );
+        assert!(parse_attach("ATTACH 'my-db.duckdb'").is_err());
+        assert!(parse_attach("ATTACH 'a.duckdb' AS a (READ_WRITE)").is_err());
+        assert_eq!(parse_attach("SELECT 1").unwrap(), None);
+        assert_eq!(parse_detach("DETACH IF EXISTS wh;"), Some(("wh".to_string(), true)));
+        assert_eq!(parse_detach("DETACH DATABASE wh"), Some(("wh".to_string(), false)));
+    }
+
+    #[test]
+    fn test_open_lists_tables_and_pushes_down_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("warehouse.duckdb");
+        create_database(&path);
+
+        let database = AttachedDatabase::open(&path, "wh").unwrap();
+        assert_eq!(
+            database.info.tables,
+            vec!["wh.main.paying", "wh.main.users", "wh.staging.orders"]
+        );
+
+        let (_, main) = &database.schemas[0];
+        let users = &main.iter().find(|(name, _)| name == "users").unwrap().1;
+        let columns: Vec<_> = users.schema.fields().iter().map(|f| f.name().clone()).collect();
+        assert_eq!(columns, vec!["id", "name", "plan"]);
+
+        let filter = col("users.plan").eq(lit("pro")).or(col("id").gt(lit(2)));
+        assert!(sql_pushdown::is_portable(&filter));
+        assert!(!sql_pushdown::is_portable(&col("name").like(lit("a%"))));
+        assert_eq!(
+            users.scan_sql(Some(&vec![1]), &[filter], Some(5)).unwrap(),
+            "SELECT \"name\" FROM \"main\".\"users\" WHERE (((\"plan\" = 'pro') OR (id > 2))) LIMIT 5"
+        );
+        assert_eq!(
+            users.scan_sql(Some(&vec![]), &[], None).unwrap(),
+            "SELECT NULL FROM \"main\".\"users\""
+        );
+
+        assert!(AttachedDatabase::open(&dir.path().join("missing.duckdb"), "x").is_err());
+    }
+}
@@ -1,39 +1,230 @@
 //! Core BlazeQueryEngine implementation using DataFusion
 
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use datafusion::prelude::*;
 use datafusion::execution::context::SessionConfig;
 use datafusion::execution::runtime_env::RuntimeEnvBuilder;
-use datafusion::datasource::MemTable;
+use datafusion::datasource::{MemTable, TableProvider, ViewTable};
 use datafusion::arrow::record_batch::RecordBatch;
-use datafusion::arrow::datatypes::Schema;
-use datafusion::arrow::array::Array;
-use datafusion::execution::memory_pool::{GreedyMemoryPool, MemoryPool};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::array::{Array, Int64Array, UInt64Array};
+use datafusion::arrow::compute::concat_batches;
+use datafusion::execution::memory_pool::MemoryPool;
+use datafusion::functions_aggregate::expr_fn::max;
+use datafusion::logical_expr::{DmlStatement, LogicalPlan, WriteOp};
+use datafusion::logical_expr::dml::InsertOp;
+use datafusion::physical_plan::memory::MemoryStream;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion::sql::TableReference;
+#[cfg(feature = "object_store")]
+use datafusion::execution::object_store::ObjectStoreUrl;
+#[cfg(feature = "object_store")]
+use object_store::ObjectStore;
 
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
-use tracing::{info, warn, error, debug, instrument};
+use tracing::{info, warn, debug, instrument};
 
+use crate::admission::{AdmissionControl, AdmissionStats, QueryPriority};
+use crate::assertions::{self, Assertion, AssertionResult};
+use crate::cardinality::CardinalityEstimate;
+use crate::cdc::{ChangeEvent, ChangeFeed, ChangeSubscription, ChangeType};
+use crate::checksums::{self, ChecksumMode};
+use crate::clock::{Clock, SharedClock};
+use crate::copy::{self, CopyDirection, CopySource, CopyStatement};
+use crate::csv_ingest::{self, BadRowPolicy, CsvIngestOptions, CsvLoadReport};
+use crate::dependencies::{ReferenceCollector, TableReferences};
+use crate::decimal::{self, DecimalOverflow, DecimalRules};
+use crate::dml;
+use crate::dry_run::{self, DryRunReport};
+#[cfg(feature = "duckdb")]
+use crate::duckdb_attach::{self, AttachedDatabase, AttachedDatabaseInfo};
+#[cfg(feature = "sqlite")]
+use crate::sqlite_attach::{SqliteDatabase, SqliteDatabaseInfo};
+use crate::engine_state::{
+    self, EngineStateInfo, SavedArrowIpcTable, SavedFileTable, SavedFlightTable, SavedOrcTable, SavedView, StateManifest,
+};
+#[cfg(feature = "duckdb")]
+use crate::engine_state::SavedAttachment;
+#[cfg(feature = "sqlite")]
+use crate::engine_state::SavedSqliteDatabase;
+#[cfg(feature = "delta")]
+use crate::engine_state::SavedDeltaTable;
+#[cfg(feature = "iceberg")]
+use crate::engine_state::SavedIcebergTable;
 use crate::error::{BlazeError, BlazeResult};
+use crate::export_data::{self, ExportStatement};
+use crate::file_tables::{self, CsvTableOptions, DataFormat, FileListing, FileTableInfo, JsonTableOptions};
+use crate::flight_tables::{self, FlightSource, FlightTableInfo};
+#[cfg(feature = "postgres")]
+use crate::postgres_tables::{self, PostgresTableInfo};
+use crate::orc_tables::{self, OrcTableInfo};
+use crate::arrow_ipc_tables::{self, ArrowIpcTableInfo};
+#[cfg(feature = "delta")]
+use crate::delta_tables::{self, DeltaTableInfo};
+#[cfg(feature = "iceberg")]
+use crate::iceberg_tables::{self, IcebergTableInfo, IcebergVersion, RegisteredIcebergTable};
+use crate::geo_ingest::{self, GeoLoadReport};
+use crate::avro_ingest::{self, AvroLoadReport};
+use crate::protobuf_ingest::{self, ProtobufFraming, ProtobufLoadReport};
+#[cfg(feature = "bigquery")]
+use crate::bigquery_ingest::{self, BigQueryLoadReport, BigQueryReadOptions};
+use crate::join_order::{self, JoinDiagnostics, StatisticsStore, TableStatistics};
+use crate::materialized_views::{MaterializedView, MaterializedViewInfo};
+use crate::memory_pool::ResizableMemoryPool;
+use crate::ml::{self, Model};
+#[cfg(feature = "object_store")]
+use crate::object_cache::{CachedStore, ObjectCache, ObjectCacheStats};
+#[cfg(feature = "object_store")]
+use crate::object_stores::{self, AzureCredentials, GcsCredentials, ObjectStoreInfo};
+use crate::parquet_sink::{self, ParquetSink, ParquetSinkOptions, ParquetSinkReport};
+use crate::plan_cache::{self, PlanCache, PlanCacheStats};
+use crate::plan_graph::{self, PlanGraph};
+use crate::plan_regressions::{self, BaselineQuery, PlanBaseline, PlanRegressionReport};
+use crate::prepared::{BoundStatement, PreparedStatement, PreparedStatementInfo};
+use crate::profiling::{self, QueryProfile, QueryProfiler};
+use crate::query_hints;
+use crate::relations::{self, ColumnInfo, FreshnessInfo, RelationInfo, RelationType};
+use crate::result_export::{CsvExportOptions, ExportFile, ExportReport, JsonExportOptions, ParquetExportOptions};
+use crate::result_cache::{self, ResultCache, ResultCacheStats, TableVersions};
+use crate::result_tables::{self, ResultPage, ResultTableInfo, ResultTables};
+use crate::search::{self, SearchIndexInfo, SearchIndexedTable};
+use crate::table_eviction::{self, EvictionEvent, TableEviction, TableMemoryInfo};
+use crate::table_versions::{self, TableWriters, VersionedTable};
+use crate::time_partitions::{TimePartitionInfo, TimePartitionedTable, TimePartitioning};
+use crate::serializers::{self, ResultSerializer, SerializeContext, SerializedResult, SerializerRegistry};
+use crate::sessionize;
+use crate::query_history::{QueryHistory, QueryHistoryEntry, QueryHistoryFilter};
+use crate::sessions::{self, SessionInfo};
+use crate::shared_results::{self, SharedResultInfo, SharedResultWriter, SharedResults};
+use crate::shutdown::{QueryTracker, RunningQueryInfo, ShutdownOptions, ShutdownReport};
+use crate::sketches;
+use crate::substr;
+use crate::slots::{SlotPool, SlotStats};
+use crate::suggestions;
+use crate::time_series;
+use crate::transactions::{self, HeldChange, SavedTable, Transaction, TransactionControl, TransactionInfo};
+use crate::time_zone;
+use crate::table_functions::HiddenResults;
+use crate::vector::{self, CreateVectorIndex, VectorIndex, VectorIndexInfo};
+use crate::workload::{ResourceGroup, ResourceGroupState, ResourceGroupStats};
+use crate::utils::{fingerprint_sql, format_bytes, normalize_sql};
+use crate::snapshots::{self, SnapshotInfo, SnapshotStore, VersionedSource};
 
 /// Query execution result with performance metrics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResult {
-    /// Number of rows returned
+    /// Number of rows in the result; `data` holds only the first page of
+    /// them with `QueryOptions::max_rows`
     pub rows: usize,
     /// Query execution time in milliseconds
     pub execution_time_ms: u64,
     /// Memory used during execution in bytes
     pub memory_used_bytes: u64,
+    /// Bytes of the result rows in Arrow memory
+    #[serde(default)]
+    pub result_bytes: u64,
     /// Query result data as JSON-serializable values
     pub data: Vec<HashMap<String, serde_json::Value>>,
     /// Query plan for debugging
     pub query_plan: Option<String>,
     /// Engine identifier
     pub engine: String,
+    /// CPU profile, when `QueryOptions::profile` was set
+    #[serde(default)]
+    pub profile: Option<QueryProfile>,
+    /// Where the result was kept, e.g. `_results.job_3f2a9c0d1e4b5a67`,
+    /// when `QueryOptions::cache_result` or `max_rows` was set
+    #[serde(default)]
+    pub result_table: Option<String>,
+    /// Token for `fetch_results` to read the rows after those in `data`,
+    /// when `QueryOptions::max_rows` left some out
+    #[serde(default)]
+    pub page_token: Option<String>,
+    /// Checksum of the rows, when `QueryOptions::checksum` was set
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Rows written to files by a `COPY ... TO` or `EXPORT DATA` statement
+    #[serde(default)]
+    pub rows_written: Option<u64>,
+    /// Bytes written to files by a `COPY ... TO` or `EXPORT DATA` statement
+    #[serde(default)]
+    pub bytes_written: Option<u64>,
+    /// Whether the result was served from the result cache
+    #[serde(default)]
+    pub cache_hit: bool,
+    /// ID the query ran under, from `QueryOptions::query_id` or generated
+    #[serde(default)]
+    pub query_id: String,
+}
+
+/// Per-query settings for `execute_query_with_options`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryOptions {
+    /// Resource group to run the query in
+    pub resource_group: Option<String>,
+    /// Free-form labels, aggregated by `get_stats_by_label`; a
+    /// `resource_group` label selects the group when the option is not set
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Capture a CPU profile of the query; needs the `profiling` feature
+    #[serde(default)]
+    pub profile: bool,
+    /// Directory for the profile files (default: `bqlite-profiles` in the
+    /// system temp directory)
+    #[serde(default)]
+    pub profile_dir: Option<PathBuf>,
+    /// Keep the result of a query as an anonymous table in the `_results`
+    /// schema for `result_table_ttl_secs`
+    #[serde(default)]
+    pub cache_result: bool,
+    /// Compute a checksum of the result, in or regardless of row order, for
+    /// comparing results without keeping them
+    #[serde(default)]
+    pub checksum: Option<ChecksumMode>,
+    /// ID to cancel the query by with `cancel_query`, unique among running
+    /// queries (default: generated)
+    #[serde(default)]
+    pub query_id: Option<String>,
+    /// Milliseconds the query may run, overriding `query_timeout_ms`; 0
+    /// means no limit
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Return at most this many rows in `data`, keeping the whole result as
+    /// a result table whose other pages `fetch_results` reads
+    #[serde(default)]
+    pub max_rows: Option<usize>,
+    /// Session to run the statement in, from `create_session`; its settings
+    /// override the engine's configuration
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Whether the query waits for admission ahead of batch queries or
+    /// behind interactive ones (default: interactive)
+    #[serde(default)]
+    pub priority: QueryPriority,
+}
+
+impl QueryOptions {
+    /// Options running a query in a resource group
+    pub fn in_group(name: &str) -> Self {
+        Self {
+            resource_group: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn resource_group(&self) -> Option<&str> {
+        self.resource_group
+            .as_deref()
+            .or_else(|| self.labels.get("resource_group").map(String::as_str))
+    }
 }
 
 /// Performance statistics for the engine
@@ -47,10 +238,77 @@ pub struct EngineStats {
     pub peak_memory_bytes: u64,
     /// Number of registered tables
     pub registered_tables: usize,
+    /// Tables evicted under memory pressure
+    #[serde(default)]
+    pub evicted_tables: u64,
+    /// In-memory bytes freed by evicting tables
+    #[serde(default)]
+    pub evicted_bytes: u64,
+    /// Usage of the object cache, when `object_cache_dir` is set
+    #[cfg(feature = "object_store")]
+    #[serde(default)]
+    pub object_cache: Option<ObjectCacheStats>,
+    /// Usage of the query result cache
+    #[serde(default)]
+    pub result_cache: ResultCacheStats,
+    /// Usage of the query plan cache
+    #[serde(default)]
+    pub plan_cache: PlanCacheStats,
+    /// Queries running and queued under admission control
+    #[serde(default)]
+    pub admission: AdmissionStats,
+    /// Use of the slot pool
+    #[serde(default)]
+    pub slots: SlotStats,
+}
+
+/// Statistics of the queries sharing one SQL fingerprint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryFingerprintStats {
+    /// Fingerprint from `utils::fingerprint_sql`
+    pub fingerprint: String,
+    /// Normalized SQL the fingerprint was computed from
+    pub normalized_sql: String,
+    /// Successful executions
+    pub executions: u64,
+    /// Execution time summed over all executions
+    pub total_execution_time_ms: u64,
+    /// Average execution time in milliseconds
+    pub avg_execution_time_ms: f64,
+    /// Largest memory increase seen for one execution
+    pub peak_memory_bytes: u64,
+}
+
+/// Distinct fingerprints tracked before the least executed is dropped
+const MAX_TRACKED_FINGERPRINTS: usize = 1000;
+
+/// Usage of the queries carrying one label value, for attributing it to
+/// teams or dashboards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelStats {
+    /// Label key, e.g. `team`
+    pub label: String,
+    /// Value of the label, e.g. `growth`
+    pub value: String,
+    /// Successful executions
+    pub queries: u64,
+    /// Execution time summed over all executions
+    pub total_execution_time_ms: u64,
+    /// Average execution time in milliseconds
+    pub avg_execution_time_ms: f64,
+    /// Rows returned summed over all executions
+    pub rows_returned: u64,
+    /// Largest memory increase seen for one execution
+    pub peak_memory_bytes: u64,
 }
 
-/// Configuration for the BlazeQueryEngine
-#[derive(Debug, Clone)]
+/// Distinct label values tracked before the least used is dropped
+const MAX_TRACKED_LABELS: usize = 1000;
+
+/// Configuration for the BlazeQueryEngine. See `EngineConfig::load` for
+/// reading it from a TOML file and environment variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct EngineConfig {
     /// Target batch size for optimal performance (default: 8192)
     pub batch_size: usize,
@@ -60,6 +318,82 @@ pub struct EngineConfig {
     pub cpu_cores: usize,
     /// Enable query plan optimization
     pub enable_optimization: bool,
+    /// Record a snapshot of a table whenever its contents change (default: true)
+    pub enable_snapshots: bool,
+    /// Maximum snapshots retained per table before the oldest is dropped (default: 10)
+    pub max_snapshots_per_table: usize,
+    /// Number of change events kept for change data capture; 0 disables it (default: 1024)
+    pub change_feed_retention: usize,
+    /// Maximum rows a query may return; 0 means unlimited (default: 0)
+    pub max_result_rows: usize,
+    /// Milliseconds a query may run before it is aborted with
+    /// `BlazeError::Timeout`; 0 means no limit (default: 0)
+    pub query_timeout_ms: u64,
+    /// Session time zone, an IANA name or a fixed offset (default: UTC)
+    pub time_zone: String,
+    /// Result types of decimal arithmetic and aggregates (default: derived)
+    pub decimal_rules: DecimalRules,
+    /// Handling of decimal values that overflow their type (default: error)
+    pub decimal_overflow: DecimalOverflow,
+    /// Seconds an anonymous result table is kept (default: 86400, one day)
+    pub result_table_ttl_secs: u64,
+    /// Evict least-recently-used tables when storing a table would exceed
+    /// `table_memory_percent` of the memory limit (default: false)
+    pub evict_tables: bool,
+    /// Share of the memory limit, in percent, that tables and running
+    /// queries may use before tables are evicted (default: 80)
+    pub table_memory_percent: usize,
+    /// Directory evicted tables are spilled to as Arrow IPC files and read
+    /// from afterwards; without one they are dropped (default: none)
+    pub table_spill_dir: Option<PathBuf>,
+    /// Reorder inner joins of tables analyzed with `ANALYZE TABLE` by
+    /// estimated size (default: false)
+    pub reorder_joins: bool,
+    /// Directory `execute_query_shared` writes results to (default:
+    /// `/dev/shm` where it exists, the temporary directory otherwise)
+    pub shared_result_dir: Option<PathBuf>,
+    /// Azure storage account of `az://` containers registered with
+    /// `register_azure`; `abfss://` URLs name their own (default: none)
+    pub azure_storage_account: Option<String>,
+    /// Access key of the Azure storage account (default: none)
+    pub azure_access_key: Option<String>,
+    /// Shared access signature token for Azure containers, used instead of
+    /// an access key (default: none)
+    pub azure_sas_token: Option<String>,
+    /// Directory reads of GCS, Azure and HTTP stores are cached in, so
+    /// repeated queries over the same remote files do not download them
+    /// again (default: none, no caching)
+    pub object_cache_dir: Option<PathBuf>,
+    /// Bytes the object cache keeps on disk before the least recently read
+    /// blocks are deleted (default: 10GB)
+    pub object_cache_max_bytes: u64,
+    /// Bytes of query results kept to answer repeated queries over
+    /// unchanged tables; 0 disables the result cache (default: 0)
+    pub result_cache_max_bytes: u64,
+    /// Seconds a cached result may be served (default: 300)
+    pub result_cache_ttl_secs: u64,
+    /// Optimized plans of recent queries kept so running them again skips
+    /// planning; 0 disables the plan cache (default: 256)
+    pub plan_cache_size: usize,
+    /// Queries run at once; more wait in a queue for one to finish. 0 means
+    /// no limit (default: 0)
+    pub max_concurrent_queries: usize,
+    /// Queries that may wait for admission; more are rejected with
+    /// `BlazeError::Overloaded` (default: 100)
+    pub max_queued_queries: usize,
+    /// Milliseconds a query waits for admission before it is rejected; 0
+    /// means no limit (default: 30000)
+    pub queue_timeout_ms: u64,
+    /// Slots shared by running queries, each planned with as many
+    /// partitions as slots it is granted instead of `cpu_cores`; 0 turns
+    /// slot scheduling off (default: 0)
+    pub slots: usize,
+    /// Executed queries kept for `get_query_history`; 0 turns the history
+    /// off (default: 10000)
+    pub query_history_size: usize,
+    /// File the query history is appended to and read back from when the
+    /// engine is created (default: none, kept in memory only)
+    pub query_history_path: Option<PathBuf>,
 }
 
 impl Default for EngineConfig {
@@ -69,20 +403,172 @@ impl Default for EngineConfig {
             memory_limit_bytes: 2 * 1024 * 1024 * 1024, // 2GB
             cpu_cores: num_cpus::get(),
             enable_optimization: true,
+            enable_snapshots: true,
+            max_snapshots_per_table: 10,
+            change_feed_retention: 1024,
+            max_result_rows: 0,
+            query_timeout_ms: 0,
+            time_zone: "UTC".to_string(),
+            decimal_rules: DecimalRules::Derived,
+            decimal_overflow: DecimalOverflow::Error,
+            result_table_ttl_secs: 24 * 60 * 60,
+            evict_tables: false,
+            table_memory_percent: 80,
+            table_spill_dir: None,
+            reorder_joins: false,
+            shared_result_dir: None,
+            azure_storage_account: None,
+            azure_access_key: None,
+            azure_sas_token: None,
+            object_cache_dir: None,
+            object_cache_max_bytes: 10 * 1024 * 1024 * 1024, // 10GB
+            result_cache_max_bytes: 0,
+            result_cache_ttl_secs: 300,
+            plan_cache_size: 256,
+            max_concurrent_queries: 0,
+            max_queued_queries: 100,
+            queue_timeout_ms: 30_000,
+            slots: 0,
+            query_history_size: 10_000,
+            query_history_path: None,
         }
     }
 }
 
+/// Settings that can be changed on a running engine with
+/// `BlazeQueryEngine::update_config`. Unset fields are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfigUpdate {
+    /// New memory limit in bytes; queries already holding more keep it, but
+    /// new reservations fail until usage drops below the limit
+    pub memory_limit_bytes: Option<usize>,
+    /// Number of partitions queries are split into
+    pub cpu_cores: Option<usize>,
+    /// Target batch size
+    pub batch_size: Option<usize>,
+    /// Maximum rows a query may return; 0 means unlimited
+    pub max_result_rows: Option<usize>,
+    /// Default query timeout in milliseconds; 0 means no limit
+    pub query_timeout_ms: Option<u64>,
+    /// Session time zone, e.g. `America/Chicago` or `+02:00`
+    pub time_zone: Option<String>,
+    /// Decimal result type rules, `derived` or `bigquery`
+    pub decimal_rules: Option<DecimalRules>,
+    /// Decimal overflow handling, `error`, `saturate` or `promote`
+    pub decimal_overflow: Option<DecimalOverflow>,
+    /// Whether to reorder inner joins of analyzed tables
+    pub reorder_joins: Option<bool>,
+    /// Bytes of results the result cache keeps; 0 disables it
+    pub result_cache_max_bytes: Option<u64>,
+    /// Seconds a cached result may be served
+    pub result_cache_ttl_secs: Option<u64>,
+    /// Plans the plan cache keeps; 0 disables it
+    pub plan_cache_size: Option<usize>,
+    /// Queries run at once; 0 means no limit
+    pub max_concurrent_queries: Option<usize>,
+    /// Queries that may wait for admission
+    pub max_queued_queries: Option<usize>,
+    /// Milliseconds a query waits for admission; 0 means no limit
+    pub queue_timeout_ms: Option<u64>,
+    /// Slots shared by running queries; 0 turns slot scheduling off
+    pub slots: Option<usize>,
+    /// Executed queries kept in the query history; 0 turns it off
+    pub query_history_size: Option<usize>,
+}
+
 /// High-performance query engine using DataFusion and Apache Arrow
 pub struct BlazeQueryEngine {
     /// DataFusion session context for SQL execution
     ctx: Arc<RwLock<SessionContext>>,
-    /// Engine configuration
-    config: EngineConfig,
+    /// Engine configuration, partly changeable at runtime
+    config: RwLock<EngineConfig>,
     /// Performance statistics
     stats: Arc<RwLock<EngineStats>>,
+    /// Statistics per SQL fingerprint
+    query_stats: Arc<RwLock<HashMap<String, QueryFingerprintStats>>>,
+    /// Usage per label key and value
+    label_stats: Arc<RwLock<HashMap<(String, String), LabelStats>>>,
     /// Memory pool for tracking usage
-    memory_pool: Arc<GreedyMemoryPool>,
+    memory_pool: Arc<ResizableMemoryPool>,
+    /// Table snapshots for time travel
+    snapshots: Arc<RwLock<SnapshotStore>>,
+    /// Materialized views keyed by name
+    materialized_views: Arc<RwLock<HashMap<String, MaterializedView>>>,
+    /// Change data capture log
+    change_feed: Arc<RwLock<ChangeFeed>>,
+    /// Trained ML models keyed by lowercase name
+    models: Arc<RwLock<HashMap<String, Model>>>,
+    /// Search indexes keyed by table name
+    search_indexes: Arc<RwLock<HashMap<String, SearchIndexInfo>>>,
+    /// Partitioning column keyed by table name
+    time_partitioning: Arc<RwLock<HashMap<String, String>>>,
+    /// Vector indexes keyed by index name
+    vector_indexes: Arc<RwLock<HashMap<String, VectorIndex>>>,
+    /// Workload management groups keyed by name
+    resource_groups: Arc<RwLock<HashMap<String, Arc<ResourceGroupState>>>>,
+    /// Files behind tables registered from a glob, keyed by table name
+    file_tables: Arc<RwLock<HashMap<String, FileTableInfo>>>,
+    /// Remote Flight streams registered as tables, keyed by table name
+    flight_tables: Arc<RwLock<HashMap<String, FlightTableInfo>>>,
+    /// ORC files registered as tables, keyed by table name
+    orc_tables: Arc<RwLock<HashMap<String, OrcTableInfo>>>,
+    /// Memory-mapped Arrow IPC files registered as tables, keyed by table name
+    arrow_ipc_tables: Arc<RwLock<HashMap<String, ArrowIpcTableInfo>>>,
+    /// Delta Lake tables keyed by table name
+    #[cfg(feature = "delta")]
+    delta_tables: Arc<RwLock<HashMap<String, DeltaTableInfo>>>,
+    /// Iceberg tables keyed by table name
+    #[cfg(feature = "iceberg")]
+    iceberg_tables: Arc<RwLock<HashMap<String, RegisteredIcebergTable>>>,
+    /// Remote PostgreSQL tables keyed by table name
+    #[cfg(feature = "postgres")]
+    postgres_tables: Arc<RwLock<HashMap<String, PostgresTableInfo>>>,
+    /// Cloud object stores keyed by the URL prefix they serve
+    #[cfg(feature = "object_store")]
+    object_stores: Arc<RwLock<HashMap<String, ObjectStoreInfo>>>,
+    /// Disk cache of object store reads, with `object_cache_dir` set
+    #[cfg(feature = "object_store")]
+    object_cache: Option<Arc<ObjectCache>>,
+    /// Attached DuckDB files keyed by alias
+    #[cfg(feature = "duckdb")]
+    attached_databases: Arc<RwLock<HashMap<String, AttachedDatabaseInfo>>>,
+    /// Attached SQLite files keyed by dataset
+    #[cfg(feature = "sqlite")]
+    sqlite_databases: Arc<RwLock<HashMap<String, SqliteDatabaseInfo>>>,
+    /// Anonymous tables holding cached query results
+    result_tables: Arc<RwLock<ResultTables>>,
+    /// Queries being run, for draining them on shutdown
+    queries: Arc<QueryTracker>,
+    /// Result formats keyed by name
+    serializers: Arc<RwLock<SerializerRegistry>>,
+    /// Sizes, recency and pins of in-memory tables for eviction
+    table_eviction: Arc<RwLock<TableEviction>>,
+    /// Serializes writes to each in-memory table
+    table_writers: Arc<TableWriters>,
+    /// Transactions opened with `BEGIN`, keyed by the session they were
+    /// opened in; `None` for the one opened outside any session
+    transactions: Arc<RwLock<HashMap<Option<String>, Transaction>>>,
+    /// Table statistics gathered by `ANALYZE TABLE`, read by join reordering
+    statistics: Arc<StatisticsStore>,
+    /// Source of the current time for time functions and expiry
+    clock: Arc<SharedClock>,
+    /// Results written to shared memory and not released yet
+    shared_results: Arc<RwLock<SharedResults>>,
+    /// Prepared statements keyed by handle
+    prepared: Arc<RwLock<HashMap<String, PreparedStatement>>>,
+    /// Results of recent queries over in-memory tables
+    result_cache: Arc<RwLock<ResultCache>>,
+    /// Optimized plans of recent queries
+    plan_cache: Arc<RwLock<PlanCache>>,
+    /// Open sessions keyed by ID
+    sessions: Arc<RwLock<HashMap<String, SessionInfo>>>,
+    /// Queries admitted to run and waiting to
+    admission: Arc<AdmissionControl>,
+    /// Slots running queries are planned with
+    slots: Arc<SlotPool>,
+    /// Recently executed queries
+    query_history: Arc<QueryHistory>,
 }
 
 impl BlazeQueryEngine {
@@ -93,12 +579,17 @@ impl BlazeQueryEngine {
 
     /// Create a new BlazeQueryEngine with custom configuration
     pub async fn with_config(config: EngineConfig) -> BlazeResult<Self> {
+        let memory_pool = Arc::new(ResizableMemoryPool::new(config.memory_limit_bytes));
+        Self::with_memory_pool(config, memory_pool).await
+    }
+
+    /// Create an engine drawing from a given memory pool, which may be shared
+    /// with other engines through its parent
+    pub(crate) async fn with_memory_pool(config: EngineConfig, memory_pool: Arc<ResizableMemoryPool>) -> BlazeResult<Self> {
+        config.validate()?;
         info!("Initializing BlazeQueryEngine with {} CPU cores, {}MB memory limit", 
               config.cpu_cores, config.memory_limit_bytes / 1024 / 1024);
 
-        // Create memory pool with limit
-        let memory_pool = Arc::new(GreedyMemoryPool::new(config.memory_limit_bytes));
-
         // Configure runtime for optimal performance
         let runtime_env = RuntimeEnvBuilder::new()
             .with_memory_pool(memory_pool.clone())
@@ -111,69 +602,263 @@ impl BlazeQueryEngine {
 
         // Create session context
         let ctx = SessionContext::new_with_config_rt(session_config, Arc::new(runtime_env));
+        search::register_functions(&ctx);
+        vector::register_functions(&ctx);
+        sketches::register_functions(&ctx);
+        substr::register_functions(&ctx);
+        time_series::register_functions(&ctx);
+        let clock = Arc::new(SharedClock::default());
+        time_zone::apply(&ctx, &config.time_zone, &clock);
+        decimal::register(&ctx);
+        decimal::apply(&ctx, config.decimal_rules, config.decimal_overflow);
+        let statistics = Arc::new(StatisticsStore::default());
+        join_order::register(&ctx, statistics.clone());
+        join_order::apply(&ctx, config.reorder_joins);
 
         let stats = EngineStats {
             total_queries: 0,
             avg_execution_time_ms: 0.0,
             peak_memory_bytes: 0,
             registered_tables: 0,
+            evicted_tables: 0,
+            evicted_bytes: 0,
+            #[cfg(feature = "object_store")]
+            object_cache: None,
+            result_cache: ResultCacheStats::default(),
+            plan_cache: PlanCacheStats::default(),
+            admission: AdmissionStats::default(),
+            slots: SlotStats::default(),
+        };
+        #[cfg(feature = "object_store")]
+        let object_cache = match &config.object_cache_dir {
+            Some(dir) => Some(Arc::new(ObjectCache::open(dir, config.object_cache_max_bytes)?)),
+            None => None,
         };
 
+        let admission = AdmissionControl::new(config.max_concurrent_queries, config.max_queued_queries);
+        let slots = SlotPool::new(config.slots);
+        let query_history = QueryHistory::open(config.query_history_path.as_deref(), config.query_history_size)?;
+        let snapshots = SnapshotStore::new(config.max_snapshots_per_table);
+        let change_feed = ChangeFeed::new(config.change_feed_retention);
+
         Ok(Self {
             ctx: Arc::new(RwLock::new(ctx)),
-            config,
+            config: RwLock::new(config),
             stats: Arc::new(RwLock::new(stats)),
+            query_stats: Arc::new(RwLock::new(HashMap::new())),
+            label_stats: Arc::new(RwLock::new(HashMap::new())),
             memory_pool,
+            snapshots: Arc::new(RwLock::new(snapshots)),
+            materialized_views: Arc::new(RwLock::new(HashMap::new())),
+            change_feed: Arc::new(RwLock::new(change_feed)),
+            models: Arc::new(RwLock::new(HashMap::new())),
+            search_indexes: Arc::new(RwLock::new(HashMap::new())),
+            time_partitioning: Arc::new(RwLock::new(HashMap::new())),
+            vector_indexes: Arc::new(RwLock::new(HashMap::new())),
+            resource_groups: Arc::new(RwLock::new(HashMap::new())),
+            file_tables: Arc::new(RwLock::new(HashMap::new())),
+            flight_tables: Arc::new(RwLock::new(HashMap::new())),
+            orc_tables: Arc::new(RwLock::new(HashMap::new())),
+            arrow_ipc_tables: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "delta")]
+            delta_tables: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "iceberg")]
+            iceberg_tables: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "postgres")]
+            postgres_tables: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "object_store")]
+            object_stores: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "object_store")]
+            object_cache,
+            #[cfg(feature = "duckdb")]
+            attached_databases: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(feature = "sqlite")]
+            sqlite_databases: Arc::new(RwLock::new(HashMap::new())),
+            result_tables: Arc::new(RwLock::new(ResultTables::default())),
+            queries: Arc::new(QueryTracker::default()),
+            serializers: Arc::new(RwLock::new(SerializerRegistry::default())),
+            table_eviction: Arc::new(RwLock::new(TableEviction::default())),
+            table_writers: Arc::new(TableWriters::default()),
+            transactions: Arc::new(RwLock::new(HashMap::new())),
+            statistics,
+            clock,
+            shared_results: Arc::new(RwLock::new(SharedResults::default())),
+            prepared: Arc::new(RwLock::new(HashMap::new())),
+            result_cache: Arc::new(RwLock::new(ResultCache::default())),
+            plan_cache: Arc::new(RwLock::new(PlanCache::default())),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            admission: Arc::new(admission),
+            slots: Arc::new(slots),
+            query_history: Arc::new(query_history),
         })
     }
 
     /// Execute a SQL query and return results with performance metrics
-    #[instrument(skip(self, sql), fields(sql_hash = %self.hash_sql(sql)))]
+    #[instrument(skip(self, sql), fields(fingerprint = %fingerprint_sql(sql)))]
     pub async fn execute_query(&self, sql: &str) -> BlazeResult<QueryResult> {
+        self.execute_query_with_options(sql, &QueryOptions::default()).await
+    }
+
+    /// Execute a SQL query with per-query options such as its resource group
+    pub async fn execute_query_with_options(&self, sql: &str, options: &QueryOptions) -> BlazeResult<QueryResult> {
+        self.execute_tracked(sql, None, options).await
+    }
+
+    /// Run `sql`, or the prepared statement `bound` planned from it, as a
+    /// tracked query
+    async fn execute_tracked(
+        &self,
+        sql: &str,
+        bound: Option<BoundStatement>,
+        options: &QueryOptions,
+    ) -> BlazeResult<QueryResult> {
+        let session = self.enter_session(options).await?;
+        let guard = self.queries.begin(sql, options.query_id.as_deref())?;
+        let (timeout_ms, queue_timeout_ms) = {
+            let config = self.config.read().await;
+            let timeout_ms = options.timeout_ms.or(session.as_ref().and_then(|s| s.settings.query_timeout_ms));
+            (timeout_ms.unwrap_or(config.query_timeout_ms), config.queue_timeout_ms)
+        };
+        let started_at = self.clock.now();
+        let start_time = Instant::now();
+        // Time queued does not count against the query's timeout
+        let result = match guard.cancellable(self.admission.admit(options.priority, queue_timeout_ms)).await {
+            Ok(_admitted) => {
+                let query = guard.cancellable(Box::pin(self.run_query(sql, bound, options, session.as_ref(), guard.query_id())));
+                with_timeout(timeout_ms, query).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(BlazeError::Timeout { timeout_ms }) = &result {
+            warn!("Aborted query {} after {}ms: {}", guard.query_id(), timeout_ms, sql);
+        }
+        if result.is_err() {
+            self.abort_transaction(sql, session.as_ref().map(|s| s.session_id.as_str())).await;
+        }
+        self.query_history
+            .record(QueryHistoryEntry::new(guard.query_id(), sql, options, started_at, start_time.elapsed(), &result));
+        result
+    }
+
+    async fn run_query(
+        &self,
+        sql: &str,
+        bound: Option<BoundStatement>,
+        options: &QueryOptions,
+        session: Option<&SessionInfo>,
+        query_id: &str,
+    ) -> BlazeResult<QueryResult> {
+        let group = match options.resource_group() {
+            Some(name) => Some(self.resource_group(name).await?),
+            None => None,
+        };
+        let slot = match &group {
+            Some(group) => Some(group.admit().await?),
+            None => None,
+        };
+
+        self.expire_result_tables().await;
+        if self.config.read().await.evict_tables {
+            self.touch_tables(sql).await;
+        }
+
+        let profiler = if options.profile { Some(QueryProfiler::start().await?) } else { None };
         let start_time = Instant::now();
         let start_memory = self.memory_pool.reserved();
 
         debug!("Executing query: {}", sql);
 
-        let ctx = self.ctx.read().await;
-        
-        // Parse and plan the query
-        let logical_plan = ctx.sql(sql).await?;
-        
-        // Get query plan for debugging (optional)
-        let query_plan = if log::log_enabled!(log::Level::Debug) {
-            Some(format!("{}", logical_plan.logical_plan().display_indent_schema()))
+        let time_zone = match session.and_then(|s| s.settings.time_zone.clone()) {
+            Some(zone) => zone,
+            None => self.config.read().await.time_zone.clone(),
+        };
+        let cache_key = match bound {
+            Some(_) => None,
+            None => self.result_cache_key(sql, &time_zone).await,
+        };
+        let cached = match &cache_key {
+            Some((key, versions)) => self.result_cache.write().await.get(key, versions, self.clock.now()),
+            None => None,
+        };
+        let cache_hit = cached.is_some();
+        let outcome = match (cached, bound) {
+            (Some(batches), _) => Ok((batches, None)),
+            (None, Some(bound)) => self.execute_bound(bound, session, group.as_deref()).await,
+            (None, None) => self.execute_statement(sql, session, group.as_deref()).await,
+        };
+        let outcome = match outcome {
+            Ok((batches, plan)) => self.check_result_size(&batches, session).await.map(|_| (batches, plan)),
+            Err(e) => Err(e),
+        };
+        let profile = match profiler {
+            Some(profiler) if outcome.is_ok() => {
+                let dir = options.profile_dir.clone().unwrap_or_else(profiling::default_profile_dir);
+                let name = format!("query-{}-{}", Utc::now().format("%Y%m%dT%H%M%S%3f"), fingerprint_sql(sql));
+                let profile = profiler.finish(&dir, &name)?;
+                info!("Wrote CPU profile of {} samples to {}", profile.samples, profile.pprof_path.display());
+                Some(profile)
+            }
+            _ => None,
+        };
+        drop(slot);
+        if let Some(group) = &group {
+            let memory_used = self.memory_pool.reserved().saturating_sub(start_memory);
+            group.record(outcome.is_ok(), start_time.elapsed().as_millis() as u64, memory_used as u64);
+        }
+        let (record_batches, query_plan) = outcome?;
+        if let (Some((key, versions)), false) = (cache_key, cache_hit) {
+            self.cache_result(key, versions, &record_batches).await;
+        }
+
+        // Queries without any batches have no schema to keep
+        let keep = options.cache_result || options.max_rows.is_some();
+        let kept = if keep && plan_graph::is_query(sql) && !record_batches.is_empty() {
+            Some(self.store_result_table(record_batches.clone(), query_id).await?)
         } else {
             None
         };
-
-        // Execute the query
-        let df = logical_plan;
-        let record_batches = df.collect().await?;
+        let total_rows: usize = record_batches.iter().map(|batch| batch.num_rows()).sum();
+        let page_rows = options.max_rows.filter(|_| kept.is_some()).unwrap_or(total_rows);
+        let page_token = kept.as_ref().and_then(|table| result_tables::page_token(table, page_rows));
 
         // Convert results to JSON-serializable format
         let mut data = Vec::new();
-        let mut total_rows = 0;
 
-        for batch in &record_batches {
-            total_rows += batch.num_rows();
-            let batch_data = self.record_batch_to_json(batch)?;
+        for batch in &result_tables::slice_rows(&record_batches, 0, page_rows) {
+            let batch_data = serializers::json_rows(batch, &time_zone)?;
             data.extend(batch_data);
         }
 
+        let checksum = match options.checksum {
+            Some(mode) => Some(checksums::checksum(&record_batches, &time_zone, mode)?),
+            None => None,
+        };
+        let (rows_written, bytes_written) = written_totals(sql, &record_batches).unzip();
+
         let execution_time = start_time.elapsed();
         let memory_used = self.memory_pool.reserved().saturating_sub(start_memory);
 
         // Update statistics
-        self.update_stats(execution_time.as_millis() as u64, memory_used as u64).await;
+        self.update_stats(sql, execution_time.as_millis() as u64, memory_used as u64).await;
+        self.update_label_stats(&options.labels, execution_time.as_millis() as u64, total_rows as u64, memory_used as u64)
+            .await;
 
         let result = QueryResult {
             rows: total_rows,
             execution_time_ms: execution_time.as_millis() as u64,
             memory_used_bytes: memory_used as u64,
+            result_bytes: table_eviction::batches_bytes(&record_batches) as u64,
             data,
             query_plan,
             engine: "blaze".to_string(),
+            profile,
+            result_table: kept.map(|table| table.reference),
+            page_token,
+            checksum,
+            rows_written,
+            bytes_written,
+            cache_hit,
+            query_id: query_id.to_string(),
         };
 
         info!("Query completed in {}ms, {} rows, {}MB memory", 
@@ -184,126 +869,3695 @@ impl BlazeQueryEngine {
         Ok(result)
     }
 
+    /// Execute a SQL query and return its result as Arrow RecordBatches,
+    /// preserving column order and types
+    pub async fn execute_query_batches(&self, sql: &str) -> BlazeResult<Vec<RecordBatch>> {
+        let timeout_ms = self.config.read().await.query_timeout_ms;
+        let query = self.queries
+            .run(sql, Box::pin(async {
+                let start_time = Instant::now();
+                let start_memory = self.memory_pool.reserved();
+
+                let (record_batches, _) = self.execute_statement(sql, None, None).await?;
+                self.check_result_size(&record_batches, None).await?;
+
+                let memory_used = self.memory_pool.reserved().saturating_sub(start_memory);
+                self.update_stats(sql, start_time.elapsed().as_millis() as u64, memory_used as u64).await;
+                Ok(record_batches)
+            }));
+        with_timeout(timeout_ms, query).await
+    }
+
+    /// Execute a SQL query and write its result in a registered format such
+    /// as `csv` or `arrow`; see `serializers` for the built-in formats
+    pub async fn execute_query_as(&self, sql: &str, format: &str) -> BlazeResult<SerializedResult> {
+        // Unknown formats fail before the query runs
+        let serializer = self.serializers.read().await.get(format)?;
+        let batches = self.execute_query_batches(sql).await?;
+        self.serialize_with(serializer, &batches).await
+    }
+
+    /// Write batches in a registered format
+    pub async fn serialize_batches(&self, batches: &[RecordBatch], format: &str) -> BlazeResult<SerializedResult> {
+        let serializer = self.serializers.read().await.get(format)?;
+        self.serialize_with(serializer, batches).await
+    }
+
+    async fn serialize_with(&self, serializer: Arc<dyn ResultSerializer>, batches: &[RecordBatch]) -> BlazeResult<SerializedResult> {
+        let context = SerializeContext { time_zone: self.config.read().await.time_zone.clone() };
+        Ok(SerializedResult {
+            format: serializer.name().to_string(),
+            content_type: serializer.content_type().to_string(),
+            rows: batches.iter().map(|batch| batch.num_rows()).sum(),
+            data: serializer.serialize(batches, &context)?,
+        })
+    }
+
+    /// Add a result format, replacing any registered under the same name
+    pub async fn register_serializer(&self, serializer: Arc<dyn ResultSerializer>) {
+        info!("Registered result format '{}'", serializer.name());
+        self.serializers.write().await.register(serializer);
+    }
+
+    /// Names of the registered result formats
+    pub async fn list_serializers(&self) -> Vec<String> {
+        self.serializers.read().await.names()
+    }
+
+    /// Execute a SQL query and stream its result batch by batch as the
+    /// consumer polls. Statements that are not plain queries run to
+    /// completion first. Streams are not counted in the engine statistics
+    /// and are not subject to `max_result_rows`.
+    pub async fn execute_stream(&self, sql: &str) -> BlazeResult<SendableRecordBatchStream> {
+        self.execute_stream_with_options(sql, &QueryOptions::default()).await
+    }
+
+    /// Stream a query's result like `execute_stream`, under
+    /// `QueryOptions::query_id` and within `QueryOptions::timeout_ms`: a
+    /// stream still open when it runs out ends with `BlazeError::Timeout`.
+    /// Since the consumer sets a stream's pace, `query_timeout_ms` does not
+    /// apply to streams. The other options are ignored.
+    pub async fn execute_stream_with_options(&self, sql: &str, options: &QueryOptions) -> BlazeResult<SendableRecordBatchStream> {
+        debug!("Streaming query: {}", sql);
+
+        let guard = self.queries.begin(sql, options.query_id.as_deref())?;
+        let timeout_ms = options.timeout_ms.unwrap_or(0);
+        let deadline = (timeout_ms > 0)
+            .then(|| (tokio::time::Instant::now() + std::time::Duration::from_millis(timeout_ms), timeout_ms));
+        let planning = guard.cancellable(Box::pin(async {
+            if let Some(batches) = self.execute_extension_statement(sql, None).await? {
+                return batch_stream(batches);
+            }
+            let ctx = self.ctx.read().await;
+            let df = self.plan_sql(&ctx, sql).await?;
+            if insert_target(df.logical_plan()).is_some() || dml::mutation_target(df.logical_plan()).is_some() {
+                drop(ctx);
+                return batch_stream(self.execute_sql(sql, None, None).await?.0);
+            }
+            Ok(df.execute_stream().await?)
+        }));
+        let stream = with_timeout(timeout_ms, planning).await;
+        if stream.is_err() {
+            self.abort_transaction(sql, None).await;
+        }
+        Ok(self.queries.track_stream(guard, stream?, deadline))
+    }
+
+    /// Execute a SQL query and write its result to an Arrow IPC file in
+    /// shared memory, which other processes can map and read zero-copy. The
+    /// result is streamed to the file rather than collected first. Like
+    /// streams, it is not counted in the engine statistics. See
+    /// `shared_results`.
+    pub async fn execute_query_shared(&self, sql: &str) -> BlazeResult<SharedResultInfo> {
+        let dir = self.config.read().await.shared_result_dir.clone().unwrap_or_else(shared_results::default_dir);
+        let mut stream = self.execute_stream(sql).await?;
+        let mut writer = SharedResultWriter::create(&dir, &stream.schema())?;
+        while let Some(batch) = stream.next().await {
+            writer.write(&batch?)?;
+        }
+        let shared = writer.finish(self.clock.now())?;
+        info!("Wrote {} rows ({}) to shared result '{}'", shared.rows, format_bytes(shared.bytes), shared.path);
+        self.shared_results.write().await.insert(shared.clone());
+        Ok(shared)
+    }
+
+    /// Remove a result written by `execute_query_shared`. Processes that
+    /// have mapped it keep their mapping.
+    pub async fn release_shared_result(&self, name: &str) -> BlazeResult<()> {
+        let released = self.shared_results.write().await.release(name)?;
+        info!("Released shared result '{}'", released.name);
+        Ok(())
+    }
+
+    /// Results written by `execute_query_shared` and not released, oldest
+    /// first
+    pub async fn list_shared_results(&self) -> Vec<SharedResultInfo> {
+        self.shared_results.read().await.list()
+    }
+
     /// Register a table from Arrow RecordBatches
     pub async fn register_table(&self, name: &str, batches: Vec<RecordBatch>) -> BlazeResult<()> {
+        self.register_table_in_session(name, batches, None).await
+    }
+
+    /// Register a table as a statement run in session `session_id`, inside
+    /// that session's open transaction if any
+    async fn register_table_in_session(
+        &self,
+        name: &str,
+        batches: Vec<RecordBatch>,
+        session_id: Option<&str>,
+    ) -> BlazeResult<()> {
         if batches.is_empty() {
             return Err(BlazeError::InvalidInput("Cannot register empty table".to_string()));
         }
 
         let schema = batches[0].schema();
         let total_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
-        let table = MemTable::try_new(schema, vec![batches])?;
-        
-        let ctx = self.ctx.write().await;
-        ctx.register_table(name, Arc::new(table))?;
 
-        // Update stats
-        let mut stats = self.stats.write().await;
-        stats.registered_tables += 1;
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        // Re-registering an existing name replaces its contents
+        let committed_at = self.clock.now();
+        let replaced = {
+            let ctx = self.ctx.write().await;
+            let previous = self.batches_for_change_feed(&ctx, name).await?;
+            let replaced = self.store_table(&ctx, name, schema, batches.clone(), committed_at, session_id).await?;
+            self.publish_changes(name, committed_at, previous, Some(batches), session_id).await;
+            replaced
+        };
+
+        if replaced {
+            self.maintain_materialized_views(name, None).await;
+        } else {
+            // Update stats
+            let mut stats = self.stats.write().await;
+            stats.registered_tables += 1;
+        }
 
         info!("Registered table '{}' with {} rows", name, total_rows);
 
         Ok(())
     }
 
-    /// Get current engine statistics
-    pub async fn get_stats(&self) -> EngineStats {
-        self.stats.read().await.clone()
+    /// Register the files matching a glob such as `data/2024-*/part-*.parquet`
+    /// as one table, scanned in place. The files must agree on column names
+    /// and types. The format is taken from the pattern's extension unless
+    /// given.
+    pub async fn register_files(&self, name: &str, pattern: &str, format: Option<DataFormat>) -> BlazeResult<FileTableInfo> {
+        self.register_file_table(name, pattern, format, None, None, FileListing::Glob).await
     }
 
-    /// Get available tables
-    pub async fn list_tables(&self) -> BlazeResult<Vec<String>> {
+    /// Register the Parquet or JSON files matching a glob as one table with
+    /// the union of their columns; files lacking a column read it as NULL.
+    /// The format is taken from the pattern's extension.
+    pub async fn register_table_from_glob(&self, name: &str, pattern: &str) -> BlazeResult<FileTableInfo> {
+        self.register_file_table(name, pattern, None, None, None, FileListing::MergedGlob).await
+    }
+
+    /// Register a directory partitioned Hive-style into `column=value`
+    /// subdirectories as one table, scanned in place. The directory levels
+    /// become columns, and filters on them skip the partitions they rule
+    /// out. The format is taken from the files' extension unless given.
+    pub async fn register_partitioned(&self, name: &str, path: &str, format: Option<DataFormat>) -> BlazeResult<FileTableInfo> {
+        self.register_file_table(name, path, format, None, None, FileListing::Partitioned).await
+    }
+
+    /// Register a CSV file, or the CSV files matching a glob, as a table
+    /// scanned in place and read with `options`. Column types are inferred
+    /// from the first `infer_rows` rows of each file unless
+    /// `options.schema` gives them.
+    pub async fn register_csv(&self, name: &str, path: &str, options: &CsvTableOptions) -> BlazeResult<FileTableInfo> {
+        self.register_file_table(name, path, Some(DataFormat::Csv), Some(options), None, FileListing::Glob).await
+    }
+
+    /// Register a newline-delimited JSON file, or the files matching a
+    /// glob, as a table scanned in place. The schema is inferred from the
+    /// first `infer_rows` rows across the files; fields missing from a row
+    /// read as NULL.
+    pub async fn register_json(&self, name: &str, path: &str, options: &JsonTableOptions) -> BlazeResult<FileTableInfo> {
+        self.register_file_table(name, path, Some(DataFormat::Json), None, Some(options), FileListing::Glob).await
+    }
+
+    async fn register_file_table(
+        &self,
+        name: &str,
+        pattern: &str,
+        format: Option<DataFormat>,
+        csv_options: Option<&CsvTableOptions>,
+        json_options: Option<&JsonTableOptions>,
+        listing: FileListing,
+    ) -> BlazeResult<FileTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let (table, info) = {
+            let ctx = self.ctx.read().await;
+            file_tables::build_table(&ctx, name, pattern, format, csv_options, json_options, listing).await?
+        };
+        self.register_provider(name, table, info.clone(), &self.file_tables).await?;
+
+        info!(
+            "Registered table '{}' from {} files ({}) matching '{}'",
+            name, info.file_count, format_bytes(info.total_bytes), pattern
+        );
+        Ok(info)
+    }
+
+    /// Register a stream of a remote Arrow Flight service as a table. The
+    /// schema is read once now; the rows are fetched from the service again
+    /// on every scan, one partition per endpoint of the flight.
+    pub async fn register_flight(&self, name: &str, endpoint: &str, source: FlightSource) -> BlazeResult<FlightTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let (table, info) = flight_tables::build_table(name, endpoint, source).await?;
+        self.register_provider(name, table, info.clone(), &self.flight_tables).await?;
+
+        info!("Registered table '{}' from Flight service {} with {} columns", name, endpoint, info.columns.len());
+        Ok(info)
+    }
+
+    /// Register a table of a remote PostgreSQL server, `schema.table` or a
+    /// table of `public`, as a table. The columns are read once now; the
+    /// rows are queried again on every scan, with the projection, simple
+    /// filters and LIMIT pushed to the server. See `postgres_tables`.
+    #[cfg(feature = "postgres")]
+    pub async fn register_postgres(&self, name: &str, connection: &str, remote_table: &str) -> BlazeResult<PostgresTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let (table, info) = postgres_tables::build_table(name, connection, remote_table).await?;
+        self.register_provider(name, table, info.clone(), &self.postgres_tables).await?;
+
+        info!("Registered table '{}' from PostgreSQL table {} with {} columns", name, info.remote_table, info.columns.len());
+        Ok(info)
+    }
+
+    /// Register an ORC file, or the `.orc` files in a directory, as a table
+    /// read in place. Scans read only the columns a query uses and skip
+    /// stripes whose statistics rule out its filters on numeric and date
+    /// columns. The files must agree on column names and types.
+    pub async fn register_orc(&self, name: &str, path: impl AsRef<Path>) -> BlazeResult<OrcTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let path = path.as_ref().to_path_buf();
+        let table_name = name.to_string();
+        let (table, info) = tokio::task::spawn_blocking(move || orc_tables::build_table(&table_name, &path))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("ORC reader failed: {}", e)))??;
+        self.register_provider(name, table, info.clone(), &self.orc_tables).await?;
+
+        info!(
+            "Registered table '{}' from {} ORC files ({}, {} rows in {} stripes)",
+            name, info.files.len(), format_bytes(info.total_bytes), info.rows, info.stripes
+        );
+        Ok(info)
+    }
+
+    /// Register an Arrow IPC (Feather V2) file, or the `.arrow`, `.feather`
+    /// and `.ipc` files in a directory, as a table by memory-mapping them.
+    /// The batches are read from the mapping without being copied; the files
+    /// must not change while registered. See `arrow_ipc_tables`.
+    pub async fn register_arrow_ipc(&self, name: &str, path: impl AsRef<Path>) -> BlazeResult<ArrowIpcTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let path = path.as_ref().to_path_buf();
+        let table_name = name.to_string();
+        let (table, info) = tokio::task::spawn_blocking(move || arrow_ipc_tables::map_table(&table_name, &path))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Arrow IPC reader failed: {}", e)))??;
+        self.register_provider(name, table, info.clone(), &self.arrow_ipc_tables).await?;
+
+        info!(
+            "Registered table '{}' by mapping {} Arrow IPC files ({}, {} rows)",
+            name, info.files.len(), format_bytes(info.mapped_bytes), info.rows
+        );
+        Ok(info)
+    }
+
+    /// Register the snapshot of a Delta Lake table at `path` as of
+    /// `version`, or its latest commit, as a table. Later commits are read
+    /// by registering it again. See `delta_tables`.
+    #[cfg(feature = "delta")]
+    pub async fn register_delta(&self, name: &str, path: &str, version: Option<i64>) -> BlazeResult<DeltaTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let (table, info) = delta_tables::open_table(name, path, version).await?;
+        self.register_provider(name, table, info.clone(), &self.delta_tables).await?;
+
+        info!(
+            "Registered table '{}' from Delta table '{}' at version {} ({} files)",
+            name, path, info.version, info.files
+        );
+        Ok(info)
+    }
+
+    /// Tables registered from Delta Lake that are still in the catalog
+    #[cfg(feature = "delta")]
+    pub async fn list_delta_tables(&self) -> Vec<DeltaTableInfo> {
         let ctx = self.ctx.read().await;
-        let catalog = ctx.catalog("datafusion").ok_or_else(|| {
-            BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Catalog not found".to_string()))
+        let mut tables: Vec<_> = self
+            .delta_tables
+            .read()
+            .await
+            .values()
+            .filter(|info| ctx.table_exist(info.table_name.as_str()).unwrap_or(false))
+            .cloned()
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Register an Iceberg table from its metadata file, reading the
+    /// snapshot `version` selects or the current one. Queries read other
+    /// snapshots with `FOR SYSTEM_TIME AS OF`; later commits are read by
+    /// registering the table again. See `iceberg_tables`.
+    #[cfg(feature = "iceberg")]
+    pub async fn register_iceberg(
+        &self,
+        name: &str,
+        metadata_path: &str,
+        version: Option<IcebergVersion>,
+    ) -> BlazeResult<IcebergTableInfo> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        let (table, registered) = iceberg_tables::open_table(name, metadata_path, version).await?;
+        let info = registered.info.clone();
+        self.register_provider(name, table, registered, &self.iceberg_tables).await?;
+
+        match info.snapshot_id {
+            Some(snapshot_id) => info!(
+                "Registered table '{}' from Iceberg table '{}' at snapshot {} ({} snapshots)",
+                name, metadata_path, snapshot_id, info.snapshots.len()
+            ),
+            None => info!("Registered table '{}' from Iceberg table '{}' with no snapshots", name, metadata_path),
+        }
+        Ok(info)
+    }
+
+    /// Tables registered from Iceberg that are still in the catalog
+    #[cfg(feature = "iceberg")]
+    pub async fn list_iceberg_tables(&self) -> Vec<IcebergTableInfo> {
+        let ctx = self.ctx.read().await;
+        let mut tables: Vec<_> = self
+            .iceberg_tables
+            .read()
+            .await
+            .values()
+            .filter(|registered| ctx.table_exist(registered.info.table_name.as_str()).unwrap_or(false))
+            .map(|registered| registered.info.clone())
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Register `table` as `name` in place of any table of that name, and
+    /// record `info` about it in `tables`, the registry of its kind. A new
+    /// name counts as a registered table; a replaced one refreshes the
+    /// materialized views over it.
+    async fn register_provider<T: Send + Sync>(
+        &self,
+        name: &str,
+        table: Arc<dyn TableProvider>,
+        info: T,
+        tables: &RwLock<HashMap<String, T>>,
+    ) -> BlazeResult<()> {
+        let replaced = {
+            let ctx = self.ctx.write().await;
+            let replaced = replace_table(&ctx, name, table)?;
+            self.forget_external_table(name).await;
+            tables.write().await.insert(name.to_string(), info);
+            replaced
+        };
+
+        if replaced {
+            self.maintain_materialized_views(name, None).await;
+        } else {
+            let mut stats = self.stats.write().await;
+            stats.registered_tables += 1;
+        }
+        Ok(())
+    }
+
+    /// Drop what is recorded about `name` as an external table, before it
+    /// is registered as another or dropped
+    async fn forget_external_table(&self, name: &str) {
+        self.file_tables.write().await.remove(name);
+        self.flight_tables.write().await.remove(name);
+        self.orc_tables.write().await.remove(name);
+        self.arrow_ipc_tables.write().await.remove(name);
+        #[cfg(feature = "delta")]
+        self.delta_tables.write().await.remove(name);
+        #[cfg(feature = "iceberg")]
+        self.iceberg_tables.write().await.remove(name);
+        #[cfg(feature = "postgres")]
+        self.postgres_tables.write().await.remove(name);
+    }
+
+    #[cfg(feature = "delta")]
+    async fn is_delta_table(&self, name: &str) -> bool {
+        self.delta_tables.read().await.contains_key(name)
+    }
+
+    #[cfg(not(feature = "delta"))]
+    async fn is_delta_table(&self, _name: &str) -> bool {
+        false
+    }
+
+    #[cfg(feature = "iceberg")]
+    async fn is_iceberg_table(&self, name: &str) -> bool {
+        self.iceberg_tables.read().await.contains_key(name)
+    }
+
+    #[cfg(not(feature = "iceberg"))]
+    async fn is_iceberg_table(&self, _name: &str) -> bool {
+        false
+    }
+
+    #[cfg(feature = "postgres")]
+    async fn is_postgres_table(&self, name: &str) -> bool {
+        self.postgres_tables.read().await.contains_key(name)
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    async fn is_postgres_table(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Let tables and statements read and write `gs://bucket/...` URLs,
+    /// authenticating with `credentials`. Registering a bucket again
+    /// replaces its credentials. See `object_stores`.
+    #[cfg(feature = "object_store")]
+    pub async fn register_gcs(&self, bucket: &str, credentials: GcsCredentials) -> BlazeResult<ObjectStoreInfo> {
+        let (url, store, credentials) = object_stores::gcs_store(bucket, &credentials)?;
+        self.register_object_store(url, store, credentials).await
+    }
+
+    /// Let tables and statements read and write an Azure Blob Storage or
+    /// ADLS Gen2 container, given as `az://container` or
+    /// `abfss://container@account.dfs.core.windows.net`, authenticating with
+    /// the access key or SAS token of `EngineConfig`. Without either, the
+    /// `AZURE_*` environment variables or a managed identity are used.
+    #[cfg(feature = "object_store")]
+    pub async fn register_azure(&self, url: &str) -> BlazeResult<ObjectStoreInfo> {
+        let credentials = {
+            let config = self.config.read().await;
+            AzureCredentials {
+                account: config.azure_storage_account.clone(),
+                access_key: config.azure_access_key.clone(),
+                sas_token: config.azure_sas_token.clone(),
+            }
+        };
+        let (url, store, credentials) = object_stores::azure_store(url, &credentials)?;
+        self.register_object_store(url, store, credentials).await
+    }
+
+    /// Register a Parquet, CSV or JSON file published at an `http://` or
+    /// `https://` URL as a table read in place, sending `headers`, e.g. an
+    /// `Authorization` header, with every request to its origin. Parquet
+    /// files are read with range requests. The format is taken from the
+    /// URL's extension unless given.
+    #[cfg(feature = "object_store")]
+    pub async fn register_http(
+        &self,
+        name: &str,
+        url: &str,
+        format: Option<DataFormat>,
+        headers: &[(String, String)],
+    ) -> BlazeResult<FileTableInfo> {
+        let (store_url, store, credentials) = object_stores::http_store(url, headers)?;
+        self.register_object_store(store_url, store, credentials).await?;
+        self.register_file_table(name, url, format, None, None, FileListing::Glob).await
+    }
+
+    #[cfg(feature = "object_store")]
+    async fn register_object_store(
+        &self,
+        url: ObjectStoreUrl,
+        store: Arc<dyn ObjectStore>,
+        credentials: String,
+    ) -> BlazeResult<ObjectStoreInfo> {
+        let info = ObjectStoreInfo {
+            url: url.as_str().trim_end_matches('/').to_string(),
+            credentials,
+            registered_at: self.clock.now(),
+        };
+        let store: Arc<dyn ObjectStore> = match &self.object_cache {
+            Some(cache) => Arc::new(CachedStore::new(store, cache.clone(), &info.url)),
+            None => store,
+        };
+        self.ctx.read().await.register_object_store(url.as_ref(), store);
+        self.object_stores.write().await.insert(info.url.clone(), info.clone());
+        info!("Registered object store {} with {}", info.url, info.credentials);
+        Ok(info)
+    }
+
+    /// Object stores registered with `register_gcs`, `register_azure` or
+    /// `register_http`, sorted by URL
+    #[cfg(feature = "object_store")]
+    pub async fn list_object_stores(&self) -> Vec<ObjectStoreInfo> {
+        let mut stores: Vec<_> = self.object_stores.read().await.values().cloned().collect();
+        stores.sort_by(|a, b| a.url.cmp(&b.url));
+        stores
+    }
+
+    /// Attach a DuckDB database file read-only under `alias`. Tables of its
+    /// `main` schema become `alias.table`, those of any schema
+    /// `alias.schema.table`; they are read from the file on every query.
+    #[cfg(feature = "duckdb")]
+    pub async fn attach_duckdb(&self, path: impl AsRef<Path>, alias: &str) -> BlazeResult<AttachedDatabaseInfo> {
+        let path = path.as_ref().to_path_buf();
+        let name = alias.to_string();
+        let database = tokio::task::spawn_blocking(move || AttachedDatabase::open(&path, &name))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Opening DuckDB database failed: {}", e)))??;
+
+        let ctx = self.ctx.write().await;
+        let mut attached = self.attached_databases.write().await;
+        if attached.contains_key(alias) {
+            return Err(BlazeError::InvalidInput(format!("A database is already attached as '{}'; DETACH it first", alias)));
+        }
+        let default_catalog = ctx.state().config_options().catalog.default_catalog.clone();
+        let catalog = ctx.catalog(&default_catalog).ok_or_else(|| {
+            BlazeError::Internal(format!("Default catalog '{}' not found", default_catalog))
         })?;
-        let schema = catalog.schema("public").ok_or_else(|| {
-            BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Schema not found".to_string()))
+        // A detached database leaves an empty catalog behind, which is reused
+        let catalog_in_use = ctx.catalog(alias).is_some_and(|c| !c.schema_names().is_empty());
+        if alias == default_catalog || catalog.schema(alias).is_some() || catalog_in_use {
+            return Err(BlazeError::InvalidInput(format!("'{}' is already a catalog or schema name", alias)));
+        }
+
+        ctx.register_catalog(alias, database.catalog()?);
+        catalog.register_schema(alias, database.main_schema()?)?;
+        attached.insert(alias.to_string(), database.info.clone());
+
+        info!(
+            "Attached DuckDB database '{}' as '{}' with {} tables",
+            database.info.path, alias, database.info.tables.len()
+        );
+        Ok(database.info)
+    }
+
+    /// Detach a database attached with `attach_duckdb`, closing its file
+    #[cfg(feature = "duckdb")]
+    pub async fn detach_database(&self, alias: &str) -> BlazeResult<()> {
+        let ctx = self.ctx.write().await;
+        if self.attached_databases.write().await.remove(alias).is_none() {
+            return Err(BlazeError::InvalidInput(format!("No database is attached as '{}'", alias)));
+        }
+        // Catalogs cannot be removed, so the attached one is emptied instead
+        if let Some(catalog) = ctx.catalog(alias) {
+            for schema in catalog.schema_names() {
+                catalog.deregister_schema(&schema, true)?;
+            }
+        }
+        let default_catalog = ctx.state().config_options().catalog.default_catalog.clone();
+        if let Some(catalog) = ctx.catalog(&default_catalog) {
+            catalog.deregister_schema(alias, true)?;
+        }
+        info!("Detached database '{}'", alias);
+        Ok(())
+    }
+
+    /// Databases attached with `attach_duckdb` or `ATTACH`, sorted by alias
+    #[cfg(feature = "duckdb")]
+    pub async fn list_attached_databases(&self) -> Vec<AttachedDatabaseInfo> {
+        let mut databases: Vec<_> = self.attached_databases.read().await.values().cloned().collect();
+        databases.sort_by(|a, b| a.alias.cmp(&b.alias));
+        databases
+    }
+
+    /// Attach a SQLite database file read-only as `dataset`, so its tables
+    /// and views become `dataset.table`. They are read from the file on
+    /// every query. See `sqlite_attach` for how column types are chosen.
+    #[cfg(feature = "sqlite")]
+    pub async fn attach_sqlite(&self, path: impl AsRef<Path>, dataset: &str) -> BlazeResult<SqliteDatabaseInfo> {
+        let path = path.as_ref().to_path_buf();
+        let name = dataset.to_string();
+        let database = tokio::task::spawn_blocking(move || SqliteDatabase::open(&path, &name))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Opening SQLite database failed: {}", e)))??;
+
+        let ctx = self.ctx.write().await;
+        let mut attached = self.sqlite_databases.write().await;
+        if attached.contains_key(dataset) {
+            return Err(BlazeError::InvalidInput(format!(
+                "A SQLite database is already attached as '{}'; detach it first",
+                dataset
+            )));
+        }
+        let default_catalog = ctx.state().config_options().catalog.default_catalog.clone();
+        let catalog = ctx.catalog(&default_catalog).ok_or_else(|| {
+            BlazeError::Internal(format!("Default catalog '{}' not found", default_catalog))
         })?;
-        let tables = schema.table_names();
-        Ok(tables)
+        if catalog.schema(dataset).is_some() {
+            return Err(BlazeError::InvalidInput(format!("'{}' is already a schema name", dataset)));
+        }
+
+        catalog.register_schema(dataset, database.schema()?)?;
+        attached.insert(dataset.to_string(), database.info.clone());
+
+        info!(
+            "Attached SQLite database '{}' as '{}' with {} tables",
+            database.info.path, dataset, database.info.tables.len()
+        );
+        Ok(database.info)
     }
 
-    /// Validate SQL query syntax without execution
-    pub async fn validate_query(&self, sql: &str) -> BlazeResult<bool> {
-        let ctx = self.ctx.read().await;
-        match ctx.sql(sql).await {
-            Ok(_) => Ok(true),
-            Err(_) => Ok(false),
+    /// Detach a database attached with `attach_sqlite`
+    #[cfg(feature = "sqlite")]
+    pub async fn detach_sqlite(&self, dataset: &str) -> BlazeResult<()> {
+        let ctx = self.ctx.write().await;
+        if self.sqlite_databases.write().await.remove(dataset).is_none() {
+            return Err(BlazeError::InvalidInput(format!("No SQLite database is attached as '{}'", dataset)));
+        }
+        let default_catalog = ctx.state().config_options().catalog.default_catalog.clone();
+        if let Some(catalog) = ctx.catalog(&default_catalog) {
+            catalog.deregister_schema(dataset, true)?;
         }
+        info!("Detached SQLite database '{}'", dataset);
+        Ok(())
     }
 
-    /// Convert RecordBatch to JSON-serializable format (simplified)
-    fn record_batch_to_json(&self, batch: &RecordBatch) -> BlazeResult<Vec<HashMap<String, serde_json::Value>>> {
-        let mut result = Vec::with_capacity(batch.num_rows());
-        
-        // Simple conversion - can be optimized later
-        for row_idx in 0..batch.num_rows() {
-            let mut row = HashMap::new();
-            
-            for (col_idx, field) in batch.schema().fields().iter().enumerate() {
-                let column = batch.column(col_idx);
-                let value = match field.data_type() {
-                    datafusion::arrow::datatypes::DataType::Int64 => {
-                        let array = column.as_any().downcast_ref::<datafusion::arrow::array::Int64Array>().unwrap();
-                        if array.is_null(row_idx) {
-                            serde_json::Value::Null
-                        } else {
-                            serde_json::Value::Number(array.value(row_idx).into())
-                        }
-                    },
-                    datafusion::arrow::datatypes::DataType::Float64 => {
-                        let array = column.as_any().downcast_ref::<datafusion::arrow::array::Float64Array>().unwrap();
-                        if array.is_null(row_idx) {
-                            serde_json::Value::Null
-                        } else {
-                            serde_json::json!(array.value(row_idx))
-                        }
-                    },
-                    datafusion::arrow::datatypes::DataType::Utf8 => {
-                        let array = column.as_any().downcast_ref::<datafusion::arrow::array::StringArray>().unwrap();
-                        if array.is_null(row_idx) {
-                            serde_json::Value::Null
-                        } else {
-                            serde_json::Value::String(array.value(row_idx).to_string())
-                        }
-                    },
-                    _ => serde_json::Value::String(format!("Unsupported type: {:?}", field.data_type())),
-                };
-                
-                row.insert(field.name().clone(), value);
+    /// Databases attached with `attach_sqlite`, sorted by dataset
+    #[cfg(feature = "sqlite")]
+    pub async fn list_sqlite_databases(&self) -> Vec<SqliteDatabaseInfo> {
+        let mut databases: Vec<_> = self.sqlite_databases.read().await.values().cloned().collect();
+        databases.sort_by(|a, b| a.dataset.cmp(&b.dataset));
+        databases
+    }
+
+    /// Load a CSV file into a table, replacing any table of that name.
+    /// Column types are inferred unless given in `options`; rows that cannot
+    /// be loaded fail the load, are skipped, or go to a rejects table.
+    pub async fn load_csv(&self, table_name: &str, path: impl AsRef<Path>, options: &CsvIngestOptions) -> BlazeResult<CsvLoadReport> {
+        let path = path.as_ref().to_path_buf();
+        let read_options = options.clone();
+        let ingest = tokio::task::spawn_blocking(move || csv_ingest::read_csv(&path, &read_options))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("CSV reader failed: {}", e)))??;
+
+        let rejects_table = match &options.bad_rows {
+            BadRowPolicy::Reject(name) if !ingest.rejected.is_empty() => {
+                self.register_table(name, vec![ingest.rejects_batch()?]).await?;
+                Some(name.clone())
             }
-            
-            result.push(row);
+            _ => None,
+        };
+
+        let schema = ingest.batch.schema();
+        let report = CsvLoadReport {
+            table_name: table_name.to_string(),
+            rows_loaded: ingest.batch.num_rows(),
+            rows_rejected: ingest.rejected.len(),
+            rejects_table,
+            columns: schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+        };
+        self.register_table(table_name, vec![ingest.batch]).await?;
+
+        if report.rows_rejected > 0 {
+            warn!("Loaded {} rows into '{}', rejected {}", report.rows_loaded, table_name, report.rows_rejected);
         }
+        Ok(report)
+    }
 
-        Ok(result)
+    /// Load a GeoJSON FeatureCollection, Feature or geometry into a table,
+    /// replacing any table of that name. Geometries are stored as WKB with
+    /// their bounding boxes; feature properties become the other columns.
+    pub async fn load_geojson(&self, table_name: &str, path: impl AsRef<Path>) -> BlazeResult<GeoLoadReport> {
+        let path = path.as_ref().to_path_buf();
+        let batch = tokio::task::spawn_blocking(move || geo_ingest::read_geojson(&path))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("GeoJSON reader failed: {}", e)))??;
+        self.load_geo_batch(table_name, batch).await
     }
 
-    /// Update engine statistics
-    async fn update_stats(&self, execution_time_ms: u64, memory_used: u64) {
-        let mut stats = self.stats.write().await;
-        
-        stats.total_queries += 1;
-        
-        // Update average execution time
-        let total_time = stats.avg_execution_time_ms * (stats.total_queries - 1) as f64 
-                        + execution_time_ms as f64;
-        stats.avg_execution_time_ms = total_time / stats.total_queries as f64;
-        
-        // Update peak memory usage
-        if memory_used > stats.peak_memory_bytes {
-            stats.peak_memory_bytes = memory_used;
-        }
-    }
-
-    /// Generate hash for SQL query (for logging/caching)
-    fn hash_sql(&self, sql: &str) -> String {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        
-        let mut hasher = DefaultHasher::new();
-        sql.hash(&mut hasher);
-        format!("{:x}", hasher.finish())
-    }
-}
\ No newline at end of file
+    /// Load a shapefile's shapes, and the attributes of the `.dbf` file next
+    /// to it, into a table, replacing any table of that name
+    pub async fn load_shapefile(&self, table_name: &str, path: impl AsRef<Path>) -> BlazeResult<GeoLoadReport> {
+        let path = path.as_ref().to_path_buf();
+        let batch = tokio::task::spawn_blocking(move || geo_ingest::read_shapefile(&path))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Shapefile reader failed: {}", e)))??;
+        self.load_geo_batch(table_name, batch).await
+    }
+
+    /// Load an Avro object container file, or the `.avro` files in a
+    /// directory, into a table, replacing any table of that name. Avro
+    /// types map to Arrow types: records to structs, arrays to lists,
+    /// enums to strings and `["null", T]` unions to nullable columns.
+    pub async fn register_avro(&self, table_name: &str, path: impl AsRef<Path>) -> BlazeResult<AvroLoadReport> {
+        let files = avro_ingest::avro_files(path.as_ref())?;
+        let read_files = files.clone();
+        let (schema, batches) = tokio::task::spawn_blocking(move || avro_ingest::read_avro(&read_files))
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Avro reader failed: {}", e)))??;
+
+        let report = AvroLoadReport {
+            table_name: table_name.to_string(),
+            files: files.iter().map(|file| file.display().to_string()).collect(),
+            rows_loaded: batches.iter().map(RecordBatch::num_rows).sum(),
+            columns: schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+        };
+        let batches = if batches.is_empty() { vec![RecordBatch::new_empty(schema)] } else { batches };
+        self.register_table(table_name, batches).await?;
+        Ok(report)
+    }
+
+    /// Decode Protocol Buffers messages of `message_type` from a file, or
+    /// the files in a directory, with the compiled descriptor set at
+    /// `descriptor_path` and load them into a table, replacing any table of
+    /// that name. Messages map to structs, repeated fields to lists and
+    /// enums to the names of their values.
+    pub async fn register_protobuf(
+        &self,
+        table_name: &str,
+        descriptor_path: impl AsRef<Path>,
+        message_type: &str,
+        path: impl AsRef<Path>,
+        framing: ProtobufFraming,
+    ) -> BlazeResult<ProtobufLoadReport> {
+        let descriptor = protobuf_ingest::message_descriptor(descriptor_path.as_ref(), message_type)?;
+        let files = protobuf_ingest::message_files(path.as_ref())?;
+        let (read_descriptor, read_files) = (descriptor.clone(), files.clone());
+        let (schema, batches) =
+            tokio::task::spawn_blocking(move || protobuf_ingest::read_messages(&read_descriptor, &read_files, framing))
+                .await
+                .map_err(|e| BlazeError::Internal(format!("Protobuf reader failed: {}", e)))??;
+
+        let report = ProtobufLoadReport {
+            table_name: table_name.to_string(),
+            message_type: descriptor.full_name().to_string(),
+            files: files.iter().map(|file| file.display().to_string()).collect(),
+            rows_loaded: batches.iter().map(RecordBatch::num_rows).sum(),
+            columns: schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+        };
+        let batches = if batches.is_empty() { vec![RecordBatch::new_empty(schema)] } else { batches };
+        self.register_table(table_name, batches).await?;
+        Ok(report)
+    }
+
+    /// Load a BigQuery table, `project.dataset.table`, into a table through
+    /// the Storage Read API, replacing any table of that name. Only the
+    /// columns in `options` are read and BigQuery filters the rows with its
+    /// row restriction. See `bigquery_ingest`.
+    #[cfg(feature = "bigquery")]
+    pub async fn register_bigquery(
+        &self,
+        table_name: &str,
+        source_table: &str,
+        options: &BigQueryReadOptions,
+    ) -> BlazeResult<BigQueryLoadReport> {
+        let read = bigquery_ingest::read_table(source_table, options).await?;
+        let report = BigQueryLoadReport {
+            table_name: table_name.to_string(),
+            source_table: read.source_table,
+            streams: read.streams,
+            rows_loaded: read.batches.iter().map(RecordBatch::num_rows).sum(),
+            estimated_bytes_scanned: read.estimated_bytes_scanned,
+            columns: read.schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+        };
+        let batches = if read.batches.is_empty() { vec![RecordBatch::new_empty(read.schema)] } else { read.batches };
+        self.register_table(table_name, batches).await?;
+        Ok(report)
+    }
+
+    async fn load_geo_batch(&self, table_name: &str, batch: RecordBatch) -> BlazeResult<GeoLoadReport> {
+        let report = GeoLoadReport {
+            table_name: table_name.to_string(),
+            rows_loaded: batch.num_rows(),
+            columns: batch.schema().fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+            bounds: geo_ingest::bounds(&batch),
+        };
+        self.register_table(table_name, vec![batch]).await?;
+        Ok(report)
+    }
+
+    /// Write a table, or the result of a query, as Parquet files under the
+    /// directory `path`, in hive-style directories for `partition_by` and
+    /// rolled over at `target_file_size_bytes`. Rows are streamed, so the
+    /// result does not have to fit in memory.
+    pub async fn write_table_to_parquet(
+        &self,
+        table_or_sql: &str,
+        path: impl AsRef<Path>,
+        options: &ParquetSinkOptions,
+    ) -> BlazeResult<ParquetSinkReport> {
+        let mut stream = self.execute_stream(&parquet_sink::source_sql(table_or_sql)).await?;
+        let mut sink = ParquetSink::create(path.as_ref(), &stream.schema(), options)?;
+
+        // Encoding and file I/O run off the async workers, one batch at a time
+        let writer_failed = |e| BlazeError::Internal(format!("Parquet writer failed: {}", e));
+        while let Some(batch) = stream.next().await {
+            let batch = batch?;
+            sink = tokio::task::spawn_blocking(move || sink.write(&batch).map(|_| sink))
+                .await
+                .map_err(writer_failed)??;
+        }
+        let report = tokio::task::spawn_blocking(move || sink.finish()).await.map_err(writer_failed)??;
+
+        info!(
+            "Wrote {} rows to {} Parquet files ({}) under {}",
+            report.rows_written, report.files.len(), format_bytes(report.bytes_written), report.path
+        );
+        Ok(report)
+    }
+
+    /// Write the result of `sql` to one Parquet file at `path`, streaming
+    /// it a batch at a time. The file appears at `path` only once it is
+    /// complete. See `result_export`.
+    pub async fn execute_query_to_parquet(
+        &self,
+        sql: &str,
+        path: impl AsRef<Path>,
+        options: &ParquetExportOptions,
+    ) -> BlazeResult<ExportReport> {
+        let stream = self.execute_stream(sql).await?;
+        let file = ExportFile::parquet(path.as_ref(), &stream.schema(), options)?;
+        Self::write_export(stream, file).await
+    }
+
+    /// Write the result of `sql` to one CSV file at `path`, streaming it a
+    /// batch at a time. See `result_export`.
+    pub async fn execute_query_to_csv(
+        &self,
+        sql: &str,
+        path: impl AsRef<Path>,
+        options: &CsvExportOptions,
+    ) -> BlazeResult<ExportReport> {
+        let stream = self.execute_stream(sql).await?;
+        let file = ExportFile::csv(path.as_ref(), options)?;
+        Self::write_export(stream, file).await
+    }
+
+    /// Write the result of `sql` to one newline-delimited JSON file at
+    /// `path`, streaming it a batch at a time. See `result_export`.
+    pub async fn execute_query_to_json(
+        &self,
+        sql: &str,
+        path: impl AsRef<Path>,
+        options: &JsonExportOptions,
+    ) -> BlazeResult<ExportReport> {
+        let stream = self.execute_stream(sql).await?;
+        let file = ExportFile::json(path.as_ref(), options)?;
+        Self::write_export(stream, file).await
+    }
+
+    async fn write_export(mut stream: SendableRecordBatchStream, mut file: ExportFile) -> BlazeResult<ExportReport> {
+        // Encoding and file I/O run off the async workers, one batch at a time
+        let writer_failed = |e| BlazeError::Internal(format!("Export writer failed: {}", e));
+        while let Some(batch) = stream.next().await {
+            let batch = batch?;
+            file = tokio::task::spawn_blocking(move || file.write(&batch).map(|_| file))
+                .await
+                .map_err(writer_failed)??;
+        }
+        let report = tokio::task::spawn_blocking(move || file.finish()).await.map_err(writer_failed)??;
+
+        info!(
+            "Exported {} rows to {} ({})",
+            report.rows_written, report.path, format_bytes(report.bytes_written)
+        );
+        Ok(report)
+    }
+
+    /// Tables registered from files that are still in the catalog
+    pub async fn list_file_tables(&self) -> Vec<FileTableInfo> {
+        let ctx = self.ctx.read().await;
+        let mut tables: Vec<_> = self
+            .file_tables
+            .read()
+            .await
+            .values()
+            .filter(|info| ctx.table_exist(info.table_name.as_str()).unwrap_or(false))
+            .cloned()
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Tables registered from ORC files that are still in the catalog
+    pub async fn list_orc_tables(&self) -> Vec<OrcTableInfo> {
+        let ctx = self.ctx.read().await;
+        let mut tables: Vec<_> = self
+            .orc_tables
+            .read()
+            .await
+            .values()
+            .filter(|info| ctx.table_exist(info.table_name.as_str()).unwrap_or(false))
+            .cloned()
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Tables registered from memory-mapped Arrow IPC files that are still
+    /// in the catalog
+    pub async fn list_arrow_ipc_tables(&self) -> Vec<ArrowIpcTableInfo> {
+        let ctx = self.ctx.read().await;
+        let mut tables: Vec<_> = self
+            .arrow_ipc_tables
+            .read()
+            .await
+            .values()
+            .filter(|info| ctx.table_exist(info.table_name.as_str()).unwrap_or(false))
+            .cloned()
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Exempt an in-memory table from eviction under memory pressure
+    pub async fn pin_table(&self, name: &str) -> BlazeResult<()> {
+        if !self.ctx.read().await.table_exist(name)? {
+            return Err(BlazeError::TableNotFound { table_name: name.to_string() });
+        }
+        self.table_eviction.write().await.set_pinned(name, true);
+        Ok(())
+    }
+
+    /// Make a pinned table evictable again
+    pub async fn unpin_table(&self, name: &str) {
+        self.table_eviction.write().await.set_pinned(name, false);
+    }
+
+    /// Memory held by the in-memory tables, most recently used first
+    pub async fn list_table_memory(&self) -> Vec<TableMemoryInfo> {
+        self.table_eviction.read().await.memory()
+    }
+
+    /// The most recent tables evicted under memory pressure, oldest first
+    pub async fn list_table_evictions(&self) -> Vec<EvictionEvent> {
+        self.table_eviction.read().await.events()
+    }
+
+    /// Open a transaction. Tables written until `commit_transaction` or
+    /// `rollback_transaction` are restored on rollback, and their change
+    /// events are published on commit.
+    pub async fn begin_transaction(&self) -> BlazeResult<()> {
+        self.begin_session_transaction(None).await
+    }
+
+    /// Commit the open transaction, publishing its change events
+    pub async fn commit_transaction(&self) -> BlazeResult<TransactionInfo> {
+        self.commit_session_transaction(None).await
+    }
+
+    /// Roll back the open transaction: restore the tables it wrote and drop
+    /// the tables it created
+    pub async fn rollback_transaction(&self) -> BlazeResult<TransactionInfo> {
+        self.rollback_session_transaction(None).await
+    }
+
+    /// The open transaction, if any
+    pub async fn current_transaction(&self) -> Option<TransactionInfo> {
+        self.transactions.read().await.get(&None).map(Transaction::info)
+    }
+
+    /// Open a transaction for the statements of session `session_id`, or
+    /// of no session. Each session has its own.
+    async fn begin_session_transaction(&self, session_id: Option<&str>) -> BlazeResult<()> {
+        let mut transactions = self.transactions.write().await;
+        let key = session_id.map(str::to_string);
+        if transactions.contains_key(&key) {
+            return Err(BlazeError::InvalidInput("A transaction is already open".to_string()));
+        }
+        transactions.insert(key, Transaction::begin(self.clock.now()));
+        info!("Began transaction");
+        Ok(())
+    }
+
+    async fn take_transaction(&self, session_id: Option<&str>) -> BlazeResult<Transaction> {
+        self.transactions.write().await.remove(&session_id.map(str::to_string)).ok_or_else(|| {
+            BlazeError::InvalidInput("No transaction is open".to_string())
+        })
+    }
+
+    async fn commit_session_transaction(&self, session_id: Option<&str>) -> BlazeResult<TransactionInfo> {
+        let transaction = self.take_transaction(session_id).await?;
+        let info = transaction.info();
+        {
+            let mut feed = self.change_feed.write().await;
+            for change in transaction.held_changes {
+                feed.publish(&change.table_name, change.change_type, change.committed_at, change.batches);
+            }
+        }
+        info!("Committed transaction writing {} tables", info.tables.len());
+        Ok(info)
+    }
+
+    async fn rollback_session_transaction(&self, session_id: Option<&str>) -> BlazeResult<TransactionInfo> {
+        let transaction = self.take_transaction(session_id).await?;
+        let info = transaction.info();
+        {
+            let ctx = self.ctx.write().await;
+            for (name, saved) in transaction.saved {
+                match saved {
+                    Some(saved) => {
+                        self.store_table(&ctx, &name, saved.schema, saved.batches, self.clock.now(), session_id).await?;
+                    }
+                    None => {
+                        ctx.deregister_table(name.as_str())?;
+                    }
+                }
+            }
+        }
+        for name in &info.tables {
+            self.maintain_materialized_views(name, None).await;
+        }
+        info!("Rolled back transaction writing {} tables", info.tables.len());
+        Ok(info)
+    }
+
+    /// Tables registered from PostgreSQL servers that are still in the
+    /// catalog
+    #[cfg(feature = "postgres")]
+    pub async fn list_postgres_tables(&self) -> Vec<PostgresTableInfo> {
+        let ctx = self.ctx.read().await;
+        let mut tables: Vec<_> = self
+            .postgres_tables
+            .read()
+            .await
+            .values()
+            .filter(|info| ctx.table_exist(info.table_name.as_str()).unwrap_or(false))
+            .cloned()
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Tables registered from Flight services that are still in the catalog
+    pub async fn list_flight_tables(&self) -> Vec<FlightTableInfo> {
+        let ctx = self.ctx.read().await;
+        let mut tables: Vec<_> = self
+            .flight_tables
+            .read()
+            .await
+            .values()
+            .filter(|info| ctx.table_exist(info.table_name.as_str()).unwrap_or(false))
+            .cloned()
+            .collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        tables
+    }
+
+    /// Append RecordBatches to an existing in-memory table. Existing rows
+    /// are not copied, and small appends are merged into the table's last
+    /// batch until it reaches `batch_size` rows, so frequent small appends
+    /// do not leave the table split into ever more tiny batches.
+    pub async fn append_to_table(&self, name: &str, batches: Vec<RecordBatch>) -> BlazeResult<()> {
+        self.append_in_session(name, batches, None).await
+    }
+
+    /// Append to a table as a statement run in session `session_id`, inside
+    /// that session's open transaction if any
+    async fn append_in_session(&self, name: &str, batches: Vec<RecordBatch>, session_id: Option<&str>) -> BlazeResult<()> {
+        if batches.is_empty() {
+            return Ok(());
+        }
+
+        let appended_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+
+        if self.materialized_views.read().await.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", name)));
+        }
+
+        // Appends build the next version of the table while queries keep
+        // reading the latest one; only writers to the same table wait
+        let _writer = self.table_writers.lock(name).await;
+        let committed_at = self.clock.now();
+        {
+            let ctx = self.ctx.read().await;
+            let provider = ctx.table_provider(name).await.map_err(|_| BlazeError::TableNotFound {
+                table_name: name.to_string(),
+            })?;
+            let provider = table_versions::latest(provider);
+            // Spilled tables are loaded back into memory
+            if !is_in_memory(provider.as_ref()) && !self.table_eviction.read().await.is_spilled(name) {
+                return Err(BlazeError::InvalidInput(format!("Table '{}' does not support appends", name)));
+            }
+
+            let schema = provider.schema();
+            if let Some(batch) = batches.iter().find(|b| !schema.contains(&b.schema())) {
+                return Err(BlazeError::SchemaMismatch(format!(
+                    "cannot append {:?} to table '{}' with schema {:?}",
+                    batch.schema(), name, schema
+                )));
+            }
+
+            // Existing batches are reference counted, so this copies no row
+            // data beyond the small last batch appends are merged into
+            let mut all_batches = ctx.read_table(provider)?.collect().await?;
+            let last = all_batches.len().saturating_sub(1);
+            all_batches.extend(batches.iter().cloned());
+            let batch_size = self.config.read().await.batch_size;
+            coalesce_batches(&schema, &mut all_batches, last, batch_size)?;
+            self.store_table(&ctx, name, schema, all_batches, committed_at, session_id).await?;
+            self.publish_changes(name, committed_at, None, Some(batches.clone()), session_id).await;
+        }
+
+        self.maintain_materialized_views(name, Some(&batches)).await;
+
+        info!("Appended {} rows to table '{}'", appended_rows, name);
+
+        Ok(())
+    }
+
+    /// Create a materialized view storing the result of `sql`. Simple
+    /// aggregations over a single table are maintained incrementally as
+    /// batches are appended to it; other views are fully recomputed.
+    pub async fn create_materialized_view(&self, name: &str, sql: &str) -> BlazeResult<MaterializedViewInfo> {
+        let mut views = self.materialized_views.write().await;
+        let ctx = self.ctx.read().await;
+
+        if views.contains_key(name) || ctx.table_exist(name)? {
+            return Err(BlazeError::InvalidInput(format!("Table or view '{}' already exists", name)));
+        }
+
+        let view = MaterializedView::create(&ctx, name, sql, self.clock.now()).await?;
+        let table = MemTable::try_new(view.schema(), vec![view.batches().to_vec()])?;
+        ctx.register_table(name, Arc::new(table))?;
+
+        let info = view.info();
+        match &info.unsupported_reason {
+            None => info!("Created incremental materialized view '{}'", name),
+            Some(reason) => info!("Created materialized view '{}' with full refresh: {}", name, reason),
+        }
+        views.insert(name.to_string(), view);
+
+        Ok(info)
+    }
+
+    /// Fully recompute a materialized view
+    pub async fn refresh_materialized_view(&self, name: &str) -> BlazeResult<MaterializedViewInfo> {
+        let mut views = self.materialized_views.write().await;
+        let view = views.get_mut(name).ok_or_else(|| BlazeError::TableNotFound { table_name: name.to_string() })?;
+
+        let ctx = self.ctx.read().await;
+        view.full_refresh(&ctx, self.clock.now()).await?;
+        Self::publish_view(&ctx, view)?;
+
+        Ok(view.info())
+    }
+
+    /// Drop a materialized view and its stored result
+    pub async fn drop_materialized_view(&self, name: &str) -> BlazeResult<()> {
+        let mut views = self.materialized_views.write().await;
+        views.remove(name).ok_or_else(|| BlazeError::TableNotFound { table_name: name.to_string() })?;
+
+        let ctx = self.ctx.read().await;
+        ctx.deregister_table(name)?;
+
+        Ok(())
+    }
+
+    /// Describe all materialized views
+    pub async fn list_materialized_views(&self) -> Vec<MaterializedViewInfo> {
+        let views = self.materialized_views.read().await;
+        let mut infos: Vec<_> = views.values().map(MaterializedView::info).collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    /// Train a model from a `CREATE [OR REPLACE] MODEL ... AS <query>` statement
+    pub async fn create_model(&self, sql: &str) -> BlazeResult<Model> {
+        let statement = ml::parse_create_model(sql)?
+            .ok_or_else(|| BlazeError::InvalidInput("Expected a CREATE MODEL statement".to_string()))?;
+        let key = statement.name.to_lowercase();
+
+        if !statement.or_replace && self.models.read().await.contains_key(&key) {
+            return Err(BlazeError::InvalidInput(format!("Model '{}' already exists", statement.name)));
+        }
+
+        let model = {
+            let ctx = self.ctx.read().await;
+            let batches = self.plan_sql(&ctx, &statement.query).await?.collect().await?;
+            let model = ml::train(&statement.name, &statement.options, &batches)?;
+            ctx.register_udf(model.to_udf());
+            model
+        };
+        self.models.write().await.insert(key, model.clone());
+
+        info!(
+            "Trained {:?} model '{}' on {} rows (loss {:.6})",
+            model.model_type, model.name, model.training_rows, model.training_loss
+        );
+        Ok(model)
+    }
+
+    /// Look up a trained model
+    pub async fn get_model(&self, name: &str) -> Option<Model> {
+        self.models.read().await.get(&name.to_lowercase()).cloned()
+    }
+
+    /// Describe all trained models
+    pub async fn list_models(&self) -> Vec<Model> {
+        let models = self.models.read().await;
+        let mut list: Vec<_> = models.values().cloned().collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Drop a trained model and its prediction function
+    pub async fn drop_model(&self, name: &str) -> BlazeResult<()> {
+        let model = self
+            .models
+            .write()
+            .await
+            .remove(&name.to_lowercase())
+            .ok_or_else(|| BlazeError::InvalidInput(format!("Model '{}' not found", name)))?;
+
+        let ctx = self.ctx.read().await;
+        ctx.deregister_udf(&model.udf_name());
+        Ok(())
+    }
+
+    /// Build a search index on `columns` of a table (all string columns when
+    /// `None`). The index is kept up to date as the table changes.
+    pub async fn create_search_index(
+        &self,
+        index_name: &str,
+        table_name: &str,
+        columns: Option<Vec<String>>,
+    ) -> BlazeResult<SearchIndexInfo> {
+        let ctx = self.ctx.write().await;
+        let provider = ctx.table_provider(table_name).await.map_err(|_| BlazeError::TableNotFound {
+            table_name: table_name.to_string(),
+        })?;
+        if let Some(existing) = self.search_indexes.read().await.get(table_name) {
+            return Err(BlazeError::InvalidInput(format!(
+                "Table '{}' already has search index '{}'", table_name, existing.name
+            )));
+        }
+        if self.time_partitioning.read().await.contains_key(table_name) {
+            return Err(BlazeError::InvalidInput(format!(
+                "Table '{}' is partitioned by time and cannot have a search index", table_name
+            )));
+        }
+
+        let schema = provider.schema();
+        let columns = columns.unwrap_or_else(|| {
+            schema
+                .fields()
+                .iter()
+                .filter(|f| matches!(f.data_type(), DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View))
+                .map(|f| f.name().clone())
+                .collect()
+        });
+        if columns.is_empty() {
+            return Err(BlazeError::InvalidInput(format!("Table '{}' has no columns to index", table_name)));
+        }
+
+        let batches = ctx.table(table_name).await?.collect().await?;
+        let table = SearchIndexedTable::try_new(schema, batches, &columns)?;
+        Self::commit_table(&ctx, table_name, Arc::new(table)).await?;
+
+        let index = SearchIndexInfo {
+            name: index_name.to_string(),
+            table_name: table_name.to_string(),
+            columns,
+        };
+        self.search_indexes.write().await.insert(table_name.to_string(), index.clone());
+
+        info!("Created search index '{}' on {}({})", index_name, table_name, index.columns.join(", "));
+        Ok(index)
+    }
+
+    /// Drop a table's search index; the table itself is kept
+    pub async fn drop_search_index(&self, index_name: &str, table_name: &str) -> BlazeResult<()> {
+        let ctx = self.ctx.write().await;
+        {
+            let mut indexes = self.search_indexes.write().await;
+            match indexes.get(table_name) {
+                Some(index) if index.name.eq_ignore_ascii_case(index_name) => indexes.remove(table_name),
+                _ => {
+                    return Err(BlazeError::InvalidInput(format!(
+                        "Search index '{}' on '{}' not found", index_name, table_name
+                    )))
+                }
+            };
+        }
+
+        let provider = ctx.table_provider(table_name).await?;
+        let batches = ctx.table(table_name).await?.collect().await?;
+        let table = MemTable::try_new(provider.schema(), vec![batches])?;
+        Self::commit_table(&ctx, table_name, Arc::new(table)).await?;
+
+        Ok(())
+    }
+
+    /// Describe all search indexes
+    pub async fn list_search_indexes(&self) -> Vec<SearchIndexInfo> {
+        let indexes = self.search_indexes.read().await;
+        let mut list: Vec<_> = indexes.values().cloned().collect();
+        list.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        list
+    }
+
+    /// Partition a table by the UTC day of a timestamp or date column. Batches
+    /// registered or appended from now on are split automatically and scans
+    /// skip the days their filters on the column rule out. An existing
+    /// table is split right away; one registered later is split on
+    /// registration.
+    pub async fn set_time_partitioning(&self, table_name: &str, column: &str) -> BlazeResult<TimePartitioning> {
+        let ctx = self.ctx.write().await;
+        if self.search_indexes.read().await.contains_key(table_name) {
+            return Err(BlazeError::InvalidInput(format!(
+                "Table '{}' has a search index and cannot be partitioned by time", table_name
+            )));
+        }
+        if self.materialized_views.read().await.contains_key(table_name) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", table_name)));
+        }
+
+        if ctx.table_exist(table_name)? {
+            let provider = table_versions::latest(ctx.table_provider(table_name).await?);
+            if !provider.as_any().is::<MemTable>() && !provider.as_any().is::<TimePartitionedTable>() {
+                return Err(BlazeError::InvalidInput(format!(
+                    "Only in-memory tables can be partitioned by time; '{}' is not one", table_name
+                )));
+            }
+            let batches = ctx.table(table_name).await?.collect().await?;
+            let table = TimePartitionedTable::try_new(provider.schema(), batches, column)?;
+            info!("Split table '{}' into {} daily partitions by '{}'", table_name, table.partition_info().len(), column);
+            Self::commit_table(&ctx, table_name, Arc::new(table)).await?;
+        }
+
+        self.time_partitioning.write().await.insert(table_name.to_string(), column.to_string());
+        Ok(TimePartitioning { table_name: table_name.to_string(), column: column.to_string() })
+    }
+
+    /// Stop partitioning a table by time; its rows are kept
+    pub async fn clear_time_partitioning(&self, table_name: &str) -> BlazeResult<()> {
+        let ctx = self.ctx.write().await;
+        if self.time_partitioning.write().await.remove(table_name).is_none() {
+            return Err(BlazeError::InvalidInput(format!("Table '{}' is not partitioned by time", table_name)));
+        }
+
+        if ctx.table_exist(table_name)? {
+            let provider = ctx.table_provider(table_name).await?;
+            let batches = ctx.table(table_name).await?.collect().await?;
+            let table = MemTable::try_new(provider.schema(), vec![batches])?;
+            Self::commit_table(&ctx, table_name, Arc::new(table)).await?;
+        }
+        Ok(())
+    }
+
+    /// The daily partitions of a table partitioned by time
+    pub async fn time_partitions(&self, table_name: &str) -> BlazeResult<TimePartitionInfo> {
+        let ctx = self.ctx.read().await;
+        let provider = ctx.table_provider(table_name).await.map_err(|_| BlazeError::TableNotFound {
+            table_name: table_name.to_string(),
+        })?;
+        let provider = table_versions::latest(provider);
+        let table = provider
+            .as_any()
+            .downcast_ref::<TimePartitionedTable>()
+            .ok_or_else(|| BlazeError::InvalidInput(format!("Table '{}' is not partitioned by time", table_name)))?;
+        Ok(TimePartitionInfo {
+            table_name: table_name.to_string(),
+            column: table.column().to_string(),
+            partitions: table.partition_info(),
+        })
+    }
+
+    /// Tables designated for time partitioning, including ones not
+    /// registered yet, sorted by table name
+    pub async fn list_time_partitioning(&self) -> Vec<TimePartitioning> {
+        let mut list: Vec<_> = self
+            .time_partitioning
+            .read()
+            .await
+            .iter()
+            .map(|(table_name, column)| TimePartitioning { table_name: table_name.clone(), column: column.clone() })
+            .collect();
+        list.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        list
+    }
+
+    /// Build an HNSW index over an embedding column. The index is rebuilt
+    /// whenever the table changes.
+    pub async fn create_vector_index(&self, statement: CreateVectorIndex) -> BlazeResult<VectorIndexInfo> {
+        let ctx = self.ctx.read().await;
+        if !ctx.table_exist(statement.table_name.as_str())? {
+            return Err(BlazeError::TableNotFound { table_name: statement.table_name.clone() });
+        }
+
+        let key = statement.name.to_lowercase();
+        if !statement.or_replace && self.vector_indexes.read().await.contains_key(&key) {
+            return Err(BlazeError::InvalidInput(format!("Vector index '{}' already exists", statement.name)));
+        }
+
+        let batches = ctx.table(statement.table_name.as_str()).await?.collect().await?;
+        let index = VectorIndex::build(statement, &batches);
+        let info = index.info();
+        if let Some(error) = info.error {
+            return Err(BlazeError::InvalidInput(format!("Cannot build vector index '{}': {}", info.name, error)));
+        }
+        self.vector_indexes.write().await.insert(key, index);
+
+        info!("Built vector index '{}' over {} rows of {}({})", info.name, info.indexed_rows, info.table_name, info.column);
+        Ok(info)
+    }
+
+    /// Drop a vector index; the table itself is kept
+    pub async fn drop_vector_index(&self, index_name: &str, table_name: &str) -> BlazeResult<()> {
+        let mut indexes = self.vector_indexes.write().await;
+        let key = index_name.to_lowercase();
+        match indexes.get(&key) {
+            Some(index) if index.table_name() == table_name => {
+                indexes.remove(&key);
+                Ok(())
+            }
+            _ => Err(BlazeError::InvalidInput(format!("Vector index '{}' on '{}' not found", index_name, table_name))),
+        }
+    }
+
+    /// Describe all vector indexes
+    pub async fn list_vector_indexes(&self) -> Vec<VectorIndexInfo> {
+        let indexes = self.vector_indexes.read().await;
+        let mut list: Vec<_> = indexes.values().map(VectorIndex::info).collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Evaluate data quality checks. Every check is run, and one that fails
+    /// to evaluate is reported as failed with its error.
+    pub async fn run_assertions(&self, checks: &[Assertion]) -> Vec<AssertionResult> {
+        let mut results = Vec::with_capacity(checks.len());
+        for check in checks {
+            let result = match self.evaluate_assertion(check).await {
+                Ok(result) => result,
+                Err(e) => check.errored(&e),
+            };
+            if !result.passed {
+                warn!("Assertion failed: {}", result.message.as_deref().unwrap_or(&check.expression));
+            }
+            results.push(result);
+        }
+        results
+    }
+
+    /// Get current engine statistics
+    pub async fn get_stats(&self) -> EngineStats {
+        #[allow(unused_mut)]
+        let mut stats = self.stats.read().await.clone();
+        #[cfg(feature = "object_store")]
+        {
+            stats.object_cache = self.object_cache.as_ref().map(|cache| cache.stats());
+        }
+        let max_bytes = self.config.read().await.result_cache_max_bytes;
+        stats.result_cache = self.result_cache.read().await.stats(max_bytes);
+        stats.plan_cache = self.plan_cache.read().await.stats();
+        stats.admission = self.admission.stats();
+        stats.slots = self.slots.stats();
+        stats
+    }
+
+    /// Statistics grouped by SQL fingerprint, most total execution time first
+    pub async fn query_stats(&self) -> Vec<QueryFingerprintStats> {
+        let mut stats: Vec<_> = self.query_stats.read().await.values().cloned().collect();
+        stats.sort_by(|a, b| {
+            b.total_execution_time_ms
+                .cmp(&a.total_execution_time_ms)
+                .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+        });
+        stats
+    }
+
+    /// Usage of labeled queries grouped by the values of `label`, e.g. per
+    /// `team`, most total execution time first. Queries without the label
+    /// are left out.
+    pub async fn get_stats_by_label(&self, label: &str) -> Vec<LabelStats> {
+        let mut stats: Vec<_> = self
+            .label_stats
+            .read()
+            .await
+            .values()
+            .filter(|entry| entry.label == label)
+            .cloned()
+            .collect();
+        stats.sort_by(|a, b| {
+            b.total_execution_time_ms
+                .cmp(&a.total_execution_time_ms)
+                .then_with(|| a.value.cmp(&b.value))
+        });
+        stats
+    }
+
+    /// Get available tables
+    pub async fn list_tables(&self) -> BlazeResult<Vec<String>> {
+        let ctx = self.ctx.read().await;
+        Self::list_tables_in(&ctx)
+    }
+
+    /// Tables and views of the default schema with their kinds, sorted by
+    /// name. Snapshot tables used for time travel are left out.
+    pub async fn list_relations(&self) -> BlazeResult<Vec<RelationInfo>> {
+        let ctx = self.ctx.read().await;
+        let mut names = Self::list_tables_in(&ctx)?;
+        names.retain(|name| !name.contains('@'));
+        names.sort();
+
+        let mut relations = Vec::with_capacity(names.len());
+        for name in names {
+            if let Some(relation_type) = self.relation_type(&ctx, &name).await {
+                relations.push(RelationInfo { name, relation_type });
+            }
+        }
+        Ok(relations)
+    }
+
+    /// Columns of a table or view in order
+    pub async fn get_columns(&self, name: &str) -> BlazeResult<Vec<ColumnInfo>> {
+        let ctx = self.ctx.read().await;
+        let provider = ctx.table_provider(name).await.map_err(|_| BlazeError::TableNotFound {
+            table_name: name.to_string(),
+        })?;
+        Ok(relations::columns(&provider.schema()))
+    }
+
+    /// Create table `name` holding the result of `sql`. An existing relation
+    /// is replaced only with `replace`. Returns the number of rows.
+    pub async fn create_table_as(&self, name: &str, sql: &str, replace: bool) -> BlazeResult<u64> {
+        let existing = {
+            let ctx = self.ctx.read().await;
+            self.relation_type(&ctx, name).await
+        };
+        match existing {
+            Some(_) if !replace => {
+                return Err(BlazeError::InvalidInput(format!("Relation '{}' already exists", name)));
+            }
+            Some(RelationType::Table) | None => {}
+            Some(_) => {
+                self.drop_relation(name).await?;
+            }
+        }
+
+        let (schema, mut batches) = {
+            let ctx = self.ctx.read().await;
+            let df = self.plan_sql(&ctx, sql).await?;
+            let schema: SchemaRef = Arc::new(df.schema().as_arrow().clone());
+            (schema, df.collect().await?)
+        };
+        // Keep the columns of an empty result
+        if batches.is_empty() {
+            batches.push(RecordBatch::new_empty(schema));
+        }
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        self.register_table(name, batches).await?;
+        Ok(rows as u64)
+    }
+
+    /// Rename a table or view; no relation may be called `to` yet. A table
+    /// keeps its search index and time partitioning.
+    pub async fn rename_relation(&self, from: &str, to: &str) -> BlazeResult<()> {
+        let (relation_type, exists) = {
+            let ctx = self.ctx.read().await;
+            (self.relation_type(&ctx, from).await, self.relation_type(&ctx, to).await.is_some())
+        };
+        let relation_type = relation_type.ok_or_else(|| BlazeError::TableNotFound { table_name: from.to_string() })?;
+        if exists {
+            return Err(BlazeError::InvalidInput(format!("Relation '{}' already exists", to)));
+        }
+
+        match relation_type {
+            RelationType::Table => {
+                let batches = {
+                    let ctx = self.ctx.read().await;
+                    ctx.table(from).await?.collect().await?
+                };
+                if let Some(column) = self.time_partitioning.write().await.remove(from) {
+                    self.time_partitioning.write().await.insert(to.to_string(), column);
+                }
+                if let Some(mut index) = self.search_indexes.write().await.remove(from) {
+                    index.table_name = to.to_string();
+                    self.search_indexes.write().await.insert(to.to_string(), index);
+                }
+                self.register_table(to, batches).await?;
+                self.drop_relation(from).await?;
+            }
+            RelationType::View => {
+                let ctx = self.ctx.write().await;
+                if let Some(view) = ctx.deregister_table(from)? {
+                    ctx.register_table(to, view)?;
+                }
+            }
+            other => {
+                return Err(BlazeError::InvalidInput(format!(
+                    "Only tables and views can be renamed; '{}' is {:?}", from, other
+                )));
+            }
+        }
+
+        info!("Renamed '{}' to '{}'", from, to);
+        Ok(())
+    }
+
+    /// Drop a table, view, materialized view or external table along with
+    /// its search index and time partitioning. Returns whether it existed.
+    pub async fn drop_relation(&self, name: &str) -> BlazeResult<bool> {
+        if self.materialized_views.read().await.contains_key(name) {
+            self.drop_materialized_view(name).await?;
+            return Ok(true);
+        }
+
+        let ctx = self.ctx.write().await;
+        let existed = ctx.deregister_table(name)?.is_some();
+        self.forget_external_table(name).await;
+        self.search_indexes.write().await.remove(name);
+        self.time_partitioning.write().await.remove(name);
+        self.statistics.remove(name);
+        if existed {
+            info!("Dropped '{}'", name);
+        }
+        Ok(existed)
+    }
+
+    /// Latest value of `loaded_at_column` in a table next to the current
+    /// time, the two values a source freshness check compares
+    pub async fn relation_freshness(&self, name: &str, loaded_at_column: &str) -> BlazeResult<FreshnessInfo> {
+        let ctx = self.ctx.read().await;
+        let table = ctx.table(name).await.map_err(|_| BlazeError::TableNotFound {
+            table_name: name.to_string(),
+        })?;
+        let batches = table.aggregate(vec![], vec![max(ident(loaded_at_column))])?.collect().await?;
+        Ok(FreshnessInfo {
+            table_name: name.to_string(),
+            max_loaded_at: relations::single_timestamp(&batches)?,
+            snapshotted_at: self.clock.now(),
+        })
+    }
+
+    /// Kind of the relation called `name`, `None` if there is none
+    async fn relation_type(&self, ctx: &SessionContext, name: &str) -> Option<RelationType> {
+        if self.materialized_views.read().await.contains_key(name) {
+            return Some(RelationType::MaterializedView);
+        }
+        let provider = ctx.table_provider(name).await.ok()?;
+        if self.file_tables.read().await.contains_key(name)
+            || self.flight_tables.read().await.contains_key(name)
+            || self.orc_tables.read().await.contains_key(name)
+            || self.arrow_ipc_tables.read().await.contains_key(name)
+            || self.is_delta_table(name).await
+            || self.is_iceberg_table(name).await
+            || self.is_postgres_table(name).await
+        {
+            return Some(RelationType::External);
+        }
+        match provider.as_any().is::<ViewTable>() {
+            true => Some(RelationType::View),
+            false => Some(RelationType::Table),
+        }
+    }
+
+    /// Tables and views of the default schema
+    fn list_tables_in(ctx: &SessionContext) -> BlazeResult<Vec<String>> {
+        let catalog = ctx.catalog("datafusion").ok_or_else(|| {
+            BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Catalog not found".to_string()))
+        })?;
+        let schema = catalog.schema("public").ok_or_else(|| {
+            BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Schema not found".to_string()))
+        })?;
+        let tables = schema.table_names();
+        Ok(tables)
+    }
+
+    /// Schema of a table or view, `None` if there is none by that name
+    pub(crate) async fn table_schema(&self, name: &str) -> Option<SchemaRef> {
+        let ctx = self.ctx.read().await;
+        ctx.table_provider(name).await.ok().map(|provider| provider.schema())
+    }
+
+    /// Define a resource group queries can be assigned to with `QueryOptions`
+    pub async fn create_resource_group(&self, group: ResourceGroup) -> BlazeResult<ResourceGroupStats> {
+        group.validate()?;
+        let mut groups = self.resource_groups.write().await;
+        if groups.contains_key(&group.name) {
+            return Err(BlazeError::InvalidInput(format!("Resource group '{}' already exists", group.name)));
+        }
+
+        let memory_limit = self.memory_pool.limit();
+        let state = ResourceGroupState::new(group.clone(), self.memory_pool.clone(), memory_limit);
+        let stats = state.stats();
+        groups.insert(group.name.clone(), Arc::new(state));
+
+        info!(
+            "Created resource group '{}': {:.0}% CPU, {:.0}% memory, {} concurrent queries",
+            group.name, group.cpu_share * 100.0, group.memory_fraction * 100.0, group.max_concurrency
+        );
+        Ok(stats)
+    }
+
+    /// Remove a resource group; its running and queued queries still finish
+    pub async fn drop_resource_group(&self, name: &str) -> BlazeResult<()> {
+        self.resource_groups
+            .write()
+            .await
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| BlazeError::InvalidInput(format!("Resource group '{}' not found", name)))
+    }
+
+    /// Anonymous result tables that have not expired, oldest first
+    pub async fn list_result_tables(&self) -> Vec<ResultTableInfo> {
+        self.expire_result_tables().await;
+        self.result_tables.read().await.list()
+    }
+
+    /// Drop a result table before it expires, by name or as `_results.<name>`
+    pub async fn drop_result_table(&self, name: &str) -> BlazeResult<()> {
+        let ctx = self.ctx.read().await;
+        self.result_tables.write().await.remove(&ctx, name)
+    }
+
+    /// Read up to `max_rows` rows of the kept result of `query_id`, from
+    /// the page `page_token` points at or from the start. The query must
+    /// have run with `QueryOptions::max_rows` or `cache_result`.
+    pub async fn fetch_results(&self, query_id: &str, page_token: Option<&str>, max_rows: usize) -> BlazeResult<ResultPage> {
+        if max_rows == 0 {
+            return Err(BlazeError::InvalidInput("max_rows must be greater than 0".to_string()));
+        }
+        self.expire_result_tables().await;
+        let (table, start_row, batches) = self.result_tables.read().await.page(query_id, page_token, max_rows)?;
+
+        let time_zone = self.config.read().await.time_zone.clone();
+        let mut data = Vec::new();
+        for batch in &batches {
+            data.extend(serializers::json_rows(batch, &time_zone)?);
+        }
+        Ok(ResultPage {
+            query_id: query_id.to_string(),
+            total_rows: table.rows,
+            start_row,
+            page_token: result_tables::page_token(&table, start_row + data.len()),
+            data,
+        })
+    }
+
+    /// Parse and plan the query `sql` once, keeping it for `execute_prepared`.
+    /// Its positional parameters `$1`, `$2`, ... are bound when it runs.
+    pub async fn prepare(&self, sql: &str) -> BlazeResult<PreparedStatementInfo> {
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput("Only queries can be prepared".to_string()));
+        }
+        if ml::contains_predict(sql)
+            || vector::contains_vector_search(sql)
+            || sessionize::contains_sessionize(sql)
+            || time_series::contains_gap_fill(sql)
+            || snapshots::contains_time_travel(sql)
+        {
+            return Err(BlazeError::InvalidInput(
+                "Queries using ML.PREDICT, table functions or time travel cannot be prepared".to_string(),
+            ));
+        }
+        let hints = query_hints::parse_hints(sql)?;
+        let plan = {
+            let ctx = self.ctx.read().await;
+            plan_statement(&ctx, sql).await.map_err(|e| suggestions::with_suggestions(e, &ctx))?
+        };
+        let statement = PreparedStatement::new(sql, plan, hints, self.clock.now())?;
+        let info = statement.info.clone();
+        self.prepared.write().await.insert(info.handle.clone(), statement);
+        info!("Prepared statement {} with {} parameters", info.handle, info.parameters.len());
+        Ok(info)
+    }
+
+    /// Run the prepared statement `handle` with `params` bound to its
+    /// parameters in order
+    pub async fn execute_prepared(&self, handle: &str, params: &[serde_json::Value]) -> BlazeResult<QueryResult> {
+        self.execute_prepared_with_options(handle, params, &QueryOptions::default()).await
+    }
+
+    /// Run a prepared statement with per-query options
+    pub async fn execute_prepared_with_options(
+        &self,
+        handle: &str,
+        params: &[serde_json::Value],
+        options: &QueryOptions,
+    ) -> BlazeResult<QueryResult> {
+        let (sql, bound) = {
+            let mut prepared = self.prepared.write().await;
+            let statement = prepared
+                .get_mut(handle)
+                .ok_or_else(|| BlazeError::InvalidInput(format!("No prepared statement '{}'", handle)))?;
+            let bound = statement.bind(params)?;
+            statement.info.executions += 1;
+            (statement.info.sql.clone(), bound)
+        };
+        self.execute_tracked(&sql, Some(bound), options).await
+    }
+
+    /// Statements kept by `prepare`, oldest first
+    pub async fn list_prepared(&self) -> Vec<PreparedStatementInfo> {
+        let mut statements: Vec<_> = self.prepared.read().await.values().map(|s| s.info.clone()).collect();
+        statements.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.handle.cmp(&b.handle)));
+        statements
+    }
+
+    /// Drop a prepared statement. Returns whether it existed.
+    pub async fn deallocate_prepared(&self, handle: &str) -> bool {
+        self.prepared.write().await.remove(handle).is_some()
+    }
+
+    /// Open a session, whose `SET` statements change settings for the
+    /// statements run in it only. See `sessions`.
+    pub async fn create_session(&self) -> SessionInfo {
+        let session = SessionInfo::new(self.clock.now());
+        self.sessions.write().await.insert(session.session_id.clone(), session.clone());
+        info!("Opened session {}", session.session_id);
+        session
+    }
+
+    /// A session's settings and activity
+    pub async fn get_session(&self, session_id: &str) -> Option<SessionInfo> {
+        self.sessions.read().await.get(session_id).cloned()
+    }
+
+    /// Open sessions, oldest first
+    pub async fn list_sessions(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<_> = self.sessions.read().await.values().cloned().collect();
+        sessions.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.session_id.cmp(&b.session_id)));
+        sessions
+    }
+
+    /// Close a session, rolling back its open transaction. Returns whether
+    /// it was open.
+    pub async fn close_session(&self, session_id: &str) -> bool {
+        let closed = self.sessions.write().await.remove(session_id).is_some();
+        let open = self.transactions.read().await.contains_key(&Some(session_id.to_string()));
+        if open {
+            if let Err(e) = self.rollback_session_transaction(Some(session_id)).await {
+                warn!("Rolling back the transaction of closed session {} did not succeed: {}", session_id, e);
+            }
+        }
+        closed
+    }
+
+    /// The session a statement runs in, counting the statement
+    async fn enter_session(&self, options: &QueryOptions) -> BlazeResult<Option<SessionInfo>> {
+        let Some(session_id) = &options.session_id else {
+            return Ok(None);
+        };
+        let mut sessions = self.sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| BlazeError::InvalidInput(format!("No open session '{}'", session_id)))?;
+        session.statements += 1;
+        session.last_used_at = self.clock.now();
+        Ok(Some(session.clone()))
+    }
+
+    /// Run a `SET` statement in a session
+    async fn set_session_variable(&self, session_id: &str, variable: &str, value: Option<&str>) -> BlazeResult<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| BlazeError::InvalidInput(format!("No open session '{}'", session_id)))?;
+        session.settings.set(variable, value)?;
+        debug!("Session {} set {} to {}", session_id, variable, value.unwrap_or("DEFAULT"));
+        Ok(())
+    }
+
+    /// Queries run with `execute_query` or `execute_prepared` that match
+    /// `filter`, most recent first; see `query_history`
+    pub fn get_query_history(&self, filter: &QueryHistoryFilter) -> Vec<QueryHistoryEntry> {
+        self.query_history.query(filter)
+    }
+
+    /// The result cache key of `sql` run in `time_zone` and the versions of
+    /// the tables it reads, or None if the cache is off or `sql` cannot be
+    /// cached
+    async fn result_cache_key(&self, sql: &str, time_zone: &str) -> Option<(String, TableVersions)> {
+        if self.config.read().await.result_cache_max_bytes == 0 || !plan_graph::is_query(sql) {
+            return None;
+        }
+        // Casts to zoned timestamps depend on the time zone, which sessions set
+        let key = format!("{} {}", time_zone, result_cache::cache_key(sql)?);
+        let references = self.get_referenced_tables(sql).await.ok()?;
+        let ctx = self.ctx.read().await;
+        let mut versions = Vec::with_capacity(references.reads.len());
+        for table in &references.reads {
+            let provider = ctx.table_provider(table.as_str()).await.ok()?;
+            versions.push(provider.as_any().downcast_ref::<VersionedTable>()?.version());
+        }
+        Some((key, versions))
+    }
+
+    /// Keep the result of a query for answering it again
+    async fn cache_result(&self, key: String, versions: TableVersions, batches: &[RecordBatch]) {
+        let (max_bytes, ttl_secs) = {
+            let config = self.config.read().await;
+            (config.result_cache_max_bytes, config.result_cache_ttl_secs)
+        };
+        let expires_at = result_cache::expiry(self.clock.now(), ttl_secs);
+        self.result_cache.write().await.put(key, versions, batches, expires_at, max_bytes);
+    }
+
+    /// Keep a query result as an anonymous table
+    async fn store_result_table(&self, batches: Vec<RecordBatch>, query_id: &str) -> BlazeResult<ResultTableInfo> {
+        let ttl_secs = self.config.read().await.result_table_ttl_secs;
+        let ctx = self.ctx.read().await;
+        let ttl = chrono::Duration::seconds(ttl_secs.min(i64::MAX as u64) as i64);
+        let info = self.result_tables.write().await.store(&ctx, batches, query_id, self.clock.now(), ttl)?;
+        debug!("Kept {} result rows as {} until {}", info.rows, info.reference, info.expires_at);
+        Ok(info)
+    }
+
+    /// Drop the result tables past their time to live
+    async fn expire_result_tables(&self) {
+        let ctx = self.ctx.read().await;
+        let expired = self.result_tables.write().await.expire(&ctx, self.clock.now());
+        if expired > 0 {
+            debug!("Dropped {} expired result tables", expired);
+        }
+    }
+
+    /// Limits and activity of every resource group, sorted by name
+    pub async fn list_resource_groups(&self) -> Vec<ResourceGroupStats> {
+        let groups = self.resource_groups.read().await;
+        let mut list: Vec<_> = groups.values().map(|g| g.stats()).collect();
+        list.sort_by(|a, b| a.group.name.cmp(&b.group.name));
+        list
+    }
+
+    async fn resource_group(&self, name: &str) -> BlazeResult<Arc<ResourceGroupState>> {
+        self.resource_groups
+            .read()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| BlazeError::InvalidInput(format!("Resource group '{}' not found", name)))
+    }
+
+    /// Read the current time for `CURRENT_TIMESTAMP`, `CURRENT_DATE`,
+    /// result table expiry and freshness checks from `clock`, e.g. a fixed
+    /// instant for reproducible tests. Applies from the next statement.
+    pub fn set_clock(&self, clock: Clock) {
+        self.clock.set(clock);
+        info!("Engine clock set to {:?}", clock);
+    }
+
+    /// The clock the engine reads the current time from
+    pub fn clock(&self) -> Clock {
+        self.clock.get()
+    }
+
+    /// Current engine configuration
+    pub async fn config(&self) -> EngineConfig {
+        self.config.read().await.clone()
+    }
+
+    /// Change settings on the running engine, keeping registered tables and
+    /// other state. Returns the resulting configuration.
+    pub async fn update_config(&self, update: EngineConfigUpdate) -> BlazeResult<EngineConfig> {
+        for (name, value) in [("cpu_cores", update.cpu_cores), ("batch_size", update.batch_size)] {
+            if value == Some(0) {
+                return Err(BlazeError::Config(format!("{} must be greater than 0", name)));
+            }
+        }
+        if update.result_cache_ttl_secs == Some(0) {
+            return Err(BlazeError::Config("result_cache_ttl_secs must be greater than 0".to_string()));
+        }
+        if let Some(zone) = &update.time_zone {
+            time_zone::validate_time_zone(zone)?;
+        }
+
+        // Statements take the session lock before the config lock
+        let ctx = self.ctx.read().await;
+        let mut config = self.config.write().await;
+        if let Some(limit) = update.memory_limit_bytes {
+            self.memory_pool.set_limit(limit);
+            config.memory_limit_bytes = limit;
+            for group in self.resource_groups.read().await.values() {
+                group.resize(limit);
+            }
+        }
+        if update.cpu_cores.is_some() || update.batch_size.is_some() {
+            let state = ctx.state_ref();
+            let mut state = state.write();
+            let execution = &mut state.config_mut().options_mut().execution;
+            if let Some(cpu_cores) = update.cpu_cores {
+                execution.target_partitions = cpu_cores;
+                config.cpu_cores = cpu_cores;
+            }
+            if let Some(batch_size) = update.batch_size {
+                execution.batch_size = batch_size;
+                config.batch_size = batch_size;
+            }
+        }
+        if let Some(max_result_rows) = update.max_result_rows {
+            config.max_result_rows = max_result_rows;
+        }
+        if let Some(query_timeout_ms) = update.query_timeout_ms {
+            config.query_timeout_ms = query_timeout_ms;
+        }
+        if let Some(zone) = update.time_zone {
+            time_zone::apply(&ctx, &zone, &self.clock);
+            config.time_zone = zone;
+        }
+        if update.decimal_rules.is_some() || update.decimal_overflow.is_some() {
+            config.decimal_rules = update.decimal_rules.unwrap_or(config.decimal_rules);
+            config.decimal_overflow = update.decimal_overflow.unwrap_or(config.decimal_overflow);
+            decimal::apply(&ctx, config.decimal_rules, config.decimal_overflow);
+        }
+        if let Some(reorder_joins) = update.reorder_joins {
+            join_order::apply(&ctx, reorder_joins);
+            config.reorder_joins = reorder_joins;
+        }
+        if let Some(max_bytes) = update.result_cache_max_bytes {
+            config.result_cache_max_bytes = max_bytes;
+        }
+        if let Some(ttl_secs) = update.result_cache_ttl_secs {
+            config.result_cache_ttl_secs = ttl_secs;
+        }
+        if let Some(plan_cache_size) = update.plan_cache_size {
+            config.plan_cache_size = plan_cache_size;
+        }
+        if update.max_concurrent_queries.is_some() || update.max_queued_queries.is_some() {
+            config.max_concurrent_queries = update.max_concurrent_queries.unwrap_or(config.max_concurrent_queries);
+            config.max_queued_queries = update.max_queued_queries.unwrap_or(config.max_queued_queries);
+            self.admission.set_limits(config.max_concurrent_queries, config.max_queued_queries);
+        }
+        if let Some(queue_timeout_ms) = update.queue_timeout_ms {
+            config.queue_timeout_ms = queue_timeout_ms;
+        }
+        if let Some(slots) = update.slots {
+            self.slots.set_total(slots);
+            config.slots = slots;
+        }
+        if let Some(query_history_size) = update.query_history_size {
+            self.query_history.set_max_entries(query_history_size);
+            config.query_history_size = query_history_size;
+        }
+        // Cached results and plans may depend on the settings changed
+        self.result_cache.write().await.clear();
+        self.plan_cache.write().await.clear();
+
+        info!(
+            "Updated engine configuration: {} CPU cores, {}MB memory limit, batch size {}, max result rows {}, time zone {}, decimals {}/{}",
+            config.cpu_cores,
+            self.memory_pool.limit() / 1024 / 1024,
+            config.batch_size,
+            config.max_result_rows,
+            config.time_zone,
+            config.decimal_rules,
+            config.decimal_overflow
+        );
+        Ok(config.clone())
+    }
+
+    /// Bytes currently reserved from the engine's memory pool
+    pub fn reserved_memory_bytes(&self) -> usize {
+        self.memory_pool.reserved()
+    }
+
+    /// Number of tables registered across all catalogs and schemas,
+    /// including snapshot and other internal tables
+    pub async fn catalog_size(&self) -> usize {
+        let ctx = self.ctx.read().await;
+        let state = ctx.state();
+        let catalogs = state.catalog_list();
+        catalogs
+            .catalog_names()
+            .iter()
+            .filter_map(|name| catalogs.catalog(name))
+            .flat_map(|catalog| {
+                catalog
+                    .schema_names()
+                    .into_iter()
+                    .filter_map(move |name| catalog.schema(&name))
+            })
+            .map(|schema| schema.table_names().len())
+            .sum()
+    }
+
+    /// Save the engine's tables, views, indexes, models, resource groups and
+    /// statistics to `dir`, which must be new, empty or hold an earlier
+    /// state. See `engine_state` for what is stored and how.
+    pub async fn snapshot_to(&self, dir: impl AsRef<Path>) -> BlazeResult<EngineStateInfo> {
+        let dir = dir.as_ref();
+        engine_state::prepare_dir(dir)?;
+
+        let materialized_views = self.list_materialized_views().await;
+        let file_tables = self.file_tables.read().await.clone();
+        let flight_tables = self.flight_tables.read().await.clone();
+        let orc_tables = self.orc_tables.read().await.clone();
+        let arrow_ipc_tables = self.arrow_ipc_tables.read().await.clone();
+        #[cfg(feature = "delta")]
+        let delta_tables = self.delta_tables.read().await.clone();
+        #[cfg(feature = "iceberg")]
+        let iceberg_tables = self.iceberg_tables.read().await.clone();
+        let mut manifest = StateManifest {
+            format_version: engine_state::STATE_FORMAT_VERSION,
+            saved_at: self.clock.now(),
+            tables: Vec::new(),
+            views: Vec::new(),
+            materialized_views: materialized_views
+                .iter()
+                .map(|view| SavedView { name: view.name.clone(), sql: view.sql.clone() })
+                .collect(),
+            file_tables: Vec::new(),
+            flight_tables: Vec::new(),
+            search_indexes: self.list_search_indexes().await,
+            time_partitioning: self.list_time_partitioning().await,
+            vector_indexes: self.vector_indexes.read().await.values().map(|index| index.definition().clone()).collect(),
+            models: self.list_models().await,
+            resource_groups: self.list_resource_groups().await.into_iter().map(|stats| stats.group).collect(),
+            attached_databases: Vec::new(),
+            sqlite_databases: Vec::new(),
+            orc_tables: Vec::new(),
+            arrow_ipc_tables: Vec::new(),
+            delta_tables: Vec::new(),
+            iceberg_tables: Vec::new(),
+            stats: self.get_stats().await,
+            query_stats: self.query_stats().await,
+            label_stats: self.label_stats.read().await.values().cloned().collect(),
+        };
+        #[cfg(feature = "duckdb")]
+        {
+            manifest.attached_databases = self
+                .list_attached_databases()
+                .await
+                .into_iter()
+                .map(|database| SavedAttachment { alias: database.alias, path: database.path })
+                .collect();
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            manifest.sqlite_databases = self
+                .list_sqlite_databases()
+                .await
+                .into_iter()
+                .map(|database| SavedSqliteDatabase { dataset: database.dataset, path: database.path })
+                .collect();
+        }
+
+        let ctx = self.ctx.read().await;
+        let mut names = Self::list_tables_in(&ctx)?;
+        names.sort();
+        for name in names {
+            if materialized_views.iter().any(|view| view.name == name) {
+                continue;
+            }
+            if let Some(info) = file_tables.get(&name) {
+                manifest.file_tables.push(SavedFileTable {
+                    name,
+                    pattern: info.pattern.clone(),
+                    format: info.format,
+                    csv_options: info.csv_options.clone(),
+                    json_options: info.json_options.clone(),
+                    listing: info.listing,
+                });
+                continue;
+            }
+            if let Some(info) = flight_tables.get(&name) {
+                manifest.flight_tables.push(SavedFlightTable {
+                    name,
+                    endpoint: info.endpoint.clone(),
+                    source: info.source.clone(),
+                });
+                continue;
+            }
+            if let Some(info) = orc_tables.get(&name) {
+                manifest.orc_tables.push(SavedOrcTable { name, path: info.path.clone() });
+                continue;
+            }
+            if let Some(info) = arrow_ipc_tables.get(&name) {
+                manifest.arrow_ipc_tables.push(SavedArrowIpcTable { name, path: info.path.clone() });
+                continue;
+            }
+            #[cfg(feature = "delta")]
+            if let Some(info) = delta_tables.get(&name) {
+                manifest.delta_tables.push(SavedDeltaTable { name, path: info.path.clone(), version: info.pinned_version });
+                continue;
+            }
+            #[cfg(feature = "iceberg")]
+            if let Some(registered) = iceberg_tables.get(&name) {
+                let info = &registered.info;
+                let snapshot_id = info.snapshot_id.filter(|_| info.pinned);
+                manifest.iceberg_tables.push(SavedIcebergTable { name, metadata_path: info.metadata_path.clone(), snapshot_id });
+                continue;
+            }
+            // The connection string may hold a password, which is not
+            // written to disk
+            if self.is_postgres_table(&name).await {
+                warn!("PostgreSQL table '{}' is not saved; register it again after restoring", name);
+                continue;
+            }
+            let provider = ctx.table_provider(name.as_str()).await?;
+            if let Some(view) = provider.as_any().downcast_ref::<ViewTable>() {
+                match view.definition() {
+                    Some(sql) => manifest.views.push(SavedView { name, sql: sql.clone() }),
+                    None => warn!("View '{}' has no SQL definition and is not saved", name),
+                }
+                continue;
+            }
+            let batches = ctx.table(name.as_str()).await?.collect().await?;
+            let saved = engine_state::write_table(dir, manifest.tables.len(), &name, provider.schema(), &batches)?;
+            manifest.tables.push(saved);
+        }
+        drop(ctx);
+
+        engine_state::write_manifest(dir, &manifest)?;
+        let info = manifest.info(dir);
+        info!(
+            "Saved engine state to '{}': {} tables with {} rows, {} views, {} indexes, {} models",
+            dir.display(), info.tables, info.total_rows, info.views, info.indexes, info.models
+        );
+        Ok(info)
+    }
+
+    /// Load a state saved with `snapshot_to` into this engine, which must not
+    /// have any tables yet. Statistics are replaced by the saved ones.
+    pub async fn restore_from(&self, dir: impl AsRef<Path>) -> BlazeResult<EngineStateInfo> {
+        let dir = dir.as_ref();
+        let manifest = engine_state::read_manifest(dir)?;
+        let existing = self.list_tables().await?;
+        if !existing.is_empty() {
+            return Err(BlazeError::InvalidInput(format!(
+                "Engine state can only be restored into an engine without tables; this one has {}",
+                existing.join(", ")
+            )));
+        }
+
+        for group in &manifest.resource_groups {
+            self.create_resource_group(group.clone()).await?;
+        }
+        // Designated before the tables are registered, which splits them
+        self.time_partitioning.write().await.extend(
+            manifest.time_partitioning.iter().map(|p| (p.table_name.clone(), p.column.clone())),
+        );
+        for table in &manifest.tables {
+            self.register_table(&table.name, engine_state::read_table(dir, table)?).await?;
+        }
+        for table in &manifest.file_tables {
+            self.register_file_table(
+                &table.name,
+                &table.pattern,
+                Some(table.format),
+                table.csv_options.as_ref(),
+                table.json_options.as_ref(),
+                table.listing,
+            )
+            .await?;
+        }
+        for table in &manifest.flight_tables {
+            self.register_flight(&table.name, &table.endpoint, table.source.clone()).await?;
+        }
+        for table in &manifest.orc_tables {
+            self.register_orc(&table.name, &table.path).await?;
+        }
+        for table in &manifest.arrow_ipc_tables {
+            self.register_arrow_ipc(&table.name, &table.path).await?;
+        }
+        #[cfg(feature = "delta")]
+        for table in &manifest.delta_tables {
+            self.register_delta(&table.name, &table.path, table.version).await?;
+        }
+        #[cfg(not(feature = "delta"))]
+        if let Some(table) = manifest.delta_tables.first() {
+            return Err(BlazeError::Config(format!(
+                "Engine state registers Delta table '{}', which needs the `delta` feature",
+                table.name
+            )));
+        }
+        #[cfg(feature = "iceberg")]
+        for table in &manifest.iceberg_tables {
+            let version = table.snapshot_id.map(IcebergVersion::SnapshotId);
+            self.register_iceberg(&table.name, &table.metadata_path, version).await?;
+        }
+        #[cfg(not(feature = "iceberg"))]
+        if let Some(table) = manifest.iceberg_tables.first() {
+            return Err(BlazeError::Config(format!(
+                "Engine state registers Iceberg table '{}', which needs the `iceberg` feature",
+                table.name
+            )));
+        }
+        #[cfg(feature = "duckdb")]
+        for database in &manifest.attached_databases {
+            self.attach_duckdb(&database.path, &database.alias).await?;
+        }
+        #[cfg(not(feature = "duckdb"))]
+        if let Some(database) = manifest.attached_databases.first() {
+            return Err(BlazeError::Config(format!(
+                "Engine state attaches DuckDB database '{}', which needs the `duckdb` feature",
+                database.alias
+            )));
+        }
+        #[cfg(feature = "sqlite")]
+        for database in &manifest.sqlite_databases {
+            self.attach_sqlite(&database.path, &database.dataset).await?;
+        }
+        #[cfg(not(feature = "sqlite"))]
+        if let Some(database) = manifest.sqlite_databases.first() {
+            return Err(BlazeError::Config(format!(
+                "Engine state attaches SQLite database '{}', which needs the `sqlite` feature",
+                database.dataset
+            )));
+        }
+
+        // Views may read other views, so each pass creates those whose
+        // inputs exist until no more can be created
+        let mut pending: Vec<&SavedView> = manifest.views.iter().collect();
+        while !pending.is_empty() {
+            let mut failed = Vec::new();
+            let mut last_error = None;
+            for view in &pending {
+                if let Err(e) = Box::pin(self.execute_query(&view.sql)).await {
+                    failed.push(*view);
+                    last_error = Some(e);
+                }
+            }
+            if failed.len() == pending.len() {
+                let error = last_error.expect("every pending view failed");
+                return Err(BlazeError::InvalidInput(format!("Cannot restore view '{}': {}", failed[0].name, error)));
+            }
+            pending = failed;
+        }
+        for view in &manifest.materialized_views {
+            self.create_materialized_view(&view.name, &view.sql).await?;
+        }
+
+        for index in &manifest.search_indexes {
+            self.create_search_index(&index.name, &index.table_name, Some(index.columns.clone())).await?;
+        }
+        for index in &manifest.vector_indexes {
+            self.create_vector_index(index.clone()).await?;
+        }
+        {
+            let ctx = self.ctx.read().await;
+            let mut models = self.models.write().await;
+            for model in &manifest.models {
+                ctx.register_udf(model.to_udf());
+                models.insert(model.name.to_lowercase(), model.clone());
+            }
+        }
+
+        {
+            let mut stats = self.stats.write().await;
+            let registered_tables = stats.registered_tables;
+            *stats = EngineStats { registered_tables, ..manifest.stats.clone() };
+        }
+        *self.query_stats.write().await = manifest
+            .query_stats
+            .iter()
+            .map(|entry| (entry.fingerprint.clone(), entry.clone()))
+            .collect();
+        *self.label_stats.write().await = manifest
+            .label_stats
+            .iter()
+            .map(|entry| ((entry.label.clone(), entry.value.clone()), entry.clone()))
+            .collect();
+
+        let info = manifest.info(dir);
+        info!(
+            "Restored engine state from '{}' saved at {}: {} tables with {} rows, {} views",
+            dir.display(), info.saved_at, info.tables, info.total_rows, info.views
+        );
+        Ok(info)
+    }
+
+    /// Queries, including open result streams, running now, oldest first
+    pub async fn running_queries(&self) -> Vec<RunningQueryInfo> {
+        self.queries.running()
+    }
+
+    /// Cancel the running query with `query_id`. It stops at its next await
+    /// point and fails with `BlazeError::Cancelled`; a cancelled stream
+    /// ends with that error.
+    pub async fn cancel_query(&self, query_id: &str) -> BlazeResult<()> {
+        if !self.queries.cancel(query_id) {
+            return Err(BlazeError::InvalidInput(format!("No running query with ID '{}'", query_id)));
+        }
+        info!("Cancelled query {}", query_id);
+        Ok(())
+    }
+
+    /// Shut the engine down: stop accepting queries, give running ones up to
+    /// `drain_timeout_ms` to finish, cancel the rest, and save the engine's
+    /// state when `state_dir` is set. See `shutdown` for the details.
+    pub async fn shutdown(&self, options: &ShutdownOptions) -> BlazeResult<ShutdownReport> {
+        let start_time = Instant::now();
+        let running = self.queries.stop_accepting()?;
+        info!("Shutting down, waiting up to {}ms for {} running queries", options.drain_timeout_ms, running);
+
+        let interrupted = if self.queries.drain(std::time::Duration::from_millis(options.drain_timeout_ms)).await {
+            Vec::new()
+        } else {
+            self.queries.cancel_all().await
+        };
+        for query in &interrupted {
+            warn!("Cancelled query {} after {}ms: {}", query.fingerprint, query.running_ms, query.sql);
+        }
+
+        let state = match &options.state_dir {
+            Some(dir) => Some(self.snapshot_to(dir).await?),
+            None => None,
+        };
+
+        let report = ShutdownReport {
+            drained_queries: running.saturating_sub(interrupted.len()),
+            interrupted,
+            state,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+        };
+        info!(
+            "Shut down in {}ms: {} queries drained, {} cancelled",
+            report.duration_ms, report.drained_queries, report.interrupted.len()
+        );
+        Ok(report)
+    }
+
+    /// Fully-qualified tables and views `sql` reads and, for DML and DDL,
+    /// writes, without running it
+    pub async fn get_referenced_tables(&self, sql: &str) -> BlazeResult<TableReferences> {
+        let rewritten;
+        let sql = if ml::contains_predict(sql) {
+            let models = self.models.read().await;
+            rewritten = ml::rewrite_predict(sql, |name| models.get(&name.to_lowercase()).cloned())?;
+            rewritten.as_str()
+        } else {
+            sql
+        };
+
+        let state = self.ctx.read().await.state();
+        let mut collector = ReferenceCollector::new(&state);
+        collector.add_sql(sql)?;
+        Ok(collector.finish())
+    }
+
+    /// Validate SQL query syntax without execution
+    pub async fn validate_query(&self, sql: &str) -> BlazeResult<bool> {
+        let ctx = self.ctx.read().await;
+        match self.plan_sql(&ctx, sql).await {
+            Ok(_) => Ok(true),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// The optimized physical plan of a query, with the planner's row and
+    /// size estimates per operator, as a tree and as Graphviz DOT. The query
+    /// is planned but not run.
+    pub async fn explain_graph(&self, sql: &str) -> BlazeResult<PlanGraph> {
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput(
+                "explain_graph only describes queries (SELECT, WITH or VALUES)".to_string(),
+            ));
+        }
+        let ctx = self.ctx.read().await;
+        let plan = self.plan_sql(&ctx, sql).await?.create_physical_plan().await?;
+        let mut graph = PlanGraph::from_plan(&plan)?;
+        graph.hints = query_hints::parse_hints(sql)?.iter().map(ToString::to_string).collect();
+        Ok(graph)
+    }
+
+    /// A query's physical plan as a tree, like `explain_graph`. With
+    /// `analyze` the query is run first, like `EXPLAIN ANALYZE`, and every
+    /// operator carries the rows it produced, its compute time and memory.
+    pub async fn explain(&self, sql: &str, analyze: bool) -> BlazeResult<PlanGraph> {
+        if !analyze {
+            return self.explain_graph(sql).await;
+        }
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput(
+                "explain only describes queries (SELECT, WITH or VALUES)".to_string(),
+            ));
+        }
+        self.queries
+            .run(sql, async {
+                let ctx = self.ctx.read().await;
+                let plan = self.plan_sql(&ctx, sql).await?.create_physical_plan().await?;
+                let start_time = Instant::now();
+                datafusion::physical_plan::collect(plan.clone(), ctx.task_ctx()).await?;
+                let mut graph = PlanGraph::from_executed_plan(&plan, start_time.elapsed().as_millis() as u64)?;
+                graph.hints = query_hints::parse_hints(sql)?.iter().map(ToString::to_string).collect();
+                Ok(graph)
+            })
+            .await
+    }
+
+    /// Estimate the rows a query and each stage of its plan produce, without
+    /// running it, so huge queries can be sent elsewhere before they start.
+    /// See `cardinality` for where the estimates come from.
+    pub async fn estimate_cardinality(&self, sql: &str) -> BlazeResult<CardinalityEstimate> {
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput(
+                "estimate_cardinality only estimates queries (SELECT, WITH or VALUES)".to_string(),
+            ));
+        }
+        Ok(CardinalityEstimate::from_graph(&self.explain_graph(sql).await?))
+    }
+
+    /// Plan a query without running it and report the bytes it would
+    /// process, the tables and columns it reads and its optimized plan, like
+    /// BigQuery's dry runs. See `dry_run` for how bytes are estimated.
+    pub async fn dry_run(&self, sql: &str) -> BlazeResult<DryRunReport> {
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput(
+                "dry_run only plans queries (SELECT, WITH or VALUES)".to_string(),
+            ));
+        }
+        let ctx = self.ctx.read().await;
+        let df = self.plan_sql(&ctx, sql).await?;
+        let optimized = df.clone().into_optimized_plan()?;
+        let (total_bytes_processed, bytes_exact) = dry_run::scanned_bytes(&df.create_physical_plan().await?)?;
+        let tables = dry_run::scanned_tables(&optimized)?;
+        let optimized_plan = optimized.display_indent().to_string();
+        Ok(DryRunReport { total_bytes_processed, bytes_exact, tables, optimized_plan })
+    }
+
+    /// Count the rows of a table and the distinct and null values of each
+    /// column, for join reordering. Same as `ANALYZE TABLE name`.
+    pub async fn analyze_table(&self, name: &str) -> BlazeResult<TableStatistics> {
+        let table = {
+            let ctx = self.ctx.read().await;
+            ctx.table(name).await?
+        };
+        let statistics = join_order::analyze(name, table).await?;
+        info!("Analyzed table '{}': {} rows", name, statistics.row_count);
+        self.statistics.insert(statistics.clone());
+        // Join orders in cached plans were chosen without these statistics
+        self.plan_cache.write().await.clear();
+        Ok(statistics)
+    }
+
+    /// Statistics from the last time a table was analyzed
+    pub async fn table_statistics(&self, name: &str) -> Option<TableStatistics> {
+        self.statistics.get(name)
+    }
+
+    /// Run a query's joins one by one and show the order the optimizer
+    /// chose, with estimated and actual rows per join
+    pub async fn join_diagnostics(&self, sql: &str) -> BlazeResult<JoinDiagnostics> {
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput(
+                "join_diagnostics only describes queries (SELECT, WITH or VALUES)".to_string(),
+            ));
+        }
+        let reorder_joins = self.config.read().await.reorder_joins;
+        let ctx = self.ctx.read().await;
+        let (state, plan) = self.plan_sql(&ctx, sql).await?.into_parts();
+        let plan = state.optimize(&plan)?;
+        Ok(join_order::diagnose(state, &plan, &self.statistics, reorder_joins).await?)
+    }
+
+    /// A query's optimized logical plan, normalized for comparison with a
+    /// `PlanBaseline`. The query is planned but not run.
+    pub async fn normalized_plan(&self, sql: &str) -> BlazeResult<String> {
+        if !plan_graph::is_query(sql) {
+            return Err(BlazeError::InvalidInput(
+                "normalized_plan only describes queries (SELECT, WITH or VALUES)".to_string(),
+            ));
+        }
+        let ctx = self.ctx.read().await;
+        let (state, plan) = self.plan_sql(&ctx, sql).await?.into_parts();
+        let plan = state.optimize(&plan)?.display_indent().to_string();
+        Ok(plan_regressions::normalize_plan(&plan))
+    }
+
+    /// Record the plans of a workload of named queries as a baseline to
+    /// check later upgrades against
+    pub async fn record_plan_baseline(&self, queries: &BTreeMap<String, String>) -> BlazeResult<PlanBaseline> {
+        let mut baseline = PlanBaseline {
+            engine_version: plan_regressions::ENGINE_VERSION.to_string(),
+            datafusion_version: datafusion::DATAFUSION_VERSION.to_string(),
+            recorded_at: self.clock.now(),
+            queries: BTreeMap::new(),
+        };
+        for (name, sql) in queries {
+            let plan = self.normalized_plan(sql).await.map_err(|e| {
+                BlazeError::InvalidInput(format!("Cannot record the plan of query '{}': {}", name, e))
+            })?;
+            baseline.queries.insert(name.clone(), BaselineQuery { sql: sql.clone(), plan });
+        }
+        info!("Recorded plan baseline of {} queries", baseline.queries.len());
+        Ok(baseline)
+    }
+
+    /// Plan the queries of a baseline again and report the ones whose plan
+    /// changed, flagging likely regressions
+    pub async fn check_plan_baseline(&self, baseline: &PlanBaseline) -> BlazeResult<PlanRegressionReport> {
+        let mut report = PlanRegressionReport {
+            baseline_engine_version: baseline.engine_version.clone(),
+            baseline_datafusion_version: baseline.datafusion_version.clone(),
+            engine_version: plan_regressions::ENGINE_VERSION.to_string(),
+            datafusion_version: datafusion::DATAFUSION_VERSION.to_string(),
+            unchanged: 0,
+            changes: Vec::new(),
+        };
+        for (name, query) in &baseline.queries {
+            let current = self.normalized_plan(&query.sql).await.map_err(|e| e.to_string());
+            match plan_regressions::compare(name, &query.plan, current) {
+                Some(change) => {
+                    warn!("Plan of query '{}' changed ({:?})", name, change.regressions);
+                    report.changes.push(change);
+                }
+                None => report.unchanged += 1,
+            }
+        }
+        Ok(report)
+    }
+
+    /// List the snapshots recorded for a table, oldest first
+    pub async fn list_snapshots(&self, table_name: &str) -> BlazeResult<Vec<SnapshotInfo>> {
+        self.snapshots.read().await.list(table_name)
+    }
+
+    /// Drop snapshots committed before `older_than`, for one table or all of
+    /// them. The current version of each table is always retained.
+    pub async fn prune_snapshots(&self, table_name: Option<&str>, older_than: DateTime<Utc>) -> BlazeResult<usize> {
+        let removed = self.snapshots.write().await.prune(table_name, older_than);
+
+        let ctx = self.ctx.read().await;
+        snapshots::deregister_snapshot_tables(&ctx, &removed);
+
+        info!("Pruned {} snapshots", removed.len());
+        Ok(removed.len())
+    }
+
+    /// Change events after `since_sequence`, oldest first, optionally for one
+    /// table. Only the most recent `change_feed_retention` events are kept.
+    pub async fn get_changes(&self, table_name: Option<&str>, since_sequence: u64) -> Vec<Arc<ChangeEvent>> {
+        self.change_feed.read().await.changes_since(table_name, since_sequence)
+    }
+
+    /// Sequence number of the most recent change event (0 if none), so a
+    /// consumer can start polling from "now"
+    pub async fn latest_change_sequence(&self) -> u64 {
+        self.change_feed.read().await.latest_sequence()
+    }
+
+    /// Subscribe to live change events, optionally for one table
+    pub async fn subscribe_changes(&self, table_name: Option<&str>) -> ChangeSubscription {
+        self.change_feed.read().await.subscribe(table_name)
+    }
+
+    /// Register (or replace) an in-memory table and snapshot it. Returns
+    /// whether an existing table was replaced.
+    async fn store_table(
+        &self,
+        ctx: &SessionContext,
+        name: &str,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        committed_at: DateTime<Utc>,
+        session_id: Option<&str>,
+    ) -> BlazeResult<bool> {
+        self.save_for_rollback(ctx, name, session_id).await?;
+        let bytes = table_eviction::batches_bytes(&batches);
+        self.make_room(ctx, name, bytes).await?;
+
+        let snapshot_batches = self.config.read().await.enable_snapshots.then(|| batches.clone());
+        self.rebuild_vector_indexes(name, &batches).await;
+        let table = self.table_provider(name, schema.clone(), batches).await?;
+
+        let replaced = Self::commit_table(ctx, name, table).await?;
+        self.table_eviction.write().await.record(name, bytes);
+
+        if let Some(batches) = snapshot_batches {
+            let (_, evicted) = self.snapshots.write().await.record(name, schema, batches, committed_at);
+            let evicted: Vec<_> = evicted.into_iter().map(|id| (name.to_string(), id)).collect();
+            snapshots::deregister_snapshot_tables(ctx, &evicted);
+        }
+
+        Ok(replaced)
+    }
+
+    /// Save the rows of `name` from before the open transaction of session
+    /// `session_id`, if any, the first time the transaction writes it
+    async fn save_for_rollback(&self, ctx: &SessionContext, name: &str, session_id: Option<&str>) -> BlazeResult<()> {
+        let mut transactions = self.transactions.write().await;
+        let key = session_id.map(str::to_string);
+        // Rolling back restores the saved rows, which would drop writes made
+        // outside the transaction in the meantime
+        if transactions.iter().any(|(other, t)| *other != key && t.has_saved(name)) {
+            return Err(BlazeError::InvalidInput(format!(
+                "Table '{}' is being written in another transaction", name
+            )));
+        }
+        let Some(transaction) = transactions.get_mut(&key).filter(|t| !t.has_saved(name)) else {
+            return Ok(());
+        };
+        let saved = match ctx.table_provider(name).await {
+            Ok(provider) => {
+                let provider = table_versions::latest(provider);
+                if !is_in_memory(provider.as_ref()) && !self.table_eviction.read().await.is_spilled(name) {
+                    return Err(BlazeError::InvalidInput(format!(
+                        "Only in-memory tables can be written in a transaction; '{}' is not one", name
+                    )));
+                }
+                let schema = provider.schema();
+                Some(SavedTable { schema, batches: ctx.read_table(provider)?.collect().await? })
+            }
+            Err(_) => None,
+        };
+        transaction.save(name, saved);
+        Ok(())
+    }
+
+    /// Roll back the open transaction of session `session_id` after `sql`
+    /// failed in it. Other sessions' transactions are left alone.
+    async fn abort_transaction(&self, sql: &str, session_id: Option<&str>) {
+        if transactions::parse_transaction_control(sql).is_some()
+            || !self.transactions.read().await.contains_key(&session_id.map(str::to_string))
+        {
+            return;
+        }
+        match self.rollback_session_transaction(session_id).await {
+            Ok(info) => warn!("Rolled back transaction writing {} tables after a statement failed", info.tables.len()),
+            Err(e) => warn!("Rolling back transaction after a statement failed did not succeed: {}", e),
+        }
+    }
+
+    /// Make `table` the latest version of in-memory table `name`. A table
+    /// with another schema or of another kind is replaced by a new
+    /// versioned table. Returns whether a table was replaced.
+    async fn commit_table(ctx: &SessionContext, name: &str, table: Arc<dyn TableProvider>) -> BlazeResult<bool> {
+        if let Ok(existing) = ctx.table_provider(name).await {
+            if let Some(versioned) = existing.as_any().downcast_ref::<VersionedTable>() {
+                if versioned.schema() == table.schema() {
+                    let version = versioned.commit(table);
+                    debug!("Committed version {} of table '{}'", version, name);
+                    return Ok(true);
+                }
+            }
+        }
+        let replaced = ctx.deregister_table(name)?.is_some();
+        ctx.register_table(name, Arc::new(VersionedTable::new(table)))?;
+        Ok(replaced)
+    }
+
+    /// Evict least-recently-used tables until `bytes` more for table `name`
+    /// fit within the tables' share of the memory limit, if eviction is on
+    async fn make_room(&self, ctx: &SessionContext, name: &str, bytes: usize) -> BlazeResult<()> {
+        let (percent, spill_dir) = {
+            let config = self.config.read().await;
+            if !config.evict_tables {
+                return Ok(());
+            }
+            (config.table_memory_percent, config.table_spill_dir.clone())
+        };
+        let budget = (self.memory_pool.limit() as u128 * percent as u128 / 100) as usize;
+
+        // Tables dropped or replaced by other kinds of tables since they
+        // were stored hold no memory any more
+        let mut in_memory = HashSet::new();
+        for table in self.table_eviction.read().await.names() {
+            if let Ok(provider) = ctx.table_provider(table.as_str()).await {
+                if is_in_memory(table_versions::latest(provider).as_ref()) {
+                    in_memory.insert(table);
+                }
+            }
+        }
+        self.table_eviction.write().await.retain(|table| in_memory.contains(table));
+
+        loop {
+            let used = self.table_eviction.read().await.bytes_except(name) + self.memory_pool.reserved();
+            if used + bytes <= budget {
+                return Ok(());
+            }
+            let Some(victim) = self.table_eviction.read().await.victim(name) else {
+                return Err(BlazeError::Memory(format!(
+                    "Table '{}' needs {} but {} of the {} available to tables are in use and no table can be evicted",
+                    name, format_bytes(bytes as u64), format_bytes(used as u64), format_bytes(budget as u64)
+                )));
+            };
+            self.evict_table(ctx, &victim, name, spill_dir.as_deref()).await?;
+        }
+    }
+
+    /// Spill a table to `spill_dir` and read it from there, or drop it
+    async fn evict_table(&self, ctx: &SessionContext, name: &str, evicted_for: &str, spill_dir: Option<&Path>) -> BlazeResult<()> {
+        let spilled = match spill_dir {
+            Some(dir) => {
+                let schema = ctx.table_provider(name).await?.schema();
+                let batches = ctx.table(name).await?.collect().await?;
+                let (dir, file_schema, table_name) = (dir.to_path_buf(), schema.clone(), name.to_string());
+                let path = tokio::task::spawn_blocking(move || {
+                    table_eviction::write_spill_file(&dir, &table_name, &file_schema, &batches)
+                })
+                .await
+                .map_err(|e| BlazeError::Internal(format!("Spilling table '{}' failed: {}", name, e)))??;
+                Some((path, schema))
+            }
+            None => None,
+        };
+
+        match &spilled {
+            Some((path, schema)) => {
+                Self::commit_table(ctx, name, table_eviction::spilled_table(path, schema.clone())?).await?;
+            }
+            None => {
+                ctx.deregister_table(name)?;
+            }
+        }
+        let event = self.table_eviction.write().await.evicted(name, spilled.map(|(path, _)| path), evicted_for);
+        {
+            let mut stats = self.stats.write().await;
+            stats.evicted_tables += 1;
+            stats.evicted_bytes += event.bytes;
+        }
+
+        match &event.spill_path {
+            Some(path) => warn!("Evicted table '{}' ({}) to {} for '{}'", name, format_bytes(event.bytes), path, evicted_for),
+            None => warn!("Dropped table '{}' ({}) to make room for '{}'", name, format_bytes(event.bytes), evicted_for),
+        }
+        Ok(())
+    }
+
+    /// Mark the in-memory tables a statement reads as just used
+    async fn touch_tables(&self, sql: &str) {
+        let Ok(references) = self.get_referenced_tables(sql).await else {
+            return;
+        };
+        let state = self.ctx.read().await.state();
+        let catalog = &state.config_options().catalog;
+        let mut eviction = self.table_eviction.write().await;
+        for name in eviction.names() {
+            let resolved = TableReference::from(name.as_str())
+                .resolve(&catalog.default_catalog, &catalog.default_schema)
+                .to_string();
+            if references.reads.contains(&resolved) {
+                eviction.touch(&name);
+            }
+        }
+    }
+
+    /// Rebuild the vector indexes of a table whose contents changed
+    async fn rebuild_vector_indexes(&self, table: &str, batches: &[RecordBatch]) {
+        let mut indexes = self.vector_indexes.write().await;
+        for index in indexes.values_mut().filter(|i| i.table_name() == table) {
+            index.rebuild(batches);
+            if let Some(error) = index.info().error {
+                warn!("Vector index '{}' unavailable, searches will scan '{}': {}", index.name(), table, error);
+            }
+        }
+    }
+
+    /// In-memory provider for a table, with inverted indexes if the table has
+    /// a search index and split by day if it is partitioned by time
+    async fn table_provider(
+        &self,
+        name: &str,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+    ) -> BlazeResult<Arc<dyn TableProvider>> {
+        if let Some(column) = self.time_partitioning.read().await.get(name) {
+            return Ok(Arc::new(TimePartitionedTable::try_new(schema, batches, column)?));
+        }
+        match self.search_indexes.read().await.get(name) {
+            Some(index) => Ok(Arc::new(SearchIndexedTable::try_new(schema, batches, &index.columns)?)),
+            None => Ok(Arc::new(MemTable::try_new(schema, vec![batches])?)),
+        }
+    }
+
+    /// Current contents of a table that is about to be replaced, if change
+    /// capture is enabled and the table exists
+    async fn batches_for_change_feed(&self, ctx: &SessionContext, name: &str) -> BlazeResult<Option<Vec<RecordBatch>>> {
+        if !self.change_feed.read().await.is_enabled() || !ctx.table_exist(name)? {
+            return Ok(None);
+        }
+        Ok(Some(ctx.table(name).await?.collect().await?))
+    }
+
+    /// Record a committed mutation in the change feed
+    async fn publish_changes(
+        &self,
+        table: &str,
+        committed_at: DateTime<Utc>,
+        deleted: Option<Vec<RecordBatch>>,
+        inserted: Option<Vec<RecordBatch>>,
+        session_id: Option<&str>,
+    ) {
+        if let Some(transaction) = self.transactions.write().await.get_mut(&session_id.map(str::to_string)) {
+            let changes = [(ChangeType::Delete, deleted), (ChangeType::Insert, inserted)];
+            for (change_type, batches) in changes {
+                if let Some(batches) = batches {
+                    transaction.hold(HeldChange { table_name: table.to_string(), change_type, committed_at, batches });
+                }
+            }
+            return;
+        }
+        let mut feed = self.change_feed.write().await;
+        if let Some(batches) = deleted {
+            feed.publish(table, ChangeType::Delete, committed_at, batches);
+        }
+        if let Some(batches) = inserted {
+            feed.publish(table, ChangeType::Insert, committed_at, batches);
+        }
+    }
+
+    /// Append rows produced by an INSERT statement, aligning them with the
+    /// table schema first. Returns the number of rows inserted.
+    async fn insert_rows(&self, table: &str, batches: Vec<RecordBatch>, session_id: Option<&str>) -> BlazeResult<u64> {
+        let schema = {
+            let ctx = self.ctx.read().await;
+            ctx.table_provider(table).await?.schema()
+        };
+
+        let batches = batches
+            .into_iter()
+            .map(|batch| RecordBatch::try_new(schema.clone(), batch.columns().to_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inserted: usize = batches.iter().map(|b| b.num_rows()).sum();
+
+        self.append_in_session(table, batches, session_id).await?;
+        Ok(inserted as u64)
+    }
+
+    /// Run an `UPDATE` or `DELETE` statement on in-memory table `table` by
+    /// storing its rewritten contents as a new version. Returns the number
+    /// of rows updated or deleted.
+    async fn mutate_table(&self, sql: &str, table: &str, session_id: Option<&str>) -> BlazeResult<u64> {
+        if self.materialized_views.read().await.contains_key(table) {
+            return Err(BlazeError::InvalidInput(format!("'{}' is a materialized view", table)));
+        }
+
+        let _writer = self.table_writers.lock(table).await;
+        let (verb, rows) = {
+            let ctx = self.ctx.read().await;
+            let provider = table_versions::latest(ctx.table_provider(table).await?);
+            if !is_in_memory(provider.as_ref()) && !self.table_eviction.read().await.is_spilled(table) {
+                return Err(BlazeError::InvalidInput(format!("Table '{}' does not support UPDATE or DELETE", table)));
+            }
+            let schema = provider.schema();
+
+            // Plan again now that no other writer can commit, so the rewrite
+            // reads the latest version of the table
+            let df = self.plan_sql(&ctx, sql).await?;
+            let LogicalPlan::Dml(statement) = df.logical_plan() else {
+                return Err(BlazeError::InvalidInput(format!("Not an UPDATE or DELETE statement: {}", sql)));
+            };
+            let verb = if statement.op == WriteOp::Delete { "Deleted" } else { "Updated" };
+            let rewrite = dml::rewrite(statement)?;
+            let affected = ctx.execute_logical_plan(rewrite.affected).await?.collect().await?;
+            let rows: usize = affected.iter().map(|b| b.num_rows()).sum();
+            if rows == 0 {
+                return Ok(0);
+            }
+            let updated = match rewrite.updated {
+                Some(plan) => Some(
+                    ctx.execute_logical_plan(plan)
+                        .await?
+                        .collect()
+                        .await?
+                        .into_iter()
+                        .map(|batch| RecordBatch::try_new(schema.clone(), batch.columns().to_vec()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                None => None,
+            };
+            let mut batches = ctx.execute_logical_plan(rewrite.kept).await?.collect().await?;
+            batches.extend(updated.iter().flatten().cloned());
+
+            let committed_at = self.clock.now();
+            self.store_table(&ctx, table, schema, batches, committed_at, session_id).await?;
+            self.publish_changes(table, committed_at, Some(affected), updated, session_id).await;
+            (verb, rows)
+        };
+
+        self.maintain_materialized_views(table, None).await;
+        info!("{} {} rows of table '{}'", verb, rows, table);
+        Ok(rows as u64)
+    }
+
+    /// Bring materialized views reading from `table` up to date after it
+    /// changed. `appended` holds the new batches for append-only changes;
+    /// `None` means the table was replaced and views must be recomputed.
+    async fn maintain_materialized_views(&self, table: &str, appended: Option<&[RecordBatch]>) {
+        let mut views = self.materialized_views.write().await;
+        let ctx = self.ctx.read().await;
+
+        for view in views.values_mut().filter(|v| v.depends_on(table)) {
+            view.mark_stale();
+            let outcome = match appended {
+                Some(delta) if view.is_incremental() => view.apply_delta(&ctx, delta, self.clock.now()).await,
+                _ => view.full_refresh(&ctx, self.clock.now()).await,
+            };
+
+            if let Err(e) = outcome.and_then(|_| Self::publish_view(&ctx, view)) {
+                warn!("Materialized view '{}' left stale after change to '{}': {}", view.name(), table, e);
+            }
+        }
+    }
+
+    /// Swap a materialized view's stored result into the session
+    fn publish_view(ctx: &SessionContext, view: &MaterializedView) -> BlazeResult<()> {
+        let table = MemTable::try_new(view.schema(), vec![view.batches().to_vec()])?;
+        ctx.deregister_table(view.name())?;
+        ctx.register_table(view.name(), Arc::new(table))?;
+        Ok(())
+    }
+
+    async fn evaluate_assertion(&self, check: &Assertion) -> BlazeResult<AssertionResult> {
+        let ctx = self.ctx.read().await;
+        let batches = self.plan_sql(&ctx, &check.to_sql()).await?.collect().await?;
+        check.evaluate(&batches)
+    }
+
+    /// Run one statement through the extension statements or the planner.
+    /// A resource group's CPU and memory limits apply to planned queries;
+    /// extension statements and INSERTs use the engine's.
+    async fn execute_statement(
+        &self,
+        sql: &str,
+        session: Option<&SessionInfo>,
+        group: Option<&ResourceGroupState>,
+    ) -> BlazeResult<(Vec<RecordBatch>, Option<String>)> {
+        if let Some(session) = session {
+            if let Some((variable, value)) = sessions::parse_set(sql) {
+                self.set_session_variable(&session.session_id, &variable, value.as_deref()).await?;
+                return Ok((Vec::new(), None));
+            }
+        }
+        match self.execute_extension_statement(sql, session.map(|s| s.session_id.as_str())).await? {
+            Some(batches) => Ok((batches, None)),
+            None => self.execute_sql(sql, session, group).await,
+        }
+    }
+
+    /// Run statements DataFusion does not understand itself. Returns `None`
+    /// when `sql` should go through the regular planner.
+    async fn execute_extension_statement(
+        &self,
+        sql: &str,
+        session_id: Option<&str>,
+    ) -> BlazeResult<Option<Vec<RecordBatch>>> {
+        if let Some(control) = transactions::parse_transaction_control(sql) {
+            match control {
+                TransactionControl::Begin => self.begin_session_transaction(session_id).await?,
+                TransactionControl::Commit => {
+                    self.commit_session_transaction(session_id).await?;
+                }
+                TransactionControl::Rollback => {
+                    self.rollback_session_transaction(session_id).await?;
+                }
+            }
+            return Ok(Some(Vec::new()));
+        }
+        if let Some(check) = assertions::parse_assert(sql) {
+            let result = self.evaluate_assertion(&check).await?;
+            if !result.passed {
+                return Err(BlazeError::AssertionFailed(result.message.unwrap_or_default()));
+            }
+            return Ok(Some(Vec::new()));
+        }
+        if let Some(table) = join_order::parse_analyze(sql) {
+            self.analyze_table(&table).await?;
+            return Ok(Some(Vec::new()));
+        }
+        if let Some(zone) = time_zone::parse_set_time_zone(sql) {
+            self.update_config(EngineConfigUpdate { time_zone: Some(zone), ..Default::default() }).await?;
+            return Ok(Some(Vec::new()));
+        }
+        if let Some(statement) = export_data::parse_export_data(sql)? {
+            return Ok(Some(vec![self.export_data(&statement).await?]));
+        }
+        if let Some(statement) = copy::parse_copy(sql)? {
+            let batch = match statement.direction {
+                CopyDirection::To => {
+                    let (rows, bytes) = self.copy_to(&statement).await?;
+                    copied_batch(rows, bytes)?
+                }
+                CopyDirection::From => count_batch(self.copy_from(&statement, session_id).await?)?,
+            };
+            return Ok(Some(vec![batch]));
+        }
+        if let Some(statement) = file_tables::parse_create_external_table(sql)? {
+            let exists = self.ctx.read().await.table_exist(statement.name.as_str())?;
+            if exists && statement.if_not_exists {
+                return Ok(Some(Vec::new()));
+            }
+            if exists && !statement.or_replace {
+                return Err(BlazeError::InvalidInput(format!("Table '{}' already exists", statement.name)));
+            }
+            self.register_file_table(&statement.name, &statement.location, statement.format, None, None, FileListing::Glob)
+                .await?;
+            return Ok(Some(Vec::new()));
+        }
+        if ml::parse_create_model(sql)?.is_some() {
+            self.create_model(sql).await?;
+            return Ok(Some(Vec::new()));
+        }
+        if let Some(statement) = search::parse_create_search_index(sql)? {
+            let exists = self.search_indexes.read().await.contains_key(&statement.table_name);
+            if !(exists && statement.if_not_exists) {
+                self.create_search_index(&statement.name, &statement.table_name, statement.columns).await?;
+            }
+            return Ok(Some(Vec::new()));
+        }
+        if let Some((index, table, if_exists)) = search::parse_drop_search_index(sql) {
+            match self.drop_search_index(&index, &table).await {
+                Err(_) if if_exists => {}
+                outcome => outcome?,
+            }
+            return Ok(Some(Vec::new()));
+        }
+        if let Some(statement) = vector::parse_create_vector_index(sql)? {
+            let exists = self.vector_indexes.read().await.contains_key(&statement.name.to_lowercase());
+            if !(exists && statement.if_not_exists) {
+                self.create_vector_index(statement).await?;
+            }
+            return Ok(Some(Vec::new()));
+        }
+        if let Some((index, table, if_exists)) = vector::parse_drop_vector_index(sql) {
+            match self.drop_vector_index(&index, &table).await {
+                Err(_) if if_exists => {}
+                outcome => outcome?,
+            }
+            return Ok(Some(Vec::new()));
+        }
+        #[cfg(feature = "duckdb")]
+        if let Some((path, alias)) = duckdb_attach::parse_attach(sql)? {
+            self.attach_duckdb(&path, &alias).await?;
+            return Ok(Some(Vec::new()));
+        }
+        #[cfg(feature = "duckdb")]
+        if let Some((alias, if_exists)) = duckdb_attach::parse_detach(sql) {
+            match self.detach_database(&alias).await {
+                Err(_) if if_exists => {}
+                outcome => outcome?,
+            }
+            return Ok(Some(Vec::new()));
+        }
+        if let Some((name, if_exists)) = ml::parse_drop_model(sql) {
+            match self.drop_model(&name).await {
+                Err(_) if if_exists => {}
+                outcome => outcome?,
+            }
+            return Ok(Some(Vec::new()));
+        }
+
+        Ok(None)
+    }
+
+    /// Write a table or query result to a file, returning the rows and
+    /// bytes written
+    async fn copy_to(&self, statement: &CopyStatement) -> BlazeResult<(u64, u64)> {
+        let ctx = self.ctx.read().await;
+        let batches = self.plan_sql(&ctx, &statement.to_datafusion_sql()).await?.collect().await?;
+        let rows = batches
+            .first()
+            .and_then(|batch| batch.column(0).as_any().downcast_ref::<UInt64Array>())
+            .map(|counts| counts.value(0))
+            .unwrap_or(0);
+        let bytes = export_data::matching_files(&ctx, &statement.path).await?.iter().map(|(_, size)| size).sum();
+        info!("Copied {} rows ({}) to '{}'", rows, format_bytes(bytes), statement.path);
+        Ok((rows, bytes))
+    }
+
+    /// Run an `EXPORT DATA` statement, returning the files written with
+    /// their rows and bytes
+    async fn export_data(&self, statement: &ExportStatement) -> BlazeResult<RecordBatch> {
+        let ctx = self.ctx.read().await;
+        if !statement.overwrite {
+            if let Some((existing, _)) = export_data::matching_files(&ctx, &statement.uri).await?.first() {
+                return Err(BlazeError::InvalidInput(format!(
+                    "EXPORT DATA destination '{}' already has file '{}'; set overwrite = true to replace it",
+                    statement.uri, existing
+                )));
+            }
+        }
+
+        let copy = statement.to_copy()?;
+        let batches = self.plan_sql(&ctx, &copy.to_datafusion_sql()).await?.collect().await?;
+        let rows = batches
+            .first()
+            .and_then(|batch| batch.column(0).as_any().downcast_ref::<UInt64Array>())
+            .map(|counts| counts.value(0))
+            .unwrap_or(0);
+        let bytes = export_data::matching_files(&ctx, &copy.path).await?.first().map_or(0, |(_, size)| *size);
+        info!("Exported {} rows ({}) to '{}'", rows, format_bytes(bytes), copy.path);
+        export_data::report_batch(&[(copy.path, rows, bytes)])
+    }
+
+    /// Append a file's rows to a table, creating the table if needed
+    async fn copy_from(&self, statement: &CopyStatement, session_id: Option<&str>) -> BlazeResult<u64> {
+        let CopySource::Table(table) = &statement.source else {
+            return Err(BlazeError::InvalidInput("COPY FROM needs a table name".to_string()));
+        };
+
+        let (batches, exists) = {
+            let ctx = self.ctx.read().await;
+            let target = ctx.table_provider(table.as_str()).await.ok().map(|provider| provider.schema());
+            let exists = target.is_some();
+            (statement.read(&ctx, target).await?, exists)
+        };
+
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        if exists {
+            self.insert_rows(table, batches, session_id).await?;
+        } else {
+            self.register_table_in_session(table, batches, session_id).await?;
+        }
+        info!("Copied {} rows from '{}' into '{}'", rows, statement.path, table);
+        Ok(rows as u64)
+    }
+
+    /// Plan and execute a regular SQL statement, returning its batches and,
+    /// when debug logging is on, the plan
+    async fn execute_sql(
+        &self,
+        sql: &str,
+        session: Option<&SessionInfo>,
+        group: Option<&ResourceGroupState>,
+    ) -> BlazeResult<(Vec<RecordBatch>, Option<String>)> {
+        let engine_ctx = self.ctx.read().await;
+        let session_ctx = session.and_then(|s| s.settings.context(&engine_ctx, &self.clock));
+        let ctx = session_ctx.as_ref().unwrap_or(&engine_ctx);
+
+        // Parse and plan the query
+        if plan_cache::changes_planning(sql) {
+            self.plan_cache.write().await.clear();
+        }
+        let df = self.plan_sql(ctx, sql).await?;
+
+        // Get query plan for debugging (optional)
+        let query_plan = if log::log_enabled!(log::Level::Debug) {
+            Some(format!("{}", df.logical_plan().display_indent_schema()))
+        } else {
+            None
+        };
+
+        // Execute the query. INSERTs are routed through the append path and
+        // UPDATEs and DELETEs replace the table's rows, so snapshots,
+        // materialized views and the change feed see them.
+        if let Some(table) = dml::mutation_target(df.logical_plan()) {
+            drop(engine_ctx);
+            let affected = self.mutate_table(sql, &table, session.map(|s| s.session_id.as_str())).await?;
+            return Ok((vec![count_batch(affected)?], query_plan));
+        }
+        let record_batches = match insert_target(df.logical_plan()) {
+            Some((table, input)) => {
+                let rows = ctx.execute_logical_plan(input).await?.collect().await?;
+                drop(engine_ctx);
+                let inserted = self.insert_rows(&table, rows, session.map(|s| s.session_id.as_str())).await?;
+                vec![count_batch(inserted)?]
+            }
+            None => self.collect_frame(df, group).await?,
+        };
+
+        Ok((record_batches, query_plan))
+    }
+
+    /// Run a prepared statement with its parameters bound
+    async fn execute_bound(
+        &self,
+        bound: BoundStatement,
+        session: Option<&SessionInfo>,
+        group: Option<&ResourceGroupState>,
+    ) -> BlazeResult<(Vec<RecordBatch>, Option<String>)> {
+        let engine_ctx = self.ctx.read().await;
+        let session_ctx = session.and_then(|s| s.settings.context(&engine_ctx, &self.clock));
+        let ctx = session_ctx.as_ref().unwrap_or(&engine_ctx);
+        // Prepared statements read the versions of tables current when they run
+        let plan = table_versions::pin_versions(bound.plan)?;
+        let df = Box::pin(query_hints::execute_hinted(ctx, plan, &bound.hints)).await?;
+        let query_plan = if log::log_enabled!(log::Level::Debug) {
+            Some(format!("{}", df.logical_plan().display_indent_schema()))
+        } else {
+            None
+        };
+        let record_batches = self.collect_frame(df, group).await?;
+        Ok((record_batches, query_plan))
+    }
+
+    /// Collect a query's result, within its resource group if it has one,
+    /// with as many partitions as slots it is granted when slot scheduling
+    /// is on
+    async fn collect_frame(&self, df: DataFrame, group: Option<&ResourceGroupState>) -> BlazeResult<Vec<RecordBatch>> {
+        let grant = self.slots.grant();
+        match (group, &grant) {
+            (Some(group), Some(grant)) => group.collect(df, grant.slots()).await,
+            (Some(group), None) => {
+                let cpu_cores = self.config.read().await.cpu_cores;
+                group.collect(df, cpu_cores).await
+            }
+            (None, Some(grant)) => {
+                let (mut state, plan) = df.into_parts();
+                state.config_mut().options_mut().execution.target_partitions = grant.slots();
+                Ok(DataFrame::new(state, plan).collect().await?)
+            }
+            (None, None) => Ok(df.collect().await?),
+        }
+    }
+
+    /// Parse and plan SQL, resolving time-travel clauses against snapshots
+    async fn plan_sql(&self, ctx: &SessionContext, sql: &str) -> BlazeResult<DataFrame> {
+        let rewritten;
+        let sql = if ml::contains_predict(sql) {
+            let models = self.models.read().await;
+            rewritten = ml::rewrite_predict(sql, |name| models.get(&name.to_lowercase()).cloned())?;
+            rewritten.as_str()
+        } else {
+            if let Some(df) = self.plan_with_cache(ctx, sql).await? {
+                return Ok(df);
+            }
+            sql
+        };
+
+        if !vector::contains_vector_search(sql)
+            && !sessionize::contains_sessionize(sql)
+            && !time_series::contains_gap_fill(sql)
+        {
+            return self.plan_rewritten_sql(ctx, sql).await;
+        }
+
+        let mut hidden = HiddenResults::default();
+        let planned = match self.resolve_table_functions(ctx, sql, &mut hidden).await {
+            Ok(sql) => self.plan_rewritten_sql(ctx, &sql).await,
+            Err(e) => Err(e),
+        };
+        hidden.deregister(ctx);
+        planned
+    }
+
+    /// Plan a query from the plan cache, or plan it and cache its plan.
+    /// Returns None for statements the cache does not hold.
+    async fn plan_with_cache(&self, ctx: &SessionContext, sql: &str) -> BlazeResult<Option<DataFrame>> {
+        let capacity = self.config.read().await.plan_cache_size;
+        if capacity == 0
+            || !plan_graph::is_query(sql)
+            || vector::contains_vector_search(sql)
+            || sessionize::contains_sessionize(sql)
+            || time_series::contains_gap_fill(sql)
+            || snapshots::contains_time_travel(sql)
+            || !query_hints::parse_hints(sql)?.is_empty()
+        {
+            return Ok(None);
+        }
+        let Some(key) = result_cache::cache_key(sql) else {
+            return Ok(None);
+        };
+        // Zoned casts are folded into plans, so sessions in other time zones
+        // keep their own
+        let time_zone = ctx.state_ref().read().config().options().execution.time_zone.clone();
+        let key = format!("{} {}", time_zone.unwrap_or_default(), key);
+
+        let cached = self.plan_cache.write().await.get(&key);
+        if let Some(cached) = cached {
+            if let Some(df) = plan_cache::cached_frame(ctx, cached).await? {
+                self.plan_cache.write().await.record_hit();
+                return Ok(Some(df));
+            }
+        }
+
+        let planned = async {
+            let plan = plan_statement(ctx, sql).await?;
+            let optimized = ctx.state().optimize(&plan)?;
+            Ok::<_, datafusion::error::DataFusionError>((plan, optimized))
+        };
+        let (plan, optimized) = planned.await.map_err(|e| suggestions::with_suggestions(e, ctx))?;
+        let scans_views = plan_cache::scans_views(&optimized)?;
+        self.plan_cache.write().await.insert(key, &plan, optimized.clone(), capacity);
+        if scans_views {
+            // Views left in the plan are planned when it runs, which needs
+            // the session's analyzer rules
+            return Ok(Some(DataFrame::new(ctx.state(), table_versions::pin_versions(plan)?)));
+        }
+        Ok(Some(plan_cache::optimized_frame(ctx, optimized)?))
+    }
+
+    async fn plan_rewritten_sql(&self, ctx: &SessionContext, sql: &str) -> BlazeResult<DataFrame> {
+        let hints = query_hints::parse_hints(sql)?;
+        if snapshots::contains_time_travel(sql) {
+            let plan = {
+                let store = self.snapshots.read().await;
+                #[cfg(feature = "iceberg")]
+                let iceberg_tables = self.iceberg_tables.read().await;
+                #[cfg(feature = "iceberg")]
+                let sources: Vec<&dyn VersionedSource> = vec![&*iceberg_tables];
+                #[cfg(not(feature = "iceberg"))]
+                let sources: Vec<&dyn VersionedSource> = Vec::new();
+                snapshots::plan_time_travel(ctx, &store, &sources, sql).await?
+            };
+            return Ok(Box::pin(query_hints::execute_hinted(ctx, table_versions::pin_versions(plan)?, &hints)).await?);
+        }
+
+        let planned = async {
+            let plan = plan_statement(ctx, sql).await?;
+            // Statements read the versions of tables current when they are planned
+            Box::pin(query_hints::execute_hinted(ctx, table_versions::pin_versions(plan)?, &hints)).await
+        };
+        planned.await.map_err(|e| suggestions::with_suggestions(e, ctx).into())
+    }
+
+    /// Run every `VECTOR_SEARCH(...)`, `SESSIONIZE(...)` and `GAP_FILL(...)`
+    /// call in `sql`, replacing each with a hidden table holding its result.
+    /// Gap filling runs last so its source may use the other functions.
+    async fn resolve_table_functions(
+        &self,
+        ctx: &SessionContext,
+        sql: &str,
+        hidden: &mut HiddenResults,
+    ) -> BlazeResult<String> {
+        let mut rewritten = sql.to_string();
+
+        for call in vector::find_vector_search_calls(sql)?.iter().rev() {
+            let base = ctx.table(call.base_table.as_str()).await?;
+            let base_schema: SchemaRef = Arc::new(base.schema().as_arrow().clone());
+            let base_batches = base.collect().await?;
+            let query_batches = ctx.sql(&call.query_sql).await?.collect().await?;
+
+            let result = {
+                let indexes = self.vector_indexes.read().await;
+                let index = indexes
+                    .values()
+                    .find(|i| i.table_name() == call.base_table && i.serves(&call.column, call.distance_type));
+                vector::execute_search(call, base_schema, &base_batches, &query_batches, index)?
+            };
+            rewritten.replace_range(call.span.0..call.span.1, &hidden.register(ctx, result)?);
+        }
+
+        let sql = rewritten.clone();
+        for call in sessionize::find_sessionize_calls(&sql)?.iter().rev() {
+            let source = ctx.sql(&call.source_sql).await?;
+            let schema: SchemaRef = Arc::new(source.schema().as_arrow().clone());
+            let batches = source.collect().await?;
+            let gap = sessionize::evaluate_gap(ctx, &call.gap).await?;
+
+            let result = sessionize::sessionize(call, schema, &batches, gap)?;
+            rewritten.replace_range(call.span.0..call.span.1, &hidden.register(ctx, result)?);
+        }
+
+        let sql = rewritten.clone();
+        for call in time_series::find_gap_fill_calls(&sql)?.iter().rev() {
+            let source = ctx.sql(&call.source_sql).await?;
+            let schema: SchemaRef = Arc::new(source.schema().as_arrow().clone());
+            let batches = source.collect().await?;
+            let (width, origin) = time_series::evaluate_buckets(ctx, call).await?;
+
+            let result = time_series::gap_fill(call, schema, &batches, width, origin)?;
+            rewritten.replace_range(call.span.0..call.span.1, &hidden.register(ctx, result)?);
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Reject a result with more rows than `max_result_rows` allows, the
+    /// session's if it sets one
+    async fn check_result_size(&self, batches: &[RecordBatch], session: Option<&SessionInfo>) -> BlazeResult<()> {
+        let limit = match session.and_then(|s| s.settings.max_result_rows) {
+            Some(limit) => limit,
+            None => self.config.read().await.max_result_rows,
+        };
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        if limit > 0 && rows > limit {
+            return Err(BlazeError::InvalidInput(format!(
+                "Query returned {} rows, more than the limit of {}; add a LIMIT clause or raise max_result_rows",
+                rows, limit
+            )));
+        }
+        Ok(())
+    }
+
+    /// Update engine statistics
+    async fn update_stats(&self, sql: &str, execution_time_ms: u64, memory_used: u64) {
+        {
+            let mut stats = self.stats.write().await;
+
+            stats.total_queries += 1;
+
+            // Update average execution time
+            let total_time = stats.avg_execution_time_ms * (stats.total_queries - 1) as f64
+                            + execution_time_ms as f64;
+            stats.avg_execution_time_ms = total_time / stats.total_queries as f64;
+
+            // Update peak memory usage
+            if memory_used > stats.peak_memory_bytes {
+                stats.peak_memory_bytes = memory_used;
+            }
+        }
+
+        let normalized_sql = normalize_sql(sql);
+        let fingerprint = fingerprint_sql(&normalized_sql);
+        let mut query_stats = self.query_stats.write().await;
+        if !query_stats.contains_key(&fingerprint) && query_stats.len() >= MAX_TRACKED_FINGERPRINTS {
+            let least_executed = query_stats
+                .values()
+                .min_by_key(|entry| (entry.executions, entry.total_execution_time_ms))
+                .map(|entry| entry.fingerprint.clone());
+            if let Some(least_executed) = least_executed {
+                query_stats.remove(&least_executed);
+            }
+        }
+        let entry = query_stats.entry(fingerprint.clone()).or_insert_with(|| QueryFingerprintStats {
+            fingerprint,
+            normalized_sql,
+            executions: 0,
+            total_execution_time_ms: 0,
+            avg_execution_time_ms: 0.0,
+            peak_memory_bytes: 0,
+        });
+        entry.executions += 1;
+        entry.total_execution_time_ms += execution_time_ms;
+        entry.avg_execution_time_ms = entry.total_execution_time_ms as f64 / entry.executions as f64;
+        entry.peak_memory_bytes = entry.peak_memory_bytes.max(memory_used);
+    }
+
+    async fn update_label_stats(&self, labels: &HashMap<String, String>, execution_time_ms: u64, rows: u64, memory_used: u64) {
+        if labels.is_empty() {
+            return;
+        }
+        let mut label_stats = self.label_stats.write().await;
+        for (label, value) in labels {
+            let key = (label.clone(), value.clone());
+            if !label_stats.contains_key(&key) && label_stats.len() >= MAX_TRACKED_LABELS {
+                let least_used = label_stats
+                    .iter()
+                    .min_by_key(|(_, entry)| (entry.queries, entry.total_execution_time_ms))
+                    .map(|(key, _)| key.clone());
+                if let Some(least_used) = least_used {
+                    label_stats.remove(&least_used);
+                }
+            }
+            let entry = label_stats.entry(key).or_insert_with(|| LabelStats {
+                label: label.clone(),
+                value: value.clone(),
+                queries: 0,
+                total_execution_time_ms: 0,
+                avg_execution_time_ms: 0.0,
+                rows_returned: 0,
+                peak_memory_bytes: 0,
+            });
+            entry.queries += 1;
+            entry.total_execution_time_ms += execution_time_ms;
+            entry.avg_execution_time_ms = entry.total_execution_time_ms as f64 / entry.queries as f64;
+            entry.rows_returned += rows;
+            entry.peak_memory_bytes = entry.peak_memory_bytes.max(memory_used);
+        }
+    }
+}
+
+/// Parse `sql` and plan its single statement, without pinning table versions
+async fn plan_statement(ctx: &SessionContext, sql: &str) -> datafusion::error::Result<LogicalPlan> {
+    let state = ctx.state();
+    let mut statement = state.sql_to_statement(sql, &state.config_options().sql_parser.dialect)?;
+    decimal::apply_type_defaults(&mut statement, decimal::options(ctx).decimal_rules);
+    state.statement_to_plan(statement).await
+}
+
+/// Target table and row source of an `INSERT INTO` plan
+fn insert_target(plan: &LogicalPlan) -> Option<(String, LogicalPlan)> {
+    match plan {
+        LogicalPlan::Dml(DmlStatement { table_name, op: WriteOp::Insert(InsertOp::Append), input, .. }) => {
+            Some((table_name.to_string(), input.as_ref().clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Register `table` as `name`, replacing a table of that name, which
+/// `register_table` alone refuses to. Returns whether one was replaced.
+fn replace_table(ctx: &SessionContext, name: &str, table: Arc<dyn TableProvider>) -> BlazeResult<bool> {
+    let replaced = ctx.deregister_table(name)?.is_some();
+    ctx.register_table(name, table)?;
+    Ok(replaced)
+}
+
+/// Whether a table holds its rows in memory
+fn is_in_memory(provider: &dyn TableProvider) -> bool {
+    provider.as_any().is::<MemTable>()
+        || provider.as_any().is::<SearchIndexedTable>()
+        || provider.as_any().is::<TimePartitionedTable>()
+}
+
+/// Merge runs of consecutive batches from `start` on that together hold at
+/// most `target_rows` rows, copying only the rows of merged batches
+fn coalesce_batches(schema: &SchemaRef, batches: &mut Vec<RecordBatch>, start: usize, target_rows: usize) -> BlazeResult<()> {
+    let tail = batches.split_off(start.min(batches.len()));
+    let mut run: Vec<RecordBatch> = Vec::new();
+    let mut run_rows = 0;
+    for batch in tail {
+        if !run.is_empty() && run_rows + batch.num_rows() > target_rows {
+            batches.push(merge_run(schema, std::mem::take(&mut run))?);
+            run_rows = 0;
+        }
+        run_rows += batch.num_rows();
+        run.push(batch);
+    }
+    if !run.is_empty() {
+        batches.push(merge_run(schema, run)?);
+    }
+    Ok(())
+}
+
+/// One batch with the rows of `run`, copied only if there are several
+fn merge_run(schema: &SchemaRef, mut run: Vec<RecordBatch>) -> BlazeResult<RecordBatch> {
+    match run.len() {
+        1 => Ok(run.remove(0)),
+        _ => Ok(concat_batches(schema, &run)?),
+    }
+}
+
+/// Single-row result reporting how many rows a DML statement affected
+fn count_batch(count: u64) -> BlazeResult<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![Field::new("count", DataType::Int64, false)]));
+    Ok(RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![count as i64]))])?)
+}
+
+/// Single-row result of `COPY ... TO`: the rows copied and the bytes written
+fn copied_batch(count: u64, bytes: u64) -> BlazeResult<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("count", DataType::Int64, false),
+        Field::new("bytes", DataType::Int64, false),
+    ]));
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![Arc::new(Int64Array::from(vec![count as i64])), Arc::new(Int64Array::from(vec![bytes as i64]))],
+    )?)
+}
+
+/// Rows and bytes a `COPY ... TO` or `EXPORT DATA` statement wrote, summed
+/// from its result; `None` for other statements
+fn written_totals(sql: &str, batches: &[RecordBatch]) -> Option<(u64, u64)> {
+    let rows_column = match copy::parse_copy(sql) {
+        Ok(Some(statement)) if statement.direction == CopyDirection::To => "count",
+        Ok(Some(_)) => return None,
+        _ => {
+            export_data::parse_export_data(sql).ok().flatten()?;
+            "rows"
+        }
+    };
+    let total = |column: &str| {
+        batches
+            .iter()
+            .filter_map(|batch| batch.column_by_name(column)?.as_any().downcast_ref::<Int64Array>())
+            .map(|values| values.iter().flatten().sum::<i64>())
+            .sum::<i64>() as u64
+    };
+    Some((total(rows_column), total("bytes")))
+}
+
+/// Stream over batches that are already computed
+fn batch_stream(batches: Vec<RecordBatch>) -> BlazeResult<SendableRecordBatchStream> {
+    let schema = batches.first().map(|b| b.schema()).unwrap_or_else(|| Arc::new(Schema::empty()));
+    Ok(Box::pin(MemoryStream::try_new(batches, schema, None)?))
+}
+
+/// Run a query for at most `timeout_ms`, 0 meaning no limit. A query that
+/// runs out of time is dropped, which stops its execution.
+async fn with_timeout<T>(timeout_ms: u64, query: impl Future<Output = BlazeResult<T>>) -> BlazeResult<T> {
+    if timeout_ms == 0 {
+        return query.await;
+    }
+    match tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), query).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(BlazeError::Timeout { timeout_ms }),
+    }
+}
@@ -0,0 +1,317 @@
+//! Saving an engine's state to a directory and loading it back
+//!
+//! `BlazeQueryEngine::snapshot_to` writes everything needed to rebuild the
+//! engine's catalog:
+//!
+//! ```text
+//! <dir>/manifest.json      views, indexes, models, groups, stats, ...
+//! <dir>/tables/0.arrow     one Arrow IPC file per in-memory table
+//! <dir>/tables/1.arrow
+//! ```
+//!
+//! `restore_from` reads it into a new engine. In-memory tables are stored
+//! with their data; views, materialized views, indexes, file-backed tables,
+//! Flight tables and attached databases are stored as definitions and
+//! rebuilt, so the files, services and databases they read must still be
+//! where they were. Table
+//! snapshots, the change feed and anonymous result tables are not saved.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::reader::FileReader;
+use datafusion::arrow::ipc::writer::FileWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{EngineStats, LabelStats, QueryFingerprintStats};
+use crate::error::{BlazeError, BlazeResult};
+use crate::file_tables::{CsvTableOptions, DataFormat, FileListing, JsonTableOptions};
+use crate::flight_tables::FlightSource;
+use crate::invalid_input;
+use crate::ml::Model;
+use crate::search::SearchIndexInfo;
+use crate::time_partitions::TimePartitioning;
+use crate::vector::CreateVectorIndex;
+use crate::workload::ResourceGroup;
+
+/// Version of the directory layout, bumped on incompatible changes
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const TABLES_DIR: &str = "tables";
+
+/// What a saved or restored engine state contains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineStateInfo {
+    /// Directory the state is stored in
+    pub path: PathBuf,
+    /// When the state was saved
+    pub saved_at: DateTime<Utc>,
+    /// In-memory tables, stored with their rows
+    pub tables: usize,
+    /// Rows across the in-memory tables
+    pub total_rows: usize,
+    /// Views, materialized views, file-backed and Flight tables, stored as
+    /// definitions
+    pub views: usize,
+    /// Search and vector indexes
+    pub indexes: usize,
+    /// Trained models
+    pub models: usize,
+}
+
+/// Everything in a state directory except the table data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StateManifest {
+    pub format_version: u32,
+    pub saved_at: DateTime<Utc>,
+    pub tables: Vec<SavedTable>,
+    /// `CREATE VIEW` statements in the order they were found
+    pub views: Vec<SavedView>,
+    pub materialized_views: Vec<SavedView>,
+    pub file_tables: Vec<SavedFileTable>,
+    pub search_indexes: Vec<SearchIndexInfo>,
+    #[serde(default)]
+    pub time_partitioning: Vec<TimePartitioning>,
+    pub vector_indexes: Vec<CreateVectorIndex>,
+    pub models: Vec<Model>,
+    pub resource_groups: Vec<ResourceGroup>,
+    #[serde(default)]
+    pub flight_tables: Vec<SavedFlightTable>,
+    #[serde(default)]
+    pub attached_databases: Vec<SavedAttachment>,
+    #[serde(default)]
+    pub sqlite_databases: Vec<SavedSqliteDatabase>,
+    #[serde(default)]
+    pub orc_tables: Vec<SavedOrcTable>,
+    #[serde(default)]
+    pub arrow_ipc_tables: Vec<SavedArrowIpcTable>,
+    #[serde(default)]
+    pub delta_tables: Vec<SavedDeltaTable>,
+    #[serde(default)]
+    pub iceberg_tables: Vec<SavedIcebergTable>,
+    pub stats: EngineStats,
+    pub query_stats: Vec<QueryFingerprintStats>,
+    #[serde(default)]
+    pub label_stats: Vec<LabelStats>,
+}
+
+/// An in-memory table and the file holding its rows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedTable {
+    pub name: String,
+    /// Path relative to the state directory
+    pub file: String,
+    pub rows: usize,
+}
+
+/// A view or materialized view by its defining SQL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedView {
+    pub name: String,
+    pub sql: String,
+}
+
+/// A table over files matched by a glob
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedFileTable {
+    pub name: String,
+    pub pattern: String,
+    pub format: DataFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csv_options: Option<CsvTableOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_options: Option<JsonTableOptions>,
+    #[serde(default)]
+    pub listing: FileListing,
+}
+
+/// A table read from a remote Flight service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedFlightTable {
+    pub name: String,
+    pub endpoint: String,
+    pub source: FlightSource,
+}
+
+/// A table over ORC files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedOrcTable {
+    pub name: String,
+    pub path: String,
+}
+
+/// A table over memory-mapped Arrow IPC files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedArrowIpcTable {
+    pub name: String,
+    pub path: String,
+}
+
+/// A Delta Lake table, read at its latest version unless one was pinned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedDeltaTable {
+    pub name: String,
+    pub path: String,
+    pub version: Option<i64>,
+}
+
+/// An Iceberg table, read at its current snapshot unless one was pinned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedIcebergTable {
+    pub name: String,
+    pub metadata_path: String,
+    pub snapshot_id: Option<i64>,
+}
+
+/// An attached DuckDB file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedAttachment {
+    pub alias: String,
+    pub path: String,
+}
+
+/// An attached SQLite file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedSqliteDatabase {
+    pub dataset: String,
+    pub path: String,
+}
+
+impl StateManifest {
+    /// Summary of the state for the caller
+    pub(crate) fn info(&self, path: &Path) -> EngineStateInfo {
+        EngineStateInfo {
+            path: path.to_path_buf(),
+            saved_at: self.saved_at,
+            tables: self.tables.len(),
+            total_rows: self.tables.iter().map(|table| table.rows).sum(),
+            views: self.views.len() + self.materialized_views.len() + self.file_tables.len() + self.flight_tables.len()
+                + self.orc_tables.len()
+                + self.arrow_ipc_tables.len()
+                + self.delta_tables.len()
+                + self.iceberg_tables.len(),
+            indexes: self.search_indexes.len() + self.vector_indexes.len(),
+            models: self.models.len(),
+        }
+    }
+}
+
+/// Prepare `dir` for a new state: it may be missing, empty, or hold an
+/// earlier state, which is removed. Anything else is refused so unrelated
+/// files are never mixed with or replaced by a state.
+pub(crate) fn prepare_dir(dir: &Path) -> BlazeResult<()> {
+    if dir.exists() {
+        let previous_state = dir.join(MANIFEST_FILE).is_file();
+        if !previous_state && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(invalid_input!(
+                "'{}' is neither empty nor a saved engine state; choose a new directory",
+                dir.display()
+            ));
+        }
+        if previous_state {
+            // The manifest goes first, so a failed save leaves no state
+            // pointing at missing table files
+            std::fs::remove_file(dir.join(MANIFEST_FILE))?;
+            if dir.join(TABLES_DIR).is_dir() {
+                std::fs::remove_dir_all(dir.join(TABLES_DIR))?;
+            }
+        }
+    }
+    std::fs::create_dir_all(dir.join(TABLES_DIR))?;
+    Ok(())
+}
+
+/// Write the rows of the `index`th table, returning its entry in the manifest
+pub(crate) fn write_table(
+    dir: &Path,
+    index: usize,
+    name: &str,
+    schema: SchemaRef,
+    batches: &[RecordBatch],
+) -> BlazeResult<SavedTable> {
+    let file = format!("{}/{}.arrow", TABLES_DIR, index);
+    let mut writer = FileWriter::try_new(File::create(dir.join(&file))?, &schema)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+
+    Ok(SavedTable {
+        name: name.to_string(),
+        file,
+        rows: batches.iter().map(|batch| batch.num_rows()).sum(),
+    })
+}
+
+/// Read a table's rows back. A table without rows comes back as one empty
+/// batch so it keeps its schema.
+pub(crate) fn read_table(dir: &Path, table: &SavedTable) -> BlazeResult<Vec<RecordBatch>> {
+    let reader = FileReader::try_new(File::open(dir.join(&table.file))?, None)?;
+    let schema = reader.schema();
+    let mut batches = reader.collect::<Result<Vec<_>, _>>()?;
+    if batches.is_empty() {
+        batches.push(RecordBatch::new_empty(schema));
+    }
+    Ok(batches)
+}
+
+/// Write the manifest, last, so a directory with a manifest is complete
+pub(crate) fn write_manifest(dir: &Path, manifest: &StateManifest) -> BlazeResult<()> {
+    let temporary = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    serde_json::to_writer_pretty(File::create(&temporary)?, manifest)?;
+    std::fs::rename(temporary, dir.join(MANIFEST_FILE))?;
+    Ok(())
+}
+
+/// Read and check the manifest of a state directory
+pub(crate) fn read_manifest(dir: &Path) -> BlazeResult<StateManifest> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.is_file() {
+        return Err(invalid_input!("'{}' does not hold a saved engine state", dir.display()));
+    }
+    let manifest: StateManifest = serde_json::from_reader(File::open(path)?)?;
+    if manifest.format_version != STATE_FORMAT_VERSION {
+        return Err(invalid_input!(
+            "Engine state in '{}' has format version {}, this engine reads version {}",
+            dir.display(),
+            manifest.format_version,
+            STATE_FORMAT_VERSION
+        ));
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_table_files_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        prepare_dir(dir.path()).unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2, 3]))]).unwrap();
+
+        let saved = write_table(dir.path(), 0, "ids", schema.clone(), &[batch.clone(), batch]).unwrap();
+        assert_eq!(saved.rows, 6);
+        assert_eq!(read_table(dir.path(), &saved).unwrap().len(), 2);
+
+        let empty = write_table(dir.path(), 1, "none", schema.clone(), &[]).unwrap();
+        let batches = read_table(dir.path(), &empty).unwrap();
+        assert_eq!(batches[0].num_rows(), 0);
+        assert_eq!(batches[0].schema(), schema);
+
+        // Only empty directories and earlier states are written to
+        let other = tempfile::tempdir().unwrap();
+        std::fs::write(other.path().join("notes.txt"), "keep").unwrap();
+        assert!(prepare_dir(other.path()).is_err());
+        assert!(read_manifest(other.path()).is_err());
+    }
+}
@@ -52,6 +52,31 @@ pub enum BlazeError {
     /// Python FFI errors
     #[error("Python FFI error: {0}")]
     Python(String),
+
+    /// An ASSERT statement's condition did not hold
+    #[error("Assertion failed: {0}")]
+    AssertionFailed(String),
+
+    /// A failure inside the engine rather than in the statement, such as a
+    /// worker that stopped
+    #[error("Internal error: {0}")]
+    Internal(String),
+
+    /// An external system such as a message broker failed or rejected a request
+    #[error("External source error: {0}")]
+    External(String),
+
+    /// A running query was stopped before it finished
+    #[error("Query cancelled: {0}")]
+    Cancelled(String),
+
+    /// The engine is shutting down and takes no new queries
+    #[error("Engine is shutting down and accepts no new queries")]
+    ShuttingDown,
+
+    /// Admission control turned a query away; it may succeed when retried
+    #[error("Engine overloaded: {0}")]
+    Overloaded(String),
 }
 
 impl From<BlazeError> for PyErr {
@@ -92,6 +117,24 @@ impl From<BlazeError> for PyErr {
             BlazeError::Python(ref msg) => {
                 PyRuntimeError::new_err(msg.clone())
             }
+            BlazeError::AssertionFailed(ref msg) => {
+                PyAssertionError::new_err(msg.clone())
+            }
+            BlazeError::Internal(ref msg) => {
+                PyRuntimeError::new_err(format!("Internal error: {}", msg))
+            }
+            BlazeError::External(ref msg) => {
+                PyIOError::new_err(format!("External source error: {}", msg))
+            }
+            BlazeError::Cancelled(ref msg) => {
+                PyRuntimeError::new_err(format!("Query cancelled: {}", msg))
+            }
+            BlazeError::ShuttingDown => {
+                PyRuntimeError::new_err("Engine is shutting down and accepts no new queries")
+            }
+            BlazeError::Overloaded(ref msg) => {
+                PyRuntimeError::new_err(format!("Engine overloaded: {}", msg))
+            }
         }
     }
 }
@@ -0,0 +1,250 @@
+//! BigQuery's `EXPORT DATA` statement
+//!
+//! ```sql
+//! EXPORT DATA OPTIONS (
+//!   uri = 'gs://analytics/exports/trips-*.parquet',
+//!   format = 'PARQUET',
+//!   compression = 'ZSTD',
+//!   overwrite = true
+//! ) AS SELECT * FROM trips WHERE fare > 10
+//! ```
+//!
+//! Export jobs written for BigQuery run unchanged against local paths and
+//! against object stores registered with the session, e.g. for `s3://` or
+//! `gs://` URLs. As in BigQuery the `uri` holds exactly one `*`, which is
+//! replaced by a 12-digit file number; the result is written as one file,
+//! `000000000000`. The `uri` must end in a file extension such as
+//! `.parquet`. Supported options:
+//!
+//! - `format`: `PARQUET`, `CSV` or `JSON` (newline-delimited)
+//! - `compression`: `NONE`, `GZIP`, and for Parquet also `SNAPPY` (the
+//!   Parquet default) and `ZSTD`
+//! - `overwrite`: replace files matching the `uri` instead of failing
+//!   (default: false); matching files other than the one written are kept
+//! - `header` and `field_delimiter` for CSV (defaults: true and `,`)
+//!
+//! The statement returns one row per written file with its `file`, `rows`
+//! and `bytes`.
+
+use std::sync::{Arc, LazyLock};
+
+use datafusion::arrow::array::{Int64Array, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::prelude::SessionContext;
+use futures::StreamExt;
+use regex::Regex;
+
+use crate::copy::{CopyDirection, CopySource, CopyStatement};
+use crate::error::{BlazeError, BlazeResult};
+use crate::file_tables::DataFormat;
+use crate::invalid_input;
+use crate::utils::{matching_paren, split_top_level};
+
+/// Number BigQuery gives the first file of an export
+const FIRST_FILE_NUMBER: &str = "000000000000";
+
+/// Parsed `EXPORT DATA` statement
+#[derive(Debug, Clone)]
+pub struct ExportStatement {
+    /// Destination with one `*` for the file number
+    pub uri: String,
+    pub format: DataFormat,
+    /// BigQuery compression name, uppercased
+    pub compression: Option<String>,
+    pub overwrite: bool,
+    pub header: bool,
+    pub field_delimiter: u8,
+    /// The exported query
+    pub query: String,
+}
+
+/// Parse `EXPORT DATA [WITH CONNECTION name] OPTIONS (...) AS query`.
+/// Returns `None` for other statements.
+pub fn parse_export_data(sql: &str) -> BlazeResult<Option<ExportStatement>> {
+    static HEADER: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*EXPORT\s+DATA\s+(?:WITH\s+CONNECTION\s+[`\w.-]+\s+)?OPTIONS\s*\(",
+        )
+        .unwrap()
+    });
+    let Some(found) = HEADER.find(sql) else {
+        return Ok(None);
+    };
+    let open = found.end() - 1;
+    let close = matching_paren(sql, open).ok_or_else(|| invalid_input!("Unbalanced parentheses in EXPORT DATA OPTIONS"))?;
+    static QUERY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^\s*AS\s+(.+?)\s*;?\s*$").unwrap());
+    let query = QUERY
+        .captures(&sql[close + 1..])
+        .ok_or_else(|| invalid_input!("Expected AS <query> after EXPORT DATA OPTIONS (...)"))?[1]
+        .to_string();
+
+    let mut uri = None;
+    let mut format = None;
+    let mut statement = ExportStatement {
+        uri: String::new(),
+        format: DataFormat::Parquet,
+        compression: None,
+        overwrite: false,
+        header: true,
+        field_delimiter: b',',
+        query,
+    };
+    for option in split_top_level(&sql[open + 1..close], ',').into_iter().map(str::trim).filter(|o| !o.is_empty()) {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| invalid_input!("Expected name = value in EXPORT DATA OPTIONS, got '{}'", option))?;
+        let value = unquote(value.trim());
+        match key.trim().to_lowercase().as_str() {
+            "uri" => uri = Some(value),
+            "format" => format = Some(DataFormat::parse(&value)?),
+            "compression" => statement.compression = Some(value.to_uppercase()),
+            "overwrite" => statement.overwrite = parse_bool(&value)?,
+            "header" => statement.header = parse_bool(&value)?,
+            "field_delimiter" if value.len() == 1 => statement.field_delimiter = value.as_bytes()[0],
+            "field_delimiter" => return Err(invalid_input!("EXPORT DATA field_delimiter must be a single character")),
+            other => return Err(invalid_input!("Unsupported EXPORT DATA option '{}'", other)),
+        }
+    }
+
+    statement.uri = uri.ok_or_else(|| invalid_input!("EXPORT DATA needs a uri option"))?;
+    if statement.uri.matches('*').count() != 1 {
+        return Err(invalid_input!("EXPORT DATA uri must contain exactly one '*' wildcard, got '{}'", statement.uri));
+    }
+    // Without an extension the file would be written as a directory
+    let file_name = statement.uri.rsplit('/').next().unwrap_or_default();
+    if !file_name.contains('.') {
+        return Err(invalid_input!("EXPORT DATA uri must end in a file extension such as '.parquet', got '{}'", statement.uri));
+    }
+    statement.format = format.ok_or_else(|| invalid_input!("EXPORT DATA needs a format option"))?;
+    statement.copy_compression()?;
+    Ok(Some(statement))
+}
+
+fn unquote(value: &str) -> String {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner.replace(&format!("{}{}", quote, quote), &quote.to_string());
+        }
+    }
+    value.to_string()
+}
+
+fn parse_bool(text: &str) -> BlazeResult<bool> {
+    match text.to_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(invalid_input!("Expected true or false, got '{}'", text)),
+    }
+}
+
+impl ExportStatement {
+    /// Path of the file written, the `uri` with its file number
+    pub fn file_path(&self) -> String {
+        self.uri.replacen('*', FIRST_FILE_NUMBER, 1)
+    }
+
+    /// The `COPY` statement writing the export
+    pub fn to_copy(&self) -> BlazeResult<CopyStatement> {
+        Ok(CopyStatement {
+            source: CopySource::Query(self.query.clone()),
+            direction: CopyDirection::To,
+            path: self.file_path(),
+            format: self.format,
+            header: self.header,
+            delimiter: self.field_delimiter,
+            compression: self.copy_compression()?,
+        })
+    }
+
+    /// DataFusion's name for the compression codec
+    fn copy_compression(&self) -> BlazeResult<Option<String>> {
+        let codec = match (self.format, self.compression.as_deref()) {
+            (_, None) => return Ok(None),
+            (DataFormat::Parquet, Some("NONE")) | (DataFormat::Csv | DataFormat::Json, Some("NONE")) => "uncompressed",
+            (DataFormat::Parquet, Some("SNAPPY")) => "snappy",
+            (DataFormat::Parquet, Some("GZIP")) => "gzip(6)",
+            (DataFormat::Parquet, Some("ZSTD")) => "zstd(3)",
+            (DataFormat::Csv | DataFormat::Json, Some("GZIP")) => "gzip",
+            (format, Some(other)) => {
+                return Err(invalid_input!(
+                    "EXPORT DATA compression '{}' is not supported for {:?}; use NONE or GZIP{}",
+                    other,
+                    format,
+                    if format == DataFormat::Parquet { ", SNAPPY or ZSTD" } else { "" }
+                ))
+            }
+        };
+        Ok(Some(codec.to_string()))
+    }
+}
+
+/// Files matching a `uri` with a `*` wildcard, with their sizes
+pub(crate) async fn matching_files(ctx: &SessionContext, uri: &str) -> BlazeResult<Vec<(String, u64)>> {
+    let url = ListingTableUrl::parse(uri)?;
+    let state = ctx.state();
+    let store = state.runtime_env().object_store(url.object_store())?;
+    let mut files = Vec::new();
+    let mut listing = url.list_all_files(&state, store.as_ref(), "").await?;
+    while let Some(file) = listing.next().await {
+        // A prefix that does not exist yet has no files
+        let Ok(file) = file else { continue };
+        files.push((file.location.to_string(), file.size as u64));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Result of an export: one row per file with its rows and bytes
+pub(crate) fn report_batch(files: &[(String, u64, u64)]) -> BlazeResult<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("file", DataType::Utf8, false),
+        Field::new("rows", DataType::Int64, false),
+        Field::new("bytes", DataType::Int64, false),
+    ]));
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from_iter_values(files.iter().map(|(file, _, _)| file.as_str()))),
+            Arc::new(Int64Array::from_iter_values(files.iter().map(|(_, rows, _)| *rows as i64))),
+            Arc::new(Int64Array::from_iter_values(files.iter().map(|(_, _, bytes)| *bytes as i64))),
+        ],
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export_data() {
+        let sql = "EXPORT DATA OPTIONS(uri='gs://b/out/part-*.csv', format=\"CSV\", overwrite=true, header=false, \
+                   field_delimiter='|', compression='gzip') AS SELECT a, ')' AS b FROM t;";
+        let statement = parse_export_data(sql).unwrap().unwrap();
+        assert_eq!(statement.query, "SELECT a, ')' AS b FROM t");
+        assert_eq!((statement.format, statement.overwrite, statement.header), (DataFormat::Csv, true, false));
+        assert_eq!(statement.file_path(), "gs://b/out/part-000000000000.csv");
+        let copy = statement.to_copy().unwrap();
+        assert_eq!((copy.delimiter, copy.compression.as_deref()), (b'|', Some("gzip")));
+
+        let connection = "export data with connection `us.lake` options (uri = '/tmp/x/*.parquet', format = 'PARQUET') as select 1";
+        let statement = parse_export_data(connection).unwrap().unwrap();
+        assert_eq!((statement.format, statement.query.as_str()), (DataFormat::Parquet, "select 1"));
+        assert!(statement.to_copy().unwrap().compression.is_none());
+
+        assert!(parse_export_data("SELECT 1").unwrap().is_none());
+        for invalid in [
+            "EXPORT DATA OPTIONS(uri='/tmp/out.parquet', format='PARQUET') AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/*/*.parquet', format='PARQUET') AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/out/*', format='PARQUET') AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/*.parquet') AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/*.avro', format='AVRO') AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/*.csv', format='CSV', compression='SNAPPY') AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/*.csv', format='CSV', partitions=4) AS SELECT 1",
+            "EXPORT DATA OPTIONS(uri='/tmp/*.csv', format='CSV')",
+        ] {
+            assert!(parse_export_data(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,712 @@
+//! Tables backed by files matched by a glob
+//!
+//! `register_files("trips", "data/2024-*/part-*.parquet", None)` lists the
+//! matching files once, checks that they agree on column names and types,
+//! and registers them as one table that is scanned in place. The files
+//! found at registration are recorded with their sizes; files added later
+//! are picked up by registering the table again.
+//!
+//! `register_csv` does the same for CSV files read with `CsvTableOptions`:
+//! delimiter, header and quoting controls, how many rows column types are
+//! inferred from, and an explicit schema that skips inference.
+//! `register_json` reads newline-delimited JSON, inferring the schema from
+//! a sample of rows across all the files, so fields that only some files
+//! have are read as NULL elsewhere.
+//!
+//! `register_table_from_glob("events", "data/events/2024-*/*.parquet")`
+//! relaxes the schema check for exports whose columns changed over time:
+//! the table has every column found in any file, in order of first
+//! appearance, and files without a column read it as NULL. A column must
+//! still have the same type in every file that has it. CSV files are read
+//! by position rather than by name, so only Parquet and JSON files can be
+//! merged this way.
+//!
+//! `register_partitioned("orders", "exports/orders", None)` reads a
+//! directory laid out Hive-style, as `dt=2024-06-01/region=eu/part-0.parquet`.
+//! Each `column=value` directory level becomes a column of the table, typed
+//! `INT64` or `DATE` when every value parses as one and `STRING` otherwise,
+//! and filters on those columns skip the directories they rule out without
+//! listing or opening their files.
+//!
+//! The same tables can be created from SQL, so files larger than the
+//! memory limit can be queried from the query editor without loading them:
+//!
+//! ```sql
+//! CREATE EXTERNAL TABLE trips STORED AS PARQUET LOCATION 'data/2024-*/part-*.parquet';
+//! CREATE OR REPLACE EXTERNAL TABLE events LOCATION 'exports/events.csv';
+//! ```
+//!
+//! registers the location like `register_files`, with the format taken
+//! from `STORED AS` or the extension. Statements declaring columns,
+//! partitions or options are left to DataFusion and are not recorded.
+//!
+//! CSV and JSON files compressed with gzip or zstd, named like
+//! `trips.csv.gz` or `events.json.zst`, are decompressed as they are read.
+//! All the files of a table must be compressed the same way.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock};
+
+use chrono::NaiveDate;
+use regex::Regex;
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::datasource::file_format::csv::CsvFormat;
+use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
+use datafusion::datasource::file_format::json::JsonFormat;
+use datafusion::datasource::file_format::parquet::ParquetFormat;
+use datafusion::datasource::file_format::FileFormat;
+use datafusion::datasource::listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl};
+use datafusion::prelude::SessionContext;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::PayloadCompression;
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Files whose schemas are inferred at once while registering a table
+const SCHEMA_INFERENCE_CONCURRENCY: usize = 32;
+
+/// File formats tables can be read from and written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    Parquet,
+    Csv,
+    Json,
+}
+
+impl DataFormat {
+    /// Parse a format name such as `parquet` or `CSV`
+    pub fn parse(name: &str) -> BlazeResult<Self> {
+        match name.to_lowercase().as_str() {
+            "parquet" => Ok(Self::Parquet),
+            "csv" => Ok(Self::Csv),
+            "json" | "ndjson" => Ok(Self::Json),
+            _ => Err(invalid_input!("Unsupported file format '{}'; expected PARQUET, CSV or JSON", name)),
+        }
+    }
+
+    /// The format implied by a path's extension, looking past a `.gz` or
+    /// `.zst` suffix on CSV and JSON files
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (path, compression) = split_compression(path);
+        let extension = path.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "parquet" if compression.is_none() => Some(Self::Parquet),
+            "csv" => Some(Self::Csv),
+            "json" | "ndjson" | "jsonl" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Name used in `STORED AS`
+    pub(crate) fn stored_as(self) -> &'static str {
+        match self {
+            Self::Parquet => "PARQUET",
+            Self::Csv => "CSV",
+            Self::Json => "JSON",
+        }
+    }
+
+    fn listing_format(self, compression: Option<PayloadCompression>) -> Arc<dyn FileFormat> {
+        match self {
+            Self::Parquet => Arc::new(ParquetFormat::default()),
+            Self::Csv => Arc::new(
+                CsvFormat::default().with_has_header(true).with_file_compression_type(compression_type(compression)),
+            ),
+            Self::Json => Arc::new(JsonFormat::default().with_file_compression_type(compression_type(compression))),
+        }
+    }
+}
+
+/// A path without its `.gz` or `.zst` suffix, and the compression the
+/// suffix names
+pub(crate) fn split_compression(path: &str) -> (&str, Option<PayloadCompression>) {
+    match path.rsplit_once('.') {
+        Some((stem, suffix)) if !suffix.contains('/') => match PayloadCompression::parse(suffix) {
+            Ok(compression) => (stem, Some(compression)),
+            Err(_) => (path, None),
+        },
+        _ => (path, None),
+    }
+}
+
+/// How DataFusion decompresses files compressed with `compression`
+pub(crate) fn compression_type(compression: Option<PayloadCompression>) -> FileCompressionType {
+    match compression {
+        Some(PayloadCompression::Gzip) => FileCompressionType::GZIP,
+        Some(PayloadCompression::Zstd) => FileCompressionType::ZSTD,
+        None => FileCompressionType::UNCOMPRESSED,
+    }
+}
+
+/// How the files of a table are found and their schemas combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileListing {
+    /// Files matching a glob, which must have the same columns
+    #[default]
+    Glob,
+    /// Files matching a glob, read with the union of their columns
+    MergedGlob,
+    /// A directory of `column=value` partition directories
+    Partitioned,
+}
+
+/// Parsed `CREATE EXTERNAL TABLE ... LOCATION` statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CreateExternalTable {
+    pub name: String,
+    pub location: String,
+    pub format: Option<DataFormat>,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+}
+
+/// Parse `CREATE [OR REPLACE] EXTERNAL TABLE [IF NOT EXISTS] name
+/// [STORED AS format] LOCATION 'path'`. Returns `None` for other
+/// statements, including external tables with clauses this form does not
+/// have, and for formats other than Parquet, CSV and JSON, which DataFusion
+/// registers itself.
+pub(crate) fn parse_create_external_table(sql: &str) -> BlazeResult<Option<CreateExternalTable>> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*CREATE\s+(OR\s+REPLACE\s+)?EXTERNAL\s+TABLE\s+(IF\s+NOT\s+EXISTS\s+)?([A-Za-z_]\w*)\s+(?:STORED\s+AS\s+(\w+)\s+)?LOCATION\s+'((?:[^']|'')*)'\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let Some(captures) = PATTERN.captures(sql) else {
+        return Ok(None);
+    };
+    let format = match captures.get(4) {
+        Some(name) => match DataFormat::parse(name.as_str()) {
+            Ok(format) => Some(format),
+            Err(_) => return Ok(None),
+        },
+        None => None,
+    };
+    let statement = CreateExternalTable {
+        name: captures[3].to_string(),
+        location: captures[5].replace("''", "'"),
+        format,
+        or_replace: captures.get(1).is_some(),
+        if_not_exists: captures.get(2).is_some(),
+    };
+    if statement.or_replace && statement.if_not_exists {
+        return Err(invalid_input!("CREATE EXTERNAL TABLE cannot have both OR REPLACE and IF NOT EXISTS"));
+    }
+    Ok(Some(statement))
+}
+
+/// How `register_csv` reads CSV files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvTableOptions {
+    /// Whether the first line holds the column names (default: true);
+    /// without one columns are named `column_1`, `column_2`, ...
+    pub has_header: bool,
+    /// Field delimiter (default: `,`)
+    pub delimiter: u8,
+    /// Quote character (default: `"`)
+    pub quote: u8,
+    /// Escape character inside quoted fields; without one a quote is
+    /// escaped by doubling it (default: none)
+    pub escape: Option<u8>,
+    /// Lines starting with this character are skipped (default: none)
+    pub comment: Option<u8>,
+    /// Whether quoted fields may span lines (default: false)
+    pub newlines_in_values: bool,
+    /// Rows per file column types are inferred from (default: 1000)
+    pub infer_rows: usize,
+    /// Whether to infer column types; without it every column is read as a
+    /// string (default: true)
+    pub infer_types: bool,
+    /// Column names and types to read the files with instead of inferring
+    /// them
+    #[serde(with = "type_names")]
+    pub schema: Option<Vec<(String, DataType)>>,
+}
+
+impl Default for CsvTableOptions {
+    fn default() -> Self {
+        Self {
+            has_header: true,
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            newlines_in_values: false,
+            infer_rows: 1000,
+            infer_types: true,
+            schema: None,
+        }
+    }
+}
+
+impl CsvTableOptions {
+    fn listing_format(&self, compression: Option<PayloadCompression>) -> Arc<dyn FileFormat> {
+        Arc::new(
+            CsvFormat::default()
+                .with_file_compression_type(compression_type(compression))
+                .with_has_header(self.has_header)
+                .with_delimiter(self.delimiter)
+                .with_quote(self.quote)
+                .with_escape(self.escape)
+                .with_comment(self.comment)
+                .with_newlines_in_values(self.newlines_in_values)
+                .with_schema_infer_max_rec(self.infer_rows),
+        )
+    }
+
+    /// The schema the files are read with when it is not inferred
+    fn fixed_schema(&self) -> Option<SchemaRef> {
+        let columns = self.schema.as_ref()?;
+        Some(Arc::new(Schema::new(
+            columns.iter().map(|(name, data_type)| Field::new(name, data_type.clone(), true)).collect::<Vec<_>>(),
+        )))
+    }
+}
+
+/// How `register_json` reads newline-delimited JSON files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonTableOptions {
+    /// Rows the schema is inferred from, counted across all files
+    /// (default: 1000)
+    pub infer_rows: usize,
+}
+
+impl Default for JsonTableOptions {
+    fn default() -> Self {
+        Self { infer_rows: 1000 }
+    }
+}
+
+impl JsonTableOptions {
+    fn listing_format(&self, compression: Option<PayloadCompression>) -> Arc<dyn FileFormat> {
+        Arc::new(
+            JsonFormat::default()
+                .with_schema_infer_max_rec(self.infer_rows)
+                .with_file_compression_type(compression_type(compression)),
+        )
+    }
+}
+
+/// Serializes column types by their Arrow names, e.g. `Int64` or
+/// `Timestamp(Microsecond, None)`
+mod type_names {
+    use datafusion::arrow::datatypes::DataType;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(columns: &Option<Vec<(String, DataType)>>, serializer: S) -> Result<S::Ok, S::Error> {
+        columns
+            .as_ref()
+            .map(|columns| columns.iter().map(|(name, data_type)| (name, data_type.to_string())).collect::<Vec<_>>())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<(String, DataType)>>, D::Error> {
+        let columns = Option::<Vec<(String, String)>>::deserialize(deserializer)?;
+        columns
+            .map(|columns| {
+                columns
+                    .into_iter()
+                    .map(|(name, type_name)| Ok((name, type_name.parse().map_err(serde::de::Error::custom)?)))
+                    .collect()
+            })
+            .transpose()
+    }
+}
+
+/// Files behind a table registered from a glob
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTableInfo {
+    /// Table name
+    pub table_name: String,
+    /// Glob the files were matched with
+    pub pattern: String,
+    /// Format of every file
+    pub format: DataFormat,
+    /// How the files were found and their schemas combined
+    #[serde(default)]
+    pub listing: FileListing,
+    /// Number of files matched
+    pub file_count: usize,
+    /// Combined size of the files in bytes
+    pub total_bytes: u64,
+    /// Matched files, sorted
+    pub files: Vec<String>,
+    /// How the files are read, for tables registered with `register_csv`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csv_options: Option<CsvTableOptions>,
+    /// How the files are read, for tables registered with `register_json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_options: Option<JsonTableOptions>,
+    /// Columns read from `column=value` directories with their types, for
+    /// tables registered with `register_partitioned`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub partition_columns: Vec<(String, String)>,
+    /// Distinct partition directories holding files
+    #[serde(default)]
+    pub partitions: usize,
+    /// Compression of the files, for CSV and JSON files named with a `.gz`
+    /// or `.zst` suffix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<PayloadCompression>,
+}
+
+/// List the files matching `pattern`, check their schemas agree and build a
+/// table scanning all of them. CSV and JSON files are read with
+/// `csv_options` and `json_options` when given. `listing` says whether the
+/// files' columns are merged or `pattern` is a directory of `column=value`
+/// subdirectories whose values become partition columns.
+pub(crate) async fn build_table(
+    ctx: &SessionContext,
+    name: &str,
+    pattern: &str,
+    format: Option<DataFormat>,
+    csv_options: Option<&CsvTableOptions>,
+    json_options: Option<&JsonTableOptions>,
+    listing: FileListing,
+) -> BlazeResult<(Arc<ListingTable>, FileTableInfo)> {
+    let partitioned = listing == FileListing::Partitioned;
+    let format = format.or_else(|| DataFormat::from_path(pattern));
+    if format.is_none() && !partitioned {
+        return Err(invalid_input!("Cannot infer the format of '{}'; specify PARQUET, CSV or JSON", pattern));
+    }
+
+    // A trailing slash makes the listing treat a partitioned path as a
+    // directory
+    let url = if partitioned {
+        ListingTableUrl::parse(format!("{}/", pattern.trim_end_matches('/')))?
+    } else {
+        ListingTableUrl::parse(pattern)?
+    };
+    // Match the glob against whole paths below its prefix, not only the
+    // first segment
+    let mut state = ctx.state();
+    state.config_mut().options_mut().execution.listing_table_ignore_subdirectory = false;
+    let store = state.runtime_env().object_store(url.object_store())?;
+    let mut files: Vec<_> = url.list_all_files(&state, store.as_ref(), "").await?.try_collect().await?;
+    files.sort_by(|a, b| a.location.cmp(&b.location));
+
+    let format = match format {
+        Some(format) => format,
+        None => files
+            .iter()
+            .find_map(|file| DataFormat::from_path(file.location.as_ref()))
+            .ok_or_else(|| invalid_input!("No Parquet, CSV or JSON files under '{}'", pattern))?,
+    };
+    let mut partitions = None;
+    let mut extension = String::new();
+    if partitioned {
+        // Partitioned directories also hold files such as `_SUCCESS`
+        // markers; only files with the extension of the first data file are
+        // read
+        extension = files
+            .iter()
+            .find(|file| DataFormat::from_path(file.location.as_ref()) == Some(format))
+            .and_then(|file| file.location.extension())
+            .map(|extension| format!(".{}", extension))
+            .ok_or_else(|| invalid_input!("No {} files under '{}'", format.stored_as(), pattern))?;
+        files.retain(|file| file.location.as_ref().ends_with(&extension));
+        let directories = files
+            .iter()
+            .map(|file| partition_directories(&url, file.location.as_ref()))
+            .collect::<Vec<Vec<String>>>();
+        partitions = Some(partition_columns(pattern, &directories)?);
+    }
+    if files.is_empty() {
+        return Err(invalid_input!("No files match '{}'", pattern));
+    }
+
+    if listing == FileListing::MergedGlob && format == DataFormat::Csv {
+        return Err(invalid_input!(
+            "Cannot merge the columns of the CSV files matching '{}': CSV columns are read by position",
+            pattern
+        ));
+    }
+
+    // Files are decompressed by suffix, which must be the same for all of
+    // them
+    let compression = split_compression(files[0].location.as_ref()).1;
+    if let Some(file) = files.iter().find(|file| split_compression(file.location.as_ref()).1 != compression) {
+        return Err(invalid_input!(
+            "'{}' and '{}' are compressed differently; the files of a table must share one compression",
+            files[0].location,
+            file.location
+        ));
+    }
+    if format == DataFormat::Parquet && compression.is_some() {
+        return Err(invalid_input!("Compressed Parquet files such as '{}' cannot be read", files[0].location));
+    }
+
+    let csv_options = csv_options.filter(|_| format == DataFormat::Csv);
+    let json_options = json_options.filter(|_| format == DataFormat::Json);
+    let file_format = match (csv_options, json_options) {
+        (Some(options), _) => options.listing_format(compression),
+        (_, Some(options)) => options.listing_format(compression),
+        _ => format.listing_format(compression),
+    };
+    let schema = match csv_options.and_then(CsvTableOptions::fixed_schema) {
+        Some(schema) => schema,
+        // Sample the files together, merging the fields each has
+        None if json_options.is_some() => file_format.infer_schema(&state, &store, &files).await?,
+        None => {
+            // Infer each file on its own so a mismatch can name the file
+            // The closure takes owned metadata: one borrowing it makes the
+            // future not `Send` for every lifetime, as spawned queries need
+            let schemas: Vec<SchemaRef> = futures::stream::iter(files.iter().cloned())
+                .map(|file| {
+                    let (file_format, state, store) = (&file_format, &state, &store);
+                    async move { file_format.infer_schema(state, store, &[file]).await }
+                })
+                .buffered(SCHEMA_INFERENCE_CONCURRENCY)
+                .try_collect()
+                .await?;
+            let named: Vec<_> = files.iter().map(|file| file.location.as_ref()).zip(schemas).collect();
+            let schema = if listing == FileListing::MergedGlob {
+                union_schemas(&named)?
+            } else {
+                let (first, mut schema) = named[0].clone();
+                for (file, file_schema) in &named[1..] {
+                    schema = merge_schemas(&schema, first, file_schema, file)?;
+                }
+                schema
+            };
+            if csv_options.is_some_and(|options| !options.infer_types) {
+                let fields = schema.fields().iter().map(|field| Field::new(field.name(), DataType::Utf8, true)).collect::<Vec<_>>();
+                Arc::new(Schema::new(fields))
+            } else {
+                schema
+            }
+        }
+    };
+
+    if let Some((column, _)) = partitions
+        .iter()
+        .flat_map(|(columns, _)| columns)
+        .find(|(column, _)| schema.field_with_name(column).is_ok())
+    {
+        return Err(BlazeError::SchemaMismatch(format!(
+            "column '{}' is in both the files and the directory names under '{}'",
+            column, pattern
+        )));
+    }
+
+    let config = match &partitions {
+        // Listed on every scan, so filters on partition columns prune
+        // directories before their files are listed
+        Some((columns, _)) => {
+            let options = ListingOptions::new(file_format)
+                .with_file_extension(extension)
+                .with_table_partition_cols(columns.clone());
+            ListingTableConfig::new(url.clone()).with_listing_options(options)
+        }
+        None => {
+            // Locations are already percent-encoded object store paths;
+            // escape them again so parsing the URL does not decode names
+            // such as `city=a%2Fb`
+            let store_url = url.object_store();
+            let paths = files
+                .iter()
+                .map(|file| {
+                    let location = file.location.as_ref().replace('%', "%25").replace('#', "%23").replace('?', "%3F");
+                    ListingTableUrl::parse(format!("{}{}", store_url.as_str(), location))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            ListingTableConfig::new_with_multi_paths(paths).with_listing_options(ListingOptions::new(file_format).with_file_extension(""))
+        }
+    };
+    let table = ListingTable::try_new(config.with_schema(schema))?;
+
+    let info = FileTableInfo {
+        table_name: name.to_string(),
+        pattern: pattern.to_string(),
+        format,
+        listing,
+        file_count: files.len(),
+        total_bytes: files.iter().map(|file| file.size as u64).sum(),
+        files: files.iter().map(|file| file.location.to_string()).collect(),
+        csv_options: csv_options.cloned(),
+        json_options: json_options.cloned(),
+        partition_columns: partitions
+            .iter()
+            .flat_map(|(columns, _)| columns)
+            .map(|(column, data_type)| (column.clone(), data_type.to_string()))
+            .collect(),
+        partitions: partitions.map_or(0, |(_, count)| count),
+        compression,
+    };
+    Ok((Arc::new(table), info))
+}
+
+/// Combine file schemas into one with every column of any file, in order of
+/// first appearance. A column must have one type across the files that have
+/// it, and is nullable if it is in any file or missing from one.
+fn union_schemas(schemas: &[(&str, SchemaRef)]) -> BlazeResult<SchemaRef> {
+    let mut fields: Vec<(Field, &str, usize)> = Vec::new();
+    for (file, schema) in schemas {
+        for field in schema.fields() {
+            match fields.iter_mut().find(|(merged, _, _)| merged.name() == field.name()) {
+                Some((merged, first_file, files)) => {
+                    if merged.data_type() != field.data_type() {
+                        return Err(BlazeError::SchemaMismatch(format!(
+                            "column '{}' is {} in '{}' but {} in '{}'",
+                            field.name(),
+                            field.data_type(),
+                            file,
+                            merged.data_type(),
+                            first_file
+                        )));
+                    }
+                    *merged = merged.clone().with_nullable(merged.is_nullable() || field.is_nullable());
+                    *files += 1;
+                }
+                None => fields.push((field.as_ref().clone(), file, 1)),
+            }
+        }
+    }
+
+    let fields = fields
+        .into_iter()
+        .map(|(field, _, files)| {
+            let nullable = field.is_nullable() || files < schemas.len();
+            field.with_nullable(nullable)
+        })
+        .collect::<Vec<_>>();
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// The directories between `root` and the file at `location`, e.g.
+/// `["dt=2024-01-01", "region=eu"]`
+fn partition_directories(root: &ListingTableUrl, location: &str) -> Vec<String> {
+    let relative = location.strip_prefix(root.prefix().as_ref()).unwrap_or_default();
+    let segments: Vec<_> = relative.split('/').filter(|segment| !segment.is_empty()).collect();
+    segments[..segments.len().saturating_sub(1)].iter().map(|segment| segment.to_string()).collect()
+}
+
+/// Partition columns with their types from the `column=value` directories
+/// each file is in, relative to `root`, and the number of distinct
+/// directories. Every file must be under the same columns in the same order.
+fn partition_columns(root: &str, directories: &[Vec<String>]) -> BlazeResult<(Vec<(String, DataType)>, usize)> {
+    let mut columns: Option<Vec<String>> = None;
+    let mut values: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for segments in directories {
+        let pairs = segments
+            .iter()
+            .map(|segment| segment.split_once('=').filter(|(column, _)| !column.is_empty()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid_input!("'{}/{}' is not a column=value directory", root, segments.join("/")))?;
+        let names: Vec<String> = pairs.iter().map(|(column, _)| column.to_string()).collect();
+        match &columns {
+            None => columns = Some(names),
+            Some(expected) if *expected != names => {
+                return Err(invalid_input!(
+                    "Directories under '{}' are partitioned by {:?} in one place and {:?} in another",
+                    root,
+                    expected,
+                    names
+                ))
+            }
+            Some(_) => {}
+        }
+        for (index, (_, value)) in pairs.iter().enumerate() {
+            values.entry(index).or_default().push(value.to_string());
+        }
+    }
+    let columns = columns.unwrap_or_default();
+    if columns.is_empty() {
+        return Err(invalid_input!("No column=value directories under '{}'; use register_files for unpartitioned files", root));
+    }
+
+    let typed = columns
+        .into_iter()
+        .enumerate()
+        .map(|(index, column)| {
+            let values = &values[&index];
+            let data_type = if values.iter().all(|value| value.parse::<i64>().is_ok()) {
+                DataType::Int64
+            } else if values.iter().all(|value| NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()) {
+                DataType::Date32
+            } else {
+                DataType::Utf8
+            };
+            (column, data_type)
+        })
+        .collect();
+    let mut distinct = directories.to_vec();
+    distinct.dedup();
+    Ok((typed, distinct.len()))
+}
+
+/// Combine two file schemas that must have the same columns with the same
+/// types; a column is nullable if it is in either file
+fn merge_schemas(merged: &Schema, first_file: &str, schema: &Schema, file: &str) -> BlazeResult<SchemaRef> {
+    let names = |schema: &Schema| schema.fields().iter().map(|f| f.name().clone()).collect::<Vec<_>>();
+    if names(merged) != names(schema) {
+        return Err(BlazeError::SchemaMismatch(format!(
+            "'{}' has columns {:?} but '{}' has {:?}",
+            file,
+            names(schema),
+            first_file,
+            names(merged)
+        )));
+    }
+
+    let fields = merged
+        .fields()
+        .iter()
+        .zip(schema.fields())
+        .map(|(expected, field)| {
+            if expected.data_type() != field.data_type() {
+                return Err(BlazeError::SchemaMismatch(format!(
+                    "column '{}' is {} in '{}' but {} in '{}'",
+                    field.name(),
+                    field.data_type(),
+                    file,
+                    expected.data_type(),
+                    first_file
+                )));
+            }
+            Ok(Field::new(field.name(), field.data_type().clone(), expected.is_nullable() || field.is_nullable()))
+        })
+        .collect::<BlazeResult<Vec<_>>>()?;
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_directories() {
+        let root = ListingTableUrl::parse("file:///data/exports/").unwrap();
+        assert_eq!(
+            partition_directories(&root, "data/exports/dt=2024-01-01/region=eu/part-0.parquet"),
+            ["dt=2024-01-01", "region=eu"]
+        );
+        assert!(partition_directories(&root, "data/exports/part-0.parquet").is_empty());
+        // Only the directories below the root count
+        let root = ListingTableUrl::parse("file:///data/year=2024/").unwrap();
+        assert_eq!(partition_directories(&root, "data/year=2024/city=Paris/part-0.parquet"), ["city=Paris"]);
+    }
+
+    #[test]
+    fn test_union_schemas() {
+        let schema = |fields: Vec<Field>| Arc::new(Schema::new(fields));
+        let first = schema(vec![Field::new("id", DataType::Int64, false), Field::new("value", DataType::Float64, false)]);
+        let second = schema(vec![Field::new("value", DataType::Float64, true), Field::new("id", DataType::Int64, false)]);
+        let third = schema(vec![Field::new("id", DataType::Int64, false), Field::new("source", DataType::Utf8, false)]);
+
+        let merged = union_schemas(&[("a", first.clone()), ("b", second), ("c", third)]).unwrap();
+        let fields: Vec<_> = merged.fields().iter().map(|f| (f.name().as_str(), f.is_nullable())).collect();
+        // Nullable in one file, or missing from one, makes a column nullable
+        assert_eq!(fields, [("id", false), ("value", true), ("source", true)]);
+
+        let mismatched = schema(vec![Field::new("id", DataType::Utf8, false)]);
+        let error = union_schemas(&[("a", first), ("b", mismatched)]).unwrap_err().to_string();
+        assert!(error.contains("column 'id' is Utf8 in 'b' but Int64 in 'a'"), "{}", error);
+    }
+}
@@ -0,0 +1,285 @@
+//! Tables uploaded over Arrow Flight
+//!
+//! A [`FlightServer`] serves the engine as an Arrow Flight service accepting
+//! `DoPut` uploads, so producers with a Flight client in any language can
+//! push RecordBatches into tables without going through Python. The path
+//! descriptor of an upload names its table: `["orders"]` appends to
+//! `orders`, creating it with the upload's schema if it does not exist, and
+//! `["orders", "replace"]` replaces its contents.
+//!
+//! Batches are gathered until the client ends the stream and written at
+//! once, so a failed or abandoned upload leaves the table as it was. Each
+//! upload is answered with one `PutResult` whose metadata is the JSON object
+//! `{"table": "orders", "rows": 1000}`. Other Flight calls are unsupported.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse,
+    PollInfo, PutResult, SchemaResult, Ticket,
+};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::convert::try_schema_from_ipc_buffer;
+use datafusion::arrow::ipc::reader::StreamDecoder;
+use datafusion::arrow::record_batch::RecordBatch;
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, warn};
+
+use crate::engine::BlazeQueryEngine;
+use crate::error::{BlazeError, BlazeResult};
+use crate::flight_tables;
+use crate::{config_error, invalid_input};
+
+type FlightResult<T> = Result<Response<T>, Status>;
+
+/// Uploads received by a `FlightServer`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlightServerStats {
+    /// Address the server listens on
+    pub address: String,
+    /// Uploads written to their table
+    pub uploads: u64,
+    pub rows_uploaded: u64,
+    /// Uploads rejected or cut off before they were written
+    pub failed_uploads: u64,
+    /// Error of the most recent failed upload
+    pub last_error: Option<String>,
+}
+
+/// A Flight service writing uploads to the engine's tables in the background
+pub struct FlightServer {
+    address: SocketAddr,
+    stats: Arc<Mutex<FlightServerStats>>,
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<Result<(), tonic::transport::Error>>,
+}
+
+impl FlightServer {
+    /// Listen on `address`, e.g. `0.0.0.0:8815`; port 0 picks a free port,
+    /// reported by `address()`
+    pub async fn start(engine: Arc<BlazeQueryEngine>, address: &str) -> BlazeResult<Self> {
+        let listener = tokio::net::TcpListener::bind(address)
+            .await
+            .map_err(|e| config_error!("Cannot listen for Flight uploads on {}: {}", address, e))?;
+        let address = listener.local_addr()?;
+        let stats = Arc::new(Mutex::new(FlightServerStats { address: address.to_string(), ..Default::default() }));
+
+        let incoming = stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(socket, _)| socket);
+            Some((connection, listener))
+        });
+        // Producers send batches of any size, well above gRPC's 4MB default
+        let service = FlightServiceServer::new(UploadService { engine, stats: stats.clone() })
+            .max_decoding_message_size(usize::MAX);
+        let (shutdown, mut stopped) = watch::channel(false);
+        let server = tonic::transport::Server::builder().add_service(service);
+        let task = tokio::spawn(server.serve_with_incoming_shutdown(incoming, async move {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+        }));
+
+        info!("Accepting Flight uploads on {}", address);
+        Ok(Self { address, stats, shutdown, task })
+    }
+
+    /// Address the server listens on
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Uploads so far
+    pub fn stats(&self) -> FlightServerStats {
+        self.stats.lock().clone()
+    }
+
+    /// Whether the server is still accepting uploads
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Stop accepting connections, let uploads in progress finish and wait
+    /// for the server to exit
+    pub async fn stop(self) -> BlazeResult<FlightServerStats> {
+        let _ = self.shutdown.send(true);
+        self.task
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Flight server on {} failed: {}", self.address, e)))?
+            .map_err(|e| BlazeError::External(format!("Flight server on {}: {}", self.address, e)))?;
+        Ok(self.stats.lock().clone())
+    }
+}
+
+/// How an upload changes its table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UploadMode {
+    Append,
+    Replace,
+}
+
+/// The table an upload's descriptor names and what to do with it
+fn upload_target(descriptor: Option<&FlightDescriptor>) -> BlazeResult<(String, UploadMode)> {
+    let path = descriptor.map(|descriptor| descriptor.path.as_slice()).unwrap_or_default();
+    let mode = match path.get(1).map(String::as_str) {
+        None | Some("append") => UploadMode::Append,
+        Some("replace") => UploadMode::Replace,
+        Some(other) => return Err(invalid_input!("Unknown upload mode '{}'; use append or replace", other)),
+    };
+    match path {
+        [table] | [table, _] if !table.is_empty() => Ok((table.clone(), mode)),
+        _ => Err(invalid_input!("Flight uploads name their table with a path descriptor such as [\"orders\"]")),
+    }
+}
+
+/// The status a failed upload is answered with
+fn upload_status(error: &BlazeError) -> Status {
+    match error {
+        BlazeError::InvalidInput(_)
+        | BlazeError::SchemaMismatch(_)
+        | BlazeError::TableNotFound { .. }
+        | BlazeError::Arrow(_) => Status::invalid_argument(error.to_string()),
+        BlazeError::External(_) => Status::aborted(error.to_string()),
+        _ => Status::internal(error.to_string()),
+    }
+}
+
+struct UploadService {
+    engine: Arc<BlazeQueryEngine>,
+    stats: Arc<Mutex<FlightServerStats>>,
+}
+
+impl UploadService {
+    /// Read an upload to its end and write it, returning the table and the
+    /// rows written
+    async fn upload(&self, messages: &mut Streaming<FlightData>) -> BlazeResult<(String, usize)> {
+        let mut target = None;
+        let mut schema: Option<SchemaRef> = None;
+        let mut decoder = StreamDecoder::new();
+        let mut batches = Vec::new();
+        while let Some(message) = messages
+            .message()
+            .await
+            .map_err(|status| BlazeError::External(format!("Flight upload ended early: {}", status.message())))?
+        {
+            // Only the first message carries the descriptor
+            if target.is_none() {
+                target = Some(upload_target(message.flight_descriptor.as_ref())?);
+            }
+            if schema.is_none() && !message.data_header.is_empty() {
+                let header = flight_tables::ipc_frame(&message.data_header, &[]);
+                schema = Some(Arc::new(try_schema_from_ipc_buffer(&header)?));
+            }
+            if let Some(batch) = flight_tables::decode_message(&mut decoder, &message)? {
+                batches.push(batch);
+            }
+        }
+        let (table, mode) = target.ok_or_else(|| invalid_input!("Flight upload sent no messages"))?;
+        let schema = schema.ok_or_else(|| invalid_input!("Flight upload to '{}' sent no schema", table))?;
+
+        let rows = batches.iter().map(|batch| batch.num_rows()).sum();
+        let exists = self.engine.table_schema(&table).await.is_some();
+        if mode == UploadMode::Append && exists {
+            self.engine.append_to_table(&table, batches).await?;
+        } else {
+            if batches.is_empty() {
+                batches.push(RecordBatch::new_empty(schema));
+            }
+            self.engine.register_table(&table, batches).await?;
+        }
+        Ok((table, rows))
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for UploadService {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+
+    async fn do_put(&self, request: Request<Streaming<FlightData>>) -> FlightResult<Self::DoPutStream> {
+        let mut messages = request.into_inner();
+        match self.upload(&mut messages).await {
+            Ok((table, rows)) => {
+                {
+                    let mut stats = self.stats.lock();
+                    stats.uploads += 1;
+                    stats.rows_uploaded += rows as u64;
+                }
+                info!("Flight upload wrote {} rows to '{}'", rows, table);
+                let metadata = serde_json::json!({ "table": table, "rows": rows }).to_string();
+                let result = PutResult { app_metadata: metadata.into() };
+                Ok(Response::new(stream::once(async move { Ok(result) }).boxed()))
+            }
+            Err(e) => {
+                warn!("Flight upload failed: {}", e);
+                let status = upload_status(&e);
+                let mut stats = self.stats.lock();
+                stats.failed_uploads += 1;
+                stats.last_error = Some(e.to_string());
+                Err(status)
+            }
+        }
+    }
+
+    async fn handshake(&self, _: Request<Streaming<HandshakeRequest>>) -> FlightResult<Self::HandshakeStream> {
+        Err(Status::unimplemented("handshake"))
+    }
+
+    async fn list_flights(&self, _: Request<Criteria>) -> FlightResult<Self::ListFlightsStream> {
+        Err(Status::unimplemented("list_flights"))
+    }
+
+    async fn get_flight_info(&self, _: Request<FlightDescriptor>) -> FlightResult<FlightInfo> {
+        Err(Status::unimplemented("get_flight_info"))
+    }
+
+    async fn poll_flight_info(&self, _: Request<FlightDescriptor>) -> FlightResult<PollInfo> {
+        Err(Status::unimplemented("poll_flight_info"))
+    }
+
+    async fn get_schema(&self, _: Request<FlightDescriptor>) -> FlightResult<SchemaResult> {
+        Err(Status::unimplemented("get_schema"))
+    }
+
+    async fn do_get(&self, _: Request<Ticket>) -> FlightResult<Self::DoGetStream> {
+        Err(Status::unimplemented("do_get"))
+    }
+
+    async fn do_exchange(&self, _: Request<Streaming<FlightData>>) -> FlightResult<Self::DoExchangeStream> {
+        Err(Status::unimplemented("do_exchange"))
+    }
+
+    async fn do_action(&self, _: Request<Action>) -> FlightResult<Self::DoActionStream> {
+        Err(Status::unimplemented("do_action"))
+    }
+
+    async fn list_actions(&self, _: Request<Empty>) -> FlightResult<Self::ListActionsStream> {
+        Err(Status::unimplemented("list_actions"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_target() {
+        let path = |parts: &[&str]| FlightDescriptor::new_path(parts.iter().map(|p| p.to_string()).collect());
+        assert_eq!(upload_target(Some(&path(&["orders"]))).unwrap(), ("orders".to_string(), UploadMode::Append));
+        assert_eq!(upload_target(Some(&path(&["orders", "replace"]))).unwrap(), ("orders".to_string(), UploadMode::Replace));
+        assert!(upload_target(Some(&path(&["orders", "merge"]))).is_err());
+        assert!(upload_target(Some(&path(&["a", "b", "c"]))).is_err());
+        assert!(upload_target(Some(&FlightDescriptor::new_cmd(b"orders".to_vec()))).is_err());
+        assert!(upload_target(None).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! Tables streamed from remote Arrow Flight services
+//!
+//! `register_flight("prices", "grpc://pricing:8815", FlightSource::Path(vec!["prices".into()]))`
+//! asks the service for the flight's schema once and registers a table that
+//! fetches the data again on every scan:
+//!
+//! ```sql
+//! SELECT o.id, p.price FROM orders o JOIN prices p ON o.sku = p.sku;
+//! ```
+//!
+//! A flight is named by a descriptor, a path or an opaque command, or by a
+//! ticket handed out by the service. For descriptors every scan calls
+//! `GetFlightInfo` and reads each endpoint of the answer as a partition of
+//! its own; a ticket is read as a single partition with `DoGet`. Batches are
+//! decoded as they arrive, so a remote stream never has to fit in memory.
+//! Nothing is pushed down beyond the projection and the LIMIT, which are
+//! applied to the decoded batches. Services behind TLS are reached with
+//! `grpc+tls://host:port` endpoints.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_flight::flight_service_client::FlightServiceClient;
+use arrow_flight::{FlightData, FlightDescriptor, FlightInfo, Ticket};
+use async_trait::async_trait;
+use datafusion::arrow::buffer::Buffer;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::ipc::convert::try_schema_from_ipc_buffer;
+use datafusion::arrow::ipc::reader::StreamDecoder;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::Session;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::empty::EmptyExec;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Prefix of each message in an Arrow IPC stream
+const CONTINUATION_MARKER: [u8; 4] = [0xff; 4];
+
+/// Location meaning "the service the flight info came from"
+const REUSE_CONNECTION: &str = "arrow-flight-reuse-connection:";
+
+/// How a flight is requested from its service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlightSource {
+    /// A ticket for `DoGet`, read as one stream
+    Ticket(Vec<u8>),
+    /// A path descriptor such as `["sales", "orders"]`
+    Path(Vec<String>),
+    /// An opaque command descriptor, e.g. a query the service runs
+    Command(Vec<u8>),
+}
+
+impl FlightSource {
+    fn descriptor(&self) -> Option<FlightDescriptor> {
+        match self {
+            Self::Ticket(_) => None,
+            Self::Path(path) => Some(FlightDescriptor::new_path(path.clone())),
+            Self::Command(command) => Some(FlightDescriptor::new_cmd(command.clone())),
+        }
+    }
+}
+
+/// A remote flight registered as a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlightTableInfo {
+    /// Table name
+    pub table_name: String,
+    /// URI of the Flight service, e.g. `grpc://host:8815`
+    pub endpoint: String,
+    /// Flight read on every scan
+    pub source: FlightSource,
+    /// Column names and types
+    pub columns: Vec<(String, String)>,
+    /// Rows the service reported at registration, when it knew
+    pub total_records: Option<u64>,
+}
+
+/// Ask the service behind `endpoint` for the flight's schema and build a
+/// table reading it
+pub(crate) async fn build_table(
+    name: &str,
+    endpoint: &str,
+    source: FlightSource,
+) -> BlazeResult<(Arc<FlightTable>, FlightTableInfo)> {
+    let mut client = connect(endpoint).await?;
+    let (schema, total_records) = match &source {
+        // Only the stream itself knows its schema, which is its first message
+        FlightSource::Ticket(ticket) => (read_stream_schema(&mut client, endpoint, ticket).await?, None),
+        _ => {
+            let descriptor = source.descriptor().expect("descriptor source");
+            let info = get_flight_info(&mut client, endpoint, descriptor).await?;
+            if info.schema.is_empty() {
+                return Err(BlazeError::External(format!("Flight service {} sent no schema for the flight", endpoint)));
+            }
+            let schema = try_schema_from_ipc_buffer(&info.schema)?;
+            (Arc::new(schema), u64::try_from(info.total_records).ok())
+        }
+    };
+
+    let info = FlightTableInfo {
+        table_name: name.to_string(),
+        endpoint: endpoint.to_string(),
+        source: source.clone(),
+        columns: schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+        total_records,
+    };
+    let table = FlightTable { endpoint: endpoint.to_string(), source, schema };
+    Ok((Arc::new(table), info))
+}
+
+/// A flight read from its service on every scan
+#[derive(Debug)]
+pub(crate) struct FlightTable {
+    endpoint: String,
+    source: FlightSource,
+    schema: SchemaRef,
+}
+
+impl FlightTable {
+    /// One stream per endpoint of the flight, each with the location to read
+    /// it from
+    async fn partitions(&self) -> BlazeResult<Vec<Arc<dyn PartitionStream>>> {
+        if let FlightSource::Ticket(ticket) = &self.source {
+            return Ok(vec![self.partition(self.endpoint.clone(), Ticket::new(ticket.clone()))]);
+        }
+        let descriptor = self.source.descriptor().expect("descriptor source");
+        let mut client = connect(&self.endpoint).await?;
+        let info = get_flight_info(&mut client, &self.endpoint, descriptor).await?;
+        info.endpoint
+            .into_iter()
+            .map(|endpoint| {
+                let ticket = endpoint.ticket.ok_or_else(|| {
+                    BlazeError::External(format!("Flight service {} sent an endpoint without a ticket", self.endpoint))
+                })?;
+                // Any of the locations serves the stream; none means this service
+                let location = match endpoint.location.first() {
+                    Some(location) if !location.uri.starts_with(REUSE_CONNECTION) => location.uri.clone(),
+                    _ => self.endpoint.clone(),
+                };
+                Ok(self.partition(location, ticket))
+            })
+            .collect()
+    }
+
+    fn partition(&self, location: String, ticket: Ticket) -> Arc<dyn PartitionStream> {
+        Arc::new(FlightPartition { schema: self.schema.clone(), location, ticket })
+    }
+}
+
+#[async_trait]
+impl TableProvider for FlightTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let partitions = self.partitions().await.map_err(external)?;
+        if partitions.is_empty() {
+            let schema = match projection {
+                Some(indices) => Arc::new(self.schema.project(indices)?),
+                None => self.schema.clone(),
+            };
+            return Ok(Arc::new(EmptyExec::new(schema)));
+        }
+        Ok(Arc::new(StreamingTableExec::try_new(self.schema.clone(), partitions, projection, Vec::new(), false, limit)?))
+    }
+}
+
+/// One `DoGet` stream of a flight
+#[derive(Debug)]
+struct FlightPartition {
+    schema: SchemaRef,
+    location: String,
+    ticket: Ticket,
+}
+
+impl PartitionStream for FlightPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let schema = self.schema.clone();
+        let location = self.location.clone();
+        let ticket = self.ticket.clone();
+        let batches = futures::stream::once(async move {
+            let mut client = connect(&location).await.map_err(external)?;
+            let messages = do_get(&mut client, &location, ticket).await.map_err(external)?;
+
+            // The stream's own schema message comes first and is checked
+            // against the registered schema with every batch
+            let mut decoder = StreamDecoder::new();
+            Ok::<_, DataFusionError>(
+                messages
+                    .map_err(move |status| external(flight_error(&location, status)))
+                    .try_filter_map(move |message| {
+                        let batch = decode_message(&mut decoder, &message)
+                            .and_then(|batch| batch.map(|b| RecordBatch::try_new(schema.clone(), b.columns().to_vec())).transpose())
+                            .map_err(DataFusionError::from);
+                        futures::future::ready(batch)
+                    }),
+            )
+        })
+        .try_flatten();
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+/// Connect to a Flight service given as `grpc://host:port`,
+/// `grpc+tcp://host:port`, `http://host:port` or `host:port`, or over TLS
+/// as `grpc+tls://host:port` or `https://host:port`. Servers are verified
+/// against the system's root certificates.
+async fn connect(location: &str) -> BlazeResult<FlightServiceClient<Channel>> {
+    let uri = match location.split_once("://") {
+        Some(("grpc" | "grpc+tcp", address)) => format!("http://{}", address),
+        Some(("grpc+tls", address)) => format!("https://{}", address),
+        Some(_) => location.to_string(),
+        None => format!("http://{}", location),
+    };
+    let mut endpoint = Endpoint::from_shared(uri.clone())
+        .map_err(|e| invalid_input!("Invalid Flight endpoint '{}': {}", location, e))?;
+    if uri.starts_with("https://") {
+        endpoint = endpoint
+            .tls_config(ClientTlsConfig::new().with_native_roots())
+            .map_err(|e| BlazeError::Config(format!("Cannot set up TLS for Flight service {}: {}", location, e)))?;
+    }
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|e| BlazeError::External(format!("Cannot connect to Flight service {}: {}", location, e)))?;
+    // Services send batches of any size, well above gRPC's 4MB default
+    Ok(FlightServiceClient::new(channel).max_decoding_message_size(usize::MAX))
+}
+
+async fn get_flight_info(
+    client: &mut FlightServiceClient<Channel>,
+    location: &str,
+    descriptor: FlightDescriptor,
+) -> BlazeResult<FlightInfo> {
+    Ok(client.get_flight_info(descriptor).await.map_err(|status| flight_error(location, status))?.into_inner())
+}
+
+async fn do_get(
+    client: &mut FlightServiceClient<Channel>,
+    location: &str,
+    ticket: Ticket,
+) -> BlazeResult<tonic::Streaming<FlightData>> {
+    Ok(client.do_get(ticket).await.map_err(|status| flight_error(location, status))?.into_inner())
+}
+
+/// The schema a ticket's stream starts with. The stream is dropped after
+/// its first message, so services handing out single-use tickets need a
+/// descriptor instead.
+async fn read_stream_schema(
+    client: &mut FlightServiceClient<Channel>,
+    location: &str,
+    ticket: &[u8],
+) -> BlazeResult<SchemaRef> {
+    let mut messages = do_get(client, location, Ticket::new(ticket.to_vec())).await?;
+    while let Some(message) = messages.next().await {
+        let message = message.map_err(|status| flight_error(location, status))?;
+        if !message.data_header.is_empty() {
+            return Ok(Arc::new(try_schema_from_ipc_buffer(&ipc_frame(&message.data_header, &[]))?));
+        }
+    }
+    Err(BlazeError::External(format!("Flight service {} sent an empty stream", location)))
+}
+
+/// Decode one Flight message, returning its batch if it holds one. Schema
+/// and dictionary messages only update the decoder.
+pub(crate) fn decode_message(decoder: &mut StreamDecoder, message: &FlightData) -> std::result::Result<Option<RecordBatch>, ArrowError> {
+    // Messages carrying only application metadata have no header
+    if message.data_header.is_empty() {
+        return Ok(None);
+    }
+    let mut buffer = Buffer::from_vec(ipc_frame(&message.data_header, &message.data_body));
+    let mut batch = None;
+    while !buffer.is_empty() {
+        if let Some(decoded) = decoder.decode(&mut buffer)? {
+            batch = Some(decoded);
+        }
+    }
+    Ok(batch)
+}
+
+/// A Flight message framed as in an IPC stream: the continuation marker,
+/// the header length, the header padded to 8 bytes and the body
+pub(crate) fn ipc_frame(header: &[u8], body: &[u8]) -> Vec<u8> {
+    let padded = header.len().div_ceil(8) * 8;
+    let mut frame = Vec::with_capacity(8 + padded + body.len());
+    frame.extend_from_slice(&CONTINUATION_MARKER);
+    frame.extend_from_slice(&(padded as i32).to_le_bytes());
+    frame.extend_from_slice(header);
+    frame.resize(8 + padded, 0);
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn flight_error(location: &str, status: tonic::Status) -> BlazeError {
+    BlazeError::External(format!("Flight service {}: {}", location, status.message()))
+}
+
+fn external(e: BlazeError) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+
+    #[test]
+    fn test_decode_flight_messages() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2, 3]))]).unwrap();
+
+        let generator = IpcDataGenerator::default();
+        let options = IpcWriteOptions::default();
+        let mut tracker = DictionaryTracker::new(false);
+        let schema_message = generator.schema_to_bytes_with_dictionary_tracker(&schema, &mut tracker, &options);
+        let (_, batch_message) = generator.encoded_batch(&batch, &mut tracker, &options).unwrap();
+        let flight_data = |encoded: &datafusion::arrow::ipc::writer::EncodedData| FlightData {
+            data_header: encoded.ipc_message.clone().into(),
+            data_body: encoded.arrow_data.clone().into(),
+            ..Default::default()
+        };
+
+        let decoded = try_schema_from_ipc_buffer(&ipc_frame(&schema_message.ipc_message, &[])).unwrap();
+        assert_eq!(decoded, *schema);
+
+        let mut decoder = StreamDecoder::new();
+        assert!(decode_message(&mut decoder, &flight_data(&schema_message)).unwrap().is_none());
+        assert!(decode_message(&mut decoder, &FlightData::default()).unwrap().is_none());
+        for _ in 0..2 {
+            assert_eq!(decode_message(&mut decoder, &flight_data(&batch_message)).unwrap(), Some(batch.clone()));
+        }
+    }
+}
@@ -0,0 +1,595 @@
+//! Loading GeoJSON and shapefiles
+//!
+//! Geometries are stored as ISO WKB in a binary `geometry` column, the form
+//! the GEOGRAPHY functions read. Coordinates are written bit for bit as the
+//! source has them: GeoJSON numbers are parsed with correct rounding and
+//! shapefile doubles are copied, with no detour through another geometry
+//! library. Every row also gets its bounding box as `bbox_xmin`,
+//! `bbox_ymin`, `bbox_xmax` and `bbox_ymax`, so spatial filters can prune
+//! with plain comparisons. GeoJSON feature properties and the dBase
+//! attributes of a shapefile become the other columns.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use datafusion::arrow::array::{ArrayRef, BinaryArray, BooleanArray, Date32Array, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Column holding each row's geometry as WKB
+pub const GEOMETRY_COLUMN: &str = "geometry";
+
+const BBOX_COLUMNS: [&str; 4] = ["bbox_xmin", "bbox_ymin", "bbox_xmax", "bbox_ymax"];
+
+/// Outcome of loading a GeoJSON file or shapefile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoLoadReport {
+    /// Table the rows were loaded into
+    pub table_name: String,
+    /// Features or shapes loaded, including ones without a geometry
+    pub rows_loaded: usize,
+    /// Column names with the types they were loaded as
+    pub columns: Vec<(String, String)>,
+    /// Bounding box of all geometries as `[xmin, ymin, xmax, ymax]`, `None`
+    /// if there are none
+    pub bounds: Option<[f64; 4]>,
+}
+
+/// A position: x and y, optionally followed by z
+type Position = Vec<f64>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Geometry {
+    Point(Position),
+    LineString(Vec<Position>),
+    Polygon(Vec<Vec<Position>>),
+    MultiPoint(Vec<Position>),
+    MultiLineString(Vec<Vec<Position>>),
+    MultiPolygon(Vec<Vec<Vec<Position>>>),
+    Collection(Vec<Geometry>),
+}
+
+impl Geometry {
+    fn positions(&self) -> Box<dyn Iterator<Item = &Position> + '_> {
+        match self {
+            Geometry::Point(point) => Box::new(std::iter::once(point)),
+            Geometry::LineString(points) | Geometry::MultiPoint(points) => Box::new(points.iter()),
+            Geometry::Polygon(rings) | Geometry::MultiLineString(rings) => Box::new(rings.iter().flatten()),
+            Geometry::MultiPolygon(polygons) => Box::new(polygons.iter().flatten().flatten()),
+            Geometry::Collection(geometries) => Box::new(geometries.iter().flat_map(Geometry::positions)),
+        }
+    }
+
+    /// `[xmin, ymin, xmax, ymax]`, `None` for an empty geometry
+    fn bbox(&self) -> Option<[f64; 4]> {
+        self.positions().filter(|p| p.len() >= 2 && !p[0].is_nan()).fold(None, |bbox, p| {
+            let [xmin, ymin, xmax, ymax] = bbox.unwrap_or([p[0], p[1], p[0], p[1]]);
+            Some([xmin.min(p[0]), ymin.min(p[1]), xmax.max(p[0]), ymax.max(p[1])])
+        })
+    }
+
+    /// ISO WKB, little endian, with z when any position has one
+    fn to_wkb(&self) -> Vec<u8> {
+        let has_z = self.positions().any(|p| p.len() > 2);
+        let mut wkb = Vec::new();
+        self.write_wkb(&mut wkb, has_z);
+        wkb
+    }
+
+    fn write_wkb(&self, wkb: &mut Vec<u8>, has_z: bool) {
+        let code = match self {
+            Geometry::Point(_) => 1,
+            Geometry::LineString(_) => 2,
+            Geometry::Polygon(_) => 3,
+            Geometry::MultiPoint(_) => 4,
+            Geometry::MultiLineString(_) => 5,
+            Geometry::MultiPolygon(_) => 6,
+            Geometry::Collection(_) => 7,
+        };
+        wkb.push(1);
+        wkb.extend_from_slice(&(code + if has_z { 1000u32 } else { 0 }).to_le_bytes());
+
+        let write_position = |wkb: &mut Vec<u8>, p: &Position| {
+            let dims = if has_z { 3 } else { 2 };
+            for i in 0..dims {
+                wkb.extend_from_slice(&p.get(i).copied().unwrap_or(f64::NAN).to_le_bytes());
+            }
+        };
+        let write_points = |wkb: &mut Vec<u8>, points: &[Position]| {
+            wkb.extend_from_slice(&(points.len() as u32).to_le_bytes());
+            for p in points {
+                write_position(wkb, p);
+            }
+        };
+        let write_rings = |wkb: &mut Vec<u8>, rings: &[Vec<Position>]| {
+            wkb.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+            for ring in rings {
+                write_points(wkb, ring);
+            }
+        };
+
+        match self {
+            Geometry::Point(p) => write_position(wkb, p),
+            Geometry::LineString(points) => write_points(wkb, points),
+            Geometry::Polygon(rings) => write_rings(wkb, rings),
+            Geometry::MultiPoint(points) => {
+                wkb.extend_from_slice(&(points.len() as u32).to_le_bytes());
+                for p in points {
+                    Geometry::Point(p.clone()).write_wkb(wkb, has_z);
+                }
+            }
+            Geometry::MultiLineString(lines) => {
+                wkb.extend_from_slice(&(lines.len() as u32).to_le_bytes());
+                for line in lines {
+                    Geometry::LineString(line.clone()).write_wkb(wkb, has_z);
+                }
+            }
+            Geometry::MultiPolygon(polygons) => {
+                wkb.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+                for polygon in polygons {
+                    Geometry::Polygon(polygon.clone()).write_wkb(wkb, has_z);
+                }
+            }
+            Geometry::Collection(geometries) => {
+                wkb.extend_from_slice(&(geometries.len() as u32).to_le_bytes());
+                for geometry in geometries {
+                    geometry.write_wkb(wkb, has_z);
+                }
+            }
+        }
+    }
+}
+
+/// Read a GeoJSON FeatureCollection, Feature or bare geometry
+pub(crate) fn read_geojson(path: &Path) -> BlazeResult<RecordBatch> {
+    let text = std::fs::read_to_string(path)?;
+    let document: Value = serde_json::from_str(&text).map_err(|e| invalid_input!("Invalid GeoJSON in '{}': {}", path.display(), e))?;
+
+    let features: Vec<&Value> = match document.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => document
+            .get("features")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid_input!("GeoJSON FeatureCollection has no features array"))?
+            .iter()
+            .collect(),
+        Some("Feature") => vec![&document],
+        Some(_) => {
+            let geometry = parse_geometry(&document)?;
+            return geo_batch(Vec::new(), vec![Some(geometry)]);
+        }
+        None => return Err(invalid_input!("GeoJSON object in '{}' has no type", path.display())),
+    };
+
+    let mut geometries = Vec::with_capacity(features.len());
+    let mut names: Vec<String> = Vec::new();
+    let mut values: HashMap<String, Vec<Option<&Value>>> = HashMap::new();
+    for (row, feature) in features.iter().enumerate() {
+        if feature.get("type").and_then(Value::as_str) != Some("Feature") {
+            return Err(invalid_input!("GeoJSON feature {} is not a Feature", row));
+        }
+        geometries.push(match feature.get("geometry") {
+            None | Some(Value::Null) => None,
+            Some(geometry) => Some(parse_geometry(geometry)?),
+        });
+        if let Some(properties) = feature.get("properties").and_then(Value::as_object) {
+            for (name, value) in properties {
+                let column = values.entry(name.clone()).or_insert_with(|| {
+                    names.push(name.clone());
+                    vec![None; row]
+                });
+                column.push(Some(value));
+            }
+        }
+        for column in values.values_mut() {
+            column.resize(row + 1, None);
+        }
+    }
+
+    let attributes = names
+        .into_iter()
+        .map(|name| {
+            let column = property_column(&values[&name]);
+            (name, column)
+        })
+        .collect();
+    geo_batch(attributes, geometries)
+}
+
+fn parse_geometry(value: &Value) -> BlazeResult<Geometry> {
+    let kind = value.get("type").and_then(Value::as_str).unwrap_or_default();
+    if kind == "GeometryCollection" {
+        let geometries = value
+            .get("geometries")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid_input!("GeometryCollection has no geometries array"))?;
+        return Ok(Geometry::Collection(geometries.iter().map(parse_geometry).collect::<BlazeResult<_>>()?));
+    }
+
+    let coordinates = value
+        .get("coordinates")
+        .ok_or_else(|| invalid_input!("GeoJSON {} has no coordinates", kind))?;
+    Ok(match kind {
+        "Point" => Geometry::Point(position(coordinates)?),
+        "LineString" => Geometry::LineString(positions(coordinates)?),
+        "Polygon" => Geometry::Polygon(rings(coordinates)?),
+        "MultiPoint" => Geometry::MultiPoint(positions(coordinates)?),
+        "MultiLineString" => Geometry::MultiLineString(rings(coordinates)?),
+        "MultiPolygon" => Geometry::MultiPolygon(array(coordinates)?.iter().map(rings).collect::<BlazeResult<_>>()?),
+        other => return Err(invalid_input!("Unsupported GeoJSON geometry type '{}'", other)),
+    })
+}
+
+fn array(value: &Value) -> BlazeResult<&Vec<Value>> {
+    value.as_array().ok_or_else(|| invalid_input!("GeoJSON coordinates must be arrays, got {}", value))
+}
+
+fn position(value: &Value) -> BlazeResult<Position> {
+    let ordinates = array(value)?;
+    // An empty point has no ordinates
+    if ordinates.is_empty() {
+        return Ok(vec![f64::NAN, f64::NAN]);
+    }
+    if ordinates.len() < 2 {
+        return Err(invalid_input!("GeoJSON position {} needs at least two ordinates", value));
+    }
+    ordinates
+        .iter()
+        .take(3)
+        .map(|ordinate| ordinate.as_f64().ok_or_else(|| invalid_input!("GeoJSON ordinate {} is not a number", ordinate)))
+        .collect()
+}
+
+fn positions(value: &Value) -> BlazeResult<Vec<Position>> {
+    array(value)?.iter().map(position).collect()
+}
+
+fn rings(value: &Value) -> BlazeResult<Vec<Vec<Position>>> {
+    array(value)?.iter().map(positions).collect()
+}
+
+/// A column of property values: booleans, integers, floats or strings when
+/// all values agree, text (objects and arrays as JSON) otherwise
+fn property_column(values: &[Option<&Value>]) -> ArrayRef {
+    fn get<'a>(row: &Option<&'a Value>) -> Option<&'a Value> {
+        row.filter(|v| !v.is_null())
+    }
+    let present: Vec<&Value> = values.iter().filter_map(get).collect();
+
+    if !present.is_empty() && present.iter().all(|v| v.is_boolean()) {
+        return Arc::new(values.iter().map(|row| get(row).and_then(Value::as_bool)).collect::<BooleanArray>());
+    }
+    if !present.is_empty() && present.iter().all(|v| v.is_i64()) {
+        return Arc::new(values.iter().map(|row| get(row).and_then(Value::as_i64)).collect::<Int64Array>());
+    }
+    if !present.is_empty() && present.iter().all(|v| v.is_number()) {
+        return Arc::new(values.iter().map(|row| get(row).and_then(Value::as_f64)).collect::<Float64Array>());
+    }
+    Arc::new(
+        values
+            .iter()
+            .map(|row| {
+                get(row).map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+            })
+            .collect::<StringArray>(),
+    )
+}
+
+/// Assemble attribute columns, the WKB geometry and bounding boxes into a
+/// batch. Attributes named like a geometry column get a `property_` prefix.
+fn geo_batch(attributes: Vec<(String, ArrayRef)>, geometries: Vec<Option<Geometry>>) -> BlazeResult<RecordBatch> {
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for (name, column) in attributes {
+        let name = if name == GEOMETRY_COLUMN || BBOX_COLUMNS.contains(&name.as_str()) {
+            format!("property_{}", name)
+        } else {
+            name
+        };
+        fields.push(Field::new(name, column.data_type().clone(), true));
+        columns.push(column);
+    }
+
+    let wkb: Vec<Option<Vec<u8>>> = geometries.iter().map(|g| g.as_ref().map(Geometry::to_wkb)).collect();
+    fields.push(Field::new(GEOMETRY_COLUMN, DataType::Binary, true));
+    columns.push(Arc::new(wkb.iter().map(|w| w.as_deref()).collect::<BinaryArray>()));
+
+    let boxes: Vec<Option<[f64; 4]>> = geometries.iter().map(|g| g.as_ref().and_then(Geometry::bbox)).collect();
+    for (i, name) in BBOX_COLUMNS.iter().enumerate() {
+        fields.push(Field::new(*name, DataType::Float64, true));
+        columns.push(Arc::new(boxes.iter().map(|b| b.map(|b| b[i])).collect::<Float64Array>()));
+    }
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+}
+
+/// Bounding box of the `bbox_*` columns of a loaded batch
+pub(crate) fn bounds(batch: &RecordBatch) -> Option<[f64; 4]> {
+    let column = |name: &str| batch.column_by_name(name)?.as_any().downcast_ref::<Float64Array>().cloned();
+    let [xmin, ymin, xmax, ymax] = BBOX_COLUMNS.map(column);
+    let fold = |array: Option<Float64Array>, min: bool| {
+        array?.iter().flatten().reduce(|a, b| if min { a.min(b) } else { a.max(b) })
+    };
+    Some([fold(xmin, true)?, fold(ymin, true)?, fold(xmax, false)?, fold(ymax, false)?])
+}
+
+/// Read a shapefile's `.shp` geometries and, if present, the attributes in
+/// the `.dbf` file next to it. Z values are kept, M values dropped.
+pub(crate) fn read_shapefile(path: &Path) -> BlazeResult<RecordBatch> {
+    let shp = std::fs::read(path)?;
+    let mut geometries = read_shapes(&shp).map_err(|e| invalid_input!("Invalid shapefile '{}': {}", path.display(), e))?;
+
+    let dbf_path = path.with_extension("dbf");
+    let attributes = if dbf_path.is_file() {
+        let dbf = std::fs::read(&dbf_path)?;
+        let (attributes, deleted) = read_dbf(&dbf).map_err(|e| invalid_input!("Invalid dBase file '{}': {}", dbf_path.display(), e))?;
+        if deleted.len() != geometries.len() {
+            return Err(invalid_input!(
+                "'{}' has {} shapes but '{}' has {} records",
+                path.display(), geometries.len(), dbf_path.display(), deleted.len()
+            ));
+        }
+        // Records deleted in the dBase file drop their shape too
+        let mut row = 0;
+        geometries.retain(|_| {
+            row += 1;
+            !deleted[row - 1]
+        });
+        attributes
+    } else {
+        Vec::new()
+    };
+    geo_batch(attributes, geometries)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.offset.checked_add(n).filter(|end| *end <= self.bytes.len());
+        let end = end.ok_or_else(|| format!("unexpected end of file at byte {}", self.offset))?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn i32_be(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32_le(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f64_le(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        usize::try_from(self.i32_le()?).map_err(|_| "negative count".to_string())
+    }
+}
+
+fn read_shapes(shp: &[u8]) -> Result<Vec<Option<Geometry>>, String> {
+    let mut header = Reader { bytes: shp, offset: 0 };
+    if header.i32_be()? != 9994 {
+        return Err("not a shapefile".to_string());
+    }
+
+    let mut reader = Reader { bytes: shp, offset: 100 };
+    let mut shapes = Vec::new();
+    while reader.offset < shp.len() {
+        let _record_number = reader.i32_be()?;
+        let length = usize::try_from(reader.i32_be()?).map_err(|_| "negative record length")? * 2;
+        let mut record = Reader { bytes: reader.take(length)?, offset: 0 };
+        shapes.push(read_shape(&mut record)?);
+    }
+    Ok(shapes)
+}
+
+fn read_shape(record: &mut Reader) -> Result<Option<Geometry>, String> {
+    let shape_type = record.i32_le()?;
+    let has_z = matches!(shape_type, 11 | 13 | 15 | 18);
+    match shape_type {
+        0 => Ok(None),
+        1 | 11 | 21 => {
+            let mut point = vec![record.f64_le()?, record.f64_le()?];
+            if has_z {
+                point.push(record.f64_le()?);
+            }
+            Ok(Some(Geometry::Point(point)))
+        }
+        8 | 18 | 28 => {
+            record.take(32)?;
+            let count = record.count()?;
+            let points = read_points(record, count, has_z)?;
+            Ok(Some(Geometry::MultiPoint(points)))
+        }
+        3 | 13 | 23 | 5 | 15 | 25 => {
+            record.take(32)?;
+            let (part_count, point_count) = (record.count()?, record.count()?);
+            let starts = (0..part_count).map(|_| record.count()).collect::<Result<Vec<_>, _>>()?;
+            let points = read_points(record, point_count, has_z)?;
+            let mut parts = Vec::with_capacity(part_count);
+            for (i, start) in starts.iter().enumerate() {
+                let end = starts.get(i + 1).copied().unwrap_or(point_count);
+                parts.push(points.get(*start..end).ok_or("part outside the shape's points")?.to_vec());
+            }
+            Ok(Some(match shape_type {
+                3 | 13 | 23 if parts.len() == 1 => Geometry::LineString(parts.remove(0)),
+                3 | 13 | 23 => Geometry::MultiLineString(parts),
+                _ => polygons(parts),
+            }))
+        }
+        other => Err(format!("unsupported shape type {}", other)),
+    }
+}
+
+/// `count` xy points followed, with `has_z`, by the z range and values
+fn read_points(record: &mut Reader, count: usize, has_z: bool) -> Result<Vec<Position>, String> {
+    let mut points = (0..count)
+        .map(|_| Ok(vec![record.f64_le()?, record.f64_le()?]))
+        .collect::<Result<Vec<_>, String>>()?;
+    if has_z {
+        record.take(16)?;
+        for point in &mut points {
+            point.push(record.f64_le()?);
+        }
+    }
+    Ok(points)
+}
+
+/// Group shapefile rings into polygons: a clockwise ring starts a polygon
+/// and the counter-clockwise rings after it are its holes
+fn polygons(rings: Vec<Vec<Position>>) -> Geometry {
+    let mut polygons: Vec<Vec<Vec<Position>>> = Vec::new();
+    for ring in rings {
+        let signed_area: f64 = ring.windows(2).map(|w| w[0][0] * w[1][1] - w[1][0] * w[0][1]).sum();
+        match polygons.last_mut() {
+            Some(polygon) if signed_area > 0.0 => polygon.push(ring),
+            _ => polygons.push(vec![ring]),
+        }
+    }
+    match polygons.len() {
+        1 => Geometry::Polygon(polygons.remove(0)),
+        _ => Geometry::MultiPolygon(polygons),
+    }
+}
+
+/// Attribute columns of a dBase III file and which records are deleted
+#[allow(clippy::type_complexity)]
+fn read_dbf(dbf: &[u8]) -> Result<(Vec<(String, ArrayRef)>, Vec<bool>), String> {
+    let mut header = Reader { bytes: dbf, offset: 4 };
+    let record_count = u32::from_le_bytes(header.take(4)?.try_into().unwrap()) as usize;
+    let header_length = u16::from_le_bytes(header.take(2)?.try_into().unwrap()) as usize;
+    let record_length = u16::from_le_bytes(header.take(2)?.try_into().unwrap()) as usize;
+
+    // (name, type, offset in record, length, decimals)
+    let mut fields = Vec::new();
+    let mut reader = Reader { bytes: dbf, offset: 32 };
+    let mut field_offset = 1;
+    while dbf.get(reader.offset).is_some_and(|b| *b != 0x0D) && reader.offset < header_length {
+        let descriptor = reader.take(32)?;
+        let name_end = descriptor[..11].iter().position(|b| *b == 0).unwrap_or(11);
+        let name = String::from_utf8_lossy(&descriptor[..name_end]).trim().to_string();
+        let (length, decimals) = (descriptor[16] as usize, descriptor[17] as usize);
+        fields.push((name, descriptor[11] as char, field_offset, length, decimals));
+        field_offset += length;
+    }
+
+    let mut deleted = Vec::with_capacity(record_count);
+    let mut text: Vec<Vec<Option<String>>> = vec![Vec::with_capacity(record_count); fields.len()];
+    let mut records = Reader { bytes: dbf, offset: header_length };
+    for _ in 0..record_count {
+        let record = records.take(record_length)?;
+        deleted.push(record[0] == b'*');
+        for (column, (_, _, offset, length, _)) in text.iter_mut().zip(&fields) {
+            let raw = record.get(*offset..offset + length).ok_or("field outside its record")?;
+            let value = String::from_utf8_lossy(raw).trim().to_string();
+            column.push((!value.is_empty()).then_some(value));
+        }
+    }
+
+    let columns = fields
+        .into_iter()
+        .zip(text)
+        .map(|((name, kind, _, length, decimals), values)| {
+            let column: ArrayRef = match kind {
+                'N' | 'F' if decimals == 0 && length <= 18 => {
+                    Arc::new(values.iter().map(|v| v.as_deref().and_then(|v| v.parse::<i64>().ok())).collect::<Int64Array>())
+                }
+                'N' | 'F' => Arc::new(values.iter().map(|v| v.as_deref().and_then(|v| v.parse::<f64>().ok())).collect::<Float64Array>()),
+                'L' => Arc::new(
+                    values
+                        .iter()
+                        .map(|v| match v.as_deref() {
+                            Some("T" | "t" | "Y" | "y") => Some(true),
+                            Some("F" | "f" | "N" | "n") => Some(false),
+                            _ => None,
+                        })
+                        .collect::<BooleanArray>(),
+                ),
+                'D' => {
+                    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+                    Arc::new(
+                        values
+                            .iter()
+                            .map(|v| {
+                                let date = NaiveDate::parse_from_str(v.as_deref()?, "%Y%m%d").ok()?;
+                                Some((date - epoch).num_days() as i32)
+                            })
+                            .collect::<Date32Array>(),
+                    )
+                }
+                _ => Arc::new(values.into_iter().collect::<StringArray>()),
+            };
+            (name, column)
+        })
+        .collect();
+    Ok((columns, deleted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Array;
+
+    #[test]
+    fn test_geojson_features() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("places.geojson");
+        std::fs::write(
+            &path,
+            r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "geometry": {"type": "Point", "coordinates": [-122.41941550000001, 37.7749295]},
+                 "properties": {"name": "SF", "population": 808437}},
+                {"type": "Feature", "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [4, 0], [4, 3], [0, 0]]]},
+                 "properties": {"name": "triangle", "population": 2.5, "tags": ["a"]}},
+                {"type": "Feature", "geometry": null, "properties": null}
+            ]}"#,
+        )
+        .unwrap();
+
+        let batch = read_geojson(&path).unwrap();
+        let names: Vec<_> = batch.schema().fields().iter().map(|f| (f.name().clone(), f.data_type().clone())).collect();
+        assert_eq!(names[..3], [
+            ("name".to_string(), DataType::Utf8),
+            ("population".to_string(), DataType::Float64),
+            ("tags".to_string(), DataType::Utf8),
+        ]);
+
+        let wkb = batch.column_by_name(GEOMETRY_COLUMN).unwrap().as_any().downcast_ref::<BinaryArray>().unwrap();
+        let point = wkb.value(0);
+        assert_eq!(point[..5], [1, 1, 0, 0, 0]);
+        assert_eq!(f64::from_le_bytes(point[5..13].try_into().unwrap()), -122.41941550000001);
+        assert!(wkb.is_null(2));
+        assert_eq!(bounds(&batch), Some([-122.41941550000001, 0.0, 4.0, 37.7749295]));
+    }
+
+    #[test]
+    fn test_shapefile_polygon_with_hole() {
+        let ring = |points: &[(f64, f64)]| points.iter().map(|(x, y)| vec![*x, *y]).collect::<Vec<_>>();
+        // Clockwise outer ring, then a counter-clockwise hole
+        let outer = ring(&[(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0), (0.0, 0.0)]);
+        let hole = ring(&[(2.0, 2.0), (4.0, 2.0), (4.0, 4.0), (2.0, 2.0)]);
+        let island = ring(&[(20.0, 20.0), (20.0, 21.0), (21.0, 21.0), (20.0, 20.0)]);
+
+        assert_eq!(polygons(vec![outer.clone(), hole.clone()]), Geometry::Polygon(vec![outer.clone(), hole.clone()]));
+        assert_eq!(
+            polygons(vec![outer.clone(), hole.clone(), island.clone()]),
+            Geometry::MultiPolygon(vec![vec![outer, hole], vec![island]])
+        );
+    }
+}
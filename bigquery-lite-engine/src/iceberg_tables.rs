@@ -0,0 +1,359 @@
+//! Tables over Apache Iceberg tables
+//!
+//! `register_iceberg("orders", "lake/orders/metadata/v3.metadata.json", None)`
+//! reads the table metadata file and registers its current snapshot; a
+//! snapshot id or a point in time pins an earlier one instead. Scans plan
+//! the snapshot's manifests, reading only the data files and columns the
+//! query needs.
+//!
+//! Filters comparing a column with a literal are passed to the scan as
+//! Iceberg predicates, which skip data files whose statistics rule them out;
+//! DataFusion still applies them to the rows read.
+//!
+//! Queries travel to other snapshots of a registered table with the same
+//! clause used for in-memory tables, resolved against the table's snapshot
+//! log:
+//!
+//! ```sql
+//! SELECT * FROM orders FOR SYSTEM_TIME AS OF TIMESTAMP '2024-06-01 00:00:00'
+//! ```
+//!
+//! The metadata file is fixed at registration: commits made afterwards
+//! write a new one, which is read by registering the table again.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use datafusion::catalog::Session;
+use datafusion::common::ScalarValue;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{BinaryExpr, Expr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use futures::TryStreamExt;
+use iceberg::arrow::schema_to_arrow_schema;
+use iceberg::expr::{Predicate, Reference};
+use iceberg::io::FileIO;
+use iceberg::spec::Datum;
+use iceberg::table::{StaticTable, Table};
+use iceberg::{ErrorKind, TableIdent};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::snapshots::VersionedSource;
+
+/// Which snapshot of an Iceberg table to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IcebergVersion {
+    /// The snapshot with this id
+    SnapshotId(i64),
+    /// The snapshot that was current at this time
+    AsOf(DateTime<Utc>),
+}
+
+/// A snapshot in an Iceberg table's metadata
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IcebergSnapshotInfo {
+    pub snapshot_id: i64,
+    pub committed_at: DateTime<Utc>,
+    /// Operation that produced the snapshot, e.g. `append` or `overwrite`
+    pub operation: String,
+}
+
+/// An Iceberg table registered with `register_iceberg`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IcebergTableInfo {
+    pub table_name: String,
+    /// Metadata file the table was registered from
+    pub metadata_path: String,
+    /// Snapshot the table reads; `None` for a table without snapshots
+    pub snapshot_id: Option<i64>,
+    /// Whether the snapshot was pinned at registration rather than being
+    /// the current one
+    pub pinned: bool,
+    /// Snapshots in the metadata, oldest first
+    pub snapshots: Vec<IcebergSnapshotInfo>,
+    /// Column names with their Arrow types
+    pub columns: Vec<(String, String)>,
+}
+
+/// A registered Iceberg table, kept to plan time travel to its snapshots
+#[derive(Debug, Clone)]
+pub(crate) struct RegisteredIcebergTable {
+    pub info: IcebergTableInfo,
+    table: Table,
+}
+
+/// Read the metadata file at `metadata_path` and build a table reading
+/// `version`, or the current snapshot
+pub(crate) async fn open_table(
+    name: &str,
+    metadata_path: &str,
+    version: Option<IcebergVersion>,
+) -> BlazeResult<(Arc<dyn TableProvider>, RegisteredIcebergTable)> {
+    let external = |e: iceberg::Error| BlazeError::External(format!("Cannot read Iceberg table '{}': {}", metadata_path, e));
+    let file_io = FileIO::from_path(metadata_path).and_then(|builder| builder.build()).map_err(external)?;
+    let ident = TableIdent::from_strs(["default", name]).map_err(external)?;
+    let table = StaticTable::from_metadata_file(metadata_path, ident, file_io).await.map_err(external)?.into_table();
+
+    let metadata = table.metadata();
+    let mut snapshots: Vec<_> = metadata
+        .snapshots()
+        .map(|snapshot| IcebergSnapshotInfo {
+            snapshot_id: snapshot.snapshot_id(),
+            committed_at: DateTime::from_timestamp_millis(snapshot.timestamp_ms()).unwrap_or_default(),
+            operation: format!("{:?}", snapshot.summary().operation).to_lowercase(),
+        })
+        .collect();
+    snapshots.sort_by_key(|snapshot| (snapshot.committed_at, snapshot.snapshot_id));
+    let snapshot_id = match version {
+        Some(version) => Some(resolve_version(&snapshots, version).map_err(|e| match e {
+            BlazeError::InvalidInput(message) => invalid_input!("{} in '{}'", message, metadata_path),
+            other => other,
+        })?),
+        None => metadata.current_snapshot().map(|snapshot| snapshot.snapshot_id()),
+    };
+
+    let provider = provider(&table, version.and(snapshot_id)).await.map_err(external)?;
+    let info = IcebergTableInfo {
+        table_name: name.to_string(),
+        metadata_path: metadata_path.to_string(),
+        snapshot_id,
+        pinned: version.is_some(),
+        snapshots,
+        columns: provider.schema().fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+    };
+    Ok((provider, RegisteredIcebergTable { info, table }))
+}
+
+/// A table reading snapshot `snapshot_id` of `table`, or its current one
+async fn provider(table: &Table, snapshot_id: Option<i64>) -> iceberg::Result<Arc<dyn TableProvider>> {
+    let metadata = table.metadata();
+    let schema = match snapshot_id {
+        Some(snapshot_id) => metadata
+            .snapshot_by_id(snapshot_id)
+            .ok_or_else(|| iceberg::Error::new(ErrorKind::DataInvalid, format!("Snapshot {} not found", snapshot_id)))?
+            .schema(metadata)?,
+        None => metadata.current_schema().clone(),
+    };
+    Ok(Arc::new(IcebergTable {
+        table: table.clone(),
+        snapshot_id,
+        schema: Arc::new(schema_to_arrow_schema(&schema)?),
+    }))
+}
+
+/// A snapshot of an Iceberg table, planned and read on every scan
+#[derive(Debug)]
+struct IcebergTable {
+    table: Table,
+    /// Snapshot to read; the current one when `None`
+    snapshot_id: Option<i64>,
+    schema: SchemaRef,
+}
+
+#[async_trait]
+impl TableProvider for IcebergTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(indices) => Arc::new(self.schema.project(indices)?),
+            None => self.schema.clone(),
+        };
+        let mut scan = self.table.scan().select(schema.fields().iter().map(|field| field.name()));
+        if let Some(snapshot_id) = self.snapshot_id {
+            scan = scan.snapshot_id(snapshot_id);
+        }
+        if let Some(predicate) = filters.iter().filter_map(|filter| predicate(filter, &self.schema)).reduce(Predicate::and) {
+            scan = scan.with_filter(predicate);
+        }
+        let batches: Vec<RecordBatch> = scan
+            .build()
+            .map_err(iceberg_error)?
+            .to_arrow()
+            .await
+            .map_err(iceberg_error)?
+            .try_collect()
+            .await
+            .map_err(iceberg_error)?;
+        // Batches carry the field ids of the data files; give them the table's schema
+        let batches = batches
+            .into_iter()
+            .map(|batch| {
+                let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+                RecordBatch::try_new_with_options(schema.clone(), batch.columns().to_vec(), &options)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        MemTable::try_new(schema, vec![batches])?.scan(state, None, &[], None).await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        // Inexact: data files that may match are read whole
+        Ok(filters
+            .iter()
+            .map(|filter| match predicate(filter, &self.schema) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+}
+
+/// The Iceberg predicate for a filter comparing a column with a literal of
+/// the column's type, or an AND of such comparisons
+fn predicate(filter: &Expr, schema: &SchemaRef) -> Option<Predicate> {
+    let Expr::BinaryExpr(BinaryExpr { left, op, right }) = filter else {
+        return None;
+    };
+    if *op == Operator::And {
+        return Some(predicate(left, schema)?.and(predicate(right, schema)?));
+    }
+    let (column, op, literal) = match (left.as_ref(), right.as_ref()) {
+        (Expr::Column(column), Expr::Literal(value)) => (column, *op, value),
+        (Expr::Literal(value), Expr::Column(column)) => (column, op.swap()?, value),
+        _ => return None,
+    };
+    if *schema.field_with_name(&column.name).ok()?.data_type() != literal.data_type() {
+        return None;
+    }
+    let datum = match literal {
+        ScalarValue::Boolean(Some(v)) => Datum::bool(*v),
+        ScalarValue::Int32(Some(v)) => Datum::int(*v),
+        ScalarValue::Int64(Some(v)) => Datum::long(*v),
+        ScalarValue::Float32(Some(v)) => Datum::float(*v),
+        ScalarValue::Float64(Some(v)) => Datum::double(*v),
+        ScalarValue::Utf8(Some(v)) => Datum::string(v),
+        ScalarValue::Date32(Some(v)) => Datum::date(*v),
+        _ => return None,
+    };
+    let reference = Reference::new(column.name.clone());
+    Some(match op {
+        Operator::Eq => reference.equal_to(datum),
+        Operator::NotEq => reference.not_equal_to(datum),
+        Operator::Lt => reference.less_than(datum),
+        Operator::LtEq => reference.less_than_or_equal_to(datum),
+        Operator::Gt => reference.greater_than(datum),
+        Operator::GtEq => reference.greater_than_or_equal_to(datum),
+        _ => return None,
+    })
+}
+
+fn iceberg_error(e: iceberg::Error) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
+
+/// The id of the snapshot `version` selects among `snapshots`, oldest first
+fn resolve_version(snapshots: &[IcebergSnapshotInfo], version: IcebergVersion) -> BlazeResult<i64> {
+    match version {
+        IcebergVersion::SnapshotId(id) => snapshots
+            .iter()
+            .find(|snapshot| snapshot.snapshot_id == id)
+            .map(|snapshot| snapshot.snapshot_id)
+            .ok_or_else(|| invalid_input!("Snapshot {} not found", id)),
+        IcebergVersion::AsOf(as_of) => snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.committed_at <= as_of)
+            .map(|snapshot| snapshot.snapshot_id)
+            .ok_or_else(|| match snapshots.first() {
+                Some(oldest) => invalid_input!(
+                    "No snapshot as of {} (oldest snapshot is from {})",
+                    as_of.to_rfc3339(),
+                    oldest.committed_at.to_rfc3339()
+                ),
+                None => invalid_input!("No snapshots"),
+            }),
+    }
+}
+
+#[async_trait]
+impl VersionedSource for HashMap<String, RegisteredIcebergTable> {
+    async fn table_as_of(&self, table: &str, as_of: DateTime<Utc>) -> Option<BlazeResult<(String, Arc<dyn TableProvider>)>> {
+        let registered = self
+            .get(table)
+            .or_else(|| self.iter().find(|(name, _)| name.eq_ignore_ascii_case(table)).map(|(_, registered)| registered))?;
+        let resolved = async {
+            let snapshot_id = resolve_version(&registered.info.snapshots, IcebergVersion::AsOf(as_of))
+                .map_err(|e| invalid_input!("Iceberg table '{}': {}", table, e))?;
+            let provider = provider(&registered.table, Some(snapshot_id))
+                .await
+                .map_err(|e| BlazeError::External(format!("Cannot read snapshot {} of '{}': {}", snapshot_id, table, e)))?;
+            Ok((format!("{}@iceberg-{}", registered.info.table_name, snapshot_id), provider))
+        };
+        Some(resolved.await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::prelude::{col, lit};
+
+    #[test]
+    fn test_resolve_version() {
+        let t0 = Utc::now();
+        let snapshots: Vec<_> = [(11, 0), (12, 60), (13, 120)]
+            .into_iter()
+            .map(|(snapshot_id, offset)| IcebergSnapshotInfo {
+                snapshot_id,
+                committed_at: t0 + Duration::seconds(offset),
+                operation: "append".to_string(),
+            })
+            .collect();
+
+        assert_eq!(resolve_version(&snapshots, IcebergVersion::SnapshotId(12)).unwrap(), 12);
+        assert!(resolve_version(&snapshots, IcebergVersion::SnapshotId(99)).is_err());
+        assert_eq!(resolve_version(&snapshots, IcebergVersion::AsOf(t0 + Duration::seconds(90))).unwrap(), 12);
+        assert_eq!(resolve_version(&snapshots, IcebergVersion::AsOf(t0 + Duration::seconds(120))).unwrap(), 13);
+        let error = resolve_version(&snapshots, IcebergVersion::AsOf(t0 - Duration::seconds(1))).unwrap_err().to_string();
+        assert!(error.contains("oldest snapshot"), "{}", error);
+        assert!(resolve_version(&[], IcebergVersion::AsOf(t0)).is_err());
+    }
+
+    #[test]
+    fn test_predicate() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("customer", DataType::Utf8, true),
+        ]));
+        let predicate = |filter: Expr| predicate(&filter, &schema).map(|p| p.to_string());
+
+        assert_eq!(predicate(col("id").gt_eq(lit(3i64))).unwrap(), "id >= 3");
+        assert_eq!(predicate(lit(3i64).gt(col("id"))).unwrap(), "id < 3");
+        assert_eq!(
+            predicate(col("customer").eq(lit("ada")).and(col("id").not_eq(lit(1i64)))).unwrap(),
+            "(customer = \"ada\") AND (id != 1)"
+        );
+        // Literals of another type, other expressions and ORs stay with DataFusion
+        assert!(predicate(col("id").gt(lit(3i32))).is_none());
+        assert!(predicate(col("customer").like(lit("a%"))).is_none());
+        assert!(predicate(col("id").eq(lit(1i64)).or(col("id").eq(lit(2i64)))).is_none());
+    }
+}
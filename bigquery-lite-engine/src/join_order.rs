@@ -0,0 +1,489 @@
+//! Join reordering from table statistics
+//!
+//! `ANALYZE TABLE t` counts the rows of `t` and the distinct and null values
+//! of each of its columns. With `reorder_joins` on, every tree of inner
+//! joins over three or more inputs that all read analyzed tables is rebuilt
+//! greedily: the pair of inputs with the smallest estimated join goes first,
+//! then the input whose join with the tree so far is estimated smallest, so
+//! intermediate results stay small whatever order the query lists its
+//! tables in. DataFusion still picks the build side of each join.
+//!
+//! A join of `l` and `r` rows on keys with `dl` and `dr` distinct values is
+//! estimated at `l * r / max(dl, dr)`, taking the most selective key, and a
+//! filter is assumed to keep a fifth of its input. Statistics describe a
+//! table as it was when analyzed; analyze it again after large changes.
+//! `BlazeQueryEngine::join_diagnostics` lists the joins of a query with
+//! their estimated and actual rows.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::array::{Array, Int64Array};
+use datafusion::common::tree_node::{Transformed, TreeNodeRecursion};
+use datafusion::common::{extensions_options, Column};
+use datafusion::config::{ConfigExtension, ConfigOptions};
+use datafusion::error::Result;
+use datafusion::execution::session_state::SessionState;
+use datafusion::functions_aggregate::expr_fn::{count, count_distinct};
+use datafusion::logical_expr::{Expr, JoinType, LogicalPlan, LogicalPlanBuilder};
+use datafusion::optimizer::{OptimizerConfig, OptimizerRule};
+use datafusion::prelude::{lit, DataFrame, SessionContext};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Share of its input a filter is assumed to keep
+const FILTER_SELECTIVITY: f64 = 0.2;
+
+extensions_options! {
+    /// The join ordering settings of a session, read while optimizing
+    pub(crate) struct JoinOrderOptions {
+        /// Reorder inner joins of analyzed tables by estimated size
+        pub reorder_joins: bool, default = false
+    }
+}
+
+impl ConfigExtension for JoinOrderOptions {
+    const PREFIX: &'static str = "bqlite_joins";
+}
+
+/// Statistics of one column from `ANALYZE TABLE`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStatistics {
+    /// Column name
+    pub name: String,
+    /// Distinct non-null values; `None` for nested types
+    pub distinct_count: Option<u64>,
+    /// NULL values
+    pub null_count: u64,
+}
+
+/// Statistics of a table from `ANALYZE TABLE`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableStatistics {
+    /// Table analyzed
+    pub table_name: String,
+    /// Rows in the table
+    pub row_count: u64,
+    /// Statistics of each column, in table order
+    pub columns: Vec<ColumnStatistics>,
+    /// When the table was analyzed
+    pub analyzed_at: DateTime<Utc>,
+}
+
+impl TableStatistics {
+    fn distinct_count(&self, column: &str) -> Option<u64> {
+        self.columns.iter().find(|c| c.name == column).and_then(|c| c.distinct_count)
+    }
+}
+
+/// One join of a query's optimized plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinStep {
+    /// Tables read by the left input, in plan order
+    pub left_tables: Vec<String>,
+    /// Tables read by the right input, in plan order
+    pub right_tables: Vec<String>,
+    /// Join type, e.g. `Inner`
+    pub join_type: String,
+    /// Rows estimated from table statistics, `None` without them
+    pub estimated_rows: Option<u64>,
+    /// Rows the join produced
+    pub actual_rows: u64,
+}
+
+/// The joins of a query as the optimizer ordered them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinDiagnostics {
+    /// Whether join reordering was on
+    pub reorder_joins: bool,
+    /// Tables in the order the plan reads them
+    pub join_order: Vec<String>,
+    /// Joins from the innermost out
+    pub joins: Vec<JoinStep>,
+}
+
+/// Statistics of analyzed tables by name, shared with the optimizer rule
+#[derive(Debug, Default)]
+pub(crate) struct StatisticsStore {
+    tables: RwLock<HashMap<String, TableStatistics>>,
+}
+
+impl StatisticsStore {
+    pub(crate) fn get(&self, table_name: &str) -> Option<TableStatistics> {
+        self.tables.read().unwrap().get(table_name).cloned()
+    }
+
+    pub(crate) fn insert(&self, statistics: TableStatistics) {
+        self.tables.write().unwrap().insert(statistics.table_name.clone(), statistics);
+    }
+
+    pub(crate) fn remove(&self, table_name: &str) {
+        self.tables.write().unwrap().remove(table_name);
+    }
+}
+
+/// Install join reordering into a new session
+pub(crate) fn register(ctx: &SessionContext, statistics: Arc<StatisticsStore>) {
+    ctx.add_optimizer_rule(Arc::new(ReorderJoins { statistics }));
+}
+
+/// Turn join reordering on or off for queries planned from now on
+pub(crate) fn apply(ctx: &SessionContext, reorder_joins: bool) {
+    let state = ctx.state_ref();
+    let mut state = state.write();
+    state.config_mut().options_mut().extensions.insert(JoinOrderOptions { reorder_joins });
+}
+
+fn options_from(config: &ConfigOptions) -> JoinOrderOptions {
+    config.extensions.get::<JoinOrderOptions>().cloned().unwrap_or_default()
+}
+
+/// Parse `ANALYZE TABLE name`, optionally followed by `COMPUTE STATISTICS`
+pub(crate) fn parse_analyze(sql: &str) -> Option<String> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*ANALYZE\s+TABLE\s+([A-Za-z_][A-Za-z0-9_]*)(?:\s+COMPUTE\s+STATISTICS)?\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    PATTERN.captures(sql).map(|captures| captures[1].to_lowercase())
+}
+
+/// Count the rows of `table` and the distinct and null values of its columns
+pub(crate) async fn analyze(table_name: &str, table: DataFrame) -> Result<TableStatistics> {
+    let fields: Vec<_> = table.schema().fields().iter().cloned().collect();
+    let mut aggregates = vec![count(lit(1)).alias("rows")];
+    for (i, field) in fields.iter().enumerate() {
+        let column = Expr::Column(Column::from_name(field.name()));
+        aggregates.push(count(column.clone()).alias(format!("values_{}", i)));
+        if !field.data_type().is_nested() {
+            aggregates.push(count_distinct(column).alias(format!("distinct_{}", i)));
+        }
+    }
+    let batches = table.aggregate(vec![], aggregates)?.collect().await?;
+    let batch = &batches[0];
+    let value = |name: &str| {
+        batch
+            .column_by_name(name)
+            .and_then(|column| column.as_any().downcast_ref::<Int64Array>())
+            .map(|counts| counts.value(0) as u64)
+    };
+
+    let row_count = value("rows").unwrap_or(0);
+    let columns = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| ColumnStatistics {
+            name: field.name().clone(),
+            distinct_count: value(&format!("distinct_{}", i)),
+            null_count: row_count - value(&format!("values_{}", i)).unwrap_or(row_count),
+        })
+        .collect();
+    Ok(TableStatistics { table_name: table_name.to_string(), row_count, columns, analyzed_at: Utc::now() })
+}
+
+/// Optimizer rule reordering inner joins when `reorder_joins` is on. Runs
+/// after DataFusion's rules, so filters are pushed down and comma joins are
+/// already inner joins.
+#[derive(Debug)]
+struct ReorderJoins {
+    statistics: Arc<StatisticsStore>,
+}
+
+impl OptimizerRule for ReorderJoins {
+    fn name(&self) -> &str {
+        "reorder_joins"
+    }
+
+    fn supports_rewrite(&self) -> bool {
+        true
+    }
+
+    fn rewrite(&self, plan: LogicalPlan, config: &dyn OptimizerConfig) -> Result<Transformed<LogicalPlan>> {
+        if !options_from(config.options()).reorder_joins {
+            return Ok(Transformed::no(plan));
+        }
+        let tables = self.statistics.tables.read().unwrap();
+        reorder_all(plan, &tables)
+    }
+}
+
+fn reorder_all(plan: LogicalPlan, tables: &HashMap<String, TableStatistics>) -> Result<Transformed<LogicalPlan>> {
+    plan.transform_down_with_subqueries(|node| reorder(node, tables))
+}
+
+/// Whether `plan` joins its inputs on column equalities alone, so its
+/// inputs may be joined in any order
+fn is_reorderable(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::Join(join) => {
+            join.join_type == JoinType::Inner
+                && join.filter.is_none()
+                && !join.null_equals_null
+                && join.on.iter().all(|(l, r)| matches!((l, r), (Expr::Column(_), Expr::Column(_))))
+        }
+        _ => false,
+    }
+}
+
+/// Collect the inputs of the reorderable join tree at `plan` and its key
+/// pairs. Projections that only pick columns between the joins are left
+/// out. Returns whether the tree is left-deep, joining one input at a time.
+fn flatten<'a>(plan: &'a LogicalPlan, inputs: &mut Vec<&'a LogicalPlan>, keys: &mut Vec<(Column, Column)>) -> bool {
+    match plan {
+        LogicalPlan::Join(join) if is_reorderable(plan) => {
+            let left_deep = flatten(&join.left, inputs, keys);
+            let right_inputs = inputs.len();
+            flatten(&join.right, inputs, keys);
+            for (l, r) in &join.on {
+                if let (Expr::Column(l), Expr::Column(r)) = (l, r) {
+                    keys.push((l.clone(), r.clone()));
+                }
+            }
+            left_deep && inputs.len() == right_inputs + 1
+        }
+        LogicalPlan::Projection(projection)
+            if is_reorderable(&projection.input) && projection.expr.iter().all(|e| matches!(e, Expr::Column(_))) =>
+        {
+            flatten(&projection.input, inputs, keys)
+        }
+        _ => {
+            inputs.push(plan);
+            true
+        }
+    }
+}
+
+fn reorder(plan: LogicalPlan, tables: &HashMap<String, TableStatistics>) -> Result<Transformed<LogicalPlan>> {
+    if !is_reorderable(&plan) {
+        return Ok(Transformed::no(plan));
+    }
+    let mut inputs = Vec::new();
+    let mut keys = Vec::new();
+    let left_deep = flatten(&plan, &mut inputs, &mut keys);
+    if inputs.len() < 3 {
+        return Ok(Transformed::no(plan));
+    }
+    let Some(rows) = inputs.iter().map(|input| estimate_rows(input, tables)).collect::<Option<Vec<f64>>>() else {
+        return Ok(Transformed::no(plan));
+    };
+
+    // Start with the smallest join of two inputs, then keep adding the
+    // input that joins smallest with the tree so far
+    let estimated: Vec<_> = inputs.iter().map(|input| (*input).clone()).zip(rows).collect();
+    let mut first = (0, 1, f64::INFINITY);
+    for i in 0..estimated.len() {
+        for j in i + 1..estimated.len() {
+            let rows = join_rows(&estimated[i], &estimated[j], &keys, tables);
+            if rows < first.2 {
+                first = (i, j, rows);
+            }
+        }
+    }
+    let mut order = vec![first.0, first.1];
+    let mut joined = (join(estimated[first.0].0.clone(), estimated[first.1].0.clone(), &keys)?, first.2);
+    while order.len() < estimated.len() {
+        let (next, rows) = (0..estimated.len())
+            .filter(|i| !order.contains(i))
+            .map(|i| (i, join_rows(&joined, &estimated[i], &keys, tables)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        order.push(next);
+        joined = (join(joined.0, estimated[next].0.clone(), &keys)?, rows);
+    }
+    if left_deep && order[2..].iter().enumerate().all(|(i, input)| *input == i + 2) {
+        return Ok(Transformed::no(plan));
+    }
+
+    // Reorder joins inside the inputs too, and keep the columns in the
+    // order the query joined them
+    let mut inputs = Vec::new();
+    for (input, _) in estimated {
+        inputs.push(reorder_all(input, tables)?.data);
+    }
+    let mut reordered = join(inputs[order[0]].clone(), inputs[order[1]].clone(), &keys)?;
+    for next in &order[2..] {
+        reordered = join(reordered, inputs[*next].clone(), &keys)?;
+    }
+    let columns = plan.schema().columns().into_iter().map(Expr::Column);
+    let reordered = LogicalPlanBuilder::from(reordered).project(columns)?.build()?;
+    Ok(Transformed::new(reordered, true, TreeNodeRecursion::Jump))
+}
+
+/// Join `left` and `right` on the keys between them, or cross join them
+fn join(left: LogicalPlan, right: LogicalPlan, keys: &[(Column, Column)]) -> Result<LogicalPlan> {
+    let (left_keys, right_keys): (Vec<Column>, Vec<Column>) = keys_between(&left, &right, keys).into_iter().unzip();
+    let builder = LogicalPlanBuilder::from(left);
+    let builder = if left_keys.is_empty() {
+        builder.cross_join(right)?
+    } else {
+        builder.join(right, JoinType::Inner, (left_keys, right_keys), None)?
+    };
+    builder.build()
+}
+
+/// The key pairs with one column from `left` and the other from `right`,
+/// left column first
+fn keys_between(left: &LogicalPlan, right: &LogicalPlan, keys: &[(Column, Column)]) -> Vec<(Column, Column)> {
+    keys.iter()
+        .filter_map(|(a, b)| {
+            if left.schema().has_column(a) && right.schema().has_column(b) {
+                Some((a.clone(), b.clone()))
+            } else if left.schema().has_column(b) && right.schema().has_column(a) {
+                Some((b.clone(), a.clone()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Estimated rows of an inner join of two estimated inputs
+fn join_rows(
+    (left, left_rows): &(LogicalPlan, f64),
+    (right, right_rows): &(LogicalPlan, f64),
+    keys: &[(Column, Column)],
+    tables: &HashMap<String, TableStatistics>,
+) -> f64 {
+    let divisor = keys_between(left, right, keys)
+        .iter()
+        .map(|(l, r)| {
+            let left_distinct = column_distinct(left, l, tables).unwrap_or(*left_rows).min(*left_rows);
+            let right_distinct = column_distinct(right, r, tables).unwrap_or(*right_rows).min(*right_rows);
+            left_distinct.max(right_distinct)
+        })
+        .fold(1.0, f64::max);
+    left_rows * right_rows / divisor
+}
+
+/// Estimated output rows of `plan`, `None` if it reads a table that has not
+/// been analyzed or uses an operator the estimate does not cover
+fn estimate_rows(plan: &LogicalPlan, tables: &HashMap<String, TableStatistics>) -> Option<f64> {
+    match plan {
+        LogicalPlan::TableScan(scan) => {
+            let rows = tables.get(scan.table_name.table())?.row_count as f64;
+            let rows = if scan.filters.is_empty() { rows } else { rows * FILTER_SELECTIVITY };
+            Some(scan.fetch.map_or(rows, |fetch| rows.min(fetch as f64)))
+        }
+        LogicalPlan::Filter(filter) => Some(estimate_rows(&filter.input, tables)? * FILTER_SELECTIVITY),
+        LogicalPlan::Projection(projection) => estimate_rows(&projection.input, tables),
+        LogicalPlan::SubqueryAlias(alias) => estimate_rows(&alias.input, tables),
+        LogicalPlan::Sort(sort) => {
+            let rows = estimate_rows(&sort.input, tables)?;
+            Some(sort.fetch.map_or(rows, |fetch| rows.min(fetch as f64)))
+        }
+        LogicalPlan::Join(join) => {
+            let left = (join.left.as_ref().clone(), estimate_rows(&join.left, tables)?);
+            let right = (join.right.as_ref().clone(), estimate_rows(&join.right, tables)?);
+            let keys: Vec<_> = join
+                .on
+                .iter()
+                .filter_map(|(l, r)| match (l, r) {
+                    (Expr::Column(l), Expr::Column(r)) => Some((l.clone(), r.clone())),
+                    _ => None,
+                })
+                .collect();
+            let inner = join_rows(&left, &right, &keys, tables);
+            let inner = if join.filter.is_some() { inner * FILTER_SELECTIVITY } else { inner };
+            Some(match join.join_type {
+                JoinType::Inner => inner,
+                JoinType::Left => inner.max(left.1),
+                JoinType::Right => inner.max(right.1),
+                JoinType::Full => inner.max(left.1 + right.1),
+                JoinType::LeftSemi | JoinType::LeftAnti | JoinType::LeftMark => left.1,
+                JoinType::RightSemi | JoinType::RightAnti => right.1,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Distinct values of `column` of `plan`, traced back to an analyzed table
+fn column_distinct(plan: &LogicalPlan, column: &Column, tables: &HashMap<String, TableStatistics>) -> Option<f64> {
+    match plan {
+        LogicalPlan::TableScan(scan) => {
+            tables.get(scan.table_name.table())?.distinct_count(&column.name).map(|count| count as f64)
+        }
+        LogicalPlan::Join(join) if join.left.schema().has_column(column) => column_distinct(&join.left, column, tables),
+        LogicalPlan::Join(join) => column_distinct(&join.right, column, tables),
+        LogicalPlan::SubqueryAlias(alias) => {
+            let index = plan.schema().index_of_column(column).ok()?;
+            column_distinct(&alias.input, &alias.input.schema().columns()[index], tables)
+        }
+        LogicalPlan::Projection(projection) => {
+            let index = plan.schema().index_of_column(column).ok()?;
+            match &projection.expr[index] {
+                Expr::Column(inner) => column_distinct(&projection.input, inner, tables),
+                Expr::Alias(alias) => match alias.expr.as_ref() {
+                    Expr::Column(inner) => column_distinct(&projection.input, inner, tables),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        LogicalPlan::Filter(filter) => column_distinct(&filter.input, column, tables),
+        LogicalPlan::Sort(sort) => column_distinct(&sort.input, column, tables),
+        _ => None,
+    }
+}
+
+/// Tables `plan` reads, in plan order
+fn table_names(plan: &LogicalPlan) -> Vec<String> {
+    let mut names = Vec::new();
+    let _ = plan.apply_with_subqueries(|node| {
+        if let LogicalPlan::TableScan(scan) = node {
+            names.push(scan.table_name.table().to_string());
+        }
+        Ok(TreeNodeRecursion::Continue)
+    });
+    names
+}
+
+/// Describe the joins of the optimized plan `plan`, running each join to
+/// count its rows
+pub(crate) async fn diagnose(
+    state: SessionState,
+    plan: &LogicalPlan,
+    statistics: &StatisticsStore,
+    reorder_joins: bool,
+) -> Result<JoinDiagnostics> {
+    let mut joins = Vec::new();
+    plan.apply_with_subqueries(|node| {
+        if let LogicalPlan::Join(join) = node {
+            joins.push((node.clone(), join.left.clone(), join.right.clone(), join.join_type));
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })?;
+    joins.reverse();
+
+    let estimates: Vec<_> = {
+        let tables = statistics.tables.read().unwrap();
+        joins.iter().map(|(join, ..)| estimate_rows(join, &tables)).collect()
+    };
+    let mut steps = Vec::new();
+    for ((join, left, right, join_type), estimate) in joins.into_iter().zip(estimates) {
+        let actual_rows = DataFrame::new(state.clone(), join).count().await? as u64;
+        steps.push(JoinStep {
+            left_tables: table_names(&left),
+            right_tables: table_names(&right),
+            join_type: join_type.to_string(),
+            estimated_rows: estimate.map(|rows| rows.round() as u64),
+            actual_rows,
+        });
+    }
+    Ok(JoinDiagnostics { reorder_joins, join_order: table_names(plan), joins: steps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_analyze() {
+        assert_eq!(parse_analyze("ANALYZE TABLE Orders"), Some("orders".to_string()));
+        assert_eq!(parse_analyze("analyze table orders compute statistics;"), Some("orders".to_string()));
+        assert_eq!(parse_analyze("ANALYZE orders"), None);
+        assert_eq!(parse_analyze("EXPLAIN ANALYZE SELECT 1"), None);
+    }
+}
@@ -0,0 +1,1109 @@
+//! Kafka topics streamed into tables
+//!
+//! A [`KafkaSource`] consumes every partition of one topic, gathers messages
+//! into batches of up to `max_batch_messages` (or whatever arrived within
+//! `max_batch_delay_ms`) and appends each batch to a table, creating the table
+//! from the first batch if it does not exist. Message values are JSON objects
+//! or Avro records in the schema registry wire format (a zero byte, the
+//! 4-byte schema id, then the Avro body); Avro schemas are fetched from the
+//! registry by id and cached.
+//!
+//! After every append the next offset of each partition is written to the
+//! checkpoint file, if one is configured, and committed to the consumer
+//! group. A restarted source resumes from the checkpoint, then from the
+//! group's committed offsets, then from `start_offset`. Delivery is
+//! at-least-once: a crash between an append and its checkpoint replays that
+//! batch. Messages that cannot be decoded, or do not fit the table's columns,
+//! are counted and skipped so one bad message cannot stall a partition.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use datafusion::arrow::compute::concat_batches;
+use datafusion::arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::json::reader::infer_json_schema_from_iterator;
+use datafusion::arrow::json::ReaderBuilder;
+use datafusion::arrow::record_batch::RecordBatch;
+use parking_lot::Mutex;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::engine::BlazeQueryEngine;
+use crate::error::{BlazeError, BlazeResult};
+use crate::{config_error, invalid_input};
+
+/// Encoding of message values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaMessageFormat {
+    /// One JSON object per message
+    Json,
+    /// Avro records framed with their schema registry id
+    Avro,
+}
+
+impl KafkaMessageFormat {
+    /// Parse `json` or `avro`
+    pub fn parse(name: &str) -> BlazeResult<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "avro" => Ok(Self::Avro),
+            _ => Err(invalid_input!("Unsupported Kafka message format '{}'; expected JSON or AVRO", name)),
+        }
+    }
+}
+
+/// Where partitions without a checkpoint or committed offset start
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartOffset {
+    /// The oldest retained message
+    Earliest,
+    /// Only messages produced after the source starts
+    Latest,
+}
+
+/// Settings of a Kafka source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KafkaSourceConfig {
+    /// Bootstrap servers, e.g. `localhost:9092`
+    pub brokers: String,
+    /// Topic to consume
+    pub topic: String,
+    /// Consumer group offsets are committed to
+    pub group_id: String,
+    /// Table the messages are appended to
+    pub table_name: String,
+    /// Encoding of message values
+    pub format: KafkaMessageFormat,
+    /// Schema registry base URL, required for Avro, e.g. `http://localhost:8081`
+    pub schema_registry_url: Option<String>,
+    /// Most messages appended as one batch (default: 1000)
+    pub max_batch_messages: usize,
+    /// Longest a received message waits before its batch is appended (default: 1000)
+    pub max_batch_delay_ms: u64,
+    /// File the next offset of each partition is written to after every append
+    pub checkpoint_path: Option<PathBuf>,
+    /// Start of partitions with no checkpoint or committed offset (default: earliest)
+    pub start_offset: StartOffset,
+    /// Extra librdkafka settings, e.g. `security.protocol`
+    pub consumer_options: HashMap<String, String>,
+}
+
+impl KafkaSourceConfig {
+    /// JSON messages from `topic` appended to `table_name`, committed to the
+    /// consumer group `bqlite-<table_name>`
+    pub fn new(brokers: &str, topic: &str, table_name: &str) -> Self {
+        Self {
+            brokers: brokers.to_string(),
+            topic: topic.to_string(),
+            group_id: format!("bqlite-{}", table_name),
+            table_name: table_name.to_string(),
+            format: KafkaMessageFormat::Json,
+            schema_registry_url: None,
+            max_batch_messages: 1000,
+            max_batch_delay_ms: 1000,
+            checkpoint_path: None,
+            start_offset: StartOffset::Earliest,
+            consumer_options: HashMap::new(),
+        }
+    }
+
+    fn validate(&self) -> BlazeResult<()> {
+        for (name, value) in [("brokers", &self.brokers), ("topic", &self.topic), ("table_name", &self.table_name)] {
+            if value.trim().is_empty() {
+                return Err(config_error!("Kafka source {} must not be empty", name));
+            }
+        }
+        if self.max_batch_messages == 0 {
+            return Err(config_error!("max_batch_messages must be greater than 0"));
+        }
+        if self.format == KafkaMessageFormat::Avro && self.schema_registry_url.is_none() {
+            return Err(config_error!("Avro messages need a schema_registry_url"));
+        }
+        Ok(())
+    }
+}
+
+/// Next offset to consume in each partition of a topic
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KafkaCheckpoint {
+    /// Topic the offsets belong to
+    pub topic: String,
+    /// Next offset keyed by partition
+    pub offsets: BTreeMap<i32, i64>,
+}
+
+impl KafkaCheckpoint {
+    /// Read the checkpoint of `topic` from `path`; `None` if the file does not
+    /// exist yet
+    pub fn load(path: &Path, topic: &str) -> BlazeResult<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let checkpoint: Self = serde_json::from_str(&text)?;
+        if checkpoint.topic != topic {
+            return Err(config_error!(
+                "Checkpoint {} is for topic '{}', not '{}'",
+                path.display(),
+                checkpoint.topic,
+                topic
+            ));
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Write the checkpoint, replacing the file only once it is complete
+    pub fn save(&self, path: &Path) -> BlazeResult<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Progress of a Kafka source
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KafkaSourceStats {
+    /// Table the messages are appended to
+    pub table_name: String,
+    /// Topic consumed
+    pub topic: String,
+    /// Messages received, including skipped ones
+    pub messages_consumed: u64,
+    /// Rows appended to the table
+    pub rows_appended: u64,
+    /// Appends made
+    pub batches_appended: u64,
+    /// Messages that could not be decoded or did not fit the table
+    pub messages_skipped: u64,
+    /// Next offset of each partition as of the last checkpoint
+    pub offsets: BTreeMap<i32, i64>,
+    /// Most recent skipped message or failed append
+    pub last_error: Option<String>,
+}
+
+/// A consumer appending one topic to a table in the background
+pub struct KafkaSource {
+    stats: Arc<Mutex<KafkaSourceStats>>,
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<BlazeResult<()>>,
+}
+
+impl KafkaSource {
+    /// Connect to the brokers, assign every partition of the topic at its
+    /// starting offset and start appending to the table
+    pub async fn start(engine: Arc<BlazeQueryEngine>, config: KafkaSourceConfig) -> BlazeResult<Self> {
+        config.validate()?;
+        let checkpoint = match &config.checkpoint_path {
+            Some(path) => KafkaCheckpoint::load(path, &config.topic)?,
+            None => None,
+        };
+        let checkpoint = checkpoint.unwrap_or_else(|| KafkaCheckpoint {
+            topic: config.topic.clone(),
+            offsets: BTreeMap::new(),
+        });
+
+        let mut client = ClientConfig::new();
+        client
+            .set("bootstrap.servers", &config.brokers)
+            .set("group.id", &config.group_id)
+            .set("enable.auto.commit", "false")
+            .set("enable.auto.offset.store", "false")
+            .set(
+                "auto.offset.reset",
+                match config.start_offset {
+                    StartOffset::Earliest => "earliest",
+                    StartOffset::Latest => "latest",
+                },
+            );
+        for (key, value) in &config.consumer_options {
+            client.set(key, value);
+        }
+        let consumer: Arc<StreamConsumer> = Arc::new(client.create().map_err(kafka_error)?);
+
+        // Partitions are assigned explicitly, not through group rebalancing,
+        // so the checkpoint decides where each one starts
+        let partitions = {
+            let consumer = consumer.clone();
+            let topic = config.topic.clone();
+            tokio::task::spawn_blocking(move || {
+                let metadata = consumer.fetch_metadata(Some(&topic), Duration::from_secs(10)).map_err(kafka_error)?;
+                let partitions: Vec<i32> = metadata
+                    .topics()
+                    .iter()
+                    .filter(|t| t.name() == topic && t.error().is_none())
+                    .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+                    .collect();
+                if partitions.is_empty() {
+                    return Err(invalid_input!("Kafka topic '{}' not found", topic));
+                }
+                Ok(partitions)
+            })
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Kafka metadata request failed: {}", e)))??
+        };
+        let mut assignment = TopicPartitionList::new();
+        for partition in partitions {
+            let offset = checkpoint.offsets.get(&partition).map_or(Offset::Stored, |&o| Offset::Offset(o));
+            assignment
+                .add_partition_offset(&config.topic, partition, offset)
+                .map_err(kafka_error)?;
+        }
+        consumer.assign(&assignment).map_err(kafka_error)?;
+
+        let stats = Arc::new(Mutex::new(KafkaSourceStats {
+            table_name: config.table_name.clone(),
+            topic: config.topic.clone(),
+            offsets: checkpoint.offsets.clone(),
+            ..Default::default()
+        }));
+        let ingestor = KafkaIngestor::new(engine, &config, checkpoint, stats.clone())?;
+        let (shutdown, stopped) = watch::channel(false);
+        let task = tokio::spawn(run(consumer, ingestor, config, stopped));
+
+        Ok(Self { stats, shutdown, task })
+    }
+
+    /// Progress so far
+    pub fn stats(&self) -> KafkaSourceStats {
+        self.stats.lock().clone()
+    }
+
+    /// Whether the consumer is still running
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Stop consuming, append the messages already received and wait for
+    /// the consumer to exit
+    pub async fn stop(self) -> BlazeResult<KafkaSourceStats> {
+        let _ = self.shutdown.send(true);
+        self.task
+            .await
+            .map_err(|e| BlazeError::Internal(format!("Kafka source for '{}' failed: {}", self.stats.lock().table_name, e)))??;
+        Ok(self.stats.lock().clone())
+    }
+}
+
+fn kafka_error(e: KafkaError) -> BlazeError {
+    BlazeError::External(format!("Kafka: {}", e))
+}
+
+/// Receive messages until stopped, appending a batch when it is full or its
+/// oldest message has waited `max_batch_delay_ms`. A failed append keeps the
+/// batch and is retried after the same delay.
+async fn run(
+    consumer: Arc<StreamConsumer>,
+    mut ingestor: KafkaIngestor,
+    config: KafkaSourceConfig,
+    mut stopped: watch::Receiver<bool>,
+) -> BlazeResult<()> {
+    let delay = Duration::from_millis(config.max_batch_delay_ms);
+    let mut deadline = Instant::now() + delay;
+    info!("Streaming Kafka topic '{}' into table '{}'", config.topic, config.table_name);
+
+    loop {
+        let full = ingestor.pending_len() >= config.max_batch_messages;
+        tokio::select! {
+            _ = stopped.changed() => break,
+            received = consumer.recv(), if !full => match received {
+                Ok(message) => {
+                    if ingestor.pending_len() == 0 {
+                        deadline = Instant::now() + delay;
+                    }
+                    ingestor.push(message.partition(), message.offset(), message.payload());
+                    if ingestor.pending_len() < config.max_batch_messages {
+                        continue;
+                    }
+                }
+                Err(e) => {
+                    ingestor.record_error(kafka_error(e).to_string());
+                    continue;
+                }
+            },
+            _ = tokio::time::sleep_until(deadline), if ingestor.pending_len() > 0 => {}
+        }
+
+        match ingestor.flush().await {
+            Ok(()) => commit(&consumer, &ingestor.checkpoint),
+            Err(e) => {
+                warn!("Append from Kafka topic '{}' to '{}' failed, retrying: {}", config.topic, config.table_name, e);
+                ingestor.record_error(e.to_string());
+                deadline = Instant::now() + delay;
+            }
+        }
+    }
+
+    ingestor.flush().await?;
+    commit(&consumer, &ingestor.checkpoint);
+    info!("Stopped streaming Kafka topic '{}' into table '{}'", config.topic, config.table_name);
+    Ok(())
+}
+
+/// Commit checkpointed offsets to the consumer group; the checkpoint file, if
+/// any, stays the primary record, so a failed commit is only logged
+fn commit(consumer: &StreamConsumer, checkpoint: &KafkaCheckpoint) {
+    if checkpoint.offsets.is_empty() {
+        return;
+    }
+    let mut offsets = TopicPartitionList::new();
+    for (&partition, &offset) in &checkpoint.offsets {
+        if let Err(e) = offsets.add_partition_offset(&checkpoint.topic, partition, Offset::Offset(offset)) {
+            warn!("Cannot commit offset {} of partition {}: {}", offset, partition, e);
+            return;
+        }
+    }
+    if let Err(e) = consumer.commit(&offsets, CommitMode::Async) {
+        warn!("Committing Kafka offsets for '{}' failed: {}", checkpoint.topic, e);
+    }
+}
+
+struct PendingMessage {
+    partition: i32,
+    offset: i64,
+    payload: Option<Vec<u8>>,
+}
+
+/// Turns received messages into appends and checkpoints, independently of
+/// the consumer they come from
+struct KafkaIngestor {
+    engine: Arc<BlazeQueryEngine>,
+    table_name: String,
+    format: KafkaMessageFormat,
+    registry: Option<SchemaRegistry>,
+    checkpoint: KafkaCheckpoint,
+    checkpoint_path: Option<PathBuf>,
+    pending: Vec<PendingMessage>,
+    stats: Arc<Mutex<KafkaSourceStats>>,
+}
+
+impl KafkaIngestor {
+    fn new(
+        engine: Arc<BlazeQueryEngine>,
+        config: &KafkaSourceConfig,
+        checkpoint: KafkaCheckpoint,
+        stats: Arc<Mutex<KafkaSourceStats>>,
+    ) -> BlazeResult<Self> {
+        let registry = config.schema_registry_url.as_deref().map(SchemaRegistry::new).transpose()?;
+        Ok(Self {
+            engine,
+            table_name: config.table_name.clone(),
+            format: config.format,
+            registry,
+            checkpoint,
+            checkpoint_path: config.checkpoint_path.clone(),
+            pending: Vec::new(),
+            stats,
+        })
+    }
+
+    fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn push(&mut self, partition: i32, offset: i64, payload: Option<&[u8]>) {
+        self.pending.push(PendingMessage {
+            partition,
+            offset,
+            payload: payload.map(<[u8]>::to_vec),
+        });
+        self.stats.lock().messages_consumed += 1;
+    }
+
+    fn record_error(&self, error: String) {
+        self.stats.lock().last_error = Some(error);
+    }
+
+    /// Append the pending messages and advance the checkpoint past them. On
+    /// failure nothing is appended and the messages stay pending.
+    async fn flush(&mut self) -> BlazeResult<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        let result = self.append(&pending).await;
+        self.pending = pending;
+        let (appended, skipped) = result?;
+
+        for message in self.pending.drain(..) {
+            let next = self.checkpoint.offsets.entry(message.partition).or_insert(0);
+            *next = (*next).max(message.offset + 1);
+        }
+        if let Some(path) = &self.checkpoint_path {
+            self.checkpoint.save(path)?;
+        }
+
+        let mut stats = self.stats.lock();
+        stats.rows_appended += appended as u64;
+        stats.batches_appended += u64::from(appended > 0);
+        stats.messages_skipped += skipped.len() as u64;
+        stats.offsets = self.checkpoint.offsets.clone();
+        if let Some(last) = skipped.last() {
+            warn!("Skipped {} Kafka messages for '{}'; last: {}", skipped.len(), self.table_name, last);
+            stats.last_error = Some(last.clone());
+        }
+        Ok(())
+    }
+
+    /// Decode and append messages, returning the rows appended and why each
+    /// skipped message was skipped
+    async fn append(&mut self, messages: &[PendingMessage]) -> BlazeResult<(usize, Vec<String>)> {
+        let mut rows = Vec::with_capacity(messages.len());
+        let mut writer_schema = None;
+        let mut skipped = Vec::new();
+        for message in messages {
+            // Tombstones carry no row
+            let Some(payload) = &message.payload else { continue };
+            match self.decode(payload).await {
+                Ok((row, schema)) => {
+                    rows.push(row);
+                    writer_schema = writer_schema.or(schema);
+                }
+                // The registry being unreachable is not the message's fault
+                Err(e @ BlazeError::External(_)) => return Err(e),
+                Err(e) => skipped.push(format!("partition {} offset {}: {}", message.partition, message.offset, e)),
+            }
+        }
+
+        let mut appended = 0;
+        if !rows.is_empty() {
+            let existing = self.engine.table_schema(&self.table_name).await;
+            let schema = match (&existing, writer_schema) {
+                (Some(schema), _) => schema.clone(),
+                (None, Some(schema)) => schema,
+                (None, None) => Arc::new(infer_json_schema_from_iterator(rows.iter().map(Ok))?),
+            };
+            let (batch, rejected) = rows_to_batch(&schema, &rows)?;
+            skipped.extend(rejected.into_iter().map(|(_, reason)| reason));
+
+            appended = batch.num_rows();
+            if existing.is_some() {
+                self.engine.append_to_table(&self.table_name, vec![batch]).await?;
+            } else if appended > 0 {
+                self.engine.register_table(&self.table_name, vec![batch]).await?;
+            }
+        }
+        Ok((appended, skipped))
+    }
+
+    /// A message as a JSON row, with the Arrow schema its Avro writer schema
+    /// maps to
+    async fn decode(&mut self, payload: &[u8]) -> BlazeResult<(Value, Option<SchemaRef>)> {
+        match self.format {
+            KafkaMessageFormat::Json => match serde_json::from_slice(payload)? {
+                row @ Value::Object(_) => Ok((row, None)),
+                other => Err(invalid_input!("expected a JSON object, got {}", other)),
+            },
+            KafkaMessageFormat::Avro => {
+                let registry = self.registry.as_mut().expect("Avro sources have a schema registry");
+                let (id, body) = match payload {
+                    [0, a, b, c, d, body @ ..] => (u32::from_be_bytes([*a, *b, *c, *d]), body),
+                    _ => return Err(invalid_input!("message does not start with a schema registry header")),
+                };
+                let schema = registry.schema(id).await?;
+                let row = AvroReader::new(body).read(&schema.root)?;
+                Ok((row, Some(schema.arrow.clone())))
+            }
+        }
+    }
+}
+
+/// Rows decoded against `schema`, with the index and reason of each row that
+/// does not fit it
+fn rows_to_batch(schema: &SchemaRef, rows: &[Value]) -> BlazeResult<(RecordBatch, Vec<(usize, String)>)> {
+    let decode = |rows: &[Value]| -> Result<Option<RecordBatch>, _> {
+        let mut decoder = ReaderBuilder::new(schema.clone())
+            .with_batch_size(rows.len().max(1))
+            .build_decoder()?;
+        decoder.serialize(rows)?;
+        decoder.flush()
+    };
+
+    if let Ok(batch) = decode(rows) {
+        return Ok((batch.unwrap_or_else(|| RecordBatch::new_empty(schema.clone())), Vec::new()));
+    }
+    // Find the offending rows one at a time
+    let mut batches = Vec::new();
+    let mut rejected = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        match decode(std::slice::from_ref(row)) {
+            Ok(batch) => batches.extend(batch),
+            Err(e) => rejected.push((index, e.to_string())),
+        }
+    }
+    Ok((concat_batches(schema, &batches)?, rejected))
+}
+
+/// Avro schemas fetched by id from a Confluent-compatible registry
+struct SchemaRegistry {
+    client: reqwest::Client,
+    url: String,
+    schemas: HashMap<u32, Arc<AvroSchema>>,
+}
+
+struct AvroSchema {
+    root: AvroType,
+    arrow: SchemaRef,
+}
+
+#[derive(Deserialize)]
+struct RegistrySchema {
+    schema: String,
+}
+
+impl SchemaRegistry {
+    /// A registry at `http[s]://host[:port][/path]`
+    fn new(url: &str) -> BlazeResult<Self> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| config_error!("Invalid schema registry URL '{}': {}", url, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(config_error!("Schema registry URL '{}' must start with http:// or https://", url));
+        }
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(|e| config_error!("Cannot create a client for schema registry '{}': {}", url, e))?;
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            schemas: HashMap::new(),
+        })
+    }
+
+    async fn schema(&mut self, id: u32) -> BlazeResult<Arc<AvroSchema>> {
+        if let Some(schema) = self.schemas.get(&id) {
+            return Ok(schema.clone());
+        }
+
+        let (status, body) = self
+            .get(&format!("/schemas/ids/{}", id))
+            .await
+            .map_err(|e| BlazeError::External(format!("Schema registry {}: {}", self.url, e)))?;
+        match status {
+            200 => {}
+            404 => return Err(invalid_input!("schema id {} is not in the registry", id)),
+            _ => {
+                return Err(BlazeError::External(format!(
+                    "Schema registry {} returned HTTP {}: {}",
+                    self.url,
+                    status,
+                    String::from_utf8_lossy(&body).trim()
+                )))
+            }
+        }
+        let response: RegistrySchema = serde_json::from_slice(&body)?;
+        let root = parse_avro_schema(&serde_json::from_str(&response.schema)?, &mut HashMap::new())?;
+        let AvroType::Record(fields) = &root else {
+            return Err(invalid_input!("schema id {} is not an Avro record", id));
+        };
+        let arrow = Arc::new(Schema::new(avro_fields(fields)?));
+        let schema = Arc::new(AvroSchema { root, arrow });
+        self.schemas.insert(id, schema.clone());
+        Ok(schema)
+    }
+
+    async fn get(&self, path: &str) -> reqwest::Result<(u16, Vec<u8>)> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .header(reqwest::header::ACCEPT, "application/vnd.schemaregistry.v1+json, application/json")
+            .send()
+            .await?;
+        let status = response.status().as_u16();
+        Ok((status, response.bytes().await?.to_vec()))
+    }
+}
+
+/// The subset of Avro the source reads. Named types may be referenced after
+/// their definition; recursive types are not supported.
+#[derive(Debug, Clone)]
+enum AvroType {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Date,
+    TimestampMillis,
+    TimestampMicros,
+    Decimal { precision: u8, scale: i8, fixed_size: Option<usize> },
+    Record(Vec<(String, AvroType)>),
+    Enum(Vec<String>),
+    Array(Box<AvroType>),
+    Map(Box<AvroType>),
+    Fixed(usize),
+    Union(Vec<AvroType>),
+}
+
+fn parse_avro_schema(schema: &Value, names: &mut HashMap<String, AvroType>) -> BlazeResult<AvroType> {
+    let definition = match schema {
+        Value::String(name) => {
+            return Ok(match name.as_str() {
+                "null" => AvroType::Null,
+                "boolean" => AvroType::Boolean,
+                "int" => AvroType::Int,
+                "long" => AvroType::Long,
+                "float" => AvroType::Float,
+                "double" => AvroType::Double,
+                "bytes" => AvroType::Bytes,
+                "string" => AvroType::String,
+                name => names
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| invalid_input!("Unknown Avro type '{}'", name))?,
+            })
+        }
+        Value::Array(branches) => {
+            return Ok(AvroType::Union(
+                branches.iter().map(|branch| parse_avro_schema(branch, names)).collect::<BlazeResult<_>>()?,
+            ))
+        }
+        Value::Object(definition) => definition,
+        other => return Err(invalid_input!("Invalid Avro schema {}", other)),
+    };
+
+    let type_name = definition.get("type").and_then(Value::as_str);
+    let logical = match (definition.get("logicalType").and_then(Value::as_str), type_name) {
+        (Some("date"), Some("int")) => Some(AvroType::Date),
+        (Some("timestamp-millis"), Some("long")) => Some(AvroType::TimestampMillis),
+        (Some("timestamp-micros"), Some("long")) => Some(AvroType::TimestampMicros),
+        (Some("decimal"), Some("bytes" | "fixed")) => Some(AvroType::Decimal {
+            precision: definition.get("precision").and_then(Value::as_u64).unwrap_or(38).min(38) as u8,
+            scale: definition.get("scale").and_then(Value::as_i64).unwrap_or(0) as i8,
+            fixed_size: definition.get("size").and_then(Value::as_u64).map(|size| size as usize),
+        }),
+        // Unknown logical types read as their underlying type
+        _ => None,
+    };
+
+    let parsed = match (logical, type_name) {
+        (Some(logical), _) => logical,
+        (None, Some("record")) => {
+            let fields = definition
+                .get("fields")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid_input!("Avro record without fields"))?
+                .iter()
+                .map(|field| {
+                    let name = field
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| invalid_input!("Avro field without a name"))?;
+                    let field_type = field.get("type").ok_or_else(|| invalid_input!("Avro field '{}' without a type", name))?;
+                    Ok((name.to_string(), parse_avro_schema(field_type, names)?))
+                })
+                .collect::<BlazeResult<_>>()?;
+            AvroType::Record(fields)
+        }
+        (None, Some("enum")) => AvroType::Enum(
+            definition
+                .get("symbols")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid_input!("Avro enum without symbols"))?
+                .iter()
+                .map(|symbol| symbol.as_str().unwrap_or_default().to_string())
+                .collect(),
+        ),
+        (None, Some("array")) => AvroType::Array(Box::new(parse_avro_schema(
+            definition.get("items").ok_or_else(|| invalid_input!("Avro array without items"))?,
+            names,
+        )?)),
+        (None, Some("map")) => AvroType::Map(Box::new(parse_avro_schema(
+            definition.get("values").ok_or_else(|| invalid_input!("Avro map without values"))?,
+            names,
+        )?)),
+        (None, Some("fixed")) => AvroType::Fixed(
+            definition
+                .get("size")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid_input!("Avro fixed without a size"))? as usize,
+        ),
+        (None, _) => parse_avro_schema(definition.get("type").unwrap_or(&Value::Null), names)?,
+    };
+
+    if let Some(name) = definition.get("name").and_then(Value::as_str) {
+        names.insert(name.to_string(), parsed.clone());
+        if let Some(namespace) = definition.get("namespace").and_then(Value::as_str) {
+            names.insert(format!("{}.{}", namespace, name), parsed.clone());
+        }
+    }
+    Ok(parsed)
+}
+
+fn avro_fields(fields: &[(String, AvroType)]) -> BlazeResult<Vec<Field>> {
+    fields
+        .iter()
+        .map(|(name, field_type)| {
+            let (data_type, nullable) = arrow_type(field_type)?;
+            Ok(Field::new(name, data_type, nullable))
+        })
+        .collect()
+}
+
+/// The Arrow type of an Avro type and whether it is nullable. Bytes and fixed
+/// values become lowercase hex strings.
+fn arrow_type(avro: &AvroType) -> BlazeResult<(DataType, bool)> {
+    Ok(match avro {
+        AvroType::Null => (DataType::Null, true),
+        AvroType::Boolean => (DataType::Boolean, false),
+        AvroType::Int => (DataType::Int32, false),
+        AvroType::Long => (DataType::Int64, false),
+        AvroType::Float => (DataType::Float32, false),
+        AvroType::Double => (DataType::Float64, false),
+        AvroType::Bytes | AvroType::Fixed(_) | AvroType::String | AvroType::Enum(_) => (DataType::Utf8, false),
+        AvroType::Date => (DataType::Date32, false),
+        AvroType::TimestampMillis => (DataType::Timestamp(TimeUnit::Millisecond, None), false),
+        AvroType::TimestampMicros => (DataType::Timestamp(TimeUnit::Microsecond, None), false),
+        AvroType::Decimal { precision, scale, .. } => (DataType::Decimal128(*precision, *scale), false),
+        AvroType::Record(fields) => (DataType::Struct(Fields::from(avro_fields(fields)?)), false),
+        AvroType::Array(items) => {
+            let (item_type, nullable) = arrow_type(items)?;
+            (DataType::List(Arc::new(Field::new("item", item_type, nullable))), false)
+        }
+        AvroType::Map(values) => {
+            let (value_type, nullable) = arrow_type(values)?;
+            let entries = Fields::from(vec![
+                Field::new("key", DataType::Utf8, false),
+                Field::new("value", value_type, nullable),
+            ]);
+            (DataType::Map(Arc::new(Field::new("entries", DataType::Struct(entries), false)), false), false)
+        }
+        AvroType::Union(branches) => {
+            let values: Vec<_> = branches.iter().filter(|b| !matches!(b, AvroType::Null)).collect();
+            match values.as_slice() {
+                [] => (DataType::Null, true),
+                [value] => (arrow_type(value)?.0, values.len() < branches.len()),
+                _ => return Err(invalid_input!("Avro unions of several non-null types are not supported")),
+            }
+        }
+    })
+}
+
+/// Reads one Avro binary value as JSON
+struct AvroReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AvroReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> BlazeResult<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(invalid_input!("Avro message is truncated"));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    /// Zig-zag variable-length integer, used for both int and long
+    fn read_long(&mut self) -> BlazeResult<i64> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err(invalid_input!("Avro integer is too long"))
+    }
+
+    fn read_bytes(&mut self) -> BlazeResult<&'a [u8]> {
+        let len = self.read_long()?;
+        let len = usize::try_from(len).map_err(|_| invalid_input!("Negative Avro length {}", len))?;
+        self.take(len)
+    }
+
+    /// Items of an array or map, written in blocks each preceded by its count
+    fn read_blocks(&mut self, mut item: impl FnMut(&mut Self) -> BlazeResult<()>) -> BlazeResult<()> {
+        loop {
+            let count = match self.read_long()? {
+                0 => return Ok(()),
+                // A negative count is followed by the block's size in bytes
+                count if count < 0 => {
+                    self.read_long()?;
+                    -count
+                }
+                count => count,
+            };
+            for _ in 0..count {
+                item(self)?;
+            }
+        }
+    }
+
+    fn read(&mut self, avro: &AvroType) -> BlazeResult<Value> {
+        Ok(match avro {
+            AvroType::Null => Value::Null,
+            AvroType::Boolean => Value::Bool(self.take(1)?[0] != 0),
+            AvroType::Int | AvroType::Long | AvroType::Date | AvroType::TimestampMillis | AvroType::TimestampMicros => {
+                self.read_long()?.into()
+            }
+            AvroType::Float => {
+                let value = f32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes"));
+                serde_json::Number::from_f64(f64::from(value)).map_or(Value::Null, Value::Number)
+            }
+            AvroType::Double => {
+                let value = f64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes"));
+                serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
+            }
+            AvroType::String => Value::String(
+                std::str::from_utf8(self.read_bytes()?)
+                    .map_err(|_| invalid_input!("Avro string is not valid UTF-8"))?
+                    .to_string(),
+            ),
+            AvroType::Bytes => Value::String(hex(self.read_bytes()?)),
+            AvroType::Fixed(size) => Value::String(hex(self.take(*size)?)),
+            AvroType::Decimal { scale, fixed_size, .. } => {
+                let bytes = match fixed_size {
+                    Some(size) => self.take(*size)?,
+                    None => self.read_bytes()?,
+                };
+                Value::String(decimal_text(bytes, *scale)?)
+            }
+            AvroType::Enum(symbols) => {
+                let index = self.read_long()?;
+                let symbol = usize::try_from(index).ok().and_then(|i| symbols.get(i));
+                Value::String(symbol.ok_or_else(|| invalid_input!("Avro enum index {} out of range", index))?.clone())
+            }
+            AvroType::Record(fields) => {
+                let mut row = Map::new();
+                for (name, field_type) in fields {
+                    row.insert(name.clone(), self.read(field_type)?);
+                }
+                Value::Object(row)
+            }
+            AvroType::Array(items) => {
+                let mut values = Vec::new();
+                self.read_blocks(|reader| {
+                    values.push(reader.read(items)?);
+                    Ok(())
+                })?;
+                Value::Array(values)
+            }
+            AvroType::Map(values) => {
+                let mut entries = Map::new();
+                self.read_blocks(|reader| {
+                    let key = String::from_utf8_lossy(reader.read_bytes()?).into_owned();
+                    entries.insert(key, reader.read(values)?);
+                    Ok(())
+                })?;
+                Value::Object(entries)
+            }
+            AvroType::Union(branches) => {
+                let index = self.read_long()?;
+                let branch = usize::try_from(index).ok().and_then(|i| branches.get(i));
+                self.read(branch.ok_or_else(|| invalid_input!("Avro union index {} out of range", index))?)?
+            }
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A big-endian two's complement unscaled value as decimal text
+fn decimal_text(bytes: &[u8], scale: i8) -> BlazeResult<String> {
+    if bytes.len() > 16 {
+        return Err(invalid_input!("Avro decimal wider than 128 bits"));
+    }
+    let fill = if bytes.first().is_some_and(|b| b & 0x80 != 0) { 0xff } else { 0 };
+    let mut buffer = [fill; 16];
+    buffer[16 - bytes.len()..].copy_from_slice(bytes);
+    let unscaled = i128::from_be_bytes(buffer);
+    if scale <= 0 {
+        return Ok(format!("{}{}", unscaled, "0".repeat(scale.unsigned_abs() as usize)));
+    }
+
+    let digits = unscaled.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    Ok(format!("{}{}.{}", if unscaled < 0 { "-" } else { "" }, whole, fraction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Array, Int64Array, StringArray};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn zigzag(value: i64) -> Vec<u8> {
+        let mut encoded = ((value << 1) ^ (value >> 63)) as u64;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (encoded & 0x7f) as u8;
+            encoded >>= 7;
+            if encoded == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn avro_string(text: &str) -> Vec<u8> {
+        let mut bytes = zigzag(text.len() as i64);
+        bytes.extend_from_slice(text.as_bytes());
+        bytes
+    }
+
+    const SCHEMA: &str = r#"{"type": "record", "name": "Trip", "fields": [
+        {"name": "id", "type": "long"},
+        {"name": "rider", "type": ["null", "string"]},
+        {"name": "fare", "type": {"type": "bytes", "logicalType": "decimal", "precision": 9, "scale": 2}},
+        {"name": "tags", "type": {"type": "array", "items": "string"}},
+        {"name": "status", "type": {"type": "enum", "name": "Status", "symbols": ["OPEN", "DONE"]}}
+    ]}"#;
+
+    fn trip(id: i64, rider: Option<&str>) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 0, 7];
+        body.extend(zigzag(id));
+        match rider {
+            Some(rider) => {
+                body.extend(zigzag(1));
+                body.extend(avro_string(rider));
+            }
+            None => body.extend(zigzag(0)),
+        }
+        // -12.34 as an unscaled two's complement value
+        body.extend(zigzag(2));
+        body.extend((-1234i16).to_be_bytes());
+        body.extend(zigzag(-2));
+        body.extend(zigzag(8));
+        body.extend(avro_string("a"));
+        body.extend(avro_string("b"));
+        body.extend(zigzag(0));
+        body.extend(zigzag(1));
+        body
+    }
+
+    #[test]
+    fn test_read_avro_record() {
+        let root = parse_avro_schema(&serde_json::from_str(SCHEMA).unwrap(), &mut HashMap::new()).unwrap();
+        let row = AvroReader::new(&trip(-3, Some("ann"))[5..]).read(&root).unwrap();
+        assert_eq!(
+            row,
+            serde_json::json!({"id": -3, "rider": "ann", "fare": "-12.34", "tags": ["a", "b"], "status": "DONE"})
+        );
+
+        let AvroType::Record(fields) = &root else { panic!("record expected") };
+        let schema = Arc::new(Schema::new(avro_fields(fields).unwrap()));
+        assert!(schema.field_with_name("rider").unwrap().is_nullable());
+        assert_eq!(schema.field_with_name("fare").unwrap().data_type(), &DataType::Decimal128(9, 2));
+
+        let (batch, rejected) = rows_to_batch(&schema, &[row, serde_json::json!({"id": "x"})]).unwrap();
+        assert_eq!((batch.num_rows(), rejected.len()), (1, 1));
+        assert_eq!(decimal_text(&[0x01], 3).unwrap(), "0.001");
+        assert!(AvroReader::new(&[0x80]).read(&AvroType::Long).is_err());
+    }
+
+    /// Serve the trip schema under id 7 to every request
+    async fn registry() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let response = if request.starts_with("GET /schemas/ids/7 ") {
+                    let body = serde_json::json!({ "schema": SCHEMA }).to_string();
+                    format!("HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nConnection: close\r\nContent-Length: 2\r\n\r\n{}".to_string()
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_ingest_avro_with_checkpoint() {
+        let engine = Arc::new(BlazeQueryEngine::new().await.unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = KafkaSourceConfig::new("localhost:9092", "trips", "trips");
+        config.format = KafkaMessageFormat::Avro;
+        config.schema_registry_url = Some(registry().await);
+        config.checkpoint_path = Some(dir.path().join("trips.checkpoint"));
+
+        let stats = Arc::new(Mutex::new(KafkaSourceStats::default()));
+        let checkpoint = KafkaCheckpoint { topic: "trips".into(), offsets: BTreeMap::new() };
+        let mut ingestor = KafkaIngestor::new(engine.clone(), &config, checkpoint, stats.clone()).unwrap();
+        ingestor.push(0, 10, Some(&trip(1, Some("ann"))));
+        ingestor.push(1, 4, Some(&trip(2, None)));
+        ingestor.push(0, 11, Some(b"not avro"));
+        ingestor.push(0, 12, None);
+        ingestor.flush().await.unwrap();
+
+        // The second batch appends to the table the first one created
+        ingestor.push(1, 5, Some(&trip(3, Some("bo"))));
+        let mut unknown_schema = trip(4, None);
+        unknown_schema[4] = 8;
+        ingestor.push(1, 6, Some(&unknown_schema));
+        ingestor.flush().await.unwrap();
+
+        let stats = stats.lock().clone();
+        assert_eq!((stats.messages_consumed, stats.rows_appended, stats.batches_appended), (6, 3, 2));
+        assert_eq!(stats.messages_skipped, 2);
+        assert!(stats.last_error.unwrap().contains("schema id 8"));
+
+        let batches = engine
+            .execute_query_batches("SELECT id, rider, fare FROM trips ORDER BY id")
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        let ids = batch.column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.values().to_vec(), vec![1, 2, 3]);
+        let riders = batch.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        assert!(riders.is_null(1));
+
+        let saved = KafkaCheckpoint::load(config.checkpoint_path.as_ref().unwrap(), "trips").unwrap().unwrap();
+        assert_eq!(saved.offsets, BTreeMap::from([(0, 13), (1, 7)]));
+        assert!(KafkaCheckpoint::load(config.checkpoint_path.as_ref().unwrap(), "rides").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ingest_json_into_existing_table() {
+        let engine = Arc::new(BlazeQueryEngine::new().await.unwrap());
+        engine.execute_query("CREATE TABLE clicks (page VARCHAR, ms BIGINT)").await.unwrap();
+
+        let config = KafkaSourceConfig::new("localhost:9092", "clicks", "clicks");
+        let stats = Arc::new(Mutex::new(KafkaSourceStats::default()));
+        let mut ingestor = KafkaIngestor::new(engine.clone(), &config, KafkaCheckpoint::default(), stats.clone()).unwrap();
+        ingestor.push(0, 0, Some(br#"{"page": "/", "ms": 12, "extra": true}"#));
+        ingestor.push(0, 1, Some(br#"{"page": "/a"}"#));
+        ingestor.push(0, 2, Some(br#"{"page": "/b", "ms": "slow"}"#));
+        ingestor.push(0, 3, Some(b"[1, 2]"));
+        ingestor.flush().await.unwrap();
+
+        let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(ms) AS total FROM clicks").await.unwrap();
+        assert_eq!(result.data[0]["n"], 2);
+        assert_eq!(result.data[0]["total"], 12);
+        assert_eq!(stats.lock().messages_skipped, 2);
+        assert_eq!(ingestor.checkpoint.offsets, BTreeMap::from([(0, 4)]));
+    }
+}
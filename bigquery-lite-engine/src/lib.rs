@@ -3,26 +3,168 @@
 //! High-performance query engine using DataFusion for 10x performance improvement
 //! over the Python implementation.
 
-use std::sync::Arc;
-use std::collections::HashMap;
-
-use datafusion::prelude::*;
-use datafusion::execution::context::SessionConfig;
-use datafusion::execution::runtime_env::RuntimeEnv;
-use datafusion::arrow::record_batch::RecordBatch;
-use datafusion::arrow::datatypes::{Schema, Field, DataType};
-
 use pyo3::prelude::*;
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
 
 mod engine;
 mod python_bindings;
+mod registry;
 mod error;
-mod utils;
+mod dependencies;
+#[cfg(feature = "duckdb")]
+mod duckdb_attach;
+#[cfg(feature = "sqlite")]
+mod sqlite_attach;
+#[cfg(feature = "postgres")]
+mod postgres_tables;
+#[cfg(any(feature = "duckdb", feature = "sqlite", feature = "postgres"))]
+mod sql_pushdown;
+mod compression;
+mod copy;
+mod cardinality;
+mod checksums;
+mod clock;
+mod csv_ingest;
+mod dml;
+mod dry_run;
+mod decimal;
+mod engine_state;
+mod export_data;
+mod file_tables;
+mod flight_server;
+mod flight_tables;
+mod geo_ingest;
+mod avro_ingest;
+mod protobuf_ingest;
+#[cfg(feature = "bigquery")]
+mod bigquery_ingest;
+mod orc_tables;
+mod arrow_ipc_tables;
+#[cfg(feature = "delta")]
+mod delta_tables;
+#[cfg(feature = "iceberg")]
+mod iceberg_tables;
+mod join_order;
+mod parquet_sink;
+mod plan_cache;
+mod plan_graph;
+mod plan_regressions;
+mod prepared;
+mod query_history;
+mod profiling;
+mod query_hints;
+mod relations;
+mod result_cache;
+mod result_export;
+mod result_tables;
+mod shared_results;
+mod shutdown;
+mod snapshots;
+mod materialized_views;
+mod memory_pool;
+mod cdc;
+mod ml;
+#[cfg(feature = "object_store")]
+mod object_cache;
+#[cfg(feature = "object_store")]
+mod object_stores;
+mod search;
+mod serializers;
+mod sessions;
+mod vector;
+mod sessionize;
+mod table_functions;
+mod assertions;
+mod sketches;
+mod substr;
+mod slots;
+mod table_eviction;
+mod table_versions;
+mod suggestions;
+mod time_series;
+mod time_partitions;
+mod time_zone;
+mod transactions;
+mod admission;
+mod workload;
+#[cfg(feature = "kafka")]
+mod kafka;
+pub mod config;
+pub mod utils;
+pub mod benchmarks;
+pub mod baselines;
+pub mod datagen;
 
-pub use engine::BlazeQueryEngine;
+pub use engine::{BlazeQueryEngine, EngineConfig, EngineConfigUpdate, EngineStats, LabelStats, QueryFingerprintStats, QueryOptions, QueryResult};
+pub use workload::{ResourceGroup, ResourceGroupStats};
+pub use admission::{AdmissionStats, QueryPriority};
+pub use slots::SlotStats;
 pub use error::{BlazeError, BlazeResult};
+pub use cardinality::{CardinalityEstimate, StageEstimate};
+pub use checksums::ChecksumMode;
+pub use clock::Clock;
+pub use compression::PayloadCompression;
+pub use csv_ingest::{parse_type_name, BadRowPolicy, CsvIngestOptions, CsvLoadReport};
+pub use decimal::{DecimalOverflow, DecimalRules};
+pub use dependencies::TableReferences;
+pub use dry_run::{DryRunReport, ScannedTable};
+pub use engine_state::EngineStateInfo;
+#[cfg(feature = "duckdb")]
+pub use duckdb_attach::AttachedDatabaseInfo;
+#[cfg(feature = "sqlite")]
+pub use sqlite_attach::SqliteDatabaseInfo;
+#[cfg(feature = "postgres")]
+pub use postgres_tables::PostgresTableInfo;
+pub use file_tables::{CsvTableOptions, DataFormat, FileListing, FileTableInfo, JsonTableOptions};
+pub use flight_server::{FlightServer, FlightServerStats};
+pub use flight_tables::{FlightSource, FlightTableInfo};
+pub use geo_ingest::{GeoLoadReport, GEOMETRY_COLUMN};
+pub use avro_ingest::AvroLoadReport;
+pub use protobuf_ingest::{ProtobufFraming, ProtobufLoadReport};
+#[cfg(feature = "bigquery")]
+pub use bigquery_ingest::{BigQueryLoadReport, BigQueryReadOptions};
+pub use orc_tables::OrcTableInfo;
+pub use arrow_ipc_tables::ArrowIpcTableInfo;
+#[cfg(feature = "delta")]
+pub use delta_tables::DeltaTableInfo;
+#[cfg(feature = "iceberg")]
+pub use iceberg_tables::{IcebergSnapshotInfo, IcebergTableInfo, IcebergVersion};
+pub use join_order::{ColumnStatistics, JoinDiagnostics, JoinStep, TableStatistics};
+pub use parquet_sink::{ParquetSinkOptions, ParquetSinkReport, WrittenParquetFile, NULL_PARTITION};
+pub use plan_cache::PlanCacheStats;
+pub use plan_graph::{OperatorMetrics, PlanGraph, PlanNode};
+pub use plan_regressions::{BaselineQuery, PlanBaseline, PlanChange, PlanRegression, PlanRegressionReport};
+pub use prepared::{PreparedParameter, PreparedStatementInfo};
+pub use profiling::QueryProfile;
+pub use query_history::{QueryHistoryEntry, QueryHistoryFilter, QueryStatus};
+pub use relations::{ColumnInfo, FreshnessInfo, RelationInfo, RelationType};
+pub use result_cache::ResultCacheStats;
+pub use result_export::{CsvExportOptions, ExportReport, JsonExportOptions, ParquetExportOptions};
+pub use result_tables::{ResultPage, ResultTableInfo};
+pub use sessions::{SessionInfo, SessionSettings};
+pub use shared_results::SharedResultInfo;
+pub use shutdown::{InterruptedQuery, RunningQueryInfo, ShutdownOptions, ShutdownReport};
+pub use registry::{EngineRegistry, RegisteredEngineInfo};
+pub use snapshots::SnapshotInfo;
+pub use materialized_views::MaterializedViewInfo;
+pub use cdc::{ChangeEvent, ChangeSubscription, ChangeType};
+pub use ml::{Model, ModelType};
+#[cfg(feature = "object_store")]
+pub use object_cache::ObjectCacheStats;
+#[cfg(feature = "object_store")]
+pub use object_stores::{GcsCredentials, ObjectStoreInfo};
+pub use search::SearchIndexInfo;
+pub use serializers::{
+    ArrowIpcSerializer, ColumnarJsonSerializer, CsvSerializer, JsonRowsSerializer, MsgpackSerializer, ResultSerializer,
+    SerializeContext, SerializedResult,
+};
+pub use table_eviction::{EvictionEvent, TableMemoryInfo};
+pub use time_partitions::{DayPartitionInfo, TimePartitionInfo, TimePartitioning};
+pub use transactions::TransactionInfo;
+pub use vector::{DistanceType, VectorIndexInfo};
+pub use assertions::{Assertion, AssertionResult};
+#[cfg(feature = "kafka")]
+pub use kafka::{KafkaCheckpoint, KafkaMessageFormat, KafkaSource, KafkaSourceConfig, KafkaSourceStats, StartOffset};
+pub use datagen::{Bound, ColumnSpec, ColumnType, DatasetSpec, Distribution};
 pub use python_bindings::*;
 
 /// Initialize the Python module
@@ -36,7 +178,21 @@ fn bigquery_lite_engine(_py: Python, m: &PyModule) -> PyResult<()> {
 
     // Register classes and functions
     m.add_class::<PyBlazeQueryEngine>()?;
+    m.add_class::<PySnapshotInfo>()?;
+    m.add_class::<PyRecordBatchStream>()?;
+    m.add_class::<PyFlightServer>()?;
+    #[cfg(feature = "kafka")]
+    m.add_class::<PyKafkaSource>()?;
     m.add_function(wrap_pyfunction!(create_engine, m)?)?;
+    m.add_function(wrap_pyfunction!(get_engine, m)?)?;
+    m.add_function(wrap_pyfunction!(list_engines, m)?)?;
+    m.add_function(wrap_pyfunction!(drop_engine, m)?)?;
+    m.add_function(wrap_pyfunction!(set_global_memory_limit, m)?)?;
+    m.add_function(wrap_pyfunction!(open_shared_result, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(fingerprint_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress_payload, m)?)?;
     
     Ok(())
 }
@@ -0,0 +1,328 @@
+//! Materialized views with incremental maintenance
+//!
+//! A materialized view stores the result of a query as a regular table. When
+//! the view is a simple aggregation (`SELECT keys, COUNT/SUM/MIN/MAX ... FROM
+//! t [WHERE ...] GROUP BY keys`) over a single table, appended batches are
+//! folded in by aggregating only the new rows and merging them with the stored
+//! result. Anything else falls back to a full recomputation.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::tree_node::{Transformed, TreeNode, TreeNodeRecursion};
+use datafusion::common::Column;
+use datafusion::datasource::{provider_as_source, MemTable};
+use datafusion::functions_aggregate::expr_fn::{max, min, sum};
+use datafusion::logical_expr::{Expr, LogicalPlan};
+use datafusion::prelude::{col, SessionContext};
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlazeResult;
+
+/// How a view output column is combined when merging a delta
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergeOp {
+    /// Grouping key
+    Key,
+    /// COUNT and SUM partials are summed
+    Sum,
+    Min,
+    Max,
+}
+
+/// Refresh strategy chosen when the view is created
+#[derive(Debug, Clone)]
+enum RefreshStrategy {
+    /// Output columns paired with their merge operation
+    Incremental(Vec<(String, MergeOp)>),
+    /// Full recomputation, with the reason incremental maintenance is unsupported
+    Full(String),
+}
+
+/// Public description of a materialized view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterializedViewInfo {
+    /// View name
+    pub name: String,
+    /// Defining query
+    pub sql: String,
+    /// Tables the view reads from
+    pub source_tables: Vec<String>,
+    /// Whether appends are applied incrementally
+    pub incremental: bool,
+    /// Why the view needs full refreshes, if it does
+    pub unsupported_reason: Option<String>,
+    /// True when the view may not reflect the latest source data
+    pub stale: bool,
+    /// Time of the last successful refresh
+    pub last_refreshed_at: DateTime<Utc>,
+    /// Number of full recomputations
+    pub full_refreshes: u64,
+    /// Number of incremental delta merges
+    pub incremental_refreshes: u64,
+    /// Rows currently stored in the view
+    pub rows: usize,
+}
+
+/// A materialized view and its stored result
+#[derive(Debug, Clone)]
+pub struct MaterializedView {
+    name: String,
+    sql: String,
+    source_tables: Vec<String>,
+    strategy: RefreshStrategy,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    stale: bool,
+    last_refreshed_at: DateTime<Utc>,
+    full_refreshes: u64,
+    incremental_refreshes: u64,
+}
+
+impl MaterializedView {
+    /// Plan and fully compute a new materialized view
+    pub async fn create(ctx: &SessionContext, name: &str, sql: &str, now: DateTime<Utc>) -> BlazeResult<Self> {
+        let df = ctx.sql(sql).await?;
+        let plan = df.logical_plan().clone();
+        let schema: SchemaRef = Arc::new(df.schema().as_arrow().clone());
+
+        let source_tables = source_tables(&plan)?;
+        let strategy = match incremental_columns(&plan, &source_tables) {
+            Ok(columns) => RefreshStrategy::Incremental(columns),
+            Err(reason) => RefreshStrategy::Full(reason),
+        };
+
+        let batches = df.collect().await?;
+
+        Ok(Self {
+            name: name.to_string(),
+            sql: sql.to_string(),
+            source_tables,
+            strategy,
+            schema,
+            batches,
+            stale: false,
+            last_refreshed_at: now,
+            full_refreshes: 1,
+            incremental_refreshes: 0,
+        })
+    }
+
+    /// View name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the view reads from `table`
+    pub fn depends_on(&self, table: &str) -> bool {
+        self.source_tables.iter().any(|t| t.eq_ignore_ascii_case(table))
+    }
+
+    /// Whether appended batches can be merged without recomputation
+    pub fn is_incremental(&self) -> bool {
+        matches!(self.strategy, RefreshStrategy::Incremental(_))
+    }
+
+    /// Stored result schema
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Stored result batches
+    pub fn batches(&self) -> &[RecordBatch] {
+        &self.batches
+    }
+
+    /// Flag the view as out of date until the next successful refresh
+    pub fn mark_stale(&mut self) {
+        self.stale = true;
+    }
+
+    /// Recompute the view from scratch
+    pub async fn full_refresh(&mut self, ctx: &SessionContext, now: DateTime<Utc>) -> BlazeResult<()> {
+        let batches = ctx.sql(&self.sql).await?.collect().await?;
+        self.store(batches, now)?;
+        self.full_refreshes += 1;
+        Ok(())
+    }
+
+    /// Fold batches appended to the source table into the stored result
+    pub async fn apply_delta(&mut self, ctx: &SessionContext, delta: &[RecordBatch], now: DateTime<Utc>) -> BlazeResult<()> {
+        let RefreshStrategy::Incremental(columns) = &self.strategy else {
+            return self.full_refresh(ctx, now).await;
+        };
+        if delta.iter().all(|b| b.num_rows() == 0) {
+            self.stale = false;
+            return Ok(());
+        }
+
+        // Aggregate only the new rows by swapping the scan's source
+        let plan = ctx.sql(&self.sql).await?.into_unoptimized_plan();
+        let delta_schema = delta[0].schema();
+        let delta_table = provider_as_source(Arc::new(MemTable::try_new(delta_schema, vec![delta.to_vec()])?));
+        let delta_plan = plan
+            .transform_up(|node| match node {
+                LogicalPlan::TableScan(mut scan) => {
+                    scan.source = delta_table.clone();
+                    Ok(Transformed::yes(LogicalPlan::TableScan(scan)))
+                }
+                other => Ok(Transformed::no(other)),
+            })?
+            .data;
+        let partials = ctx.execute_logical_plan(delta_plan).await?.collect().await?;
+
+        // Merge the partial aggregates with the stored result
+        let combined: Vec<RecordBatch> = self.batches.iter().chain(partials.iter()).cloned().collect();
+        let column = |name: &str| col(Column::from_name(name));
+        let keys = columns
+            .iter()
+            .filter(|(_, op)| *op == MergeOp::Key)
+            .map(|(name, _)| column(name))
+            .collect();
+        let aggregates = columns
+            .iter()
+            .filter_map(|(name, op)| {
+                let merged = match op {
+                    MergeOp::Key => return None,
+                    MergeOp::Sum => sum(column(name)),
+                    MergeOp::Min => min(column(name)),
+                    MergeOp::Max => max(column(name)),
+                };
+                Some(merged.alias(name))
+            })
+            .collect();
+        let ordered = columns.iter().map(|(name, _)| column(name)).collect::<Vec<_>>();
+
+        let merged = ctx
+            .read_batches(combined)?
+            .aggregate(keys, aggregates)?
+            .select(ordered)?
+            .collect()
+            .await?;
+
+        self.store(merged, now)?;
+        self.incremental_refreshes += 1;
+        Ok(())
+    }
+
+    /// Describe the view
+    pub fn info(&self) -> MaterializedViewInfo {
+        let unsupported_reason = match &self.strategy {
+            RefreshStrategy::Incremental(_) => None,
+            RefreshStrategy::Full(reason) => Some(reason.clone()),
+        };
+
+        MaterializedViewInfo {
+            name: self.name.clone(),
+            sql: self.sql.clone(),
+            source_tables: self.source_tables.clone(),
+            incremental: unsupported_reason.is_none(),
+            unsupported_reason,
+            stale: self.stale,
+            last_refreshed_at: self.last_refreshed_at,
+            full_refreshes: self.full_refreshes,
+            incremental_refreshes: self.incremental_refreshes,
+            rows: self.batches.iter().map(|b| b.num_rows()).sum(),
+        }
+    }
+
+    /// Replace the stored result, coercing columns back to the view schema
+    fn store(&mut self, batches: Vec<RecordBatch>, now: DateTime<Utc>) -> BlazeResult<()> {
+        self.batches = batches
+            .into_iter()
+            .map(|batch| {
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .zip(self.schema.fields())
+                    .map(|(array, field)| cast(array, field.data_type()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+            })
+            .collect::<BlazeResult<_>>()?;
+        self.stale = false;
+        self.last_refreshed_at = now;
+        Ok(())
+    }
+}
+
+/// Names of all tables scanned by a plan, including inside subqueries
+fn source_tables(plan: &LogicalPlan) -> BlazeResult<Vec<String>> {
+    let mut tables: Vec<String> = Vec::new();
+    plan.apply_with_subqueries(|node| {
+        if let LogicalPlan::TableScan(scan) = node {
+            let name = scan.table_name.table().to_string();
+            if !tables.contains(&name) {
+                tables.push(name);
+            }
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })?;
+    Ok(tables)
+}
+
+/// Work out how each output column merges, or why the plan is unsupported
+fn incremental_columns(plan: &LogicalPlan, sources: &[String]) -> Result<Vec<(String, MergeOp)>, String> {
+    if sources.len() != 1 {
+        return Err(format!("reads {} tables; only single-table views are incremental", sources.len()));
+    }
+
+    let LogicalPlan::Projection(projection) = plan else {
+        return Err(format!("top-level {} is not supported", plan.display()));
+    };
+    let LogicalPlan::Aggregate(aggregate) = projection.input.as_ref() else {
+        return Err("query is not a GROUP BY aggregation".to_string());
+    };
+
+    let mut unsupported = None;
+    aggregate.input.apply(|node| {
+        match node {
+            LogicalPlan::TableScan(_)
+            | LogicalPlan::Projection(_)
+            | LogicalPlan::Filter(_)
+            | LogicalPlan::SubqueryAlias(_) => Ok(TreeNodeRecursion::Continue),
+            other => {
+                unsupported = Some(other.display().to_string());
+                Ok(TreeNodeRecursion::Stop)
+            }
+        }
+    }).map_err(|e| e.to_string())?;
+    if let Some(node) = unsupported {
+        return Err(format!("{} below the aggregation is not supported", node));
+    }
+
+    projection
+        .expr
+        .iter()
+        .zip(projection.schema.fields())
+        .map(|(expr, field)| {
+            let Expr::Column(column) = expr.clone().unalias() else {
+                return Err(format!("output column '{}' is computed from aggregates", field.name()));
+            };
+            let index = aggregate.schema.index_of_column(&column).map_err(|e| e.to_string())?;
+            let op = match index.checked_sub(aggregate.group_expr.len()) {
+                None => MergeOp::Key,
+                Some(i) => merge_op(&aggregate.aggr_expr[i])?,
+            };
+            Ok((field.name().clone(), op))
+        })
+        .collect()
+}
+
+fn merge_op(expr: &Expr) -> Result<MergeOp, String> {
+    let Expr::AggregateFunction(function) = expr.clone().unalias() else {
+        return Err(format!("unsupported aggregate expression {}", expr));
+    };
+    if function.distinct || function.filter.is_some() {
+        return Err(format!("{} uses DISTINCT or FILTER", expr));
+    }
+    match function.func.name().to_lowercase().as_str() {
+        "count" | "sum" => Ok(MergeOp::Sum),
+        "min" => Ok(MergeOp::Min),
+        "max" => Ok(MergeOp::Max),
+        other => Err(format!("aggregate {} cannot be merged incrementally", other)),
+    }
+}
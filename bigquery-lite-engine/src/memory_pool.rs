@@ -0,0 +1,106 @@
+//! Memory pool whose limit can be changed while queries are running
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::memory_pool::{MemoryPool, MemoryReservation};
+
+/// Greedy first-come first-served pool, like DataFusion's `GreedyMemoryPool`,
+/// with an adjustable limit. Lowering the limit below what is already
+/// reserved fails new reservations until enough memory is released.
+///
+/// A pool may draw from a parent pool shared with other engines, in which
+/// case a reservation must fit within both limits.
+#[derive(Debug)]
+pub(crate) struct ResizableMemoryPool {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+    parent: Option<Arc<ResizableMemoryPool>>,
+}
+
+impl ResizableMemoryPool {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+            parent: None,
+        }
+    }
+
+    /// A pool whose reservations also count against `parent`
+    pub(crate) fn with_parent(limit: usize, parent: Arc<ResizableMemoryPool>) -> Self {
+        Self {
+            parent: Some(parent),
+            ..Self::new(limit)
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    fn add(&self, additional: usize) {
+        self.used.fetch_add(additional, Ordering::Relaxed);
+        if let Some(parent) = &self.parent {
+            parent.add(additional);
+        }
+    }
+
+    fn remove(&self, released: usize) {
+        self.used.fetch_sub(released, Ordering::Relaxed);
+        if let Some(parent) = &self.parent {
+            parent.remove(released);
+        }
+    }
+
+    /// Reserve `additional` bytes in this pool and its parents, or return the
+    /// bytes that were available in the pool that ran out
+    fn try_add(&self, additional: usize) -> std::result::Result<(), usize> {
+        let limit = self.limit();
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                let new_used = used + additional;
+                (new_used <= limit).then_some(new_used)
+            })
+            .map_err(|used| limit.saturating_sub(used))?;
+
+        if let Some(parent) = &self.parent {
+            if let Err(available) = parent.try_add(additional) {
+                self.used.fetch_sub(additional, Ordering::Relaxed);
+                return Err(available);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MemoryPool for ResizableMemoryPool {
+    fn grow(&self, _reservation: &MemoryReservation, additional: usize) {
+        self.add(additional);
+    }
+
+    fn shrink(&self, _reservation: &MemoryReservation, shrink: usize) {
+        self.remove(shrink);
+    }
+
+    fn try_grow(&self, reservation: &MemoryReservation, additional: usize) -> Result<()> {
+        self.try_add(additional).map_err(|available| {
+            DataFusionError::ResourcesExhausted(format!(
+                "Failed to allocate additional {} bytes for {} with {} bytes already allocated for this reservation - {} bytes remain available for the total pool",
+                additional,
+                reservation.consumer().name(),
+                reservation.size(),
+                available
+            ))
+        })
+    }
+
+    fn reserved(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+}
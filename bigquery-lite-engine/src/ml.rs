@@ -0,0 +1,476 @@
+//! Minimal BigQuery ML surface: linear and logistic regression
+//!
+//! ```sql
+//! CREATE MODEL m OPTIONS(model_type='linear_reg', input_label_cols=['y']) AS
+//!   SELECT x1, x2, y FROM training;
+//! SELECT * FROM ML.PREDICT(MODEL m, TABLE new_rows);
+//! ```
+//!
+//! Training runs over the collected Arrow batches in Rust. Every non-label
+//! column is a feature and must be numeric or boolean; rows with NULLs are
+//! skipped. Each trained model is registered as a scalar UDF and
+//! `ML.PREDICT` is rewritten into a projection that calls it, adding
+//! `predicted_<label>` (plus `predicted_<label>_prob` for logistic models).
+
+use std::sync::{Arc, LazyLock};
+
+use datafusion::arrow::array::{Array, ArrayRef, Float64Array};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::logical_expr::{create_udf, ColumnarValue, ScalarUDF, Volatility};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::utils::{matching_paren, parse_options};
+
+/// Supported model families
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelType {
+    LinearReg,
+    LogisticReg,
+}
+
+/// Parsed `CREATE MODEL` statement
+#[derive(Debug, Clone)]
+pub struct CreateModel {
+    pub name: String,
+    pub or_replace: bool,
+    pub options: TrainingOptions,
+    /// Query producing the training data
+    pub query: String,
+}
+
+/// Training hyper-parameters taken from `OPTIONS(...)`
+#[derive(Debug, Clone)]
+pub struct TrainingOptions {
+    pub model_type: ModelType,
+    pub label: String,
+    pub l2_reg: f64,
+    pub max_iterations: usize,
+    pub learn_rate: f64,
+}
+
+/// A trained model; weights are in the original (unscaled) feature space
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Model {
+    pub name: String,
+    pub model_type: ModelType,
+    pub label: String,
+    pub features: Vec<String>,
+    pub weights: Vec<f64>,
+    pub intercept: f64,
+    pub training_rows: usize,
+    pub iterations: usize,
+    /// Mean squared error (linear) or mean log loss (logistic) on the training data
+    pub training_loss: f64,
+}
+
+/// Parse `CREATE [OR REPLACE] MODEL name OPTIONS(...) AS <query>`, returning
+/// `None` if the statement is not a CREATE MODEL
+pub fn parse_create_model(sql: &str) -> BlazeResult<Option<CreateModel>> {
+    static HEADER: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*CREATE\s+(OR\s+REPLACE\s+)?MODEL\s+([A-Za-z_][\w.]*)\s*OPTIONS\s*\(",
+        )
+        .unwrap()
+    });
+    let Some(captures) = HEADER.captures(sql) else {
+        return Ok(None);
+    };
+
+    let name = captures[2].to_string();
+    let or_replace = captures.get(1).is_some();
+    let open = captures.get(0).unwrap().end() - 1;
+    let close = matching_paren(sql, open).ok_or_else(|| invalid_input!("Unbalanced OPTIONS(...) in CREATE MODEL"))?;
+
+    let rest = sql[close + 1..].trim_start();
+    let query = match rest.get(..2) {
+        Some(kw) if kw.eq_ignore_ascii_case("AS") => rest[2..].trim().trim_end_matches(';').to_string(),
+        _ => return Err(invalid_input!("CREATE MODEL {} requires AS <query>", name)),
+    };
+
+    let options = parse_options(&sql[open + 1..close]).map_err(BlazeError::InvalidInput)?;
+    let model_type = match options.get("model_type").and_then(|v| v.as_str()).map(str::to_lowercase).as_deref() {
+        Some("linear_reg") => ModelType::LinearReg,
+        Some("logistic_reg") => ModelType::LogisticReg,
+        Some(other) => return Err(invalid_input!("Unsupported model_type '{}'", other)),
+        None => return Err(invalid_input!("CREATE MODEL {} requires OPTIONS(model_type=...)", name)),
+    };
+    let label = match options.get("input_label_cols").and_then(|v| v.as_string_list()) {
+        Some(labels) if labels.len() == 1 => labels[0].clone(),
+        Some(_) => return Err(invalid_input!("input_label_cols must name exactly one column")),
+        None => "label".to_string(),
+    };
+    let number = |key: &str, default: f64| options.get(key).and_then(|v| v.as_f64()).unwrap_or(default);
+
+    Ok(Some(CreateModel {
+        name,
+        or_replace,
+        options: TrainingOptions {
+            model_type,
+            label,
+            l2_reg: number("l2_reg", 0.0),
+            max_iterations: number("max_iterations", 50.0) as usize,
+            learn_rate: number("learn_rate", 0.5),
+        },
+        query,
+    }))
+}
+
+/// Parse `DROP MODEL [IF EXISTS] name`, returning the name and whether
+/// IF EXISTS was given
+pub fn parse_drop_model(sql: &str) -> Option<(String, bool)> {
+    static PATTERN: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)^\s*DROP\s+MODEL\s+(IF\s+EXISTS\s+)?([A-Za-z_][\w.]*)\s*;?\s*$").unwrap());
+    let captures = PATTERN.captures(sql)?;
+    Some((captures[2].to_string(), captures.get(1).is_some()))
+}
+
+/// Train a model on the result batches of the training query
+pub fn train(name: &str, options: &TrainingOptions, batches: &[RecordBatch]) -> BlazeResult<Model> {
+    let schema = batches
+        .first()
+        .map(|b| b.schema())
+        .ok_or_else(|| invalid_input!("Training query for model '{}' returned no rows", name))?;
+    let label_index = schema
+        .index_of(&options.label)
+        .map_err(|_| invalid_input!("Label column '{}' not found in training data", options.label))?;
+    let features: Vec<String> = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != label_index)
+        .map(|(_, f)| f.name().clone())
+        .collect();
+    if features.is_empty() {
+        return Err(invalid_input!("Model '{}' needs at least one feature column", name));
+    }
+
+    // Gather complete rows as dense f64 vectors
+    let mut rows: Vec<Vec<f64>> = Vec::new();
+    let mut labels: Vec<f64> = Vec::new();
+    for batch in batches {
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(schema.fields())
+            .map(|(column, field)| to_f64(column, field.name()))
+            .collect::<BlazeResult<Vec<_>>>()?;
+        for row in 0..batch.num_rows() {
+            if columns.iter().any(|c| c.is_null(row)) {
+                continue;
+            }
+            let values = columns.iter().map(|c| c.value(row));
+            let (mut x, mut y) = (Vec::with_capacity(features.len()), 0.0);
+            for (i, value) in values.enumerate() {
+                if i == label_index { y = value } else { x.push(value) }
+            }
+            rows.push(x);
+            labels.push(y);
+        }
+    }
+    if rows.is_empty() {
+        return Err(invalid_input!("Training data for model '{}' has no complete rows", name));
+    }
+
+    let (weights, intercept, iterations, training_loss) = match options.model_type {
+        ModelType::LinearReg => fit_linear(&rows, &labels, options.l2_reg)?,
+        ModelType::LogisticReg => {
+            if labels.iter().any(|y| *y != 0.0 && *y != 1.0) {
+                return Err(invalid_input!("logistic_reg labels must be 0/1 or boolean"));
+            }
+            fit_logistic(&rows, &labels, options)
+        }
+    };
+
+    Ok(Model {
+        name: name.to_string(),
+        model_type: options.model_type,
+        label: options.label.clone(),
+        features,
+        weights,
+        intercept,
+        training_rows: rows.len(),
+        iterations,
+        training_loss,
+    })
+}
+
+impl Model {
+    /// Name of the scalar UDF computing this model's raw prediction
+    pub fn udf_name(&self) -> String {
+        format!("__ml_predict_{}", self.name.to_lowercase())
+    }
+
+    /// Scalar UDF returning the prediction (probability for logistic models)
+    pub fn to_udf(&self) -> ScalarUDF {
+        let weights = self.weights.clone();
+        let intercept = self.intercept;
+        let logistic = self.model_type == ModelType::LogisticReg;
+
+        create_udf(
+            &self.udf_name(),
+            vec![DataType::Float64; self.features.len()],
+            DataType::Float64,
+            Volatility::Immutable,
+            Arc::new(move |args: &[ColumnarValue]| {
+                let arrays = ColumnarValue::values_to_arrays(args)?;
+                let columns = arrays
+                    .iter()
+                    .map(|a| a.as_any().downcast_ref::<Float64Array>().cloned().unwrap_or_else(|| Float64Array::from(vec![None; a.len()])))
+                    .collect::<Vec<_>>();
+                let rows = columns.first().map_or(0, |c| c.len());
+
+                let predictions: Float64Array = (0..rows)
+                    .map(|row| {
+                        if columns.iter().any(|c| c.is_null(row)) {
+                            return None;
+                        }
+                        let z = intercept + columns.iter().zip(&weights).map(|(c, w)| c.value(row) * w).sum::<f64>();
+                        Some(if logistic { sigmoid(z) } else { z })
+                    })
+                    .collect();
+
+                Ok(ColumnarValue::Array(Arc::new(predictions)))
+            }),
+        )
+    }
+
+    /// SELECT list appending this model's prediction columns to `*`
+    fn prediction_projection(&self) -> String {
+        let args = self
+            .features
+            .iter()
+            .map(|f| format!("\"{}\"", f.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let call = format!("{}({})", self.udf_name(), args);
+        let predicted = format!("predicted_{}", self.label);
+
+        match self.model_type {
+            ModelType::LinearReg => format!("*, {} AS \"{}\"", call, predicted),
+            ModelType::LogisticReg => format!(
+                "*, CAST({call} >= 0.5 AS BIGINT) AS \"{predicted}\", {call} AS \"{predicted}_prob\""
+            ),
+        }
+    }
+}
+
+/// Rewrite every `ML.PREDICT(MODEL m, TABLE t)` / `ML.PREDICT(MODEL m, (query))`
+/// into a derived table computing the model's predictions
+pub fn rewrite_predict(sql: &str, lookup: impl Fn(&str) -> Option<Model>) -> BlazeResult<String> {
+    static CALL: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)\bML\.PREDICT\s*\(\s*MODEL\s+([A-Za-z_][\w.]*)\s*,\s*").unwrap());
+    let mut output = String::with_capacity(sql.len());
+    let mut cursor = 0;
+
+    while let Some(captures) = CALL.captures_at(sql, cursor) {
+        let whole = captures.get(0).unwrap();
+        let open = sql[whole.start()..].find('(').map(|i| whole.start() + i).unwrap();
+        let close = matching_paren(sql, open).ok_or_else(|| invalid_input!("Unbalanced ML.PREDICT(...)"))?;
+
+        let model_name = &captures[1];
+        let model = lookup(model_name).ok_or_else(|| invalid_input!("Model '{}' not found", model_name))?;
+
+        let input = sql[whole.end()..close].trim();
+        let source = match input.get(..5) {
+            Some(kw) if kw.eq_ignore_ascii_case("TABLE") => format!("SELECT * FROM {}", input[5..].trim()),
+            _ if input.starts_with('(') && input.ends_with(')') => input[1..input.len() - 1].to_string(),
+            _ => return Err(invalid_input!("ML.PREDICT input must be TABLE <name> or a (subquery)")),
+        };
+
+        output.push_str(&sql[cursor..whole.start()]);
+        output.push_str(&format!(
+            "(SELECT {} FROM ({}) AS __ml_input)",
+            model.prediction_projection(),
+            source
+        ));
+        cursor = close + 1;
+    }
+    output.push_str(&sql[cursor..]);
+
+    Ok(output)
+}
+
+/// Cheap pre-check so only ML queries pay for the rewrite
+pub fn contains_predict(sql: &str) -> bool {
+    sql.to_ascii_uppercase().contains("ML.PREDICT")
+}
+
+fn to_f64(column: &ArrayRef, name: &str) -> BlazeResult<Float64Array> {
+    match column.data_type() {
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+            Err(invalid_input!("Column '{}' is a string; only numeric and boolean features are supported", name))
+        }
+        _ => {
+            let cast_column = cast(column, &DataType::Float64)
+                .map_err(|_| invalid_input!("Column '{}' of type {} cannot be used as a feature", name, column.data_type()))?;
+            Ok(cast_column.as_any().downcast_ref::<Float64Array>().unwrap().clone())
+        }
+    }
+}
+
+fn sigmoid(z: f64) -> f64 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+/// Ridge regression via the normal equations: (XᵀX + λI) w = Xᵀy, with an
+/// unpenalized intercept column
+fn fit_linear(rows: &[Vec<f64>], labels: &[f64], l2_reg: f64) -> BlazeResult<(Vec<f64>, f64, usize, f64)> {
+    let n = rows[0].len() + 1;
+    let mut xtx = vec![vec![0.0; n]; n];
+    let mut xty = vec![0.0; n];
+
+    for (x, y) in rows.iter().zip(labels) {
+        let augmented: Vec<f64> = std::iter::once(1.0).chain(x.iter().copied()).collect();
+        for i in 0..n {
+            xty[i] += augmented[i] * y;
+            for j in 0..n {
+                xtx[i][j] += augmented[i] * augmented[j];
+            }
+        }
+    }
+    for (i, row) in xtx.iter_mut().enumerate().skip(1) {
+        row[i] += l2_reg;
+    }
+
+    let solution = solve(xtx, xty).ok_or_else(|| {
+        invalid_input!("Training data is singular (collinear or constant features); try setting l2_reg")
+    })?;
+    let (intercept, weights) = (solution[0], solution[1..].to_vec());
+
+    let mse = rows
+        .iter()
+        .zip(labels)
+        .map(|(x, y)| {
+            let prediction = intercept + x.iter().zip(&weights).map(|(a, w)| a * w).sum::<f64>();
+            (prediction - y).powi(2)
+        })
+        .sum::<f64>()
+        / rows.len() as f64;
+
+    Ok((weights, intercept, 1, mse))
+}
+
+/// Batch gradient descent on standardized features, converted back to the
+/// original scale at the end
+fn fit_logistic(rows: &[Vec<f64>], labels: &[f64], options: &TrainingOptions) -> (Vec<f64>, f64, usize, f64) {
+    let (n_rows, n_features) = (rows.len() as f64, rows[0].len());
+    let means: Vec<f64> = (0..n_features).map(|j| rows.iter().map(|r| r[j]).sum::<f64>() / n_rows).collect();
+    let stds: Vec<f64> = (0..n_features)
+        .map(|j| {
+            let variance = rows.iter().map(|r| (r[j] - means[j]).powi(2)).sum::<f64>() / n_rows;
+            if variance > 0.0 { variance.sqrt() } else { 1.0 }
+        })
+        .collect();
+    let scaled: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|r| r.iter().enumerate().map(|(j, v)| (v - means[j]) / stds[j]).collect())
+        .collect();
+
+    let mut weights = vec![0.0; n_features];
+    let mut bias = 0.0;
+    let mut previous_loss = f64::INFINITY;
+    let mut iterations = 0;
+    let mut loss = f64::INFINITY;
+
+    while iterations < options.max_iterations.max(1) {
+        iterations += 1;
+        let mut gradient = vec![0.0; n_features];
+        let mut bias_gradient = 0.0;
+        loss = 0.0;
+
+        for (x, y) in scaled.iter().zip(labels) {
+            let p = sigmoid(bias + x.iter().zip(&weights).map(|(a, w)| a * w).sum::<f64>());
+            let error = p - y;
+            bias_gradient += error;
+            for (g, a) in gradient.iter_mut().zip(x) {
+                *g += error * a;
+            }
+            let p = p.clamp(1e-12, 1.0 - 1e-12);
+            loss -= y * p.ln() + (1.0 - y) * (1.0 - p).ln();
+        }
+        loss /= n_rows;
+
+        bias -= options.learn_rate * bias_gradient / n_rows;
+        for (w, g) in weights.iter_mut().zip(&gradient) {
+            *w -= options.learn_rate * (g / n_rows + options.l2_reg * *w);
+        }
+
+        if (previous_loss - loss).abs() < 1e-7 {
+            break;
+        }
+        previous_loss = loss;
+    }
+
+    let original_weights: Vec<f64> = weights.iter().zip(&stds).map(|(w, s)| w / s).collect();
+    let intercept = bias - original_weights.iter().zip(&means).map(|(w, m)| w * m).sum::<f64>();
+
+    (original_weights, intercept, iterations, loss)
+}
+
+/// Gaussian elimination with partial pivoting
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(col) {
+                *value -= factor * pivot_value;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_create_model() {
+        let statement = parse_create_model(
+            "CREATE OR REPLACE MODEL price OPTIONS(model_type='linear_reg', input_label_cols=['y']) AS SELECT x, y FROM t;",
+        ).unwrap().unwrap();
+        assert_eq!(statement.name, "price");
+        assert!(statement.or_replace);
+        assert_eq!(statement.options.model_type, ModelType::LinearReg);
+        assert_eq!(statement.options.label, "y");
+        assert_eq!(statement.query, "SELECT x, y FROM t");
+
+        assert!(parse_create_model("SELECT 1").unwrap().is_none());
+        assert!(parse_create_model("CREATE MODEL m OPTIONS(model_type='kmeans') AS SELECT 1").is_err());
+    }
+
+    #[test]
+    fn test_fit_linear_recovers_coefficients() {
+        let rows: Vec<Vec<f64>> = (0..20).map(|i| vec![i as f64, (i % 3) as f64]).collect();
+        let labels: Vec<f64> = rows.iter().map(|r| 3.0 + 2.0 * r[0] - r[1]).collect();
+        let (weights, intercept, _, mse) = fit_linear(&rows, &labels, 0.0).unwrap();
+
+        assert!((intercept - 3.0).abs() < 1e-6);
+        assert!((weights[0] - 2.0).abs() < 1e-6);
+        assert!((weights[1] + 1.0).abs() < 1e-6);
+        assert!(mse < 1e-9);
+    }
+}
@@ -0,0 +1,423 @@
+//! On-disk cache of remote object store reads
+//!
+//! With `object_cache_dir` set in `EngineConfig`, stores registered with
+//! `register_gcs`, `register_azure` or `register_http` read through a cache
+//! on local disk. Objects are fetched in blocks of 4MB, each kept as a file
+//! of its own, so repeated queries over the same remote Parquet files read
+//! footers and row groups from disk instead of downloading them again, while
+//! the parts of a file no query read are never downloaded.
+//!
+//! Blocks are keyed by the object's location, ETag, version, size and
+//! modification time. Every read asks the store for the object's metadata
+//! first, so an object replaced remotely is fetched again instead of being
+//! served stale. When the blocks outgrow `object_cache_max_bytes` the least
+//! recently read ones are deleted. Blocks left by earlier runs are reused;
+//! writes go to the store unchanged.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::{
+    Attributes, GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::checksums;
+use crate::config_error;
+use crate::error::{BlazeError, BlazeResult};
+
+/// Size objects are fetched and cached in
+const BLOCK_BYTES: usize = 4 * 1024 * 1024;
+
+/// Extension of block files
+const BLOCK_EXTENSION: &str = "block";
+
+/// Usage of the object cache, reported in `EngineStats`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectCacheStats {
+    /// Size the cache is kept under
+    pub max_bytes: u64,
+    /// Bytes of the blocks on disk
+    pub cached_bytes: u64,
+    pub cached_blocks: usize,
+    /// Blocks read from disk
+    pub hits: u64,
+    /// Blocks downloaded from their store
+    pub misses: u64,
+    pub bytes_from_cache: u64,
+    pub bytes_downloaded: u64,
+    /// Blocks deleted to stay under `max_bytes`
+    pub evicted_blocks: u64,
+    pub evicted_bytes: u64,
+}
+
+/// Blocks kept in a directory, shared by all cached stores
+#[derive(Debug)]
+pub(crate) struct ObjectCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Blocks on disk by file name
+    blocks: HashMap<String, CachedBlock>,
+    /// Bumped on every read, ordering blocks by their last one
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    bytes_from_cache: u64,
+    bytes_downloaded: u64,
+    evicted_blocks: u64,
+    evicted_bytes: u64,
+}
+
+#[derive(Debug)]
+struct CachedBlock {
+    bytes: u64,
+    last_read: u64,
+}
+
+impl CacheState {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn cached_bytes(&self) -> u64 {
+        self.blocks.values().map(|block| block.bytes).sum()
+    }
+
+    /// Forget the least recently read blocks until the rest fit in
+    /// `max_bytes`, returning the names of the files to delete
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut cached = self.cached_bytes();
+        let mut evicted = Vec::new();
+        while cached > max_bytes {
+            let Some(name) = self.blocks.iter().min_by_key(|(_, block)| block.last_read).map(|(name, _)| name.clone()) else {
+                break;
+            };
+            let block = self.blocks.remove(&name).expect("block just found");
+            cached -= block.bytes;
+            self.evicted_blocks += 1;
+            self.evicted_bytes += block.bytes;
+            evicted.push(name);
+        }
+        evicted
+    }
+}
+
+impl ObjectCache {
+    /// Use `dir` as the cache, picking up the blocks already in it, oldest
+    /// first in line for eviction
+    pub(crate) fn open(dir: &FsPath, max_bytes: u64) -> BlazeResult<Self> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| config_error!("Cannot create object cache directory {}: {}", dir.display(), e))?;
+        let mut found = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != BLOCK_EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                found.push((metadata.modified().ok(), name.to_string(), metadata.len()));
+            }
+        }
+        found.sort();
+
+        let mut state = CacheState::default();
+        for (_, name, bytes) in found {
+            let last_read = state.tick();
+            state.blocks.insert(name, CachedBlock { bytes, last_read });
+        }
+        let evicted = state.evict(max_bytes);
+        for name in evicted {
+            let _ = std::fs::remove_file(dir.join(name));
+        }
+        Ok(Self { dir: dir.to_path_buf(), max_bytes, state: Mutex::new(state) })
+    }
+
+    pub(crate) fn stats(&self) -> ObjectCacheStats {
+        let state = self.state.lock();
+        ObjectCacheStats {
+            max_bytes: self.max_bytes,
+            cached_bytes: state.cached_bytes(),
+            cached_blocks: state.blocks.len(),
+            hits: state.hits,
+            misses: state.misses,
+            bytes_from_cache: state.bytes_from_cache,
+            bytes_downloaded: state.bytes_downloaded,
+            evicted_blocks: state.evicted_blocks,
+            evicted_bytes: state.evicted_bytes,
+        }
+    }
+
+    /// A cached block of `expected` bytes, or `None` when it is not on disk
+    async fn get(&self, name: &str, expected: usize) -> Option<Bytes> {
+        if !self.state.lock().blocks.contains_key(name) {
+            return None;
+        }
+        let data = tokio::fs::read(self.dir.join(name)).await.ok().filter(|data| data.len() == expected);
+        let mut state = self.state.lock();
+        match data {
+            Some(data) => {
+                let last_read = state.tick();
+                if let Some(block) = state.blocks.get_mut(name) {
+                    block.last_read = last_read;
+                }
+                state.hits += 1;
+                state.bytes_from_cache += data.len() as u64;
+                Some(Bytes::from(data))
+            }
+            // Deleted or damaged outside the engine; fetched again
+            None => {
+                state.blocks.remove(name);
+                None
+            }
+        }
+    }
+
+    /// Keep a downloaded block, evicting others to make room
+    async fn put(&self, name: &str, data: &Bytes) {
+        let temporary = {
+            let mut state = self.state.lock();
+            state.misses += 1;
+            state.bytes_downloaded += data.len() as u64;
+            self.dir.join(format!("{}.{}.tmp", name, state.tick()))
+        };
+        // Written aside and renamed, so a block file is never seen half written
+        let written = match tokio::fs::write(&temporary, data).await {
+            Ok(()) => tokio::fs::rename(&temporary, self.dir.join(name)).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!("Cannot write object cache block {}: {}", name, e);
+            let _ = tokio::fs::remove_file(&temporary).await;
+            return;
+        }
+
+        let evicted = {
+            let mut state = self.state.lock();
+            let last_read = state.tick();
+            state.blocks.insert(name.to_string(), CachedBlock { bytes: data.len() as u64, last_read });
+            state.evict(self.max_bytes)
+        };
+        for name in evicted {
+            let _ = tokio::fs::remove_file(self.dir.join(name)).await;
+        }
+    }
+}
+
+/// File name prefix of an object's blocks: a hash of everything that
+/// changes when the object does
+fn object_key(store: &str, meta: &ObjectMeta) -> String {
+    let identity = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        store,
+        meta.location,
+        meta.e_tag.as_deref().unwrap_or(""),
+        meta.version.as_deref().unwrap_or(""),
+        meta.size,
+        meta.last_modified.timestamp_nanos_opt().unwrap_or_default()
+    );
+    format!("{:032x}", checksums::fnv1a(checksums::OFFSET_BASIS, identity.as_bytes()))
+}
+
+/// The bytes `range` asks for of an object of `size` bytes
+fn resolve_range(range: &GetRange, size: usize) -> Result<Range<usize>, String> {
+    let resolved = match range {
+        GetRange::Bounded(range) => range.start..range.end.min(size),
+        GetRange::Offset(offset) => *offset..size,
+        GetRange::Suffix(suffix) => size.saturating_sub(*suffix)..size,
+    };
+    if resolved.start > resolved.end || (resolved.start >= size && size > 0) {
+        return Err(format!("range {:?} is outside an object of {} bytes", range, size));
+    }
+    Ok(resolved)
+}
+
+/// A store whose reads go through an `ObjectCache`
+#[derive(Debug)]
+pub(crate) struct CachedStore {
+    inner: Arc<dyn ObjectStore>,
+    cache: Arc<ObjectCache>,
+    /// URL of the store, e.g. `gs://lake`, keeping stores' blocks apart
+    url: String,
+}
+
+impl CachedStore {
+    pub(crate) fn new(inner: Arc<dyn ObjectStore>, cache: Arc<ObjectCache>, url: &str) -> Self {
+        Self { inner, cache, url: url.to_string() }
+    }
+
+    /// Read `range` of an object block by block, from disk where cached
+    async fn read_range(&self, meta: &ObjectMeta, range: &Range<usize>) -> object_store::Result<Bytes> {
+        if range.is_empty() {
+            return Ok(Bytes::new());
+        }
+        let key = object_key(&self.url, meta);
+        let mut data = BytesMut::with_capacity(range.len());
+        for index in range.start / BLOCK_BYTES..=(range.end - 1) / BLOCK_BYTES {
+            let block_range = index * BLOCK_BYTES..((index + 1) * BLOCK_BYTES).min(meta.size);
+            let name = format!("{}-{}.{}", key, index, BLOCK_EXTENSION);
+            let block = match self.cache.get(&name, block_range.len()).await {
+                Some(block) => block,
+                None => {
+                    let block = self.inner.get_range(&meta.location, block_range.clone()).await?;
+                    self.cache.put(&name, &block).await;
+                    block
+                }
+            };
+            let start = range.start.max(block_range.start) - block_range.start;
+            let end = range.end.min(block_range.end) - block_range.start;
+            data.extend_from_slice(&block[start..end]);
+        }
+        Ok(data.freeze())
+    }
+}
+
+impl fmt::Display for CachedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cached({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for CachedStore {
+    async fn put_opts(&self, location: &Path, payload: PutPayload, opts: PutOptions) -> object_store::Result<PutResult> {
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> object_store::Result<GetResult> {
+        // Conditional, versioned and metadata-only requests go to the store
+        if options.head
+            || options.version.is_some()
+            || options.if_match.is_some()
+            || options.if_none_match.is_some()
+            || options.if_modified_since.is_some()
+            || options.if_unmodified_since.is_some()
+        {
+            return self.inner.get_opts(location, options).await;
+        }
+
+        let meta = self.inner.head(location).await?;
+        let range = match &options.range {
+            Some(range) => resolve_range(range, meta.size)
+                .map_err(|message| object_store::Error::Generic { store: "ObjectCache", source: message.into() })?,
+            None => 0..meta.size,
+        };
+        let data = self.read_range(&meta, &range).await?;
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(futures::stream::once(async move { Ok(data) }).boxed()),
+            meta,
+            range,
+            attributes: Attributes::default(),
+        })
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn cached_store(dir: &FsPath, max_bytes: u64) -> (Arc<InMemory>, Arc<ObjectCache>, CachedStore) {
+        let inner = Arc::new(InMemory::new());
+        let cache = Arc::new(ObjectCache::open(dir, max_bytes).unwrap());
+        let store = CachedStore::new(inner.clone(), cache.clone(), "memory://test");
+        (inner, cache, store)
+    }
+
+    #[tokio::test]
+    async fn test_reads_are_cached_by_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let (inner, cache, store) = cached_store(dir.path(), u64::MAX);
+        let location = Path::from("data/file.bin");
+        let data: Vec<u8> = (0..BLOCK_BYTES + 100).map(|i| (i % 251) as u8).collect();
+        inner.put(&location, data.clone().into()).await.unwrap();
+
+        // A range across both blocks downloads each once
+        let range = BLOCK_BYTES - 10..BLOCK_BYTES + 10;
+        assert_eq!(store.get_range(&location, range.clone()).await.unwrap(), data[range.clone()]);
+        assert_eq!(store.get_range(&location, range.clone()).await.unwrap(), data[range]);
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.hits, stats.cached_blocks), (2, 2, 2));
+        assert_eq!(stats.bytes_downloaded, data.len() as u64);
+        assert_eq!(store.get(&location).await.unwrap().bytes().await.unwrap(), data);
+        let suffix = store.get_opts(&location, GetOptions { range: Some(GetRange::Suffix(5)), ..Default::default() });
+        assert_eq!(suffix.await.unwrap().bytes().await.unwrap(), data[data.len() - 5..]);
+
+        // A replaced object has a new ETag, so its old blocks are not served
+        inner.put(&location, Bytes::from_static(b"replaced").into()).await.unwrap();
+        assert_eq!(store.get(&location).await.unwrap().bytes().await.unwrap(), Bytes::from_static(b"replaced"));
+        assert_eq!(cache.stats().misses, 3);
+
+        // Blocks on disk are picked up by a new cache
+        let reopened = ObjectCache::open(dir.path(), u64::MAX).unwrap();
+        assert_eq!(reopened.stats().cached_blocks, 3);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_read_blocks_are_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let (inner, cache, store) = cached_store(dir.path(), 25);
+        for name in ["a", "b", "c"] {
+            inner.put(&Path::from(name), Bytes::from(vec![0u8; 10]).into()).await.unwrap();
+        }
+        store.get(&Path::from("a")).await.unwrap().bytes().await.unwrap();
+        store.get(&Path::from("b")).await.unwrap().bytes().await.unwrap();
+        store.get(&Path::from("a")).await.unwrap().bytes().await.unwrap();
+        store.get(&Path::from("c")).await.unwrap().bytes().await.unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.cached_blocks, stats.cached_bytes), (2, 20));
+        assert_eq!((stats.evicted_blocks, stats.evicted_bytes), (1, 10));
+        // "b" was read least recently
+        store.get(&Path::from("a")).await.unwrap().bytes().await.unwrap();
+        assert_eq!(cache.stats().hits, 2);
+        let files = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 2);
+    }
+}
@@ -0,0 +1,225 @@
+//! Cloud object stores for tables registered from remote files
+//!
+//! `register_gcs("lake", GcsCredentials::ApplicationDefault)` lets tables
+//! read `gs://lake/...` URLs, e.g.
+//! `register_files("trips", "gs://lake/trips/*.parquet", None)`. Stores are
+//! registered per bucket with the session's runtime, so every table, `COPY`
+//! and `EXPORT DATA` statement reaching the bucket goes through them.
+//!
+//! `register_azure("az://lake")` does the same for an Azure Blob Storage or
+//! ADLS Gen2 container, with the account, access key or SAS token set in
+//! `EngineConfig`. DataFusion looks stores up by scheme and host, which for
+//! `abfss://container@account.dfs.core.windows.net` is the account: only
+//! one container per account can be registered through `abfss://` URLs,
+//! while `az://` URLs register each container on its own.
+//!
+//! `register_http("census", "https://data.example.org/census.parquet",
+//! None, &[])` registers a file published over HTTP(S) as a table, reading
+//! Parquet footers and row groups with range requests instead of
+//! downloading whole files. The headers given, such as `Authorization`,
+//! are sent with every request to that origin. The server must answer
+//! `HEAD` requests with the file size; globs additionally need a server
+//! that lists directories through WebDAV.
+//!
+//! Stores are not saved by `snapshot_to`, as they carry credentials;
+//! register them again before `restore_from` restores tables reading them.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::execution::object_store::ObjectStoreUrl;
+use object_store::azure::{AzureConfigKey, MicrosoftAzureBuilder};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::http::HttpBuilder;
+use object_store::{ClientOptions, ObjectStore};
+use serde::{Deserialize, Serialize};
+
+use crate::config_error;
+use crate::error::{BlazeError, BlazeResult};
+
+/// How requests to Google Cloud Storage are authenticated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcsCredentials {
+    /// Application default credentials: the `GOOGLE_*` environment
+    /// variables, the file written by `gcloud auth application-default
+    /// login`, or the metadata server on GCE and GKE, in that order
+    #[default]
+    ApplicationDefault,
+    /// Application default credentials read from a file, such as an
+    /// `authorized_user` file copied from another machine
+    ApplicationCredentialsFile(String),
+    /// A service account JSON key file
+    ServiceAccountFile(String),
+    /// The contents of a service account JSON key
+    ServiceAccountKey(String),
+}
+
+impl GcsCredentials {
+    /// Names the credentials without revealing a key
+    fn describe(&self) -> String {
+        match self {
+            Self::ApplicationDefault => "application default credentials".to_string(),
+            Self::ApplicationCredentialsFile(path) => format!("application credentials file {}", path),
+            Self::ServiceAccountFile(path) => format!("service account key file {}", path),
+            Self::ServiceAccountKey(_) => "service account key".to_string(),
+        }
+    }
+}
+
+/// Azure settings from `EngineConfig`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AzureCredentials {
+    pub account: Option<String>,
+    pub access_key: Option<String>,
+    pub sas_token: Option<String>,
+}
+
+/// An object store registered with the engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectStoreInfo {
+    /// URL prefix the store serves, e.g. `gs://lake`
+    pub url: String,
+    /// How requests are authenticated, without secrets
+    pub credentials: String,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// Build a store for a GCS bucket; nothing is fetched until it is read
+pub(crate) fn gcs_store(bucket: &str, credentials: &GcsCredentials) -> BlazeResult<(ObjectStoreUrl, Arc<dyn ObjectStore>, String)> {
+    let bucket = bucket.strip_prefix("gs://").unwrap_or(bucket).trim_end_matches('/');
+    if bucket.is_empty() || bucket.contains('/') {
+        return Err(config_error!("'{}' is not a GCS bucket name", bucket));
+    }
+    let url = ObjectStoreUrl::parse(format!("gs://{}", bucket))?;
+
+    let builder = match credentials {
+        GcsCredentials::ApplicationDefault => GoogleCloudStorageBuilder::from_env(),
+        GcsCredentials::ApplicationCredentialsFile(path) => GoogleCloudStorageBuilder::new().with_application_credentials(path),
+        GcsCredentials::ServiceAccountFile(path) => GoogleCloudStorageBuilder::new().with_service_account_path(path),
+        GcsCredentials::ServiceAccountKey(key) => GoogleCloudStorageBuilder::new().with_service_account_key(key),
+    };
+    let store = builder
+        .with_bucket_name(bucket)
+        .build()
+        .map_err(|e| config_error!("Cannot configure GCS bucket '{}' with {}: {}", bucket, credentials.describe(), e))?;
+    Ok((url, Arc::new(store), credentials.describe()))
+}
+
+/// Build a store for an Azure container URL such as `az://lake` or
+/// `abfss://lake@account.dfs.core.windows.net`; nothing is fetched until
+/// it is read
+pub(crate) fn azure_store(url: &str, credentials: &AzureCredentials) -> BlazeResult<(ObjectStoreUrl, Arc<dyn ObjectStore>, String)> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_lowercase()).unwrap_or_default();
+    if !matches!(scheme.as_str(), "az" | "azure" | "abfs" | "abfss") {
+        return Err(config_error!("'{}' is not an az://, abfs:// or abfss:// container URL", url));
+    }
+    let store_url = ListingTableUrl::parse(url)?.object_store();
+
+    // Explicit settings win over the AZURE_* environment variables
+    let mut builder = MicrosoftAzureBuilder::from_env().with_url(store_url.as_str());
+    if let Some(account) = &credentials.account {
+        builder = builder.with_account(account);
+    }
+    let described = match (&credentials.access_key, &credentials.sas_token) {
+        (Some(key), _) => {
+            builder = builder.with_access_key(key);
+            "access key"
+        }
+        (None, Some(token)) => {
+            builder = builder.with_config(AzureConfigKey::SasKey, token.trim_start_matches('?'));
+            "SAS token"
+        }
+        (None, None) => "Azure credentials from the environment",
+    };
+    let store = builder
+        .build()
+        .map_err(|e| config_error!("Cannot configure Azure container '{}' with {}: {}", store_url.as_str(), described, e))?;
+    Ok((store_url, Arc::new(store), described.to_string()))
+}
+
+/// Build a store for the origin of an `http://` or `https://` URL, sending
+/// `headers` with every request; nothing is fetched until it is read
+pub(crate) fn http_store(url: &str, headers: &[(String, String)]) -> BlazeResult<(ObjectStoreUrl, Arc<dyn ObjectStore>, String)> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_lowercase()).unwrap_or_default();
+    if !matches!(scheme.as_str(), "http" | "https") {
+        return Err(config_error!("'{}' is not an http:// or https:// URL", url));
+    }
+    let store_url = ListingTableUrl::parse(url)?.object_store();
+
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| config_error!("'{}' is not an HTTP header name", name))?;
+        // Values are kept out of errors and logs, as they are usually secrets
+        let mut value = HeaderValue::from_str(value).map_err(|_| config_error!("The value of HTTP header '{}' is not valid", name))?;
+        value.set_sensitive(true);
+        header_map.append(header, value);
+    }
+    let options = ClientOptions::new().with_allow_http(scheme == "http").with_default_headers(header_map);
+    let store = HttpBuilder::new()
+        .with_url(store_url.as_str())
+        .with_client_options(options)
+        .build()
+        .map_err(|e| config_error!("Cannot configure HTTP store for '{}': {}", store_url.as_str(), e))?;
+
+    let described = match headers {
+        [] => "no authentication".to_string(),
+        headers => format!("headers {}", headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ")),
+    };
+    Ok((store_url, Arc::new(store), described))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcs_store_configuration() {
+        let (url, _, credentials) = gcs_store("gs://lake/", &GcsCredentials::ApplicationDefault).unwrap();
+        assert_eq!(url.as_str(), "gs://lake/");
+        assert_eq!(credentials, "application default credentials");
+
+        let error = gcs_store("lake/trips", &GcsCredentials::ApplicationDefault).unwrap_err().to_string();
+        assert!(error.contains("not a GCS bucket name"), "{}", error);
+        let missing = GcsCredentials::ServiceAccountFile("/nonexistent/key.json".to_string());
+        let error = gcs_store("lake", &missing).unwrap_err().to_string();
+        assert!(error.contains("service account key file /nonexistent/key.json"), "{}", error);
+        let error = gcs_store("lake", &GcsCredentials::ServiceAccountKey("{\"secret\": 1}".to_string())).unwrap_err().to_string();
+        assert!(!error.contains("secret"), "{}", error);
+    }
+
+    #[test]
+    fn test_azure_store_configuration() {
+        let credentials = AzureCredentials {
+            account: Some("devstore".to_string()),
+            access_key: Some("c2VjcmV0LWtleQ==".to_string()),
+            sas_token: None,
+        };
+        let (url, _, described) = azure_store("az://lake/trips/", &credentials).unwrap();
+        assert_eq!((url.as_str(), described.as_str()), ("az://lake/", "access key"));
+        let (url, _, _) = azure_store("abfss://lake@devstore.dfs.core.windows.net/trips", &credentials).unwrap();
+        assert_eq!(url.as_str(), "abfss://lake@devstore.dfs.core.windows.net/");
+
+        let sas = AzureCredentials { access_key: None, sas_token: Some("?sv=2022-11-02&sig=abc".to_string()), ..credentials };
+        assert_eq!(azure_store("az://lake", &sas).unwrap().2, "SAS token");
+        let error = azure_store("s3://lake", &sas).unwrap_err().to_string();
+        assert!(error.contains("not an az://"), "{}", error);
+    }
+
+    #[test]
+    fn test_http_store_configuration() {
+        let headers = vec![("Authorization".to_string(), "Bearer secret".to_string())];
+        let (url, _, described) = http_store("https://data.example.org/census/2020.parquet", &headers).unwrap();
+        assert_eq!((url.as_str(), described.as_str()), ("https://data.example.org/", "headers Authorization"));
+        assert_eq!(http_store("http://localhost:8080/a.csv", &[]).unwrap().2, "no authentication");
+
+        let bad_name = vec![("Bad Header".to_string(), "x".to_string())];
+        assert!(http_store("https://data.example.org/a.csv", &bad_name).is_err());
+        let bad_value = vec![("Authorization".to_string(), "secret\nvalue".to_string())];
+        let error = http_store("https://data.example.org/a.csv", &bad_value).unwrap_err().to_string();
+        assert!(!error.contains("secret"), "{}", error);
+        assert!(http_store("ftp://data.example.org/a.csv", &[]).is_err());
+    }
+}
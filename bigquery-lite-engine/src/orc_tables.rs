@@ -0,0 +1,403 @@
+//! Tables over ORC files
+//!
+//! `register_orc("events", "exports/events/")` registers an ORC file, or the
+//! `.orc` files in a directory, as a table read in place on every scan.
+//! ORC types map to Arrow as orc-rust reads them: integers, floating point,
+//! strings, binary, decimals, dates and timestamps to the Arrow types of
+//! the same width, structs, lists and maps to their nested Arrow types.
+//!
+//! Scans read only the projected columns, and skip files and stripes whose
+//! column statistics show that no row can match a filter comparing an
+//! integer, floating-point or date column with a literal (`=`, `<`, `<=`,
+//! `>`, `>=`). The filters are still applied to the rows that are read.
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use datafusion::catalog::Session;
+use datafusion::common::ScalarValue;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::utils::split_conjunction;
+use datafusion::logical_expr::{BinaryExpr, Expr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use orc_rust::projection::ProjectionMask;
+use orc_rust::schema::RootDataType;
+use orc_rust::statistics::{ColumnStatistics, TypeStatistics};
+use orc_rust::ArrowReaderBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Files behind a table registered from ORC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrcTableInfo {
+    pub table_name: String,
+    /// File or directory the table was registered from
+    pub path: String,
+    /// Files read, sorted
+    pub files: Vec<String>,
+    /// Rows across the files
+    pub rows: u64,
+    /// Stripes across the files
+    pub stripes: usize,
+    /// Combined size of the files in bytes
+    pub total_bytes: u64,
+    /// Column names with their Arrow types
+    pub columns: Vec<(String, String)>,
+}
+
+/// A table scanning ORC files
+#[derive(Debug)]
+pub(crate) struct OrcTable {
+    files: Vec<PathBuf>,
+    schema: SchemaRef,
+}
+
+/// Open the ORC file or the `.orc` files in the directory at `path`, check
+/// that their schemas agree and build a table over them
+pub(crate) fn build_table(name: &str, path: &Path) -> BlazeResult<(Arc<OrcTable>, OrcTableInfo)> {
+    let files = if path.is_dir() {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let file = entry?.path();
+            if file.is_file() && file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("orc")) {
+                files.push(file);
+            }
+        }
+        if files.is_empty() {
+            return Err(invalid_input!("No .orc files in '{}'", path.display()));
+        }
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut schema: Option<SchemaRef> = None;
+    let (mut rows, mut stripes, mut total_bytes) = (0, 0, 0);
+    for file in &files {
+        let builder = open(file)?;
+        let metadata = builder.file_metadata();
+        let file_schema = Arc::new(metadata.root_data_type().create_arrow_schema(&HashMap::new()));
+        match &schema {
+            None => schema = Some(file_schema),
+            Some(expected) if *expected != file_schema => {
+                return Err(BlazeError::SchemaMismatch(format!(
+                    "'{}' has schema {} but '{}' has {}",
+                    file.display(),
+                    file_schema,
+                    files[0].display(),
+                    expected
+                )))
+            }
+            Some(_) => {}
+        }
+        rows += metadata.number_of_rows();
+        stripes += metadata.stripe_metadatas().len();
+        total_bytes += std::fs::metadata(file)?.len();
+    }
+    let schema = schema.expect("at least one file");
+
+    let info = OrcTableInfo {
+        table_name: name.to_string(),
+        path: path.display().to_string(),
+        files: files.iter().map(|file| file.display().to_string()).collect(),
+        rows,
+        stripes,
+        total_bytes,
+        columns: schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+    };
+    Ok((Arc::new(OrcTable { files, schema }), info))
+}
+
+fn open(file: &Path) -> BlazeResult<ArrowReaderBuilder<File>> {
+    ArrowReaderBuilder::try_new(File::open(file)?)
+        .map_err(|e| invalid_input!("Cannot read ORC file '{}': {}", file.display(), e))
+}
+
+impl OrcTable {
+    /// Read the projected columns of the stripes that may match `bounds`
+    fn read(&self, projection: Option<&[usize]>, bounds: &[ColumnBound], limit: Option<usize>) -> BlazeResult<Vec<RecordBatch>> {
+        let schema = match projection {
+            Some(indices) => Arc::new(self.schema.project(indices)?),
+            None => self.schema.clone(),
+        };
+        // Columns come back in file order; put them in projection order
+        let order: Option<Vec<usize>> = projection.map(|indices| {
+            let mut sorted = indices.to_vec();
+            sorted.sort_unstable();
+            indices.iter().map(|index| sorted.binary_search(index).unwrap_or_default()).collect()
+        });
+
+        let mut batches = Vec::new();
+        let mut rows = 0;
+        for file in &self.files {
+            let builder = open(file)?;
+            let metadata = builder.file_metadata();
+            let root = metadata.root_data_type();
+            if !may_match(root, metadata.column_file_statistics(), bounds) {
+                continue;
+            }
+            let stripes: Vec<_> = metadata
+                .stripe_metadatas()
+                .iter()
+                .filter(|stripe| may_match(root, stripe.column_statistics(), bounds))
+                .map(|stripe| (stripe.offset() as usize, stripe.number_of_rows() as usize))
+                .collect();
+
+            for (offset, stripe_rows) in stripes {
+                if limit.is_some_and(|limit| rows >= limit) {
+                    return Ok(batches);
+                }
+                // Only the row count is needed, e.g. for COUNT(*)
+                if schema.fields().is_empty() {
+                    let options = RecordBatchOptions::new().with_row_count(Some(stripe_rows));
+                    batches.push(RecordBatch::try_new_with_options(schema.clone(), vec![], &options)?);
+                    rows += stripe_rows;
+                    continue;
+                }
+                let mut builder = open(file)?.with_file_byte_range(offset..offset + 1);
+                if let Some(indices) = projection {
+                    let mask = ProjectionMask::roots(builder.file_metadata().root_data_type(), column_indices(root, indices));
+                    builder = builder.with_projection(mask);
+                }
+                for batch in builder.build() {
+                    let batch = batch?;
+                    let batch = match &order {
+                        Some(order) => batch.project(order)?,
+                        None => batch,
+                    };
+                    rows += batch.num_rows();
+                    batches.push(RecordBatch::try_new(schema.clone(), batch.columns().to_vec())?);
+                }
+            }
+        }
+        Ok(batches)
+    }
+}
+
+/// ORC column indices of the root columns at `indices`
+fn column_indices<'a>(root: &'a RootDataType, indices: &'a [usize]) -> impl Iterator<Item = usize> + 'a {
+    indices.iter().map(|index| root.children()[*index].data_type().column_index())
+}
+
+#[async_trait]
+impl TableProvider for OrcTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(indices) => Arc::new(self.schema.project(indices)?),
+            None => self.schema.clone(),
+        };
+        let bounds: Vec<_> = filters.iter().flat_map(split_conjunction).filter_map(ColumnBound::from_expr).collect();
+        // Filters are applied after the scan, so a limit only holds without them
+        let limit = if filters.is_empty() { limit } else { None };
+
+        let table = OrcTable { files: self.files.clone(), schema: self.schema.clone() };
+        let projection = projection.cloned();
+        let batches = tokio::task::spawn_blocking(move || table.read(projection.as_deref(), &bounds, limit))
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+        MemTable::try_new(schema, vec![batches])?.scan(state, None, &[], None).await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        // Inexact: stripes that may match are read whole
+        Ok(filters
+            .iter()
+            .map(|filter| match split_conjunction(filter).into_iter().any(|f| ColumnBound::from_expr(f).is_some()) {
+                true => TableProviderFilterPushDown::Inexact,
+                false => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+}
+
+/// A comparison of a column with a literal that statistics can rule out
+#[derive(Debug, Clone)]
+struct ColumnBound {
+    column: String,
+    op: Operator,
+    value: BoundValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BoundValue {
+    Integer(i64),
+    Double(f64),
+    Date(i32),
+}
+
+impl PartialOrd for BoundValue {
+    /// Integers compare exactly with integers and as floating point with
+    /// doubles; dates only compare with dates
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (*self, *other) {
+            (BoundValue::Integer(a), BoundValue::Integer(b)) => Some(a.cmp(&b)),
+            (BoundValue::Date(a), BoundValue::Date(b)) => Some(a.cmp(&b)),
+            (BoundValue::Integer(a), BoundValue::Double(b)) => (a as f64).partial_cmp(&b),
+            (BoundValue::Double(a), BoundValue::Integer(b)) => a.partial_cmp(&(b as f64)),
+            (BoundValue::Double(a), BoundValue::Double(b)) => a.partial_cmp(&b),
+            _ => None,
+        }
+    }
+}
+
+/// Smallest and largest value of a column, where its statistics give them
+fn value_range(statistics: &ColumnStatistics) -> Option<(BoundValue, BoundValue)> {
+    match statistics.type_statistics()? {
+        TypeStatistics::Integer { min, max, .. } => Some((BoundValue::Integer(*min), BoundValue::Integer(*max))),
+        TypeStatistics::Double { min, max, .. } => Some((BoundValue::Double(*min), BoundValue::Double(*max))),
+        TypeStatistics::Date { min, max } => Some((BoundValue::Date(*min), BoundValue::Date(*max))),
+        _ => None,
+    }
+}
+
+impl ColumnBound {
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        let Expr::BinaryExpr(BinaryExpr { left, op, right }) = expr else {
+            return None;
+        };
+        let (column, op, literal) = match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(column), Expr::Literal(value)) => (column, *op, value),
+            (Expr::Literal(value), Expr::Column(column)) => (column, op.swap()?, value),
+            _ => return None,
+        };
+        if !matches!(op, Operator::Eq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq) {
+            return None;
+        }
+        let value = match literal {
+            ScalarValue::Int8(Some(v)) => BoundValue::Integer(*v as i64),
+            ScalarValue::Int16(Some(v)) => BoundValue::Integer(*v as i64),
+            ScalarValue::Int32(Some(v)) => BoundValue::Integer(*v as i64),
+            ScalarValue::Int64(Some(v)) => BoundValue::Integer(*v),
+            ScalarValue::Float32(Some(v)) => BoundValue::Double(*v as f64),
+            ScalarValue::Float64(Some(v)) => BoundValue::Double(*v),
+            ScalarValue::Date32(Some(v)) => BoundValue::Date(*v),
+            _ => return None,
+        };
+        Some(Self { column: column.name.clone(), op, value })
+    }
+
+    /// Whether a column with values from `min` to `max` may hold a
+    /// matching value. Values that do not compare, such as NaN bounds or a
+    /// date against a number, rule nothing out.
+    fn within(&self, min: BoundValue, max: BoundValue) -> bool {
+        let value = self.value;
+        match self.op {
+            Operator::Eq => value.partial_cmp(&min) != Some(Ordering::Less) && value.partial_cmp(&max) != Some(Ordering::Greater),
+            Operator::Lt => !matches!(min.partial_cmp(&value), Some(Ordering::Greater | Ordering::Equal)),
+            Operator::LtEq => min.partial_cmp(&value) != Some(Ordering::Greater),
+            Operator::Gt => !matches!(max.partial_cmp(&value), Some(Ordering::Less | Ordering::Equal)),
+            Operator::GtEq => max.partial_cmp(&value) != Some(Ordering::Less),
+            _ => true,
+        }
+    }
+}
+
+/// Whether rows with these column statistics may match every bound
+fn may_match(root: &RootDataType, statistics: &[ColumnStatistics], bounds: &[ColumnBound]) -> bool {
+    bounds.iter().all(|bound| {
+        root.children()
+            .iter()
+            .find(|column| column.name() == bound.column)
+            .and_then(|column| statistics.get(column.data_type().column_index()))
+            .and_then(value_range)
+            .is_none_or(|(min, max)| bound.within(min, max))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Float64Array, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::logical_expr::{col, lit};
+    use orc_rust::ArrowWriterBuilder;
+
+    #[test]
+    fn test_bounds_rule_out_value_ranges() {
+        let bound = |expr: Expr| ColumnBound::from_expr(&expr).unwrap();
+        let ints = (BoundValue::Integer(100), BoundValue::Integer(199));
+        let within = |bound: ColumnBound, (min, max): (BoundValue, BoundValue)| bound.within(min, max);
+
+        assert!(within(bound(col("id").eq(lit(150i64))), ints));
+        assert!(!within(bound(col("id").eq(lit(200i64))), ints));
+        assert!(!within(bound(col("id").gt(lit(199i64))), ints));
+        assert!(within(bound(col("id").gt_eq(lit(199i64))), ints));
+        assert!(!within(bound(lit(100i64).gt(col("id"))), ints));
+        assert!(within(bound(col("id").lt_eq(lit(100i32))), ints));
+        assert!(!within(bound(col("id").lt(lit(99.5))), ints));
+        assert!(within(bound(col("id").lt(lit(100.5))), ints));
+
+        let doubles = (BoundValue::Double(0.5), BoundValue::Double(f64::NAN));
+        assert!(within(bound(col("score").gt(lit(10i64))), doubles));
+        assert!(!within(bound(col("score").lt(lit(0i64))), doubles));
+        let dates = (BoundValue::Date(19_800), BoundValue::Date(19_810));
+        assert!(!within(bound(col("day").gt(lit(ScalarValue::Date32(Some(19_810))))), dates));
+        assert!(within(bound(col("day").gt(lit(5i64))), dates));
+
+        assert!(ColumnBound::from_expr(&col("name").eq(lit("a"))).is_none());
+        assert!(ColumnBound::from_expr(&col("id").not_eq(lit(1i64))).is_none());
+        assert!(ColumnBound::from_expr(&col("id").eq(col("other"))).is_none());
+    }
+
+    #[test]
+    fn test_read_projection_in_stripes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("values.orc");
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("score", DataType::Float64, true),
+        ]));
+        let mut writer = ArrowWriterBuilder::new(File::create(&path).unwrap(), schema.clone()).try_build().unwrap();
+        for stripe in 0..4i64 {
+            let ids = Int64Array::from_iter_values(stripe * 100..(stripe + 1) * 100);
+            let scores = Float64Array::from_iter_values((0..100).map(|i| (stripe * 100 + i) as f64 / 10.0));
+            writer.write(&RecordBatch::try_new(schema.clone(), vec![Arc::new(ids), Arc::new(scores)]).unwrap()).unwrap();
+            writer.flush_stripe().unwrap();
+        }
+        writer.close().unwrap();
+
+        let (table, info) = build_table("values", &path).unwrap();
+        assert_eq!((info.rows, info.stripes), (400, 4));
+        // Without statistics in the file no stripe is ruled out
+        let bounds = [ColumnBound::from_expr(&col("id").gt(lit(1000i64))).unwrap()];
+        let batches = table.read(Some(&[1, 0]), &bounds, None).unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 400);
+        assert_eq!(batches[0].schema().field(0).name(), "score");
+        let ids = batches[3].column(1).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.value(0), 300);
+
+        let batches = table.read(Some(&[]), &[], Some(150)).unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).collect::<Vec<_>>(), vec![100, 100]);
+    }
+}
@@ -0,0 +1,293 @@
+//! Writing tables and query results as hive-partitioned Parquet
+//!
+//! `write_table_to_parquet("trips", "lake/trips", &options)` with
+//! `partition_by = ["year", "city"]` writes one directory per combination of
+//! values, e.g. `lake/trips/year=2024/city=Paris/part-00000.parquet`, and
+//! leaves those columns out of the files as readers of hive layouts expect.
+//! NULL values go to `__HIVE_DEFAULT_PARTITION__`. A file is closed and the
+//! next one started once it reaches `target_file_size_bytes`, so files end up
+//! slightly above the target. Without `partition_by` the files go straight
+//! into the directory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+
+use datafusion::arrow::array::UInt32Array;
+use datafusion::arrow::compute::take_record_batch;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use datafusion::parquet::arrow::ArrowWriter;
+use datafusion::parquet::basic::Compression;
+use datafusion::parquet::file::properties::WriterProperties;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Directory name of NULL partition values, as used by Hive and Spark
+pub const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// How `write_table_to_parquet` lays out its files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParquetSinkOptions {
+    /// Columns whose values name the partition directories, outermost first
+    pub partition_by: Vec<String>,
+    /// Size at which a file is closed and the next one started (default: 128 MiB)
+    pub target_file_size_bytes: usize,
+    /// Parquet codec such as `snappy`, `zstd(3)` or `uncompressed` (default: snappy)
+    pub compression: String,
+    /// Replace a non-empty output directory instead of failing (default: false)
+    pub overwrite: bool,
+}
+
+impl Default for ParquetSinkOptions {
+    fn default() -> Self {
+        Self {
+            partition_by: Vec::new(),
+            target_file_size_bytes: 128 * 1024 * 1024,
+            compression: "snappy".to_string(),
+            overwrite: false,
+        }
+    }
+}
+
+/// A Parquet file written by the sink
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrittenParquetFile {
+    /// Path of the file
+    pub path: String,
+    /// Partition directory relative to the output directory, e.g.
+    /// `year=2024/city=Paris`; empty without `partition_by`
+    pub partition: String,
+    /// Rows in the file
+    pub rows: usize,
+    /// Size of the file in bytes
+    pub bytes: u64,
+}
+
+/// Result of `write_table_to_parquet`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParquetSinkReport {
+    /// Output directory
+    pub path: String,
+    /// Rows written across all files
+    pub rows_written: usize,
+    /// Combined size of the files in bytes
+    pub bytes_written: u64,
+    /// Files written, sorted by path
+    pub files: Vec<WrittenParquetFile>,
+}
+
+/// The query to write for a table name or SQL text
+pub(crate) fn source_sql(table_or_sql: &str) -> String {
+    static TABLE_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*[A-Za-z_][\w.]*\s*$").unwrap());
+    if TABLE_NAME.is_match(table_or_sql) {
+        format!("SELECT * FROM {}", table_or_sql.trim())
+    } else {
+        table_or_sql.to_string()
+    }
+}
+
+struct OpenFile {
+    path: PathBuf,
+    writer: ArrowWriter<File>,
+    rows: usize,
+}
+
+/// Writes batches of one schema into partition directories, rolling files
+/// over at the target size
+pub(crate) struct ParquetSink {
+    root: PathBuf,
+    partition_columns: Vec<(String, usize)>,
+    data_columns: Vec<usize>,
+    file_schema: SchemaRef,
+    properties: WriterProperties,
+    target_file_size: usize,
+    open: HashMap<String, OpenFile>,
+    files_started: HashMap<String, usize>,
+    written: Vec<WrittenParquetFile>,
+}
+
+impl ParquetSink {
+    /// Check the options against `schema` and prepare the output directory
+    pub(crate) fn create(root: &Path, schema: &SchemaRef, options: &ParquetSinkOptions) -> BlazeResult<Self> {
+        let mut partition_columns = Vec::new();
+        for name in &options.partition_by {
+            let index = schema
+                .index_of(name)
+                .map_err(|_| invalid_input!("Partition column '{}' is not in the result", name))?;
+            if partition_columns.iter().any(|(_, i)| *i == index) {
+                return Err(invalid_input!("Partition column '{}' is listed twice", name));
+            }
+            partition_columns.push((name.clone(), index));
+        }
+        let data_columns: Vec<usize> = (0..schema.fields().len())
+            .filter(|i| !partition_columns.iter().any(|(_, p)| p == i))
+            .collect();
+        if data_columns.is_empty() {
+            return Err(invalid_input!("Cannot partition by every column; no columns would be left to write"));
+        }
+        if options.target_file_size_bytes == 0 {
+            return Err(invalid_input!("target_file_size_bytes must be greater than 0"));
+        }
+        let compression = Compression::from_str(&options.compression)
+            .map_err(|e| invalid_input!("Invalid Parquet compression '{}': {}", options.compression, e))?;
+
+        if root.exists() && std::fs::read_dir(root)?.next().is_some() {
+            if !options.overwrite {
+                return Err(invalid_input!(
+                    "'{}' is not empty; pass overwrite to replace its contents",
+                    root.display()
+                ));
+            }
+            std::fs::remove_dir_all(root)?;
+        }
+        std::fs::create_dir_all(root)?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            partition_columns,
+            file_schema: Arc::new(schema.project(&data_columns)?),
+            data_columns,
+            properties: WriterProperties::builder().set_compression(compression).build(),
+            target_file_size: options.target_file_size_bytes,
+            open: HashMap::new(),
+            files_started: HashMap::new(),
+            written: Vec::new(),
+        })
+    }
+
+    /// Route each row of `batch` to its partition's current file
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let data = batch.project(&self.data_columns)?;
+        if self.partition_columns.is_empty() {
+            return self.write_partition(String::new(), &data);
+        }
+
+        let mut partitions: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        let options = FormatOptions::default();
+        let formatters = self
+            .partition_columns
+            .iter()
+            .map(|(_, index)| ArrayFormatter::try_new(batch.column(*index).as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            let segments = self
+                .partition_columns
+                .iter()
+                .zip(&formatters)
+                .map(|((name, index), formatter)| {
+                    let value = if batch.column(*index).is_null(row) {
+                        NULL_PARTITION.to_string()
+                    } else {
+                        escape_partition_value(&formatter.value(row).to_string())
+                    };
+                    format!("{}={}", escape_partition_value(name), value)
+                })
+                .collect::<Vec<_>>();
+            partitions.entry(segments.join("/")).or_default().push(row as u32);
+        }
+
+        for (partition, rows) in partitions {
+            let rows = take_record_batch(&data, &UInt32Array::from(rows))?;
+            self.write_partition(partition, &rows)?;
+        }
+        Ok(())
+    }
+
+    fn write_partition(&mut self, partition: String, rows: &RecordBatch) -> BlazeResult<()> {
+        if !self.open.contains_key(&partition) {
+            let file = self.start_file(&partition)?;
+            self.open.insert(partition.clone(), file);
+        }
+        let file = self.open.get_mut(&partition).expect("file opened above");
+        file.writer.write(rows).map_err(parquet_error)?;
+        file.rows += rows.num_rows();
+
+        if file.writer.bytes_written() + file.writer.in_progress_size() >= self.target_file_size {
+            let file = self.open.remove(&partition).expect("file is open");
+            self.finish_file(partition, file)?;
+        }
+        Ok(())
+    }
+
+    fn start_file(&mut self, partition: &str) -> BlazeResult<OpenFile> {
+        let directory = self.root.join(partition);
+        std::fs::create_dir_all(&directory)?;
+        let number = self.files_started.entry(partition.to_string()).or_insert(0);
+        let path = directory.join(format!("part-{:05}.parquet", number));
+        *number += 1;
+
+        let writer = ArrowWriter::try_new(File::create(&path)?, self.file_schema.clone(), Some(self.properties.clone()))
+            .map_err(parquet_error)?;
+        Ok(OpenFile { path, writer, rows: 0 })
+    }
+
+    fn finish_file(&mut self, partition: String, file: OpenFile) -> BlazeResult<()> {
+        file.writer.close().map_err(parquet_error)?;
+        self.written.push(WrittenParquetFile {
+            bytes: std::fs::metadata(&file.path)?.len(),
+            path: file.path.display().to_string(),
+            partition,
+            rows: file.rows,
+        });
+        Ok(())
+    }
+
+    /// Close the files still open
+    pub(crate) fn finish(mut self) -> BlazeResult<ParquetSinkReport> {
+        for (partition, file) in std::mem::take(&mut self.open) {
+            self.finish_file(partition, file)?;
+        }
+        self.written.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(ParquetSinkReport {
+            path: self.root.display().to_string(),
+            rows_written: self.written.iter().map(|f| f.rows).sum(),
+            bytes_written: self.written.iter().map(|f| f.bytes).sum(),
+            files: self.written,
+        })
+    }
+}
+
+pub(crate) fn parquet_error(e: datafusion::parquet::errors::ParquetError) -> BlazeError {
+    BlazeError::QueryExecution(e.into())
+}
+
+/// Percent-encode the characters Hive escapes in partition directory names
+fn escape_partition_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_control() || "\"#%'*/:=?\\{[]^".contains(c) {
+            let mut buffer = [0; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_paths() {
+        assert_eq!(escape_partition_value("a/b=c 100%"), "a%2Fb%3Dc 100%25");
+        assert_eq!(escape_partition_value("Zürich"), "Zürich");
+        assert_eq!(source_sql(" trips "), "SELECT * FROM trips");
+        assert_eq!(source_sql("db.trips"), "SELECT * FROM db.trips");
+        assert_eq!(source_sql("SELECT 1"), "SELECT 1");
+    }
+}
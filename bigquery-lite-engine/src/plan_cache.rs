@@ -0,0 +1,274 @@
+//! Cached query plans
+//!
+//! Planning dominates small interactive queries, so the engine keeps the
+//! optimized logical plans of up to `plan_cache_size` recent queries, keyed
+//! by their SQL with whitespace, comments and the case of unquoted words
+//! normalized away. Running the same query again skips parsing, planning
+//! and logical optimization and goes straight to physical planning.
+//!
+//! A cached plan is used only while every table and view it reads is still
+//! the one it was planned against, so dropping, replacing or redefining one
+//! plans the query again. Writes to in-memory tables do not: cached plans
+//! read the latest version of their tables like any other statement. Cached
+//! plans refer to their tables weakly, so they never keep a dropped table's
+//! rows in memory.
+//!
+//! Statements changing session settings, `ANALYZE TABLE` and configuration
+//! changes clear the cache, as they change how queries are planned.
+//!
+//! Queries with hints, time travel, `ML.PREDICT`, table functions or
+//! functions such as `now()`, which optimization turns into constants, are
+//! always planned afresh. So are queries reading views defined over other
+//! views, whose inner views are only planned when the query runs.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Weak};
+
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::common::tree_node::{Transformed, TreeNodeRecursion};
+use datafusion::common::TableReference;
+use datafusion::datasource::{provider_as_source, source_as_provider, TableProvider};
+use datafusion::error::Result;
+use datafusion::execution::session_state::SessionStateBuilder;
+use datafusion::logical_expr::{LogicalPlan, TableSource};
+use datafusion::prelude::{DataFrame, SessionContext};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::table_versions;
+
+/// Usage of the plan cache
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlanCacheStats {
+    /// Plans held now
+    pub entries: usize,
+    /// Queries run from a cached plan
+    pub hits: u64,
+    /// Cacheable queries that were planned because no current plan was cached
+    pub misses: u64,
+}
+
+/// A table or view a cached plan was planned against
+type PlannedTable = (TableReference, Weak<dyn TableProvider>);
+
+/// An optimized plan and the tables it was planned against
+#[derive(Debug, Clone)]
+pub(crate) struct CachedPlan {
+    /// Optimized plan whose scans hold `DetachedSource`s
+    plan: LogicalPlan,
+    tables: Vec<PlannedTable>,
+    last_used: u64,
+}
+
+/// A cached plan's scan source, which does not keep its table alive
+#[derive(Debug)]
+struct DetachedSource {
+    provider: Weak<dyn TableProvider>,
+    schema: SchemaRef,
+}
+
+impl TableSource for DetachedSource {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Optimized plans of recent queries keyed by normalized SQL
+#[derive(Debug, Default)]
+pub(crate) struct PlanCache {
+    plans: HashMap<String, CachedPlan>,
+    uses: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl PlanCache {
+    /// The cached plan of `key`, which the caller checks is still current
+    pub(crate) fn get(&mut self, key: &str) -> Option<CachedPlan> {
+        self.uses += 1;
+        let plan = self.plans.get_mut(key)?;
+        plan.last_used = self.uses;
+        Some(plan.clone())
+    }
+
+    /// Count a query run from a cached plan
+    pub(crate) fn record_hit(&mut self) {
+        self.hits += 1;
+    }
+
+    /// Cache `optimized`, the plan of a query that was just planned from
+    /// `planned`, dropping the least recently used plan beyond `capacity`.
+    /// Plans reading sources other than table providers, or still scanning
+    /// views after optimization, are not cached.
+    pub(crate) fn insert(&mut self, key: String, planned: &LogicalPlan, optimized: LogicalPlan, capacity: usize) {
+        self.misses += 1;
+        self.plans.remove(&key);
+        if !matches!(scans_views(&optimized), Ok(false)) {
+            return;
+        }
+        let (Ok(tables), Ok(plan)) = (scanned_tables(planned), detach(optimized)) else {
+            return;
+        };
+        while self.plans.len() >= capacity {
+            let Some(oldest) = self.plans.iter().min_by_key(|(_, plan)| plan.last_used).map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.plans.remove(&oldest);
+        }
+        self.uses += 1;
+        self.plans.insert(key, CachedPlan { plan, tables, last_used: self.uses });
+    }
+
+    /// Drop every cached plan
+    pub(crate) fn clear(&mut self) {
+        self.plans.clear();
+    }
+
+    pub(crate) fn stats(&self) -> PlanCacheStats {
+        PlanCacheStats { entries: self.plans.len(), hits: self.hits, misses: self.misses }
+    }
+}
+
+/// Whether `sql` changes a session setting or defines or drops a function,
+/// which change how queries are planned
+pub(crate) fn changes_planning(sql: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?i)^\s*(SET|(CREATE(\s+OR\s+REPLACE)?(\s+TEMP|\s+TEMPORARY)?|DROP)\s+FUNCTION)\b",
+        )
+        .unwrap()
+    });
+    PATTERN.is_match(sql)
+}
+
+/// The tables and views scanned by a plan before optimization inlines
+/// views, including in subqueries
+fn scanned_tables(plan: &LogicalPlan) -> Result<Vec<PlannedTable>> {
+    let mut tables = Vec::new();
+    plan.apply_with_subqueries(|plan| {
+        if let LogicalPlan::TableScan(scan) = plan {
+            tables.push((scan.table_name.clone(), Arc::downgrade(&source_as_provider(&scan.source)?)));
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })?;
+    Ok(tables)
+}
+
+/// Whether `plan` scans a view, which the session running a cached plan
+/// could not analyze
+pub(crate) fn scans_views(plan: &LogicalPlan) -> Result<bool> {
+    let mut found = false;
+    plan.apply_with_subqueries(|plan| {
+        if let LogicalPlan::TableScan(scan) = plan {
+            found |= source_as_provider(&scan.source)?.get_logical_plan().is_some();
+        }
+        Ok(if found { TreeNodeRecursion::Stop } else { TreeNodeRecursion::Continue })
+    })?;
+    Ok(found)
+}
+
+/// `plan` with its scans referring to their tables weakly
+fn detach(plan: LogicalPlan) -> Result<LogicalPlan> {
+    let detached = plan.transform_up_with_subqueries(|plan| match plan {
+        LogicalPlan::TableScan(mut scan) => {
+            let provider = source_as_provider(&scan.source)?;
+            scan.source = Arc::new(DetachedSource { schema: provider.schema(), provider: Arc::downgrade(&provider) });
+            Ok(Transformed::yes(LogicalPlan::TableScan(scan)))
+        }
+        plan => Ok(Transformed::no(plan)),
+    })?;
+    Ok(detached.data)
+}
+
+/// `plan` with its scans referring to their tables again, or None if one
+/// was dropped
+fn attach(plan: LogicalPlan) -> Result<Option<LogicalPlan>> {
+    let mut dropped = false;
+    let attached = plan.transform_up_with_subqueries(|plan| match plan {
+        LogicalPlan::TableScan(mut scan) => {
+            let provider = scan
+                .source
+                .as_any()
+                .downcast_ref::<DetachedSource>()
+                .and_then(|source| source.provider.upgrade());
+            match provider {
+                Some(provider) => scan.source = provider_as_source(provider),
+                None => dropped = true,
+            }
+            Ok(Transformed::yes(LogicalPlan::TableScan(scan)))
+        }
+        plan => Ok(Transformed::no(plan)),
+    })?;
+    Ok((!dropped).then_some(attached.data))
+}
+
+/// A frame running a cached plan, if every table it was planned against is
+/// still registered under its name
+pub(crate) async fn cached_frame(ctx: &SessionContext, cached: CachedPlan) -> Result<Option<DataFrame>> {
+    for (name, planned) in &cached.tables {
+        match ctx.table_provider(name.clone()).await {
+            Ok(current) if Arc::as_ptr(&current) as *const () == planned.as_ptr() as *const () => {}
+            _ => return Ok(None),
+        }
+    }
+    match attach(cached.plan)? {
+        Some(plan) => Ok(Some(optimized_frame(ctx, plan)?)),
+        None => Ok(None),
+    }
+}
+
+/// A frame running an optimized plan without analyzing or optimizing it
+/// again, reading the latest versions of its tables
+pub(crate) fn optimized_frame(ctx: &SessionContext, plan: LogicalPlan) -> Result<DataFrame> {
+    let state = SessionStateBuilder::new_from_existing(ctx.state())
+        .with_analyzer_rules(Vec::new())
+        .with_optimizer_rules(Vec::new())
+        .build();
+    Ok(DataFrame::new(state, table_versions::pin_versions(plan)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    #[tokio::test]
+    async fn test_cached_plans_follow_their_tables() {
+        let ctx = SessionContext::new();
+        let batch = RecordBatch::try_from_iter([("n", Arc::new(Int64Array::from(vec![1, 2, 3])) as _)]).unwrap();
+        ctx.register_batch("t", batch.clone()).unwrap();
+
+        let plan = ctx.state().create_logical_plan("SELECT n FROM t WHERE n > 1").await.unwrap();
+        let optimized = ctx.state().optimize(&plan).unwrap();
+        let mut cache = PlanCache::default();
+        cache.insert("q".to_string(), &plan, optimized, 1);
+
+        let df = cached_frame(&ctx, cache.get("q").unwrap()).await.unwrap().expect("the table is unchanged");
+        let rows: usize = df.collect().await.unwrap().iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(rows, 2);
+
+        // Replacing the table, even with the same rows, makes the plan stale
+        ctx.deregister_table("t").unwrap();
+        ctx.register_batch("t", batch).unwrap();
+        assert!(cached_frame(&ctx, cache.get("q").unwrap()).await.unwrap().is_none());
+
+        // Views over views are planned when they run, so are not cached
+        ctx.sql("CREATE VIEW big AS SELECT n FROM t WHERE n > 1").await.unwrap();
+        ctx.sql("CREATE VIEW top AS SELECT MAX(n) AS n FROM big").await.unwrap();
+        let plan = ctx.state().create_logical_plan("SELECT n FROM top").await.unwrap();
+        let optimized = ctx.state().optimize(&plan).unwrap();
+        cache.insert("views".to_string(), &plan, optimized, 2);
+        assert!(cache.get("views").is_none());
+
+        assert!(changes_planning("set datafusion.execution.batch_size = 10"));
+        assert!(changes_planning("CREATE OR REPLACE FUNCTION f(a INT) RETURNS INT RETURN a"));
+        assert!(!changes_planning("SELECT * FROM settings"));
+    }
+}
@@ -0,0 +1,240 @@
+//! Physical plans as graphs for display
+//!
+//! `BlazeQueryEngine::explain_graph` turns the optimized physical plan of a
+//! query into a tree of `PlanNode`s, which serializes to JSON for the
+//! frontend, and the same tree as a Graphviz DOT digraph:
+//!
+//! ```text
+//! digraph plan {
+//!   node [shape=box, fontname="Helvetica"];
+//!   n0 [label="ProjectionExec: expr=[id@0 as id]\nrows: 3"];
+//!   n1 [label="MemoryExec: partitions=1, partition_sizes=[1]\nrows: 3"];
+//!   n1 -> n0;
+//! }
+//! ```
+//!
+//! Edges point the way data flows, from a node's inputs to the node. Row and
+//! byte counts are the planner's estimates; `stats_exact` says whether they
+//! are known exactly, as for in-memory tables.
+//!
+//! `explain(sql, true)` runs the query first, like `EXPLAIN ANALYZE`, and
+//! gives every node the `metrics` its operator recorded: the rows it
+//! produced, its compute time summed over partitions, the memory it reported
+//! holding and the bytes it spilled. DOT labels then show actual rows and
+//! time as well.
+
+use std::fmt::Write;
+use std::sync::{Arc, LazyLock};
+
+use datafusion::common::stats::Precision;
+use datafusion::physical_plan::metrics::MetricValue;
+use datafusion::physical_plan::{displayable, ExecutionPlan, ExecutionPlanProperties};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlazeResult;
+
+/// One operator of a physical plan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanNode {
+    /// Position in a depth-first walk from the root, which is 0
+    pub id: usize,
+    /// Operator name, e.g. `HashJoinExec`
+    pub name: String,
+    /// The operator with its settings, as in `EXPLAIN`
+    pub description: String,
+    /// Partitions the operator produces
+    pub output_partitions: usize,
+    /// Estimated output rows, when the planner knows
+    pub estimated_rows: Option<usize>,
+    /// Estimated output size in bytes, when the planner knows
+    pub estimated_bytes: Option<usize>,
+    /// Whether the estimates are exact
+    pub stats_exact: bool,
+    /// What the operator did, when the plan was run
+    #[serde(default)]
+    pub metrics: Option<OperatorMetrics>,
+    /// Inputs of the operator
+    pub children: Vec<PlanNode>,
+}
+
+/// What an operator recorded while its plan ran, where it records it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperatorMetrics {
+    /// Rows the operator produced
+    pub output_rows: Option<usize>,
+    /// CPU time spent in the operator, summed over its partitions
+    pub elapsed_compute_ns: Option<usize>,
+    /// Memory the operator reported holding, summed over its partitions
+    pub memory_bytes: Option<usize>,
+    /// Bytes the operator spilled to disk
+    pub spilled_bytes: Option<usize>,
+}
+
+/// A query's physical plan as a tree and as Graphviz DOT
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraph {
+    /// The root operator, which produces the query's result
+    pub root: PlanNode,
+    /// The same plan as a DOT digraph
+    pub dot: String,
+    /// Query hints applied to the plan, e.g. `BROADCAST(d)`
+    #[serde(default)]
+    pub hints: Vec<String>,
+    /// Milliseconds the query ran for, when it was run to gather `metrics`
+    #[serde(default)]
+    pub execution_time_ms: Option<u64>,
+}
+
+impl PlanGraph {
+    /// Describe `plan` and every operator below it
+    pub(crate) fn from_plan(plan: &Arc<dyn ExecutionPlan>) -> BlazeResult<Self> {
+        let mut next_id = 0;
+        let root = describe(plan, &mut next_id, false)?;
+        let dot = to_dot(&root);
+        Ok(Self { root, dot, hints: Vec::new(), execution_time_ms: None })
+    }
+
+    /// Describe `plan` after it ran for `execution_time_ms`, with the
+    /// metrics of every operator
+    pub(crate) fn from_executed_plan(plan: &Arc<dyn ExecutionPlan>, execution_time_ms: u64) -> BlazeResult<Self> {
+        let mut next_id = 0;
+        let root = describe(plan, &mut next_id, true)?;
+        let dot = to_dot(&root);
+        Ok(Self { root, dot, hints: Vec::new(), execution_time_ms: Some(execution_time_ms) })
+    }
+
+    /// Number of operators in the plan
+    pub fn node_count(&self) -> usize {
+        fn count(node: &PlanNode) -> usize {
+            1 + node.children.iter().map(count).sum::<usize>()
+        }
+        count(&self.root)
+    }
+}
+
+/// Whether `sql` is a query, the only statements `explain_graph` plans
+/// without running them
+pub(crate) fn is_query(sql: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^[\s(]*(SELECT|WITH|VALUES)\b").unwrap());
+    PATTERN.is_match(sql)
+}
+
+fn describe(plan: &Arc<dyn ExecutionPlan>, next_id: &mut usize, executed: bool) -> BlazeResult<PlanNode> {
+    let id = *next_id;
+    *next_id += 1;
+
+    let statistics = plan.statistics()?;
+    let description = displayable(plan.as_ref()).one_line().to_string().trim_end().to_string();
+    let children = plan
+        .children()
+        .into_iter()
+        .map(|child| describe(child, next_id, executed))
+        .collect::<BlazeResult<Vec<_>>>()?;
+
+    Ok(PlanNode {
+        id,
+        name: plan.name().to_string(),
+        description,
+        output_partitions: plan.output_partitioning().partition_count(),
+        estimated_rows: statistics.num_rows.get_value().copied(),
+        estimated_bytes: statistics.total_byte_size.get_value().copied(),
+        stats_exact: matches!(statistics.num_rows, Precision::Exact(_)),
+        metrics: if executed { operator_metrics(plan) } else { None },
+        children,
+    })
+}
+
+/// The metrics `plan` recorded, summed over its partitions
+fn operator_metrics(plan: &Arc<dyn ExecutionPlan>) -> Option<OperatorMetrics> {
+    let metrics = plan.metrics()?.aggregate_by_name();
+    let memory_bytes = metrics
+        .iter()
+        .filter_map(|metric| match metric.value() {
+            MetricValue::CurrentMemoryUsage(gauge) => Some(gauge.value()),
+            MetricValue::Gauge { name, gauge } if name.ends_with("mem_used") => Some(gauge.value()),
+            _ => None,
+        })
+        .max();
+    Some(OperatorMetrics {
+        output_rows: metrics.output_rows(),
+        elapsed_compute_ns: metrics.elapsed_compute(),
+        memory_bytes,
+        spilled_bytes: metrics.spilled_bytes(),
+    })
+}
+
+fn to_dot(root: &PlanNode) -> String {
+    let mut dot = String::from("digraph plan {\n  node [shape=box, fontname=\"Helvetica\"];\n");
+    let mut edges = String::new();
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        let mut label = node.description.clone();
+        if let Some(rows) = node.estimated_rows {
+            let approximate = if node.stats_exact { "" } else { "~" };
+            label.push_str(&format!("\nrows: {}{}", approximate, rows));
+        }
+        if let Some(metrics) = &node.metrics {
+            if let Some(rows) = metrics.output_rows {
+                label.push_str(&format!("\nactual rows: {}", rows));
+            }
+            if let Some(ns) = metrics.elapsed_compute_ns {
+                label.push_str(&format!("\ntime: {:.3}ms", ns as f64 / 1_000_000.0));
+            }
+        }
+        let _ = writeln!(dot, "  n{} [label=\"{}\"];", node.id, escape_label(&label));
+        for child in &node.children {
+            let _ = writeln!(edges, "  n{} -> n{};", child.id, node.id);
+        }
+        pending.extend(node.children.iter().rev());
+    }
+    dot.push_str(&edges);
+    dot.push_str("}\n");
+    dot
+}
+
+/// Quote a DOT label, keeping line breaks
+fn escape_label(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: usize, name: &str, children: Vec<PlanNode>) -> PlanNode {
+        PlanNode {
+            id,
+            name: name.to_string(),
+            description: format!("{}: filter=\"a\"", name),
+            output_partitions: 1,
+            estimated_rows: Some(10),
+            estimated_bytes: None,
+            stats_exact: id == 1,
+            metrics: None,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_dot_output() {
+        let root = node(0, "FilterExec", vec![node(1, "MemoryExec", vec![])]);
+        let dot = to_dot(&root);
+        assert!(dot.contains("  n0 [label=\"FilterExec: filter=\\\"a\\\"\\nrows: ~10\"];\n"), "{}", dot);
+        assert!(dot.contains("  n1 [label=\"MemoryExec: filter=\\\"a\\\"\\nrows: 10\"];\n"), "{}", dot);
+        assert!(dot.ends_with("  n1 -> n0;\n}\n"), "{}", dot);
+
+        let mut run = node(0, "MemoryExec", vec![]);
+        run.metrics = Some(OperatorMetrics {
+            output_rows: Some(7),
+            elapsed_compute_ns: Some(1_500_000),
+            memory_bytes: None,
+            spilled_bytes: None,
+        });
+        assert!(to_dot(&run).contains("\\nactual rows: 7\\ntime: 1.500ms\"]"), "{}", to_dot(&run));
+
+        assert!(is_query("  (SELECT 1)"));
+        assert!(is_query("with t AS (SELECT 1) SELECT * FROM t"));
+        assert!(!is_query("CREATE TABLE t AS SELECT 1"));
+    }
+}
@@ -0,0 +1,327 @@
+//! Query plan regression detection
+//!
+//! A `PlanBaseline` records the optimized logical plan of each query of a
+//! workload, normalized so that runs on different days and machines compare
+//! equal. After upgrading DataFusion or the engine,
+//! `BlazeQueryEngine::check_plan_baseline` plans the same queries again and
+//! reports the ones whose plan changed, flagging the changes that usually
+//! cost performance:
+//!
+//! - a different join order over the same tables
+//! - more `Sort` operators
+//! - lost pruning: a scan reading more columns, or with fewer filters pushed
+//!   into it, than before
+//!
+//! Baselines are JSON files, meant to be committed next to the workload and
+//! checked in CI.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlazeResult;
+
+/// Version of the engine recorded in baselines
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Normalized plans of a workload's queries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanBaseline {
+    pub engine_version: String,
+    pub datafusion_version: String,
+    pub recorded_at: DateTime<Utc>,
+    /// Queries by name
+    pub queries: BTreeMap<String, BaselineQuery>,
+}
+
+/// A query and its plan when the baseline was recorded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaselineQuery {
+    pub sql: String,
+    pub plan: String,
+}
+
+impl PlanBaseline {
+    /// Write the baseline as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> BlazeResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Read a baseline written by `save`
+    pub fn load(path: impl AsRef<Path>) -> BlazeResult<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Kinds of plan change worth a closer look
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanRegression {
+    /// The same tables are joined in a different order
+    JoinOrderChanged,
+    /// The plan sorts more often
+    SortAdded,
+    /// A table scan reads more columns or has fewer filters pushed into it
+    PruningLost,
+}
+
+/// A query whose plan differs from the baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanChange {
+    pub query: String,
+    /// Flagged kinds of change; empty when the plan changed otherwise
+    pub regressions: Vec<PlanRegression>,
+    pub baseline_plan: String,
+    /// The plan now, or `None` when the query no longer plans
+    pub current_plan: Option<String>,
+    /// Planning error, when the query no longer plans
+    pub error: Option<String>,
+    /// Line diff of the plans, with `-` for baseline and `+` for current lines
+    pub diff: String,
+}
+
+/// Result of comparing a workload's plans against a baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanRegressionReport {
+    pub baseline_engine_version: String,
+    pub baseline_datafusion_version: String,
+    pub engine_version: String,
+    pub datafusion_version: String,
+    /// Queries whose plan is the same as in the baseline
+    pub unchanged: usize,
+    /// Queries whose plan changed, by name
+    pub changes: Vec<PlanChange>,
+}
+
+impl PlanRegressionReport {
+    /// Whether any query's plan changed
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// Whether any change was flagged as a likely regression
+    pub fn has_regressions(&self) -> bool {
+        self.changes.iter().any(|change| !change.regressions.is_empty() || change.current_plan.is_none())
+    }
+}
+
+/// Normalize a displayed plan: trailing whitespace is dropped and the values
+/// of timestamp and date literals, which `now()` and `CURRENT_DATE` fold
+/// into, are masked
+pub(crate) fn normalize_plan(plan: &str) -> String {
+    static TIMESTAMPS: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r#"(Timestamp(?:Second|Millisecond|Microsecond|Nanosecond)|Date32|Date64)\((?:-?\d+|"[\d-]+")"#,
+        )
+        .unwrap()
+    });
+    plan.lines()
+        .map(|line| TIMESTAMPS.replace_all(line.trim_end(), "$1(<now>").into_owned())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Compare a query's baseline plan with its current one
+pub(crate) fn compare(query: &str, baseline: &str, current: Result<String, String>) -> Option<PlanChange> {
+    let (current_plan, error) = match current {
+        Ok(plan) if plan == baseline => return None,
+        Ok(plan) => (Some(plan), None),
+        Err(error) => (None, Some(error)),
+    };
+    let regressions = current_plan.as_deref().map(|current| regressions(baseline, current)).unwrap_or_default();
+    Some(PlanChange {
+        query: query.to_string(),
+        regressions,
+        diff: diff_lines(baseline, current_plan.as_deref().unwrap_or_default()),
+        baseline_plan: baseline.to_string(),
+        current_plan,
+        error,
+    })
+}
+
+fn regressions(baseline: &str, current: &str) -> Vec<PlanRegression> {
+    let (before, after) = (PlanShape::of(baseline), PlanShape::of(current));
+    let mut found = Vec::new();
+    let mut tables_before = before.join_tables.clone();
+    let mut tables_after = after.join_tables.clone();
+    tables_before.sort();
+    tables_after.sort();
+    if before.join_tables != after.join_tables && tables_before == tables_after {
+        found.push(PlanRegression::JoinOrderChanged);
+    }
+    if after.sorts > before.sorts {
+        found.push(PlanRegression::SortAdded);
+    }
+    let lost_pruning = before.scans.iter().any(|(table, old)| {
+        after.scans.get(table).is_some_and(|new| {
+            new.filters < old.filters
+                || match (old.columns, new.columns) {
+                    (Some(old), Some(new)) => new > old,
+                    (Some(_), None) => true,
+                    _ => false,
+                }
+        })
+    });
+    if lost_pruning {
+        found.push(PlanRegression::PruningLost);
+    }
+    found
+}
+
+/// The parts of a displayed logical plan regressions are judged on
+#[derive(Debug, Default)]
+struct PlanShape {
+    /// Scanned tables under joins, in plan order
+    join_tables: Vec<String>,
+    sorts: usize,
+    scans: BTreeMap<String, ScanShape>,
+}
+
+#[derive(Debug, Default)]
+struct ScanShape {
+    /// Projected columns; `None` when all are read
+    columns: Option<usize>,
+    /// Filters pushed into the scan
+    filters: usize,
+}
+
+impl PlanShape {
+    fn of(plan: &str) -> Self {
+        static SCAN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^TableScan: (\S+)(.*)$").unwrap());
+        static PROJECTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"projection=\[([^\]]*)\]").unwrap());
+        static FILTERS: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(?:partial_filters|full_filters|unsupported_filters)=\[").unwrap());
+
+        let mut shape = PlanShape::default();
+        // Indentation of joins whose inputs are being read
+        let mut joins: Vec<usize> = Vec::new();
+        for line in plan.lines() {
+            let indent = line.len() - line.trim_start().len();
+            let node = line.trim_start();
+            joins.retain(|join| *join < indent);
+            if node.contains(" Join:") || node.starts_with("Join:") || node.starts_with("CrossJoin") {
+                joins.push(indent);
+            } else if node.starts_with("Sort:") {
+                shape.sorts += 1;
+            } else if let Some(captures) = SCAN.captures(node) {
+                let table = captures[1].to_string();
+                if !joins.is_empty() {
+                    shape.join_tables.push(table.clone());
+                }
+                let settings = &captures[2];
+                let scanned = ScanShape {
+                    columns: PROJECTION
+                        .captures(settings)
+                        .map(|columns| columns[1].split(',').filter(|c| !c.trim().is_empty()).count()),
+                    filters: FILTERS
+                        .find_iter(settings)
+                        .map(|found| count_list(&settings[found.end()..]))
+                        .sum(),
+                };
+                shape.scans.insert(table, scanned);
+            }
+        }
+        shape
+    }
+}
+
+/// Number of top-level items of a bracketed list starting after its `[`
+fn count_list(text: &str) -> usize {
+    let mut depth = 0;
+    let mut items = 0;
+    let mut empty = true;
+    for c in text.chars() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' if depth == 0 => break,
+            ']' | ')' => depth -= 1,
+            ',' if depth == 0 => items += 1,
+            c if !c.is_whitespace() => empty = false,
+            _ => {}
+        }
+    }
+    if empty { 0 } else { items + 1 }
+}
+
+/// Line diff from the longest common subsequence of lines
+fn diff_lines(before: &str, after: &str) -> String {
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASELINE: &str = "Sort: total ASC NULLS LAST
+  Aggregate: groupBy=[[a.name]], aggr=[[sum(b.amount) AS total]]
+    Inner Join: a.id = b.id
+      TableScan: a projection=[id, name]
+      TableScan: b projection=[id, amount], partial_filters=[b.amount > Int64(5), b.day = Date32(\"2024-01-01\")]";
+
+    #[test]
+    fn test_flag_regressions() {
+        assert!(compare("q", BASELINE, Ok(BASELINE.to_string())).is_none());
+
+        let reordered = "Sort: total ASC NULLS LAST
+  Sort: a.name ASC NULLS LAST
+    Aggregate: groupBy=[[a.name]], aggr=[[sum(b.amount) AS total]]
+      Inner Join: b.id = a.id
+        TableScan: b projection=[id, amount, day], partial_filters=[b.amount > Int64(5)]
+        TableScan: a projection=[id, name]";
+        let change = compare("q", BASELINE, Ok(reordered.to_string())).unwrap();
+        assert_eq!(
+            change.regressions,
+            vec![PlanRegression::JoinOrderChanged, PlanRegression::SortAdded, PlanRegression::PruningLost]
+        );
+        assert!(change.diff.contains("+   Sort: a.name ASC NULLS LAST"));
+        assert!(change.diff.lines().any(|line| line.starts_with("- ") && line.contains("TableScan: b")));
+
+        let renamed = BASELINE.replace("AS total", "AS sum_amount").replace("Sort: total", "Sort: sum_amount");
+        let change = compare("q", BASELINE, Ok(renamed)).unwrap();
+        assert!(change.regressions.is_empty());
+
+        let failed = compare("q", BASELINE, Err("table 'b' not found".to_string())).unwrap();
+        assert_eq!((failed.current_plan, failed.error.as_deref()), (None, Some("table 'b' not found")));
+    }
+
+    #[test]
+    fn test_normalize_plan() {
+        let plan = "Filter: t.at > TimestampNanosecond(1700000000000000000, Some(\"+00:00\"))   \n  TableScan: t\n";
+        assert_eq!(
+            normalize_plan(plan),
+            "Filter: t.at > TimestampNanosecond(<now>, Some(\"+00:00\"))\n  TableScan: t"
+        );
+        assert_eq!(count_list("a > Int64(1), b IN ([1, 2])] rest"), 2);
+        assert_eq!(count_list("]"), 0);
+    }
+}
@@ -0,0 +1,345 @@
+//! Tables read from a remote PostgreSQL server
+//!
+//! `register_postgres("accounts", "host=db user=reader dbname=app", "billing.accounts")`
+//! reads the remote table's columns once and registers a table that queries
+//! the server on every scan, so operational data can be joined with local
+//! files without copying it first:
+//!
+//! ```sql
+//! SELECT a.plan, SUM(e.amount) FROM events e JOIN accounts a ON e.account_id = a.id GROUP BY 1;
+//! ```
+//!
+//! Scans select only the projected columns and pass the simple comparisons
+//! of the WHERE clause and the LIMIT to the server (see `sql_pushdown`).
+//! Booleans, integers, floating point, text, `bytea`, dates and timestamps
+//! read as the matching Arrow types; `numeric` columns are converted to
+//! `FLOAT64` by the server and any other type to its text form.
+//!
+//! Connections are made without TLS. The connection string is kept for the
+//! scans but only shown with its password removed, and tables read from
+//! PostgreSQL are not saved by `snapshot_to`, so no password is written to
+//! disk.
+
+use std::any::Any;
+use std::sync::{Arc, LazyLock};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use datafusion::arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Date32Array, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array,
+    StringArray, TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use datafusion::catalog::Session;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::{FromSql, Type};
+use tokio_postgres::{Client, NoTls, Row};
+use tracing::warn;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::sql_pushdown::{self, quote_identifier};
+
+/// Rows per batch read from the server
+const BATCH_ROWS: usize = 8192;
+
+/// A table registered with `register_postgres`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostgresTableInfo {
+    /// Table name
+    pub table_name: String,
+    /// Remote table as `schema.table`
+    pub remote_table: String,
+    /// Connection string with any password removed
+    pub connection: String,
+    /// Column names and the Arrow types they are read as
+    pub columns: Vec<(String, String)>,
+}
+
+/// Read the columns of `remote_table`, `schema.table` or `table`, and build
+/// a table querying it
+pub(crate) async fn build_table(
+    name: &str,
+    connection: &str,
+    remote_table: &str,
+) -> BlazeResult<(Arc<PostgresTable>, PostgresTableInfo)> {
+    let (schema_name, table_name) = split_remote_table(remote_table)?;
+    let table_reference = format!("{}.{}", quote_identifier(schema_name), quote_identifier(table_name));
+
+    let client = connect(connection).await?;
+    let statement = client
+        .prepare(&format!("SELECT * FROM {} LIMIT 0", table_reference))
+        .await
+        .map_err(postgres_error)?;
+    let columns: Vec<_> = statement.columns().iter().map(|column| (column.name(), column.type_())).collect();
+
+    let table = PostgresTable::new(connection, &table_reference, &columns);
+    let info = PostgresTableInfo {
+        table_name: name.to_string(),
+        remote_table: format!("{}.{}", schema_name, table_name),
+        connection: redact(connection),
+        columns: table.schema.fields().iter().map(|f| (f.name().clone(), f.data_type().to_string())).collect(),
+    };
+    Ok((Arc::new(table), info))
+}
+
+/// The schema and table of `schema.table`, or of `table` in `public`
+fn split_remote_table(remote_table: &str) -> BlazeResult<(&str, &str)> {
+    let (schema_name, table_name) = match remote_table.split_once('.') {
+        Some((schema_name, table_name)) => (schema_name, table_name),
+        None => ("public", remote_table),
+    };
+    if schema_name.is_empty() || table_name.is_empty() {
+        return Err(invalid_input!("Invalid PostgreSQL table '{}'; expected schema.table", remote_table));
+    }
+    Ok((schema_name, table_name))
+}
+
+/// A remote PostgreSQL table, queried on every scan
+#[derive(Debug)]
+pub(crate) struct PostgresTable {
+    connection: String,
+    /// Subquery selecting the table's columns, cast where needed
+    reference: String,
+    schema: SchemaRef,
+}
+
+impl PostgresTable {
+    /// A table reading `columns`, names and their PostgreSQL types, of the
+    /// already quoted `table_reference`
+    fn new(connection: &str, table_reference: &str, columns: &[(&str, &Type)]) -> Self {
+        let mut fields = Vec::new();
+        let mut selected = Vec::new();
+        for (name, postgres_type) in columns {
+            let (data_type, cast) = column_type(postgres_type);
+            let quoted = quote_identifier(name);
+            selected.push(match cast {
+                Some(cast) => format!("{}::{} AS {}", quoted, cast, quoted),
+                None => quoted,
+            });
+            fields.push(Field::new(*name, data_type, true));
+        }
+
+        Self {
+            connection: connection.to_string(),
+            reference: format!("(SELECT {} FROM {}) AS \"remote\"", selected.join(", "), table_reference),
+            schema: Arc::new(Schema::new(fields)),
+        }
+    }
+
+    /// The query the server runs for a scan
+    fn scan_sql(&self, projection: Option<&Vec<usize>>, filters: &[Expr], limit: Option<usize>) -> Result<String> {
+        sql_pushdown::scan_sql(&self.reference, &self.schema, projection, filters, limit)
+    }
+}
+
+#[async_trait]
+impl TableProvider for PostgresTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(indices) => Arc::new(self.schema.project(indices)?),
+            None => self.schema.clone(),
+        };
+        let sql = self.scan_sql(projection, filters, limit)?;
+        let batches = read_rows(&self.connection, &sql, &schema).await.map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+        MemTable::try_new(schema, vec![batches])?.scan(state, None, &[], None).await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(sql_pushdown::filter_pushdown(filters))
+    }
+}
+
+/// The Arrow type a column of PostgreSQL type `column_type` is read as,
+/// with the type the server casts it to first when it has no direct
+/// equivalent
+fn column_type(column_type: &Type) -> (DataType, Option<&'static str>) {
+    match *column_type {
+        Type::BOOL => (DataType::Boolean, None),
+        Type::INT2 => (DataType::Int16, None),
+        Type::INT4 => (DataType::Int32, None),
+        Type::INT8 => (DataType::Int64, None),
+        Type::FLOAT4 => (DataType::Float32, None),
+        Type::FLOAT8 => (DataType::Float64, None),
+        Type::NUMERIC => (DataType::Float64, Some("float8")),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => (DataType::Utf8, None),
+        Type::BYTEA => (DataType::Binary, None),
+        Type::DATE => (DataType::Date32, None),
+        Type::TIMESTAMP => (DataType::Timestamp(TimeUnit::Microsecond, None), None),
+        Type::TIMESTAMPTZ => (DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), None),
+        _ => (DataType::Utf8, Some("text")),
+    }
+}
+
+/// Run `sql` on the server and read its rows into batches of `schema`
+async fn read_rows(connection: &str, sql: &str, schema: &SchemaRef) -> BlazeResult<Vec<RecordBatch>> {
+    let client = connect(connection).await?;
+    let rows = client.query(sql, &[]).await.map_err(postgres_error)?;
+    rows.chunks(BATCH_ROWS)
+        .map(|rows| {
+            let columns = schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(index, field)| column(rows, index, field.data_type()))
+                .collect::<BlazeResult<Vec<_>>>()?;
+            let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+            Ok(RecordBatch::try_new_with_options(schema.clone(), columns, &options)?)
+        })
+        .collect()
+}
+
+fn column(rows: &[Row], index: usize, data_type: &DataType) -> BlazeResult<ArrayRef> {
+    Ok(match data_type {
+        DataType::Boolean => Arc::new(BooleanArray::from(values::<bool>(rows, index)?)),
+        DataType::Int16 => Arc::new(Int16Array::from(values::<i16>(rows, index)?)),
+        DataType::Int32 => Arc::new(Int32Array::from(values::<i32>(rows, index)?)),
+        DataType::Int64 => Arc::new(Int64Array::from(values::<i64>(rows, index)?)),
+        DataType::Float32 => Arc::new(Float32Array::from(values::<f32>(rows, index)?)),
+        DataType::Float64 => Arc::new(Float64Array::from(values::<f64>(rows, index)?)),
+        DataType::Binary => Arc::new(BinaryArray::from_iter(values::<Vec<u8>>(rows, index)?)),
+        DataType::Date32 => {
+            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+            let days = values::<NaiveDate>(rows, index)?;
+            Arc::new(Date32Array::from_iter(days.into_iter().map(|date| date.map(|date| (date - epoch).num_days() as i32))))
+        }
+        DataType::Timestamp(_, None) => {
+            let times = values::<NaiveDateTime>(rows, index)?;
+            Arc::new(TimestampMicrosecondArray::from_iter(
+                times.into_iter().map(|time| time.map(|time| time.and_utc().timestamp_micros())),
+            ))
+        }
+        DataType::Timestamp(_, Some(time_zone)) => {
+            let times = values::<DateTime<Utc>>(rows, index)?;
+            Arc::new(
+                TimestampMicrosecondArray::from_iter(times.into_iter().map(|time| time.map(|time| time.timestamp_micros())))
+                    .with_timezone(time_zone.clone()),
+            )
+        }
+        _ => Arc::new(StringArray::from(values::<String>(rows, index)?)),
+    })
+}
+
+fn values<'a, T: FromSql<'a>>(rows: &'a [Row], index: usize) -> BlazeResult<Vec<Option<T>>> {
+    rows.iter().map(|row| row.try_get(index).map_err(postgres_error)).collect()
+}
+
+async fn connect(connection: &str) -> BlazeResult<Client> {
+    let (client, driver) = tokio_postgres::connect(connection, NoTls).await.map_err(postgres_error)?;
+    tokio::spawn(async move {
+        if let Err(e) = driver.await {
+            warn!("PostgreSQL connection failed: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+/// A connection string, `key=value` or URL, without its password
+fn redact(connection: &str) -> String {
+    static KEY_VALUE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)(\bpassword\s*=\s*)('(?:[^'\\]|\\.)*'|\S+)").unwrap());
+    static URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\w+://[^:/@]*):[^@/]*@").unwrap());
+    let redacted = KEY_VALUE.replace_all(connection, "${1}***");
+    URL.replace(&redacted, "${1}:***@").into_owned()
+}
+
+fn postgres_error(e: tokio_postgres::Error) -> BlazeError {
+    BlazeError::External(format!("PostgreSQL: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::common::Column;
+    use datafusion::prelude::{col, lit};
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("host=db user=reader password=s3cret dbname=app"), "host=db user=reader password=*** dbname=app");
+        assert_eq!(redact("host=db password = 'a b\\'c' dbname=app"), "host=db password = *** dbname=app");
+        assert_eq!(redact("postgresql://reader:s3cret@db:5432/app"), "postgresql://reader:***@db:5432/app");
+        assert_eq!(redact("postgresql://reader@db/app"), "postgresql://reader@db/app");
+    }
+
+    #[test]
+    fn test_column_type() {
+        assert_eq!(column_type(&Type::INT4), (DataType::Int32, None));
+        assert_eq!(column_type(&Type::NUMERIC), (DataType::Float64, Some("float8")));
+        assert_eq!(column_type(&Type::UUID), (DataType::Utf8, Some("text")));
+        assert_eq!(
+            column_type(&Type::TIMESTAMPTZ).0,
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+    }
+
+    #[test]
+    fn test_split_remote_table() {
+        assert_eq!(split_remote_table("billing.accounts").unwrap(), ("billing", "accounts"));
+        assert_eq!(split_remote_table("accounts").unwrap(), ("public", "accounts"));
+        assert!(split_remote_table("billing.").is_err());
+        assert!(split_remote_table(".accounts").is_err());
+    }
+
+    #[test]
+    fn test_scan_sql() {
+        let table = PostgresTable::new(
+            "host=db",
+            &format!("{}.{}", quote_identifier("Billing"), quote_identifier("accounts")),
+            &[("id", &Type::INT8), ("Plan", &Type::TEXT), ("balance", &Type::NUMERIC), ("say \"hi\"", &Type::UUID)],
+        );
+        let remote = "(SELECT \"id\", \"Plan\", \"balance\"::float8 AS \"balance\", \"say \"\"hi\"\"\"::text AS \"say \"\"hi\"\"\" \
+                      FROM \"Billing\".\"accounts\") AS \"remote\"";
+
+        // Every column, no filters or LIMIT
+        assert_eq!(table.scan_sql(None, &[], None).unwrap(), format!("SELECT * FROM {}", remote));
+
+        // Projection and LIMIT, with column names quoted as written
+        assert_eq!(
+            table.scan_sql(Some(&vec![1, 3]), &[], Some(10)).unwrap(),
+            format!("SELECT \"Plan\", \"say \"\"hi\"\"\" FROM {} LIMIT 10", remote)
+        );
+
+        // Only the row count is needed
+        assert_eq!(table.scan_sql(Some(&vec![]), &[], None).unwrap(), format!("SELECT NULL FROM {}", remote));
+
+        // Filters lose the engine's table qualifier and are ANDed together
+        let plan = Expr::Column(Column::new(Some("accounts"), "Plan"));
+        let filters = [plan.clone().eq(lit("pro")), col("id").between(lit(1), lit(9)).or(col("balance").is_null())];
+        assert_eq!(
+            table.scan_sql(Some(&vec![0]), &filters, Some(5)).unwrap(),
+            format!("SELECT \"id\" FROM {} WHERE ((\"Plan\" = 'pro')) AND (((id BETWEEN 1 AND 9) OR balance IS NULL)) LIMIT 5", remote)
+        );
+
+        // Filters DataFusion might evaluate differently are not pushed down
+        let like = plan.like(lit("p%"));
+        assert_eq!(
+            sql_pushdown::filter_pushdown(&[&filters[0], &like]),
+            vec![TableProviderFilterPushDown::Inexact, TableProviderFilterPushDown::Unsupported]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Prepared statements
+//!
+//! `prepare` parses and plans a query once, keeping its logical plan under a
+//! handle such as `stmt_3f2a9c0d1e4b5a67`. Values are marked in the SQL with
+//! positional parameters:
+//!
+//! ```sql
+//! SELECT region, SUM(amount) FROM orders WHERE day >= $1 AND region = $2 GROUP BY region
+//! ```
+//!
+//! `execute_prepared(handle, params)` binds the values into the stored plan
+//! and runs it, skipping parsing and planning. Values are bound as literals,
+//! never spliced into the SQL text, so a string parameter cannot change the
+//! statement. Each value is cast to the type its parameter was inferred to
+//! have from its use, so `'2024-01-01'` binds to a date parameter and `5` to
+//! a decimal one; a value that does not convert is rejected.
+//!
+//! Tables are read at their latest version when the statement is executed,
+//! not when it was prepared. Only queries can be prepared, and not ones using
+//! `ML.PREDICT`, table functions such as `VECTOR_SEARCH` or time travel,
+//! which are resolved while they are planned.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::{ParamValues, ScalarValue};
+use datafusion::logical_expr::LogicalPlan;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::query_hints::QueryHint;
+
+/// A parameter of a prepared statement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreparedParameter {
+    /// Placeholder in the SQL, e.g. `$1`
+    pub name: String,
+    /// Type values are cast to, if it could be inferred from the parameter's
+    /// use; values of parameters without one are bound as given
+    pub data_type: Option<String>,
+}
+
+/// A statement kept by `prepare`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreparedStatementInfo {
+    /// Handle to execute the statement by
+    pub handle: String,
+    pub sql: String,
+    /// Parameters `$1`, `$2`, ... in order
+    pub parameters: Vec<PreparedParameter>,
+    pub created_at: DateTime<Utc>,
+    /// Times the statement was executed
+    pub executions: u64,
+}
+
+/// A prepared statement's plan, with placeholders for its parameters
+#[derive(Debug, Clone)]
+pub(crate) struct PreparedStatement {
+    pub(crate) info: PreparedStatementInfo,
+    plan: LogicalPlan,
+    hints: Vec<QueryHint>,
+    types: Vec<Option<DataType>>,
+}
+
+impl PreparedStatement {
+    pub(crate) fn new(
+        sql: &str,
+        plan: LogicalPlan,
+        hints: Vec<QueryHint>,
+        created_at: DateTime<Utc>,
+    ) -> BlazeResult<Self> {
+        let types = parameter_types(&plan)?;
+        let parameters = types
+            .iter()
+            .enumerate()
+            .map(|(i, data_type)| PreparedParameter {
+                name: format!("${}", i + 1),
+                data_type: data_type.as_ref().map(DataType::to_string),
+            })
+            .collect();
+        let info = PreparedStatementInfo {
+            handle: format!("stmt_{:016x}", rand::random::<u64>()),
+            sql: sql.to_string(),
+            parameters,
+            created_at,
+            executions: 0,
+        };
+        Ok(Self { info, plan, hints, types })
+    }
+
+    /// The statement with `params` in place of its parameters
+    pub(crate) fn bind(&self, params: &[serde_json::Value]) -> BlazeResult<BoundStatement> {
+        if params.len() != self.types.len() {
+            return Err(invalid_input!(
+                "Prepared statement {} takes {} parameters, got {}",
+                self.info.handle,
+                self.types.len(),
+                params.len()
+            ));
+        }
+        let values = params
+            .iter()
+            .zip(&self.types)
+            .enumerate()
+            .map(|(i, (value, data_type))| {
+                bind_value(value, data_type.as_ref())
+                    .map_err(|e| invalid_input!("Cannot bind parameter ${} of {}: {}", i + 1, self.info.handle, e))
+            })
+            .collect::<BlazeResult<Vec<_>>>()?;
+        let plan = self.plan.clone().with_param_values(ParamValues::List(values))?;
+        Ok(BoundStatement { plan, hints: self.hints.clone() })
+    }
+}
+
+/// A prepared statement with its parameters bound, ready to run
+#[derive(Debug)]
+pub(crate) struct BoundStatement {
+    pub(crate) plan: LogicalPlan,
+    pub(crate) hints: Vec<QueryHint>,
+}
+
+/// Types of the parameters `$1` to `$n` of `plan`, where `n` is the highest
+/// one used
+fn parameter_types(plan: &LogicalPlan) -> BlazeResult<Vec<Option<DataType>>> {
+    let mut positions = HashMap::new();
+    for (name, data_type) in plan.get_parameter_types()? {
+        let position = name
+            .strip_prefix('$')
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| invalid_input!("Use positional parameters $1, $2, ... instead of {}", name))?;
+        positions.insert(position, data_type);
+    }
+    let count = positions.keys().max().copied().unwrap_or(0);
+    Ok((1..=count).map(|n| positions.remove(&n).flatten()).collect())
+}
+
+/// A JSON value as a scalar of `data_type`, or why it cannot be one
+fn bind_value(value: &serde_json::Value, data_type: Option<&DataType>) -> Result<ScalarValue, String> {
+    let scalar = match value {
+        serde_json::Value::Null => match data_type {
+            Some(data_type) => return ScalarValue::try_from(data_type).map_err(|e| e.to_string()),
+            None => ScalarValue::Null,
+        },
+        serde_json::Value::Bool(b) => ScalarValue::Boolean(Some(*b)),
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => ScalarValue::Int64(Some(i)),
+            (None, Some(u)) => ScalarValue::UInt64(Some(u)),
+            _ => ScalarValue::Float64(n.as_f64()),
+        },
+        serde_json::Value::String(s) => ScalarValue::Utf8(Some(s.clone())),
+        other => return Err(format!("{} is not a scalar value", other)),
+    };
+    match data_type {
+        Some(data_type) if scalar.data_type() != *data_type => scalar
+            .cast_to(data_type)
+            .map_err(|_| format!("{} does not convert to {}", value, data_type)),
+        _ => Ok(scalar),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bind_value() {
+        assert_eq!(bind_value(&json!(5), Some(&DataType::Int32)).unwrap(), ScalarValue::Int32(Some(5)));
+        assert_eq!(bind_value(&json!("x' OR '1'='1"), None).unwrap(), ScalarValue::Utf8(Some("x' OR '1'='1".into())));
+        assert_eq!(bind_value(&json!("2024-01-02"), Some(&DataType::Date32)).unwrap(), ScalarValue::Date32(Some(19_724)));
+        assert_eq!(bind_value(&json!(null), Some(&DataType::Int64)).unwrap(), ScalarValue::Int64(None));
+        assert!(bind_value(&json!("abc"), Some(&DataType::Int64)).is_err());
+        assert!(bind_value(&json!([1, 2]), None).is_err());
+    }
+}
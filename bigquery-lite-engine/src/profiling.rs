@@ -0,0 +1,116 @@
+//! CPU profiles of single queries
+//!
+//! With the `profiling` feature, a query run with `QueryOptions::profile`
+//! set is sampled from the moment it is planned until its result is ready.
+//! Two files are written, a pprof protobuf for `go tool pprof` and a
+//! flamegraph SVG, and their paths are returned in `QueryResult::profile`.
+//!
+//! The sampler is process-wide, so only one query is profiled at a time;
+//! other profiled queries wait for it. Work of unprofiled queries that runs
+//! at the same time shows up in the profile too.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+
+/// Where a query's CPU profile was written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryProfile {
+    /// pprof protobuf, for `go tool pprof` or speedscope
+    pub pprof_path: PathBuf,
+    /// Flamegraph SVG
+    pub flamegraph_path: PathBuf,
+    /// Stack samples taken
+    pub samples: u64,
+    /// Time the profiler ran in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Directory profiles are written to when the query does not name one
+pub fn default_profile_dir() -> PathBuf {
+    std::env::temp_dir().join("bqlite-profiles")
+}
+
+#[cfg(feature = "profiling")]
+pub(crate) use enabled::QueryProfiler;
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::Instant;
+
+    use pprof::protos::Message;
+    use pprof::{ProfilerGuard, ProfilerGuardBuilder};
+    use tokio::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    /// Samples taken per second while a query is profiled
+    const PROFILE_FREQUENCY_HZ: i32 = 999;
+
+    /// Held by the query being profiled
+    static PROFILER: Mutex<()> = Mutex::const_new(());
+
+    /// A running profile of one query
+    pub(crate) struct QueryProfiler {
+        guard: ProfilerGuard<'static>,
+        started: Instant,
+        _turn: MutexGuard<'static, ()>,
+    }
+
+    impl QueryProfiler {
+        /// Start sampling, once no other query is profiled
+        pub(crate) async fn start() -> BlazeResult<Self> {
+            let turn = PROFILER.lock().await;
+            let guard = ProfilerGuardBuilder::default()
+                .frequency(PROFILE_FREQUENCY_HZ)
+                .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+                .build()
+                .map_err(profiler_error)?;
+            Ok(Self { guard, started: Instant::now(), _turn: turn })
+        }
+
+        /// Stop sampling and write the profile to `dir` as `<name>.pb` and
+        /// `<name>.svg`
+        pub(crate) fn finish(self, dir: &Path, name: &str) -> BlazeResult<QueryProfile> {
+            let duration_ms = self.started.elapsed().as_millis() as u64;
+            let report = self.guard.report().build().map_err(profiler_error)?;
+            drop(self.guard);
+
+            std::fs::create_dir_all(dir)?;
+            let flamegraph_path = dir.join(format!("{}.svg", name));
+            report.flamegraph(File::create(&flamegraph_path)?).map_err(profiler_error)?;
+            let pprof_path = dir.join(format!("{}.pb", name));
+            let profile = report.pprof().map_err(profiler_error)?;
+            File::create(&pprof_path)?.write_all(&profile.encode_to_vec())?;
+
+            let samples = report.data.values().map(|count| (*count).max(0) as u64).sum();
+            Ok(QueryProfile { pprof_path, flamegraph_path, samples, duration_ms })
+        }
+    }
+
+    fn profiler_error(error: pprof::Error) -> BlazeError {
+        BlazeError::Internal(format!("CPU profiler failed: {}", error))
+    }
+}
+
+/// Stand-in when the engine is built without the `profiling` feature
+#[cfg(not(feature = "profiling"))]
+pub(crate) struct QueryProfiler;
+
+#[cfg(not(feature = "profiling"))]
+impl QueryProfiler {
+    pub(crate) async fn start() -> BlazeResult<Self> {
+        Err(BlazeError::Config(
+            "CPU profiling needs the engine built with the `profiling` feature".to_string(),
+        ))
+    }
+
+    pub(crate) fn finish(self, _dir: &std::path::Path, _name: &str) -> BlazeResult<QueryProfile> {
+        unreachable!("a profiler cannot be started without the profiling feature")
+    }
+}
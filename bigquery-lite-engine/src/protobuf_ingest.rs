@@ -0,0 +1,546 @@
+//! Loading Protocol Buffers messages
+//!
+//! `register_protobuf` decodes serialized messages with a compiled
+//! descriptor set, as written by
+//! `protoc --include_imports --descriptor_set_out=events.desc events.proto`,
+//! and loads them into an in-memory table with a column per field of the
+//! message type:
+//!
+//! - scalars to the Arrow types of the same width and signedness, `bytes`
+//!   to binary and enums to the names of their values
+//! - messages to structs, repeated fields to lists and maps to maps, sorted
+//!   by key
+//! - `google.protobuf.Timestamp` to a UTC timestamp
+//!
+//! Fields that track presence, such as messages and `optional` fields, are
+//! null when unset; other fields read as their default value, as they do in
+//! generated code.
+//!
+//! A file holds either a stream of messages, each prefixed with its length
+//! as a varint as written by `writeDelimitedTo`, or a single message. A
+//! directory is read as all the files in it.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int32Array, Int64Array, ListArray, MapArray,
+    StringArray, StructArray, TimestampMicrosecondArray, UInt32Array, UInt64Array,
+};
+use datafusion::arrow::buffer::{NullBuffer, OffsetBuffer};
+use datafusion::arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use prost_reflect::{DescriptorPool, DynamicMessage, FieldDescriptor, Kind, MapKey, MessageDescriptor, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Rows per loaded batch
+const BATCH_ROWS: usize = 8192;
+
+/// Well-known type read as a timestamp
+const TIMESTAMP: &str = "google.protobuf.Timestamp";
+
+/// How messages are laid out in a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtobufFraming {
+    /// Messages each prefixed with their length as a varint
+    #[default]
+    Delimited,
+    /// The whole file is one message
+    Single,
+}
+
+impl ProtobufFraming {
+    /// Parse a framing name, `delimited` or `single`
+    pub fn parse(name: &str) -> BlazeResult<Self> {
+        match name.to_lowercase().as_str() {
+            "delimited" => Ok(Self::Delimited),
+            "single" => Ok(Self::Single),
+            _ => Err(invalid_input!("Unsupported framing '{}'; expected delimited or single", name)),
+        }
+    }
+}
+
+/// Outcome of loading Protocol Buffers messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtobufLoadReport {
+    /// Table the rows were loaded into
+    pub table_name: String,
+    /// Full name of the decoded message type
+    pub message_type: String,
+    /// Files read, sorted
+    pub files: Vec<String>,
+    pub rows_loaded: usize,
+    /// Column names with the types they were loaded as
+    pub columns: Vec<(String, String)>,
+}
+
+/// The files behind `path`: the file itself, or the files in a directory
+/// other than hidden ones
+pub(crate) fn message_files(path: &Path) -> BlazeResult<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        let hidden = file.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with('.'));
+        if file.is_file() && !hidden {
+            files.push(file);
+        }
+    }
+    if files.is_empty() {
+        return Err(invalid_input!("No files in '{}'", path.display()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The descriptor of `message_type`, e.g. `app.Event`, from the descriptor
+/// set at `path`
+pub(crate) fn message_descriptor(path: &Path, message_type: &str) -> BlazeResult<MessageDescriptor> {
+    let bytes = std::fs::read(path)?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .map_err(|e| invalid_input!("Cannot read descriptor set '{}': {}", path.display(), e))?;
+    pool.get_message_by_name(message_type.trim_start_matches('.')).ok_or_else(|| {
+        let mut names: Vec<_> = pool
+            .all_messages()
+            .filter(|message| !message.is_map_entry())
+            .map(|message| message.full_name().to_string())
+            .collect();
+        names.sort();
+        invalid_input!(
+            "Message type '{}' is not in '{}'; it defines {}",
+            message_type,
+            path.display(),
+            names.join(", ")
+        )
+    })
+}
+
+/// Decode the messages in `files` into batches
+pub(crate) fn read_messages(
+    descriptor: &MessageDescriptor,
+    files: &[PathBuf],
+    framing: ProtobufFraming,
+) -> BlazeResult<(SchemaRef, Vec<RecordBatch>)> {
+    let schema = arrow_schema(descriptor)?;
+    let mut batches = Vec::new();
+    for file in files {
+        let data = std::fs::read(file)?;
+        let messages = decode_messages(descriptor, &data, framing)
+            .map_err(|e| invalid_input!("Cannot read '{}': {}", file.display(), e))?;
+        for messages in messages.chunks(BATCH_ROWS) {
+            batches.push(to_batch(&schema, descriptor, messages)?);
+        }
+    }
+    Ok((schema, batches))
+}
+
+/// Split `data` into messages and decode them
+pub(crate) fn decode_messages(
+    descriptor: &MessageDescriptor,
+    data: &[u8],
+    framing: ProtobufFraming,
+) -> BlazeResult<Vec<DynamicMessage>> {
+    let decode = |bytes: &[u8], index: usize| {
+        DynamicMessage::decode(descriptor.clone(), bytes)
+            .map_err(|e| invalid_input!("Cannot decode message {} as {}: {}", index, descriptor.full_name(), e))
+    };
+    match framing {
+        ProtobufFraming::Single => Ok(vec![decode(data, 0)?]),
+        ProtobufFraming::Delimited => {
+            let mut messages = Vec::new();
+            let mut remaining = data;
+            while !remaining.is_empty() {
+                let index = messages.len();
+                let length = prost::encoding::decode_varint(&mut remaining)
+                    .map_err(|e| invalid_input!("Cannot read the length of message {}: {}", index, e))?;
+                let length = usize::try_from(length)
+                    .ok()
+                    .filter(|length| *length <= remaining.len())
+                    .ok_or_else(|| {
+                        invalid_input!("Message {} is truncated: {} bytes expected, {} left", index, length, remaining.len())
+                    })?;
+                let (message, rest) = remaining.split_at(length);
+                messages.push(decode(message, index)?);
+                remaining = rest;
+            }
+            Ok(messages)
+        }
+    }
+}
+
+/// The Arrow schema of `message`'s fields
+fn arrow_schema(message: &MessageDescriptor) -> BlazeResult<SchemaRef> {
+    let fields = message_fields(message, &mut vec![message.full_name().to_string()])?;
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// `messages` holds the types being mapped, to reject recursive types
+fn message_fields(message: &MessageDescriptor, messages: &mut Vec<String>) -> BlazeResult<Fields> {
+    let fields = message.fields().map(|field| arrow_field(&field, messages)).collect::<BlazeResult<Vec<_>>>()?;
+    Ok(Fields::from(fields))
+}
+
+fn arrow_field(field: &FieldDescriptor, messages: &mut Vec<String>) -> BlazeResult<Field> {
+    let data_type = match field.kind() {
+        Kind::Message(entry) if field.is_map() => {
+            let key = kind_type(field.name(), &entry.map_entry_key_field().kind(), messages)?;
+            let value = kind_type(field.name(), &entry.map_entry_value_field().kind(), messages)?;
+            let entries = Fields::from(vec![Field::new("key", key, false), Field::new("value", value, false)]);
+            DataType::Map(Arc::new(Field::new("entries", DataType::Struct(entries), false)), false)
+        }
+        kind if field.is_list() => {
+            DataType::List(Arc::new(Field::new("item", kind_type(field.name(), &kind, messages)?, false)))
+        }
+        kind => kind_type(field.name(), &kind, messages)?,
+    };
+    let nullable = !field.is_list() && !field.is_map() && field.supports_presence();
+    Ok(Field::new(field.name(), data_type, nullable))
+}
+
+fn kind_type(name: &str, kind: &Kind, messages: &mut Vec<String>) -> BlazeResult<DataType> {
+    Ok(match kind {
+        Kind::Double => DataType::Float64,
+        Kind::Float => DataType::Float32,
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => DataType::Int32,
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => DataType::Int64,
+        Kind::Uint32 | Kind::Fixed32 => DataType::UInt32,
+        Kind::Uint64 | Kind::Fixed64 => DataType::UInt64,
+        Kind::Bool => DataType::Boolean,
+        Kind::String | Kind::Enum(_) => DataType::Utf8,
+        Kind::Bytes => DataType::Binary,
+        Kind::Message(message) if message.full_name() == TIMESTAMP => {
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        }
+        Kind::Message(message) => {
+            if messages.iter().any(|mapped| mapped == message.full_name()) {
+                return Err(invalid_input!("Protobuf field '{}' has recursive type '{}'", name, message.full_name()));
+            }
+            messages.push(message.full_name().to_string());
+            let fields = message_fields(message, messages);
+            messages.pop();
+            DataType::Struct(fields?)
+        }
+    })
+}
+
+fn to_batch(schema: &SchemaRef, descriptor: &MessageDescriptor, messages: &[DynamicMessage]) -> BlazeResult<RecordBatch> {
+    let messages: Vec<_> = messages.iter().map(Some).collect();
+    let columns = message_columns(descriptor, schema.fields(), &messages)?;
+    let options = RecordBatchOptions::new().with_row_count(Some(messages.len()));
+    Ok(RecordBatch::try_new_with_options(schema.clone(), columns, &options)?)
+}
+
+/// The columns of messages' fields; fields of null messages are null, as
+/// are unset fields that track presence
+fn message_columns(
+    descriptor: &MessageDescriptor,
+    fields: &Fields,
+    messages: &[Option<&DynamicMessage>],
+) -> BlazeResult<Vec<ArrayRef>> {
+    descriptor
+        .fields()
+        .zip(fields.iter())
+        .map(|(field, arrow_field)| {
+            let values: Vec<_> = messages
+                .iter()
+                .map(|message| {
+                    message
+                        .filter(|message| !field.supports_presence() || message.has_field(&field))
+                        .map(|message| message.get_field(&field))
+                })
+                .collect();
+            let values: Vec<_> = values.iter().map(|value| value.as_deref()).collect();
+            field_array(&field, arrow_field.data_type(), &values)
+        })
+        .collect()
+}
+
+fn unexpected(data_type: &DataType, value: &Value) -> BlazeError {
+    BlazeError::SchemaMismatch(format!("expected a value for {}, got {:?}", data_type, value))
+}
+
+/// Convert each present value with `convert`, which returns `None` for a
+/// value of the wrong kind
+fn convert<'a, T>(
+    data_type: &DataType,
+    values: &[Option<&'a Value>],
+    convert: impl Fn(&'a Value) -> Option<T>,
+) -> BlazeResult<Vec<Option<T>>> {
+    values
+        .iter()
+        .map(|value| value.map(|value| convert(value).ok_or_else(|| unexpected(data_type, value))).transpose())
+        .collect()
+}
+
+/// Validity of `values`; `None` when none are null
+fn validity<T>(values: &[Option<T>]) -> Option<NullBuffer> {
+    let nulls = NullBuffer::from(values.iter().map(Option::is_some).collect::<Vec<_>>());
+    (nulls.null_count() > 0).then_some(nulls)
+}
+
+fn key_value(key: &MapKey) -> Value {
+    match key {
+        MapKey::Bool(b) => Value::Bool(*b),
+        MapKey::I32(i) => Value::I32(*i),
+        MapKey::I64(i) => Value::I64(*i),
+        MapKey::U32(u) => Value::U32(*u),
+        MapKey::U64(u) => Value::U64(*u),
+        MapKey::String(s) => Value::String(s.clone()),
+    }
+}
+
+fn field_array(field: &FieldDescriptor, data_type: &DataType, values: &[Option<&Value>]) -> BlazeResult<ArrayRef> {
+    match data_type {
+        DataType::List(item) if field.is_list() => {
+            let lists = convert(data_type, values, |value| value.as_list())?;
+            let items: Vec<_> = lists.iter().flatten().flat_map(|items| items.iter().map(Some)).collect();
+            let lengths = lists.iter().map(|items| items.map_or(0, <[Value]>::len));
+            Ok(Arc::new(ListArray::try_new(
+                item.clone(),
+                OffsetBuffer::from_lengths(lengths),
+                kind_array(&field.kind(), item.data_type(), &items)?,
+                validity(values),
+            )?))
+        }
+        DataType::Map(entries, sorted) if field.is_map() => {
+            let (Kind::Message(entry), DataType::Struct(entry_fields)) = (field.kind(), entries.data_type()) else {
+                return Err(BlazeError::Internal(format!("Map field '{}' has no entry type", field.name())));
+            };
+            let maps = convert(data_type, values, |value| {
+                value.as_map().map(|map| {
+                    let mut map: Vec<_> = map.iter().collect();
+                    map.sort_by_key(|(key, _)| *key);
+                    map
+                })
+            })?;
+            let keys: Vec<_> = maps.iter().flatten().flatten().map(|(key, _)| key_value(key)).collect();
+            let keys: Vec<_> = keys.iter().map(Some).collect();
+            let map_values: Vec<_> = maps.iter().flatten().flatten().map(|(_, value)| Some(*value)).collect();
+            let entries_array = StructArray::try_new(
+                entry_fields.clone(),
+                vec![
+                    kind_array(&entry.map_entry_key_field().kind(), entry_fields[0].data_type(), &keys)?,
+                    kind_array(&entry.map_entry_value_field().kind(), entry_fields[1].data_type(), &map_values)?,
+                ],
+                None,
+            )?;
+            let lengths = maps.iter().map(|map| map.as_ref().map_or(0, Vec::len));
+            Ok(Arc::new(MapArray::try_new(
+                entries.clone(),
+                OffsetBuffer::from_lengths(lengths),
+                entries_array,
+                validity(values),
+                *sorted,
+            )?))
+        }
+        _ => kind_array(&field.kind(), data_type, values),
+    }
+}
+
+/// Microseconds since the epoch of a `google.protobuf.Timestamp`
+fn timestamp_micros(message: &DynamicMessage) -> i64 {
+    let seconds = message.get_field_by_name("seconds").and_then(|value| value.as_i64()).unwrap_or(0);
+    let nanos = message.get_field_by_name("nanos").and_then(|value| value.as_i32()).unwrap_or(0);
+    seconds * 1_000_000 + i64::from(nanos) / 1_000
+}
+
+fn kind_array(kind: &Kind, data_type: &DataType, values: &[Option<&Value>]) -> BlazeResult<ArrayRef> {
+    Ok(match (kind, data_type) {
+        (_, DataType::Boolean) => Arc::new(BooleanArray::from(convert(data_type, values, Value::as_bool)?)),
+        (_, DataType::Int32) => Arc::new(Int32Array::from(convert(data_type, values, Value::as_i32)?)),
+        (_, DataType::Int64) => Arc::new(Int64Array::from(convert(data_type, values, Value::as_i64)?)),
+        (_, DataType::UInt32) => Arc::new(UInt32Array::from(convert(data_type, values, Value::as_u32)?)),
+        (_, DataType::UInt64) => Arc::new(UInt64Array::from(convert(data_type, values, Value::as_u64)?)),
+        (_, DataType::Float32) => Arc::new(Float32Array::from(convert(data_type, values, Value::as_f32)?)),
+        (_, DataType::Float64) => Arc::new(Float64Array::from(convert(data_type, values, Value::as_f64)?)),
+        (Kind::Enum(enumeration), DataType::Utf8) => {
+            // Numbers the descriptor does not know are kept as numbers
+            let names = convert(data_type, values, |value| {
+                value.as_enum_number().map(|number| match enumeration.get_value(number) {
+                    Some(enum_value) => enum_value.name().to_string(),
+                    None => number.to_string(),
+                })
+            })?;
+            Arc::new(StringArray::from(names))
+        }
+        (_, DataType::Utf8) => Arc::new(StringArray::from(convert(data_type, values, |value| value.as_str())?)),
+        (_, DataType::Binary) => {
+            Arc::new(BinaryArray::from_iter(convert(data_type, values, |value| value.as_bytes().map(|bytes| &bytes[..]))?))
+        }
+        (_, DataType::Timestamp(_, time_zone)) => {
+            let times = convert(data_type, values, |value| value.as_message().map(timestamp_micros))?;
+            Arc::new(TimestampMicrosecondArray::from(times).with_timezone_opt(time_zone.clone()))
+        }
+        (Kind::Message(message), DataType::Struct(fields)) => {
+            let messages = convert(data_type, values, |value| value.as_message())?;
+            Arc::new(StructArray::try_new(
+                fields.clone(),
+                message_columns(message, fields, &messages)?,
+                validity(values),
+            )?)
+        }
+        (_, other) => return Err(BlazeError::Internal(format!("Cannot load Protobuf values as {}", other))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use datafusion::arrow::array::{Array, AsArray};
+    use datafusion::arrow::datatypes::{Int64Type, TimestampMicrosecondType};
+    use prost::Message;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+        FileDescriptorSet, MessageOptions,
+    };
+
+    use super::*;
+
+    fn field(name: &str, number: i32, kind: Type, label: Label, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(kind as i32),
+            type_name: type_name.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    fn message(name: &str, fields: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto { name: Some(name.to_string()), field: fields, ..Default::default() }
+    }
+
+    /// `app.Event` with enum, nested, repeated, map and timestamp fields,
+    /// and the recursive `app.Node`
+    fn descriptor_pool() -> DescriptorPool {
+        let timestamp = FileDescriptorProto {
+            name: Some("google/protobuf/timestamp.proto".to_string()),
+            package: Some("google.protobuf".to_string()),
+            message_type: vec![message(
+                "Timestamp",
+                vec![
+                    field("seconds", 1, Type::Int64, Label::Optional, None),
+                    field("nanos", 2, Type::Int32, Label::Optional, None),
+                ],
+            )],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        };
+        let mut event = message(
+            "Event",
+            vec![
+                field("id", 1, Type::Int64, Label::Optional, None),
+                field("kind", 2, Type::Enum, Label::Optional, Some(".app.Kind")),
+                field("origin", 3, Type::Message, Label::Optional, Some(".app.Point")),
+                field("tags", 4, Type::String, Label::Repeated, None),
+                field("counts", 5, Type::Message, Label::Repeated, Some(".app.Event.CountsEntry")),
+                field("at", 6, Type::Message, Label::Optional, Some(".google.protobuf.Timestamp")),
+            ],
+        );
+        event.nested_type.push(DescriptorProto {
+            options: Some(MessageOptions { map_entry: Some(true), ..Default::default() }),
+            ..message(
+                "CountsEntry",
+                vec![
+                    field("key", 1, Type::String, Label::Optional, None),
+                    field("value", 2, Type::Int64, Label::Optional, None),
+                ],
+            )
+        });
+        let kind = EnumDescriptorProto {
+            name: Some("Kind".to_string()),
+            value: ["KIND_UNKNOWN", "KIND_CLICK"]
+                .iter()
+                .zip(0..)
+                .map(|(name, number)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(number),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let app = FileDescriptorProto {
+            name: Some("app.proto".to_string()),
+            package: Some("app".to_string()),
+            dependency: vec!["google/protobuf/timestamp.proto".to_string()],
+            message_type: vec![
+                event,
+                message("Point", vec![field("x", 1, Type::Double, Label::Optional, None)]),
+                message("Node", vec![field("next", 1, Type::Message, Label::Optional, Some(".app.Node"))]),
+            ],
+            enum_type: vec![kind],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        };
+        DescriptorPool::from_file_descriptor_set(FileDescriptorSet { file: vec![timestamp, app] }).unwrap()
+    }
+
+    #[test]
+    fn test_map_schema() {
+        let pool = descriptor_pool();
+        let schema = arrow_schema(&pool.get_message_by_name("app.Event").unwrap()).unwrap();
+        let types: Vec<_> = schema.fields().iter().map(|f| (f.data_type().to_string(), f.is_nullable())).collect();
+        assert_eq!(types[0], ("Int64".to_string(), false));
+        assert_eq!(types[1], ("Utf8".to_string(), false));
+        assert!(matches!(schema.field(2).data_type(), DataType::Struct(_)));
+        assert!(schema.field(2).is_nullable());
+        assert!(matches!(schema.field(3).data_type(), DataType::List(_)));
+        assert!(matches!(schema.field(4).data_type(), DataType::Map(_, _)));
+        assert_eq!(types[5], ("Timestamp(Microsecond, Some(\"UTC\"))".to_string(), true));
+
+        let error = arrow_schema(&pool.get_message_by_name("app.Node").unwrap()).unwrap_err();
+        assert!(error.to_string().contains("recursive"), "{}", error);
+    }
+
+    #[test]
+    fn test_decode_delimited_messages() {
+        let pool = descriptor_pool();
+        let descriptor = pool.get_message_by_name("app.Event").unwrap();
+        let mut at = DynamicMessage::new(pool.get_message_by_name(TIMESTAMP).unwrap());
+        at.set_field_by_name("seconds", Value::I64(1_700_000_000));
+        at.set_field_by_name("nanos", Value::I32(5_000_000));
+        let mut event = DynamicMessage::new(descriptor.clone());
+        event.set_field_by_name("id", Value::I64(7));
+        event.set_field_by_name("kind", Value::EnumNumber(1));
+        event.set_field_by_name("tags", Value::List(vec![Value::String("a".into()), Value::String("b".into())]));
+        event.set_field_by_name(
+            "counts",
+            Value::Map(HashMap::from([
+                (MapKey::String("y".into()), Value::I64(2)),
+                (MapKey::String("x".into()), Value::I64(1)),
+            ])),
+        );
+        event.set_field_by_name("at", Value::Message(at));
+        let mut data = event.encode_length_delimited_to_vec();
+        data.extend(DynamicMessage::new(descriptor.clone()).encode_length_delimited_to_vec());
+
+        let messages = decode_messages(&descriptor, &data, ProtobufFraming::Delimited).unwrap();
+        assert_eq!(messages.len(), 2);
+        let batch = to_batch(&arrow_schema(&descriptor).unwrap(), &descriptor, &messages).unwrap();
+        assert_eq!(batch.column(0).as_primitive::<Int64Type>().values().to_vec(), vec![7, 0]);
+        let kinds = batch.column(1).as_string::<i32>();
+        assert_eq!((kinds.value(0), kinds.value(1)), ("KIND_CLICK", "KIND_UNKNOWN"));
+        assert_eq!(batch.column(2).null_count(), 2);
+        assert_eq!(batch.column(3).as_list::<i32>().value_length(1), 0);
+        let counts = batch.column(4).as_map();
+        assert_eq!(counts.keys().as_string::<i32>().iter().collect::<Vec<_>>(), vec![Some("x"), Some("y")]);
+        let at = batch.column(5).as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(at.value(0), 1_700_000_000_005_000);
+        assert!(at.is_null(1));
+
+        let error = decode_messages(&descriptor, &data[..3], ProtobufFraming::Delimited).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{}", error);
+        let single = decode_messages(&descriptor, &event.encode_to_vec(), ProtobufFraming::Single).unwrap();
+        assert_eq!(single[0], event);
+    }
+}
@@ -1,14 +1,53 @@
 //! Python FFI bindings for BlazeQueryEngine
 
-use std::collections::HashMap;
+// pyo3 0.20's #[pymethods] expansion trips this lint on newer toolchains
+#![allow(non_local_definitions)]
+
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, OnceLock};
 
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::StreamExt;
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyLong, PyTuple, PyType};
 use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
 
-use crate::engine::{BlazeQueryEngine, QueryResult, EngineStats, EngineConfig};
+use crate::admission::QueryPriority;
+use crate::assertions::Assertion;
+#[cfg(feature = "bigquery")]
+use crate::bigquery_ingest::BigQueryReadOptions;
+use crate::checksums::ChecksumMode;
+use crate::clock::Clock;
+use crate::compression::PayloadCompression;
+use crate::csv_ingest::{parse_type_name, BadRowPolicy, CsvIngestOptions};
+use crate::datagen::{self, ColumnSpec, DatasetSpec};
+use crate::engine::{BlazeQueryEngine, EngineConfig, EngineConfigUpdate, QueryOptions, QueryResult};
 use crate::error::{BlazeError, BlazeResult, IntoPyResult};
+use crate::file_tables::{CsvTableOptions, DataFormat, JsonTableOptions};
+use crate::flight_server::FlightServer;
+use crate::flight_tables::FlightSource;
+#[cfg(feature = "iceberg")]
+use crate::iceberg_tables::IcebergVersion;
+#[cfg(feature = "object_store")]
+use crate::object_stores::GcsCredentials;
+use crate::parquet_sink::ParquetSinkOptions;
+use crate::result_export::{CsvExportOptions, JsonExportOptions, ParquetExportOptions};
+use crate::plan_regressions::PlanBaseline;
+use crate::profiling::QueryProfile;
+use crate::query_history::{QueryHistoryFilter, QueryStatus};
+use crate::protobuf_ingest::ProtobufFraming;
+#[cfg(feature = "kafka")]
+use crate::kafka::{KafkaMessageFormat, KafkaSource, KafkaSourceConfig, StartOffset};
+use crate::registry::EngineRegistry;
+use crate::serializers;
+use crate::shutdown::ShutdownOptions;
+use crate::snapshots::SnapshotInfo;
+use crate::utils;
+use crate::workload::ResourceGroup;
 
 /// Global shared Tokio runtime for all Python bindings
 static GLOBAL_RUNTIME: OnceLock<Runtime> = OnceLock::new();
@@ -20,12 +59,51 @@ fn get_runtime() -> &'static Runtime {
     })
 }
 
+/// Named engines handed out by `get_engine`
+static GLOBAL_REGISTRY: OnceLock<EngineRegistry> = OnceLock::new();
+
+/// Get or initialize the engine registry. Its engines are configured like
+/// `BlazeQueryEngine()` and share that configuration's memory limit.
+fn get_registry() -> PyResult<&'static EngineRegistry> {
+    if let Some(registry) = GLOBAL_REGISTRY.get() {
+        return Ok(registry);
+    }
+    let config = EngineConfig::load(None).into_py_result()?;
+    Ok(GLOBAL_REGISTRY.get_or_init(|| {
+        EngineRegistry::with_default_config(config.memory_limit_bytes, config)
+    }))
+}
+
 /// Python wrapper for BlazeQueryEngine
 #[pyclass(name = "BlazeQueryEngine")]
 pub struct PyBlazeQueryEngine {
     engine: Arc<BlazeQueryEngine>,
 }
 
+/// Iterator, sync or async, over a query's result as pyarrow RecordBatches.
+/// The query starts on the first `__next__` or `__anext__` and each batch is
+/// produced only when asked for, so a slow consumer holds back execution.
+#[pyclass(name = "RecordBatchStream")]
+pub struct PyRecordBatchStream {
+    engine: Arc<BlazeQueryEngine>,
+    sql: String,
+    options: QueryOptions,
+    stream: Arc<Mutex<Option<SendableRecordBatchStream>>>,
+}
+
+/// A Kafka topic being appended to a table in the background
+#[cfg(feature = "kafka")]
+#[pyclass(name = "KafkaSource")]
+pub struct PyKafkaSource {
+    source: parking_lot::Mutex<Option<KafkaSource>>,
+}
+
+/// An Arrow Flight service writing uploads to the engine's tables
+#[pyclass(name = "FlightServer")]
+pub struct PyFlightServer {
+    server: parking_lot::Mutex<Option<FlightServer>>,
+}
+
 /// Python wrapper for QueryResult
 #[pyclass(name = "QueryResult")]
 #[derive(Clone)]
@@ -36,11 +114,37 @@ pub struct PyQueryResult {
     pub execution_time_ms: u64,
     #[pyo3(get)]
     pub memory_used_bytes: u64,
+    /// Bytes of the result rows in Arrow memory
+    #[pyo3(get)]
+    pub result_bytes: u64,
     #[pyo3(get)]
     pub engine: String,
-    // Store data as JSON string for simplicity
-    pub data_json: String,
+    /// Rows serialized as JSON, compressed with `compression` if set
+    payload: Vec<u8>,
+    compression: Option<PayloadCompression>,
     query_plan: Option<String>,
+    profile: Option<QueryProfile>,
+    /// Anonymous table holding the result, with `cache_result=True`
+    #[pyo3(get)]
+    pub result_table: Option<String>,
+    /// Checksum of the rows, with `checksum` set
+    #[pyo3(get)]
+    pub checksum: Option<String>,
+    /// Rows written by a `COPY ... TO` or `EXPORT DATA` statement
+    #[pyo3(get)]
+    pub rows_written: Option<u64>,
+    /// Bytes written by a `COPY ... TO` or `EXPORT DATA` statement
+    #[pyo3(get)]
+    pub bytes_written: Option<u64>,
+    /// Whether the result was served from the result cache
+    #[pyo3(get)]
+    pub cache_hit: bool,
+    /// ID the query ran under
+    #[pyo3(get)]
+    pub query_id: String,
+    /// Token of the next page for `fetch_results_sync`, with `max_rows`
+    #[pyo3(get)]
+    pub page_token: Option<String>,
 }
 
 /// Python wrapper for EngineStats
@@ -55,87 +159,1982 @@ pub struct PyEngineStats {
     pub peak_memory_bytes: u64,
     #[pyo3(get)]
     pub registered_tables: usize,
+    /// Tables evicted under memory pressure
+    #[pyo3(get)]
+    pub evicted_tables: u64,
+    /// Object cache usage as a dict, or None without `object_cache_dir`
+    #[pyo3(get)]
+    pub object_cache: Option<PyObject>,
+    /// Result cache usage as a dict with its `hits` and `misses`
+    #[pyo3(get)]
+    pub result_cache: PyObject,
+    /// Plan cache usage as a dict with its `hits` and `misses`
+    #[pyo3(get)]
+    pub plan_cache: PyObject,
+    /// Running and queued queries as a dict, from admission control
+    #[pyo3(get)]
+    pub admission: PyObject,
+    /// Use of the slot pool as a dict, including `total_slot_ms`
+    #[pyo3(get)]
+    pub slots: PyObject,
+}
+
+/// Python wrapper for SnapshotInfo
+#[pyclass(name = "SnapshotInfo")]
+#[derive(Clone)]
+pub struct PySnapshotInfo {
+    #[pyo3(get)]
+    pub table_name: String,
+    #[pyo3(get)]
+    pub snapshot_id: u64,
+    /// Commit time in milliseconds since the Unix epoch
+    #[pyo3(get)]
+    pub committed_at_ms: i64,
+    #[pyo3(get)]
+    pub num_rows: usize,
+}
+
+impl From<SnapshotInfo> for PySnapshotInfo {
+    fn from(info: SnapshotInfo) -> Self {
+        Self {
+            table_name: info.table_name,
+            snapshot_id: info.snapshot_id,
+            committed_at_ms: info.committed_at.timestamp_millis(),
+            num_rows: info.num_rows,
+        }
+    }
 }
 
 #[pymethods]
 impl PyBlazeQueryEngine {
-    /// Create a new BlazeQueryEngine instance
+    /// Create a new BlazeQueryEngine instance configured from the TOML file
+    /// at `config_path` (or `BQLITE_ENGINE_CONFIG`) and `BQLITE_ENGINE_*`
+    /// environment variables
     #[new]
-    fn new() -> PyResult<Self> {
+    #[pyo3(signature = (config_path=None))]
+    fn new(config_path: Option<String>) -> PyResult<Self> {
+        let config = EngineConfig::load(config_path.as_deref().map(std::path::Path::new)).into_py_result()?;
+
         // Use shared global runtime
         let rt = get_runtime();
-        let engine = rt.block_on(async {
-            BlazeQueryEngine::new().await.map_err(|e| PyErr::from(e))
+        let engine = rt.block_on(async {
+            BlazeQueryEngine::with_config(config).await.into_py_result()
+        })?;
+        
+        Ok(PyBlazeQueryEngine {
+            engine: Arc::new(engine),
+        })
+    }
+
+    /// Execute a SQL query synchronously (simplified version), optionally in
+    /// a resource group given directly or as a `resource_group` label. With
+    /// `profile=True` a CPU profile is written to `profile_dir` and described
+    /// by the result's `profile`. With `cache_result=True` the result is kept
+    /// as the table named by the result's `result_table`. With `compression`
+    /// set to `zstd` or `gzip` the rows are kept compressed; `payload` holds
+    /// the compressed bytes for sending on as they are. With `checksum` set
+    /// to `ordered` or `unordered` the result's `checksum` identifies its rows.
+    /// The query runs under `query_id`, or a generated ID, which another
+    /// thread can pass to `cancel_query_sync`. `timeout_ms` overrides the
+    /// engine's `query_timeout_ms`; a query running longer raises TimeoutError.
+    /// With `max_rows` only the first page of rows is returned and the
+    /// result's `page_token` leads to the next with `fetch_results_sync`.
+    /// With `session_id` from `create_session_sync` the statement runs with
+    /// the session's settings, and a `SET` changes them for the session only.
+    /// With `priority="batch"` the query waits for admission behind every
+    /// `interactive` one, the default.
+    #[pyo3(signature = (sql, resource_group=None, labels=None, profile=false, profile_dir=None, cache_result=false, compression=None, checksum=None, query_id=None, timeout_ms=None, max_rows=None, session_id=None, priority=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn execute_query_sync(
+        &self,
+        py: Python,
+        sql: String,
+        resource_group: Option<String>,
+        labels: Option<HashMap<String, String>>,
+        profile: bool,
+        profile_dir: Option<String>,
+        cache_result: bool,
+        compression: Option<String>,
+        checksum: Option<String>,
+        query_id: Option<String>,
+        timeout_ms: Option<u64>,
+        max_rows: Option<usize>,
+        session_id: Option<String>,
+        priority: Option<String>,
+    ) -> PyResult<PyQueryResult> {
+        let compression = compression.as_deref().map(PayloadCompression::parse).transpose().into_py_result()?;
+        let checksum = checksum.as_deref().map(ChecksumMode::parse).transpose().into_py_result()?;
+        let priority = priority.as_deref().map(QueryPriority::parse).transpose().into_py_result()?.unwrap_or_default();
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = QueryOptions {
+            resource_group,
+            labels: labels.unwrap_or_default(),
+            profile,
+            profile_dir: profile_dir.map(Into::into),
+            cache_result,
+            checksum,
+            query_id,
+            timeout_ms,
+            max_rows,
+            session_id,
+            priority,
+        };
+        
+        // Released so other threads can cancel the query meanwhile
+        let result = py.allow_threads(|| rt.block_on(async move {
+            engine.execute_query_with_options(&sql, &options).await.into_py_result()
+        }))?;
+        
+        PyQueryResult::from_result(result, compression)
+    }
+
+    /// Parse and plan a query once synchronously, returning a dict with its
+    /// `handle` and `parameters`. Mark values with `$1`, `$2`, ... and pass
+    /// them to `execute_prepared_sync`.
+    fn prepare_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.prepare(&sql).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Run a prepared statement synchronously with `params` bound to its
+    /// parameters in order. Values are bound as literals of the parameters'
+    /// types, never spliced into the SQL. `query_id`, `timeout_ms`,
+    /// `max_rows` and `session_id` work as for `execute_query_sync`.
+    #[pyo3(signature = (handle, params=None, query_id=None, timeout_ms=None, max_rows=None, session_id=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn execute_prepared_sync(
+        &self,
+        py: Python,
+        handle: String,
+        params: Option<&PyList>,
+        query_id: Option<String>,
+        timeout_ms: Option<u64>,
+        max_rows: Option<usize>,
+        session_id: Option<String>,
+    ) -> PyResult<PyQueryResult> {
+        let params = match params {
+            Some(params) => params.iter().map(python_to_json_value).collect::<PyResult<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = QueryOptions { query_id, timeout_ms, max_rows, session_id, ..Default::default() };
+
+        let result = py.allow_threads(|| rt.block_on(async move {
+            engine.execute_prepared_with_options(&handle, &params, &options).await.into_py_result()
+        }))?;
+
+        PyQueryResult::from_result(result, None)
+    }
+
+    /// Prepared statements as a list of dicts, oldest first
+    fn list_prepared_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let statements = rt.block_on(async move {
+            engine.list_prepared().await
+        });
+
+        to_python_object(py, &statements)
+    }
+
+    /// Drop a prepared statement synchronously; returns whether it existed
+    fn deallocate_prepared_sync(&self, handle: String) -> bool {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        rt.block_on(async move { engine.deallocate_prepared(&handle).await })
+    }
+
+    /// Open a session synchronously, returning a dict with its `session_id`.
+    /// `SET batch_size`, `time_zone`, `max_result_rows` or `query_timeout_ms`
+    /// run with that `session_id` change the setting for the session only.
+    fn create_session_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let session = rt.block_on(async move {
+            engine.create_session().await
+        });
+
+        to_python_object(py, &session)
+    }
+
+    /// A session's `settings` and activity as a dict, or None if it is not open
+    fn get_session_sync(&self, py: Python, session_id: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let session = rt.block_on(async move {
+            engine.get_session(&session_id).await
+        });
+
+        to_python_object(py, &session)
+    }
+
+    /// Open sessions as a list of dicts, oldest first
+    fn list_sessions_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let sessions = rt.block_on(async move {
+            engine.list_sessions().await
+        });
+
+        to_python_object(py, &sessions)
+    }
+
+    /// Close a session synchronously; returns whether it was open
+    fn close_session_sync(&self, session_id: String) -> bool {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        rt.block_on(async move { engine.close_session(&session_id).await })
+    }
+
+    /// Executed queries as a list of dicts, most recent first. `since_ms`
+    /// and `until_ms` are epoch milliseconds; `status` is `succeeded`,
+    /// `failed`, `cancelled`, `timed_out` or `rejected`.
+    #[pyo3(signature = (since_ms=None, until_ms=None, status=None, sql_contains=None, fingerprint=None, session_id=None, min_duration_ms=None, limit=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn get_query_history_sync(
+        &self,
+        py: Python,
+        since_ms: Option<i64>,
+        until_ms: Option<i64>,
+        status: Option<&str>,
+        sql_contains: Option<String>,
+        fingerprint: Option<String>,
+        session_id: Option<String>,
+        min_duration_ms: Option<u64>,
+        limit: Option<usize>,
+    ) -> PyResult<PyObject> {
+        let timestamp = |ms: i64| {
+            chrono::DateTime::from_timestamp_millis(ms).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid timestamp: {}", ms))
+            })
+        };
+        let filter = QueryHistoryFilter {
+            since: since_ms.map(timestamp).transpose()?,
+            until: until_ms.map(timestamp).transpose()?,
+            status: status.map(QueryStatus::parse).transpose().into_py_result()?,
+            sql_contains,
+            fingerprint,
+            session_id,
+            min_duration_ms,
+            limit,
+        };
+        to_python_object(py, &self.engine.get_query_history(&filter))
+    }
+
+    /// Execute a SQL query synchronously and return its result as bytes in
+    /// `format`: `json`, `columnar_json`, `arrow`, `csv`, `msgpack` or one
+    /// registered by the embedding application
+    #[pyo3(signature = (sql, format="json"))]
+    fn execute_query_as_sync<'py>(&self, py: Python<'py>, sql: String, format: &str) -> PyResult<&'py PyBytes> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let format = format.to_string();
+
+        let result = rt.block_on(async move {
+            engine.execute_query_as(&sql, &format).await.into_py_result()
+        })?;
+
+        Ok(PyBytes::new(py, &result.data))
+    }
+
+    /// Names of the result formats `execute_query_as_sync` accepts
+    fn list_result_formats_sync(&self) -> Vec<String> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        rt.block_on(async move { engine.list_serializers().await })
+    }
+
+    /// Stream a query's result for `for batch in engine.stream(sql)` or
+    /// `async for`, without collecting it first. The stream runs under
+    /// `query_id`, or a generated ID, and ends with a TimeoutError if still
+    /// open after `timeout_ms`.
+    #[pyo3(signature = (sql, query_id=None, timeout_ms=None))]
+    fn stream(&self, sql: String, query_id: Option<String>, timeout_ms: Option<u64>) -> PyRecordBatchStream {
+        // Awaitables returned by the stream run on the shared runtime; this
+        // fails harmlessly once it is already set
+        let _ = pyo3_asyncio::tokio::init_with_runtime(get_runtime());
+
+        PyRecordBatchStream {
+            engine: self.engine.clone(),
+            sql,
+            options: QueryOptions { query_id, timeout_ms, ..Default::default() },
+            stream: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Get engine statistics synchronously
+    fn get_stats_sync(&self, py: Python) -> PyResult<PyEngineStats> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let stats = rt.block_on(async move {
+            engine.get_stats().await
+        });
+        
+        #[cfg(feature = "object_store")]
+        let object_cache = stats.object_cache.as_ref().map(|cache| to_python_object(py, cache)).transpose()?;
+        #[cfg(not(feature = "object_store"))]
+        let object_cache = None;
+        let result_cache = to_python_object(py, &stats.result_cache)?;
+        let plan_cache = to_python_object(py, &stats.plan_cache)?;
+        let admission = to_python_object(py, &stats.admission)?;
+        let slots = to_python_object(py, &stats.slots)?;
+
+        Ok(PyEngineStats {
+            total_queries: stats.total_queries,
+            avg_execution_time_ms: stats.avg_execution_time_ms,
+            peak_memory_bytes: stats.peak_memory_bytes,
+            registered_tables: stats.registered_tables,
+            evicted_tables: stats.evicted_tables,
+            object_cache,
+            result_cache,
+            plan_cache,
+            admission,
+            slots,
+        })
+    }
+
+    /// Statistics per SQL fingerprint as a list of dicts, most total
+    /// execution time first
+    fn get_query_stats_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let stats = rt.block_on(async move {
+            engine.query_stats().await
+        });
+
+        to_python_object(py, &stats)
+    }
+
+    /// Usage of labeled queries per value of `group_by_label` synchronously,
+    /// e.g. `get_stats_by_label_sync("team")`, as a list of dicts
+    fn get_stats_by_label_sync(&self, py: Python, group_by_label: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let stats = rt.block_on(async move {
+            engine.get_stats_by_label(&group_by_label).await
+        });
+
+        to_python_object(py, &stats)
+    }
+
+    /// Register the files matching a glob as one table synchronously,
+    /// returning the matched files' count, sizes and paths as a dict
+    #[pyo3(signature = (table_name, pattern, format=None))]
+    fn register_files_sync(&self, py: Python, table_name: String, pattern: String, format: Option<String>) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let format = format.as_deref().map(DataFormat::parse).transpose().into_py_result()?;
+
+        let info = rt.block_on(async move {
+            engine.register_files(&table_name, &pattern, format).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register the Parquet or JSON files matching a glob as one table with
+    /// the union of their columns synchronously, returning the matched
+    /// files as a dict
+    fn register_table_from_glob_sync(&self, py: Python, table_name: String, pattern: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_table_from_glob(&table_name, &pattern).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register a directory of `column=value` partitions as one table
+    /// synchronously, returning its files and partition columns as a dict
+    #[pyo3(signature = (table_name, path, format=None))]
+    fn register_partitioned_sync(&self, py: Python, table_name: String, path: String, format: Option<String>) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let format = format.as_deref().map(DataFormat::parse).transpose().into_py_result()?;
+
+        let info = rt.block_on(async move {
+            engine.register_partitioned(&table_name, &path, format).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register a CSV file, or the CSV files matching a glob, as a table
+    /// scanned in place, returning the matched files as a dict. `schema` is
+    /// a list of `(column, type)` pairs with types such as `INT64` or
+    /// `STRING` and skips type inference; `infer_types=False` reads every
+    /// column as a string.
+    #[pyo3(signature = (
+        table_name,
+        path,
+        header=true,
+        delimiter=",",
+        quote="\"",
+        escape=None,
+        comment=None,
+        newlines_in_values=false,
+        infer_rows=1000,
+        infer_types=true,
+        schema=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn register_csv_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        path: String,
+        header: bool,
+        delimiter: &str,
+        quote: &str,
+        escape: Option<&str>,
+        comment: Option<&str>,
+        newlines_in_values: bool,
+        infer_rows: usize,
+        infer_types: bool,
+        schema: Option<Vec<(String, String)>>,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let character = |name: &str, value: &str| match value.as_bytes() {
+            [byte] => Ok(*byte),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} must be a single character", name))),
+        };
+        let schema = schema
+            .map(|columns| {
+                columns
+                    .into_iter()
+                    .map(|(name, type_name)| Ok((name, parse_type_name(&type_name)?)))
+                    .collect::<Result<Vec<_>, BlazeError>>()
+            })
+            .transpose()
+            .into_py_result()?;
+        let options = CsvTableOptions {
+            has_header: header,
+            delimiter: character("delimiter", delimiter)?,
+            quote: character("quote", quote)?,
+            escape: escape.map(|escape| character("escape", escape)).transpose()?,
+            comment: comment.map(|comment| character("comment", comment)).transpose()?,
+            newlines_in_values,
+            infer_rows,
+            infer_types,
+            schema,
+        };
+
+        let info = rt.block_on(async move {
+            engine.register_csv(&table_name, &path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register a newline-delimited JSON file, or the files matching a glob,
+    /// as a table scanned in place, inferring the schema from the first
+    /// `infer_rows` rows. Returns the matched files as a dict.
+    #[pyo3(signature = (table_name, path, infer_rows=1000))]
+    fn register_json_sync(&self, py: Python, table_name: String, path: String, infer_rows: usize) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = JsonTableOptions { infer_rows };
+
+        let info = rt.block_on(async move {
+            engine.register_json(&table_name, &path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Load a CSV file into a table synchronously, returning a report of
+    /// rows loaded and rejected. `column_types` maps column names to types
+    /// such as `INT64` or `STRING`; `bad_rows` is `fail`, `skip` or `reject`,
+    /// the last storing bad rows in `rejects_table` (default
+    /// `<table_name>_rejects`).
+    #[pyo3(signature = (
+        table_name,
+        path,
+        header=true,
+        delimiter=",",
+        column_types=None,
+        coerce_types=true,
+        bad_rows="fail",
+        rejects_table=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn load_csv_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        path: String,
+        header: bool,
+        delimiter: &str,
+        column_types: Option<HashMap<String, String>>,
+        coerce_types: bool,
+        bad_rows: &str,
+        rejects_table: Option<String>,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let [delimiter] = delimiter.as_bytes() else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("delimiter must be a single character"));
+        };
+        let column_types = column_types
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, type_name)| Ok((name, parse_type_name(&type_name)?)))
+            .collect::<Result<HashMap<_, _>, BlazeError>>()
+            .into_py_result()?;
+        let bad_rows = match bad_rows {
+            "fail" => BadRowPolicy::Fail,
+            "skip" => BadRowPolicy::Skip,
+            "reject" => BadRowPolicy::Reject(rejects_table.unwrap_or_else(|| format!("{}_rejects", table_name))),
+            other => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "bad_rows must be 'fail', 'skip' or 'reject', got '{}'",
+                    other
+                )))
+            }
+        };
+        let options = CsvIngestOptions {
+            has_header: header,
+            delimiter: *delimiter,
+            column_types,
+            coerce_types,
+            bad_rows,
+            ..Default::default()
+        };
+
+        let report = rt.block_on(async move {
+            engine.load_csv(&table_name, &path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Load an Avro file, or the `.avro` files in a directory, into a table
+    /// synchronously, returning a report of files read, rows and columns
+    fn register_avro_sync(&self, py: Python, table_name: String, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let report = rt.block_on(async move {
+            engine.register_avro(&table_name, &path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Decode Protocol Buffers messages from a file, or the files in a
+    /// directory, into a table synchronously, returning a report of files
+    /// read, rows and columns. `framing` is "delimited" or "single".
+    #[pyo3(signature = (table_name, descriptor_path, message_type, path, framing="delimited"))]
+    fn register_protobuf_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        descriptor_path: String,
+        message_type: String,
+        path: String,
+        framing: &str,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let framing = ProtobufFraming::parse(framing).into_py_result()?;
+
+        let report = rt.block_on(async move {
+            engine
+                .register_protobuf(&table_name, &descriptor_path, &message_type, &path, framing)
+                .await
+                .into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Load a BigQuery table, `project.dataset.table`, through the Storage
+    /// Read API synchronously, reading only `columns` (all when unset) and
+    /// the rows matching `row_restriction`. Returns a report of streams,
+    /// rows and columns.
+    #[cfg(feature = "bigquery")]
+    #[pyo3(signature = (table_name, source_table, columns=None, row_restriction=None, billing_project=None, access_token=None, endpoint=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn register_bigquery_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        source_table: String,
+        columns: Option<Vec<String>>,
+        row_restriction: Option<String>,
+        billing_project: Option<String>,
+        access_token: Option<String>,
+        endpoint: Option<String>,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = BigQueryReadOptions {
+            columns: columns.unwrap_or_default(),
+            row_restriction,
+            billing_project,
+            access_token,
+            endpoint,
+            ..Default::default()
+        };
+
+        let report = rt.block_on(async move {
+            engine.register_bigquery(&table_name, &source_table, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Register an ORC file, or the `.orc` files in a directory, as a table
+    /// read in place synchronously, returning its files, rows and columns
+    fn register_orc_sync(&self, py: Python, table_name: String, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_orc(&table_name, &path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register an Arrow IPC (Feather V2) file, or the IPC files in a
+    /// directory, as a memory-mapped table synchronously, returning its files,
+    /// rows and columns
+    fn register_arrow_ipc_sync(&self, py: Python, table_name: String, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_arrow_ipc(&table_name, &path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register a Delta Lake table synchronously, at `version` or its latest
+    /// commit, returning the version read, its file count and columns
+    #[cfg(feature = "delta")]
+    #[pyo3(signature = (table_name, path, version=None))]
+    fn register_delta_sync(&self, py: Python, table_name: String, path: String, version: Option<i64>) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_delta(&table_name, &path, version).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register an Iceberg table synchronously from its metadata file, at
+    /// `snapshot_id`, the snapshot current at `as_of_ms` (epoch
+    /// milliseconds) or its current snapshot
+    #[cfg(feature = "iceberg")]
+    #[pyo3(signature = (table_name, metadata_path, snapshot_id=None, as_of_ms=None))]
+    fn register_iceberg_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        metadata_path: String,
+        snapshot_id: Option<i64>,
+        as_of_ms: Option<i64>,
+    ) -> PyResult<PyObject> {
+        let version = match (snapshot_id, as_of_ms) {
+            (None, None) => None,
+            (Some(snapshot_id), None) => Some(IcebergVersion::SnapshotId(snapshot_id)),
+            (None, Some(as_of_ms)) => Some(IcebergVersion::AsOf(chrono::DateTime::from_timestamp_millis(as_of_ms).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid timestamp: {}", as_of_ms))
+            })?)),
+            (Some(_), Some(_)) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("Set either snapshot_id or as_of_ms, not both"))
+            }
+        };
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_iceberg(&table_name, &metadata_path, version).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Load a GeoJSON file into a table synchronously, returning a report
+    /// of rows loaded, columns and bounds
+    fn load_geojson_sync(&self, py: Python, table_name: String, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let report = rt.block_on(async move {
+            engine.load_geojson(&table_name, &path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Load a shapefile and its `.dbf` attributes into a table
+    /// synchronously, returning a report of rows loaded, columns and bounds
+    fn load_shapefile_sync(&self, py: Python, table_name: String, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let report = rt.block_on(async move {
+            engine.load_shapefile(&table_name, &path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Write a query result to one Parquet file at `output_path`
+    /// synchronously, returning the rows and bytes written as a dict
+    #[pyo3(signature = (sql, output_path, compression="snappy", row_group_size=1048576, overwrite=false))]
+    fn execute_query_to_parquet_sync(
+        &self,
+        py: Python,
+        sql: String,
+        output_path: String,
+        compression: &str,
+        row_group_size: usize,
+        overwrite: bool,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = ParquetExportOptions { compression: compression.to_string(), row_group_size, overwrite };
+
+        let report = rt.block_on(async move {
+            engine.execute_query_to_parquet(&sql, &output_path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Write a query result to one CSV file at `output_path` synchronously,
+    /// returning the rows and bytes written as a dict. `delimiter` is a
+    /// single ASCII character.
+    #[pyo3(signature = (sql, output_path, has_header=true, delimiter=",", gzip=false, overwrite=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn execute_query_to_csv_sync(
+        &self,
+        py: Python,
+        sql: String,
+        output_path: String,
+        has_header: bool,
+        delimiter: &str,
+        gzip: bool,
+        overwrite: bool,
+    ) -> PyResult<PyObject> {
+        let delimiter = match delimiter.as_bytes() {
+            [byte] => *byte,
+            _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("delimiter must be a single ASCII character")),
+        };
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = CsvExportOptions { has_header, delimiter, gzip, overwrite };
+
+        let report = rt.block_on(async move {
+            engine.execute_query_to_csv(&sql, &output_path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Write a query result to one newline-delimited JSON file at
+    /// `output_path` synchronously, returning the rows and bytes written
+    #[pyo3(signature = (sql, output_path, gzip=false, overwrite=false))]
+    fn execute_query_to_json_sync(&self, py: Python, sql: String, output_path: String, gzip: bool, overwrite: bool) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = JsonExportOptions { gzip, overwrite };
+
+        let report = rt.block_on(async move {
+            engine.execute_query_to_json(&sql, &output_path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Write a table or query result as Parquet files under `path`,
+    /// partitioned into hive-style `column=value` directories
+    #[pyo3(signature = (
+        table_or_sql,
+        path,
+        partition_by=None,
+        target_file_size_bytes=134217728,
+        compression="snappy",
+        overwrite=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn write_table_to_parquet_sync(
+        &self,
+        py: Python,
+        table_or_sql: String,
+        path: String,
+        partition_by: Option<Vec<String>>,
+        target_file_size_bytes: usize,
+        compression: &str,
+        overwrite: bool,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = ParquetSinkOptions {
+            partition_by: partition_by.unwrap_or_default(),
+            target_file_size_bytes,
+            compression: compression.to_string(),
+            overwrite,
+        };
+
+        let report = rt.block_on(async move {
+            engine.write_table_to_parquet(&table_or_sql, &path, &options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Tables registered from files, as a list of dicts
+    fn list_file_tables_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let tables = rt.block_on(async move {
+            engine.list_file_tables().await
+        });
+
+        to_python_object(py, &tables)
+    }
+
+    /// Register a stream of a remote Arrow Flight service as a table
+    /// synchronously. Exactly one of `ticket`, `path` (a list of path
+    /// segments) and `command` names the flight. Returns the table's
+    /// columns and, when the service reported it, its row count.
+    #[pyo3(signature = (table_name, endpoint, ticket=None, path=None, command=None))]
+    fn register_flight_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        endpoint: String,
+        ticket: Option<Vec<u8>>,
+        path: Option<Vec<String>>,
+        command: Option<Vec<u8>>,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let source = match (ticket, path, command) {
+            (Some(ticket), None, None) => FlightSource::Ticket(ticket),
+            (None, Some(path), None) => FlightSource::Path(path),
+            (None, None, Some(command)) => FlightSource::Command(command),
+            _ => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "Give exactly one of ticket, path and command",
+                ))
+            }
+        };
+
+        let info = rt.block_on(async move {
+            engine.register_flight(&table_name, &endpoint, source).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Start accepting Arrow Flight `DoPut` uploads on `address` in the
+    /// background. An upload's path descriptor `[table]` appends to the
+    /// table, creating it if needed; `[table, "replace"]` replaces it.
+    #[pyo3(signature = (address="127.0.0.1:8815"))]
+    fn start_flight_server_sync(&self, address: &str) -> PyResult<PyFlightServer> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        // The server keeps running on the shared runtime after this returns
+        let server = rt.block_on(async move {
+            FlightServer::start(engine, address).await.into_py_result()
+        })?;
+
+        Ok(PyFlightServer {
+            server: parking_lot::Mutex::new(Some(server)),
+        })
+    }
+
+    /// Let tables read `gs://bucket/...` URLs synchronously. Give at most
+    /// one of a service account key file, the key's JSON contents, or an
+    /// application default credentials file; without any, application
+    /// default credentials are looked up from the environment, gcloud's
+    /// well-known file and the metadata server.
+    #[cfg(feature = "object_store")]
+    #[pyo3(signature = (bucket, service_account_path=None, service_account_key=None, application_credentials_path=None))]
+    fn register_gcs_sync(
+        &self,
+        py: Python,
+        bucket: String,
+        service_account_path: Option<String>,
+        service_account_key: Option<String>,
+        application_credentials_path: Option<String>,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let credentials = match (service_account_path, service_account_key, application_credentials_path) {
+            (None, None, None) => GcsCredentials::ApplicationDefault,
+            (Some(path), None, None) => GcsCredentials::ServiceAccountFile(path),
+            (None, Some(key), None) => GcsCredentials::ServiceAccountKey(key),
+            (None, None, Some(path)) => GcsCredentials::ApplicationCredentialsFile(path),
+            _ => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "Give at most one of service_account_path, service_account_key and application_credentials_path",
+                ))
+            }
+        };
+
+        let info = rt.block_on(async move {
+            engine.register_gcs(&bucket, credentials).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Let tables read an Azure container, given as `az://container` or
+    /// `abfss://container@account.dfs.core.windows.net`, synchronously, with
+    /// the `azure_*` credentials of the engine configuration
+    #[cfg(feature = "object_store")]
+    fn register_azure_sync(&self, py: Python, url: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_azure(&url).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Register a file published at an `http://` or `https://` URL as a
+    /// table read in place synchronously, sending `headers` (a dict such as
+    /// `{"Authorization": "Bearer ..."}`) with every request to its origin
+    #[cfg(feature = "object_store")]
+    #[pyo3(signature = (table_name, url, format=None, headers=None))]
+    fn register_http_sync(
+        &self,
+        py: Python,
+        table_name: String,
+        url: String,
+        format: Option<String>,
+        headers: Option<BTreeMap<String, String>>,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let format = format.as_deref().map(DataFormat::parse).transpose().into_py_result()?;
+        let headers: Vec<_> = headers.unwrap_or_default().into_iter().collect();
+
+        let info = rt.block_on(async move {
+            engine.register_http(&table_name, &url, format, &headers).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Object stores registered with the engine, as a list of dicts
+    #[cfg(feature = "object_store")]
+    fn list_object_stores_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let stores = rt.block_on(async move {
+            engine.list_object_stores().await
+        });
+
+        to_python_object(py, &stores)
+    }
+
+    /// Tables registered from Flight services, as a list of dicts
+    fn list_flight_tables_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let tables = rt.block_on(async move {
+            engine.list_flight_tables().await
+        });
+
+        to_python_object(py, &tables)
+    }
+
+    /// Register a table of a remote PostgreSQL server as a table
+    /// synchronously, returning its columns and the connection string
+    /// without its password
+    #[cfg(feature = "postgres")]
+    fn register_postgres_sync(&self, py: Python, table_name: String, connection: String, remote_table: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.register_postgres(&table_name, &connection, &remote_table).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Tables registered from PostgreSQL servers, as a list of dicts
+    #[cfg(feature = "postgres")]
+    fn list_postgres_tables_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let tables = rt.block_on(async move {
+            engine.list_postgres_tables().await
+        });
+
+        to_python_object(py, &tables)
+    }
+
+    /// Attach a DuckDB database file read-only synchronously, returning its
+    /// alias, path and tables. The alias defaults to the file name.
+    #[cfg(feature = "duckdb")]
+    #[pyo3(signature = (path, alias=None))]
+    fn attach_duckdb_sync(&self, py: Python, path: String, alias: Option<String>) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let alias = match alias {
+            Some(alias) => alias,
+            None => crate::duckdb_attach::default_alias(std::path::Path::new(&path)).into_py_result()?,
+        };
+
+        let info = rt.block_on(async move {
+            engine.attach_duckdb(&path, &alias).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Detach a database attached with `attach_duckdb_sync` synchronously
+    #[cfg(feature = "duckdb")]
+    fn detach_database_sync(&self, alias: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.detach_database(&alias).await.into_py_result()
+        })
+    }
+
+    /// List attached databases synchronously
+    #[cfg(feature = "duckdb")]
+    fn list_attached_databases_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let databases = rt.block_on(async move {
+            engine.list_attached_databases().await
+        });
+
+        to_python_object(py, &databases)
+    }
+
+    /// Attach a SQLite database file read-only synchronously as `dataset`,
+    /// returning its dataset, path and tables
+    #[cfg(feature = "sqlite")]
+    fn attach_sqlite_sync(&self, py: Python, path: String, dataset: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.attach_sqlite(&path, &dataset).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Detach a database attached with `attach_sqlite_sync` synchronously
+    #[cfg(feature = "sqlite")]
+    fn detach_sqlite_sync(&self, dataset: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.detach_sqlite(&dataset).await.into_py_result()
+        })
+    }
+
+    /// List attached SQLite databases synchronously
+    #[cfg(feature = "sqlite")]
+    fn list_sqlite_databases_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let databases = rt.block_on(async move {
+            engine.list_sqlite_databases().await
+        });
+
+        to_python_object(py, &databases)
+    }
+
+    /// Start appending a Kafka topic's messages to a table in the background.
+    /// `format` is "json" or "avro"; Avro needs `schema_registry_url`.
+    #[cfg(feature = "kafka")]
+    #[pyo3(signature = (
+        table_name,
+        brokers,
+        topic,
+        group_id=None,
+        format="json",
+        schema_registry_url=None,
+        checkpoint_path=None,
+        max_batch_messages=1000,
+        max_batch_delay_ms=1000,
+        start_offset="earliest",
+        consumer_options=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn start_kafka_source_sync(
+        &self,
+        table_name: String,
+        brokers: String,
+        topic: String,
+        group_id: Option<String>,
+        format: &str,
+        schema_registry_url: Option<String>,
+        checkpoint_path: Option<String>,
+        max_batch_messages: usize,
+        max_batch_delay_ms: u64,
+        start_offset: &str,
+        consumer_options: Option<HashMap<String, String>>,
+    ) -> PyResult<PyKafkaSource> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let mut config = KafkaSourceConfig::new(&brokers, &topic, &table_name);
+        config.format = KafkaMessageFormat::parse(format).into_py_result()?;
+        config.start_offset = match start_offset {
+            "earliest" => StartOffset::Earliest,
+            "latest" => StartOffset::Latest,
+            other => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "start_offset must be 'earliest' or 'latest', got '{}'",
+                    other
+                )))
+            }
+        };
+        if let Some(group_id) = group_id {
+            config.group_id = group_id;
+        }
+        config.schema_registry_url = schema_registry_url;
+        config.checkpoint_path = checkpoint_path.map(Into::into);
+        config.max_batch_messages = max_batch_messages;
+        config.max_batch_delay_ms = max_batch_delay_ms;
+        config.consumer_options = consumer_options.unwrap_or_default();
+
+        // The consumer keeps running on the shared runtime after this returns
+        let source = rt.block_on(async move {
+            KafkaSource::start(engine, config).await.into_py_result()
+        })?;
+
+        Ok(PyKafkaSource {
+            source: parking_lot::Mutex::new(Some(source)),
+        })
+    }
+
+    /// List available tables synchronously
+    fn list_tables_sync(&self) -> PyResult<Vec<String>> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let tables = rt.block_on(async move {
+            engine.list_tables().await.into_py_result()
+        })?;
+        
+        Ok(tables)
+    }
+
+    /// Validate SQL query syntax synchronously
+    fn validate_query_sync(&self, sql: String) -> PyResult<bool> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let is_valid = rt.block_on(async move {
+            engine.validate_query(&sql).await.into_py_result()
+        })?;
+        
+        Ok(is_valid)
+    }
+
+    /// Tables and views a statement depends on, without running it, as
+    /// `{"reads": [...], "writes": [...]}` of `catalog.schema.table` names
+    fn get_referenced_tables_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let references = rt.block_on(async move {
+            engine.get_referenced_tables(&sql).await.into_py_result()
+        })?;
+
+        to_python_object(py, &references)
+    }
+
+    /// The optimized physical plan of a query as
+    /// `{"root": {...}, "dot": "digraph plan {...}"}`, where each node has
+    /// its operator, estimated rows and bytes, and children
+    fn explain_graph_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let graph = rt.block_on(async move {
+            engine.explain_graph(&sql).await.into_py_result()
+        })?;
+
+        to_python_object(py, &graph)
+    }
+
+    /// A query's plan like `explain_graph_sync`. With `analyze=True` the
+    /// query is run and each node's `metrics` hold its `output_rows`,
+    /// `elapsed_compute_ns`, `memory_bytes` and `spilled_bytes`.
+    #[pyo3(signature = (sql, analyze=false))]
+    fn explain_sync(&self, py: Python, sql: String, analyze: bool) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let graph = rt.block_on(async move {
+            engine.explain(&sql, analyze).await.into_py_result()
+        })?;
+
+        to_python_object(py, &graph)
+    }
+
+    /// Estimated rows of a query without running it, as a dict with the
+    /// result's `estimated_rows`, the largest stage's `max_stage_rows` and
+    /// the estimate of every plan stage under `stages`
+    fn estimate_cardinality_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let estimate = rt.block_on(async move {
+            engine.estimate_cardinality(&sql).await.into_py_result()
+        })?;
+
+        to_python_object(py, &estimate)
+    }
+
+    /// Plan a query without running it, returning a dict with the
+    /// `total_bytes_processed` it would read, whether that is `bytes_exact`,
+    /// the `tables` and columns it reads and its `optimized_plan`
+    fn dry_run_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let report = rt.block_on(async move {
+            engine.dry_run(&sql).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Gather a table's row count and per-column distinct and null counts
+    /// synchronously, returning them as a dict
+    fn analyze_table_sync(&self, py: Python, name: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let statistics = rt.block_on(async move {
+            engine.analyze_table(&name).await.into_py_result()
+        })?;
+
+        to_python_object(py, &statistics)
+    }
+
+    /// Statistics from the last `ANALYZE TABLE` of a table, or None
+    fn table_statistics_sync(&self, py: Python, name: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let statistics = rt.block_on(async move {
+            engine.table_statistics(&name).await
+        });
+
+        to_python_object(py, &statistics)
+    }
+
+    /// The join order of a query with estimated and actual rows per join,
+    /// running the joins to count them
+    fn join_diagnostics_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let diagnostics = rt.block_on(async move {
+            engine.join_diagnostics(&sql).await.into_py_result()
+        })?;
+
+        to_python_object(py, &diagnostics)
+    }
+
+    /// Record the optimized plans of named queries (a dict of name to SQL)
+    /// as a baseline JSON file at `path`
+    fn record_plan_baseline_sync(&self, queries: BTreeMap<String, String>, path: String) -> PyResult<usize> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            let baseline = engine.record_plan_baseline(&queries).await?;
+            baseline.save(&path)?;
+            Ok::<_, BlazeError>(baseline.queries.len())
+        })
+        .into_py_result()
+    }
+
+    /// Plan the queries of the baseline at `path` again, returning the
+    /// changed plans with flagged regressions and diffs as a dict
+    fn check_plan_baseline_sync(&self, py: Python, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let report = rt.block_on(async move {
+            let baseline = PlanBaseline::load(&path)?;
+            engine.check_plan_baseline(&baseline).await
+        })
+        .into_py_result()?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Current engine configuration as a dict
+    fn get_config_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let config = rt.block_on(async move {
+            engine.config().await
+        });
+
+        to_python_object(py, &config)
+    }
+
+    /// Change settings on the running engine synchronously, keeping its
+    /// tables. Accepts `memory_limit_bytes`, `cpu_cores`, `batch_size`,
+    /// `max_result_rows`, `query_timeout_ms`, `time_zone`, `decimal_rules`,
+    /// `decimal_overflow`, `reorder_joins`, `result_cache_max_bytes`,
+    /// `result_cache_ttl_secs`, `plan_cache_size`, `max_concurrent_queries`,
+    /// `max_queued_queries`, `queue_timeout_ms`, `slots` and
+    /// `query_history_size`; returns the resulting configuration. Cached
+    /// results and plans are dropped.
+    fn update_config_sync(&self, py: Python, settings: &PyDict) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let update: EngineConfigUpdate = serde_json::from_value(python_to_json_value(settings)?).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid configuration update: {}", e))
+        })?;
+
+        let config = rt.block_on(async move {
+            engine.update_config(update).await.into_py_result()
+        })?;
+
+        to_python_object(py, &config)
+    }
+
+    /// Fix the engine clock at `fixed_ms` (epoch milliseconds) or shift it
+    /// by `offset_ms`; with neither it follows the system clock again
+    #[pyo3(signature = (fixed_ms=None, offset_ms=None))]
+    fn set_clock_sync(&self, fixed_ms: Option<i64>, offset_ms: Option<i64>) -> PyResult<()> {
+        let clock = match (fixed_ms, offset_ms) {
+            (None, None) => Clock::System,
+            (Some(fixed_ms), None) => Clock::Fixed(chrono::DateTime::from_timestamp_millis(fixed_ms).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid timestamp: {}", fixed_ms))
+            })?),
+            (None, Some(offset_ms)) => Clock::Offset(chrono::Duration::milliseconds(offset_ms)),
+            (Some(_), Some(_)) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("Set either fixed_ms or offset_ms, not both"))
+            }
+        };
+        self.engine.set_clock(clock);
+        Ok(())
+    }
+
+    /// Define a resource group synchronously, returning its stats
+    #[pyo3(signature = (name, max_concurrency, cpu_share=1.0, memory_fraction=1.0))]
+    fn create_resource_group_sync(
+        &self,
+        py: Python,
+        name: String,
+        max_concurrency: usize,
+        cpu_share: f64,
+        memory_fraction: f64,
+    ) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let group = ResourceGroup { name, cpu_share, memory_fraction, max_concurrency };
+
+        let stats = rt.block_on(async move {
+            engine.create_resource_group(group).await.into_py_result()
+        })?;
+
+        to_python_object(py, &stats)
+    }
+
+    /// Remove a resource group synchronously
+    fn drop_resource_group_sync(&self, name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.drop_resource_group(&name).await.into_py_result()
+        })
+    }
+
+    /// Limits and activity of every resource group synchronously
+    fn list_resource_groups_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let groups = rt.block_on(async move {
+            engine.list_resource_groups().await
+        });
+
+        to_python_object(py, &groups)
+    }
+
+    /// Save tables, views, indexes, models and statistics to a directory
+    /// synchronously, returning a summary of what was saved
+    fn snapshot_to_sync(&self, py: Python, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.snapshot_to(&path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// Load a directory written by `snapshot_to_sync` into this engine, which
+    /// must not have tables yet, synchronously
+    fn restore_from_sync(&self, py: Python, path: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let info = rt.block_on(async move {
+            engine.restore_from(&path).await.into_py_result()
+        })?;
+
+        to_python_object(py, &info)
+    }
+
+    /// A page of a result kept by `execute_query_sync(..., max_rows=...)`
+    /// synchronously, as a dict with its `data`, `total_rows`, `start_row`
+    /// and the `page_token` of the next page
+    #[pyo3(signature = (query_id, page_token=None, max_rows=1000))]
+    fn fetch_results_sync(&self, py: Python, query_id: String, page_token: Option<String>, max_rows: usize) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let page = rt.block_on(async move {
+            engine.fetch_results(&query_id, page_token.as_deref(), max_rows).await.into_py_result()
+        })?;
+
+        to_python_object(py, &page)
+    }
+
+    /// Running queries as a list of dicts with their `query_id`, SQL and
+    /// running time, oldest first
+    fn running_queries_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let queries = rt.block_on(async move {
+            engine.running_queries().await
+        });
+
+        to_python_object(py, &queries)
+    }
+
+    /// Cancel the running query with `query_id` synchronously; it fails with
+    /// a "Query cancelled" error
+    fn cancel_query_sync(&self, query_id: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.cancel_query(&query_id).await.into_py_result()
+        })
+    }
+
+    /// Shut the engine down synchronously: refuse new queries, wait up to
+    /// `drain_timeout_ms` for running ones, cancel the rest, and save the
+    /// engine's state to `state_dir` when given
+    #[pyo3(signature = (drain_timeout_ms=30000, state_dir=None))]
+    fn shutdown_sync(&self, py: Python, drain_timeout_ms: u64, state_dir: Option<String>) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let options = ShutdownOptions { drain_timeout_ms, state_dir: state_dir.map(Into::into) };
+
+        let report = rt.block_on(async move {
+            engine.shutdown(&options).await.into_py_result()
+        })?;
+
+        to_python_object(py, &report)
+    }
+
+    /// Anonymous result tables that have not expired synchronously, oldest first
+    fn list_result_tables_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let tables = rt.block_on(async move {
+            engine.list_result_tables().await
+        });
+
+        to_python_object(py, &tables)
+    }
+
+    /// Drop an anonymous result table before it expires synchronously
+    fn drop_result_table_sync(&self, name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.drop_result_table(&name).await.into_py_result()
+        })
+    }
+
+    /// List snapshots of a table synchronously, oldest first
+    fn list_snapshots_sync(&self, table_name: String) -> PyResult<Vec<PySnapshotInfo>> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let snapshots = rt.block_on(async move {
+            engine.list_snapshots(&table_name).await.into_py_result()
+        })?;
+        
+        Ok(snapshots.into_iter().map(PySnapshotInfo::from).collect())
+    }
+
+    /// Drop snapshots older than `older_than_ms` (epoch milliseconds) synchronously
+    #[pyo3(signature = (older_than_ms, table_name=None))]
+    fn prune_snapshots_sync(&self, older_than_ms: i64, table_name: Option<String>) -> PyResult<usize> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let older_than = chrono::DateTime::from_timestamp_millis(older_than_ms).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid timestamp: {}", older_than_ms))
+        })?;
+        
+        let pruned = rt.block_on(async move {
+            engine.prune_snapshots(table_name.as_deref(), older_than).await.into_py_result()
+        })?;
+        
+        Ok(pruned)
+    }
+
+    /// Create a materialized view synchronously, returning its description
+    fn create_materialized_view_sync(&self, py: Python, name: String, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let info = rt.block_on(async move {
+            engine.create_materialized_view(&name, &sql).await.into_py_result()
+        })?;
+        
+        to_python_object(py, &info)
+    }
+
+    /// Fully recompute a materialized view synchronously
+    fn refresh_materialized_view_sync(&self, py: Python, name: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let info = rt.block_on(async move {
+            engine.refresh_materialized_view(&name).await.into_py_result()
+        })?;
+        
+        to_python_object(py, &info)
+    }
+
+    /// Drop a materialized view synchronously
+    fn drop_materialized_view_sync(&self, name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        rt.block_on(async move {
+            engine.drop_materialized_view(&name).await.into_py_result()
+        })
+    }
+
+    /// Describe all materialized views synchronously
+    fn list_materialized_views_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        
+        let views = rt.block_on(async move {
+            engine.list_materialized_views().await
+        });
+        
+        to_python_object(py, &views)
+    }
+
+    /// Partition a table by the UTC day of a timestamp or date column
+    /// synchronously; batches registered or appended later are split
+    /// automatically
+    fn set_time_partitioning_sync(&self, py: Python, table_name: String, column: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let partitioning = rt.block_on(async move {
+            engine.set_time_partitioning(&table_name, &column).await.into_py_result()
+        })?;
+
+        to_python_object(py, &partitioning)
+    }
+
+    /// Stop partitioning a table by time synchronously
+    fn clear_time_partitioning_sync(&self, table_name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.clear_time_partitioning(&table_name).await.into_py_result()
+        })
+    }
+
+    /// Tables and views with their kinds synchronously, sorted by name
+    fn list_relations_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let relations = rt.block_on(async move {
+            engine.list_relations().await.into_py_result()
+        })?;
+
+        to_python_object(py, &relations)
+    }
+
+    /// Columns of a table or view synchronously, as dicts with name,
+    /// data_type and nullable
+    fn get_columns_sync(&self, py: Python, name: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let columns = rt.block_on(async move {
+            engine.get_columns(&name).await.into_py_result()
+        })?;
+
+        to_python_object(py, &columns)
+    }
+
+    /// Create a table from the result of a query synchronously, returning
+    /// its row count
+    #[pyo3(signature = (name, sql, replace=false))]
+    fn create_table_as_sync(&self, name: String, sql: String, replace: bool) -> PyResult<u64> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.create_table_as(&name, &sql, replace).await.into_py_result()
+        })
+    }
+
+    /// Rename a table or view synchronously
+    fn rename_relation_sync(&self, from_name: String, to_name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.rename_relation(&from_name, &to_name).await.into_py_result()
+        })
+    }
+
+    /// Drop a relation synchronously, returning whether it existed
+    fn drop_relation_sync(&self, name: String) -> PyResult<bool> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.drop_relation(&name).await.into_py_result()
+        })
+    }
+
+    /// Latest loaded-at value of a table and the current time synchronously
+    fn relation_freshness_sync(&self, py: Python, name: String, loaded_at_column: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let freshness = rt.block_on(async move {
+            engine.relation_freshness(&name, &loaded_at_column).await.into_py_result()
+        })?;
+
+        to_python_object(py, &freshness)
+    }
+
+    /// Exempt a table from eviction under memory pressure synchronously
+    fn pin_table_sync(&self, table_name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.pin_table(&table_name).await.into_py_result()
+        })
+    }
+
+    /// Make a pinned table evictable again synchronously
+    fn unpin_table_sync(&self, table_name: String) {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.unpin_table(&table_name).await
+        })
+    }
+
+    /// Memory held by each in-memory table synchronously, most recently
+    /// used first
+    fn list_table_memory_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let tables = rt.block_on(async move {
+            engine.list_table_memory().await
+        });
+
+        to_python_object(py, &tables)
+    }
+
+    /// Recent table evictions synchronously, oldest first
+    fn list_table_evictions_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let events = rt.block_on(async move {
+            engine.list_table_evictions().await
+        });
+
+        to_python_object(py, &events)
+    }
+
+    /// Run a query and write its result to shared memory, returning a dict
+    /// with its `name`, `path`, `rows` and `bytes`. Worker processes read it
+    /// zero-copy with `open_shared_result(path)`.
+    fn execute_query_shared_sync(&self, py: Python, sql: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let shared = py.allow_threads(|| {
+            rt.block_on(async move {
+                engine.execute_query_shared(&sql).await.into_py_result()
+            })
+        })?;
+
+        to_python_object(py, &shared)
+    }
+
+    /// Remove a result written by `execute_query_shared_sync`
+    fn release_shared_result_sync(&self, name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.release_shared_result(&name).await.into_py_result()
+        })
+    }
+
+    /// Shared results not released yet, oldest first
+    fn list_shared_results_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let results = rt.block_on(async move {
+            engine.list_shared_results().await
+        });
+
+        to_python_object(py, &results)
+    }
+
+    /// Open a transaction synchronously
+    fn begin_transaction_sync(&self) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.begin_transaction().await.into_py_result()
+        })
+    }
+
+    /// Commit the open transaction synchronously, returning the tables it
+    /// wrote
+    fn commit_transaction_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let transaction = rt.block_on(async move {
+            engine.commit_transaction().await.into_py_result()
+        })?;
+
+        to_python_object(py, &transaction)
+    }
+
+    /// Roll back the open transaction synchronously, returning the tables
+    /// it restored
+    fn rollback_transaction_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let transaction = rt.block_on(async move {
+            engine.rollback_transaction().await.into_py_result()
+        })?;
+
+        to_python_object(py, &transaction)
+    }
+
+    /// The open transaction as a dict synchronously, or None
+    fn current_transaction_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let transaction = rt.block_on(async move {
+            engine.current_transaction().await
+        });
+
+        to_python_object(py, &transaction)
+    }
+
+    /// Days and row counts of a time-partitioned table synchronously
+    fn time_partitions_sync(&self, py: Python, table_name: String) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let partitions = rt.block_on(async move {
+            engine.time_partitions(&table_name).await.into_py_result()
         })?;
-        
-        Ok(PyBlazeQueryEngine {
-            engine: Arc::new(engine),
-        })
+
+        to_python_object(py, &partitions)
     }
 
-    /// Execute a SQL query synchronously (simplified version)
-    fn execute_query_sync(&self, sql: String) -> PyResult<PyQueryResult> {
+    /// Describe all search indexes synchronously
+    fn list_search_indexes_sync(&self, py: Python) -> PyResult<PyObject> {
         let rt = get_runtime();
         let engine = self.engine.clone();
-        
-        let result = rt.block_on(async move {
-            engine.execute_query(&sql).await.map_err(|e| PyErr::from(e))
-        })?;
-        
-        // Convert to JSON string for simplicity
-        let data_json = serde_json::to_string(&result.data).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON serialization error: {}", e))
-        })?;
-        
-        Ok(PyQueryResult {
-            rows: result.rows,
-            execution_time_ms: result.execution_time_ms,
-            memory_used_bytes: result.memory_used_bytes,
-            engine: result.engine,
-            data_json,
-            query_plan: result.query_plan,
-        })
+
+        let indexes = rt.block_on(async move {
+            engine.list_search_indexes().await
+        });
+
+        to_python_object(py, &indexes)
     }
 
-    /// Get engine statistics synchronously
-    fn get_stats_sync(&self) -> PyResult<PyEngineStats> {
+    /// Describe all vector indexes synchronously
+    fn list_vector_indexes_sync(&self, py: Python) -> PyResult<PyObject> {
         let rt = get_runtime();
         let engine = self.engine.clone();
-        
-        let stats = rt.block_on(async move {
-            engine.get_stats().await
+
+        let indexes = rt.block_on(async move {
+            engine.list_vector_indexes().await
         });
-        
-        Ok(PyEngineStats {
-            total_queries: stats.total_queries,
-            avg_execution_time_ms: stats.avg_execution_time_ms,
-            peak_memory_bytes: stats.peak_memory_bytes,
-            registered_tables: stats.registered_tables,
-        })
+
+        to_python_object(py, &indexes)
     }
 
-    /// List available tables synchronously
-    fn list_tables_sync(&self) -> PyResult<Vec<String>> {
+    /// Evaluate data quality checks synchronously. Each check is a dict with
+    /// an `expression` and optional `table`, `name` and `message`.
+    fn run_assertions_sync(&self, py: Python, checks: Vec<HashMap<String, String>>) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let checks = checks
+            .into_iter()
+            .map(|mut check| {
+                let expression = check.remove("expression").ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>("Each assertion needs an 'expression'")
+                })?;
+                Ok(Assertion {
+                    name: check.remove("name"),
+                    table: check.remove("table"),
+                    expression,
+                    message: check.remove("message"),
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let results = rt.block_on(async move {
+            engine.run_assertions(&checks).await
+        });
+
+        to_python_object(py, &results)
+    }
+
+    /// Describe all trained ML models synchronously
+    fn list_models_sync(&self, py: Python) -> PyResult<PyObject> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        let models = rt.block_on(async move {
+            engine.list_models().await
+        });
+
+        to_python_object(py, &models)
+    }
+
+    /// Drop a trained ML model synchronously
+    fn drop_model_sync(&self, name: String) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+
+        rt.block_on(async move {
+            engine.drop_model(&name).await
+        }).into_py_result()
+    }
+
+    /// Fetch change events after `since_sequence` synchronously. Each event is
+    /// a dict with its sequence, table, change type, commit time and rows.
+    #[pyo3(signature = (table_name=None, since_sequence=0))]
+    fn get_changes_sync(&self, py: Python, table_name: Option<String>, since_sequence: u64) -> PyResult<PyObject> {
         let rt = get_runtime();
         let engine = self.engine.clone();
         
-        let tables = rt.block_on(async move {
-            engine.list_tables().await.map_err(|e| PyErr::from(e))
-        })?;
+        let (events, time_zone) = rt.block_on({
+            let engine = engine.clone();
+            async move {
+                let events = engine.get_changes(table_name.as_deref(), since_sequence).await;
+                (events, engine.config().await.time_zone)
+            }
+        });
         
-        Ok(tables)
+        let mut json_events = Vec::with_capacity(events.len());
+        for event in events {
+            let mut rows = Vec::with_capacity(event.num_rows());
+            for batch in &event.batches {
+                rows.extend(serializers::json_rows(batch, &time_zone).into_py_result()?);
+            }
+            json_events.push(serde_json::json!({
+                "sequence": event.sequence,
+                "table_name": event.table_name,
+                "change_type": event.change_type,
+                "committed_at_ms": event.committed_at.timestamp_millis(),
+                "rows": rows,
+            }));
+        }
+        
+        to_python_object(py, &json_events)
     }
 
-    /// Validate SQL query syntax synchronously
-    fn validate_query_sync(&self, sql: String) -> PyResult<bool> {
+    /// Sequence number of the most recent change event synchronously
+    fn latest_change_sequence_sync(&self) -> u64 {
         let rt = get_runtime();
         let engine = self.engine.clone();
         
-        let is_valid = rt.block_on(async move {
-            engine.validate_query(&sql).await.map_err(|e| PyErr::from(e))
-        })?;
-        
-        Ok(is_valid)
+        rt.block_on(async move {
+            engine.latest_change_sequence().await
+        })
     }
 
     /// Register test data for benchmarking
@@ -144,12 +2143,34 @@ impl PyBlazeQueryEngine {
         let engine = self.engine.clone();
         
         rt.block_on(async move {
-            let batches = create_test_data(rows).await.map_err(|e| PyErr::from(e))?;
-            engine.register_table(&table_name, batches).await.map_err(|e| PyErr::from(e))
+            let batches = datagen::generate(&DatasetSpec::test_data(rows)).into_py_result()?;
+            engine.register_table(&table_name, batches).await.into_py_result()
         })?;
         
         Ok(())
     }
+
+    /// Generate a synthetic table and register it synchronously. Each column
+    /// is a dict (`name`, `type`, and optionally `distribution`, `min`, `max`,
+    /// `cardinality`, `skew`, `null_fraction`, `prefix`) or a
+    /// `name:type[:key=value,...]` string.
+    #[pyo3(signature = (table_name, rows, columns, seed=None))]
+    fn register_synthetic_data(
+        &self,
+        table_name: String,
+        rows: usize,
+        columns: Vec<&PyAny>,
+        seed: Option<u64>,
+    ) -> PyResult<()> {
+        let rt = get_runtime();
+        let engine = self.engine.clone();
+        let spec = dataset_spec(rows, columns, seed)?;
+
+        rt.block_on(async move {
+            let batches = datagen::generate(&spec).into_py_result()?;
+            engine.register_table(&table_name, batches).await.into_py_result()
+        })
+    }
 }
 
 #[pymethods]
@@ -157,9 +2178,7 @@ impl PyQueryResult {
     /// Get query result data as parsed JSON
     #[getter]
     fn data(&self, py: Python) -> PyResult<PyObject> {
-        let data: Vec<HashMap<String, serde_json::Value>> = serde_json::from_str(&self.data_json).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON deserialization error: {}", e))
-        })?;
+        let data = self.rows()?;
         
         let py_list = PyList::empty(py);
         for row in data {
@@ -174,12 +2193,109 @@ impl PyQueryResult {
         Ok(py_list.into())
     }
 
+    /// Map rows onto instances of a dataclass, matching init fields to
+    /// columns by name. Values are converted to the field types where nothing
+    /// is lost (int to float, ISO strings to dates and times, numbers to
+    /// Decimal); other mismatches raise ValueError naming the row and column,
+    /// as do columns without a field and required fields without a column.
+    fn as_objects(&self, py: Python, cls: &PyAny) -> PyResult<PyObject> {
+        let dataclasses = py.import("dataclasses")?;
+        if !cls.is_instance_of::<PyType>() || !dataclasses.call_method1("is_dataclass", (cls,))?.is_true()? {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "as_objects expects a dataclass type, got {}",
+                cls.repr()?
+            )));
+        }
+        let class_name: String = cls.getattr("__name__")?.extract()?;
+
+        // Resolved type hints, so string annotations work too
+        let hints = py.import("typing")?.call_method1("get_type_hints", (cls,))?;
+        let missing = dataclasses.getattr("MISSING")?;
+        let mut fields = Vec::new();
+        for field in dataclasses.call_method1("fields", (cls,))?.iter()? {
+            let field = field?;
+            if !field.getattr("init")?.is_true()? {
+                continue;
+            }
+            let name: String = field.getattr("name")?.extract()?;
+            let required = field.getattr("default")?.is(missing) && field.getattr("default_factory")?.is(missing);
+            fields.push((hints.get_item(name.as_str())?, name, required));
+        }
+
+        let objects = PyList::empty(py);
+        for (index, row) in self.rows()?.iter().enumerate() {
+            let mut extra: Vec<_> =
+                row.keys().filter(|column| !fields.iter().any(|(_, name, _)| name == *column)).map(String::as_str).collect();
+            if !extra.is_empty() {
+                extra.sort();
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "{} has no field for column(s) {}",
+                    class_name,
+                    extra.join(", ")
+                )));
+            }
+            let kwargs = PyDict::new(py);
+            for (hint, name, required) in &fields {
+                match row.get(name) {
+                    Some(value) => {
+                        let value = json_value_to_python(py, value)?;
+                        let value = coerce_to_type(py, value.as_ref(py), hint).map_err(|e| {
+                            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                                "Row {}, column '{}': {}",
+                                index,
+                                name,
+                                e.value(py)
+                            ))
+                        })?;
+                        kwargs.set_item(name, value)?;
+                    }
+                    None if *required => {
+                        let mut columns: Vec<_> = row.keys().map(String::as_str).collect();
+                        columns.sort();
+                        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                            "Field '{}' of {} has no matching column; the result has columns {}",
+                            name,
+                            class_name,
+                            columns.join(", ")
+                        )));
+                    }
+                    None => {}
+                }
+            }
+            objects.append(cls.call((), Some(kwargs))?)?;
+        }
+
+        Ok(objects.into())
+    }
+
+    /// The rows as JSON bytes, compressed as named by `compression`; decode
+    /// them elsewhere with `decompress_payload`
+    #[getter]
+    fn payload<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.payload)
+    }
+
+    /// `zstd` or `gzip` if the payload is compressed, otherwise None
+    #[getter]
+    fn compression(&self) -> Option<&'static str> {
+        self.compression.map(PayloadCompression::name)
+    }
+
     /// Get query plan if available
     #[getter]
     fn query_plan(&self) -> Option<String> {
         self.query_plan.clone()
     }
 
+    /// Paths and sample count of the CPU profile, if one was captured
+    #[getter]
+    fn profile(&self, py: Python) -> PyResult<PyObject> {
+        match &self.profile {
+            Some(profile) => to_python_object(py, profile),
+            None => Ok(py.None()),
+        }
+    }
+
     /// String representation
     fn __repr__(&self) -> String {
         format!(
@@ -191,24 +2307,404 @@ impl PyQueryResult {
     }
 }
 
+impl PyQueryResult {
+    fn from_result(result: QueryResult, compression: Option<PayloadCompression>) -> PyResult<Self> {
+        let data_json = serde_json::to_vec(&result.data).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON serialization error: {}", e))
+        })?;
+        let payload = match compression {
+            Some(compression) => compression.compress(&data_json).into_py_result()?,
+            None => data_json,
+        };
+
+        Ok(PyQueryResult {
+            rows: result.rows,
+            execution_time_ms: result.execution_time_ms,
+            memory_used_bytes: result.memory_used_bytes,
+            result_bytes: result.result_bytes,
+            engine: result.engine,
+            payload,
+            compression,
+            query_plan: result.query_plan,
+            profile: result.profile,
+            result_table: result.result_table,
+            checksum: result.checksum,
+            rows_written: result.rows_written,
+            bytes_written: result.bytes_written,
+            cache_hit: result.cache_hit,
+            query_id: result.query_id,
+            page_token: result.page_token,
+        })
+    }
+
+    fn rows(&self) -> PyResult<Vec<HashMap<String, serde_json::Value>>> {
+        let decompressed;
+        let json = match self.compression {
+            Some(compression) => {
+                decompressed = compression.decompress(&self.payload).into_py_result()?;
+                &decompressed
+            }
+            None => &self.payload,
+        };
+        serde_json::from_slice(json).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON deserialization error: {}", e))
+        })
+    }
+}
+
+#[pymethods]
+impl PyRecordBatchStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Awaitable resolving to the next batch, or raising StopAsyncIteration
+    /// after the last one
+    fn __anext__(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let next = self.next_batch();
+
+        let next = pyo3_asyncio::tokio::future_into_py(py, async move {
+            match next.await.into_py_result()? {
+                Some(batch) => Python::with_gil(|py| record_batch_to_pyarrow(py, &batch)),
+                None => Err(pyo3::exceptions::PyStopAsyncIteration::new_err(())),
+            }
+        })?;
+
+        Ok(Some(next.into()))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next batch, blocking until it is produced; None ends iteration
+    fn __next__(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let next = self.next_batch();
+        match py.allow_threads(|| get_runtime().block_on(next)).into_py_result()? {
+            Some(batch) => Ok(Some(record_batch_to_pyarrow(py, &batch)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// String representation
+    fn __repr__(&self) -> String {
+        format!("RecordBatchStream(sql={:?})", self.sql)
+    }
+}
+
+impl PyRecordBatchStream {
+    /// Start the query if needed and produce its next batch
+    fn next_batch(&self) -> impl std::future::Future<Output = BlazeResult<Option<RecordBatch>>> + Send + 'static {
+        let engine = self.engine.clone();
+        let sql = self.sql.clone();
+        let options = self.options.clone();
+        let stream = self.stream.clone();
+
+        async move {
+            let mut stream = stream.lock().await;
+            if stream.is_none() {
+                *stream = Some(engine.execute_stream_with_options(&sql, &options).await?);
+            }
+            match stream.as_mut().expect("stream started").next().await {
+                Some(Ok(batch)) => Ok(Some(batch)),
+                // Cancellations and timeouts keep their own error
+                Some(Err(DataFusionError::External(error))) => match error.downcast::<BlazeError>() {
+                    Ok(error) => Err(*error),
+                    Err(error) => Err(DataFusionError::External(error).into()),
+                },
+                Some(Err(error)) => Err(error.into()),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+#[pymethods]
+impl PyKafkaSource {
+    /// Messages consumed, rows appended, offsets and the last error
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        match self.source.lock().as_ref() {
+            Some(source) => to_python_object(py, &source.stats()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Kafka source is stopped")),
+        }
+    }
+
+    /// Whether the consumer is still running
+    fn is_running(&self) -> bool {
+        self.source.lock().as_ref().is_some_and(KafkaSource::is_running)
+    }
+
+    /// Append the messages already received, stop consuming and return the
+    /// final stats
+    fn stop(&self, py: Python) -> PyResult<PyObject> {
+        let source = self
+            .source
+            .lock()
+            .take()
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Kafka source is stopped"))?;
+        let stats = py.allow_threads(|| get_runtime().block_on(source.stop()).into_py_result())?;
+        to_python_object(py, &stats)
+    }
+}
+
+#[pymethods]
+impl PyFlightServer {
+    /// Address the server listens on, as `host:port`
+    #[getter]
+    fn address(&self) -> PyResult<String> {
+        match self.server.lock().as_ref() {
+            Some(server) => Ok(server.address().to_string()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Flight server is stopped")),
+        }
+    }
+
+    /// Uploads written and failed, and the last error
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        match self.server.lock().as_ref() {
+            Some(server) => to_python_object(py, &server.stats()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Flight server is stopped")),
+        }
+    }
+
+    /// Whether the server is still accepting uploads
+    fn is_running(&self) -> bool {
+        self.server.lock().as_ref().is_some_and(FlightServer::is_running)
+    }
+
+    /// Let uploads in progress finish, stop the server and return the final
+    /// stats
+    fn stop(&self, py: Python) -> PyResult<PyObject> {
+        let server = self
+            .server
+            .lock()
+            .take()
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Flight server is stopped"))?;
+        let stats = py.allow_threads(|| get_runtime().block_on(server.stop()).into_py_result())?;
+        to_python_object(py, &stats)
+    }
+}
+
+#[pymethods]
+impl PySnapshotInfo {
+    /// String representation
+    fn __repr__(&self) -> String {
+        format!(
+            "SnapshotInfo(table={}, id={}, committed_at_ms={}, rows={})",
+            self.table_name,
+            self.snapshot_id,
+            self.committed_at_ms,
+            self.num_rows
+        )
+    }
+}
+
 #[pymethods]
 impl PyEngineStats {
     /// String representation
     fn __repr__(&self) -> String {
         format!(
-            "EngineStats(queries={}, avg_time={:.2}ms, peak_memory={:.2}MB, tables={})",
+            "EngineStats(queries={}, avg_time={:.2}ms, peak_memory={:.2}MB, tables={}, evicted={})",
             self.total_queries,
             self.avg_execution_time_ms,
             self.peak_memory_bytes as f64 / 1024.0 / 1024.0,
-            self.registered_tables
+            self.registered_tables,
+            self.evicted_tables
         )
     }
 }
 
 /// Create a new engine instance (convenience function)
 #[pyfunction]
-pub fn create_engine() -> PyResult<PyBlazeQueryEngine> {
-    PyBlazeQueryEngine::new()
+#[pyo3(signature = (config_path=None))]
+pub fn create_engine(config_path: Option<String>) -> PyResult<PyBlazeQueryEngine> {
+    PyBlazeQueryEngine::new(config_path)
+}
+
+/// Get the named engine, creating it on first use. Engines obtained this way
+/// share one memory budget, set with `set_global_memory_limit`.
+#[pyfunction]
+pub fn get_engine(name: String) -> PyResult<PyBlazeQueryEngine> {
+    let rt = get_runtime();
+    let registry = get_registry()?;
+    let engine = rt.block_on(async move {
+        registry.get_or_create(&name).await.into_py_result()
+    })?;
+
+    Ok(PyBlazeQueryEngine { engine })
+}
+
+/// Describe the engines created by `get_engine`
+#[pyfunction]
+pub fn list_engines(py: Python) -> PyResult<PyObject> {
+    let rt = get_runtime();
+    let registry = get_registry()?;
+    let engines = rt.block_on(async move {
+        registry.list().await
+    });
+
+    to_python_object(py, &engines)
+}
+
+/// Remove a named engine; existing handles to it keep working
+#[pyfunction]
+pub fn drop_engine(name: String) -> PyResult<()> {
+    let rt = get_runtime();
+    let registry = get_registry()?;
+    rt.block_on(async move {
+        registry.remove(&name).await.into_py_result()
+    })
+}
+
+/// Set the memory limit shared by all engines from `get_engine`
+#[pyfunction]
+pub fn set_global_memory_limit(memory_limit_bytes: usize) -> PyResult<()> {
+    get_registry()?.set_memory_limit(memory_limit_bytes);
+    Ok(())
+}
+
+/// Stable fingerprint of a query, equal for queries differing only in
+/// literals, whitespace, comments or keyword case
+#[pyfunction]
+pub fn fingerprint_sql(sql: &str) -> String {
+    utils::fingerprint_sql(sql)
+}
+
+/// The normalized form of a query that `fingerprint_sql` hashes
+#[pyfunction]
+pub fn normalize_sql(sql: &str) -> String {
+    utils::normalize_sql(sql)
+}
+
+/// Decompress a `QueryResult.payload` received elsewhere, returning the JSON
+/// bytes of its rows; `json.loads` turns them into a list of dicts
+#[pyfunction]
+pub fn decompress_payload<'py>(py: Python<'py>, payload: &[u8], compression: &str) -> PyResult<&'py PyBytes> {
+    let compression = PayloadCompression::parse(compression).into_py_result()?;
+    let json = compression.decompress(payload).into_py_result()?;
+    Ok(PyBytes::new(py, &json))
+}
+
+/// Read a result written by `execute_query_shared_sync` as a pyarrow Table,
+/// memory-mapping the file so the table's buffers are not copied
+#[pyfunction]
+pub fn open_shared_result(py: Python, path: String) -> PyResult<PyObject> {
+    let pyarrow = py.import("pyarrow")?;
+    let source = pyarrow.call_method1("memory_map", (path,))?;
+    let reader = py.import("pyarrow.ipc")?.call_method1("open_file", (source,))?;
+    Ok(reader.call_method0("read_all")?.into())
+}
+
+/// Helper function to convert any serializable value to a Python object
+fn to_python_object<T: serde::Serialize>(py: Python, value: &T) -> PyResult<PyObject> {
+    let value = serde_json::to_value(value).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON serialization error: {}", e))
+    })?;
+    json_value_to_python(py, &value)
+}
+
+/// Convert a result value to a dataclass field's type hint, or raise
+/// ValueError saying why it does not fit
+fn coerce_to_type(py: Python, value: &PyAny, hint: &PyAny) -> PyResult<PyObject> {
+    let typing = py.import("typing")?;
+    if hint.is(typing.getattr("Any")?) {
+        return Ok(value.into());
+    }
+    let mismatch = || -> PyResult<PyErr> {
+        Ok(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "cannot convert {} ({}) to {}",
+            value.repr()?,
+            value.get_type().name()?,
+            type_name(hint)?
+        )))
+    };
+
+    // Optional[X], X | None and other unions take the first member that fits
+    let origin = typing.call_method1("get_origin", (hint,))?;
+    let args: &PyTuple = typing.call_method1("get_args", (hint,))?.downcast()?;
+    let none_type = py.eval("type(None)", None, None)?;
+    // `X | Y` unions have their own origin from Python 3.10
+    let union_type = py.import("types")?.getattr("UnionType").ok();
+    if origin.is(typing.getattr("Union")?) || union_type.is_some_and(|union_type| origin.is(union_type)) {
+        if value.is_none() && args.iter().any(|arg| arg.is(none_type)) {
+            return Ok(py.None());
+        }
+        for arg in args.iter().filter(|arg| !arg.is(none_type)) {
+            if let Ok(converted) = coerce_to_type(py, value, arg) {
+                return Ok(converted);
+            }
+        }
+        return Err(mismatch()?);
+    }
+    if value.is_none() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "NULL is not allowed for non-Optional type {}",
+            type_name(hint)?
+        )));
+    }
+    // Generics such as list[int] are checked by their container type only
+    if !origin.is_none() {
+        return match value.is_instance(origin)? {
+            true => Ok(value.into()),
+            false => Err(mismatch()?),
+        };
+    }
+    let Ok(target) = hint.downcast::<PyType>() else {
+        return Ok(value.into());
+    };
+
+    let builtins = py.import("builtins")?;
+    let is_bool = value.is_instance_of::<PyBool>();
+    let is_number = (value.is_instance_of::<PyLong>() || value.is_instance_of::<PyFloat>()) && !is_bool;
+    if target.is(builtins.getattr("int")?) {
+        if is_bool {
+            return Err(mismatch()?);
+        }
+        if value.is_instance_of::<PyFloat>() && value.call_method0("is_integer")?.is_true()? {
+            return Ok(target.call1((value,))?.into());
+        }
+    }
+    if target.is(builtins.getattr("float")?) && is_number {
+        return Ok(target.call1((value,))?.into());
+    }
+    if value.is_instance(target)? {
+        return Ok(value.into());
+    }
+
+    let datetime = py.import("datetime")?;
+    if let Ok(text) = value.extract::<&str>() {
+        for name in ["datetime", "date", "time"] {
+            if target.is(datetime.getattr(name)?) {
+                return target.call_method1("fromisoformat", (text,)).map(Into::into).or_else(|_| Err(mismatch()?));
+            }
+        }
+    }
+    if target.is(py.import("decimal")?.getattr("Decimal")?) && (is_number || value.extract::<&str>().is_ok()) {
+        return target.call1((value.str()?,)).map(Into::into).or_else(|_| Err(mismatch()?));
+    }
+    Err(mismatch()?)
+}
+
+/// Readable name of a type hint: `int` for classes, the hint's repr otherwise
+fn type_name(hint: &PyAny) -> PyResult<String> {
+    match hint.downcast::<PyType>() {
+        Ok(class) => Ok(class.name()?.to_string()),
+        Err(_) => Ok(hint.repr()?.to_string()),
+    }
+}
+
+/// Hand a RecordBatch to pyarrow through the Arrow IPC stream format
+fn record_batch_to_pyarrow(py: Python, batch: &RecordBatch) -> PyResult<PyObject> {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new(&mut buffer, &batch.schema()).map_err(BlazeError::from).into_py_result()?;
+        writer.write(batch).map_err(BlazeError::from).into_py_result()?;
+        writer.finish().map_err(BlazeError::from).into_py_result()?;
+    }
+
+    let ipc = py.import("pyarrow.ipc")?;
+    let reader = ipc.call_method1("open_stream", (PyBytes::new(py, &buffer),))?;
+    Ok(reader.call_method0("read_next_batch")?.into())
 }
 
 /// Helper function to convert serde_json::Value to Python object
@@ -243,52 +2739,62 @@ fn json_value_to_python(py: Python, value: &serde_json::Value) -> PyResult<PyObj
     }
 }
 
-/// Create test data for benchmarking
-async fn create_test_data(
-    rows: usize
-) -> BlazeResult<Vec<datafusion::arrow::record_batch::RecordBatch>> {
-    use datafusion::arrow::array::*;
-    use datafusion::arrow::datatypes::{Schema, Field, DataType};
-    use std::sync::Arc;
-    use rand::Rng;
-
-    let mut rng = rand::thread_rng();
-    
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("id", DataType::Int64, false),
-        Field::new("value", DataType::Float64, false),
-        Field::new("category", DataType::Utf8, false),
-    ]));
-
-    let batch_size = 10_000;
-    let mut batches = Vec::new();
+/// Generate a synthetic dataset into a CSV file, returning the rows written.
+/// Columns take the same forms as `register_synthetic_data`.
+#[pyfunction]
+#[pyo3(signature = (path, rows, columns, seed=None))]
+pub fn generate_csv(path: String, rows: usize, columns: Vec<&PyAny>, seed: Option<u64>) -> PyResult<usize> {
+    let spec = dataset_spec(rows, columns, seed)?;
+    let batches = datagen::generate(&spec).into_py_result()?;
+    datagen::write_csv(path, &batches).into_py_result()
+}
 
-    for batch_start in (0..rows).step_by(batch_size) {
-        let batch_rows = std::cmp::min(batch_size, rows - batch_start);
-        
-        let id_array = Int64Array::from_iter_values(
-            (batch_start..batch_start + batch_rows).map(|i| i as i64)
-        );
-        
-        let value_array = Float64Array::from_iter_values(
-            (0..batch_rows).map(|_| rng.gen_range(0.0..1000.0))
-        );
-        
-        let category_array = StringArray::from_iter_values(
-            (0..batch_rows).map(|i| format!("category_{}", (batch_start + i) % 10))
-        );
+/// Build a dataset spec from Python column dicts or spec strings
+fn dataset_spec(rows: usize, columns: Vec<&PyAny>, seed: Option<u64>) -> PyResult<DatasetSpec> {
+    let columns = columns
+        .into_iter()
+        .map(|column| {
+            if let Ok(text) = column.extract::<&str>() {
+                return text.parse::<ColumnSpec>().into_py_result();
+            }
+            let value = python_to_json_value(column)?;
+            serde_json::from_value(value).map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid column spec: {}", e))
+            })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
 
-        let batch = datafusion::arrow::record_batch::RecordBatch::try_new(
-            schema.clone(),
-            vec![
-                Arc::new(id_array),
-                Arc::new(value_array),
-                Arc::new(category_array),
-            ],
-        )?;
+    let spec = DatasetSpec::new(rows, columns);
+    Ok(match seed {
+        Some(seed) => spec.with_seed(seed),
+        None => spec,
+    })
+}
 
-        batches.push(batch);
+/// Helper function to convert a Python object to serde_json::Value
+fn python_to_json_value(value: &PyAny) -> PyResult<serde_json::Value> {
+    if value.is_none() {
+        Ok(serde_json::Value::Null)
+    } else if let Ok(b) = value.downcast::<pyo3::types::PyBool>() {
+        Ok(b.is_true().into())
+    } else if let Ok(i) = value.extract::<i64>() {
+        Ok(i.into())
+    } else if let Ok(f) = value.extract::<f64>() {
+        Ok(serde_json::Number::from_f64(f).map_or(serde_json::Value::Null, serde_json::Value::Number))
+    } else if let Ok(s) = value.extract::<String>() {
+        Ok(s.into())
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = serde_json::Map::new();
+        for (key, val) in dict {
+            map.insert(key.extract::<String>()?, python_to_json_value(val)?);
+        }
+        Ok(map.into())
+    } else if let Ok(list) = value.downcast::<PyList>() {
+        Ok(list.iter().map(python_to_json_value).collect::<PyResult<Vec<_>>>()?.into())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "Unsupported value: {}",
+            value
+        )))
     }
-
-    Ok(batches)
-}
\ No newline at end of file
+}
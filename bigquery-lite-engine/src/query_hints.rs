@@ -0,0 +1,365 @@
+//! Planner hints in SQL comments
+//!
+//! A query carries hints in a `/*+ ... */` comment, usually right after
+//! `SELECT`:
+//!
+//! ```text
+//! SELECT /*+ BROADCAST(d) REPARTITION(16) */ f.amount, d.name
+//! FROM fact f JOIN dim d ON f.dim_id = d.id
+//! ```
+//!
+//! - `BROADCAST(t, ...)` builds the hash table of each join reading `t`
+//!   directly from all of `t`, collected once, and streams the other side
+//!   past it.
+//! - `SHUFFLE_HASH(t, ...)` also builds from `t`, but repartitions both
+//!   sides on the join keys first.
+//! - `REPARTITION(n)` plans the query with `n` partitions. A resource
+//!   group's CPU share takes precedence.
+//!
+//! Tables are named as the query names them, by alias or by table name. The
+//! join hints override the join selection the planner makes from table
+//! statistics, which has nothing to go on for tables without them. `EXPLAIN`
+//! output gets a `query_hints` row listing the hints applied. Hints on
+//! statements other than queries are ignored.
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use async_trait::async_trait;
+use datafusion::arrow::array::StringArray;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::Session;
+use datafusion::common::tree_node::{Transformed, TreeNode, TreeNodeRecursion};
+use datafusion::config::ConfigOptions;
+use datafusion::datasource::{provider_as_source, source_as_provider, TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::session_state::SessionStateBuilder;
+use datafusion::logical_expr::{Expr, LogicalPlan, TableProviderFilterPushDown};
+use datafusion::physical_optimizer::join_selection::swap_hash_join;
+use datafusion::physical_optimizer::PhysicalOptimizerRule;
+use datafusion::physical_plan::joins::{HashJoinExec, PartitionMode};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::{DataFrame, SessionContext};
+use regex::Regex;
+use tracing::warn;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// A hint from a `/*+ ... */` comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum QueryHint {
+    /// Collect these tables whole as the build side of their joins
+    Broadcast(Vec<String>),
+    /// Build from these tables after repartitioning both join sides
+    ShuffleHash(Vec<String>),
+    /// Plan with this many partitions
+    Repartition(usize),
+}
+
+impl fmt::Display for QueryHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryHint::Broadcast(tables) => write!(f, "BROADCAST({})", tables.join(", ")),
+            QueryHint::ShuffleHash(tables) => write!(f, "SHUFFLE_HASH({})", tables.join(", ")),
+            QueryHint::Repartition(partitions) => write!(f, "REPARTITION({})", partitions),
+        }
+    }
+}
+
+/// The hints in every `/*+ ... */` comment of `sql`, in order
+pub(crate) fn parse_hints(sql: &str) -> BlazeResult<Vec<QueryHint>> {
+    if !sql.contains("/*+") {
+        return Ok(Vec::new());
+    }
+    static COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)/\*\+(.*?)\*/").unwrap());
+    static HINT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([A-Za-z_]+)\s*\(([^)]*)\)").unwrap());
+
+    let mut hints = Vec::new();
+    for body in COMMENT.captures_iter(sql) {
+        let mut rest = body[1].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        while !rest.is_empty() {
+            let captures = HINT
+                .captures(rest)
+                .ok_or_else(|| invalid_input!("Malformed query hint '{}'", rest.trim_end()))?;
+            let args: Vec<String> = captures[2]
+                .split(',')
+                .map(|arg| arg.trim().trim_matches(|c| c == '"' || c == '`').to_lowercase())
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            let name = captures[1].to_uppercase();
+            hints.push(match name.as_str() {
+                "BROADCAST" | "SHUFFLE_HASH" if args.is_empty() => {
+                    return Err(invalid_input!("Query hint {} needs at least one table", name))
+                }
+                "BROADCAST" => QueryHint::Broadcast(args),
+                "SHUFFLE_HASH" => QueryHint::ShuffleHash(args),
+                "REPARTITION" => match args.as_slice() {
+                    [partitions] => match partitions.parse::<usize>() {
+                        Ok(partitions) if partitions > 0 => QueryHint::Repartition(partitions),
+                        _ => return Err(invalid_input!("REPARTITION needs a positive partition count, got '{}'", partitions)),
+                    },
+                    _ => return Err(invalid_input!("REPARTITION takes one partition count")),
+                },
+                _ => return Err(invalid_input!("Unknown query hint '{}'", &captures[1])),
+            });
+            rest = rest[captures[0].len()..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        }
+    }
+    Ok(hints)
+}
+
+/// Plan `plan` with `hints` applied. The hints only change queries; other
+/// statements are executed as they are.
+pub(crate) async fn execute_hinted(ctx: &SessionContext, plan: LogicalPlan, hints: &[QueryHint]) -> Result<DataFrame> {
+    if hints.is_empty() {
+        return ctx.execute_logical_plan(plan).await;
+    }
+    if matches!(
+        plan,
+        LogicalPlan::Ddl(_) | LogicalPlan::Dml(_) | LogicalPlan::Copy(_) | LogicalPlan::Statement(_)
+    ) {
+        warn!("Ignoring query hints on a statement that is not a query");
+        return ctx.execute_logical_plan(plan).await;
+    }
+
+    let mut build_sides = HashMap::new();
+    let mut partitions = None;
+    for hint in hints {
+        let (tables, mode) = match hint {
+            QueryHint::Broadcast(tables) => (tables, PartitionMode::CollectLeft),
+            QueryHint::ShuffleHash(tables) => (tables, PartitionMode::Partitioned),
+            QueryHint::Repartition(n) => {
+                partitions = Some(*n);
+                continue;
+            }
+        };
+        for table in tables {
+            if build_sides.insert(table.clone(), mode).is_some_and(|other| other != mode) {
+                return Err(DataFusionError::Plan(format!(
+                    "Query hints ask for both BROADCAST and SHUFFLE_HASH joins of '{}'",
+                    table
+                )));
+            }
+        }
+    }
+
+    let scans = Arc::new(HintedScans::default());
+    let plan = mark_build_sides(plan, &build_sides, &scans)?;
+
+    let state = ctx.state();
+    let mut builder = SessionStateBuilder::new_from_existing(state.clone());
+    if !build_sides.is_empty() {
+        // Right after join selection, before repartitioning is planned
+        let mut rules = state.physical_optimizers().to_vec();
+        let position = rules.iter().position(|rule| rule.name() == "join_selection").map_or(0, |i| i + 1);
+        rules.insert(position, Arc::new(HintedJoins { scans }));
+        builder = builder.with_physical_optimizer_rules(rules);
+    }
+    let mut state = builder.build();
+    if let Some(partitions) = partitions {
+        state.config_mut().options_mut().execution.target_partitions = partitions;
+    }
+
+    let explain = matches!(plan, LogicalPlan::Explain(_) | LogicalPlan::Analyze(_));
+    let df = DataFrame::new(state, plan);
+    if !explain {
+        return Ok(df);
+    }
+    // Explain plans must be the root of a plan, so the hints row is added
+    // to the explanation itself
+    let schema: SchemaRef = Arc::new(df.schema().as_arrow().clone());
+    let mut batches = df.collect().await?;
+    let applied: Vec<String> = hints.iter().map(ToString::to_string).collect();
+    batches.push(RecordBatch::try_new(
+        schema,
+        vec![Arc::new(StringArray::from(vec!["query_hints"])), Arc::new(StringArray::from(vec![applied.join(" ")]))],
+    )?);
+    ctx.read_batches(batches)
+}
+
+/// Wrap every table scan named in `build_sides`, by table name or by the
+/// alias of a subquery, so its physical scans are recorded in `scans`
+fn mark_build_sides(
+    plan: LogicalPlan,
+    build_sides: &HashMap<String, PartitionMode>,
+    scans: &Arc<HintedScans>,
+) -> Result<LogicalPlan> {
+    if build_sides.is_empty() {
+        return Ok(plan);
+    }
+    let mut found = HashSet::new();
+    let marked = plan.transform_down_with_subqueries(|node| match node {
+        LogicalPlan::SubqueryAlias(mut alias) => match build_sides.get(&alias.alias.table().to_lowercase()) {
+            Some(mode) => {
+                found.insert(alias.alias.table().to_lowercase());
+                let input = Arc::unwrap_or_clone(alias.input).transform_up(|node| mark_scan(node, *mode, scans))?;
+                alias.input = Arc::new(input.data);
+                Ok(Transformed::new(LogicalPlan::SubqueryAlias(alias), true, TreeNodeRecursion::Jump))
+            }
+            None => Ok(Transformed::no(LogicalPlan::SubqueryAlias(alias))),
+        },
+        LogicalPlan::TableScan(scan) => match build_sides.get(&scan.table_name.table().to_lowercase()) {
+            Some(mode) => {
+                found.insert(scan.table_name.table().to_lowercase());
+                mark_scan(LogicalPlan::TableScan(scan), *mode, scans)
+            }
+            None => Ok(Transformed::no(LogicalPlan::TableScan(scan))),
+        },
+        node => Ok(Transformed::no(node)),
+    })?;
+
+    let mut missing: Vec<_> = build_sides.keys().filter(|table| !found.contains(*table)).cloned().collect();
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(DataFusionError::Plan(format!(
+            "Query hints name tables the query does not read: {}",
+            missing.join(", ")
+        )));
+    }
+    Ok(marked.data)
+}
+
+fn mark_scan(node: LogicalPlan, mode: PartitionMode, scans: &Arc<HintedScans>) -> Result<Transformed<LogicalPlan>> {
+    let LogicalPlan::TableScan(mut scan) = node else {
+        return Ok(Transformed::no(node));
+    };
+    let Ok(inner) = source_as_provider(&scan.source) else {
+        return Ok(Transformed::no(LogicalPlan::TableScan(scan)));
+    };
+    if inner.as_any().is::<HintedTable>() {
+        return Ok(Transformed::no(LogicalPlan::TableScan(scan)));
+    }
+    scan.source = provider_as_source(Arc::new(HintedTable { inner, mode, scans: scans.clone() }));
+    Ok(Transformed::yes(LogicalPlan::TableScan(scan)))
+}
+
+/// Physical scans of hinted tables, with the join mode their hint asks for
+#[derive(Debug, Default)]
+struct HintedScans {
+    scans: Mutex<Vec<(Arc<dyn ExecutionPlan>, PartitionMode)>>,
+}
+
+impl HintedScans {
+    /// The mode hinted for `plan`, if it scans a hinted table without
+    /// joining it to anything first
+    fn mode(&self, plan: &Arc<dyn ExecutionPlan>) -> Option<PartitionMode> {
+        if let Some((_, mode)) = self.scans.lock().unwrap().iter().find(|(scan, _)| Arc::ptr_eq(scan, plan)) {
+            return Some(*mode);
+        }
+        match plan.children().as_slice() {
+            [child] => self.mode(child),
+            _ => None,
+        }
+    }
+}
+
+/// A table named by a join hint, recording the scans planned for it
+#[derive(Debug)]
+struct HintedTable {
+    inner: Arc<dyn TableProvider>,
+    mode: PartitionMode,
+    scans: Arc<HintedScans>,
+}
+
+#[async_trait]
+impl TableProvider for HintedTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        self.inner.table_type()
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let scan = self.inner.scan(state, projection, filters, limit).await?;
+        self.scans.scans.lock().unwrap().push((scan.clone(), self.mode));
+        Ok(scan)
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        self.inner.supports_filters_pushdown(filters)
+    }
+}
+
+/// Physical optimizer rule making hinted tables the build side of their
+/// hash joins, in the hinted mode
+#[derive(Debug)]
+struct HintedJoins {
+    scans: Arc<HintedScans>,
+}
+
+impl PhysicalOptimizerRule for HintedJoins {
+    fn optimize(&self, plan: Arc<dyn ExecutionPlan>, _config: &ConfigOptions) -> Result<Arc<dyn ExecutionPlan>> {
+        let optimized = plan.transform_up(|node| {
+            let Some(join) = node.as_any().downcast_ref::<HashJoinExec>() else {
+                return Ok(Transformed::no(node));
+            };
+            if let Some(mode) = self.scans.mode(join.left()) {
+                if mode == *join.partition_mode() {
+                    return Ok(Transformed::no(node));
+                }
+                let rebuilt = HashJoinExec::try_new(
+                    join.left().clone(),
+                    join.right().clone(),
+                    join.on().to_vec(),
+                    join.filter().cloned(),
+                    join.join_type(),
+                    join.projection.clone(),
+                    mode,
+                    join.null_equals_null(),
+                )?;
+                return Ok(Transformed::yes(Arc::new(rebuilt)));
+            }
+            match self.scans.mode(join.right()) {
+                Some(mode) => Ok(Transformed::yes(swap_hash_join(join, mode)?)),
+                None => Ok(Transformed::no(node)),
+            }
+        })?;
+        Ok(optimized.data)
+    }
+
+    fn name(&self) -> &str {
+        "query_hints"
+    }
+
+    fn schema_check(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hints() {
+        let hints = parse_hints("SELECT /*+ broadcast(d, \"Dim2\") , REPARTITION( 16 ) */ 1 /*+SHUFFLE_HASH(f)*/").unwrap();
+        assert_eq!(hints, vec![
+            QueryHint::Broadcast(vec!["d".to_string(), "dim2".to_string()]),
+            QueryHint::Repartition(16),
+            QueryHint::ShuffleHash(vec!["f".to_string()]),
+        ]);
+        assert_eq!(hints[0].to_string(), "BROADCAST(d, dim2)");
+        assert!(parse_hints("SELECT /* plain comment */ 1").unwrap().is_empty());
+
+        assert!(parse_hints("SELECT /*+ BROADCAST() */ 1").is_err());
+        assert!(parse_hints("SELECT /*+ REPARTITION(0) */ 1").is_err());
+        assert!(parse_hints("SELECT /*+ MERGE(d) */ 1").is_err());
+        assert!(parse_hints("SELECT /*+ BROADCAST(d) junk */ 1").is_err());
+    }
+}
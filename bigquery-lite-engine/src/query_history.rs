@@ -0,0 +1,353 @@
+//! History of executed queries
+//!
+//! Every query run with `execute_query` or `execute_prepared` is recorded
+//! when it finishes, whether it succeeded, failed, was cancelled, timed out
+//! or was rejected by admission control, like BigQuery's
+//! `INFORMATION_SCHEMA.JOBS`. `get_query_history` returns the recorded
+//! queries matching a `QueryHistoryFilter`, most recent first.
+//!
+//! The history keeps the last `query_history_size` queries. With
+//! `query_history_path` set, entries are also appended to that file as JSON
+//! lines and read back when an engine is created with the same path, so the
+//! history outlives the process. The file is rewritten with only the kept
+//! entries once it holds twice as many; failing to write it is logged and
+//! does not fail the query.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::admission::QueryPriority;
+use crate::config_error;
+use crate::engine::{QueryOptions, QueryResult};
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::utils::fingerprint_sql;
+
+/// How a recorded query ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryStatus {
+    Succeeded,
+    Failed,
+    /// Cancelled with `cancel_query` or by shutdown
+    Cancelled,
+    /// Ran longer than its timeout
+    TimedOut,
+    /// Rejected by admission control before it started
+    Rejected,
+}
+
+impl QueryStatus {
+    /// Parse a status name, e.g. `succeeded` or `timed_out`
+    pub fn parse(name: &str) -> BlazeResult<Self> {
+        match name.to_lowercase().as_str() {
+            "succeeded" => Ok(Self::Succeeded),
+            "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
+            "timed_out" => Ok(Self::TimedOut),
+            "rejected" => Ok(Self::Rejected),
+            _ => Err(invalid_input!(
+                "Unsupported query status '{}'; expected succeeded, failed, cancelled, timed_out or rejected",
+                name
+            )),
+        }
+    }
+
+    fn of(error: &BlazeError) -> Self {
+        match error {
+            BlazeError::Cancelled(_) | BlazeError::ShuttingDown => Self::Cancelled,
+            BlazeError::Timeout { .. } => Self::TimedOut,
+            BlazeError::Overloaded(_) => Self::Rejected,
+            _ => Self::Failed,
+        }
+    }
+}
+
+/// A query recorded in the history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryHistoryEntry {
+    pub query_id: String,
+    pub sql: String,
+    /// Fingerprint of the SQL, shared by queries differing only in literals
+    pub fingerprint: String,
+    /// Session the query ran in
+    pub session_id: Option<String>,
+    pub priority: QueryPriority,
+    pub started_at: DateTime<Utc>,
+    /// Milliseconds from submission to completion, including time queued
+    pub duration_ms: u64,
+    /// Rows returned; 0 unless the query succeeded
+    pub rows: usize,
+    /// Bytes of the rows returned in Arrow memory
+    pub result_bytes: u64,
+    /// Bytes written to files by a `COPY ... TO` or `EXPORT DATA` statement
+    pub bytes_written: Option<u64>,
+    /// Memory used during execution in bytes
+    pub memory_used_bytes: u64,
+    /// Whether the result was served from the result cache
+    pub cache_hit: bool,
+    pub status: QueryStatus,
+    /// Error message of a query that did not succeed
+    pub error: Option<String>,
+}
+
+impl QueryHistoryEntry {
+    pub(crate) fn new(
+        query_id: &str,
+        sql: &str,
+        options: &QueryOptions,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+        result: &BlazeResult<QueryResult>,
+    ) -> Self {
+        let mut entry = Self {
+            query_id: query_id.to_string(),
+            sql: sql.to_string(),
+            fingerprint: fingerprint_sql(sql),
+            session_id: options.session_id.clone(),
+            priority: options.priority,
+            started_at,
+            duration_ms: duration.as_millis() as u64,
+            rows: 0,
+            result_bytes: 0,
+            bytes_written: None,
+            memory_used_bytes: 0,
+            cache_hit: false,
+            status: QueryStatus::Succeeded,
+            error: None,
+        };
+        match result {
+            Ok(result) => {
+                entry.rows = result.rows;
+                entry.result_bytes = result.result_bytes;
+                entry.bytes_written = result.bytes_written;
+                entry.memory_used_bytes = result.memory_used_bytes;
+                entry.cache_hit = result.cache_hit;
+            }
+            Err(e) => {
+                entry.status = QueryStatus::of(e);
+                entry.error = Some(e.to_string());
+            }
+        }
+        entry
+    }
+}
+
+/// Which recorded queries `get_query_history` returns; unset fields match
+/// every query
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryHistoryFilter {
+    /// Queries started at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Queries started before this time
+    pub until: Option<DateTime<Utc>>,
+    pub status: Option<QueryStatus>,
+    /// Queries whose SQL contains this text, ignoring case
+    pub sql_contains: Option<String>,
+    pub fingerprint: Option<String>,
+    pub session_id: Option<String>,
+    /// Queries that took at least this many milliseconds
+    pub min_duration_ms: Option<u64>,
+    /// Return at most this many queries, the most recent ones
+    pub limit: Option<usize>,
+}
+
+impl QueryHistoryFilter {
+    fn matches(&self, entry: &QueryHistoryEntry, sql_contains: Option<&str>) -> bool {
+        self.since.is_none_or(|since| entry.started_at >= since)
+            && self.until.is_none_or(|until| entry.started_at < until)
+            && self.status.is_none_or(|status| entry.status == status)
+            && sql_contains.is_none_or(|text| entry.sql.to_lowercase().contains(text))
+            && self.fingerprint.as_ref().is_none_or(|fingerprint| &entry.fingerprint == fingerprint)
+            && self.session_id.as_ref().is_none_or(|id| entry.session_id.as_ref() == Some(id))
+            && self.min_duration_ms.is_none_or(|min| entry.duration_ms >= min)
+    }
+}
+
+/// The file recorded queries are appended to
+struct HistoryFile {
+    path: PathBuf,
+    writer: File,
+    /// Entries in the file, including those no longer kept
+    lines: usize,
+}
+
+impl HistoryFile {
+    fn open(path: &Path, lines: usize) -> BlazeResult<Self> {
+        let writer = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { path: path.to_path_buf(), writer, lines })
+    }
+
+    fn append(&mut self, entry: &QueryHistoryEntry) -> BlazeResult<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Replace the file with `entries`
+    fn rewrite<'a>(&mut self, entries: impl Iterator<Item = &'a QueryHistoryEntry>) -> BlazeResult<()> {
+        let partial = self.path.with_extension("partial");
+        let mut file = std::io::BufWriter::new(File::create(&partial)?);
+        let mut lines = 0;
+        for entry in entries {
+            serde_json::to_writer(&mut file, entry)?;
+            file.write_all(b"\n")?;
+            lines += 1;
+        }
+        file.flush()?;
+        drop(file);
+        std::fs::rename(&partial, &self.path)?;
+        *self = Self::open(&self.path, lines)?;
+        Ok(())
+    }
+}
+
+struct HistoryState {
+    entries: VecDeque<QueryHistoryEntry>,
+    max_entries: usize,
+    file: Option<HistoryFile>,
+}
+
+impl HistoryState {
+    /// Drop the oldest entries beyond `max_entries`, compacting the file
+    /// once it holds twice as many
+    fn trim(&mut self) {
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        if file.lines > 2 * self.max_entries.max(1) || (self.max_entries == 0 && file.lines > 0) {
+            if let Err(e) = file.rewrite(self.entries.iter()) {
+                warn!("Failed to rewrite query history {}: {}", file.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Recently executed queries, kept in memory and optionally in a file
+pub(crate) struct QueryHistory {
+    state: Mutex<HistoryState>,
+}
+
+impl QueryHistory {
+    /// Keep the last `max_entries` queries, reading and appending to `path`
+    /// if given
+    pub(crate) fn open(path: Option<&Path>, max_entries: usize) -> BlazeResult<Self> {
+        let mut entries = VecDeque::new();
+        let file = match path {
+            Some(path) => {
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| config_error!("Cannot create query history directory {}: {}", parent.display(), e))?;
+                }
+                let mut lines = 0;
+                if path.exists() {
+                    for line in BufReader::new(File::open(path)?).lines() {
+                        let line = line?;
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        lines += 1;
+                        // A line cut short by a crash loses only its query
+                        match serde_json::from_str(&line) {
+                            Ok(entry) => entries.push_back(entry),
+                            Err(e) => warn!("Skipping unreadable query history entry in {}: {}", path.display(), e),
+                        }
+                    }
+                }
+                Some(HistoryFile::open(path, lines)?)
+            }
+            None => None,
+        };
+        let mut state = HistoryState { entries, max_entries, file };
+        state.trim();
+        Ok(Self { state: Mutex::new(state) })
+    }
+
+    /// Change how many queries are kept, dropping the oldest ones beyond it
+    pub(crate) fn set_max_entries(&self, max_entries: usize) {
+        let mut state = self.state.lock();
+        state.max_entries = max_entries;
+        state.trim();
+    }
+
+    pub(crate) fn record(&self, entry: QueryHistoryEntry) {
+        let mut state = self.state.lock();
+        if state.max_entries == 0 {
+            return;
+        }
+        if let Some(file) = &mut state.file {
+            if let Err(e) = file.append(&entry) {
+                warn!("Failed to append to query history {}: {}", file.path.display(), e);
+            }
+        }
+        state.entries.push_back(entry);
+        state.trim();
+    }
+
+    /// Recorded queries matching `filter`, most recent first
+    pub(crate) fn query(&self, filter: &QueryHistoryFilter) -> Vec<QueryHistoryEntry> {
+        let sql_contains = filter.sql_contains.as_ref().map(|text| text.to_lowercase());
+        let state = self.state.lock();
+        state
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry, sql_contains.as_deref()))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(query_id: &str, sql: &str, result: BlazeResult<QueryResult>) -> QueryHistoryEntry {
+        let started_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        QueryHistoryEntry::new(query_id, sql, &QueryOptions::default(), started_at, Duration::from_millis(5), &result)
+    }
+
+    #[test]
+    fn test_filters_and_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history").join("queries.jsonl");
+
+        let history = QueryHistory::open(Some(&path), 3).unwrap();
+        history.record(entry("q1", "SELECT 1", Err(BlazeError::Timeout { timeout_ms: 10 })));
+        history.record(entry("q2", "SELECT * FROM sales", Err(invalid_input!("bad"))));
+        history.record(entry("q3", "select count(*) from SALES", Err(BlazeError::Overloaded("full".to_string()))));
+        history.record(entry("q4", "SELECT 2", Err(BlazeError::Timeout { timeout_ms: 10 })));
+
+        let ids = |entries: Vec<QueryHistoryEntry>| entries.into_iter().map(|e| e.query_id).collect::<Vec<_>>();
+        assert_eq!(ids(history.query(&QueryHistoryFilter::default())), ["q4", "q3", "q2"]);
+        let filter = QueryHistoryFilter { sql_contains: Some("Sales".to_string()), ..Default::default() };
+        assert_eq!(ids(history.query(&filter)), ["q3", "q2"]);
+        let filter = QueryHistoryFilter { status: Some(QueryStatus::TimedOut), limit: Some(1), ..Default::default() };
+        assert_eq!(ids(history.query(&filter)), ["q4"]);
+        assert_eq!(history.query(&filter)[0].error.as_deref(), Some("Query timed out after 10ms"));
+
+        // A new history over the same file sees the kept entries
+        drop(history);
+        let history = QueryHistory::open(Some(&path), 2).unwrap();
+        assert_eq!(ids(history.query(&QueryHistoryFilter::default())), ["q4", "q3"]);
+        history.set_max_entries(0);
+        history.record(entry("q5", "SELECT 3", Err(invalid_input!("bad"))));
+        assert!(history.query(&QueryHistoryFilter::default()).is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+}
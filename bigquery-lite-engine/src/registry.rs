@@ -0,0 +1,155 @@
+//! Named engines sharing one memory budget
+//!
+//! An `EngineRegistry` holds one `BlazeQueryEngine` per tenant or workload.
+//! Each engine keeps its own catalog and memory limit, and every reservation
+//! also counts against the registry's global limit, so together the engines
+//! cannot use more than the budget.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use datafusion::execution::memory_pool::MemoryPool;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::engine::{BlazeQueryEngine, EngineConfig, EngineStats};
+use crate::error::{BlazeError, BlazeResult};
+use crate::memory_pool::ResizableMemoryPool;
+
+/// Memory use and statistics of one registered engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredEngineInfo {
+    /// Engine name
+    pub name: String,
+    /// The engine's own memory limit in bytes
+    pub memory_limit_bytes: usize,
+    /// Bytes the engine currently has reserved
+    pub reserved_memory_bytes: usize,
+    /// Query statistics
+    pub stats: EngineStats,
+}
+
+/// Named engines with a shared global memory limit
+pub struct EngineRegistry {
+    /// Configuration for engines created without one
+    default_config: EngineConfig,
+    /// Parent pool every engine's reservations are charged to
+    memory_pool: Arc<ResizableMemoryPool>,
+    engines: RwLock<HashMap<String, Arc<BlazeQueryEngine>>>,
+}
+
+impl EngineRegistry {
+    /// Create an empty registry whose engines may together reserve at most
+    /// `global_memory_limit_bytes`
+    pub fn new(global_memory_limit_bytes: usize) -> Self {
+        Self::with_default_config(global_memory_limit_bytes, EngineConfig::default())
+    }
+
+    /// Create an empty registry using `default_config` for engines created by
+    /// `get_or_create`
+    pub fn with_default_config(global_memory_limit_bytes: usize, default_config: EngineConfig) -> Self {
+        Self {
+            default_config,
+            memory_pool: Arc::new(ResizableMemoryPool::new(global_memory_limit_bytes)),
+            engines: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Create a named engine; fails if the name is taken
+    pub async fn create(&self, name: &str, config: EngineConfig) -> BlazeResult<Arc<BlazeQueryEngine>> {
+        let mut engines = self.engines.write().await;
+        if engines.contains_key(name) {
+            return Err(BlazeError::InvalidInput(format!("Engine '{}' already exists", name)));
+        }
+
+        let engine = Arc::new(self.new_engine(config).await?);
+        engines.insert(name.to_string(), engine.clone());
+        info!("Registered engine '{}'", name);
+        Ok(engine)
+    }
+
+    /// The named engine, created with the default configuration if missing
+    pub async fn get_or_create(&self, name: &str) -> BlazeResult<Arc<BlazeQueryEngine>> {
+        if let Some(engine) = self.get(name).await {
+            return Ok(engine);
+        }
+
+        let mut engines = self.engines.write().await;
+        if let Some(engine) = engines.get(name) {
+            return Ok(engine.clone());
+        }
+        let engine = Arc::new(self.new_engine(self.default_config.clone()).await?);
+        engines.insert(name.to_string(), engine.clone());
+        info!("Registered engine '{}'", name);
+        Ok(engine)
+    }
+
+    /// The named engine, if registered
+    pub async fn get(&self, name: &str) -> Option<Arc<BlazeQueryEngine>> {
+        self.engines.read().await.get(name).cloned()
+    }
+
+    /// Remove an engine from the registry. Its memory returns to the shared
+    /// budget once the last handle to it is dropped.
+    pub async fn remove(&self, name: &str) -> BlazeResult<()> {
+        self.engines
+            .write()
+            .await
+            .remove(name)
+            .map(|_| info!("Removed engine '{}'", name))
+            .ok_or_else(|| BlazeError::InvalidInput(format!("Engine '{}' not found", name)))
+    }
+
+    /// Names of registered engines, sorted
+    pub async fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.engines.read().await.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Describe every registered engine, sorted by name
+    pub async fn list(&self) -> Vec<RegisteredEngineInfo> {
+        let engines: Vec<_> = self
+            .engines
+            .read()
+            .await
+            .iter()
+            .map(|(name, engine)| (name.clone(), engine.clone()))
+            .collect();
+
+        let mut infos = Vec::with_capacity(engines.len());
+        for (name, engine) in engines {
+            infos.push(RegisteredEngineInfo {
+                name,
+                memory_limit_bytes: engine.config().await.memory_limit_bytes,
+                reserved_memory_bytes: engine.reserved_memory_bytes(),
+                stats: engine.get_stats().await,
+            });
+        }
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    /// Global memory limit shared by all engines
+    pub fn memory_limit_bytes(&self) -> usize {
+        self.memory_pool.limit()
+    }
+
+    /// Change the global memory limit; engines already holding more keep it,
+    /// but new reservations fail until usage drops below the limit
+    pub fn set_memory_limit(&self, limit: usize) {
+        self.memory_pool.set_limit(limit);
+        info!("Set shared engine memory limit to {}MB", limit / 1024 / 1024);
+    }
+
+    /// Bytes reserved across all engines
+    pub fn reserved_memory_bytes(&self) -> usize {
+        self.memory_pool.reserved()
+    }
+
+    async fn new_engine(&self, config: EngineConfig) -> BlazeResult<BlazeQueryEngine> {
+        let pool = ResizableMemoryPool::with_parent(config.memory_limit_bytes, self.memory_pool.clone());
+        BlazeQueryEngine::with_memory_pool(config, Arc::new(pool)).await
+    }
+}
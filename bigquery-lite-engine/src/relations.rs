@@ -0,0 +1,140 @@
+//! Catalog operations for dbt-style adapters
+//!
+//! An adapter materializes a model by creating a table from its SELECT,
+//! swapping it in with renames and dropping what is left over, and reads
+//! the catalog to find out what already exists. These are the engine APIs
+//! it builds on; relation names are unqualified tables and views of the
+//! default schema.
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::array::{Array, TimestampMicrosecondArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlazeResult;
+
+/// Kind of a relation in the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationType {
+    /// Table held by the engine
+    Table,
+    /// View defined with `CREATE VIEW`
+    View,
+    /// Materialized view maintained by the engine
+    MaterializedView,
+    /// Table scanning files or a Flight service in place
+    External,
+}
+
+/// A table or view of the default schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationInfo {
+    /// Relation name
+    pub name: String,
+    /// Kind of relation
+    pub relation_type: RelationType,
+}
+
+/// A column of a relation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnInfo {
+    /// Column name
+    pub name: String,
+    /// SQL type name, e.g. `INT64` or `ARRAY<STRING>`
+    pub data_type: String,
+    /// Whether the column may hold NULLs
+    pub nullable: bool,
+}
+
+/// Result of a source freshness check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreshnessInfo {
+    /// Table checked
+    pub table_name: String,
+    /// Latest value of the loaded-at column, `None` if the table is empty
+    pub max_loaded_at: Option<DateTime<Utc>>,
+    /// When the check ran
+    pub snapshotted_at: DateTime<Utc>,
+}
+
+/// Columns of a schema in order
+pub(crate) fn columns(schema: &Schema) -> Vec<ColumnInfo> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| ColumnInfo {
+            name: field.name().clone(),
+            data_type: sql_type_name(field.data_type()),
+            nullable: field.is_nullable(),
+        })
+        .collect()
+}
+
+/// SQL name of a column type, using the names `parse_type_name` accepts
+/// where there is one
+pub(crate) fn sql_type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => "INT64".to_string(),
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => "INT64".to_string(),
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => "FLOAT64".to_string(),
+        DataType::Boolean => "BOOL".to_string(),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => "STRING".to_string(),
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView | DataType::FixedSizeBinary(_) => {
+            "BYTES".to_string()
+        }
+        DataType::Date32 | DataType::Date64 => "DATE".to_string(),
+        DataType::Timestamp(_, None) => "TIMESTAMP".to_string(),
+        DataType::Timestamp(_, Some(zone)) => format!("TIMESTAMP WITH TIME ZONE '{}'", zone),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            format!("NUMERIC({}, {})", precision, scale)
+        }
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            format!("ARRAY<{}>", sql_type_name(field.data_type()))
+        }
+        DataType::Struct(fields) => {
+            let fields: Vec<_> = fields.iter().map(|f| format!("{} {}", f.name(), sql_type_name(f.data_type()))).collect();
+            format!("STRUCT<{}>", fields.join(", "))
+        }
+        other => other.to_string().to_uppercase(),
+    }
+}
+
+/// The single value of a one-row aggregate as a UTC time
+pub(crate) fn single_timestamp(batches: &[RecordBatch]) -> BlazeResult<Option<DateTime<Utc>>> {
+    let Some(batch) = batches.iter().find(|batch| batch.num_rows() > 0) else {
+        return Ok(None);
+    };
+    let micros = cast(batch.column(0), &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())))?;
+    let micros = micros.as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+    if micros.is_null(0) {
+        return Ok(None);
+    }
+    Ok(DateTime::from_timestamp_micros(micros.value(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::Field;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_column_type_names() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("tags", DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))), true),
+            Field::new("price", DataType::Decimal128(10, 2), true),
+            Field::new("seen", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+        ]);
+        let names: Vec<_> = columns(&schema).into_iter().map(|c| (c.name, c.data_type, c.nullable)).collect();
+        assert_eq!(names, vec![
+            ("id".to_string(), "INT64".to_string(), false),
+            ("tags".to_string(), "ARRAY<STRING>".to_string(), true),
+            ("price".to_string(), "NUMERIC(10, 2)".to_string(), true),
+            ("seen".to_string(), "TIMESTAMP".to_string(), true),
+        ]);
+    }
+}
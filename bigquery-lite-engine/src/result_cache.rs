@@ -0,0 +1,226 @@
+//! Cached query results
+//!
+//! With `result_cache_max_bytes` set, the engine keeps the results of
+//! queries over in-memory tables and answers the same query from memory
+//! while the tables it reads are unchanged, like BigQuery's cached results.
+//! `QueryResult::cache_hit` tells a cached answer apart.
+//!
+//! Results are keyed by their SQL normalized as for query fingerprints, but
+//! with literals kept: only whitespace, comments and the case of unquoted
+//! words are normalized away. They are stored with the version of every
+//! table the query reads. A write to any of those tables commits a new
+//! version, so the cached result no longer matches and the query runs again.
+//! Results live for `result_cache_ttl_secs` at most, and the least recently
+//! used are dropped once the cache outgrows `result_cache_max_bytes`.
+//!
+//! Only queries whose result is determined by their tables are cached: ones
+//! reading views, files or remote tables, whose changes the engine cannot
+//! see, and ones calling functions such as `random()` or `now()` always run.
+//! Changing the engine's configuration clears the cache.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::normalize_sql_keeping_literals;
+
+/// Functions whose result differs between runs over the same tables
+const VOLATILE_FUNCTIONS: &[&str] = &[
+    "random",
+    "uuid",
+    "now",
+    "today",
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "localtime",
+    "localtimestamp",
+];
+
+/// Usage of the result cache
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResultCacheStats {
+    /// Bytes the cache may hold; 0 when it is disabled
+    pub max_bytes: u64,
+    /// Results held now
+    pub entries: usize,
+    /// Bytes of the results held now
+    pub cached_bytes: u64,
+    /// Queries answered from the cache
+    pub hits: u64,
+    /// Cacheable queries that ran because no current result was cached
+    pub misses: u64,
+    /// Results dropped to make room for others
+    pub evictions: u64,
+}
+
+/// Identity and version of every table a query reads
+pub(crate) type TableVersions = Vec<(u64, u64)>;
+
+#[derive(Debug)]
+struct CachedResult {
+    versions: TableVersions,
+    batches: Vec<RecordBatch>,
+    bytes: u64,
+    expires_at: DateTime<Utc>,
+    last_used: u64,
+}
+
+/// Results of recent queries keyed by normalized SQL
+#[derive(Debug, Default)]
+pub(crate) struct ResultCache {
+    results: HashMap<String, CachedResult>,
+    bytes: u64,
+    uses: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl ResultCache {
+    /// The cached result of `key` if it was computed from `versions` and has
+    /// not expired
+    pub(crate) fn get(&mut self, key: &str, versions: &TableVersions, now: DateTime<Utc>) -> Option<Vec<RecordBatch>> {
+        self.uses += 1;
+        let current = match self.results.get_mut(key) {
+            Some(result) if result.versions == *versions && result.expires_at > now => {
+                result.last_used = self.uses;
+                Some(result.batches.clone())
+            }
+            Some(_) => {
+                self.remove(key);
+                None
+            }
+            None => None,
+        };
+        match current {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        current
+    }
+
+    /// Cache the result of `key`, dropping the least recently used results
+    /// until the cache fits in `max_bytes`. Results larger than that are not
+    /// cached.
+    pub(crate) fn put(
+        &mut self,
+        key: String,
+        versions: TableVersions,
+        batches: &[RecordBatch],
+        expires_at: DateTime<Utc>,
+        max_bytes: u64,
+    ) {
+        let bytes: u64 = batches.iter().map(|batch| batch.get_array_memory_size() as u64).sum();
+        self.remove(&key);
+        if bytes > max_bytes {
+            return;
+        }
+        while self.bytes + bytes > max_bytes {
+            let Some(oldest) = self.results.iter().min_by_key(|(_, result)| result.last_used).map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+            self.evictions += 1;
+        }
+        self.uses += 1;
+        self.bytes += bytes;
+        let result = CachedResult { versions, batches: batches.to_vec(), bytes, expires_at, last_used: self.uses };
+        self.results.insert(key, result);
+    }
+
+    /// Drop every cached result
+    pub(crate) fn clear(&mut self) {
+        self.results.clear();
+        self.bytes = 0;
+    }
+
+    pub(crate) fn stats(&self, max_bytes: u64) -> ResultCacheStats {
+        ResultCacheStats {
+            max_bytes,
+            entries: self.results.len(),
+            cached_bytes: self.bytes,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(result) = self.results.remove(key) {
+            self.bytes -= result.bytes;
+        }
+    }
+}
+
+/// When a result cached now expires
+pub(crate) fn expiry(now: DateTime<Utc>, ttl_secs: u64) -> DateTime<Utc> {
+    now + Duration::seconds(ttl_secs.min(i64::MAX as u64 / 1000) as i64)
+}
+
+/// `sql` with whitespace, comments and the case of unquoted words
+/// normalized, or None if its result can differ between runs over the same
+/// tables. Naming a volatile function anywhere, even inside a literal, is
+/// enough to skip the cache.
+pub(crate) fn cache_key(sql: &str) -> Option<String> {
+    let key = normalize_sql_keeping_literals(sql)?;
+    let volatile = key
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .any(|word| VOLATILE_FUNCTIONS.contains(&word.to_lowercase().as_str()));
+    (!volatile).then_some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use std::sync::Arc;
+
+    fn batch(rows: i64) -> RecordBatch {
+        RecordBatch::try_from_iter([("n", Arc::new(Int64Array::from_iter_values(0..rows)) as _)]).unwrap()
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(
+            cache_key("SELECT  id\nFROM t -- latest\nWHERE name = 'A'"),
+            cache_key("select id from T where NAME = 'A'")
+        );
+        assert_ne!(cache_key("SELECT 1 FROM t WHERE name = 'A'"), cache_key("SELECT 1 FROM t WHERE name = 'a'"));
+        assert_ne!(cache_key("SELECT \"Id\" FROM t"), cache_key("SELECT id FROM t"));
+        assert_ne!(cache_key("SELECT * FROM t WHERE id = 1"), cache_key("SELECT * FROM t WHERE id = 2"));
+        assert_eq!(cache_key("SELECT * FROM t;"), Some(normalize_sql_keeping_literals("select * from T").unwrap()));
+        assert_eq!(cache_key("SELECT RANDOM() FROM t"), None);
+        assert_eq!(cache_key("SELECT * FROM t WHERE day = current_date"), None);
+    }
+
+    #[test]
+    fn test_versions_and_eviction() {
+        let now = Utc::now();
+        let later = now + Duration::seconds(60);
+        let mut cache = ResultCache::default();
+        let size = batch(100).get_array_memory_size() as u64;
+
+        cache.put("a".into(), vec![(1, 1)], &[batch(100)], later, size * 2);
+        assert!(cache.get("a", &vec![(1, 1)], now).is_some());
+        // A new version of the table, or expiry, makes the result stale
+        assert!(cache.get("a", &vec![(1, 2)], now).is_none());
+        assert_eq!(cache.stats(0).entries, 0);
+        cache.put("a".into(), vec![(1, 2)], &[batch(100)], later, size * 2);
+        assert!(cache.get("a", &vec![(1, 2)], later).is_none());
+
+        cache.put("a".into(), vec![], &[batch(100)], later, size * 2);
+        cache.put("b".into(), vec![], &[batch(100)], later, size * 2);
+        assert!(cache.get("a", &vec![], now).is_some());
+        cache.put("c".into(), vec![], &[batch(100)], later, size * 2);
+        assert!(cache.get("b", &vec![], now).is_none(), "the least recently used result is evicted");
+        assert!(cache.get("a", &vec![], now).is_some());
+
+        let stats = cache.stats(size * 2);
+        assert_eq!((stats.entries, stats.cached_bytes, stats.evictions), (2, size * 2, 1));
+        assert_eq!((stats.hits, stats.misses), (3, 3));
+    }
+}
@@ -0,0 +1,328 @@
+//! Writing query results to a single file
+//!
+//! `execute_query_to_parquet("SELECT * FROM trips", "out/trips.parquet",
+//! &options)` streams the result into one Parquet file a batch at a time,
+//! so exports of millions of rows never pass through the JSON result path
+//! or have to fit in memory. The file is written under a temporary name
+//! next to the output and renamed into place once complete: readers never
+//! see a half-written file, and a failed export leaves nothing behind.
+//!
+//! `execute_query_to_csv` and `execute_query_to_json` do the same for CSV,
+//! with or without a header and with any single-byte delimiter, and for
+//! newline-delimited JSON. Either can be gzip-compressed as it is written;
+//! the path is used as given, so name such files with a `.gz` extension.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use datafusion::arrow::csv::WriterBuilder as CsvWriterBuilder;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::json::LineDelimitedWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::arrow::ArrowWriter;
+use datafusion::parquet::basic::Compression;
+use datafusion::parquet::file::properties::WriterProperties;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::file_tables::DataFormat;
+use crate::invalid_input;
+use crate::parquet_sink::parquet_error;
+
+/// How `execute_query_to_parquet` writes its file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParquetExportOptions {
+    /// Parquet codec such as `snappy`, `zstd(3)` or `uncompressed` (default: snappy)
+    pub compression: String,
+    /// Rows per row group (default: 1048576)
+    pub row_group_size: usize,
+    /// Replace an existing file instead of failing (default: false)
+    pub overwrite: bool,
+}
+
+impl Default for ParquetExportOptions {
+    fn default() -> Self {
+        Self { compression: "snappy".to_string(), row_group_size: 1024 * 1024, overwrite: false }
+    }
+}
+
+/// How `execute_query_to_csv` writes its file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvExportOptions {
+    /// Whether the first line holds the column names (default: true)
+    pub has_header: bool,
+    /// Field delimiter (default: `,`)
+    pub delimiter: u8,
+    /// Compress the file with gzip (default: false)
+    pub gzip: bool,
+    /// Replace an existing file instead of failing (default: false)
+    pub overwrite: bool,
+}
+
+impl Default for CsvExportOptions {
+    fn default() -> Self {
+        Self { has_header: true, delimiter: b',', gzip: false, overwrite: false }
+    }
+}
+
+/// How `execute_query_to_json` writes its file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonExportOptions {
+    /// Compress the file with gzip (default: false)
+    pub gzip: bool,
+    /// Replace an existing file instead of failing (default: false)
+    pub overwrite: bool,
+}
+
+/// A file written from a query result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportReport {
+    /// Path of the file
+    pub path: String,
+    pub format: DataFormat,
+    pub rows_written: usize,
+    /// Size of the file in bytes
+    pub bytes_written: u64,
+}
+
+/// Encodes batches into an open file
+trait BatchWriter: Send {
+    fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()>;
+
+    /// Flush everything buffered and write any footer
+    fn close(self: Box<Self>) -> BlazeResult<()>;
+}
+
+impl BatchWriter for ArrowWriter<File> {
+    fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()> {
+        ArrowWriter::write(self, batch).map_err(parquet_error)
+    }
+
+    fn close(self: Box<Self>) -> BlazeResult<()> {
+        ArrowWriter::close(*self).map_err(parquet_error)?;
+        Ok(())
+    }
+}
+
+impl BatchWriter for datafusion::arrow::csv::Writer<Output> {
+    fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()> {
+        Ok(datafusion::arrow::csv::Writer::write(self, batch)?)
+    }
+
+    fn close(self: Box<Self>) -> BlazeResult<()> {
+        self.into_inner().finish()
+    }
+}
+
+impl BatchWriter for LineDelimitedWriter<Output> {
+    fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()> {
+        Ok(LineDelimitedWriter::write(self, batch)?)
+    }
+
+    fn close(mut self: Box<Self>) -> BlazeResult<()> {
+        self.finish()?;
+        self.into_inner().finish()
+    }
+}
+
+/// The bytes of a text export, gzip-compressed or not
+enum Output {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Output {
+    fn new(file: File, gzip: bool) -> Self {
+        let file = BufWriter::new(file);
+        match gzip {
+            true => Self::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            false => Self::Plain(file),
+        }
+    }
+
+    /// Write the gzip trailer, if any, and flush to the file
+    fn finish(self) -> BlazeResult<()> {
+        let mut file = match self {
+            Self::Plain(file) => file,
+            Self::Gzip(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// An export being written under a temporary name next to its final path
+pub(crate) struct ExportFile {
+    path: PathBuf,
+    partial: PathBuf,
+    format: DataFormat,
+    /// `None` once the file is finished
+    writer: Option<Box<dyn BatchWriter>>,
+    rows: usize,
+}
+
+impl ExportFile {
+    /// Start a Parquet file at `path` for batches of `schema`
+    pub(crate) fn parquet(path: &Path, schema: &SchemaRef, options: &ParquetExportOptions) -> BlazeResult<Self> {
+        let compression = Compression::from_str(&options.compression)
+            .map_err(|e| invalid_input!("Invalid Parquet compression '{}': {}", options.compression, e))?;
+        if options.row_group_size == 0 {
+            return Err(invalid_input!("row_group_size must be greater than 0"));
+        }
+        let properties = WriterProperties::builder()
+            .set_compression(compression)
+            .set_max_row_group_size(options.row_group_size)
+            .build();
+
+        let (file, partial) = create_partial(path, options.overwrite)?;
+        let writer = match ArrowWriter::try_new(file, schema.clone(), Some(properties)) {
+            Ok(writer) => writer,
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                return Err(parquet_error(e));
+            }
+        };
+        Ok(Self::new(path, partial, DataFormat::Parquet, Box::new(writer)))
+    }
+
+    /// Start a CSV file at `path`
+    pub(crate) fn csv(path: &Path, options: &CsvExportOptions) -> BlazeResult<Self> {
+        let (file, partial) = create_partial(path, options.overwrite)?;
+        let writer = CsvWriterBuilder::new()
+            .with_header(options.has_header)
+            .with_delimiter(options.delimiter)
+            .build(Output::new(file, options.gzip));
+        Ok(Self::new(path, partial, DataFormat::Csv, Box::new(writer)))
+    }
+
+    /// Start a newline-delimited JSON file at `path`
+    pub(crate) fn json(path: &Path, options: &JsonExportOptions) -> BlazeResult<Self> {
+        let (file, partial) = create_partial(path, options.overwrite)?;
+        let writer = LineDelimitedWriter::new(Output::new(file, options.gzip));
+        Ok(Self::new(path, partial, DataFormat::Json, Box::new(writer)))
+    }
+
+    fn new(path: &Path, partial: PathBuf, format: DataFormat, writer: Box<dyn BatchWriter>) -> Self {
+        Self { path: path.to_path_buf(), partial, format, writer: Some(writer), rows: 0 }
+    }
+
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        self.writer.as_mut().expect("export is not finished").write(batch)?;
+        self.rows += batch.num_rows();
+        Ok(())
+    }
+
+    /// Complete the file and move it into place
+    pub(crate) fn finish(mut self) -> BlazeResult<ExportReport> {
+        self.writer.take().expect("export is not finished").close()?;
+        std::fs::rename(&self.partial, &self.path)?;
+        Ok(ExportReport {
+            path: self.path.display().to_string(),
+            format: self.format,
+            rows_written: self.rows,
+            bytes_written: std::fs::metadata(&self.path)?.len(),
+        })
+    }
+}
+
+impl Drop for ExportFile {
+    fn drop(&mut self) {
+        // An export dropped before it finished, e.g. after a failed batch
+        if self.writer.take().is_some() {
+            let _ = std::fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// Create the temporary file an export to `path` is written to, refusing to
+/// replace an existing file unless `overwrite`
+fn create_partial(path: &Path, overwrite: bool) -> BlazeResult<(File, PathBuf)> {
+    if path.is_dir() {
+        return Err(invalid_input!("'{}' is a directory", path.display()));
+    }
+    if path.exists() && !overwrite {
+        return Err(invalid_input!("'{}' already exists; pass overwrite to replace it", path.display()));
+    }
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let name = path.file_name().ok_or_else(|| invalid_input!("'{}' is not a file path", path.display()))?;
+    let partial = path.with_file_name(format!(".{}.partial", name.to_string_lossy()));
+    Ok((File::create(&partial)?, partial))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_partial_file_is_renamed_or_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2, 3]))]).unwrap();
+        let path = dir.path().join("out").join("ids.parquet");
+
+        let mut export = ExportFile::parquet(&path, &schema, &ParquetExportOptions::default()).unwrap();
+        export.write(&batch).unwrap();
+        assert!(!path.exists());
+        let report = export.finish().unwrap();
+        assert_eq!((report.rows_written, report.format), (3, DataFormat::Parquet));
+        assert_eq!(std::fs::read_dir(dir.path().join("out")).unwrap().count(), 1);
+
+        assert!(ExportFile::parquet(&path, &schema, &ParquetExportOptions::default()).is_err());
+        let overwrite = ParquetExportOptions { overwrite: true, ..Default::default() };
+        drop(ExportFile::parquet(&path, &schema, &overwrite).unwrap());
+        assert_eq!(std::fs::read_dir(dir.path().join("out")).unwrap().count(), 1);
+        let bad = ParquetExportOptions { compression: "lz5".to_string(), overwrite: true, ..Default::default() };
+        assert!(ExportFile::parquet(&path, &schema, &bad).is_err());
+    }
+
+    #[test]
+    fn test_text_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2]))]).unwrap();
+
+        let path = dir.path().join("ids.csv");
+        let mut export = ExportFile::csv(&path, &CsvExportOptions { has_header: false, ..Default::default() }).unwrap();
+        export.write(&batch).unwrap();
+        export.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n2\n");
+
+        let path = dir.path().join("ids.json.gz");
+        let mut export = ExportFile::json(&path, &JsonExportOptions { gzip: true, ..Default::default() }).unwrap();
+        export.write(&batch).unwrap();
+        assert_eq!(export.finish().unwrap().format, DataFormat::Json);
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(File::open(&path).unwrap()), &mut text).unwrap();
+        assert_eq!(text, "{\"id\":1}\n{\"id\":2}\n");
+    }
+}
@@ -0,0 +1,253 @@
+//! Anonymous result tables
+//!
+//! A query run with `QueryOptions::cache_result` keeps its result as a hidden
+//! table in the `_results` schema, like BigQuery's anonymous destination
+//! tables. `QueryResult::result_table` names it, so follow-up queries and
+//! pagination read the stored rows instead of running the query again:
+//!
+//! ```sql
+//! SELECT * FROM _results.job_3f2a9c0d1e4b5a67 ORDER BY id LIMIT 100 OFFSET 200
+//! ```
+//!
+//! A query run with `QueryOptions::max_rows` is kept the same way, but
+//! returns only its first page of rows. `QueryResult::page_token` then points
+//! at the next page, which `fetch_results(query_id, page_token, max_rows)`
+//! reads from the kept batches, like BigQuery's `getQueryResults`. Page
+//! tokens are opaque and stay valid as long as the result table.
+//!
+//! Result tables expire `result_table_ttl_secs` after they are created (24
+//! hours by default). Expired tables are dropped the next time a query runs
+//! or the tables are listed. They do not appear in `list_tables`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog_common::MemorySchemaProvider;
+use datafusion::common::TableReference;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::SessionContext;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Schema holding the anonymous result tables
+pub const RESULTS_SCHEMA: &str = "_results";
+
+/// A query result kept as a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultTableInfo {
+    /// Table name within the `_results` schema, e.g. `job_3f2a9c0d1e4b5a67`
+    pub name: String,
+    /// Qualified name to use in SQL, e.g. `_results.job_3f2a9c0d1e4b5a67`
+    pub reference: String,
+    /// Rows in the table
+    pub rows: usize,
+    /// ID of the query that produced the result
+    #[serde(default)]
+    pub query_id: String,
+    /// When the query finished
+    pub created_at: DateTime<Utc>,
+    /// When the table is dropped
+    pub expires_at: DateTime<Utc>,
+}
+
+/// One page of a kept result, from `fetch_results`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultPage {
+    /// ID of the query that produced the result
+    pub query_id: String,
+    /// Rows in the whole result
+    pub total_rows: usize,
+    /// Position of the page's first row in the result
+    pub start_row: usize,
+    /// The page's rows
+    pub data: Vec<HashMap<String, serde_json::Value>>,
+    /// Token of the next page, `None` after the last one
+    pub page_token: Option<String>,
+}
+
+#[derive(Debug)]
+struct StoredResult {
+    info: ResultTableInfo,
+    /// The registered table's batches, kept for paging
+    batches: Vec<RecordBatch>,
+}
+
+/// The result tables of an engine, keyed by name
+#[derive(Debug, Default)]
+pub(crate) struct ResultTables {
+    tables: HashMap<String, StoredResult>,
+}
+
+impl ResultTables {
+    /// Register `batches` as a new result table created at `created_at`
+    /// that lives for `ttl`
+    pub(crate) fn store(
+        &mut self,
+        ctx: &SessionContext,
+        batches: Vec<RecordBatch>,
+        query_id: &str,
+        created_at: DateTime<Utc>,
+        ttl: Duration,
+    ) -> BlazeResult<ResultTableInfo> {
+        let schema = batches
+            .first()
+            .map(|batch| batch.schema())
+            .ok_or_else(|| invalid_input!("Cannot keep a result without batches"))?;
+
+        let catalog = ctx.catalog("datafusion").ok_or_else(|| {
+            BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Catalog not found".to_string()))
+        })?;
+        if catalog.schema(RESULTS_SCHEMA).is_none() {
+            catalog.register_schema(RESULTS_SCHEMA, Arc::new(MemorySchemaProvider::new()))?;
+        }
+
+        let name = loop {
+            let name = format!("job_{:016x}", rand::random::<u64>());
+            if !self.tables.contains_key(&name) {
+                break name;
+            }
+        };
+        let rows = batches.iter().map(|batch| batch.num_rows()).sum();
+        let table = MemTable::try_new(schema, vec![batches.clone()])?;
+        ctx.register_table(TableReference::partial(RESULTS_SCHEMA, name.as_str()), Arc::new(table))?;
+
+        let info = ResultTableInfo {
+            reference: format!("{}.{}", RESULTS_SCHEMA, name),
+            name: name.clone(),
+            rows,
+            query_id: query_id.to_string(),
+            created_at,
+            expires_at: created_at + ttl,
+        };
+        self.tables.insert(name, StoredResult { info: info.clone(), batches });
+        Ok(info)
+    }
+
+    /// Up to `max_rows` rows of the result of `query_id` from the page
+    /// `page_token` points at, or the first page without one. Returns the
+    /// table, the page's first row and its rows.
+    pub(crate) fn page(
+        &self,
+        query_id: &str,
+        page_token: Option<&str>,
+        max_rows: usize,
+    ) -> BlazeResult<(ResultTableInfo, usize, Vec<RecordBatch>)> {
+        let stored = self
+            .tables
+            .values()
+            .find(|stored| stored.info.query_id == query_id)
+            .ok_or_else(|| invalid_input!("No kept result for query '{}'; it may have expired", query_id))?;
+        let start = match page_token {
+            Some(token) => page_start(&stored.info, token)
+                .ok_or_else(|| invalid_input!("Invalid page token '{}' for query '{}'", token, query_id))?,
+            None => 0,
+        };
+        Ok((stored.info.clone(), start, slice_rows(&stored.batches, start, max_rows)))
+    }
+
+
+    /// Drop the tables that expired by `now`, returning how many there were
+    pub(crate) fn expire(&mut self, ctx: &SessionContext, now: DateTime<Utc>) -> usize {
+        let expired: Vec<_> = self
+            .tables
+            .values()
+            .filter(|stored| stored.info.expires_at <= now)
+            .map(|stored| stored.info.name.clone())
+            .collect();
+        for name in &expired {
+            self.tables.remove(name);
+            let _ = ctx.deregister_table(TableReference::partial(RESULTS_SCHEMA, name.as_str()));
+        }
+        expired.len()
+    }
+
+    /// Drop one table before it expires
+    pub(crate) fn remove(&mut self, ctx: &SessionContext, name: &str) -> BlazeResult<()> {
+        let name = name.strip_prefix(&format!("{}.", RESULTS_SCHEMA)).unwrap_or(name);
+        self.tables
+            .remove(name)
+            .ok_or_else(|| invalid_input!("Result table '{}' not found", name))?;
+        ctx.deregister_table(TableReference::partial(RESULTS_SCHEMA, name))?;
+        Ok(())
+    }
+
+    /// Every result table, oldest first
+    pub(crate) fn list(&self) -> Vec<ResultTableInfo> {
+        let mut tables: Vec<_> = self.tables.values().map(|stored| stored.info.clone()).collect();
+        tables.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+        tables
+    }
+}
+
+/// Token of the page of `table` starting at row `start`
+pub(crate) fn page_token(table: &ResultTableInfo, start: usize) -> Option<String> {
+    (start < table.rows).then(|| format!("{}:{}", table.name, start))
+}
+
+/// The row a page token of `table` starts at
+fn page_start(table: &ResultTableInfo, token: &str) -> Option<usize> {
+    let (name, start) = token.split_once(':')?;
+    let start = start.parse().ok()?;
+    (name == table.name && start <= table.rows).then_some(start)
+}
+
+/// Up to `max_rows` rows from row `start` on, sharing the batches' buffers
+pub(crate) fn slice_rows(batches: &[RecordBatch], start: usize, max_rows: usize) -> Vec<RecordBatch> {
+    let mut skip = start;
+    let mut remaining = max_rows;
+    let mut sliced = Vec::new();
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        if skip >= batch.num_rows() {
+            skip -= batch.num_rows();
+            continue;
+        }
+        let length = (batch.num_rows() - skip).min(remaining);
+        sliced.push(batch.slice(skip, length));
+        remaining -= length;
+        skip = 0;
+    }
+    sliced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    #[tokio::test]
+    async fn test_store_and_expire() {
+        let ctx = SessionContext::new();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2, 3]))]).unwrap();
+
+        let mut tables = ResultTables::default();
+        let info = tables.store(&ctx, vec![batch], "query_1", Utc::now(), Duration::hours(24)).unwrap();
+        assert_eq!(info.rows, 3);
+        assert!(info.reference.starts_with("_results.job_"));
+        let count = ctx.sql(&format!("SELECT COUNT(*) FROM {}", info.reference)).await.unwrap().collect().await.unwrap();
+        assert_eq!(count[0].num_rows(), 1);
+
+        let (_, start, page) = tables.page("query_1", None, 2).unwrap();
+        assert_eq!((start, page[0].num_rows()), (0, 2));
+        let token = page_token(&info, 2).unwrap();
+        let (_, start, page) = tables.page("query_1", Some(&token), 2).unwrap();
+        assert_eq!((start, page[0].num_rows()), (2, 1));
+        assert_eq!(page_token(&info, 3), None);
+        assert!(tables.page("query_1", Some("job_0:1"), 2).is_err());
+        assert!(tables.page("query_2", None, 2).is_err());
+
+        assert_eq!(tables.expire(&ctx, Utc::now()), 0);
+        assert_eq!(tables.expire(&ctx, info.expires_at), 1);
+        assert!(tables.list().is_empty());
+        assert!(ctx.sql(&format!("SELECT * FROM {}", info.reference)).await.is_err());
+        assert!(tables.remove(&ctx, &info.name).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+//! Full-text search over text columns
+//!
+//! Provides the `SEARCH(data, query)` and `CONTAINS_SUBSTR(data, text)`
+//! functions, and search indexes declared with
+//!
+//! ```sql
+//! CREATE SEARCH INDEX logs_idx ON logs(message);
+//! SELECT * FROM logs WHERE SEARCH(message, 'timeout upstream');
+//! ```
+//!
+//! `SEARCH` lowercases its inputs and splits them on non-alphanumeric
+//! characters; a row matches when it contains every query token. Tables with
+//! a search index are registered as [`SearchIndexedTable`], which answers
+//! pushed-down `SEARCH` predicates from an inverted index so only candidate
+//! rows are scanned instead of the whole table.
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{Array, ArrayRef, BooleanArray, StringArray};
+use datafusion::arrow::compute::{cast, filter_record_batch};
+use datafusion::arrow::datatypes::{DataType, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::Session;
+use datafusion::common::ScalarValue;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::logical_expr::{
+    ColumnarValue, Expr, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature,
+    TableProviderFilterPushDown, Volatility,
+};
+use datafusion::physical_plan::ExecutionPlan;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Definition of a search index on a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndexInfo {
+    /// Index name
+    pub name: String,
+    /// Indexed table
+    pub table_name: String,
+    /// Indexed columns
+    pub columns: Vec<String>,
+}
+
+/// Parsed `CREATE SEARCH INDEX` statement
+#[derive(Debug, Clone)]
+pub struct CreateSearchIndex {
+    pub name: String,
+    pub table_name: String,
+    /// `None` for `ALL COLUMNS`
+    pub columns: Option<Vec<String>>,
+    pub if_not_exists: bool,
+}
+
+/// Parse `CREATE SEARCH INDEX [IF NOT EXISTS] name ON table(col, ... | ALL COLUMNS)`
+pub fn parse_create_search_index(sql: &str) -> BlazeResult<Option<CreateSearchIndex>> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*CREATE\s+SEARCH\s+INDEX\s+(IF\s+NOT\s+EXISTS\s+)?([A-Za-z_]\w*)\s+ON\s+([A-Za-z_][\w.]*)\s*\((.*)\)\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let Some(captures) = PATTERN.captures(sql) else {
+        return Ok(None);
+    };
+
+    let column_list = captures[4].trim();
+    static ALL_COLUMNS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^ALL\s+COLUMNS$").unwrap());
+    let columns = if ALL_COLUMNS.is_match(column_list) {
+        None
+    } else {
+        let columns: Vec<String> = column_list.split(',').map(|c| c.trim().to_string()).collect();
+        if columns.iter().any(|c| c.is_empty()) {
+            return Err(invalid_input!("Invalid column list in CREATE SEARCH INDEX: '{}'", column_list));
+        }
+        Some(columns)
+    };
+
+    Ok(Some(CreateSearchIndex {
+        name: captures[2].to_string(),
+        table_name: captures[3].to_string(),
+        columns,
+        if_not_exists: captures.get(1).is_some(),
+    }))
+}
+
+/// Parse `DROP SEARCH INDEX [IF EXISTS] name ON table`, returning the index
+/// name, table name and whether IF EXISTS was given
+pub fn parse_drop_search_index(sql: &str) -> Option<(String, String, bool)> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*DROP\s+SEARCH\s+INDEX\s+(IF\s+EXISTS\s+)?([A-Za-z_]\w*)\s+ON\s+([A-Za-z_][\w.]*)\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    Some((captures[2].to_string(), captures[3].to_string(), captures.get(1).is_some()))
+}
+
+/// Lowercased alphanumeric tokens of `text`
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Register the search functions with a session
+pub fn register_functions(ctx: &datafusion::prelude::SessionContext) {
+    ctx.register_udf(ScalarUDF::from(TextMatch::new(TextMatchKind::Search)));
+    ctx.register_udf(ScalarUDF::from(TextMatch::new(TextMatchKind::ContainsSubstr)));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextMatchKind {
+    /// Every query token appears in the data
+    Search,
+    /// Case-insensitive substring match
+    ContainsSubstr,
+}
+
+/// Implementation of `SEARCH` and `CONTAINS_SUBSTR`
+#[derive(Debug)]
+struct TextMatch {
+    kind: TextMatchKind,
+    signature: Signature,
+}
+
+impl TextMatch {
+    fn new(kind: TextMatchKind) -> Self {
+        Self {
+            kind,
+            signature: Signature::any(2, Volatility::Immutable),
+        }
+    }
+}
+
+impl ScalarUDFImpl for TextMatch {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        match self.kind {
+            TextMatchKind::Search => "search",
+            TextMatchKind::ContainsSubstr => "contains_substr",
+        }
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(&args.args)?;
+        let data = as_strings(&arrays[0])?;
+        let queries = as_strings(&arrays[1])?;
+
+        // The query is almost always a literal, so prepare it once per distinct value
+        let mut prepared: HashMap<&str, Vec<String>> = HashMap::new();
+        let matches: BooleanArray = (0..data.len())
+            .map(|row| {
+                if data.is_null(row) || queries.is_null(row) {
+                    return None;
+                }
+                let (text, query) = (data.value(row), queries.value(row));
+                Some(match self.kind {
+                    TextMatchKind::Search => {
+                        let wanted = prepared.entry(query).or_insert_with(|| tokenize(query).collect());
+                        let tokens: HashSet<String> = tokenize(text).collect();
+                        wanted.iter().all(|t| tokens.contains(t))
+                    }
+                    TextMatchKind::ContainsSubstr => {
+                        let needle = prepared.entry(query).or_insert_with(|| vec![query.to_lowercase()]);
+                        text.to_lowercase().contains(needle[0].as_str())
+                    }
+                })
+            })
+            .collect();
+
+        Ok(ColumnarValue::Array(Arc::new(matches)))
+    }
+}
+
+fn as_strings(array: &ArrayRef) -> Result<StringArray> {
+    let strings = cast(array, &DataType::Utf8)?;
+    Ok(strings.as_any().downcast_ref::<StringArray>().cloned().unwrap())
+}
+
+/// Token → sorted row positions for one column
+#[derive(Debug, Default)]
+struct InvertedIndex {
+    postings: HashMap<String, Vec<u32>>,
+}
+
+impl InvertedIndex {
+    fn build(batches: &[RecordBatch], column: usize) -> Result<Self> {
+        let mut postings: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut row_id: u32 = 0;
+
+        for batch in batches {
+            let strings = as_strings(batch.column(column))?;
+            for row in 0..strings.len() {
+                if !strings.is_null(row) {
+                    for token in tokenize(strings.value(row)) {
+                        let rows = postings.entry(token).or_default();
+                        if rows.last() != Some(&row_id) {
+                            rows.push(row_id);
+                        }
+                    }
+                }
+                row_id += 1;
+            }
+        }
+
+        Ok(Self { postings })
+    }
+
+    /// Rows containing every token of `query`, or `None` if the query has no
+    /// tokens and cannot narrow the scan
+    fn lookup(&self, query: &str) -> Option<Vec<u32>> {
+        let mut result: Option<Vec<u32>> = None;
+        for token in tokenize(query) {
+            let rows = self.postings.get(&token).map(Vec::as_slice).unwrap_or(&[]);
+            result = Some(match result {
+                None => rows.to_vec(),
+                Some(current) => intersect(&current, rows),
+            });
+        }
+        result
+    }
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j, mut out) = (0, 0, Vec::new());
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+/// In-memory table with inverted indexes on some of its columns
+#[derive(Debug)]
+pub struct SearchIndexedTable {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    indexes: HashMap<String, InvertedIndex>,
+}
+
+impl SearchIndexedTable {
+    /// Build indexes on `columns` over `batches`
+    pub fn try_new(schema: SchemaRef, batches: Vec<RecordBatch>, columns: &[String]) -> BlazeResult<Self> {
+        let mut indexes = HashMap::new();
+        for column in columns {
+            let index = schema
+                .index_of(column)
+                .map_err(|_| invalid_input!("Cannot index unknown column '{}'", column))?;
+            indexes.insert(column.clone(), InvertedIndex::build(&batches, index)?);
+        }
+
+        Ok(Self { schema, batches, indexes })
+    }
+
+    /// The indexed column and query of a `SEARCH(column, 'literal')` filter
+    /// this table can answer
+    fn indexed_search<'a>(&self, filter: &'a Expr) -> Option<(&InvertedIndex, &'a str)> {
+        let Expr::ScalarFunction(function) = filter else {
+            return None;
+        };
+        if function.func.name() != "search" {
+            return None;
+        }
+        match function.args.as_slice() {
+            [Expr::Column(column), Expr::Literal(ScalarValue::Utf8(Some(query)))] => {
+                self.indexes.get(&column.name).map(|index| (index, query.as_str()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Keep only rows listed in `candidates` (sorted global row positions)
+    fn select_rows(&self, candidates: &[u32]) -> Result<Vec<RecordBatch>> {
+        let mut selected = Vec::new();
+        let mut offset: u32 = 0;
+        let mut cursor = candidates.iter().peekable();
+
+        for batch in &self.batches {
+            let end = offset + batch.num_rows() as u32;
+            let mut mask = vec![false; batch.num_rows()];
+            while let Some(&&row) = cursor.peek() {
+                if row >= end {
+                    break;
+                }
+                mask[(row - offset) as usize] = true;
+                cursor.next();
+            }
+            if mask.iter().any(|m| *m) {
+                selected.push(filter_record_batch(batch, &BooleanArray::from(mask))?);
+            }
+            offset = end;
+        }
+
+        Ok(selected)
+    }
+}
+
+#[async_trait]
+impl TableProvider for SearchIndexedTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // Filters are inexact, so DataFusion re-applies them to the candidates
+        let mut candidates: Option<Vec<u32>> = None;
+        for (index, query) in filters.iter().filter_map(|f| self.indexed_search(f)) {
+            if let Some(rows) = index.lookup(query) {
+                candidates = Some(match candidates {
+                    None => rows,
+                    Some(current) => intersect(&current, &rows),
+                });
+            }
+        }
+
+        let batches = match candidates {
+            Some(rows) => self.select_rows(&rows)?,
+            None => self.batches.clone(),
+        };
+        MemTable::try_new(self.schema.clone(), vec![batches])?
+            .scan(state, projection, &[], limit)
+            .await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|f| match self.indexed_search(f) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::{Field, Schema};
+
+    #[test]
+    fn test_inverted_index_lookup() {
+        let schema = Arc::new(Schema::new(vec![Field::new("message", DataType::Utf8, true)]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(StringArray::from(vec![
+                Some("Upstream TIMEOUT after 30s"),
+                None,
+                Some("connection reset by peer"),
+                Some("timeout: upstream=api-2"),
+            ]))],
+        )
+        .unwrap();
+        let index = InvertedIndex::build(&[batch], 0).unwrap();
+
+        assert_eq!(index.lookup("timeout upstream"), Some(vec![0, 3]));
+        assert_eq!(index.lookup("reset"), Some(vec![2]));
+        assert_eq!(index.lookup("missing"), Some(vec![]));
+        assert_eq!(index.lookup("  --  "), None);
+    }
+}
@@ -0,0 +1,398 @@
+//! Serializing query results
+//!
+//! Every output format implements `ResultSerializer` and is looked up by name
+//! in the engine's `SerializerRegistry`, so adding a format does not touch
+//! the engine. The built-in formats are:
+//!
+//! | Name            | Output                                             |
+//! |-----------------|----------------------------------------------------|
+//! | `json`          | Array of row objects, as in `QueryResult::data`    |
+//! | `columnar_json` | `{"columns": [...], "data": {"col": [...], ...}}`  |
+//! | `arrow`         | Arrow IPC stream                                   |
+//! | `csv`           | CSV with a header row                              |
+//! | `msgpack`       | MessagePack array of row maps, like `json`         |
+//!
+//! Embedders add their own with `BlazeQueryEngine::register_serializer`,
+//! which replaces a built-in format of the same name.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{Array, ArrayRef, AsArray, Float64Array, Int64Array, LargeStringArray, StringArray, StringViewArray};
+use datafusion::arrow::csv::WriterBuilder;
+use datafusion::arrow::datatypes::{DataType, Schema};
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+
+use crate::decimal;
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::time_zone;
+
+/// Settings of the engine that affect how values are written
+#[derive(Debug, Clone)]
+pub struct SerializeContext {
+    /// Session time zone zoned timestamps are shown in
+    pub time_zone: String,
+}
+
+/// Turns a query's result into bytes of one format
+pub trait ResultSerializer: Send + Sync {
+    /// Name the format is requested by, e.g. `csv`
+    fn name(&self) -> &str;
+
+    /// MIME type of the output, e.g. `text/csv`
+    fn content_type(&self) -> &str;
+
+    /// Write `batches`, which all share one schema and may be empty
+    fn serialize(&self, batches: &[RecordBatch], context: &SerializeContext) -> BlazeResult<Vec<u8>>;
+}
+
+/// A query result written in one format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializedResult {
+    /// Name of the serializer used
+    pub format: String,
+    /// MIME type of `data`
+    pub content_type: String,
+    /// Rows in the result
+    pub rows: usize,
+    /// The serialized result
+    pub data: Vec<u8>,
+}
+
+/// Serializers keyed by lowercase name
+#[derive(Clone)]
+pub(crate) struct SerializerRegistry {
+    serializers: HashMap<String, Arc<dyn ResultSerializer>>,
+}
+
+impl Default for SerializerRegistry {
+    fn default() -> Self {
+        let mut registry = Self { serializers: HashMap::new() };
+        registry.register(Arc::new(JsonRowsSerializer));
+        registry.register(Arc::new(ColumnarJsonSerializer));
+        registry.register(Arc::new(ArrowIpcSerializer));
+        registry.register(Arc::new(CsvSerializer));
+        registry.register(Arc::new(MsgpackSerializer));
+        registry
+    }
+}
+
+impl SerializerRegistry {
+    /// Add a serializer, replacing any with the same name
+    pub(crate) fn register(&mut self, serializer: Arc<dyn ResultSerializer>) {
+        self.serializers.insert(serializer.name().to_lowercase(), serializer);
+    }
+
+    /// The serializer for `format`
+    pub(crate) fn get(&self, format: &str) -> BlazeResult<Arc<dyn ResultSerializer>> {
+        self.serializers.get(&format.to_lowercase()).cloned().ok_or_else(|| {
+            invalid_input!("Unknown result format '{}'; available formats: {}", format, self.names().join(", "))
+        })
+    }
+
+    /// Names of the registered formats, sorted
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.serializers.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// Rows as objects keyed by column name. Integers, floats and strings keep
+/// their JSON types; dates, times and decimals become text, zoned timestamps
+/// in the session zone. Lists become arrays and structs objects, with their
+/// values converted the same way.
+pub(crate) fn json_rows(batch: &RecordBatch, time_zone: &str) -> BlazeResult<Vec<HashMap<String, serde_json::Value>>> {
+    let columns = json_columns(batch, time_zone)?;
+    let schema = batch.schema();
+    Ok((0..batch.num_rows())
+        .map(|row_idx| {
+            schema
+                .fields()
+                .iter()
+                .zip(&columns)
+                .map(|(field, values)| (field.name().clone(), values[row_idx].clone()))
+                .collect()
+        })
+        .collect())
+}
+
+/// The values of every column of `batch`, converted as by `json_rows`
+fn json_columns(batch: &RecordBatch, time_zone: &str) -> BlazeResult<Vec<Vec<serde_json::Value>>> {
+    batch.columns().iter().map(|column| json_values(column, time_zone)).collect()
+}
+
+/// Every value of `column`, converted as by `json_rows`
+fn json_values(column: &ArrayRef, time_zone: &str) -> BlazeResult<Vec<serde_json::Value>> {
+    let null_or = |row_idx: usize, value: serde_json::Value| match column.is_null(row_idx) {
+        true => serde_json::Value::Null,
+        false => value,
+    };
+    match column.data_type() {
+        DataType::List(_) => {
+            let list = column.as_list::<i32>();
+            let items = json_values(list.values(), time_zone)?;
+            Ok(list.offsets().windows(2).enumerate().map(|(row_idx, range)| {
+                null_or(row_idx, serde_json::Value::Array(items[range[0] as usize..range[1] as usize].to_vec()))
+            }).collect())
+        }
+        DataType::LargeList(_) => {
+            let list = column.as_list::<i64>();
+            let items = json_values(list.values(), time_zone)?;
+            Ok(list.offsets().windows(2).enumerate().map(|(row_idx, range)| {
+                null_or(row_idx, serde_json::Value::Array(items[range[0] as usize..range[1] as usize].to_vec()))
+            }).collect())
+        }
+        DataType::Struct(fields) => {
+            let fields_values = column
+                .as_struct()
+                .columns()
+                .iter()
+                .map(|field_column| json_values(field_column, time_zone))
+                .collect::<BlazeResult<Vec<_>>>()?;
+            Ok((0..column.len())
+                .map(|row_idx| {
+                    let object = fields
+                        .iter()
+                        .zip(&fields_values)
+                        .map(|(field, values)| (field.name().clone(), values[row_idx].clone()))
+                        .collect();
+                    null_or(row_idx, serde_json::Value::Object(object))
+                })
+                .collect())
+        }
+        _ => {
+            let as_text = match time_zone::render_temporal(column, time_zone)? {
+                Some(text) => Some(text),
+                None => decimal::render_decimal(column)?,
+            };
+            Ok((0..column.len()).map(|row_idx| json_value(column, as_text.as_ref(), row_idx)).collect())
+        }
+    }
+}
+
+fn json_value(column: &ArrayRef, as_text: Option<&StringArray>, row_idx: usize) -> serde_json::Value {
+    if column.is_null(row_idx) {
+        return serde_json::Value::Null;
+    }
+    match column.data_type() {
+        DataType::Int64 => {
+            let array = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            serde_json::Value::Number(array.value(row_idx).into())
+        }
+        DataType::Float64 => {
+            let array = column.as_any().downcast_ref::<Float64Array>().unwrap();
+            serde_json::json!(array.value(row_idx))
+        }
+        DataType::Utf8 => {
+            let array = column.as_any().downcast_ref::<StringArray>().unwrap();
+            serde_json::Value::String(array.value(row_idx).to_string())
+        }
+        // Parquet scans read strings as views
+        DataType::Utf8View => {
+            let array = column.as_any().downcast_ref::<StringViewArray>().unwrap();
+            serde_json::Value::String(array.value(row_idx).to_string())
+        }
+        DataType::LargeUtf8 => {
+            let array = column.as_any().downcast_ref::<LargeStringArray>().unwrap();
+            serde_json::Value::String(array.value(row_idx).to_string())
+        }
+        _ => match as_text {
+            Some(text) => serde_json::Value::String(text.value(row_idx).to_string()),
+            None => serde_json::Value::String(format!("Unsupported type: {:?}", column.data_type())),
+        },
+    }
+}
+
+fn all_json_rows(batches: &[RecordBatch], context: &SerializeContext) -> BlazeResult<Vec<HashMap<String, serde_json::Value>>> {
+    let mut rows = Vec::new();
+    for batch in batches {
+        rows.extend(json_rows(batch, &context.time_zone)?);
+    }
+    Ok(rows)
+}
+
+/// Schema shared by `batches`, empty when there are none
+fn schema_of(batches: &[RecordBatch]) -> Arc<Schema> {
+    batches.first().map(|batch| batch.schema()).unwrap_or_else(|| Arc::new(Schema::empty()))
+}
+
+/// Array of row objects
+pub struct JsonRowsSerializer;
+
+impl ResultSerializer for JsonRowsSerializer {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn serialize(&self, batches: &[RecordBatch], context: &SerializeContext) -> BlazeResult<Vec<u8>> {
+        Ok(serde_json::to_vec(&all_json_rows(batches, context)?)?)
+    }
+}
+
+/// Column names and one array of values per column, which is smaller than
+/// row objects for results with many rows
+pub struct ColumnarJsonSerializer;
+
+impl ResultSerializer for ColumnarJsonSerializer {
+    fn name(&self) -> &str {
+        "columnar_json"
+    }
+
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn serialize(&self, batches: &[RecordBatch], context: &SerializeContext) -> BlazeResult<Vec<u8>> {
+        let schema = schema_of(batches);
+        let mut columns = vec![Vec::new(); schema.fields().len()];
+        for batch in batches {
+            for (values, batch_values) in columns.iter_mut().zip(json_columns(batch, &context.time_zone)?) {
+                values.extend(batch_values);
+            }
+        }
+        let data: serde_json::Map<_, _> = schema
+            .fields()
+            .iter()
+            .zip(columns)
+            .map(|(field, values)| (field.name().clone(), serde_json::Value::Array(values)))
+            .collect();
+        let names: Vec<_> = schema.fields().iter().map(|field| field.name()).collect();
+        Ok(serde_json::to_vec(&serde_json::json!({ "columns": names, "data": data }))?)
+    }
+}
+
+/// Arrow IPC stream, keeping every type exactly
+pub struct ArrowIpcSerializer;
+
+impl ResultSerializer for ArrowIpcSerializer {
+    fn name(&self) -> &str {
+        "arrow"
+    }
+
+    fn content_type(&self) -> &str {
+        "application/vnd.apache.arrow.stream"
+    }
+
+    fn serialize(&self, batches: &[RecordBatch], _context: &SerializeContext) -> BlazeResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = StreamWriter::try_new(&mut buffer, &schema_of(batches))?;
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+        }
+        Ok(buffer)
+    }
+}
+
+/// CSV with a header row
+pub struct CsvSerializer;
+
+impl ResultSerializer for CsvSerializer {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn content_type(&self) -> &str {
+        "text/csv"
+    }
+
+    fn serialize(&self, batches: &[RecordBatch], _context: &SerializeContext) -> BlazeResult<Vec<u8>> {
+        // The header is written with the first batch, so no batches give
+        // an empty file
+        let mut buffer = Vec::new();
+        {
+            let mut writer = WriterBuilder::new().with_header(true).build(&mut buffer);
+            for batch in batches {
+                writer.write(batch)?;
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+/// MessagePack array of row maps, the binary form of `json`
+pub struct MsgpackSerializer;
+
+impl ResultSerializer for MsgpackSerializer {
+    fn name(&self) -> &str {
+        "msgpack"
+    }
+
+    fn content_type(&self) -> &str {
+        "application/msgpack"
+    }
+
+    fn serialize(&self, batches: &[RecordBatch], context: &SerializeContext) -> BlazeResult<Vec<u8>> {
+        rmp_serde::to_vec_named(&all_json_rows(batches, context)?)
+            .map_err(|e| BlazeError::Internal(format!("MessagePack serialization failed: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Date32Array, ListArray, StructArray};
+    use datafusion::arrow::datatypes::{Field, Int64Type};
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from(vec![1, 2])), Arc::new(StringArray::from(vec![Some("a"), None]))],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_builtin_formats() {
+        let registry = SerializerRegistry::default();
+        let context = SerializeContext { time_zone: "UTC".to_string() };
+        let batches = [batch()];
+        let write = |format: &str| registry.get(format).unwrap().serialize(&batches, &context).unwrap();
+
+        let rows: serde_json::Value = serde_json::from_slice(&write("JSON")).unwrap();
+        assert_eq!(rows, serde_json::json!([{"id": 1, "name": "a"}, {"id": 2, "name": null}]));
+        let columnar: serde_json::Value = serde_json::from_slice(&write("columnar_json")).unwrap();
+        assert_eq!(columnar, serde_json::json!({"columns": ["id", "name"], "data": {"id": [1, 2], "name": ["a", null]}}));
+        assert_eq!(String::from_utf8(write("csv")).unwrap(), "id,name\n1,a\n2,\n");
+        let unpacked: serde_json::Value = rmp_serde::from_slice(&write("msgpack")).unwrap();
+        assert_eq!(unpacked, rows);
+
+        let ipc = write("arrow");
+        let reader = datafusion::arrow::ipc::reader::StreamReader::try_new(ipc.as_slice(), None).unwrap();
+        let read: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, batches);
+
+        let error = registry.get("xml").err().unwrap();
+        assert!(error.to_string().contains("arrow, columnar_json, csv, json, msgpack"));
+    }
+
+    #[test]
+    fn test_nested_json_rows() {
+        let tags = ListArray::from_iter_primitive::<Int64Type, _, _>(vec![Some(vec![Some(1), None]), None, Some(vec![])]);
+        let address = StructArray::from(vec![
+            (Arc::new(Field::new("city", DataType::Utf8, true)), Arc::new(StringArray::from(vec![Some("Oslo"), None, None])) as ArrayRef),
+            (Arc::new(Field::new("since", DataType::Date32, true)), Arc::new(Date32Array::from(vec![Some(0), Some(1), None])) as ArrayRef),
+        ]);
+        let batch = RecordBatch::try_from_iter(vec![("tags", Arc::new(tags) as ArrayRef), ("address", Arc::new(address) as ArrayRef)]).unwrap();
+
+        let rows = json_rows(&batch.slice(0, 3), "UTC").unwrap();
+        assert_eq!(serde_json::json!(rows[0]), serde_json::json!({"tags": [1, null], "address": {"city": "Oslo", "since": "1970-01-01"}}));
+        assert_eq!(serde_json::json!(rows[1]), serde_json::json!({"tags": null, "address": {"city": null, "since": "1970-01-02"}}));
+        let rows = json_rows(&batch.slice(2, 1), "UTC").unwrap();
+        assert_eq!(serde_json::json!(rows[0]), serde_json::json!({"tags": [], "address": {"city": null, "since": null}}));
+    }
+}
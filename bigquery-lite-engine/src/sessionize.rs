@@ -0,0 +1,264 @@
+//! Gap-based sessionization of event streams
+//!
+//! ```sql
+//! SELECT user_id, session_id, COUNT(*) AS events
+//! FROM SESSIONIZE(TABLE events, 'user_id', 'event_time', INTERVAL 30 MINUTE)
+//! GROUP BY user_id, session_id;
+//! ```
+//!
+//! The source is `TABLE name` or a `(subquery)`; the key may name several
+//! comma-separated columns. Rows are ordered by key and time, and a new
+//! session starts whenever the key changes or more than the gap has passed
+//! since the key's previous event. The output adds `session_id` (unique across
+//! the result) and `session_number` (1-based per key). Rows with a NULL time
+//! get NULL for both.
+//!
+//! The gap is an interval for timestamp and date columns, or a plain number
+//! in the column's own units for numeric time columns (seconds for timestamps).
+
+use std::sync::{Arc, LazyLock};
+
+use datafusion::arrow::array::{Array, ArrayRef, Float64Array, Int64Array, TimestampNanosecondArray};
+use datafusion::arrow::compute::{cast, concat_batches, lexsort_to_indices, take, take_record_batch, SortColumn};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::row::{RowConverter, SortField};
+use datafusion::prelude::SessionContext;
+use regex::Regex;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::utils::{matching_paren, split_top_level};
+
+/// Parsed `SESSIONIZE(...)` call and its position in the query text
+#[derive(Debug, Clone)]
+pub struct SessionizeCall {
+    /// Byte range of the call in the original SQL
+    pub span: (usize, usize),
+    /// Query producing the events
+    pub source_sql: String,
+    /// Columns identifying whose events belong together
+    pub keys: Vec<String>,
+    /// Event time column
+    pub time_column: String,
+    /// Gap expression as written
+    pub gap: String,
+}
+
+/// Maximum allowed gap between events of one session
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gap {
+    /// A plain number, in the time column's units
+    Number(f64),
+    /// An interval, in nanoseconds
+    Nanos(i64),
+}
+
+/// Cheap pre-check so only sessionizing queries pay for the rewrite
+pub fn contains_sessionize(sql: &str) -> bool {
+    sql.to_ascii_uppercase().contains("SESSIONIZE")
+}
+
+/// Find every `SESSIONIZE(source, 'key', 'time', gap)` call in `sql`
+pub fn find_sessionize_calls(sql: &str) -> BlazeResult<Vec<SessionizeCall>> {
+    static CALL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bSESSIONIZE\s*\(").unwrap());
+    static TABLE_ARG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^TABLE\s+([A-Za-z_][\w.]*)$").unwrap());
+
+    let mut calls = Vec::new();
+    let mut cursor = 0;
+    while let Some(found) = CALL.find_at(sql, cursor) {
+        let open = found.end() - 1;
+        let close = matching_paren(sql, open).ok_or_else(|| invalid_input!("Unbalanced SESSIONIZE(...)"))?;
+        let args: Vec<&str> = split_top_level(&sql[open + 1..close], ',').into_iter().map(str::trim).collect();
+        let [source, keys, time_column, gap] = args.as_slice() else {
+            return Err(invalid_input!("SESSIONIZE expects (TABLE source | (query), 'key_columns', 'time_column', gap)"));
+        };
+
+        let source_sql = if let Some(table) = TABLE_ARG.captures(source) {
+            format!("SELECT * FROM {}", &table[1])
+        } else if source.starts_with('(') && source.ends_with(')') {
+            source[1..source.len() - 1].to_string()
+        } else {
+            return Err(invalid_input!("SESSIONIZE source must be TABLE <name> or a (subquery), got '{}'", source));
+        };
+        let keys: Vec<String> = string_literal(keys)?
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        if keys.is_empty() {
+            return Err(invalid_input!("SESSIONIZE needs at least one key column"));
+        }
+
+        calls.push(SessionizeCall {
+            span: (found.start(), close + 1),
+            source_sql,
+            keys,
+            time_column: string_literal(time_column)?,
+            gap: gap.to_string(),
+        });
+        cursor = close + 1;
+    }
+
+    Ok(calls)
+}
+
+fn string_literal(text: &str) -> BlazeResult<String> {
+    let quoted = text.len() >= 2
+        && ((text.starts_with('\'') && text.ends_with('\'')) || (text.starts_with('"') && text.ends_with('"')));
+    if !quoted {
+        return Err(invalid_input!("SESSIONIZE column arguments must be string literals, got '{}'", text));
+    }
+    Ok(text[1..text.len() - 1].to_string())
+}
+
+/// Evaluate the gap argument, using the engine for interval expressions
+pub async fn evaluate_gap(ctx: &SessionContext, gap: &str) -> BlazeResult<Gap> {
+    if let Ok(number) = gap.parse::<f64>() {
+        return Ok(Gap::Number(number));
+    }
+
+    let sql = format!(
+        "SELECT CAST(TIMESTAMP '1970-01-01 00:00:00' + ({}) AS TIMESTAMP)",
+        gap
+    );
+    let batches = ctx.sql(&sql).await?.collect().await?;
+    batches
+        .first()
+        .filter(|batch| batch.num_rows() > 0)
+        .and_then(|batch| batch.column(0).as_any().downcast_ref::<TimestampNanosecondArray>())
+        .filter(|array| !array.is_null(0))
+        .map(|array| Gap::Nanos(array.value(0)))
+        .ok_or_else(|| invalid_input!("SESSIONIZE gap '{}' is not an interval or number", gap))
+}
+
+/// Assign sessions to the source rows, returning them ordered by key and time
+pub fn sessionize(call: &SessionizeCall, schema: SchemaRef, batches: &[RecordBatch], gap: Gap) -> BlazeResult<RecordBatch> {
+    let events = concat_batches(&schema, batches)?;
+    let column = |name: &str| -> BlazeResult<ArrayRef> {
+        let index = schema
+            .index_of(name)
+            .map_err(|_| invalid_input!("SESSIONIZE column '{}' not found", name))?;
+        Ok(events.column(index).clone())
+    };
+
+    let keys = call.keys.iter().map(|k| column(k)).collect::<BlazeResult<Vec<_>>>()?;
+    let time = column(&call.time_column)?;
+
+    let mut sort_columns: Vec<SortColumn> = keys
+        .iter()
+        .map(|values| SortColumn { values: values.clone(), options: None })
+        .collect();
+    sort_columns.push(SortColumn { values: time.clone(), options: None });
+    let order = lexsort_to_indices(&sort_columns, None)?;
+    let sorted = take_record_batch(&events, &order)?;
+
+    let sorted_keys = keys
+        .iter()
+        .map(|k| take(k, &order, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    let times = time_values(&take(&time, &order, None)?, gap)?;
+    let threshold = match gap {
+        Gap::Number(n) if is_temporal(time.data_type()) => n * 1e9,
+        Gap::Number(n) => n,
+        Gap::Nanos(n) => n as f64,
+    };
+
+    let converter = RowConverter::new(sorted_keys.iter().map(|k| SortField::new(k.data_type().clone())).collect())?;
+    let key_rows = converter.convert_columns(&sorted_keys)?;
+
+    let mut session_ids = Vec::with_capacity(sorted.num_rows());
+    let mut session_numbers = Vec::with_capacity(sorted.num_rows());
+    let (mut session_id, mut session_number) = (0i64, 0i64);
+    let mut previous: Option<(usize, f64)> = None;
+
+    for (row, time) in times.iter().enumerate() {
+        let Some(current) = *time else {
+            session_ids.push(None);
+            session_numbers.push(None);
+            continue;
+        };
+
+        match previous {
+            Some((prev_row, prev_time)) if key_rows.row(prev_row) == key_rows.row(row) => {
+                if current - prev_time > threshold {
+                    session_id += 1;
+                    session_number += 1;
+                }
+            }
+            _ => {
+                session_id += 1;
+                session_number = 1;
+            }
+        }
+        previous = Some((row, current));
+        session_ids.push(Some(session_id));
+        session_numbers.push(Some(session_number));
+    }
+
+    let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+    fields.push(Arc::new(Field::new("session_id", DataType::Int64, true)));
+    fields.push(Arc::new(Field::new("session_number", DataType::Int64, true)));
+    let mut columns = sorted.columns().to_vec();
+    columns.push(Arc::new(Int64Array::from(session_ids)));
+    columns.push(Arc::new(Int64Array::from(session_numbers)));
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+}
+
+fn is_temporal(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64)
+}
+
+/// Time values as f64: nanoseconds for temporal columns, raw values otherwise
+fn time_values(time: &ArrayRef, gap: Gap) -> BlazeResult<Vec<Option<f64>>> {
+    if is_temporal(time.data_type()) {
+        let nanos = cast(time, &DataType::Timestamp(TimeUnit::Nanosecond, None))?;
+        let nanos = cast(&nanos, &DataType::Int64)?;
+        let nanos = nanos.as_any().downcast_ref::<Int64Array>().unwrap();
+        return Ok(nanos.iter().map(|v| v.map(|n| n as f64)).collect());
+    }
+
+    if let Gap::Nanos(_) = gap {
+        return Err(BlazeError::InvalidInput(format!(
+            "SESSIONIZE interval gap needs a timestamp or date column, got {}",
+            time.data_type()
+        )));
+    }
+    let values = cast(time, &DataType::Float64)
+        .map_err(|_| invalid_input!("SESSIONIZE time column of type {} is not supported", time.data_type()))?;
+    let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+    Ok(values.iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::StringArray;
+
+    #[test]
+    fn test_sessionize_numeric_gap() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("user", DataType::Utf8, false),
+            Field::new("t", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["b", "a", "a", "a", "b", "a"])),
+                Arc::new(Int64Array::from(vec![Some(5), Some(1), Some(3), Some(20), Some(7), None])),
+            ],
+        )
+        .unwrap();
+        let call = find_sessionize_calls("SELECT * FROM SESSIONIZE(TABLE e, 'user', 't', 5)").unwrap().remove(0);
+        assert_eq!(call.source_sql, "SELECT * FROM e");
+
+        let result = sessionize(&call, schema, &[batch], Gap::Number(5.0)).unwrap();
+        let ids = result.column(2).as_any().downcast_ref::<Int64Array>().unwrap();
+        let numbers = result.column(3).as_any().downcast_ref::<Int64Array>().unwrap();
+
+        // Sorted: a/NULL, a/1, a/3, a/20, b/5, b/7
+        assert_eq!(ids.iter().collect::<Vec<_>>(), vec![None, Some(1), Some(1), Some(2), Some(3), Some(3)]);
+        assert_eq!(numbers.iter().collect::<Vec<_>>(), vec![None, Some(1), Some(1), Some(2), Some(1), Some(1)]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Sessions with their own settings
+//!
+//! The engine's configuration applies to every query, so one user tuning it
+//! changes it for everyone. A session, opened with `create_session`, keeps
+//! its own values of some settings, changed with `SET` statements run in it:
+//!
+//! ```sql
+//! SET batch_size = 1024;
+//! SET time_zone = 'America/Chicago';  -- or SET TIME ZONE 'America/Chicago'
+//! SET max_result_rows = 10000;
+//! SET query_timeout_ms = 30000;
+//! SET max_result_rows = DEFAULT;      -- back to the engine's setting
+//! ```
+//!
+//! Statements run in a session by passing its ID as `QueryOptions::session_id`,
+//! like BigQuery's session jobs. Variables not set in a session follow the
+//! engine's configuration. Tables, views and other objects are shared by all
+//! sessions; only settings are kept per session. Other `SET` statements are
+//! rejected in a session, as they would change the whole engine.
+
+use std::sync::{Arc, LazyLock};
+
+use chrono::{DateTime, Utc};
+use datafusion::prelude::SessionContext;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::SharedClock;
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::time_zone;
+
+/// Variables a session can set
+const SESSION_VARIABLES: &[&str] = &["batch_size", "time_zone", "max_result_rows", "query_timeout_ms"];
+
+/// Settings of a session overriding the engine's configuration; unset ones
+/// follow it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSettings {
+    /// Target batch size
+    pub batch_size: Option<usize>,
+    /// Time zone, e.g. `America/Chicago` or `+02:00`
+    pub time_zone: Option<String>,
+    /// Maximum rows a query may return; 0 means unlimited
+    pub max_result_rows: Option<usize>,
+    /// Milliseconds a query may run; 0 means no limit
+    pub query_timeout_ms: Option<u64>,
+}
+
+impl SessionSettings {
+    /// Set `variable` to `value`, or back to the engine's setting when
+    /// `value` is None
+    pub(crate) fn set(&mut self, variable: &str, value: Option<&str>) -> BlazeResult<()> {
+        match variable {
+            "batch_size" => {
+                let batch_size = value.map(|value| parse_number(variable, value)).transpose()?;
+                if batch_size == Some(0) {
+                    return Err(invalid_input!("batch_size must be greater than 0"));
+                }
+                self.batch_size = batch_size;
+            }
+            "time_zone" | "timezone" => {
+                if let Some(zone) = value {
+                    time_zone::validate_time_zone(zone)?;
+                }
+                self.time_zone = value.map(str::to_string);
+            }
+            "max_result_rows" => self.max_result_rows = value.map(|value| parse_number(variable, value)).transpose()?,
+            "query_timeout_ms" => self.query_timeout_ms = value.map(|value| parse_number(variable, value)).transpose()?,
+            _ => {
+                return Err(invalid_input!(
+                    "Cannot set '{}' in a session; session variables are {}",
+                    variable,
+                    SESSION_VARIABLES.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// A context planning queries with these settings over the tables of
+    /// `ctx`, or None if none of them changes planning
+    pub(crate) fn context(&self, ctx: &SessionContext, clock: &Arc<SharedClock>) -> Option<SessionContext> {
+        if self.batch_size.is_none() && self.time_zone.is_none() {
+            return None;
+        }
+        // The state shares its catalogs with `ctx`, so tables created in the
+        // session are seen by every session
+        let mut state = ctx.state();
+        if let Some(batch_size) = self.batch_size {
+            state.config_mut().options_mut().execution.batch_size = batch_size;
+        }
+        let session = SessionContext::new_with_state(state);
+        if let Some(zone) = &self.time_zone {
+            time_zone::apply(&session, zone, clock);
+        }
+        Some(session)
+    }
+}
+
+/// A session opened with `create_session`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// ID to pass as `QueryOptions::session_id`
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    /// When a statement last ran in the session
+    pub last_used_at: DateTime<Utc>,
+    /// Statements run in the session
+    pub statements: u64,
+    pub settings: SessionSettings,
+}
+
+impl SessionInfo {
+    pub(crate) fn new(created_at: DateTime<Utc>) -> Self {
+        Self {
+            session_id: format!("session_{:016x}", rand::random::<u64>()),
+            created_at,
+            last_used_at: created_at,
+            statements: 0,
+            settings: SessionSettings::default(),
+        }
+    }
+}
+
+/// Parse `SET variable = value`, `SET variable TO value` or
+/// `SET TIME ZONE 'zone'`, returning the lowercase variable and the value,
+/// or None for `DEFAULT`
+pub(crate) fn parse_set(sql: &str) -> Option<(String, Option<String>)> {
+    if let Some(zone) = time_zone::parse_set_time_zone(sql) {
+        return Some(("time_zone".to_string(), Some(zone)));
+    }
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*SET\s+([\w.]+)\s*(?:=|\s+TO\s)\s*(?:'([^']*)'|([^\s;']+))\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    let variable = captures[1].to_lowercase();
+    let value = match (captures.get(2), captures.get(3)) {
+        (Some(quoted), _) => Some(quoted.as_str().to_string()),
+        (None, Some(word)) if word.as_str().eq_ignore_ascii_case("default") => None,
+        (None, word) => word.map(|word| word.as_str().to_string()),
+    };
+    Some((variable, value))
+}
+
+fn parse_number<T: std::str::FromStr>(variable: &str, value: &str) -> BlazeResult<T> {
+    value
+        .parse()
+        .map_err(|_| invalid_input!("{} must be a non-negative integer, got '{}'", variable, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_set() {
+        assert_eq!(parse_set("SET batch_size = 1024"), Some(("batch_size".to_string(), Some("1024".to_string()))));
+        assert_eq!(parse_set("set Time_Zone TO 'Asia/Tokyo';"), Some(("time_zone".to_string(), Some("Asia/Tokyo".to_string()))));
+        assert_eq!(parse_set("SET TIME ZONE '+02:00'"), Some(("time_zone".to_string(), Some("+02:00".to_string()))));
+        assert_eq!(parse_set("SET max_result_rows = DEFAULT"), Some(("max_result_rows".to_string(), None)));
+        assert_eq!(parse_set("SELECT 1"), None);
+
+        let mut settings = SessionSettings::default();
+        settings.set("batch_size", Some("1024")).unwrap();
+        settings.set("timezone", Some("Europe/Paris")).unwrap();
+        assert_eq!((settings.batch_size, settings.time_zone.as_deref()), (Some(1024), Some("Europe/Paris")));
+        settings.set("batch_size", None).unwrap();
+        assert_eq!(settings.batch_size, None);
+
+        assert!(settings.set("batch_size", Some("0")).is_err());
+        assert!(settings.set("max_result_rows", Some("-1")).is_err());
+        assert!(settings.set("time_zone", Some("Mars/Olympus")).is_err());
+        assert!(settings.set("datafusion.execution.batch_size", Some("1")).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Query results handed to other processes through shared memory
+//!
+//! `BlazeQueryEngine::execute_query_shared` streams a query's result into an
+//! Arrow IPC file in `shared_result_dir`, by default `/dev/shm` where it
+//! exists, so the file lives in memory without being held by the engine. Any
+//! number of worker processes can map the file and read the result
+//! zero-copy, e.g. with pyarrow:
+//!
+//! ```python
+//! table = pyarrow.ipc.open_file(pyarrow.memory_map(handle["path"])).read_all()
+//! ```
+//!
+//! The file stays until `release_shared_result` is called or the engine is
+//! dropped. Removing it does not disturb workers that have already mapped
+//! it; their mapping lasts until they close it.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::writer::FileWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// A query result written to shared memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedResultInfo {
+    /// Name to release the result by, e.g. `blaze_result_3f2a9c0d1e4b5a67`
+    pub name: String,
+    /// Arrow IPC file holding the result
+    pub path: String,
+    pub rows: usize,
+    /// Size of the file
+    pub bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Where shared results go without `shared_result_dir`: the shared memory
+/// file system on Linux, the temporary directory elsewhere
+pub(crate) fn default_dir() -> PathBuf {
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() {
+        shm.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    }
+}
+
+/// Writes a result to its file batch by batch; the file is removed again
+/// unless the writer is finished
+pub(crate) struct SharedResultWriter {
+    name: String,
+    path: PathBuf,
+    writer: Option<FileWriter<BufWriter<File>>>,
+    rows: usize,
+}
+
+impl SharedResultWriter {
+    pub(crate) fn create(dir: &Path, schema: &SchemaRef) -> BlazeResult<Self> {
+        let name = format!("blaze_result_{:016x}", rand::random::<u64>());
+        let path = dir.join(format!("{}.arrow", name));
+        let file = File::create(&path)?;
+        let mut writer = Self { name, path, writer: None, rows: 0 };
+        writer.writer = Some(FileWriter::try_new(BufWriter::new(file), schema)?);
+        Ok(writer)
+    }
+
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> BlazeResult<()> {
+        self.rows += batch.num_rows();
+        match &mut self.writer {
+            Some(writer) => Ok(writer.write(batch)?),
+            None => Err(BlazeError::Internal("Shared result is already finished".to_string())),
+        }
+    }
+
+    pub(crate) fn finish(mut self, created_at: DateTime<Utc>) -> BlazeResult<SharedResultInfo> {
+        let mut writer = self.writer.take().ok_or_else(|| BlazeError::Internal("Shared result is already finished".to_string()))?;
+        let finished = writer.finish().map_err(BlazeError::from).and_then(|_| {
+            writer.into_inner().map_err(BlazeError::from)?.into_inner().map_err(|e| BlazeError::Io(e.into_error()))
+        });
+        if let Err(e) = finished {
+            remove_file(&self.path);
+            return Err(e);
+        }
+        Ok(SharedResultInfo {
+            name: self.name.clone(),
+            path: self.path.display().to_string(),
+            rows: self.rows,
+            bytes: std::fs::metadata(&self.path)?.len(),
+            created_at,
+        })
+    }
+}
+
+impl Drop for SharedResultWriter {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            remove_file(&self.path);
+        }
+    }
+}
+
+/// Results written to shared memory and not released yet
+#[derive(Debug, Default)]
+pub(crate) struct SharedResults {
+    results: HashMap<String, SharedResultInfo>,
+}
+
+impl SharedResults {
+    pub(crate) fn insert(&mut self, info: SharedResultInfo) {
+        self.results.insert(info.name.clone(), info);
+    }
+
+    pub(crate) fn release(&mut self, name: &str) -> BlazeResult<SharedResultInfo> {
+        let info = self.results.remove(name).ok_or_else(|| invalid_input!("Shared result '{}' not found", name))?;
+        remove_file(Path::new(&info.path));
+        Ok(info)
+    }
+
+    /// Unreleased results, oldest first
+    pub(crate) fn list(&self) -> Vec<SharedResultInfo> {
+        let mut results: Vec<_> = self.results.values().cloned().collect();
+        results.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+        results
+    }
+}
+
+impl Drop for SharedResults {
+    fn drop(&mut self) {
+        for info in self.results.values() {
+            remove_file(Path::new(&info.path));
+        }
+    }
+}
+
+fn remove_file(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove shared result file {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::ipc::reader::FileReader;
+
+    #[test]
+    fn test_write_and_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2, 3]))]).unwrap();
+
+        let mut writer = SharedResultWriter::create(dir.path(), &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.write(&batch).unwrap();
+        let info = writer.finish(Utc::now()).unwrap();
+        assert_eq!(info.rows, 6);
+        let reader = FileReader::try_new(File::open(&info.path).unwrap(), None).unwrap();
+        assert_eq!(reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>(), 6);
+
+        // Unfinished writers leave no file behind
+        let abandoned = SharedResultWriter::create(dir.path(), &schema).unwrap();
+        drop(abandoned);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let mut results = SharedResults::default();
+        results.insert(info.clone());
+        assert_eq!(results.list().len(), 1);
+        results.release(&info.name).unwrap();
+        assert!(!Path::new(&info.path).exists());
+        assert!(results.release(&info.name).is_err());
+    }
+}
@@ -0,0 +1,403 @@
+//! Graceful shutdown
+//!
+//! Every query run through `execute_query`, `execute_query_batches` or
+//! `execute_stream` is tracked from the moment it is accepted until its
+//! result, or for streams the stream itself, is dropped.
+//! `BlazeQueryEngine::shutdown` then:
+//!
+//! 1. stops accepting queries; new ones fail with `BlazeError::ShuttingDown`,
+//! 2. waits up to `drain_timeout_ms` for the tracked queries to finish,
+//! 3. cancels the ones still running, which fail with `BlazeError::Cancelled`,
+//! 4. saves the engine's state with `snapshot_to`, when a directory is given,
+//!    so tables and query statistics are written out from a quiet engine,
+//! 5. and reports what was drained and what was interrupted.
+//!
+//! Queries are cancelled by dropping them at their next await point, so a
+//! query cancelled between two batches leaves no partial writes behind.
+//! Dropping a query drops its DataFusion streams, which stops the tasks
+//! executing its plan.
+//!
+//! Each tracked query has an ID, chosen by the caller through
+//! `QueryOptions::query_id` or generated, e.g. `query_3f2a9c0d1e4b5a67`.
+//! `BlazeQueryEngine::running_queries` lists them and `cancel_query` cancels
+//! one the same way shutdown cancels them all.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
+use futures::{Future, FutureExt, Stream};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use crate::engine_state::EngineStateInfo;
+use crate::error::{BlazeError, BlazeResult};
+use crate::utils::fingerprint_sql;
+
+const SHUTDOWN_REASON: &str = "the engine is shutting down";
+
+/// How `BlazeQueryEngine::shutdown` winds the engine down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownOptions {
+    /// How long running queries may take to finish before they are
+    /// cancelled, in milliseconds (default: 30000)
+    pub drain_timeout_ms: u64,
+    /// Save the engine's state here once no query is running
+    pub state_dir: Option<PathBuf>,
+}
+
+impl Default for ShutdownOptions {
+    fn default() -> Self {
+        Self { drain_timeout_ms: 30_000, state_dir: None }
+    }
+}
+
+/// A query that was still running when the drain timeout ran out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterruptedQuery {
+    /// ID the query ran under
+    #[serde(default)]
+    pub query_id: String,
+    /// Fingerprint from `utils::fingerprint_sql`
+    pub fingerprint: String,
+    /// The statement as submitted
+    pub sql: String,
+    /// When the query was accepted
+    pub started_at: DateTime<Utc>,
+    /// How long it had been running when it was cancelled
+    pub running_ms: u64,
+}
+
+/// What happened during a shutdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    /// Queries running when the shutdown began that finished in time
+    pub drained_queries: usize,
+    /// Queries cancelled after the drain timeout
+    pub interrupted: Vec<InterruptedQuery>,
+    /// The saved state, when `state_dir` was given
+    pub state: Option<EngineStateInfo>,
+    /// Total time the shutdown took in milliseconds
+    pub duration_ms: u64,
+}
+
+/// A query being tracked, as listed by `running_queries`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningQueryInfo {
+    /// ID to cancel the query by
+    pub query_id: String,
+    /// Fingerprint from `utils::fingerprint_sql`
+    pub fingerprint: String,
+    /// The statement as submitted
+    pub sql: String,
+    /// When the query was accepted
+    pub started_at: DateTime<Utc>,
+    /// How long it has been running
+    pub running_ms: u64,
+}
+
+struct TrackedQuery {
+    query_id: String,
+    sql: String,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    cancel: CancellationToken,
+}
+
+impl TrackedQuery {
+    fn info(&self) -> RunningQueryInfo {
+        RunningQueryInfo {
+            query_id: self.query_id.clone(),
+            fingerprint: fingerprint_sql(&self.sql),
+            sql: self.sql.clone(),
+            started_at: self.started_at,
+            running_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackerState {
+    shutting_down: bool,
+    next_id: u64,
+    running: HashMap<u64, TrackedQuery>,
+}
+
+/// The queries an engine is running
+#[derive(Default)]
+pub(crate) struct QueryTracker {
+    state: Mutex<TrackerState>,
+    finished: Notify,
+    cancel: CancellationToken,
+}
+
+/// Keeps a query counted as running until dropped
+pub(crate) struct QueryGuard {
+    tracker: Arc<QueryTracker>,
+    id: u64,
+    query_id: String,
+    /// Cancelled by `cancel_query` or with the whole tracker
+    cancel: CancellationToken,
+}
+
+impl QueryGuard {
+    pub(crate) fn query_id(&self) -> &str {
+        &self.query_id
+    }
+
+    /// Run `query`, giving up on it if it is cancelled first
+    pub(crate) async fn cancellable<T>(&self, query: impl Future<Output = BlazeResult<T>>) -> BlazeResult<T> {
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => Err(self.cancelled_error()),
+            outcome = query => outcome,
+        }
+    }
+
+    fn cancelled_error(&self) -> BlazeError {
+        cancelled_error(&self.tracker.cancel, &self.query_id)
+    }
+}
+
+/// Why a query was cancelled: with all others on shutdown, or on its own
+fn cancelled_error(shutdown: &CancellationToken, query_id: &str) -> BlazeError {
+    match shutdown.is_cancelled() {
+        true => BlazeError::Cancelled(SHUTDOWN_REASON.to_string()),
+        false => BlazeError::Cancelled(format!("query {} was cancelled", query_id)),
+    }
+}
+
+impl Drop for QueryGuard {
+    fn drop(&mut self) {
+        self.tracker.state.lock().running.remove(&self.id);
+        self.tracker.finished.notify_waiters();
+    }
+}
+
+impl QueryTracker {
+    /// Count `sql` as running under `query_id`, or a generated ID, unless
+    /// the engine is shutting down or a running query has that ID
+    pub(crate) fn begin(self: &Arc<Self>, sql: &str, query_id: Option<&str>) -> BlazeResult<QueryGuard> {
+        let mut state = self.state.lock();
+        if state.shutting_down {
+            return Err(BlazeError::ShuttingDown);
+        }
+        let query_id = match query_id {
+            Some("") => return Err(BlazeError::InvalidInput("Query IDs cannot be empty".to_string())),
+            Some(query_id) if state.running.values().any(|query| query.query_id == query_id) => {
+                return Err(BlazeError::InvalidInput(format!("A query with ID '{}' is already running", query_id)));
+            }
+            Some(query_id) => query_id.to_string(),
+            None => format!("query_{:016x}", rand::random::<u64>()),
+        };
+        let id = state.next_id;
+        state.next_id += 1;
+        let cancel = self.cancel.child_token();
+        state.running.insert(
+            id,
+            TrackedQuery {
+                query_id: query_id.clone(),
+                sql: sql.to_string(),
+                started_at: Utc::now(),
+                started: Instant::now(),
+                cancel: cancel.clone(),
+            },
+        );
+        Ok(QueryGuard { tracker: self.clone(), id, query_id, cancel })
+    }
+
+    /// Run `query` as a tracked query that is cancelled if the engine shuts
+    /// down before it finishes
+    pub(crate) async fn run<T>(self: &Arc<Self>, sql: &str, query: impl Future<Output = BlazeResult<T>>) -> BlazeResult<T> {
+        let guard = self.begin(sql, None)?;
+        guard.cancellable(query).await
+    }
+
+    /// Wrap a query's result stream so it counts as running until dropped
+    /// and ends with an error if the query is cancelled first, or is still
+    /// open at `deadline`
+    pub(crate) fn track_stream(
+        &self,
+        guard: QueryGuard,
+        inner: SendableRecordBatchStream,
+        deadline: Option<(tokio::time::Instant, u64)>,
+    ) -> SendableRecordBatchStream {
+        Box::pin(TrackedStream {
+            inner,
+            cancelled: Box::pin(guard.cancel.clone().cancelled_owned()),
+            deadline: deadline.map(|(at, timeout_ms)| (Box::pin(tokio::time::sleep_until(at)), timeout_ms)),
+            guard,
+            done: false,
+        })
+    }
+
+    /// The queries running now, oldest first
+    pub(crate) fn running(&self) -> Vec<RunningQueryInfo> {
+        let mut running: Vec<_> = self.state.lock().running.values().map(TrackedQuery::info).collect();
+        running.sort_by_key(|query| query.started_at);
+        running
+    }
+
+    /// Cancel the running query with `query_id`, returning whether there
+    /// was one
+    pub(crate) fn cancel(&self, query_id: &str) -> bool {
+        let state = self.state.lock();
+        match state.running.values().find(|query| query.query_id == query_id) {
+            Some(query) => {
+                query.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop accepting queries, returning how many are running
+    pub(crate) fn stop_accepting(&self) -> BlazeResult<usize> {
+        let mut state = self.state.lock();
+        if state.shutting_down {
+            return Err(BlazeError::ShuttingDown);
+        }
+        state.shutting_down = true;
+        Ok(state.running.len())
+    }
+
+    /// Wait until no query is running or `timeout` passes, returning whether
+    /// the queries all finished
+    pub(crate) async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Registered before checking, so a query finishing in between
+            // still wakes this up
+            let finished = self.finished.notified();
+            if self.state.lock().running.is_empty() {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, finished).await.is_err() {
+                return self.state.lock().running.is_empty();
+            }
+        }
+    }
+
+    /// Cancel the queries still running and wait for them to stop
+    pub(crate) async fn cancel_all(&self) -> Vec<InterruptedQuery> {
+        let interrupted = self
+            .running()
+            .into_iter()
+            .map(|query| InterruptedQuery {
+                query_id: query.query_id,
+                fingerprint: query.fingerprint,
+                sql: query.sql,
+                started_at: query.started_at,
+                running_ms: query.running_ms,
+            })
+            .collect();
+        self.cancel.cancel();
+
+        // Cancelled queries stop at their next await point; streams nobody
+        // polls any more are only released when dropped, so don't wait on
+        // them forever
+        self.drain(Duration::from_secs(1)).await;
+        interrupted
+    }
+}
+
+/// A result stream that ends with a cancellation error when its query is
+/// cancelled, or a timeout error at its deadline
+struct TrackedStream {
+    inner: SendableRecordBatchStream,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+    deadline: Option<(Pin<Box<tokio::time::Sleep>>, u64)>,
+    guard: QueryGuard,
+    done: bool,
+}
+
+impl Stream for TrackedStream {
+    type Item = datafusion::error::Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if self.cancelled.poll_unpin(cx).is_ready() {
+            self.done = true;
+            let error = self.guard.cancelled_error();
+            return Poll::Ready(Some(Err(DataFusionError::External(Box::new(error)))));
+        }
+        if let Some((deadline, timeout_ms)) = &mut self.deadline {
+            if deadline.poll_unpin(cx).is_ready() {
+                let error = BlazeError::Timeout { timeout_ms: *timeout_ms };
+                self.done = true;
+                return Poll::Ready(Some(Err(DataFusionError::External(Box::new(error)))));
+            }
+        }
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for TrackedStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_then_cancel() {
+        let tracker = Arc::new(QueryTracker::default());
+        let quick = tracker.begin("SELECT 1", None).unwrap();
+        let slow = {
+            let tracker = tracker.clone();
+            tokio::spawn(async move {
+                tracker.run("SELECT slow()", futures::future::pending::<BlazeResult<()>>()).await
+            })
+        };
+        tokio::task::yield_now().await;
+
+        assert_eq!(tracker.stop_accepting().unwrap(), 2);
+        assert!(matches!(tracker.begin("SELECT 2", None), Err(BlazeError::ShuttingDown)));
+        drop(quick);
+        assert!(!tracker.drain(Duration::from_millis(50)).await);
+
+        let interrupted = tracker.cancel_all().await;
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0].sql, "SELECT slow()");
+        assert!(matches!(slow.await.unwrap(), Err(BlazeError::Cancelled(_))));
+        assert!(tracker.drain(Duration::ZERO).await);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_one_query() {
+        let tracker = Arc::new(QueryTracker::default());
+        let other = tracker.begin("SELECT 1", None).unwrap();
+        assert!(other.query_id().starts_with("query_"));
+        let guard = tracker.begin("SELECT slow()", Some("nightly")).unwrap();
+        assert!(matches!(tracker.begin("SELECT 2", Some("nightly")), Err(BlazeError::InvalidInput(_))));
+        assert_eq!(tracker.running().len(), 2);
+
+        assert!(!tracker.cancel("missing"));
+        assert!(tracker.cancel("nightly"));
+        let outcome = guard.cancellable(futures::future::pending::<BlazeResult<()>>()).await;
+        match outcome {
+            Err(BlazeError::Cancelled(reason)) => assert_eq!(reason, "query nightly was cancelled"),
+            other => panic!("expected a cancellation, got {:?}", other),
+        }
+        // Other queries keep running
+        assert!(!other.cancel.is_cancelled());
+        drop(guard);
+        assert_eq!(tracker.running()[0].query_id, other.query_id());
+    }
+}
@@ -0,0 +1,643 @@
+//! Approximate aggregates with bounded memory
+//!
+//! `APPROX_QUANTILES(col, n)` returns the `n + 1` approximate quantile
+//! boundaries of a numeric column: the minimum, the `n - 1` interior cut
+//! points and the maximum, as BigQuery does.
+//!
+//! ```sql
+//! SELECT array_element(APPROX_QUANTILES(latency_ms, 100), 96) AS p95 FROM requests;
+//! ```
+//!
+//! Values are summarized in a t-digest, so the aggregate runs in one pass and
+//! partial digests from parallel partitions merge without revisiting rows.
+//!
+//! Distinct counts use HyperLogLog sketches that can be stored and merged
+//! later, like BigQuery's `HLL_COUNT` functions:
+//!
+//! ```sql
+//! CREATE TABLE daily AS
+//!   SELECT day, HLL_COUNT.INIT(user_id) AS users FROM events GROUP BY day;
+//! SELECT HLL_COUNT.MERGE(users) AS monthly_users FROM daily;
+//! ```
+//!
+//! `HLL_COUNT.INIT(value [, precision])` builds a sketch from INT64, STRING or
+//! BYTES values, `HLL_COUNT.MERGE_PARTIAL` unions sketches into a sketch,
+//! `HLL_COUNT.MERGE` unions them into a count and `HLL_COUNT.EXTRACT` reads the
+//! count of a single sketch. Sketches of different precisions merge at the
+//! lowest one.
+
+use std::any::Any;
+use std::mem::size_of_val;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{Array, ArrayRef, BinaryArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::compute::{cast, filter, is_not_null, sort};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::common::ScalarValue;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::function::{AccumulatorArgs, StateFieldsArgs};
+use datafusion::logical_expr::utils::format_state_name;
+use datafusion::logical_expr::{
+    create_udf, Accumulator, AggregateUDF, AggregateUDFImpl, ColumnarValue, Signature, TypeSignature, Volatility,
+};
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion::prelude::SessionContext;
+use datafusion_functions_aggregate_common::tdigest::TDigest;
+
+/// Centroids kept per digest; bounds memory regardless of input size
+const DIGEST_SIZE: usize = 200;
+
+/// Largest number of quantiles `APPROX_QUANTILES` accepts
+const MAX_QUANTILES: i64 = 100_000;
+
+/// Default HyperLogLog precision, as in BigQuery
+const DEFAULT_PRECISION: u8 = 15;
+
+/// Accepted HyperLogLog precisions
+const PRECISIONS: std::ops::RangeInclusive<i64> = 10..=24;
+
+/// Serialization format version of HyperLogLog sketches
+const SKETCH_VERSION: u8 = 1;
+
+/// Register the approximate aggregates with a session
+pub fn register_functions(ctx: &SessionContext) {
+    ctx.register_udaf(AggregateUDF::from(ApproxQuantiles {
+        signature: Signature::any(2, Volatility::Immutable),
+    }));
+
+    // Dotted names are looked up exactly as written, so register both cases
+    for (kind, upper) in [
+        (HllFunction::Init, "HLL_COUNT.INIT"),
+        (HllFunction::Merge, "HLL_COUNT.MERGE"),
+        (HllFunction::MergePartial, "HLL_COUNT.MERGE_PARTIAL"),
+    ] {
+        let signature = match kind {
+            HllFunction::Init => Signature::one_of(
+                vec![TypeSignature::Any(1), TypeSignature::Any(2)],
+                Volatility::Immutable,
+            ),
+            _ => Signature::any(1, Volatility::Immutable),
+        };
+        ctx.register_udaf(AggregateUDF::from(HllAggregate { kind, signature }).with_aliases([upper]));
+    }
+    ctx.register_udf(
+        create_udf(
+            "hll_count.extract",
+            vec![DataType::Binary],
+            DataType::Int64,
+            Volatility::Immutable,
+            Arc::new(extract_counts),
+        )
+        .with_aliases(["HLL_COUNT.EXTRACT"]),
+    );
+}
+
+#[derive(Debug)]
+struct ApproxQuantiles {
+    signature: Signature,
+}
+
+impl AggregateUDFImpl for ApproxQuantiles {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "approx_quantiles"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        if !arg_types[0].is_numeric() && arg_types[0] != DataType::Null {
+            return Err(DataFusionError::Plan(format!(
+                "APPROX_QUANTILES requires a numeric column, got {}",
+                arg_types[0]
+            )));
+        }
+        if !arg_types[1].is_integer() {
+            return Err(DataFusionError::Plan(
+                "APPROX_QUANTILES number of quantiles must be an integer".to_string(),
+            ));
+        }
+        Ok(DataType::new_list(element_type(&arg_types[0]), true))
+    }
+
+    fn state_fields(&self, args: StateFieldsArgs) -> Result<Vec<Field>> {
+        // Same layout as `TDigest::to_scalar_state`
+        Ok(vec![
+            Field::new(format_state_name(args.name, "max_size"), DataType::UInt64, false),
+            Field::new(format_state_name(args.name, "sum"), DataType::Float64, false),
+            Field::new(format_state_name(args.name, "count"), DataType::UInt64, false),
+            Field::new(format_state_name(args.name, "max"), DataType::Float64, false),
+            Field::new(format_state_name(args.name, "min"), DataType::Float64, false),
+            Field::new_list(
+                format_state_name(args.name, "centroids"),
+                Field::new_list_field(DataType::Float64, true),
+                false,
+            ),
+        ])
+    }
+
+    fn accumulator(&self, args: AccumulatorArgs) -> Result<Box<dyn Accumulator>> {
+        let quantiles = literal_integer(&args.exprs[1], "APPROX_QUANTILES number of quantiles")?;
+        if !(1..=MAX_QUANTILES).contains(&quantiles) {
+            return Err(DataFusionError::Plan(format!(
+                "APPROX_QUANTILES number of quantiles must be between 1 and {}, got {}",
+                MAX_QUANTILES, quantiles
+            )));
+        }
+
+        let DataType::List(element) = args.return_type else {
+            return Err(DataFusionError::Internal("APPROX_QUANTILES must return a list".to_string()));
+        };
+        Ok(Box::new(QuantilesAccumulator {
+            digest: TDigest::new(DIGEST_SIZE),
+            quantiles: quantiles as usize,
+            element_type: element.data_type().clone(),
+        }))
+    }
+}
+
+/// Integer columns keep integer boundaries; everything else is FLOAT64
+fn element_type(input: &DataType) -> DataType {
+    if input.is_integer() {
+        DataType::Int64
+    } else {
+        DataType::Float64
+    }
+}
+
+/// Value of a constant argument
+fn literal_value(expr: &Arc<dyn PhysicalExpr>, what: &str) -> Result<ScalarValue> {
+    let batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
+    match expr.evaluate(&batch) {
+        Ok(ColumnarValue::Scalar(value)) => Ok(value),
+        _ => Err(DataFusionError::Plan(format!("{} must be a literal, got {}", what, expr))),
+    }
+}
+
+/// Integer value of a constant argument
+fn literal_integer(expr: &Arc<dyn PhysicalExpr>, what: &str) -> Result<i64> {
+    match literal_value(expr, what)?.cast_to(&DataType::Int64)? {
+        ScalarValue::Int64(Some(n)) => Ok(n),
+        other => Err(DataFusionError::Plan(format!("{} must be an integer literal, got {}", what, other))),
+    }
+}
+
+#[derive(Debug)]
+struct QuantilesAccumulator {
+    digest: TDigest,
+    quantiles: usize,
+    element_type: DataType,
+}
+
+impl QuantilesAccumulator {
+    /// The `quantiles + 1` boundaries, from minimum to maximum
+    fn boundaries(&self) -> Vec<f64> {
+        (0..=self.quantiles)
+            .map(|i| self.digest.estimate_quantile(i as f64 / self.quantiles as f64))
+            .collect()
+    }
+}
+
+impl Accumulator for QuantilesAccumulator {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        let mut values = cast(&values[0], &DataType::Float64)?;
+        if values.null_count() > 0 {
+            values = filter(&values, &is_not_null(&values)?)?;
+        }
+        let sorted = sort(&values, None)?;
+        let sorted = sorted.as_any().downcast_ref::<Float64Array>().unwrap();
+        self.digest = self.digest.merge_sorted_f64(sorted.values());
+        Ok(())
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        if states.is_empty() {
+            return Ok(());
+        }
+        let mut digests = (0..states[0].len())
+            .map(|row| {
+                states
+                    .iter()
+                    .map(|state| ScalarValue::try_from_array(state, row))
+                    .collect::<Result<Vec<_>>>()
+                    .map(|state| TDigest::from_scalar_state(&state))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        digests.push(self.digest.clone());
+        self.digest = TDigest::merge_digests(&digests);
+        Ok(())
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        Ok(self.digest.to_scalar_state())
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        if self.digest.count() == 0 {
+            return ScalarValue::try_from(DataType::new_list(self.element_type.clone(), true));
+        }
+        let boundaries: Vec<ScalarValue> = self
+            .boundaries()
+            .into_iter()
+            .map(|value| match self.element_type {
+                DataType::Int64 => ScalarValue::Int64(Some(value.round() as i64)),
+                _ => ScalarValue::Float64(Some(value)),
+            })
+            .collect();
+        Ok(ScalarValue::List(ScalarValue::new_list_nullable(&boundaries, &self.element_type)))
+    }
+
+    fn size(&self) -> usize {
+        size_of_val(self) + self.digest.size() - size_of_val(&self.digest)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HllFunction {
+    Init,
+    Merge,
+    MergePartial,
+}
+
+#[derive(Debug)]
+struct HllAggregate {
+    kind: HllFunction,
+    signature: Signature,
+}
+
+impl AggregateUDFImpl for HllAggregate {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        match self.kind {
+            HllFunction::Init => "hll_count.init",
+            HllFunction::Merge => "hll_count.merge",
+            HllFunction::MergePartial => "hll_count.merge_partial",
+        }
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        match self.kind {
+            HllFunction::Init => {
+                let input = &arg_types[0];
+                if !(input.is_integer() || is_string(input) || is_binary(input) || *input == DataType::Null) {
+                    return Err(DataFusionError::Plan(format!(
+                        "HLL_COUNT.INIT supports INT64, STRING and BYTES values, got {}",
+                        input
+                    )));
+                }
+            }
+            _ => {
+                if !(is_binary(&arg_types[0]) || arg_types[0] == DataType::Null) {
+                    return Err(DataFusionError::Plan(format!(
+                        "{} expects a sketch produced by HLL_COUNT.INIT, got {}",
+                        self.name().to_uppercase(),
+                        arg_types[0]
+                    )));
+                }
+            }
+        }
+        Ok(match self.kind {
+            HllFunction::Merge => DataType::Int64,
+            _ => DataType::Binary,
+        })
+    }
+
+    fn state_fields(&self, args: StateFieldsArgs) -> Result<Vec<Field>> {
+        Ok(vec![Field::new(format_state_name(args.name, "sketch"), DataType::Binary, true)])
+    }
+
+    fn accumulator(&self, args: AccumulatorArgs) -> Result<Box<dyn Accumulator>> {
+        let precision = match args.exprs.get(1) {
+            Some(expr) if self.kind == HllFunction::Init => {
+                let precision = literal_integer(expr, "HLL_COUNT.INIT precision")?;
+                if !PRECISIONS.contains(&precision) {
+                    return Err(DataFusionError::Plan(format!(
+                        "HLL_COUNT.INIT precision must be between {} and {}, got {}",
+                        PRECISIONS.start(),
+                        PRECISIONS.end(),
+                        precision
+                    )));
+                }
+                precision as u8
+            }
+            _ => DEFAULT_PRECISION,
+        };
+        Ok(Box::new(HllAccumulator {
+            kind: self.kind,
+            precision,
+            sketch: None,
+        }))
+    }
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View)
+}
+
+fn is_binary(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Binary | DataType::LargeBinary | DataType::BinaryView)
+}
+
+#[derive(Debug)]
+struct HllAccumulator {
+    kind: HllFunction,
+    /// Precision of sketches started by `HLL_COUNT.INIT`
+    precision: u8,
+    /// `None` until a non-NULL value or sketch is seen
+    sketch: Option<Hll>,
+}
+
+impl HllAccumulator {
+    fn merge_sketches(&mut self, sketches: &ArrayRef) -> Result<()> {
+        let sketches = cast(sketches, &DataType::Binary)?;
+        let sketches = sketches.as_any().downcast_ref::<BinaryArray>().unwrap();
+        for bytes in sketches.iter().flatten() {
+            let other = Hll::from_bytes(bytes)?;
+            match &mut self.sketch {
+                Some(sketch) => sketch.merge(&other),
+                None => self.sketch = Some(other),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Accumulator for HllAccumulator {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        if self.kind != HllFunction::Init {
+            return self.merge_sketches(&values[0]);
+        }
+
+        let values = &values[0];
+        if values.len() == values.null_count() {
+            return Ok(());
+        }
+        let sketch = self.sketch.get_or_insert_with(|| Hll::new(self.precision));
+        if values.data_type().is_integer() {
+            let values = cast(values, &DataType::Int64)?;
+            let values = values.as_any().downcast_ref::<Int64Array>().unwrap();
+            values.iter().flatten().for_each(|v| sketch.insert(&v.to_le_bytes()));
+        } else if is_string(values.data_type()) {
+            let values = cast(values, &DataType::Utf8)?;
+            let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+            values.iter().flatten().for_each(|v| sketch.insert(v.as_bytes()));
+        } else {
+            let values = cast(values, &DataType::Binary)?;
+            let values = values.as_any().downcast_ref::<BinaryArray>().unwrap();
+            values.iter().flatten().for_each(|v| sketch.insert(v));
+        }
+        Ok(())
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        self.merge_sketches(&states[0])
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![ScalarValue::Binary(self.sketch.as_ref().map(Hll::to_bytes))])
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        Ok(match self.kind {
+            HllFunction::Merge => ScalarValue::Int64(Some(self.sketch.as_ref().map_or(0, Hll::estimate) as i64)),
+            _ => ScalarValue::Binary(self.sketch.as_ref().map(Hll::to_bytes)),
+        })
+    }
+
+    fn size(&self) -> usize {
+        size_of_val(self) + self.sketch.as_ref().map_or(0, |s| s.registers.capacity())
+    }
+}
+
+/// `HLL_COUNT.EXTRACT(sketch)`
+fn extract_counts(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let arrays = ColumnarValue::values_to_arrays(args)?;
+    let sketches = cast(&arrays[0], &DataType::Binary)?;
+    let sketches = sketches.as_any().downcast_ref::<BinaryArray>().unwrap();
+    let counts = sketches
+        .iter()
+        .map(|bytes| bytes.map(|b| Hll::from_bytes(b).map(|s| s.estimate() as i64)).transpose())
+        .collect::<Result<Int64Array>>()?;
+
+    let counts: ArrayRef = Arc::new(counts);
+    if args.iter().all(|arg| matches!(arg, ColumnarValue::Scalar(_))) {
+        return Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(&counts, 0)?));
+    }
+    Ok(ColumnarValue::Array(counts))
+}
+
+/// HyperLogLog sketch with `2^precision` six-bit registers
+#[derive(Debug, Clone, PartialEq)]
+struct Hll {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl Hll {
+    fn new(precision: u8) -> Self {
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    fn insert(&mut self, value: &[u8]) {
+        let hash = hash64(value);
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rank = ((hash << self.precision).leading_zeros() as u8 + 1).min(64 - self.precision + 1);
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Union with `other`, dropping to the lower of the two precisions
+    fn merge(&mut self, other: &Hll) {
+        if other.precision < self.precision {
+            *self = self.downgrade(other.precision);
+        }
+        let downgraded;
+        let other = if other.precision > self.precision {
+            downgraded = other.downgrade(self.precision);
+            &downgraded
+        } else {
+            other
+        };
+        for (register, value) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*value);
+        }
+    }
+
+    /// The sketch that would have been built at a lower precision. Index bits
+    /// dropped from each register move into the front of its rank.
+    fn downgrade(&self, precision: u8) -> Hll {
+        let shift = self.precision - precision;
+        let mut lower = Hll::new(precision);
+        for (index, &rank) in self.registers.iter().enumerate().filter(|(_, r)| **r > 0) {
+            let dropped = (index & ((1 << shift) - 1)) as u64;
+            let rank = if dropped == 0 {
+                shift + rank
+            } else {
+                shift - (64 - dropped.leading_zeros() as u8) + 1
+            };
+            let target = &mut lower.registers[index >> shift];
+            *target = (*target).max(rank);
+        }
+        lower
+    }
+
+    /// Estimated number of distinct values, with linear counting for small
+    /// cardinalities
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+
+    /// Serialize as version, precision and encoding bytes followed by either
+    /// every register (dense) or `(u32 index, u8 rank)` pairs (sparse)
+    fn to_bytes(&self) -> Vec<u8> {
+        let occupied = self.registers.iter().filter(|&&r| r > 0).count();
+        let sparse = occupied * 5 < self.registers.len();
+
+        let mut bytes = vec![SKETCH_VERSION, self.precision, sparse as u8];
+        if sparse {
+            bytes.reserve(occupied * 5);
+            for (index, &rank) in self.registers.iter().enumerate().filter(|(_, r)| **r > 0) {
+                bytes.extend_from_slice(&(index as u32).to_le_bytes());
+                bytes.push(rank);
+            }
+        } else {
+            bytes.extend_from_slice(&self.registers);
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Hll> {
+        let invalid = || DataFusionError::Execution("Invalid HLL_COUNT sketch".to_string());
+        let [version, precision, sparse, payload @ ..] = bytes else {
+            return Err(invalid());
+        };
+        if *version != SKETCH_VERSION || !PRECISIONS.contains(&(*precision as i64)) {
+            return Err(invalid());
+        }
+
+        let mut sketch = Hll::new(*precision);
+        match sparse {
+            0 if payload.len() == sketch.registers.len() => sketch.registers.copy_from_slice(payload),
+            1 if payload.len() % 5 == 0 => {
+                for entry in payload.chunks_exact(5) {
+                    let index = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
+                    *sketch.registers.get_mut(index).ok_or_else(invalid)? = entry[4];
+                }
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(sketch)
+    }
+}
+
+/// FNV-1a with a SplitMix64 finalizer. Sketches outlive the process, so the
+/// hash must not depend on the Rust version or a random seed.
+fn hash64(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::ListArray;
+
+    fn accumulator(quantiles: usize) -> QuantilesAccumulator {
+        QuantilesAccumulator {
+            digest: TDigest::new(DIGEST_SIZE),
+            quantiles,
+            element_type: DataType::Float64,
+        }
+    }
+
+    #[test]
+    fn test_quantiles_merge_partial_digests() {
+        let mut left = accumulator(4);
+        let mut right = accumulator(4);
+        left.update_batch(&[Arc::new(Float64Array::from_iter_values((1..=500).map(f64::from)))]).unwrap();
+        right.update_batch(&[Arc::new(Float64Array::from(vec![Some(1000.0), None, Some(600.0)]))]).unwrap();
+        right.update_batch(&[Arc::new(Float64Array::from_iter_values((501..=1000).map(f64::from)))]).unwrap();
+
+        let state = right
+            .state()
+            .unwrap()
+            .into_iter()
+            .map(|value| value.to_array())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        left.merge_batch(&state).unwrap();
+
+        let ScalarValue::List(list) = left.evaluate().unwrap() else { panic!("expected a list") };
+        let values = list.as_any().downcast_ref::<ListArray>().unwrap().value(0);
+        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(values.len(), 5);
+        assert_eq!(values.value(0), 1.0);
+        assert_eq!(values.value(4), 1000.0);
+        assert!((values.value(2) - 500.0).abs() < 10.0);
+
+        assert!(accumulator(4).evaluate().unwrap().is_null());
+    }
+
+    #[test]
+    fn test_hll_estimate_and_serialization() {
+        let mut sketch = Hll::new(DEFAULT_PRECISION);
+        for i in 0..50_000i64 {
+            sketch.insert(&(i % 20_000).to_le_bytes());
+        }
+        let estimate = sketch.estimate() as f64;
+        assert!((estimate - 20_000.0).abs() / 20_000.0 < 0.02, "estimate {}", estimate);
+        assert_eq!(Hll::from_bytes(&sketch.to_bytes()).unwrap(), sketch);
+
+        let mut small = Hll::new(12);
+        small.insert(b"a");
+        small.insert(b"b");
+        let bytes = small.to_bytes();
+        assert_eq!(bytes.len(), 3 + 2 * 5);
+        assert_eq!(Hll::from_bytes(&bytes).unwrap().estimate(), 2);
+        assert!(Hll::from_bytes(&bytes[..7]).is_err());
+    }
+
+    #[test]
+    fn test_hll_downgrade_matches_lower_precision() {
+        let (mut high, mut low) = (Hll::new(14), Hll::new(12));
+        for i in 0..5_000i64 {
+            high.insert(&i.to_le_bytes());
+            low.insert(&i.to_le_bytes());
+        }
+        assert_eq!(high.downgrade(12), low);
+
+        high.merge(&Hll::new(12));
+        assert_eq!(high, low);
+    }
+}
@@ -0,0 +1,155 @@
+//! Slot-based scheduling, after BigQuery's slots
+//!
+//! BigQuery runs queries on a reservation of slots, units of parallelism
+//! shared among the queries running at the time. With `slots` set, the
+//! engine does the same for capacity planning: each query is planned with
+//! as many partitions as slots it is granted, so its parallelism shrinks as
+//! more queries run alongside it.
+//!
+//! A query is granted an equal share of the pool among the queries running
+//! with it, `slots / running`, limited to the slots not held by others,
+//! which queries started earlier keep until they finish. A query always gets
+//! at least one slot, so the pool never makes queries wait; admission control
+//! (see `admission`) limits how many run. Queries in a resource group get
+//! their group's `cpu_share` of their slots.
+//!
+//! `EngineStats::slots` reports the pool's use, including the slot-milliseconds
+//! spent, BigQuery's `totalSlotMs`, for comparing workloads.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Use of the slot pool
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlotStats {
+    /// Slots in the pool; 0 when slot scheduling is off
+    pub total_slots: usize,
+    /// Slots held by running queries
+    pub slots_in_use: usize,
+    /// Queries holding slots
+    pub running_queries: usize,
+    /// Queries granted slots so far
+    pub scheduled_queries: u64,
+    /// Average slots granted per query
+    pub avg_slots_per_query: f64,
+    /// Most slots held at once
+    pub peak_slots_in_use: usize,
+    /// Slots held multiplied by the milliseconds they were held, summed over
+    /// all queries
+    pub total_slot_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    total: usize,
+    in_use: usize,
+    running: usize,
+    scheduled: u64,
+    granted: u64,
+    peak_in_use: usize,
+    slot_ms: u64,
+}
+
+/// The engine's slots and the queries holding them
+#[derive(Debug, Default)]
+pub(crate) struct SlotPool {
+    state: Mutex<PoolState>,
+}
+
+/// Slots granted to a query, returned to the pool when dropped
+pub(crate) struct SlotGrant<'a> {
+    pool: &'a SlotPool,
+    slots: usize,
+    started: std::time::Instant,
+}
+
+impl SlotGrant<'_> {
+    /// Partitions the query is planned with
+    pub(crate) fn slots(&self) -> usize {
+        self.slots
+    }
+}
+
+impl Drop for SlotGrant<'_> {
+    fn drop(&mut self) {
+        let mut state = self.pool.state.lock();
+        state.in_use -= self.slots;
+        state.running -= 1;
+        state.slot_ms += self.slots as u64 * self.started.elapsed().as_millis() as u64;
+    }
+}
+
+impl SlotPool {
+    pub(crate) fn new(total: usize) -> Self {
+        let pool = Self::default();
+        pool.set_total(total);
+        pool
+    }
+
+    /// Resize the pool. Running queries keep the slots they hold.
+    pub(crate) fn set_total(&self, total: usize) {
+        self.state.lock().total = total;
+    }
+
+    /// Slots for a query starting now, or None when slot scheduling is off
+    pub(crate) fn grant(&self) -> Option<SlotGrant<'_>> {
+        let mut state = self.state.lock();
+        if state.total == 0 {
+            return None;
+        }
+        let fair_share = state.total.div_ceil(state.running + 1);
+        let free = state.total.saturating_sub(state.in_use);
+        let slots = fair_share.min(free).max(1);
+        state.in_use += slots;
+        state.running += 1;
+        state.scheduled += 1;
+        state.granted += slots as u64;
+        state.peak_in_use = state.peak_in_use.max(state.in_use);
+        Some(SlotGrant { pool: self, slots, started: std::time::Instant::now() })
+    }
+
+    pub(crate) fn stats(&self) -> SlotStats {
+        let state = self.state.lock();
+        SlotStats {
+            total_slots: state.total,
+            slots_in_use: state.in_use,
+            running_queries: state.running,
+            scheduled_queries: state.scheduled,
+            avg_slots_per_query: if state.scheduled > 0 {
+                state.granted as f64 / state.scheduled as f64
+            } else {
+                0.0
+            },
+            peak_slots_in_use: state.peak_in_use,
+            total_slot_ms: state.slot_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fair_shares() {
+        assert!(SlotPool::new(0).grant().is_none());
+
+        let pool = SlotPool::new(8);
+        let first = pool.grant().unwrap();
+        assert_eq!(first.slots(), 8);
+        // Later queries get what is left, and at least one slot
+        let second = pool.grant().unwrap();
+        assert_eq!(second.slots(), 1);
+        drop(first);
+        let third = pool.grant().unwrap();
+        assert_eq!(third.slots(), 4);
+        let fourth = pool.grant().unwrap();
+        assert_eq!(fourth.slots(), 3);
+
+        let stats = pool.stats();
+        assert_eq!((stats.slots_in_use, stats.running_queries, stats.peak_slots_in_use), (8, 3, 9));
+        drop((second, third, fourth));
+        let stats = pool.stats();
+        assert_eq!((stats.slots_in_use, stats.scheduled_queries, stats.avg_slots_per_query), (0, 4, 4.0));
+    }
+}
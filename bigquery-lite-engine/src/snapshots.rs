@@ -0,0 +1,416 @@
+//! Table snapshots and time travel (`FOR SYSTEM_TIME AS OF`)
+//!
+//! Every time a table's contents change the engine records a lightweight
+//! snapshot (the RecordBatches are reference counted, so this does not copy
+//! data). Queries can then read a table as it looked at a point in time:
+//!
+//! ```sql
+//! SELECT * FROM events FOR SYSTEM_TIME AS OF TIMESTAMP '2024-01-01 12:00:00'
+//! ```
+//!
+//! Tables that keep their own history, such as Iceberg tables, answer the
+//! same clause through a [`VersionedSource`] instead of the snapshot store.
+
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+use datafusion::arrow::array::{Array, TimestampNanosecondArray};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog_common::MemorySchemaProvider;
+use datafusion::datasource::{MemTable, TableProvider};
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::prelude::SessionContext;
+use datafusion::sql::parser::Statement as DFStatement;
+use datafusion::sql::sqlparser::ast::{
+    visit_relations_mut, Expr, Ident, ObjectName, TableFactor, TableVersion, VisitMut, VisitorMut,
+};
+use datafusion::sql::TableReference;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// Schema that holds the hidden tables backing time-travel reads
+pub const SNAPSHOT_SCHEMA: &str = "__snapshots";
+
+/// A point-in-time copy of a registered table
+#[derive(Debug, Clone)]
+pub struct TableSnapshot {
+    /// Monotonically increasing identifier, unique per engine
+    pub snapshot_id: u64,
+    /// When the change that produced this snapshot was committed
+    pub committed_at: DateTime<Utc>,
+    /// Table schema at this snapshot
+    pub schema: SchemaRef,
+    /// Table contents at this snapshot
+    pub batches: Vec<RecordBatch>,
+    /// Total number of rows
+    pub num_rows: usize,
+}
+
+/// Snapshot metadata returned by the listing APIs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    /// Table the snapshot belongs to
+    pub table_name: String,
+    /// Snapshot identifier
+    pub snapshot_id: u64,
+    /// Commit timestamp of the snapshot
+    pub committed_at: DateTime<Utc>,
+    /// Number of rows in the snapshot
+    pub num_rows: usize,
+}
+
+/// In-memory store of table snapshots, ordered by commit time per table
+#[derive(Debug, Default)]
+pub struct SnapshotStore {
+    tables: HashMap<String, Vec<TableSnapshot>>,
+    next_id: u64,
+    max_per_table: usize,
+}
+
+impl SnapshotStore {
+    /// Create a store keeping at most `max_per_table` snapshots per table
+    pub fn new(max_per_table: usize) -> Self {
+        Self {
+            tables: HashMap::new(),
+            next_id: 1,
+            max_per_table: max_per_table.max(1),
+        }
+    }
+
+    /// Record a new snapshot for `table`, returning its identifier and the
+    /// identifiers of any snapshots evicted by the per-table limit
+    pub fn record(
+        &mut self,
+        table: &str,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        committed_at: DateTime<Utc>,
+    ) -> (u64, Vec<u64>) {
+        let snapshot_id = self.next_id;
+        self.next_id += 1;
+
+        // Match the microsecond precision of SQL timestamps so a commit time
+        // read back from list() resolves to the same snapshot
+        let committed_at = committed_at.trunc_subsecs(6);
+
+        let num_rows = batches.iter().map(|b| b.num_rows()).sum();
+        let versions = self.tables.entry(table.to_string()).or_default();
+        versions.push(TableSnapshot {
+            snapshot_id,
+            committed_at,
+            schema,
+            batches,
+            num_rows,
+        });
+
+        let excess = versions.len().saturating_sub(self.max_per_table);
+        let evicted = versions.drain(..excess).map(|s| s.snapshot_id).collect();
+
+        (snapshot_id, evicted)
+    }
+
+    /// Find the snapshot of `table` that was current at `as_of`
+    pub fn snapshot_as_of(&self, table: &str, as_of: DateTime<Utc>) -> BlazeResult<&TableSnapshot> {
+        let versions = self.versions(table)?;
+        versions
+            .iter()
+            .rev()
+            .find(|s| s.committed_at <= as_of)
+            .ok_or_else(|| invalid_input!(
+                "Table '{}' has no snapshot as of {} (oldest snapshot is from {})",
+                table,
+                as_of.to_rfc3339(),
+                versions[0].committed_at.to_rfc3339()
+            ))
+    }
+
+    /// List snapshots for a table, oldest first
+    pub fn list(&self, table: &str) -> BlazeResult<Vec<SnapshotInfo>> {
+        let (name, versions) = self.entry(table)?;
+        Ok(versions
+            .iter()
+            .map(|s| SnapshotInfo {
+                table_name: name.clone(),
+                snapshot_id: s.snapshot_id,
+                committed_at: s.committed_at,
+                num_rows: s.num_rows,
+            })
+            .collect())
+    }
+
+    /// Drop snapshots committed before `older_than`. The latest snapshot of
+    /// each table is always kept since it represents the current contents.
+    /// Returns the `(table, snapshot_id)` pairs that were removed.
+    pub fn prune(&mut self, table: Option<&str>, older_than: DateTime<Utc>) -> Vec<(String, u64)> {
+        let mut removed = Vec::new();
+
+        for (name, versions) in self.tables.iter_mut() {
+            if table.is_some_and(|t| !t.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let keep_from = versions
+                .iter()
+                .take(versions.len().saturating_sub(1))
+                .take_while(|s| s.committed_at < older_than)
+                .count();
+            removed.extend(versions.drain(..keep_from).map(|s| (name.clone(), s.snapshot_id)));
+        }
+
+        removed
+    }
+
+    fn versions(&self, table: &str) -> BlazeResult<&Vec<TableSnapshot>> {
+        self.entry(table).map(|(_, versions)| versions)
+    }
+
+    fn entry(&self, table: &str) -> BlazeResult<(&String, &Vec<TableSnapshot>)> {
+        self.tables
+            .get_key_value(table)
+            .or_else(|| self.tables.iter().find(|(name, _)| name.eq_ignore_ascii_case(table)))
+            .filter(|(_, versions)| !versions.is_empty())
+            .ok_or_else(|| BlazeError::TableNotFound { table_name: table.to_string() })
+    }
+}
+
+/// Tables outside the snapshot store that keep versions of their own
+#[async_trait]
+pub(crate) trait VersionedSource: Sync {
+    /// A provider reading `table` as of `as_of`, with a hidden table name
+    /// unique to that version, or `None` when the source does not hold
+    /// `table`
+    async fn table_as_of(&self, table: &str, as_of: DateTime<Utc>) -> Option<BlazeResult<(String, Arc<dyn TableProvider>)>>;
+}
+
+/// Hidden table name used to expose a snapshot to the planner
+pub fn snapshot_table_name(table: &str, snapshot_id: u64) -> String {
+    format!("{}@{}", table, snapshot_id)
+}
+
+/// Cheap pre-check so only time-travel queries pay for the rewrite
+pub fn contains_time_travel(sql: &str) -> bool {
+    sql.to_ascii_uppercase().contains("SYSTEM_TIME")
+}
+
+/// Plan a query containing `FOR SYSTEM_TIME AS OF` clauses by rewriting each
+/// versioned table reference to the hidden table backing the matching snapshot,
+/// taken from the first of `sources` holding the table or else from `store`
+pub(crate) async fn plan_time_travel(
+    ctx: &SessionContext,
+    store: &SnapshotStore,
+    sources: &[&dyn VersionedSource],
+    sql: &str,
+) -> BlazeResult<LogicalPlan> {
+    let state = ctx.state();
+    let mut statement = state.sql_to_statement(sql, "BigQuery")?;
+
+    let mut collector = VersionCollector::default();
+    if let DFStatement::Statement(stmt) = &mut statement {
+        let _ = stmt.visit(&mut collector);
+    }
+    if collector.versions.is_empty() {
+        return Ok(state.statement_to_plan(statement).await?);
+    }
+
+    let mut replacements = HashMap::new();
+    for (placeholder, (table, expr)) in collector.versions.into_iter().enumerate() {
+        let as_of = evaluate_timestamp(ctx, &expr).await?;
+        let mut external = None;
+        for source in sources {
+            if let Some(resolved) = source.table_as_of(&table, as_of).await {
+                external = Some(resolved?);
+                break;
+            }
+        }
+        let hidden_name = match external {
+            Some((hidden_name, provider)) => {
+                ensure_snapshot_registered(ctx, &hidden_name, || Ok(provider))?;
+                hidden_name
+            }
+            None => {
+                let snapshot = store.snapshot_as_of(&table, as_of)?;
+                let hidden_name = snapshot_table_name(&table, snapshot.snapshot_id);
+                ensure_snapshot_registered(ctx, &hidden_name, || {
+                    Ok(Arc::new(MemTable::try_new(snapshot.schema.clone(), vec![snapshot.batches.clone()])?))
+                })?;
+                hidden_name
+            }
+        };
+        replacements.insert(placeholder_name(placeholder), hidden_name);
+    }
+
+    if let DFStatement::Statement(stmt) = &mut statement {
+        let _ = visit_relations_mut(stmt.as_mut(), |name: &mut ObjectName| {
+            if let Some(hidden) = name.0.last().and_then(|ident| replacements.get(&ident.value)) {
+                *name = ObjectName(vec![
+                    Ident::with_quote('"', SNAPSHOT_SCHEMA),
+                    Ident::with_quote('"', hidden.clone()),
+                ]);
+            }
+            ControlFlow::<()>::Continue(())
+        });
+    }
+
+    Ok(state.statement_to_plan(statement).await?)
+}
+
+/// Deregister the hidden tables of pruned snapshots
+pub fn deregister_snapshot_tables(ctx: &SessionContext, removed: &[(String, u64)]) {
+    for (table, snapshot_id) in removed {
+        let reference = TableReference::partial(SNAPSHOT_SCHEMA, snapshot_table_name(table, *snapshot_id));
+        let _ = ctx.deregister_table(reference);
+    }
+}
+
+fn placeholder_name(index: usize) -> String {
+    format!("__time_travel_{}", index)
+}
+
+/// Pulls `FOR SYSTEM_TIME AS OF` clauses out of the AST, leaving a
+/// placeholder table name behind for each one
+#[derive(Default)]
+struct VersionCollector {
+    versions: Vec<(String, Expr)>,
+}
+
+impl VisitorMut for VersionCollector {
+    type Break = ();
+
+    fn pre_visit_table_factor(&mut self, table_factor: &mut TableFactor) -> ControlFlow<Self::Break> {
+        if let TableFactor::Table { name, version, .. } = table_factor {
+            if let Some(TableVersion::ForSystemTimeAsOf(expr)) = version.take() {
+                let table = name
+                    .0
+                    .last()
+                    .map(|ident| match ident.quote_style {
+                        Some(_) => ident.value.clone(),
+                        None => ident.value.to_lowercase(),
+                    })
+                    .unwrap_or_default();
+                *name = ObjectName(vec![Ident::new(placeholder_name(self.versions.len()))]);
+                self.versions.push((table, expr));
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Evaluate a constant timestamp expression using the engine's own functions
+async fn evaluate_timestamp(ctx: &SessionContext, expr: &Expr) -> BlazeResult<DateTime<Utc>> {
+    let state = ctx.state();
+    let statement = state.sql_to_statement(&format!("SELECT CAST({} AS TIMESTAMP)", expr), "BigQuery")?;
+    let plan = state.statement_to_plan(statement).await?;
+    let batches = ctx.execute_logical_plan(plan).await?.collect().await?;
+
+    let value = batches
+        .first()
+        .filter(|batch| batch.num_rows() > 0)
+        .and_then(|batch| batch.column(0).as_any().downcast_ref::<TimestampNanosecondArray>())
+        .filter(|array| !array.is_null(0))
+        .map(|array| array.value(0))
+        .ok_or_else(|| invalid_input!("FOR SYSTEM_TIME AS OF expression '{}' is not a timestamp", expr))?;
+
+    Ok(DateTime::from_timestamp_nanos(value))
+}
+
+fn ensure_snapshot_registered(
+    ctx: &SessionContext,
+    hidden_name: &str,
+    table: impl FnOnce() -> BlazeResult<Arc<dyn TableProvider>>,
+) -> BlazeResult<()> {
+    let catalog = ctx.catalog("datafusion").ok_or_else(|| {
+        BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Catalog not found".to_string()))
+    })?;
+    if catalog.schema(SNAPSHOT_SCHEMA).is_none() {
+        catalog.register_schema(SNAPSHOT_SCHEMA, Arc::new(MemorySchemaProvider::new()))?;
+    }
+
+    let reference = TableReference::partial(SNAPSHOT_SCHEMA, hidden_name);
+    if !ctx.table_exist(reference.clone())? {
+        ctx.register_table(reference, table()?)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]))
+    }
+
+    #[test]
+    fn test_snapshot_as_of_picks_latest_before_timestamp() {
+        let mut store = SnapshotStore::new(10);
+        let t0 = Utc::now();
+        store.record("events", schema(), vec![], t0);
+        store.record("events", schema(), vec![], t0 + Duration::seconds(10));
+
+        assert_eq!(store.snapshot_as_of("events", t0 + Duration::seconds(5)).unwrap().snapshot_id, 1);
+        assert_eq!(store.snapshot_as_of("events", t0 + Duration::seconds(10)).unwrap().snapshot_id, 2);
+        assert!(store.snapshot_as_of("events", t0 - Duration::seconds(1)).is_err());
+        assert!(store.snapshot_as_of("missing", t0).is_err());
+    }
+
+    #[test]
+    fn test_prune_keeps_latest_snapshot() {
+        let mut store = SnapshotStore::new(10);
+        let t0 = Utc::now();
+        for i in 0..3 {
+            store.record("events", schema(), vec![], t0 + Duration::seconds(i));
+        }
+
+        let removed = store.prune(None, t0 + Duration::seconds(100));
+        assert_eq!(removed.len(), 2);
+        assert_eq!(store.list("events").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_record_enforces_per_table_limit() {
+        let mut store = SnapshotStore::new(2);
+        let t0 = Utc::now();
+        store.record("events", schema(), vec![], t0);
+        store.record("events", schema(), vec![], t0);
+        let (_, evicted) = store.record("events", schema(), vec![], t0);
+
+        assert_eq!(evicted, vec![1]);
+        assert_eq!(store.list("events").unwrap().len(), 2);
+    }
+
+    struct FixedVersions;
+
+    #[async_trait]
+    impl VersionedSource for FixedVersions {
+        async fn table_as_of(&self, table: &str, _as_of: DateTime<Utc>) -> Option<BlazeResult<(String, Arc<dyn TableProvider>)>> {
+            let ids = Arc::new(datafusion::arrow::array::Int64Array::from(vec![7, 8]));
+            let batch = RecordBatch::try_new(schema(), vec![ids]).unwrap();
+            let provider: Arc<dyn TableProvider> = Arc::new(MemTable::try_new(schema(), vec![vec![batch]]).unwrap());
+            (table == "lake").then(|| Ok(("lake@v2".to_string(), provider)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_time_travel_reads_versioned_sources() {
+        let ctx = SessionContext::new();
+        let store = SnapshotStore::new(10);
+        let sql = "SELECT SUM(id) AS total FROM lake FOR SYSTEM_TIME AS OF TIMESTAMP '2024-01-01 00:00:00'";
+
+        let plan = plan_time_travel(&ctx, &store, &[&FixedVersions], sql).await.unwrap();
+        let batches = ctx.execute_logical_plan(plan).await.unwrap().collect().await.unwrap();
+        let totals = batches[0].column(0).as_any().downcast_ref::<datafusion::arrow::array::Int64Array>().unwrap();
+        assert_eq!(totals.value(0), 15);
+        assert!(ctx.table_exist(TableReference::partial(SNAPSHOT_SCHEMA, "lake@v2")).unwrap());
+
+        let other = sql.replace("FROM lake", "FROM events");
+        assert!(plan_time_travel(&ctx, &store, &[&FixedVersions], &other).await.is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Pushing scans down to other SQL databases
+//!
+//! Tables of attached DuckDB and SQLite files and of PostgreSQL servers are
+//! read by running a query against the database on every scan. The query
+//! selects only the projected columns and carries the LIMIT and the
+//! comparisons of the WHERE clause that the database evaluates the same way
+//! DataFusion does. Filters are pushed down
+//! as inexact, so DataFusion still applies them to the rows returned.
+
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{Column, ScalarValue};
+use datafusion::error::Result;
+use datafusion::logical_expr::{Expr, Operator, TableProviderFilterPushDown};
+use datafusion::sql::unparser::Unparser;
+
+/// The query a database runs for a scan of `reference`, an already quoted
+/// table name
+pub(crate) fn scan_sql(
+    reference: &str,
+    schema: &SchemaRef,
+    projection: Option<&Vec<usize>>,
+    filters: &[Expr],
+    limit: Option<usize>,
+) -> Result<String> {
+    let columns = match projection {
+        // Only the row count is needed, e.g. for COUNT(*)
+        Some(indices) if indices.is_empty() => "NULL".to_string(),
+        Some(indices) => indices
+            .iter()
+            .map(|i| quote_identifier(schema.field(*i).name()))
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => "*".to_string(),
+    };
+    let mut sql = format!("SELECT {} FROM {}", columns, reference);
+
+    let unparser = Unparser::default();
+    let predicates = filters
+        .iter()
+        .map(|filter| Ok(format!("({})", unparser.expr_to_sql(&unqualified(filter)?)?)))
+        .collect::<Result<Vec<_>>>()?;
+    if !predicates.is_empty() {
+        sql.push_str(&format!(" WHERE {}", predicates.join(" AND ")));
+    }
+    if let Some(limit) = limit {
+        sql.push_str(&format!(" LIMIT {}", limit));
+    }
+    Ok(sql)
+}
+
+/// How each filter is pushed down: inexact when portable, so differences in
+/// how the two engines evaluate an expression cannot change the result
+pub(crate) fn filter_pushdown(filters: &[&Expr]) -> Vec<TableProviderFilterPushDown> {
+    filters
+        .iter()
+        .map(|f| match is_portable(f) {
+            true => TableProviderFilterPushDown::Inexact,
+            false => TableProviderFilterPushDown::Unsupported,
+        })
+        .collect()
+}
+
+/// Whether a filter only uses columns, plain literals and operators that
+/// the other databases evaluate the same way
+pub(crate) fn is_portable(expr: &Expr) -> bool {
+    match expr {
+        Expr::Column(_) => true,
+        Expr::Literal(value) => matches!(
+            value,
+            ScalarValue::Boolean(_)
+                | ScalarValue::Int8(_)
+                | ScalarValue::Int16(_)
+                | ScalarValue::Int32(_)
+                | ScalarValue::Int64(_)
+                | ScalarValue::UInt8(_)
+                | ScalarValue::UInt16(_)
+                | ScalarValue::UInt32(_)
+                | ScalarValue::UInt64(_)
+                | ScalarValue::Float32(_)
+                | ScalarValue::Float64(_)
+                | ScalarValue::Utf8(_)
+                | ScalarValue::LargeUtf8(_)
+                | ScalarValue::Date32(_)
+        ),
+        Expr::BinaryExpr(binary) => {
+            matches!(
+                binary.op,
+                Operator::Eq
+                    | Operator::NotEq
+                    | Operator::Lt
+                    | Operator::LtEq
+                    | Operator::Gt
+                    | Operator::GtEq
+                    | Operator::And
+                    | Operator::Or
+            ) && is_portable(&binary.left)
+                && is_portable(&binary.right)
+        }
+        Expr::Not(inner) | Expr::IsNull(inner) | Expr::IsNotNull(inner) => is_portable(inner),
+        Expr::Between(between) => {
+            is_portable(&between.expr) && is_portable(&between.low) && is_portable(&between.high)
+        }
+        Expr::InList(in_list) => is_portable(&in_list.expr) && in_list.list.iter().all(is_portable),
+        _ => false,
+    }
+}
+
+/// Drop table qualifiers, which name the table on the engine's side
+fn unqualified(expr: &Expr) -> Result<Expr> {
+    expr.clone()
+        .transform(|e| match e {
+            Expr::Column(column) if column.relation.is_some() => {
+                Ok(Transformed::yes(Expr::Column(Column::new_unqualified(column.name))))
+            }
+            other => Ok(Transformed::no(other)),
+        })
+        .map(|transformed| transformed.data)
+}
+
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,391 @@
+//! Read-only access to SQLite database files
+//!
+//! `attach_sqlite("app.db", "app")` opens the file read-only and registers
+//! every table and view in it as `dataset.table`, so small application
+//! databases can be joined with the engine's own tables:
+//!
+//! ```sql
+//! SELECT e.*, u.plan FROM events e JOIN app.users u ON e.user_id = u.id;
+//! ```
+//!
+//! Nothing is copied at attach time: each scan queries the file with the
+//! projected columns, the simple comparisons of the WHERE clause and the
+//! LIMIT pushed down (see `sql_pushdown`).
+//!
+//! SQLite columns have declared types rather than fixed ones, so column
+//! types follow SQLite's affinity rules: declared types containing `INT`
+//! read as `INT64`, `CHAR`, `CLOB` or `TEXT` as `STRING`, `BLOB` as
+//! `BYTES`, `REAL`, `FLOA` or `DOUB` as `FLOAT64` and `BOOL` as `BOOL`.
+//! Dates and times, which SQLite keeps as text, and columns without a
+//! declared type read as `STRING`; other numeric types as `FLOAT64`. A value
+//! that cannot be read as its column's type fails the scan.
+
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use datafusion::catalog::{SchemaProvider, Session};
+use datafusion::catalog_common::MemorySchemaProvider;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::sql_pushdown::{self, quote_identifier};
+
+/// Rows per batch read from SQLite
+const BATCH_ROWS: usize = 8192;
+
+/// A SQLite file attached with `attach_sqlite`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqliteDatabaseInfo {
+    /// Dataset the database's tables are qualified with
+    pub dataset: String,
+    /// Path of the database file
+    pub path: String,
+    /// Registered tables and views as `dataset.table`, sorted
+    pub tables: Vec<String>,
+}
+
+/// An opened SQLite file with its tables wrapped as providers
+pub(crate) struct SqliteDatabase {
+    pub(crate) info: SqliteDatabaseInfo,
+    tables: Vec<(String, Arc<SqliteTable>)>,
+}
+
+impl SqliteDatabase {
+    /// Open `path` read-only and read the columns of every table and view
+    pub(crate) fn open(path: &Path, dataset: &str) -> BlazeResult<Self> {
+        if !path.is_file() {
+            return Err(invalid_input!("SQLite database '{}' does not exist", path.display()));
+        }
+        let connection = open_read_only(path)?;
+        let mut statement = connection
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type IN ('table', 'view') \
+                 AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name",
+            )
+            .map_err(sqlite_error)?;
+        let names = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sqlite_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+
+        let tables = names
+            .into_iter()
+            .map(|name| {
+                let table = SqliteTable::try_new(&connection, path, &name)?;
+                Ok((name, Arc::new(table)))
+            })
+            .collect::<BlazeResult<Vec<_>>>()?;
+
+        Ok(Self {
+            info: SqliteDatabaseInfo {
+                dataset: dataset.to_string(),
+                path: path.display().to_string(),
+                tables: tables.iter().map(|(name, _)| format!("{}.{}", dataset, name)).collect(),
+            },
+            tables,
+        })
+    }
+
+    /// Schema holding the database's tables, for `dataset.table`
+    pub(crate) fn schema(&self) -> BlazeResult<Arc<dyn SchemaProvider>> {
+        let schema = MemorySchemaProvider::new();
+        for (name, table) in &self.tables {
+            schema.register_table(name.clone(), table.clone())?;
+        }
+        Ok(Arc::new(schema))
+    }
+}
+
+/// A table or view of an attached SQLite file, queried on every scan
+#[derive(Debug)]
+pub(crate) struct SqliteTable {
+    path: PathBuf,
+    /// Quoted `"table"` reference
+    reference: String,
+    schema: SchemaRef,
+}
+
+impl SqliteTable {
+    fn try_new(connection: &Connection, path: &Path, name: &str) -> BlazeResult<Self> {
+        let mut statement = connection.prepare("SELECT name, type FROM pragma_table_info(?1)").map_err(sqlite_error)?;
+        let fields = statement
+            .query_map([name], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(sqlite_error)?
+            .map(|column| column.map(|(name, declared)| Field::new(name, column_type(&declared), true)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            reference: quote_identifier(name),
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    /// The SQL SQLite runs for a scan
+    fn scan_sql(&self, projection: Option<&Vec<usize>>, filters: &[Expr], limit: Option<usize>) -> Result<String> {
+        sql_pushdown::scan_sql(&self.reference, &self.schema, projection, filters, limit)
+    }
+}
+
+#[async_trait]
+impl TableProvider for SqliteTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(indices) => Arc::new(self.schema.project(indices)?),
+            None => self.schema.clone(),
+        };
+        let sql = self.scan_sql(projection, filters, limit)?;
+        let path = self.path.clone();
+
+        let batch_schema = schema.clone();
+        let batches = tokio::task::spawn_blocking(move || read_rows(&path, &sql, &batch_schema))
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+        MemTable::try_new(schema, vec![batches])?.scan(state, None, &[], None).await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(sql_pushdown::filter_pushdown(filters))
+    }
+}
+
+/// The Arrow type a column with SQLite's `declared` type is read as
+fn column_type(declared: &str) -> DataType {
+    let declared = declared.to_uppercase();
+    let has = |part: &str| declared.contains(part);
+    if has("INT") {
+        DataType::Int64
+    } else if has("CHAR") || has("CLOB") || has("TEXT") {
+        DataType::Utf8
+    } else if has("BLOB") {
+        DataType::Binary
+    } else if has("REAL") || has("FLOA") || has("DOUB") {
+        DataType::Float64
+    } else if has("BOOL") {
+        DataType::Boolean
+    } else if declared.is_empty() || has("DATE") || has("TIME") {
+        DataType::Utf8
+    } else {
+        DataType::Float64
+    }
+}
+
+/// Run `sql` and read its rows into batches of `schema`
+fn read_rows(path: &Path, sql: &str, schema: &SchemaRef) -> BlazeResult<Vec<RecordBatch>> {
+    let connection = open_read_only(path)?;
+    let mut statement = connection.prepare(sql).map_err(sqlite_error)?;
+    let mut rows = statement.query([]).map_err(sqlite_error)?;
+
+    let mut batches = Vec::new();
+    let mut builders = ColumnBuilders::new(schema);
+    while let Some(row) = rows.next().map_err(sqlite_error)? {
+        for (index, builder) in builders.columns.iter_mut().enumerate() {
+            let value = row.get_ref(index).map_err(sqlite_error)?;
+            builder.append(value).map_err(|kind| {
+                BlazeError::SchemaMismatch(format!(
+                    "SQLite column '{}' holds a {} value that cannot be read as {}",
+                    schema.field(index).name(),
+                    kind,
+                    schema.field(index).data_type()
+                ))
+            })?;
+        }
+        builders.rows += 1;
+        if builders.rows == BATCH_ROWS {
+            batches.push(builders.finish(schema)?);
+        }
+    }
+    if builders.rows > 0 {
+        batches.push(builders.finish(schema)?);
+    }
+    Ok(batches)
+}
+
+/// Builders for the columns of the batch being read
+struct ColumnBuilders {
+    columns: Vec<ColumnBuilder>,
+    /// Rows appended, counted separately as a scan may read no columns
+    rows: usize,
+}
+
+impl ColumnBuilders {
+    fn new(schema: &SchemaRef) -> Self {
+        Self { columns: schema.fields().iter().map(|field| ColumnBuilder::new(field.data_type())).collect(), rows: 0 }
+    }
+
+    fn finish(&mut self, schema: &SchemaRef) -> BlazeResult<RecordBatch> {
+        let columns: Vec<ArrayRef> = self.columns.iter_mut().map(ColumnBuilder::finish).collect();
+        let options = RecordBatchOptions::new().with_row_count(Some(self.rows));
+        self.rows = 0;
+        Ok(RecordBatch::try_new_with_options(schema.clone(), columns, &options)?)
+    }
+}
+
+enum ColumnBuilder {
+    Int(Int64Builder),
+    Float(Float64Builder),
+    Bool(BooleanBuilder),
+    Text(StringBuilder),
+    Blob(BinaryBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Int64 => Self::Int(Int64Builder::new()),
+            DataType::Float64 => Self::Float(Float64Builder::new()),
+            DataType::Boolean => Self::Bool(BooleanBuilder::new()),
+            DataType::Binary => Self::Blob(BinaryBuilder::new()),
+            _ => Self::Text(StringBuilder::new()),
+        }
+    }
+
+    /// Append a value, or return the name of its storage class when it
+    /// cannot be read as the column's type
+    fn append(&mut self, value: ValueRef) -> std::result::Result<(), &'static str> {
+        match (self, value) {
+            (Self::Int(builder), ValueRef::Null) => builder.append_null(),
+            (Self::Float(builder), ValueRef::Null) => builder.append_null(),
+            (Self::Bool(builder), ValueRef::Null) => builder.append_null(),
+            (Self::Text(builder), ValueRef::Null) => builder.append_null(),
+            (Self::Blob(builder), ValueRef::Null) => builder.append_null(),
+            (Self::Int(builder), ValueRef::Integer(i)) => builder.append_value(i),
+            (Self::Int(builder), ValueRef::Real(f)) if f.fract() == 0.0 => builder.append_value(f as i64),
+            (Self::Int(builder), ValueRef::Text(text)) => builder.append_value(parse_text(text).ok_or("TEXT")?),
+            (Self::Float(builder), ValueRef::Integer(i)) => builder.append_value(i as f64),
+            (Self::Float(builder), ValueRef::Real(f)) => builder.append_value(f),
+            (Self::Float(builder), ValueRef::Text(text)) => builder.append_value(parse_text(text).ok_or("TEXT")?),
+            (Self::Bool(builder), ValueRef::Integer(i)) => builder.append_value(i != 0),
+            (Self::Bool(builder), ValueRef::Text(text)) => builder.append_value(parse_text(text).ok_or("TEXT")?),
+            (Self::Text(builder), ValueRef::Integer(i)) => builder.append_value(i.to_string()),
+            (Self::Text(builder), ValueRef::Real(f)) => builder.append_value(f.to_string()),
+            (Self::Text(builder), ValueRef::Text(text)) => builder.append_value(String::from_utf8_lossy(text)),
+            (Self::Blob(builder), ValueRef::Blob(bytes) | ValueRef::Text(bytes)) => builder.append_value(bytes),
+            (_, value) => return Err(storage_class(value)),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Self::Int(builder) => Arc::new(builder.finish()),
+            Self::Float(builder) => Arc::new(builder.finish()),
+            Self::Bool(builder) => Arc::new(builder.finish()),
+            Self::Text(builder) => Arc::new(builder.finish()),
+            Self::Blob(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// A number or boolean stored as text, as SQLite's numeric affinity would
+/// convert it
+fn parse_text<T: std::str::FromStr>(text: &[u8]) -> Option<T> {
+    std::str::from_utf8(text).ok()?.trim().parse().ok()
+}
+
+fn storage_class(value: ValueRef) -> &'static str {
+    match value {
+        ValueRef::Null => "NULL",
+        ValueRef::Integer(_) => "INTEGER",
+        ValueRef::Real(_) => "REAL",
+        ValueRef::Text(_) => "TEXT",
+        ValueRef::Blob(_) => "BLOB",
+    }
+}
+
+fn open_read_only(path: &Path) -> BlazeResult<Connection> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .map_err(sqlite_error)
+}
+
+fn sqlite_error(e: rusqlite::Error) -> BlazeError {
+    BlazeError::External(format!("SQLite: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::prelude::{col, lit};
+
+    fn create_database(path: &Path) {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, plan VARCHAR(10), score REAL, joined DATE);
+                 INSERT INTO users VALUES (1, 'ada', 'pro', 9.5, '2024-01-02'), (2, 'bob', 'free', 3, NULL);
+                 CREATE TABLE notes (body, weight NUMERIC, flagged BOOLEAN);
+                 INSERT INTO notes VALUES ('x', '1.5', 1), (2, 3, 0);
+                 CREATE VIEW paying AS SELECT * FROM users WHERE plan = 'pro';",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_column_type() {
+        assert_eq!(column_type("BIGINT"), DataType::Int64);
+        assert_eq!(column_type("varchar(20)"), DataType::Utf8);
+        assert_eq!(column_type("DOUBLE PRECISION"), DataType::Float64);
+        assert_eq!(column_type("DECIMAL(10,2)"), DataType::Float64);
+        assert_eq!(column_type("DATETIME"), DataType::Utf8);
+        assert_eq!(column_type(""), DataType::Utf8);
+        assert_eq!(column_type("BLOB"), DataType::Binary);
+    }
+
+    #[test]
+    fn test_open_lists_tables_and_reads_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        create_database(&path);
+
+        let database = SqliteDatabase::open(&path, "app").unwrap();
+        assert_eq!(database.info.tables, vec!["app.notes", "app.paying", "app.users"]);
+
+        let users = &database.tables.iter().find(|(name, _)| name == "users").unwrap().1;
+        let filter = col("users.plan").eq(lit("pro"));
+        let sql = users.scan_sql(Some(&vec![1, 3]), &[filter], Some(5)).unwrap();
+        assert_eq!(sql, "SELECT \"name\", \"score\" FROM \"users\" WHERE ((\"plan\" = 'pro')) LIMIT 5");
+        let schema = Arc::new(users.schema.project(&[1, 3]).unwrap());
+        let batches = read_rows(&path, &sql, &schema).unwrap();
+        assert_eq!(batches[0].num_rows(), 1);
+
+        // Text that parses as the column's type is converted
+        let notes = &database.tables.iter().find(|(name, _)| name == "notes").unwrap().1;
+        let batches = read_rows(&path, "SELECT * FROM \"notes\"", &notes.schema).unwrap();
+        assert_eq!(batches[0].num_rows(), 2);
+
+        assert!(SqliteDatabase::open(&dir.path().join("missing.db"), "x").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! `SUBSTR` and `SUBSTRING` with their arguments range-checked
+//!
+//! DataFusion's kernel adds a call's start position and length in plain
+//! `i64` arithmetic, which panics in debug builds and wraps around in
+//! release builds for values near the ends of the `i64` range. The
+//! replacement rejects positions and lengths far beyond any string's length
+//! before they reach the kernel, so such calls return an error instead.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::cast::as_int64_array;
+use datafusion::common::config::ConfigOptions;
+use datafusion::common::exec_err;
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::error::Result;
+use datafusion::functions::unicode::substr::SubstrFunc;
+use datafusion::logical_expr::expr::ScalarFunction;
+use datafusion::logical_expr::{ColumnarValue, Expr, LogicalPlan, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature};
+use datafusion::optimizer::AnalyzerRule;
+use datafusion::prelude::SessionContext;
+
+/// Largest position or length, in either direction, passed to the kernel.
+/// It sums position plus length over up to ten rows, which stays in range.
+const MAX_ARGUMENT: u64 = 1 << 58;
+
+/// Replace DataFusion's `substr` (and its `substring` alias). The SQL
+/// planner maps `SUBSTRING(...)` to DataFusion's function directly, so an
+/// analyzer rule swaps those calls too.
+pub(crate) fn register_functions(ctx: &SessionContext) {
+    let checked = Arc::new(ScalarUDF::from(CheckedSubstr { inner: datafusion::functions::unicode::substr() }));
+    ctx.register_udf(checked.as_ref().clone());
+    ctx.add_analyzer_rule(Arc::new(UseCheckedSubstr { checked }));
+}
+
+#[derive(Debug)]
+struct CheckedSubstr {
+    inner: Arc<ScalarUDF>,
+}
+
+impl ScalarUDFImpl for CheckedSubstr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        self.inner.signature()
+    }
+
+    fn aliases(&self) -> &[String] {
+        self.inner.aliases()
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        self.inner.coerce_types(arg_types)
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        self.inner.return_type(arg_types)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        // Without a length the kernel does no arithmetic that can overflow
+        if let [_, position, length] = args.args.as_slice() {
+            check_range(position, "position")?;
+            check_range(length, "length")?;
+        }
+        self.inner.invoke_with_args(args)
+    }
+}
+
+#[derive(Debug)]
+struct UseCheckedSubstr {
+    checked: Arc<ScalarUDF>,
+}
+
+impl AnalyzerRule for UseCheckedSubstr {
+    fn analyze(&self, plan: LogicalPlan, _config: &ConfigOptions) -> Result<LogicalPlan> {
+        let plan = plan.transform_up_with_subqueries(|plan| {
+            plan.map_expressions(|expr| {
+                expr.transform_up(|expr| match expr {
+                    Expr::ScalarFunction(call) if call.func.inner().as_any().is::<SubstrFunc>() => {
+                        Ok(Transformed::yes(Expr::ScalarFunction(ScalarFunction::new_udf(self.checked.clone(), call.args))))
+                    }
+                    expr => Ok(Transformed::no(expr)),
+                })
+            })
+        })?;
+        Ok(plan.data)
+    }
+
+    fn name(&self) -> &str {
+        "use_checked_substr"
+    }
+}
+
+fn check_range(arg: &ColumnarValue, what: &str) -> Result<()> {
+    let values = cast(&arg.to_array(1)?, &DataType::Int64)?;
+    if let Some(value) = as_int64_array(&values)?.iter().flatten().find(|v| v.unsigned_abs() > MAX_ARGUMENT) {
+        return exec_err!("SUBSTR {} {} is out of range", what, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_substr_arguments_are_checked() {
+        let ctx = SessionContext::new();
+        register_functions(&ctx);
+
+        let sql = "SELECT SUBSTR('hello', 2, 3) AS a, SUBSTRING('hello', -1, 4) AS b, SUBSTR('hello', 3) AS c";
+        let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        let row = datafusion::arrow::util::pretty::pretty_format_batches(&batches).unwrap().to_string();
+        assert!(row.contains("| ell | he | llo |"), "{}", row);
+
+        let sql = "SELECT SUBSTR('', 9223372036854775807, 2147483648) AS s";
+        let error = ctx.sql(sql).await.unwrap().collect().await.unwrap_err();
+        assert!(error.to_string().contains("SUBSTR position 9223372036854775807 is out of range"), "{}", error);
+    }
+}
@@ -0,0 +1,148 @@
+//! "Did you mean" hints for unknown tables and columns
+//!
+//! When planning fails because a table or column does not exist, the names
+//! that are closest by edit distance are added to the error:
+//!
+//! ```text
+//! Error during planning: table 'datafusion.public.evnts' not found; did you mean 'events'?
+//! Error during planning: No field named usr_id; did you mean 'user_id'?
+//! ```
+//!
+//! Names are compared case-insensitively, so a table created as `"Events"`
+//! is suggested for `events`. Errors without a close name are unchanged.
+
+use std::sync::LazyLock;
+
+use datafusion::common::{Column, SchemaError};
+use datafusion::error::DataFusionError;
+use datafusion::prelude::SessionContext;
+use regex::Regex;
+
+/// Most names listed in one hint
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Levenshtein distance between `a` and `b`, ignoring case
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The candidates close enough to `name` to be likely meant, closest first.
+/// A third of the name's length may differ, and at least one character.
+pub(crate) fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let allowed = (name.chars().count() / 3).max(1);
+    let mut matches: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= allowed)
+        .collect();
+    matches.sort();
+    matches.dedup_by(|a, b| a.1 == b.1);
+    matches.into_iter().take(MAX_SUGGESTIONS).map(|(_, candidate)| candidate.to_string()).collect()
+}
+
+/// `did you mean 'a'?`, `did you mean 'a' or 'b'?`, ...
+fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+    match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("did you mean {} or {}?", rest.join(", "), last),
+        _ => format!("did you mean {}?", quoted.join("")),
+    }
+}
+
+/// Add the closest table or column names to a planning error about an
+/// unknown table or column; other errors are returned as they are
+pub(crate) fn with_suggestions(error: DataFusionError, ctx: &SessionContext) -> DataFusionError {
+    let hint = match error.find_root() {
+        DataFusionError::Plan(message) => table_hint(message, ctx),
+        DataFusionError::SchemaError(SchemaError::FieldNotFound { field, valid_fields }, _) => {
+            column_hint(field, valid_fields)
+        }
+        _ => None,
+    };
+    match hint {
+        Some(message) => DataFusionError::Plan(message),
+        None => error,
+    }
+}
+
+fn table_hint(message: &str, ctx: &SessionContext) -> Option<String> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^table '([^']+)' not found$").unwrap());
+    let captures = PATTERN.captures(message)?;
+    let parts: Vec<&str> = captures[1].splitn(3, '.').collect();
+    let [catalog_name, schema_name, table_name] = parts[..] else {
+        return None;
+    };
+
+    let schema = ctx.catalog(catalog_name)?.schema(schema_name)?;
+    let tables = schema.table_names();
+    let suggestions = closest(table_name, tables.iter().map(String::as_str));
+    if suggestions.is_empty() {
+        return None;
+    }
+
+    // Suggest names the way they would be written in the failed query
+    let options = ctx.state().config_options().catalog.clone();
+    let prefix = if catalog_name != options.default_catalog {
+        format!("{}.{}.", catalog_name, schema_name)
+    } else if schema_name != options.default_schema {
+        format!("{}.", schema_name)
+    } else {
+        String::new()
+    };
+    let suggestions: Vec<String> = suggestions.into_iter().map(|s| format!("{}{}", prefix, s)).collect();
+    Some(format!("{}; {}", message, did_you_mean(&suggestions)))
+}
+
+fn column_hint(field: &Column, valid_fields: &[Column]) -> Option<String> {
+    // A qualified reference is only matched against that table's columns
+    let candidates: Vec<&Column> = valid_fields
+        .iter()
+        .filter(|valid| field.relation.is_none() || valid.relation == field.relation)
+        .collect();
+    let suggestions = closest(&field.name, candidates.iter().map(|c| c.name.as_str()));
+    if suggestions.is_empty() {
+        return None;
+    }
+    let suggestions: Vec<String> = match &field.relation {
+        Some(relation) => suggestions.into_iter().map(|s| format!("{}.{}", relation, s)).collect(),
+        None => suggestions,
+    };
+    Some(format!("No field named {}; {}", field.quoted_flat_name(), did_you_mean(&suggestions)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_names() {
+        assert_eq!(edit_distance("evnts", "events"), 1);
+        assert_eq!(edit_distance("Events", "events"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+
+        let tables = ["events", "event_types", "users", "orders"];
+        assert_eq!(closest("evnts", tables), vec!["events"]);
+        assert_eq!(closest("EVENTS", tables), vec!["events"]);
+        assert_eq!(closest("order", tables), vec!["orders"]);
+        assert!(closest("payments", tables).is_empty());
+        assert!(closest("events", tables).is_empty());
+
+        assert_eq!(did_you_mean(&["a".to_string()]), "did you mean 'a'?");
+        assert_eq!(
+            did_you_mean(&["a".to_string(), "b".to_string(), "c".to_string()]),
+            "did you mean 'a', 'b' or 'c'?"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Evicting least-recently-used tables under memory pressure
+//!
+//! The engine tracks the in-memory size of every table registered with
+//! `register_table` or grown with `append_to_table`, and when each was last
+//! registered, appended to or read by a query. With `evict_tables` set,
+//! storing a table first evicts other tables, least recently used first,
+//! until its rows, the remaining tables and the memory reserved by running
+//! queries fit within `table_memory_percent` of the memory limit. Pinned
+//! tables are never evicted; a table that cannot be made to fit fails to
+//! register.
+//!
+//! An evicted table is written to `table_spill_dir` as an Arrow IPC file and
+//! stays queryable from disk, or is dropped when no directory is set.
+//! Appending to a spilled table loads it back into memory.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::writer::FileWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::datasource::file_format::arrow::ArrowFormat;
+use datafusion::datasource::listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl};
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlazeResult;
+
+/// Evictions kept for `list_table_evictions`
+const MAX_EVICTION_EVENTS: usize = 100;
+
+/// A table evicted to make room for another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvictionEvent {
+    /// Evicted table
+    pub table_name: String,
+    /// In-memory size the eviction freed
+    pub bytes: u64,
+    /// When the table was evicted
+    pub evicted_at: DateTime<Utc>,
+    /// Arrow IPC file the table was spilled to, `None` if it was dropped
+    pub spill_path: Option<String>,
+    /// Table whose registration needed the room
+    pub evicted_for: String,
+}
+
+/// Memory held by one in-memory table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableMemoryInfo {
+    /// Table name
+    pub table_name: String,
+    /// Size of the table's batches in bytes
+    pub bytes: u64,
+    /// Whether the table is exempt from eviction
+    pub pinned: bool,
+}
+
+#[derive(Debug)]
+struct TrackedTable {
+    bytes: usize,
+    /// Value of the use counter when the table was last used
+    last_used: u64,
+}
+
+/// Sizes, recency and pins of the in-memory tables
+#[derive(Debug, Default)]
+pub(crate) struct TableEviction {
+    tables: HashMap<String, TrackedTable>,
+    /// Pinned names, kept while the table is replaced or not registered yet
+    pinned: HashSet<String>,
+    /// Spill files of evicted tables keyed by table name
+    spilled: HashMap<String, PathBuf>,
+    events: VecDeque<EvictionEvent>,
+    uses: u64,
+}
+
+impl TableEviction {
+    /// Record a table stored in memory with `bytes` of batches
+    pub(crate) fn record(&mut self, name: &str, bytes: usize) {
+        self.uses += 1;
+        self.tables.insert(name.to_string(), TrackedTable { bytes, last_used: self.uses });
+        if let Some(path) = self.spilled.remove(name) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Mark a table as just used
+    pub(crate) fn touch(&mut self, name: &str) {
+        self.uses += 1;
+        if let Some(table) = self.tables.get_mut(name) {
+            table.last_used = self.uses;
+        }
+    }
+
+    /// Stop tracking tables that `exists` no longer finds in memory
+    pub(crate) fn retain(&mut self, exists: impl Fn(&str) -> bool) {
+        self.tables.retain(|name, _| exists(name));
+    }
+
+    /// Names of the tracked tables
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.tables.keys().cloned().collect()
+    }
+
+    /// Bytes held by the tracked tables other than `except`
+    pub(crate) fn bytes_except(&self, except: &str) -> usize {
+        self.tables.iter().filter(|(name, _)| name.as_str() != except).map(|(_, t)| t.bytes).sum()
+    }
+
+    /// The least recently used table that may be evicted to make room for
+    /// `except`
+    pub(crate) fn victim(&self, except: &str) -> Option<String> {
+        self.tables
+            .iter()
+            .filter(|(name, _)| name.as_str() != except && !self.pinned.contains(name.as_str()))
+            .min_by_key(|(_, table)| table.last_used)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Record that `name` was evicted for `evicted_for`
+    pub(crate) fn evicted(&mut self, name: &str, spill_path: Option<PathBuf>, evicted_for: &str) -> EvictionEvent {
+        let bytes = self.tables.remove(name).map_or(0, |table| table.bytes);
+        let event = EvictionEvent {
+            table_name: name.to_string(),
+            bytes: bytes as u64,
+            evicted_at: Utc::now(),
+            spill_path: spill_path.as_ref().map(|path| path.display().to_string()),
+            evicted_for: evicted_for.to_string(),
+        };
+        if let Some(path) = spill_path {
+            self.spilled.insert(name.to_string(), path);
+        }
+        if self.events.len() == MAX_EVICTION_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+        event
+    }
+
+    pub(crate) fn is_spilled(&self, name: &str) -> bool {
+        self.spilled.contains_key(name)
+    }
+
+    /// Exempt a table from eviction, or make it evictable again
+    pub(crate) fn set_pinned(&mut self, name: &str, pinned: bool) {
+        match pinned {
+            true => self.pinned.insert(name.to_string()),
+            false => self.pinned.remove(name),
+        };
+    }
+
+    /// Tracked tables, most recently used first
+    pub(crate) fn memory(&self) -> Vec<TableMemoryInfo> {
+        let mut tables: Vec<_> = self.tables.iter().collect();
+        tables.sort_by_key(|(_, table)| std::cmp::Reverse(table.last_used));
+        tables
+            .into_iter()
+            .map(|(name, table)| TableMemoryInfo {
+                table_name: name.clone(),
+                bytes: table.bytes as u64,
+                pinned: self.pinned.contains(name),
+            })
+            .collect()
+    }
+
+    /// Recent evictions, oldest first
+    pub(crate) fn events(&self) -> Vec<EvictionEvent> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+/// In-memory size of a table's batches
+pub(crate) fn batches_bytes(batches: &[RecordBatch]) -> usize {
+    batches.iter().map(|batch| batch.get_array_memory_size()).sum()
+}
+
+/// Write an evicted table to an Arrow IPC file in `dir`
+pub(crate) fn write_spill_file(dir: &Path, name: &str, schema: &SchemaRef, batches: &[RecordBatch]) -> BlazeResult<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let file_name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    let path = dir.join(format!("{}.arrow", file_name));
+    let mut writer = FileWriter::try_new(File::create(&path)?, schema)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    Ok(path)
+}
+
+/// A table reading a spill file
+pub(crate) fn spilled_table(path: &Path, schema: SchemaRef) -> BlazeResult<Arc<ListingTable>> {
+    let path = std::path::absolute(path)?;
+    let url = ListingTableUrl::parse(path.to_string_lossy())?;
+    let config = ListingTableConfig::new(url)
+        .with_listing_options(ListingOptions::new(Arc::new(ArrowFormat)).with_file_extension(".arrow"))
+        .with_schema(schema);
+    Ok(Arc::new(ListingTable::try_new(config)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_used_victim() {
+        let mut eviction = TableEviction::default();
+        eviction.record("a", 100);
+        eviction.record("b", 200);
+        eviction.record("c", 300);
+        eviction.touch("a");
+
+        assert_eq!(eviction.victim("c").as_deref(), Some("b"));
+        assert_eq!(eviction.bytes_except("c"), 300);
+        eviction.set_pinned("b", true);
+        assert_eq!(eviction.victim("c").as_deref(), Some("a"));
+
+        let event = eviction.evicted("a", None, "c");
+        assert_eq!(event.bytes, 100);
+        assert_eq!(eviction.victim("c"), None);
+        assert_eq!(
+            eviction.memory().iter().map(|t| (t.table_name.as_str(), t.pinned)).collect::<Vec<_>>(),
+            vec![("c", false), ("b", true)]
+        );
+        assert_eq!(eviction.events().len(), 1);
+    }
+}
@@ -0,0 +1,56 @@
+//! Table functions evaluated by the engine rather than by DataFusion
+//!
+//! Calls such as `VECTOR_SEARCH(...)` and `SESSIONIZE(...)` are executed
+//! before the surrounding query is planned. Each result is registered as a
+//! hidden table and the call text is replaced by a reference to it. The plan
+//! keeps the table provider alive, so the hidden tables are deregistered as
+//! soon as planning finishes.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog_common::MemorySchemaProvider;
+use datafusion::common::TableReference;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::SessionContext;
+
+use crate::error::{BlazeError, BlazeResult};
+
+/// Schema holding table function results while a query is planned
+pub const RESULT_SCHEMA: &str = "__table_functions";
+
+static NEXT_RESULT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Hidden result tables registered for one query
+#[derive(Debug, Default)]
+pub struct HiddenResults {
+    names: Vec<String>,
+}
+
+impl HiddenResults {
+    /// Register `batch` as a hidden table, returning the SQL that references it
+    pub fn register(&mut self, ctx: &SessionContext, batch: RecordBatch) -> BlazeResult<String> {
+        let catalog = ctx.catalog("datafusion").ok_or_else(|| {
+            BlazeError::QueryExecution(datafusion::error::DataFusionError::Plan("Catalog not found".to_string()))
+        })?;
+        if catalog.schema(RESULT_SCHEMA).is_none() {
+            catalog.register_schema(RESULT_SCHEMA, Arc::new(MemorySchemaProvider::new()))?;
+        }
+
+        let name = format!("result_{}", NEXT_RESULT_ID.fetch_add(1, Ordering::Relaxed));
+        let table = MemTable::try_new(batch.schema(), vec![vec![batch]])?;
+        ctx.register_table(TableReference::partial(RESULT_SCHEMA, name.as_str()), Arc::new(table))?;
+
+        let reference = format!("\"{}\".\"{}\"", RESULT_SCHEMA, name);
+        self.names.push(name);
+        Ok(reference)
+    }
+
+    /// Drop the hidden tables once the query has been planned
+    pub fn deregister(self, ctx: &SessionContext) {
+        for name in self.names {
+            let _ = ctx.deregister_table(TableReference::partial(RESULT_SCHEMA, name));
+        }
+    }
+}
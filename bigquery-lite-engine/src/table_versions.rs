@@ -0,0 +1,181 @@
+//! Snapshot isolation for in-memory tables
+//!
+//! Every in-memory table is registered as a `VersionedTable`, which holds
+//! the table's latest version. A write builds the next version from the
+//! latest one and commits it with a single swap, so the catalog never loses
+//! the table part way through a write and readers never wait for writers.
+//! Statements pin the version of every table they read when they are
+//! planned: a query sees the same rows for the whole of its execution, even
+//! if it scans a table twice or appends commit while it runs. Writes to one
+//! table are serialized by `TableWriters`, so concurrent appends never
+//! build on the same version.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::catalog::Session;
+use datafusion::common::tree_node::Transformed;
+use datafusion::datasource::{provider_as_source, source_as_provider, TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::logical_expr::{Expr, LogicalPlan, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use tokio::sync::OwnedMutexGuard;
+
+#[derive(Debug, Clone)]
+struct TableVersion {
+    version: u64,
+    provider: Arc<dyn TableProvider>,
+}
+
+/// Source of the IDs telling apart tables registered under the same name
+static NEXT_TABLE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// An in-memory table whose contents are replaced by committing versions
+#[derive(Debug)]
+pub(crate) struct VersionedTable {
+    id: u64,
+    schema: SchemaRef,
+    latest: RwLock<TableVersion>,
+}
+
+impl VersionedTable {
+    pub(crate) fn new(provider: Arc<dyn TableProvider>) -> Self {
+        Self {
+            id: NEXT_TABLE_ID.fetch_add(1, Ordering::Relaxed),
+            schema: provider.schema(),
+            latest: RwLock::new(TableVersion { version: 1, provider }),
+        }
+    }
+
+    /// The table's ID, unique within the process, and its latest version
+    pub(crate) fn version(&self) -> (u64, u64) {
+        (self.id, self.latest.read().unwrap().version)
+    }
+
+    /// The table's latest committed contents
+    pub(crate) fn latest(&self) -> Arc<dyn TableProvider> {
+        self.latest.read().unwrap().provider.clone()
+    }
+
+    /// Make `provider`, which must have the table's schema, the latest
+    /// version. Returns its number.
+    pub(crate) fn commit(&self, provider: Arc<dyn TableProvider>) -> u64 {
+        let mut latest = self.latest.write().unwrap();
+        latest.version += 1;
+        latest.provider = provider;
+        latest.version
+    }
+}
+
+#[async_trait]
+impl TableProvider for VersionedTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        self.latest().scan(state, projection, filters, limit).await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        self.latest().supports_filters_pushdown(filters)
+    }
+}
+
+/// The latest contents behind a registered table: the latest version of a
+/// versioned table, or the provider itself
+pub(crate) fn latest(provider: Arc<dyn TableProvider>) -> Arc<dyn TableProvider> {
+    match provider.as_any().downcast_ref::<VersionedTable>() {
+        Some(table) => table.latest(),
+        None => provider,
+    }
+}
+
+/// Replace every versioned table read by `plan`, including in subqueries,
+/// with its latest version. Definitions such as `CREATE VIEW` are left
+/// alone so views keep reading the latest rows.
+pub(crate) fn pin_versions(plan: LogicalPlan) -> Result<LogicalPlan> {
+    if matches!(plan, LogicalPlan::Ddl(_)) {
+        return Ok(plan);
+    }
+    let pinned = plan.transform_up_with_subqueries(|plan| match plan {
+        LogicalPlan::TableScan(mut scan) => {
+            let version = source_as_provider(&scan.source)
+                .ok()
+                .and_then(|provider| provider.as_any().downcast_ref::<VersionedTable>().map(VersionedTable::latest));
+            match version {
+                Some(version) => {
+                    scan.source = provider_as_source(version);
+                    Ok(Transformed::yes(LogicalPlan::TableScan(scan)))
+                }
+                None => Ok(Transformed::no(LogicalPlan::TableScan(scan))),
+            }
+        }
+        plan => Ok(Transformed::no(plan)),
+    })?;
+    Ok(pinned.data)
+}
+
+/// One write lock per table name
+#[derive(Debug, Default)]
+pub(crate) struct TableWriters {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl TableWriters {
+    /// Wait until no other write to `name` is in progress
+    pub(crate) async fn lock(&self, name: &str) -> OwnedMutexGuard<()> {
+        let lock = self.locks.lock().unwrap().entry(name.to_string()).or_default().clone();
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::datasource::MemTable;
+    use datafusion::prelude::SessionContext;
+
+    fn table(values: Vec<i64>) -> Arc<dyn TableProvider> {
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(values))]).unwrap();
+        Arc::new(MemTable::try_new(schema, vec![vec![batch]]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_pinned_plan_ignores_later_commits() {
+        let ctx = SessionContext::new();
+        let versioned = Arc::new(VersionedTable::new(table(vec![1, 2])));
+        ctx.register_table("t", versioned.clone()).unwrap();
+
+        let plan = ctx.sql("SELECT COUNT(*) FROM t WHERE x IN (SELECT x FROM t)").await.unwrap().into_unoptimized_plan();
+        let pinned = pin_versions(plan).unwrap();
+        assert_eq!(versioned.commit(table(vec![1, 2, 3])), 2);
+
+        let batches = ctx.execute_logical_plan(pinned).await.unwrap().collect().await.unwrap();
+        let count = batches[0].column(0).as_any().downcast_ref::<Int64Array>().unwrap().value(0);
+        assert_eq!(count, 2);
+        assert_eq!(ctx.table("t").await.unwrap().count().await.unwrap(), 3);
+    }
+}
@@ -0,0 +1,327 @@
+//! Daily partitioning of in-memory tables by a timestamp column
+//!
+//! `set_time_partitioning("events", "event_time")` designates a column; from
+//! then on every batch registered or appended to `events` is split by the
+//! UTC day of that column, so callers never pre-split their data. Each day
+//! remembers the smallest and largest value it holds, and scans skip the
+//! days that comparisons on the column rule out:
+//!
+//! ```sql
+//! -- Reads only the partitions of 2024-03-01 and 2024-03-02
+//! SELECT COUNT(*) FROM events
+//! WHERE event_time >= TIMESTAMP '2024-03-01' AND event_time < TIMESTAMP '2024-03-03';
+//! ```
+//!
+//! Rows whose column is NULL go to a partition of their own, which no
+//! comparison selects and only `IS NULL` reads.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use datafusion::arrow::array::{Array, Date32Array, Int64Array, UInt32Array};
+use datafusion::arrow::compute::{cast, max, min, take_record_batch};
+use datafusion::arrow::datatypes::{DataType, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::Session;
+use datafusion::common::ScalarValue;
+use datafusion::datasource::{MemTable, TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::logical_expr::{Between, BinaryExpr, Expr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+
+/// A table's designated partitioning column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimePartitioning {
+    /// Partitioned table
+    pub table_name: String,
+    /// Timestamp or date column rows are bucketed by
+    pub column: String,
+}
+
+/// One day of a partitioned table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayPartitionInfo {
+    /// UTC day, `None` for rows whose column is NULL
+    pub day: Option<NaiveDate>,
+    /// Rows in the partition
+    pub rows: usize,
+    /// Batches holding the rows
+    pub batches: usize,
+}
+
+/// The partitions of a table, oldest day first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimePartitionInfo {
+    /// Partitioned table
+    pub table_name: String,
+    /// Column rows are bucketed by
+    pub column: String,
+    /// Partitions, the NULL partition first
+    pub partitions: Vec<DayPartitionInfo>,
+}
+
+/// Rows of one day with the range of the column's raw values
+#[derive(Debug)]
+struct DayPartition {
+    batches: Vec<RecordBatch>,
+    /// Smallest and largest raw value, `None` for the NULL partition
+    range: Option<(i64, i64)>,
+}
+
+/// Check that `column` of `schema` can partition a table
+fn validate_column(schema: &SchemaRef, column: &str) -> BlazeResult<()> {
+    let field = schema
+        .field_with_name(column)
+        .map_err(|_| invalid_input!("Cannot partition by unknown column '{}'", column))?;
+    match field.data_type() {
+        DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64 => Ok(()),
+        other => Err(invalid_input!(
+            "Cannot partition by column '{}' of type {}; expected a TIMESTAMP or DATE column",
+            column, other
+        )),
+    }
+}
+
+/// In-memory table split into daily partitions
+#[derive(Debug)]
+pub struct TimePartitionedTable {
+    schema: SchemaRef,
+    column: String,
+    /// Partitions keyed by days since the epoch, NULLs under `None`
+    partitions: BTreeMap<Option<i32>, DayPartition>,
+}
+
+impl TimePartitionedTable {
+    /// Split `batches` by the day of `column`. Batches holding a single day
+    /// are kept as they are, so appending day-aligned batches copies nothing.
+    pub fn try_new(schema: SchemaRef, batches: Vec<RecordBatch>, column: &str) -> BlazeResult<Self> {
+        validate_column(&schema, column)?;
+        let index = schema.index_of(column)?;
+
+        let mut partitions: BTreeMap<Option<i32>, DayPartition> = BTreeMap::new();
+        for batch in batches.into_iter().filter(|b| b.num_rows() > 0) {
+            let values = batch.column(index);
+            let days = cast(values, &DataType::Date32)?;
+            let days = days.as_any().downcast_ref::<Date32Array>().expect("cast to Date32");
+            let raw = cast(values, &DataType::Int64)?;
+            let raw = raw.as_any().downcast_ref::<Int64Array>().expect("cast to Int64");
+
+            let mut rows_by_day: BTreeMap<Option<i32>, Vec<u32>> = BTreeMap::new();
+            for row in 0..batch.num_rows() {
+                let day = days.is_valid(row).then(|| days.value(row));
+                rows_by_day.entry(day).or_default().push(row as u32);
+            }
+            let single_day = rows_by_day.len() == 1;
+
+            for (day, rows) in rows_by_day {
+                let indices = UInt32Array::from(rows);
+                let (part, part_raw) = if single_day {
+                    (batch.clone(), raw.clone())
+                } else {
+                    let part_raw = datafusion::arrow::compute::take(raw, &indices, None)?;
+                    let part_raw = part_raw.as_any().downcast_ref::<Int64Array>().expect("Int64").clone();
+                    (take_record_batch(&batch, &indices)?, part_raw)
+                };
+                let range = min(&part_raw).zip(max(&part_raw));
+
+                let partition = partitions.entry(day).or_insert(DayPartition { batches: Vec::new(), range: None });
+                partition.batches.push(part);
+                partition.range = match (partition.range, range) {
+                    (Some((lo, hi)), Some((part_lo, part_hi))) => Some((lo.min(part_lo), hi.max(part_hi))),
+                    (current, part) => current.or(part),
+                };
+            }
+        }
+
+        Ok(Self { schema, column: column.to_string(), partitions })
+    }
+
+    /// Days and sizes of the partitions
+    pub fn partition_info(&self) -> Vec<DayPartitionInfo> {
+        self.partitions
+            .iter()
+            .map(|(day, partition)| DayPartitionInfo {
+                day: day.and_then(|d| NaiveDate::from_num_days_from_ce_opt(d + EPOCH_DAYS_FROM_CE)),
+                rows: partition.batches.iter().map(|b| b.num_rows()).sum(),
+                batches: partition.batches.len(),
+            })
+            .collect()
+    }
+
+    /// Column the table is partitioned by
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Whether a partition may hold rows matching `filter`. Filters this
+    /// table does not understand never rule a partition out.
+    fn may_match(&self, filter: &Expr, range: Option<(i64, i64)>) -> bool {
+        match filter {
+            Expr::BinaryExpr(BinaryExpr { left, op: Operator::And, right }) => {
+                self.may_match(left, range) && self.may_match(right, range)
+            }
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => match self.comparison(left, *op, right) {
+                // Comparisons with NULL are never true
+                Some((op, value)) => range.is_some_and(|(lo, hi)| match op {
+                    Operator::Eq => lo <= value && value <= hi,
+                    Operator::Lt => lo < value,
+                    Operator::LtEq => lo <= value,
+                    Operator::Gt => hi > value,
+                    Operator::GtEq => hi >= value,
+                    _ => true,
+                }),
+                None => true,
+            },
+            Expr::Between(Between { expr, negated: false, low, high }) if self.is_column(expr) => {
+                match (self.raw_literal(low), self.raw_literal(high)) {
+                    (Some(low), Some(high)) => range.is_some_and(|(lo, hi)| hi >= low && lo <= high),
+                    _ => true,
+                }
+            }
+            // Only the NULL partition holds NULLs, and nothing else
+            Expr::IsNull(expr) if self.is_column(expr) => range.is_none(),
+            Expr::IsNotNull(expr) if self.is_column(expr) => range.is_some(),
+            _ => true,
+        }
+    }
+
+    /// A comparison of the column with a literal as `column op value`
+    fn comparison(&self, left: &Expr, op: Operator, right: &Expr) -> Option<(Operator, i64)> {
+        if !matches!(op, Operator::Eq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq) {
+            return None;
+        }
+        if self.is_column(left) {
+            Some((op, self.raw_literal(right)?))
+        } else if self.is_column(right) {
+            Some((op.swap()?, self.raw_literal(left)?))
+        } else {
+            None
+        }
+    }
+
+    fn is_column(&self, expr: &Expr) -> bool {
+        matches!(expr, Expr::Column(column) if column.name == self.column)
+    }
+
+    /// A literal as a raw value of the column's type, e.g. nanoseconds for a
+    /// nanosecond timestamp column
+    fn raw_literal(&self, expr: &Expr) -> Option<i64> {
+        let Expr::Literal(value) = expr else {
+            return None;
+        };
+        let column_type = self.schema.field_with_name(&self.column).ok()?.data_type();
+        match value.cast_to(column_type).ok()?.cast_to(&DataType::Int64).ok()? {
+            ScalarValue::Int64(value) => value,
+            _ => None,
+        }
+    }
+
+    /// Batches of the partitions that may hold rows matching all `filters`
+    fn pruned_batches(&self, filters: &[Expr]) -> Vec<RecordBatch> {
+        let selected: Vec<_> = self
+            .partitions
+            .values()
+            .filter(|partition| filters.iter().all(|filter| self.may_match(filter, partition.range)))
+            .collect();
+        debug!("Scanning {} of {} partitions of '{}'", selected.len(), self.partitions.len(), self.column);
+        selected.into_iter().flat_map(|partition| partition.batches.iter().cloned()).collect()
+    }
+}
+
+/// Days from 0001-01-01 to 1970-01-01
+const EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+#[async_trait]
+impl TableProvider for TimePartitionedTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // Filters are inexact, so DataFusion re-applies them to the rows of
+        // the partitions kept
+        MemTable::try_new(self.schema.clone(), vec![self.pruned_batches(filters)])?
+            .scan(state, projection, &[], limit)
+            .await
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|f| match f.column_refs().iter().any(|c| c.name == self.column) {
+                true => TableProviderFilterPushDown::Inexact,
+                false => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::TimestampMillisecondArray;
+    use datafusion::arrow::datatypes::{Field, Schema, TimeUnit};
+    use datafusion::prelude::{col, lit};
+
+    const DAY_MS: i64 = 86_400_000;
+
+    #[test]
+    fn test_split_and_prune_days() {
+        let schema = Arc::new(Schema::new(vec![Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true)]));
+        let batch = |values: Vec<Option<i64>>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(TimestampMillisecondArray::from(values))]).unwrap()
+        };
+        // Day 0 and day 2 mixed in one batch, then a batch of day 2 only
+        let mixed = batch(vec![Some(1_000), Some(2 * DAY_MS + 5), None, Some(DAY_MS - 1)]);
+        let aligned = batch(vec![Some(2 * DAY_MS + 10)]);
+        let table = TimePartitionedTable::try_new(schema.clone(), vec![mixed, aligned.clone()], "ts").unwrap();
+
+        let info = table.partition_info();
+        assert_eq!(info.iter().map(|p| p.day).collect::<Vec<_>>(), vec![
+            None,
+            NaiveDate::from_ymd_opt(1970, 1, 1),
+            NaiveDate::from_ymd_opt(1970, 1, 3),
+        ]);
+        assert_eq!(info.iter().map(|p| p.rows).collect::<Vec<_>>(), vec![1, 2, 2]);
+        // The aligned batch is kept whole
+        assert_eq!(table.partitions[&Some(2)].batches[1], aligned);
+
+        let ts = |ms: i64| lit(ScalarValue::TimestampMillisecond(Some(ms), None));
+        let rows = |filters: &[Expr]| table.pruned_batches(filters).iter().map(|b| b.num_rows()).sum::<usize>();
+        assert_eq!(rows(&[]), 5);
+        assert_eq!(rows(&[col("ts").gt_eq(ts(DAY_MS))]), 2);
+        assert_eq!(rows(&[ts(DAY_MS).gt(col("ts"))]), 2);
+        assert_eq!(rows(&[col("ts").eq(ts(DAY_MS - 1))]), 2);
+        assert_eq!(rows(&[col("ts").between(ts(DAY_MS), ts(2 * DAY_MS))]), 0);
+        assert_eq!(rows(&[col("ts").gt(ts(0)).and(col("ts").lt(ts(3 * DAY_MS)))]), 4);
+        assert_eq!(rows(&[col("ts").is_null()]), 1);
+        assert_eq!(rows(&[col("ts").is_not_null()]), 4);
+        // Filters the table cannot evaluate keep every partition
+        assert_eq!(rows(&[col("ts").not_eq(ts(0))]), 5);
+
+        let error = TimePartitionedTable::try_new(schema, vec![], "missing").err().unwrap();
+        assert!(error.to_string().contains("unknown column 'missing'"));
+    }
+}
@@ -0,0 +1,783 @@
+//! Date spines and time-series gap filling
+//!
+//! `GENERATE_DATE_ARRAY(start, end [, step])` and
+//! `GENERATE_TIMESTAMP_ARRAY(start, end, step)` return every value from
+//! `start` to `end` inclusive, `step` apart (one day by default for dates).
+//! They are scalar functions returning arrays, and also table functions
+//! producing one `value` row per element:
+//!
+//! ```sql
+//! SELECT value AS day FROM GENERATE_DATE_ARRAY(DATE '2024-01-01', DATE '2024-01-31');
+//! SELECT * FROM UNNEST(GENERATE_TIMESTAMP_ARRAY(TIMESTAMP '2024-01-01', TIMESTAMP '2024-01-02', INTERVAL 1 HOUR));
+//! ```
+//!
+//! `GAP_FILL` follows BigQuery's table function of the same name, emitting
+//! one row per bucket between the first and last event of each partition:
+//!
+//! ```sql
+//! SELECT * FROM GAP_FILL(
+//!   TABLE readings,
+//!   ts_column => 'time',
+//!   bucket_width => INTERVAL 1 MINUTE,
+//!   partitioning_columns => ['device'],
+//!   value_columns => [('temperature', 'locf'), ('status', 'null')]
+//! );
+//! ```
+//!
+//! Value columns are filled with `'null'` (only an event exactly on the
+//! bucket), `'locf'` (last observation carried forward) or `'linear'`
+//! (interpolated between the surrounding events). Without `value_columns`
+//! every other column is filled with `'null'`. Buckets are aligned to
+//! `origin`, `1950-01-01 00:00:00` by default, and `ignore_null_values`
+//! (default TRUE) makes `'locf'` and `'linear'` skip NULL observations.
+
+use std::any::Any;
+use std::sync::{Arc, LazyLock};
+
+use chrono::{DateTime, Months, NaiveDate, NaiveDateTime, TimeDelta};
+use datafusion::arrow::array::{
+    Array, ArrayRef, Float64Array, Int64Array, IntervalMonthDayNanoArray, ListArray, RecordBatch,
+    TimestampNanosecondArray, UInt32Array,
+};
+use datafusion::arrow::compute::{
+    cast, concat_batches, filter, filter_record_batch, is_not_null, lexsort_to_indices, take, SortColumn,
+};
+use datafusion::arrow::datatypes::{
+    DataType, Date32Type, Field, IntervalMonthDayNano, IntervalUnit, Schema, SchemaRef, TimeUnit,
+    TimestampNanosecondType,
+};
+use datafusion::arrow::row::{RowConverter, SortField};
+use datafusion::catalog::{TableFunctionImpl, TableProvider};
+use datafusion::common::{DFSchema, ScalarValue};
+use datafusion::datasource::MemTable;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::ExecutionProps;
+use datafusion::logical_expr::{
+    ColumnarValue, Expr, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, TypeSignature, Volatility,
+};
+use datafusion::physical_expr::create_physical_expr;
+use datafusion::prelude::SessionContext;
+use regex::Regex;
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::utils::{matching_paren, split_top_level};
+
+/// Upper bound on values generated by one array or gap-filled result
+const MAX_GENERATED_VALUES: usize = 10_000_000;
+
+const NANOS_PER_DAY: i64 = 86_400_000_000_000;
+
+/// Default bucket origin, `1950-01-01 00:00:00`, in nanoseconds
+const DEFAULT_ORIGIN: i64 = -631_152_000 * 1_000_000_000;
+
+/// Register the array generators as scalar and table functions
+pub fn register_functions(ctx: &SessionContext) {
+    for kind in [SeriesKind::Date, SeriesKind::Timestamp] {
+        let signature = match kind {
+            SeriesKind::Date => {
+                Signature::one_of(vec![TypeSignature::Any(2), TypeSignature::Any(3)], Volatility::Immutable)
+            }
+            SeriesKind::Timestamp => Signature::any(3, Volatility::Immutable),
+        };
+        ctx.register_udf(ScalarUDF::from(GenerateArray { kind, signature }));
+
+        // Table function names are looked up exactly as written
+        let table = Arc::new(GenerateArrayTable { kind });
+        ctx.register_udtf(kind.name(), table.clone());
+        ctx.register_udtf(&kind.name().to_uppercase(), table);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SeriesKind {
+    Date,
+    Timestamp,
+}
+
+impl SeriesKind {
+    fn name(self) -> &'static str {
+        match self {
+            SeriesKind::Date => "generate_date_array",
+            SeriesKind::Timestamp => "generate_timestamp_array",
+        }
+    }
+
+    fn element_type(self) -> DataType {
+        match self {
+            SeriesKind::Date => DataType::Date32,
+            SeriesKind::Timestamp => DataType::Timestamp(TimeUnit::Nanosecond, None),
+        }
+    }
+
+    /// Generated value (days or nanoseconds since the epoch) as a date-time
+    fn datetime(self, value: i64) -> Option<NaiveDateTime> {
+        match self {
+            SeriesKind::Date => unix_epoch()
+                .checked_add_signed(TimeDelta::try_days(value)?)
+                .map(|date| date.and_time(Default::default())),
+            SeriesKind::Timestamp => Some(DateTime::from_timestamp_nanos(value).naive_utc()),
+        }
+    }
+
+    /// Inverse of [`Self::datetime`]
+    fn value(self, datetime: NaiveDateTime) -> Option<i64> {
+        match self {
+            SeriesKind::Date => Some(datetime.date().signed_duration_since(unix_epoch()).num_days()),
+            SeriesKind::Timestamp => datetime.and_utc().timestamp_nanos_opt(),
+        }
+    }
+}
+
+fn unix_epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+/// `GENERATE_DATE_ARRAY` and `GENERATE_TIMESTAMP_ARRAY`
+#[derive(Debug)]
+struct GenerateArray {
+    kind: SeriesKind,
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for GenerateArray {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.kind.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::new_list(self.kind.element_type(), true))
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(&args.args)?;
+        let lists: ArrayRef = Arc::new(generate_lists(self.kind, &arrays)?);
+
+        if args.args.iter().all(|arg| matches!(arg, ColumnarValue::Scalar(_))) {
+            return Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(&lists, 0)?));
+        }
+        Ok(ColumnarValue::Array(lists))
+    }
+}
+
+/// One generated array per row of `(start, end [, step])`
+fn generate_lists(kind: SeriesKind, arrays: &[ArrayRef]) -> Result<ListArray> {
+    let bounds = |array: &ArrayRef| -> Result<Int64Array> {
+        let values = cast(&cast(array, &kind.element_type())?, &DataType::Int64)?;
+        Ok(values.as_any().downcast_ref::<Int64Array>().unwrap().clone())
+    };
+    let (starts, ends) = (bounds(&arrays[0])?, bounds(&arrays[1])?);
+    let steps = match arrays.get(2) {
+        Some(step) => {
+            let step = cast(step, &DataType::Interval(IntervalUnit::MonthDayNano)).map_err(|_| {
+                DataFusionError::Execution(format!("{} step must be an INTERVAL, got {}", kind.name(), step.data_type()))
+            })?;
+            step.as_any().downcast_ref::<IntervalMonthDayNanoArray>().unwrap().clone()
+        }
+        None => IntervalMonthDayNanoArray::from(vec![IntervalMonthDayNano::new(0, 1, 0); starts.len()]),
+    };
+
+    let rows = (0..starts.len())
+        .map(|row| {
+            if starts.is_null(row) || ends.is_null(row) || steps.is_null(row) {
+                return Ok(None);
+            }
+            generate(kind, starts.value(row), ends.value(row), steps.value(row)).map(Some)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(match kind {
+        SeriesKind::Date => ListArray::from_iter_primitive::<Date32Type, _, _>(
+            rows.into_iter().map(|row| row.map(|values| values.into_iter().map(|v| Some(v as i32)))),
+        ),
+        SeriesKind::Timestamp => ListArray::from_iter_primitive::<TimestampNanosecondType, _, _>(
+            rows.into_iter().map(|row| row.map(|values| values.into_iter().map(Some))),
+        ),
+    })
+}
+
+/// Values from `start` to `end` inclusive. Each is computed from `start`, so
+/// month steps keep the day of month where it exists (Jan 31, Feb 29, Mar 31).
+fn generate(kind: SeriesKind, start: i64, end: i64, step: IntervalMonthDayNano) -> Result<Vec<i64>> {
+    let error = |message: &str| DataFusionError::Execution(format!("{} {}", kind.name(), message));
+    if kind == SeriesKind::Date && step.nanoseconds % NANOS_PER_DAY != 0 {
+        return Err(error("step must be a whole number of days"));
+    }
+
+    let at = |times: i64| kind.datetime(start).and_then(|base| shift(base, step, times)).and_then(|v| kind.value(v));
+    let ascending = match at(1) {
+        Some(next) if next == start => return Err(error("step cannot be zero")),
+        Some(next) => next > start,
+        None => true,
+    };
+
+    let mut values = Vec::new();
+    for times in 0.. {
+        let Some(value) = at(times) else { break };
+        if (ascending && value > end) || (!ascending && value < end) {
+            break;
+        }
+        if values.len() == MAX_GENERATED_VALUES {
+            return Err(error(&format!("would produce more than {} values", MAX_GENERATED_VALUES)));
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// `value + step * times`, or `None` on overflow
+fn shift(value: NaiveDateTime, step: IntervalMonthDayNano, times: i64) -> Option<NaiveDateTime> {
+    let months = (step.months as i64).checked_mul(times)?;
+    let value = if months >= 0 {
+        value.checked_add_months(Months::new(u32::try_from(months).ok()?))?
+    } else {
+        value.checked_sub_months(Months::new(u32::try_from(-months).ok()?))?
+    };
+    let value = value.checked_add_signed(TimeDelta::try_days((step.days as i64).checked_mul(times)?)?)?;
+    value.checked_add_signed(TimeDelta::nanoseconds(step.nanoseconds.checked_mul(times)?))
+}
+
+/// `FROM GENERATE_DATE_ARRAY(...)` and `FROM GENERATE_TIMESTAMP_ARRAY(...)`
+#[derive(Debug)]
+struct GenerateArrayTable {
+    kind: SeriesKind,
+}
+
+impl TableFunctionImpl for GenerateArrayTable {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let expected = match self.kind {
+            SeriesKind::Date => 2..=3,
+            SeriesKind::Timestamp => 3..=3,
+        };
+        if !expected.contains(&args.len()) {
+            return Err(DataFusionError::Plan(format!(
+                "{} expects (start, end{}) arguments",
+                self.kind.name().to_uppercase(),
+                if self.kind == SeriesKind::Date { " [, step]" } else { ", step" }
+            )));
+        }
+
+        let props = ExecutionProps::new();
+        let empty = RecordBatch::new_empty(Arc::new(Schema::empty()));
+        let arrays = args
+            .iter()
+            .map(|arg| create_physical_expr(arg, &DFSchema::empty(), &props)?.evaluate(&empty)?.into_array(1))
+            .collect::<Result<Vec<_>>>()?;
+
+        let lists = generate_lists(self.kind, &arrays)?;
+        let values = if lists.is_null(0) {
+            datafusion::arrow::array::new_empty_array(&self.kind.element_type())
+        } else {
+            lists.value(0)
+        };
+        let schema = Arc::new(Schema::new(vec![Field::new("value", self.kind.element_type(), false)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![values])?;
+        Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+    }
+}
+
+/// How a value column is filled for buckets without an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillMethod {
+    /// NULL unless an event falls exactly on the bucket
+    Null,
+    /// Last observation carried forward
+    Locf,
+    /// Linear interpolation between the surrounding events
+    Linear,
+}
+
+impl FillMethod {
+    fn parse(value: &str) -> BlazeResult<Self> {
+        match value.to_lowercase().as_str() {
+            "null" => Ok(Self::Null),
+            "locf" => Ok(Self::Locf),
+            "linear" => Ok(Self::Linear),
+            other => Err(invalid_input!("Unknown GAP_FILL method '{}', expected 'null', 'locf' or 'linear'", other)),
+        }
+    }
+}
+
+/// Parsed `GAP_FILL(...)` call and its position in the query text
+#[derive(Debug, Clone)]
+pub struct GapFillCall {
+    /// Byte range of the call in the original SQL
+    pub span: (usize, usize),
+    /// Query producing the time series
+    pub source_sql: String,
+    /// Timestamp or date column
+    pub ts_column: String,
+    /// Bucket width expression as written
+    pub bucket_width: String,
+    /// Columns identifying independent series
+    pub partitioning_columns: Vec<String>,
+    /// Columns to fill and how; every other column with `Null` when absent
+    pub value_columns: Option<Vec<(String, FillMethod)>>,
+    /// Bucket origin expression as written
+    pub origin: Option<String>,
+    /// Whether `Locf` and `Linear` skip NULL observations
+    pub ignore_null_values: bool,
+}
+
+/// Cheap pre-check so only gap-filling queries pay for the rewrite
+pub fn contains_gap_fill(sql: &str) -> bool {
+    sql.to_ascii_uppercase().contains("GAP_FILL")
+}
+
+/// Find every `GAP_FILL(...)` call in `sql`
+pub fn find_gap_fill_calls(sql: &str) -> BlazeResult<Vec<GapFillCall>> {
+    static CALL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bGAP_FILL\s*\(").unwrap());
+    static TABLE_ARG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^TABLE\s+([A-Za-z_][\w.]*)$").unwrap());
+    static NAMED_ARG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^([A-Za-z_]\w*)\s*=>\s*(.+)$").unwrap());
+
+    let mut calls = Vec::new();
+    let mut cursor = 0;
+    while let Some(found) = CALL.find_at(sql, cursor) {
+        let open = found.end() - 1;
+        let close = matching_paren(sql, open).ok_or_else(|| invalid_input!("Unbalanced GAP_FILL(...)"))?;
+        let (positional, named): (Vec<&str>, Vec<&str>) = split_top_level(&sql[open + 1..close], ',')
+            .into_iter()
+            .map(str::trim)
+            .partition(|a| !NAMED_ARG.is_match(a));
+        let Some((source, positional)) = positional.split_first() else {
+            return Err(invalid_input!("GAP_FILL expects (TABLE source | (query), ts_column => '...', bucket_width => INTERVAL ...)"));
+        };
+
+        let source_sql = if let Some(table) = TABLE_ARG.captures(source) {
+            format!("SELECT * FROM {}", &table[1])
+        } else if source.starts_with('(') && source.ends_with(')') {
+            source[1..source.len() - 1].to_string()
+        } else {
+            return Err(invalid_input!("GAP_FILL source must be TABLE <name> or a (subquery), got '{}'", source));
+        };
+
+        let mut ts_column = positional.first().map(|c| string_literal(c)).transpose()?;
+        let mut bucket_width = positional.get(1).map(|w| w.to_string());
+        if positional.len() > 2 {
+            return Err(invalid_input!("GAP_FILL takes at most three positional arguments"));
+        }
+        let mut partitioning_columns = Vec::new();
+        let mut value_columns = None;
+        let mut origin = None;
+        let mut ignore_null_values = true;
+
+        for arg in named {
+            let captures = NAMED_ARG.captures(arg).unwrap();
+            let value = captures[2].trim();
+            match captures[1].to_lowercase().as_str() {
+                "ts_column" => ts_column = Some(string_literal(value)?),
+                "bucket_width" => bucket_width = Some(value.to_string()),
+                "partitioning_columns" => {
+                    partitioning_columns = array_items(value)?
+                        .into_iter()
+                        .map(string_literal)
+                        .collect::<BlazeResult<_>>()?
+                }
+                "value_columns" => {
+                    let columns = array_items(value)?
+                        .into_iter()
+                        .map(|item| {
+                            let pair = item
+                                .strip_prefix('(')
+                                .and_then(|p| p.strip_suffix(')'))
+                                .map(|p| split_top_level(p, ','));
+                            match pair.as_deref() {
+                                Some([column, method]) => {
+                                    Ok((string_literal(column.trim())?, FillMethod::parse(&string_literal(method.trim())?)?))
+                                }
+                                _ => Err(invalid_input!("GAP_FILL value_columns entries must be ('column', 'method'), got '{}'", item)),
+                            }
+                        })
+                        .collect::<BlazeResult<_>>()?;
+                    value_columns = Some(columns);
+                }
+                "origin" => origin = Some(value.to_string()),
+                "ignore_null_values" => {
+                    ignore_null_values = match value.to_lowercase().as_str() {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(invalid_input!("GAP_FILL ignore_null_values must be TRUE or FALSE")),
+                    }
+                }
+                other => return Err(invalid_input!("Unknown GAP_FILL argument '{}'", other)),
+            }
+        }
+
+        calls.push(GapFillCall {
+            span: (found.start(), close + 1),
+            source_sql,
+            ts_column: ts_column.ok_or_else(|| invalid_input!("GAP_FILL requires ts_column"))?,
+            bucket_width: bucket_width.ok_or_else(|| invalid_input!("GAP_FILL requires bucket_width"))?,
+            partitioning_columns,
+            value_columns,
+            origin,
+            ignore_null_values,
+        });
+        cursor = close + 1;
+    }
+
+    Ok(calls)
+}
+
+fn string_literal(text: &str) -> BlazeResult<String> {
+    let quoted = text.len() >= 2
+        && ((text.starts_with('\'') && text.ends_with('\'')) || (text.starts_with('"') && text.ends_with('"')));
+    if !quoted {
+        return Err(invalid_input!("GAP_FILL column names must be string literals, got '{}'", text));
+    }
+    Ok(text[1..text.len() - 1].to_string())
+}
+
+/// Items of an `[a, b]` or `ARRAY[a, b]` literal
+fn array_items(text: &str) -> BlazeResult<Vec<&str>> {
+    let text = text.trim();
+    let text = match text.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("ARRAY") => text[5..].trim_start(),
+        _ => text,
+    };
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .ok_or_else(|| invalid_input!("GAP_FILL expected an array literal, got '{}'", text))?;
+    Ok(split_top_level(inner, ',').into_iter().map(str::trim).filter(|i| !i.is_empty()).collect())
+}
+
+/// Evaluate the bucket width and origin of a call, in nanoseconds
+pub async fn evaluate_buckets(ctx: &SessionContext, call: &GapFillCall) -> BlazeResult<(i64, i64)> {
+    let batches = ctx.sql(&format!("SELECT {}", call.bucket_width)).await?.collect().await?;
+    let width = batches
+        .first()
+        .filter(|batch| batch.num_rows() > 0)
+        .and_then(|batch| cast(batch.column(0), &DataType::Interval(IntervalUnit::MonthDayNano)).ok())
+        .and_then(|array| {
+            let array = array.as_any().downcast_ref::<IntervalMonthDayNanoArray>()?;
+            (!array.is_null(0)).then(|| array.value(0))
+        })
+        .ok_or_else(|| invalid_input!("GAP_FILL bucket_width '{}' is not an interval", call.bucket_width))?;
+    if width.months != 0 {
+        return Err(invalid_input!("GAP_FILL bucket_width must not include months or years"));
+    }
+    let width = (width.days as i64)
+        .checked_mul(NANOS_PER_DAY)
+        .and_then(|days| days.checked_add(width.nanoseconds))
+        .filter(|nanos| *nanos > 0)
+        .ok_or_else(|| invalid_input!("GAP_FILL bucket_width must be positive"))?;
+
+    let origin = match &call.origin {
+        Some(origin) => {
+            let batches = ctx.sql(&format!("SELECT CAST(({}) AS TIMESTAMP)", origin)).await?.collect().await?;
+            batches
+                .first()
+                .filter(|batch| batch.num_rows() > 0)
+                .and_then(|batch| batch.column(0).as_any().downcast_ref::<TimestampNanosecondArray>())
+                .filter(|array| !array.is_null(0))
+                .map(|array| array.value(0))
+                .ok_or_else(|| invalid_input!("GAP_FILL origin '{}' is not a timestamp", origin))?
+        }
+        None => DEFAULT_ORIGIN,
+    };
+
+    Ok((width, origin))
+}
+
+/// Fill the gaps of each partition's series, returning rows ordered by
+/// partition and bucket
+pub fn gap_fill(
+    call: &GapFillCall,
+    schema: SchemaRef,
+    batches: &[RecordBatch],
+    width: i64,
+    origin: i64,
+) -> BlazeResult<RecordBatch> {
+    let events = concat_batches(&schema, batches)?;
+    let index_of = |name: &str| -> BlazeResult<usize> {
+        schema
+            .index_of(name)
+            .map_err(|_| invalid_input!("GAP_FILL column '{}' not found", name))
+    };
+
+    let ts_index = index_of(&call.ts_column)?;
+    let ts_type = schema.field(ts_index).data_type().clone();
+    let nanos_type = match &ts_type {
+        DataType::Timestamp(_, tz) => DataType::Timestamp(TimeUnit::Nanosecond, tz.clone()),
+        DataType::Date32 | DataType::Date64 => DataType::Timestamp(TimeUnit::Nanosecond, None),
+        other => return Err(invalid_input!("GAP_FILL ts_column must be a TIMESTAMP or DATE, got {}", other)),
+    };
+    let partition_indices = call
+        .partitioning_columns
+        .iter()
+        .map(|c| index_of(c))
+        .collect::<BlazeResult<Vec<_>>>()?;
+    let value_columns = match &call.value_columns {
+        Some(columns) => columns
+            .iter()
+            .map(|(c, method)| Ok((index_of(c)?, *method)))
+            .collect::<BlazeResult<Vec<_>>>()?,
+        None => (0..schema.fields().len())
+            .filter(|i| *i != ts_index && !partition_indices.contains(i))
+            .map(|i| (i, FillMethod::Null))
+            .collect(),
+    };
+    for (index, method) in &value_columns {
+        if *method == FillMethod::Linear && !schema.field(*index).data_type().is_numeric() {
+            return Err(invalid_input!("GAP_FILL 'linear' needs a numeric column, '{}' is not", schema.field(*index).name()));
+        }
+    }
+
+    // Events without a time belong to no bucket
+    let mut times = cast(&cast(events.column(ts_index), &nanos_type)?, &DataType::Int64)?;
+    let events = if times.null_count() > 0 {
+        let valid = is_not_null(&times)?;
+        times = filter(&times, &valid)?;
+        filter_record_batch(&events, &valid)?
+    } else {
+        events
+    };
+
+    // Order by partition, then time
+    let mut sort_columns: Vec<SortColumn> = partition_indices
+        .iter()
+        .map(|i| SortColumn { values: events.column(*i).clone(), options: None })
+        .collect();
+    sort_columns.push(SortColumn { values: times.clone(), options: None });
+    let order = lexsort_to_indices(&sort_columns, None)?;
+    let times = take(&times, &order, None)?;
+    let times = times.as_any().downcast_ref::<Int64Array>().unwrap();
+    let sorted = |index: usize| take(events.column(index), &order, None);
+
+    let partitions = partition_indices.iter().map(|i| sorted(*i)).collect::<Result<Vec<_>, _>>()?;
+    let partition_rows = if partitions.is_empty() {
+        None
+    } else {
+        let converter = RowConverter::new(partitions.iter().map(|p| SortField::new(p.data_type().clone())).collect())?;
+        Some(converter.convert_columns(&partitions)?)
+    };
+    let same_partition = |a: usize, b: usize| partition_rows.as_ref().is_none_or(|rows| rows.row(a) == rows.row(b));
+
+    let mut fills: Vec<ColumnFill> = value_columns
+        .iter()
+        .map(|(index, method)| ColumnFill::new(sorted(*index)?, *method, call.ignore_null_values))
+        .collect::<BlazeResult<_>>()?;
+
+    let mut bucket_times = Vec::new();
+    let mut representatives = Vec::new();
+    let mut start = 0;
+    while start < times.len() {
+        let mut end = start + 1;
+        while end < times.len() && same_partition(start, end) {
+            end += 1;
+        }
+
+        let first = align(times.value(start), origin, width, true);
+        let last = align(times.value(end - 1), origin, width, false);
+        let mut cursor = start;
+        let mut bucket = first;
+        while bucket <= last {
+            if bucket_times.len() == MAX_GENERATED_VALUES {
+                return Err(invalid_input!("GAP_FILL would produce more than {} rows", MAX_GENERATED_VALUES));
+            }
+            while cursor < end && times.value(cursor) <= bucket {
+                fills.iter_mut().for_each(|fill| fill.observe(cursor));
+                cursor += 1;
+            }
+            for fill in fills.iter_mut() {
+                fill.emit(times, bucket, cursor, end);
+            }
+            bucket_times.push(bucket);
+            representatives.push(start as u32);
+            bucket += width;
+        }
+        fills.iter_mut().for_each(ColumnFill::reset);
+        start = end;
+    }
+
+    let representatives = UInt32Array::from(representatives);
+    let bucket_times: ArrayRef = Arc::new(Int64Array::from(bucket_times));
+    let mut output: Vec<(usize, ArrayRef)> = vec![(ts_index, cast(&cast(&bucket_times, &nanos_type)?, &ts_type)?)];
+    for (position, index) in partition_indices.iter().enumerate() {
+        output.push((*index, take(&partitions[position], &representatives, None)?));
+    }
+    for ((index, _), fill) in value_columns.iter().zip(fills) {
+        output.push((*index, fill.finish(schema.field(*index).data_type())?));
+    }
+    output.sort_by_key(|(index, _)| *index);
+    output.dedup_by_key(|(index, _)| *index);
+
+    let fields: Vec<Field> = output
+        .iter()
+        .map(|(index, _)| schema.field(*index).clone().with_nullable(true))
+        .collect();
+    let columns = output.into_iter().map(|(_, column)| column).collect();
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+}
+
+/// First bucket at or after `time` (`up`), or last at or before it
+fn align(time: i64, origin: i64, width: i64, up: bool) -> i64 {
+    let offset = (time - origin).rem_euclid(width);
+    match (offset, up) {
+        (0, _) => time,
+        (_, true) => time - offset + width,
+        (_, false) => time - offset,
+    }
+}
+
+/// Output of one value column, built bucket by bucket
+struct ColumnFill {
+    /// Sorted source values
+    values: ArrayRef,
+    /// Source values as numbers, for `Linear`
+    numbers: Option<Float64Array>,
+    method: FillMethod,
+    ignore_nulls: bool,
+    /// Latest row at or before the current bucket, and latest non-NULL one
+    last: Option<usize>,
+    last_valid: Option<usize>,
+    taken: Vec<Option<u32>>,
+    interpolated: Vec<Option<f64>>,
+}
+
+impl ColumnFill {
+    fn new(values: ArrayRef, method: FillMethod, ignore_nulls: bool) -> BlazeResult<Self> {
+        let numbers = match method {
+            FillMethod::Linear => {
+                let numbers = cast(&values, &DataType::Float64)?;
+                Some(numbers.as_any().downcast_ref::<Float64Array>().unwrap().clone())
+            }
+            _ => None,
+        };
+        Ok(Self {
+            values,
+            numbers,
+            method,
+            ignore_nulls,
+            last: None,
+            last_valid: None,
+            taken: Vec::new(),
+            interpolated: Vec::new(),
+        })
+    }
+
+    fn observe(&mut self, row: usize) {
+        self.last = Some(row);
+        if self.values.is_valid(row) {
+            self.last_valid = Some(row);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+        self.last_valid = None;
+    }
+
+    /// Value for `bucket`, where rows `cursor..end` of the partition are after it
+    fn emit(&mut self, times: &Int64Array, bucket: i64, cursor: usize, end: usize) {
+        let previous = if self.ignore_nulls { self.last_valid } else { self.last };
+        match self.method {
+            FillMethod::Null => {
+                let exact = self.last.filter(|row| times.value(*row) == bucket);
+                self.taken.push(exact.map(|row| row as u32));
+            }
+            FillMethod::Locf => self.taken.push(previous.map(|row| row as u32)),
+            FillMethod::Linear => {
+                let numbers = self.numbers.as_ref().unwrap();
+                let next = if self.ignore_nulls {
+                    (cursor..end).find(|row| numbers.is_valid(*row))
+                } else {
+                    (cursor < end).then_some(cursor)
+                };
+                let value = match (previous, next) {
+                    (Some(p), _) if times.value(p) == bucket => numbers.is_valid(p).then(|| numbers.value(p)),
+                    (Some(p), Some(n)) if numbers.is_valid(p) && numbers.is_valid(n) => {
+                        let fraction = (bucket - times.value(p)) as f64 / (times.value(n) - times.value(p)) as f64;
+                        Some(numbers.value(p) + (numbers.value(n) - numbers.value(p)) * fraction)
+                    }
+                    _ => None,
+                };
+                self.interpolated.push(value);
+            }
+        }
+    }
+
+    fn finish(self, data_type: &DataType) -> BlazeResult<ArrayRef> {
+        match self.method {
+            FillMethod::Linear => {
+                let integer = data_type.is_integer();
+                let values: ArrayRef = Arc::new(Float64Array::from_iter(
+                    self.interpolated.into_iter().map(|v| v.map(|v| if integer { v.round() } else { v })),
+                ));
+                Ok(cast(&values, data_type)?)
+            }
+            _ => Ok(take(&self.values, &UInt32Array::from(self.taken), None)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::StringArray;
+
+    #[test]
+    fn test_generate_date_array_months() {
+        let day = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap().signed_duration_since(unix_epoch()).num_days();
+        let (start, end) = (day(2016, 1, 31), day(2016, 5, 31));
+        let values = generate(SeriesKind::Date, start, end, IntervalMonthDayNano::new(1, 0, 0)).unwrap();
+        let dates: Vec<String> = values
+            .iter()
+            .map(|d| SeriesKind::Date.datetime(*d).unwrap().date().to_string())
+            .collect();
+        assert_eq!(dates, vec!["2016-01-31", "2016-02-29", "2016-03-31", "2016-04-30", "2016-05-31"]);
+
+        let descending = generate(SeriesKind::Date, 10, 4, IntervalMonthDayNano::new(0, -3, 0)).unwrap();
+        assert_eq!(descending, vec![10, 7, 4]);
+        assert!(generate(SeriesKind::Date, 4, 10, IntervalMonthDayNano::new(0, -1, 0)).unwrap().is_empty());
+        assert!(generate(SeriesKind::Date, 0, 1, IntervalMonthDayNano::new(0, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_gap_fill_methods() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("device", DataType::Utf8, false),
+            Field::new("t", DataType::Timestamp(TimeUnit::Nanosecond, None), false),
+            Field::new("reading", DataType::Float64, true),
+        ]));
+        let second = 1_000_000_000;
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "a", "b", "a"])),
+                Arc::new(TimestampNanosecondArray::from(vec![0, 3 * second, 0, 4 * second + 1])),
+                Arc::new(Float64Array::from(vec![Some(1.0), Some(4.0), Some(7.0), None])),
+            ],
+        )
+        .unwrap();
+        let sql = "SELECT * FROM GAP_FILL(TABLE r, 't', INTERVAL 1 SECOND, partitioning_columns => ['device'], \
+                   value_columns => [('reading', 'linear')])";
+        let mut call = find_gap_fill_calls(sql).unwrap().remove(0);
+        assert_eq!(call.source_sql, "SELECT * FROM r");
+        assert_eq!(call.partitioning_columns, vec!["device"]);
+
+        let reading = |call: &GapFillCall| {
+            let result = gap_fill(call, schema.clone(), std::slice::from_ref(&batch), second, 0).unwrap();
+            let values = result.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+            values.iter().collect::<Vec<_>>()
+        };
+
+        // a: buckets 0..=4 (the event at 4s+1ns is past the last full bucket), b: bucket 0
+        assert_eq!(reading(&call), vec![Some(1.0), Some(2.0), Some(3.0), Some(4.0), None, Some(7.0)]);
+
+        call.value_columns = Some(vec![("reading".to_string(), FillMethod::Locf)]);
+        assert_eq!(reading(&call), vec![Some(1.0), Some(1.0), Some(1.0), Some(4.0), Some(4.0), Some(7.0)]);
+
+        call.value_columns = Some(vec![("reading".to_string(), FillMethod::Null)]);
+        assert_eq!(reading(&call), vec![Some(1.0), None, None, Some(4.0), None, Some(7.0)]);
+    }
+}
@@ -0,0 +1,201 @@
+//! The session time zone
+//!
+//! `EngineConfig::time_zone` (default `UTC`) is an IANA name such as
+//! `America/New_York` or a fixed offset such as `+05:30`. It decides:
+//!
+//! - the zone of `CURRENT_TIMESTAMP`/`NOW()`, and the local date and time
+//!   returned by `CURRENT_DATE` and `CURRENT_TIME`
+//! - how `TIMESTAMP WITH TIME ZONE` literals and casts without an offset are
+//!   read, e.g. `CAST('2024-03-01 09:00:00' AS TIMESTAMPTZ)`
+//! - the fields `EXTRACT` returns for those zoned timestamps
+//! - the zone zoned timestamps are shown in within query results
+//!
+//! Timestamps without a time zone are wall-clock values and are left as
+//! they are. The zone can be changed at runtime with `update_config` or
+//! `SET TIME ZONE 'Europe/Paris'`. The current date and time come from the
+//! engine clock (see `clock`).
+
+use std::any::Any;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Timelike, Utc};
+use datafusion::arrow::array::timezone::Tz;
+use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::common::ScalarValue;
+use datafusion::error::Result;
+use datafusion::logical_expr::simplify::{ExprSimplifyResult, SimplifyInfo};
+use datafusion::logical_expr::{ColumnarValue, Expr, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, Volatility};
+use datafusion::prelude::SessionContext;
+use regex::Regex;
+
+use crate::clock::SharedClock;
+use crate::config_error;
+use crate::error::{BlazeError, BlazeResult};
+
+/// Check that `name` is a time zone DataFusion and Arrow understand
+pub(crate) fn validate_time_zone(name: &str) -> BlazeResult<()> {
+    Tz::from_str(name)
+        .map(|_| ())
+        .map_err(|_| config_error!("Unknown time zone '{}'; use an IANA name such as 'Europe/Paris' or an offset such as '+05:30'", name))
+}
+
+/// Parse `SET TIME ZONE 'zone'` or `SET timezone = 'zone'`, returning the zone
+pub(crate) fn parse_set_time_zone(sql: &str) -> Option<String> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*SET\s+(?:TIME\s+ZONE\s+(?:TO\s+|=\s*)?|timezone\s*(?:=|TO)\s*)'([^']*)'\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    Some(captures[1].to_string())
+}
+
+/// Use `time_zone` for the session: DataFusion's own setting, which zoned
+/// casts read, and the current date and time functions, which read `clock`
+pub(crate) fn apply(ctx: &SessionContext, time_zone: &str, clock: &Arc<SharedClock>) {
+    {
+        let state = ctx.state_ref();
+        let mut state = state.write();
+        state.config_mut().options_mut().execution.time_zone = Some(time_zone.to_string());
+    }
+    for kind in [CurrentKind::Timestamp, CurrentKind::Date, CurrentKind::Time] {
+        ctx.register_udf(ScalarUDF::from(CurrentInZone::new(kind, time_zone, clock.clone())));
+    }
+}
+
+/// Render a date or timestamp column as text, zoned timestamps in
+/// `time_zone`; `None` for other types
+pub(crate) fn render_temporal(column: &ArrayRef, time_zone: &str) -> BlazeResult<Option<StringArray>> {
+    let column = match column.data_type() {
+        DataType::Timestamp(unit, Some(_)) => cast(column, &DataType::Timestamp(*unit, Some(time_zone.into())))?,
+        DataType::Timestamp(_, None) | DataType::Date32 | DataType::Date64 | DataType::Time32(_) | DataType::Time64(_) => {
+            column.clone()
+        }
+        _ => return Ok(None),
+    };
+    let text = cast(&column, &DataType::Utf8)?;
+    Ok(text.as_any().downcast_ref::<StringArray>().cloned())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CurrentKind {
+    /// `now()` and `current_timestamp()`, in the session zone
+    Timestamp,
+    /// `current_date()` and `today()`, the local date
+    Date,
+    /// `current_time()`, the local time of day
+    Time,
+}
+
+/// Replaces DataFusion's UTC-only current date and time functions. Like
+/// those, every call in a statement returns the statement's start time.
+#[derive(Debug)]
+struct CurrentInZone {
+    kind: CurrentKind,
+    time_zone: Arc<str>,
+    clock: Arc<SharedClock>,
+    aliases: Vec<String>,
+    signature: Signature,
+}
+
+impl CurrentInZone {
+    fn new(kind: CurrentKind, time_zone: &str, clock: Arc<SharedClock>) -> Self {
+        let aliases: &[&str] = match kind {
+            CurrentKind::Timestamp => &["current_timestamp"],
+            CurrentKind::Date => &["today"],
+            CurrentKind::Time => &[],
+        };
+        Self {
+            kind,
+            time_zone: time_zone.into(),
+            clock,
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            signature: Signature::nullary(Volatility::Stable),
+        }
+    }
+
+    fn value_at(&self, instant: DateTime<Utc>) -> Result<ScalarValue> {
+        let zone = Tz::from_str(&self.time_zone)?;
+        let local = zone.from_utc_datetime(&instant.naive_utc()).naive_local();
+        Ok(match self.kind {
+            CurrentKind::Timestamp => ScalarValue::TimestampNanosecond(instant.timestamp_nanos_opt(), Some(self.time_zone.clone())),
+            CurrentKind::Date => {
+                let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+                ScalarValue::Date32(Some((local.date() - epoch).num_days() as i32))
+            }
+            CurrentKind::Time => {
+                let time = local.time();
+                let nanos = time.num_seconds_from_midnight() as i64 * 1_000_000_000 + time.nanosecond() as i64;
+                ScalarValue::Time64Nanosecond(Some(nanos))
+            }
+        })
+    }
+}
+
+impl ScalarUDFImpl for CurrentInZone {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        match self.kind {
+            CurrentKind::Timestamp => "now",
+            CurrentKind::Date => "current_date",
+            CurrentKind::Time => "current_time",
+        }
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(match self.kind {
+            CurrentKind::Timestamp => DataType::Timestamp(TimeUnit::Nanosecond, Some(self.time_zone.clone())),
+            CurrentKind::Date => DataType::Date32,
+            CurrentKind::Time => DataType::Time64(TimeUnit::Nanosecond),
+        })
+    }
+
+    fn simplify(&self, _args: Vec<Expr>, info: &dyn SimplifyInfo) -> Result<ExprSimplifyResult> {
+        let start = info.execution_props().query_execution_start_time;
+        Ok(ExprSimplifyResult::Simplified(Expr::Literal(self.value_at(self.clock.get().adjust(start))?)))
+    }
+
+    fn invoke_with_args(&self, _args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        // Only reached when the optimizer is bypassed
+        Ok(ColumnarValue::Scalar(self.value_at(self.clock.now())?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_zone_names_and_local_values() {
+        assert!(validate_time_zone("UTC").is_ok());
+        assert!(validate_time_zone("America/New_York").is_ok());
+        assert!(validate_time_zone("+05:30").is_ok());
+        assert!(validate_time_zone("Mars/Olympus").is_err());
+
+        assert_eq!(parse_set_time_zone("SET TIME ZONE 'Asia/Tokyo';"), Some("Asia/Tokyo".to_string()));
+        assert_eq!(parse_set_time_zone("set timezone = '+01:00'"), Some("+01:00".to_string()));
+        assert_eq!(parse_set_time_zone("SET datafusion.execution.batch_size = 1"), None);
+
+        // 03:30 UTC is still the previous evening in New York
+        let instant = Utc.with_ymd_and_hms(2024, 3, 1, 3, 30, 0).unwrap();
+        let date = CurrentInZone::new(CurrentKind::Date, "America/New_York", Default::default()).value_at(instant).unwrap();
+        assert_eq!(date.to_string(), "2024-02-29");
+        let time = CurrentInZone::new(CurrentKind::Time, "+05:30", Default::default()).value_at(instant).unwrap();
+        assert_eq!(time, ScalarValue::Time64Nanosecond(Some(9 * 3_600_000_000_000)));
+    }
+}
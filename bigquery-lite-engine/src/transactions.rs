@@ -0,0 +1,123 @@
+//! Explicit transactions over in-memory tables
+//!
+//! `BEGIN` opens a transaction for the session it runs in, or for the
+//! statements run outside any session; each session has its own. The first
+//! time a table is written inside it, by `register_table`, `append_to_table`
+//! or `INSERT`, the engine saves the table's rows from before the
+//! transaction; change events are held back until the end. `COMMIT`
+//! publishes the held events and forgets the saved rows. `ROLLBACK` restores
+//! every table written in the transaction and drops the tables it created,
+//! and a statement that fails inside a transaction rolls it back the same
+//! way. Until then, no other transaction or session may write those tables.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::cdc::ChangeType;
+
+/// A statement starting or ending a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TransactionControl {
+    Begin,
+    Commit,
+    Rollback,
+}
+
+/// Parse `BEGIN`, `START TRANSACTION`, `COMMIT` or `ROLLBACK`, each
+/// optionally followed by `TRANSACTION` or `WORK`
+pub(crate) fn parse_transaction_control(sql: &str) -> Option<TransactionControl> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*(BEGIN|START|COMMIT|ROLLBACK)(\s+(?:TRANSACTION|WORK))?\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    match captures[1].to_uppercase().as_str() {
+        "BEGIN" => Some(TransactionControl::Begin),
+        "START" if captures.get(2).is_some() => Some(TransactionControl::Begin),
+        "COMMIT" => Some(TransactionControl::Commit),
+        "ROLLBACK" => Some(TransactionControl::Rollback),
+        _ => None,
+    }
+}
+
+/// State of an open transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionInfo {
+    /// When the transaction began
+    pub started_at: DateTime<Utc>,
+    /// Tables written in the transaction, sorted by name
+    pub tables: Vec<String>,
+}
+
+/// Rows of a table from before the transaction wrote it
+#[derive(Debug)]
+pub(crate) struct SavedTable {
+    pub(crate) schema: SchemaRef,
+    pub(crate) batches: Vec<RecordBatch>,
+}
+
+/// A change event held back until the transaction commits
+#[derive(Debug)]
+pub(crate) struct HeldChange {
+    pub(crate) table_name: String,
+    pub(crate) change_type: ChangeType,
+    pub(crate) committed_at: DateTime<Utc>,
+    pub(crate) batches: Vec<RecordBatch>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Transaction {
+    started_at: DateTime<Utc>,
+    /// Rows of each written table from before the transaction, `None` for
+    /// tables the transaction created
+    pub(crate) saved: HashMap<String, Option<SavedTable>>,
+    pub(crate) held_changes: Vec<HeldChange>,
+}
+
+impl Transaction {
+    pub(crate) fn begin(started_at: DateTime<Utc>) -> Self {
+        Self { started_at, saved: HashMap::new(), held_changes: Vec::new() }
+    }
+
+    /// Whether the rows of `name` from before the transaction are saved
+    pub(crate) fn has_saved(&self, name: &str) -> bool {
+        self.saved.contains_key(name)
+    }
+
+    pub(crate) fn save(&mut self, name: &str, table: Option<SavedTable>) {
+        self.saved.insert(name.to_string(), table);
+    }
+
+    pub(crate) fn hold(&mut self, change: HeldChange) {
+        self.held_changes.push(change);
+    }
+
+    pub(crate) fn info(&self) -> TransactionInfo {
+        let mut tables: Vec<_> = self.saved.keys().cloned().collect();
+        tables.sort();
+        TransactionInfo { started_at: self.started_at, tables }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transaction_control() {
+        assert_eq!(parse_transaction_control("BEGIN"), Some(TransactionControl::Begin));
+        assert_eq!(parse_transaction_control("start transaction;"), Some(TransactionControl::Begin));
+        assert_eq!(parse_transaction_control(" COMMIT WORK "), Some(TransactionControl::Commit));
+        assert_eq!(parse_transaction_control("rollback transaction"), Some(TransactionControl::Rollback));
+        assert_eq!(parse_transaction_control("START"), None);
+        assert_eq!(parse_transaction_control("SELECT 1"), None);
+    }
+}
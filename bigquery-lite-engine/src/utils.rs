@@ -1,7 +1,12 @@
 //! Utility functions for the BlazeQueryEngine
 
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
+use datafusion::sql::sqlparser::dialect::GenericDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::tokenizer::{Token, Tokenizer};
+
 /// Format bytes into human-readable string
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -189,37 +194,29 @@ impl QueryAnalyzer {
                     in_double_quote = !in_double_quote;
                     result.push(' '); // Replace string content with space
                 }
-                '-' if !in_single_quote && !in_double_quote => {
-                    if chars.peek() == Some(&'-') {
-                        // Skip line comment
-                        chars.next(); // consume second '-'
-                        while let Some(ch) = chars.next() {
-                            if ch == '\n' {
-                                result.push('\n');
-                                break;
-                            }
+                '-' if !in_single_quote && !in_double_quote && chars.peek() == Some(&'-') => {
+                    // Skip line comment
+                    chars.next(); // consume second '-'
+                    for ch in chars.by_ref() {
+                        if ch == '\n' {
+                            result.push('\n');
+                            break;
                         }
-                    } else {
-                        result.push(ch);
                     }
                 }
-                '/' if !in_single_quote && !in_double_quote => {
-                    if chars.peek() == Some(&'*') {
-                        // Skip block comment
-                        chars.next(); // consume '*'
-                        let mut found_end = false;
-                        while let Some(ch) = chars.next() {
-                            if ch == '*' && chars.peek() == Some(&'/') {
-                                chars.next(); // consume '/'
-                                found_end = true;
-                                break;
-                            }
-                        }
-                        if found_end {
-                            result.push(' ');
+                '/' if !in_single_quote && !in_double_quote && chars.peek() == Some(&'*') => {
+                    // Skip block comment
+                    chars.next(); // consume '*'
+                    let mut found_end = false;
+                    while let Some(ch) = chars.next() {
+                        if ch == '*' && chars.peek() == Some(&'/') {
+                            chars.next(); // consume '/'
+                            found_end = true;
+                            break;
                         }
-                    } else {
-                        result.push(ch);
+                    }
+                    if found_end {
+                        result.push(' ');
                     }
                 }
                 _ if in_single_quote || in_double_quote => {
@@ -269,18 +266,16 @@ impl QueryAnalyzer {
                     paren_depth -= 1;
                     in_subquery = false;
                 }
-                'S' if paren_depth > 0 && !in_subquery => {
+                'S' if paren_depth > 0 && !in_subquery && i + 6 <= chars.len() => {
                     // Check if this starts "SELECT"
-                    if i + 6 <= chars.len() {
-                        let word: String = chars[i..i+6].iter().collect();
-                        if word == "SELECT" {
-                            // Check if it's a word boundary before and after
-                            let before_ok = i == 0 || !chars[i-1].is_alphanumeric();
-                            let after_ok = i + 6 >= chars.len() || !chars[i+6].is_alphanumeric();
-                            if before_ok && after_ok {
-                                count += 1;
-                                in_subquery = true;
-                            }
+                    let word: String = chars[i..i+6].iter().collect();
+                    if word == "SELECT" {
+                        // Check if it's a word boundary before and after
+                        let before_ok = i == 0 || !chars[i-1].is_alphanumeric();
+                        let after_ok = i + 6 >= chars.len() || !chars[i+6].is_alphanumeric();
+                        if before_ok && after_ok {
+                            count += 1;
+                            in_subquery = true;
                         }
                     }
                 }
@@ -335,6 +330,331 @@ impl std::fmt::Display for QueryComplexity {
     }
 }
 
+/// Value of a BigQuery-style `OPTIONS(key=value, ...)` entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    List(Vec<OptionValue>),
+}
+
+impl OptionValue {
+    /// String value, if this is a string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            OptionValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Numeric value, if this is a number
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            OptionValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Strings of a list value (a single string is treated as a one-element list)
+    pub fn as_string_list(&self) -> Option<Vec<String>> {
+        match self {
+            OptionValue::String(s) => Some(vec![s.clone()]),
+            OptionValue::List(items) => items.iter().map(|i| i.as_str().map(str::to_string)).collect(),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the body of an `OPTIONS(...)` clause, e.g.
+/// `model_type='linear_reg', input_label_cols=['y'], max_iterations=20`.
+/// Keys are lower-cased.
+pub fn parse_options(text: &str) -> Result<HashMap<String, OptionValue>, String> {
+    let mut options = HashMap::new();
+
+    for entry in split_top_level(text, ',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value in OPTIONS, found '{}'", entry))?;
+        options.insert(key.trim().to_lowercase(), parse_option_value(value.trim())?);
+    }
+
+    Ok(options)
+}
+
+fn parse_option_value(text: &str) -> Result<OptionValue, String> {
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return split_top_level(inner, ',')
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| parse_option_value(item.trim()))
+            .collect::<Result<_, _>>()
+            .map(OptionValue::List);
+    }
+    for quote in ['\'', '"'] {
+        if text.len() >= 2 && text.starts_with(quote) && text.ends_with(quote) {
+            return Ok(OptionValue::String(text[1..text.len() - 1].to_string()));
+        }
+    }
+    match text.to_lowercase().as_str() {
+        "true" => return Ok(OptionValue::Bool(true)),
+        "false" => return Ok(OptionValue::Bool(false)),
+        _ => {}
+    }
+    text.parse::<f64>()
+        .map(OptionValue::Number)
+        .map_err(|_| format!("invalid OPTIONS value '{}'", text))
+}
+
+/// Split on `separator` outside of quotes, brackets and parentheses
+pub fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+
+    for (i, ch) in text.char_indices() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(ch),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+
+    parts
+}
+
+/// Byte offset of the parenthesis closing the one at `open`, skipping quoted text
+pub fn matching_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+
+    for (i, ch) in text[open..].char_indices() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Normalize SQL so that queries differing only in literals, whitespace,
+/// comments or keyword case compare equal.
+///
+/// Literals and bind parameters become `?`, `IN` lists collapse to `(?)`,
+/// repeated `VALUES` rows collapse to the first and unquoted words are
+/// lowercased. Quoted identifiers are kept as written. Text the tokenizer rejects only has its whitespace collapsed.
+pub fn normalize_sql(sql: &str) -> String {
+    match normalized_parts(sql, false) {
+        Some(parts) => render_sql_parts(&collapse_literal_lists(parts)),
+        None => sql.split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// Normalize SQL like `normalize_sql`, but keep literals as written, so
+/// only queries running the same statement compare equal. `None` if the
+/// tokenizer rejects `sql`.
+pub fn normalize_sql_keeping_literals(sql: &str) -> Option<String> {
+    Some(render_sql_parts(&normalized_parts(sql, true)?))
+}
+
+/// The tokens of `sql` as normalized SQL renders them, with literals
+/// replaced by `?` unless `keep_literals`
+fn normalized_parts(sql: &str, keep_literals: bool) -> Option<Vec<String>> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql).tokenize().ok()?;
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut after_operand = false;
+    let mut tokens = tokens
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .peekable();
+    while let Some(token) = tokens.next() {
+        let (part, operand) = match token {
+            // A sign in front of a number is part of the literal
+            Token::Minus if !keep_literals && !after_operand && matches!(tokens.peek(), Some(Token::Number(..))) => continue,
+            Token::Word(word) if word.quote_style.is_some() => (word.to_string(), true),
+            Token::Word(word) => {
+                let operand = !matches!(
+                    word.keyword,
+                    Keyword::SELECT
+                        | Keyword::WHERE
+                        | Keyword::HAVING
+                        | Keyword::ON
+                        | Keyword::AND
+                        | Keyword::OR
+                        | Keyword::NOT
+                        | Keyword::CASE
+                        | Keyword::WHEN
+                        | Keyword::THEN
+                        | Keyword::ELSE
+                        | Keyword::IN
+                        | Keyword::BETWEEN
+                        | Keyword::LIKE
+                        | Keyword::BY
+                        | Keyword::LIMIT
+                        | Keyword::OFFSET
+                        | Keyword::INTERVAL
+                );
+                (word.value.to_lowercase(), operand)
+            }
+            Token::RParen | Token::RBracket => (token.to_string(), true),
+            token if !keep_literals && is_literal(&token) => ("?".to_string(), true),
+            token => (token.to_string(), false),
+        };
+        parts.push(part);
+        after_operand = operand;
+    }
+    while parts.last().is_some_and(|part| part == ";") {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+/// Stable 64-bit fingerprint of `normalize_sql(sql)` as 16 hex digits.
+///
+/// The hash is FNV-1a, so fingerprints stay the same across processes,
+/// platforms and compiler versions and can be stored or compared between
+/// runs.
+pub fn fingerprint_sql(sql: &str) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let hash = normalize_sql(sql)
+        .bytes()
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME));
+    format!("{:016x}", hash)
+}
+
+fn is_literal(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Number(..)
+            | Token::Placeholder(_)
+            | Token::SingleQuotedString(_)
+            | Token::DoubleQuotedString(_)
+            | Token::TripleSingleQuotedString(_)
+            | Token::TripleDoubleQuotedString(_)
+            | Token::DollarQuotedString(_)
+            | Token::SingleQuotedByteStringLiteral(_)
+            | Token::DoubleQuotedByteStringLiteral(_)
+            | Token::TripleSingleQuotedByteStringLiteral(_)
+            | Token::TripleDoubleQuotedByteStringLiteral(_)
+            | Token::SingleQuotedRawStringLiteral(_)
+            | Token::DoubleQuotedRawStringLiteral(_)
+            | Token::TripleSingleQuotedRawStringLiteral(_)
+            | Token::TripleDoubleQuotedRawStringLiteral(_)
+            | Token::NationalStringLiteral(_)
+            | Token::EscapedStringLiteral(_)
+            | Token::UnicodeStringLiteral(_)
+            | Token::HexStringLiteral(_)
+    )
+}
+
+/// Collapse `in (?, ?, ...)` to `in (?)` and drop `values` rows repeating
+/// the shape of the first row
+fn collapse_literal_lists(parts: Vec<String>) -> Vec<String> {
+    let mut collapsed = Vec::with_capacity(parts.len());
+    let mut i = 0;
+    while i < parts.len() {
+        collapsed.push(parts[i].clone());
+        match parts[i].as_str() {
+            "in" => {
+                if let Some(end) = group_end(&parts, i + 1) {
+                    let items = &parts[i + 2..end];
+                    if items.iter().enumerate().all(|(n, part)| part == if n % 2 == 0 { "?" } else { "," }) {
+                        collapsed.extend(["(", "?", ")"].map(String::from));
+                        i = end + 1;
+                        continue;
+                    }
+                }
+            }
+            "values" => {
+                if let Some(end) = group_end(&parts, i + 1) {
+                    let row = &parts[i + 1..=end];
+                    collapsed.extend_from_slice(row);
+                    let mut next = end + 1;
+                    while parts.get(next).is_some_and(|part| part == ",")
+                        && parts.get(next + 1..next + 1 + row.len()) == Some(row)
+                    {
+                        next += 1 + row.len();
+                    }
+                    i = next;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    collapsed
+}
+
+/// Index of the `)` closing the `(` at `start`
+fn group_end(parts: &[String], start: usize) -> Option<usize> {
+    if parts.get(start)? != "(" {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (i, part) in parts.iter().enumerate().skip(start) {
+        match part.as_str() {
+            "(" => depth += 1,
+            ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Join normalized tokens with single spaces, except around punctuation
+/// and between a function name and its arguments
+fn render_sql_parts(parts: &[String]) -> String {
+    let mut sql = String::new();
+    let mut previous: Option<&str> = None;
+    for part in parts {
+        if let Some(previous) = previous {
+            let identifier = previous.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '"' || c == '`')
+                && !matches!(previous, "in" | "values" | "as" | "on" | "and" | "or" | "not" | "exists" | "from" | "join" | "where" | "select");
+            let tight = matches!(part.as_str(), "," | ")" | "]" | "." | ";" | "::")
+                || matches!(previous, "(" | "[" | "." | "::")
+                || (matches!(part.as_str(), "(" | "[") && identifier);
+            if !tight {
+                sql.push(' ');
+            }
+        }
+        sql.push_str(part);
+        previous = Some(part);
+    }
+    sql
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -497,6 +817,18 @@ mod tests {
         assert_eq!(QueryAnalyzer::count_subqueries("SELECT * FROM (SELECT * FROM table) t WHERE col IN (1, 2, 3)"), 1);
     }
     
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options("model_type='linear_reg', input_label_cols=['y', \"z\"], l2_reg=0.5, enabled=TRUE").unwrap();
+        assert_eq!(options["model_type"].as_str(), Some("linear_reg"));
+        assert_eq!(options["input_label_cols"].as_string_list(), Some(vec!["y".to_string(), "z".to_string()]));
+        assert_eq!(options["l2_reg"].as_f64(), Some(0.5));
+        assert_eq!(options["enabled"], OptionValue::Bool(true));
+
+        assert!(parse_options("no_equals_sign").is_err());
+        assert_eq!(matching_paren("f(a, ')', (b)) tail", 1), Some(13));
+    }
+
     #[test]
     fn test_memory_tracker() {
         let mut tracker = MemoryTracker::new();
@@ -513,4 +845,33 @@ mod tests {
         assert_eq!(tracker.current_usage(), 2048);
         assert_eq!(tracker.peak_usage(), 3072); // Peak remains
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_normalize_sql() {
+        assert_eq!(
+            normalize_sql("select Name, COUNT(*)  from Users -- all users\n where id = 42 and city = 'Oslo';"),
+            "select name, count(*) from users where id = ? and city = ?"
+        );
+        assert_eq!(normalize_sql("SELECT id - 1 FROM t WHERE b > -2.5"), "select id - ? from t where b > ?");
+        assert_eq!(normalize_sql("SELECT \"Mixed\" FROM t WHERE x IN (1, 2, 3)"), "select \"Mixed\" from t where x in (?)");
+        assert_eq!(normalize_sql("INSERT INTO t VALUES (1, 'a'), (2, 'b')"), "insert into t values (?, ?)");
+        assert_eq!(normalize_sql("SELECT  'unterminated"), "SELECT 'unterminated");
+        assert_eq!(
+            normalize_sql_keeping_literals("select Name  from Users -- all users\n where id = -42 and x IN (1, 2);").unwrap(),
+            "select name from users where id = - 42 and x in (1, 2)"
+        );
+        assert_eq!(normalize_sql_keeping_literals("SELECT  'unterminated"), None);
+    }
+
+    #[test]
+    fn test_fingerprint_sql() {
+        let fingerprint = fingerprint_sql("SELECT * FROM t WHERE id = 1");
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(fingerprint, fingerprint_sql("select *\n  from T where ID=99 /* retry */"));
+        assert_eq!(fingerprint, fingerprint_sql(&normalize_sql("SELECT * FROM t WHERE id = 1")));
+        assert_ne!(fingerprint, fingerprint_sql("SELECT * FROM t WHERE name = 1"));
+        assert_ne!(fingerprint, fingerprint_sql("SELECT * FROM \"T\" WHERE id = 1"));
+        // Fixed hash function: the value must not change between builds
+        assert_eq!(fingerprint_sql(""), "cbf29ce484222325");
+    }
+}
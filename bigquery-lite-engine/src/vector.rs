@@ -0,0 +1,731 @@
+//! Vector similarity search over embedding columns
+//!
+//! Embeddings are list or fixed-size-list columns of floats. This module
+//! provides the `COSINE_DISTANCE`, `EUCLIDEAN_DISTANCE` and `DOT_PRODUCT`
+//! functions, optional HNSW indexes declared with
+//!
+//! ```sql
+//! CREATE VECTOR INDEX docs_idx ON docs(embedding) OPTIONS(distance_type='COSINE');
+//! ```
+//!
+//! and the `VECTOR_SEARCH` table function:
+//!
+//! ```sql
+//! SELECT id, distance
+//! FROM VECTOR_SEARCH(TABLE docs, 'embedding', [0.1, 0.7, 0.2], top_k => 5, distance_type => 'COSINE');
+//! ```
+//!
+//! The query argument is an array literal, `(subquery)` or `TABLE name`; each
+//! query row yields up to `top_k` neighbours, tagged with its `query_index`.
+//! Searches use a matching index when one exists and scan every row otherwise.
+
+use std::any::Any;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::sync::{Arc, LazyLock};
+
+use datafusion::arrow::array::{
+    Array, ArrayRef, FixedSizeListArray, Float64Array, LargeListArray, ListArray, UInt32Array,
+};
+use datafusion::arrow::compute::{cast, concat_batches, take_record_batch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::prelude::SessionContext;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::invalid_input;
+use crate::utils::{matching_paren, parse_options, split_top_level};
+
+/// How the distance between two vectors is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DistanceType {
+    Euclidean,
+    Cosine,
+    /// Negated dot product, so smaller is closer
+    DotProduct,
+}
+
+impl DistanceType {
+    /// Parse a BigQuery `distance_type` value
+    pub fn parse(value: &str) -> BlazeResult<Self> {
+        match value.to_uppercase().as_str() {
+            "EUCLIDEAN" => Ok(Self::Euclidean),
+            "COSINE" => Ok(Self::Cosine),
+            "DOT_PRODUCT" => Ok(Self::DotProduct),
+            other => Err(invalid_input!("Unsupported distance_type '{}'", other)),
+        }
+    }
+
+    /// Distance between two vectors of equal length; `None` when undefined
+    /// (cosine distance involving a zero vector)
+    pub fn distance(self, a: &[f64], b: &[f64]) -> Option<f64> {
+        match self {
+            Self::Euclidean => Some(a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()),
+            Self::Cosine => cosine_distance(a, b),
+            Self::DotProduct => Some(-dot(a, b)),
+        }
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn cosine_distance(a: &[f64], b: &[f64]) -> Option<f64> {
+    let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+    (norms > 0.0).then(|| 1.0 - dot(a, b) / norms)
+}
+
+/// Register the vector functions with a session
+pub fn register_functions(ctx: &SessionContext) {
+    for kind in [VectorFunctionKind::CosineDistance, VectorFunctionKind::EuclideanDistance, VectorFunctionKind::DotProduct] {
+        ctx.register_udf(ScalarUDF::from(VectorFunction {
+            kind,
+            signature: Signature::any(2, Volatility::Immutable),
+        }));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VectorFunctionKind {
+    CosineDistance,
+    EuclideanDistance,
+    DotProduct,
+}
+
+/// Implementation of the pairwise vector functions
+#[derive(Debug)]
+struct VectorFunction {
+    kind: VectorFunctionKind,
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for VectorFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        match self.kind {
+            VectorFunctionKind::CosineDistance => "cosine_distance",
+            VectorFunctionKind::EuclideanDistance => "euclidean_distance",
+            VectorFunctionKind::DotProduct => "dot_product",
+        }
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(&args.args)?;
+        let (left, right) = (vectors(&arrays[0])?, vectors(&arrays[1])?);
+
+        let values = left
+            .iter()
+            .zip(&right)
+            .map(|(a, b)| match (a, b) {
+                (Some(a), Some(b)) if a.len() != b.len() => Err(DataFusionError::Execution(format!(
+                    "{} requires vectors of equal length, got {} and {}",
+                    self.name(), a.len(), b.len()
+                ))),
+                (Some(a), Some(b)) => Ok(match self.kind {
+                    VectorFunctionKind::CosineDistance => cosine_distance(a, b),
+                    VectorFunctionKind::EuclideanDistance => DistanceType::Euclidean.distance(a, b),
+                    VectorFunctionKind::DotProduct => Some(dot(a, b)),
+                }),
+                _ => Ok(None),
+            })
+            .collect::<Result<Float64Array>>()?;
+
+        Ok(ColumnarValue::Array(Arc::new(values)))
+    }
+}
+
+/// Decode a list-typed column into one vector per row
+fn vectors(array: &ArrayRef) -> Result<Vec<Option<Vec<f64>>>> {
+    let element = |values: ArrayRef| -> Result<Vec<f64>> {
+        let values = cast(&values, &DataType::Float64)?;
+        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+        Ok(values.iter().map(|v| v.unwrap_or(f64::NAN)).collect())
+    };
+    let rows = |value: &dyn Fn(usize) -> ArrayRef| {
+        (0..array.len())
+            .map(|row| if array.is_null(row) { Ok(None) } else { element(value(row)).map(Some) })
+            .collect()
+    };
+
+    match array.data_type() {
+        DataType::List(_) => {
+            let list = array.as_any().downcast_ref::<ListArray>().unwrap();
+            rows(&|row| list.value(row))
+        }
+        DataType::LargeList(_) => {
+            let list = array.as_any().downcast_ref::<LargeListArray>().unwrap();
+            rows(&|row| list.value(row))
+        }
+        DataType::FixedSizeList(_, _) => {
+            let list = array.as_any().downcast_ref::<FixedSizeListArray>().unwrap();
+            rows(&|row| list.value(row))
+        }
+        other => Err(DataFusionError::Execution(format!("Expected an ARRAY of numbers, got {}", other))),
+    }
+}
+
+/// Public description of a vector index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorIndexInfo {
+    /// Index name
+    pub name: String,
+    /// Indexed table
+    pub table_name: String,
+    /// Indexed embedding column
+    pub column: String,
+    /// Distance the index is built for
+    pub distance_type: DistanceType,
+    /// Rows currently in the index
+    pub indexed_rows: usize,
+    /// Embedding dimensionality
+    pub dimensions: usize,
+    /// Why the index could not be built for the current table contents, in
+    /// which case searches fall back to a full scan
+    pub error: Option<String>,
+}
+
+/// Parsed `CREATE VECTOR INDEX` statement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateVectorIndex {
+    pub name: String,
+    pub table_name: String,
+    pub column: String,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+    pub distance_type: DistanceType,
+    pub m: usize,
+    pub ef_construction: usize,
+}
+
+/// Parse `CREATE [OR REPLACE] VECTOR INDEX [IF NOT EXISTS] name ON table(column) [OPTIONS(...)]`
+pub fn parse_create_vector_index(sql: &str) -> BlazeResult<Option<CreateVectorIndex>> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*CREATE\s+(OR\s+REPLACE\s+)?VECTOR\s+INDEX\s+(IF\s+NOT\s+EXISTS\s+)?([A-Za-z_]\w*)\s+ON\s+([A-Za-z_][\w.]*)\s*\(\s*([A-Za-z_]\w*)\s*\)\s*(?:OPTIONS\s*\((.*)\))?\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let Some(captures) = PATTERN.captures(sql) else {
+        return Ok(None);
+    };
+
+    let options = match captures.get(6) {
+        Some(text) => parse_options(text.as_str()).map_err(BlazeError::InvalidInput)?,
+        None => Default::default(),
+    };
+    if let Some(index_type) = options.get("index_type").and_then(|v| v.as_str()) {
+        if !index_type.eq_ignore_ascii_case("HNSW") {
+            return Err(invalid_input!("Unsupported index_type '{}'; only HNSW is available", index_type));
+        }
+    }
+    let distance_type = match options.get("distance_type").and_then(|v| v.as_str()) {
+        Some(value) => DistanceType::parse(value)?,
+        None => DistanceType::Euclidean,
+    };
+    let number = |key: &str, default: usize| options.get(key).and_then(|v| v.as_f64()).map_or(default, |n| n as usize);
+
+    Ok(Some(CreateVectorIndex {
+        name: captures[3].to_string(),
+        table_name: captures[4].to_string(),
+        column: captures[5].to_string(),
+        or_replace: captures.get(1).is_some(),
+        if_not_exists: captures.get(2).is_some(),
+        distance_type,
+        m: number("m", 16).max(2),
+        ef_construction: number("ef_construction", 100).max(1),
+    }))
+}
+
+/// Parse `DROP VECTOR INDEX [IF EXISTS] name ON table`, returning the index
+/// name, table name and whether IF EXISTS was given
+pub fn parse_drop_vector_index(sql: &str) -> Option<(String, String, bool)> {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)^\s*DROP\s+VECTOR\s+INDEX\s+(IF\s+EXISTS\s+)?([A-Za-z_]\w*)\s+ON\s+([A-Za-z_][\w.]*)\s*;?\s*$",
+        )
+        .unwrap()
+    });
+    let captures = PATTERN.captures(sql)?;
+    Some((captures[2].to_string(), captures[3].to_string(), captures.get(1).is_some()))
+}
+
+/// An HNSW index over one embedding column, rebuilt whenever the table changes
+#[derive(Debug)]
+pub struct VectorIndex {
+    definition: CreateVectorIndex,
+    graph: std::result::Result<Hnsw, String>,
+}
+
+impl VectorIndex {
+    /// Build the index over the table's current batches. Build failures (for
+    /// example a dropped column) are kept on the index rather than returned,
+    /// so later table changes can still succeed.
+    pub fn build(definition: CreateVectorIndex, batches: &[RecordBatch]) -> Self {
+        let graph = Hnsw::build(&definition, batches).map_err(|e| e.to_string());
+        Self { definition, graph }
+    }
+
+    /// Rebuild after the table's contents changed
+    pub fn rebuild(&mut self, batches: &[RecordBatch]) {
+        self.graph = Hnsw::build(&self.definition, batches).map_err(|e| e.to_string());
+    }
+
+    pub fn table_name(&self) -> &str {
+        &self.definition.table_name
+    }
+
+    pub fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    /// The statement the index was built from
+    pub fn definition(&self) -> &CreateVectorIndex {
+        &self.definition
+    }
+
+    /// Whether this index can answer a search on `column` by `distance_type`
+    pub fn serves(&self, column: &str, distance_type: DistanceType) -> bool {
+        self.definition.column == column && self.definition.distance_type == distance_type && self.graph.is_ok()
+    }
+
+    /// Describe the index
+    pub fn info(&self) -> VectorIndexInfo {
+        let (indexed_rows, dimensions, error) = match &self.graph {
+            Ok(graph) => (graph.row_ids.len(), graph.dimensions, None),
+            Err(e) => (0, 0, Some(e.clone())),
+        };
+        VectorIndexInfo {
+            name: self.definition.name.clone(),
+            table_name: self.definition.table_name.clone(),
+            column: self.definition.column.clone(),
+            distance_type: self.definition.distance_type,
+            indexed_rows,
+            dimensions,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored(f64, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Hierarchical navigable small world graph
+#[derive(Debug)]
+struct Hnsw {
+    distance_type: DistanceType,
+    m: usize,
+    ef_construction: usize,
+    dimensions: usize,
+    vectors: Vec<Vec<f64>>,
+    /// Table row position of each node
+    row_ids: Vec<u32>,
+    /// Neighbours of each node, per layer it appears on
+    links: Vec<Vec<Vec<u32>>>,
+    entry: Option<u32>,
+    max_level: usize,
+    rng: StdRng,
+}
+
+impl Hnsw {
+    fn build(definition: &CreateVectorIndex, batches: &[RecordBatch]) -> BlazeResult<Self> {
+        let mut graph = Self {
+            distance_type: definition.distance_type,
+            m: definition.m,
+            ef_construction: definition.ef_construction,
+            dimensions: 0,
+            vectors: Vec::new(),
+            row_ids: Vec::new(),
+            links: Vec::new(),
+            entry: None,
+            max_level: 0,
+            // Fixed seed so rebuilding the same data yields the same graph
+            rng: StdRng::seed_from_u64(0x5eed),
+        };
+
+        let mut row_id: u32 = 0;
+        for batch in batches {
+            let index = batch
+                .schema()
+                .index_of(&definition.column)
+                .map_err(|_| invalid_input!("Column '{}' not found in '{}'", definition.column, definition.table_name))?;
+            for vector in vectors(batch.column(index))? {
+                if let Some(vector) = vector {
+                    if graph.dimensions == 0 {
+                        graph.dimensions = vector.len();
+                    } else if vector.len() != graph.dimensions {
+                        return Err(invalid_input!(
+                            "Row {} of '{}' has {} dimensions, expected {}",
+                            row_id, definition.column, vector.len(), graph.dimensions
+                        ));
+                    }
+                    graph.insert(row_id, vector);
+                }
+                row_id += 1;
+            }
+        }
+
+        Ok(graph)
+    }
+
+    fn distance(&self, query: &[f64], node: u32) -> f64 {
+        self.distance_type.distance(query, &self.vectors[node as usize]).unwrap_or(f64::INFINITY)
+    }
+
+    fn random_level(&mut self) -> usize {
+        let scale = 1.0 / (self.m as f64).ln();
+        let uniform: f64 = self.rng.gen_range(f64::MIN_POSITIVE..1.0);
+        (-uniform.ln() * scale).floor() as usize
+    }
+
+    fn insert(&mut self, row_id: u32, vector: Vec<f64>) {
+        let node = self.vectors.len() as u32;
+        let level = self.random_level();
+        self.vectors.push(vector.clone());
+        self.row_ids.push(row_id);
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        let Some(mut entry) = self.entry else {
+            self.entry = Some(node);
+            self.max_level = level;
+            return;
+        };
+
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.search_layer(&vector, entry, 1, layer)[0].1;
+        }
+
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let found = self.search_layer(&vector, entry, self.ef_construction, layer);
+            let max_links = if layer == 0 { self.m * 2 } else { self.m };
+            let neighbours: Vec<u32> = found.iter().take(self.m).map(|s| s.1).collect();
+
+            for &neighbour in &neighbours {
+                self.links[neighbour as usize][layer].push(node);
+                if self.links[neighbour as usize][layer].len() > max_links {
+                    let origin = self.vectors[neighbour as usize].clone();
+                    let mut scored: Vec<Scored> = self.links[neighbour as usize][layer]
+                        .iter()
+                        .map(|&n| Scored(self.distance(&origin, n), n))
+                        .collect();
+                    scored.sort();
+                    self.links[neighbour as usize][layer] = scored.into_iter().take(max_links).map(|s| s.1).collect();
+                }
+            }
+            self.links[node as usize][layer] = neighbours;
+            entry = found[0].1;
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry = Some(node);
+        }
+    }
+
+    /// Best-first search of one layer, returning up to `ef` nodes closest first
+    fn search_layer(&self, query: &[f64], entry: u32, ef: usize, layer: usize) -> Vec<Scored> {
+        let start = Scored(self.distance(query, entry), entry);
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::from([Reverse(start)]);
+        let mut results = BinaryHeap::from([start]);
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            if results.len() >= ef && current.0 > results.peek().unwrap().0 {
+                break;
+            }
+            for &neighbour in &self.links[current.1 as usize][layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored(self.distance(query, neighbour), neighbour);
+                if results.len() < ef || scored.0 < results.peek().unwrap().0 {
+                    candidates.push(Reverse(scored));
+                    results.push(scored);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Approximate `k` nearest rows as `(row position, distance)`
+    fn search(&self, query: &[f64], k: usize) -> Vec<(u32, f64)> {
+        let Some(mut entry) = self.entry else {
+            return Vec::new();
+        };
+        for layer in (1..=self.max_level).rev() {
+            entry = self.search_layer(query, entry, 1, layer)[0].1;
+        }
+        self.search_layer(query, entry, k.max(self.ef_construction), 0)
+            .into_iter()
+            .filter(|s| s.0.is_finite())
+            .take(k)
+            .map(|s| (self.row_ids[s.1 as usize], s.0))
+            .collect()
+    }
+}
+
+/// Parsed `VECTOR_SEARCH(...)` call and its position in the query text
+#[derive(Debug, Clone)]
+pub struct VectorSearchCall {
+    /// Byte range of the call in the original SQL
+    pub span: (usize, usize),
+    pub base_table: String,
+    pub column: String,
+    /// Query producing the search vectors, in a column named like `column`
+    pub query_sql: String,
+    pub top_k: usize,
+    pub distance_type: DistanceType,
+}
+
+/// Cheap pre-check so only vector queries pay for the rewrite
+pub fn contains_vector_search(sql: &str) -> bool {
+    sql.to_ascii_uppercase().contains("VECTOR_SEARCH")
+}
+
+/// Find every `VECTOR_SEARCH(...)` call in `sql`
+pub fn find_vector_search_calls(sql: &str) -> BlazeResult<Vec<VectorSearchCall>> {
+    static CALL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bVECTOR_SEARCH\s*\(").unwrap());
+    static TABLE_ARG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^TABLE\s+([A-Za-z_][\w.]*)$").unwrap());
+    static NAMED_ARG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)^([A-Za-z_]\w*)\s*=>\s*(.+)$").unwrap());
+
+    let mut calls = Vec::new();
+    let mut cursor = 0;
+    while let Some(found) = CALL.find_at(sql, cursor) {
+        let open = found.end() - 1;
+        let close = matching_paren(sql, open).ok_or_else(|| invalid_input!("Unbalanced VECTOR_SEARCH(...)"))?;
+        let (positional, named): (Vec<&str>, Vec<&str>) = split_top_level(&sql[open + 1..close], ',')
+            .into_iter()
+            .map(str::trim)
+            .partition(|a| !NAMED_ARG.is_match(a));
+        let [base, column, query] = positional.as_slice() else {
+            return Err(invalid_input!(
+                "VECTOR_SEARCH expects (TABLE base, 'column', query, [top_k => n], [distance_type => '...'])"
+            ));
+        };
+
+        let base_table = TABLE_ARG
+            .captures(base)
+            .map(|c| c[1].to_string())
+            .ok_or_else(|| invalid_input!("VECTOR_SEARCH base must be TABLE <name>, got '{}'", base))?;
+        let column = unquote(column).ok_or_else(|| invalid_input!("VECTOR_SEARCH column must be a string literal"))?;
+        let query_sql = if let Some(table) = TABLE_ARG.captures(query) {
+            format!("SELECT * FROM {}", &table[1])
+        } else if query.starts_with('(') && query.ends_with(')') {
+            query[1..query.len() - 1].to_string()
+        } else {
+            format!("SELECT {} AS \"{}\"", query, column)
+        };
+
+        let mut top_k = 10;
+        let mut distance_type = DistanceType::Euclidean;
+        for arg in named {
+            let captures = NAMED_ARG.captures(arg).unwrap();
+            let value = captures[2].trim();
+            match captures[1].to_lowercase().as_str() {
+                "top_k" => {
+                    top_k = value.parse().map_err(|_| invalid_input!("top_k must be a positive integer, got '{}'", value))?
+                }
+                "distance_type" => {
+                    let value = unquote(value).ok_or_else(|| invalid_input!("distance_type must be a string literal"))?;
+                    distance_type = DistanceType::parse(&value)?;
+                }
+                other => return Err(invalid_input!("Unknown VECTOR_SEARCH argument '{}'", other)),
+            }
+        }
+
+        calls.push(VectorSearchCall {
+            span: (found.start(), close + 1),
+            base_table,
+            column,
+            query_sql,
+            top_k,
+            distance_type,
+        });
+        cursor = close + 1;
+    }
+
+    Ok(calls)
+}
+
+fn unquote(text: &str) -> Option<String> {
+    let quoted = text.len() >= 2
+        && ((text.starts_with('\'') && text.ends_with('\'')) || (text.starts_with('"') && text.ends_with('"')));
+    quoted.then(|| text[1..text.len() - 1].to_string())
+}
+
+/// Run a search, returning `query_index`, the base table's columns and `distance`
+pub fn execute_search(
+    call: &VectorSearchCall,
+    base_schema: SchemaRef,
+    base_batches: &[RecordBatch],
+    query_batches: &[RecordBatch],
+    index: Option<&VectorIndex>,
+) -> BlazeResult<RecordBatch> {
+    let queries: Vec<Option<Vec<f64>>> = query_batches
+        .iter()
+        .map(|batch| {
+            let schema = batch.schema();
+            let column = match schema.index_of(&call.column) {
+                Ok(i) => i,
+                Err(_) if schema.fields().len() == 1 => 0,
+                Err(_) => return Err(invalid_input!("VECTOR_SEARCH query has no column '{}'", call.column)),
+            };
+            Ok(vectors(batch.column(column))?)
+        })
+        .collect::<BlazeResult<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let graph = index
+        .filter(|i| i.serves(&call.column, call.distance_type))
+        .and_then(|i| i.graph.as_ref().ok());
+    let base_vectors = match graph {
+        Some(_) => Vec::new(),
+        None => {
+            let column = base_schema
+                .index_of(&call.column)
+                .map_err(|_| invalid_input!("Column '{}' not found in '{}'", call.column, call.base_table))?;
+            base_batches
+                .iter()
+                .map(|batch| vectors(batch.column(column)))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+    };
+
+    let (mut query_ids, mut row_ids, mut distances) = (Vec::new(), Vec::new(), Vec::new());
+    for (query_index, query) in queries.iter().enumerate() {
+        let Some(query) = query else { continue };
+        let hits = match graph {
+            Some(graph) => graph.search(query, call.top_k),
+            None => brute_force(&base_vectors, query, call.top_k, call.distance_type),
+        };
+        for (row, distance) in hits {
+            query_ids.push(query_index as i64);
+            row_ids.push(row);
+            distances.push(distance);
+        }
+    }
+
+    let base = concat_batches(&base_schema, base_batches)?;
+    let matched = take_record_batch(&base, &UInt32Array::from(row_ids))?;
+
+    let mut fields = vec![Arc::new(Field::new("query_index", DataType::Int64, false))];
+    fields.extend(base_schema.fields().iter().cloned());
+    fields.push(Arc::new(Field::new("distance", DataType::Float64, false)));
+
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(datafusion::arrow::array::Int64Array::from(query_ids))];
+    columns.extend(matched.columns().iter().cloned());
+    columns.push(Arc::new(Float64Array::from(distances)));
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+}
+
+fn brute_force(rows: &[Option<Vec<f64>>], query: &[f64], k: usize, distance_type: DistanceType) -> Vec<(u32, f64)> {
+    let mut best = BinaryHeap::with_capacity(k + 1);
+    for (row, vector) in rows.iter().enumerate() {
+        let Some(distance) = vector
+            .as_ref()
+            .filter(|v| v.len() == query.len())
+            .and_then(|v| distance_type.distance(query, v))
+        else {
+            continue;
+        };
+        best.push(Scored(distance, row as u32));
+        if best.len() > k {
+            best.pop();
+        }
+    }
+    best.into_sorted_vec().into_iter().map(|s| (s.1, s.0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(distance_type: DistanceType) -> CreateVectorIndex {
+        CreateVectorIndex {
+            name: "idx".to_string(),
+            table_name: "t".to_string(),
+            column: "v".to_string(),
+            or_replace: false,
+            if_not_exists: false,
+            distance_type,
+            m: 4,
+            ef_construction: 32,
+        }
+    }
+
+    #[test]
+    fn test_hnsw_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let rows: Vec<Option<Vec<f64>>> = (0..300)
+            .map(|_| Some((0..8).map(|_| rng.gen_range(-1.0..1.0)).collect()))
+            .collect();
+
+        let mut graph = Hnsw::build(&definition(DistanceType::Cosine), &[]).unwrap();
+        for (row, vector) in rows.iter().enumerate() {
+            graph.insert(row as u32, vector.clone().unwrap());
+        }
+
+        let query = rows[42].clone().unwrap();
+        let exact: Vec<u32> = brute_force(&rows, &query, 5, DistanceType::Cosine).iter().map(|h| h.0).collect();
+        let approximate: Vec<u32> = graph.search(&query, 5).iter().map(|h| h.0).collect();
+        assert_eq!(exact[0], 42);
+        assert_eq!(approximate, exact);
+    }
+
+    #[test]
+    fn test_parse_vector_search_call() {
+        let sql = "SELECT * FROM VECTOR_SEARCH(TABLE docs, 'embedding', [1.0, 0.0], top_k => 3, distance_type => 'COSINE') s";
+        let calls = find_vector_search_calls(sql).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].base_table, "docs");
+        assert_eq!(calls[0].column, "embedding");
+        assert_eq!(calls[0].query_sql, "SELECT [1.0, 0.0] AS \"embedding\"");
+        assert_eq!(calls[0].top_k, 3);
+        assert_eq!(calls[0].distance_type, DistanceType::Cosine);
+        assert_eq!(&sql[calls[0].span.0..calls[0].span.1], &sql[14..sql.len() - 2]);
+    }
+}
@@ -0,0 +1,196 @@
+//! Workload management through resource groups
+//!
+//! A resource group caps the queries assigned to it: how many partitions
+//! each query is split into, how much of the engine's memory the group's
+//! queries may reserve together, and how many run at once. Queries beyond
+//! the concurrency limit wait for a slot. Queries without a group run with
+//! the engine's own limits.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::execution::memory_pool::MemoryPool;
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::execution::session_state::SessionStateBuilder;
+use datafusion::physical_plan::collect;
+use datafusion::prelude::DataFrame;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::error::{BlazeError, BlazeResult};
+use crate::memory_pool::ResizableMemoryPool;
+
+/// Limits for the queries assigned to a group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceGroup {
+    /// Group name queries refer to
+    pub name: String,
+    /// Fraction of the engine's CPU cores each query is partitioned across, in (0, 1]
+    pub cpu_share: f64,
+    /// Fraction of the engine's memory limit the group's running queries may
+    /// reserve together, in (0, 1]
+    pub memory_fraction: f64,
+    /// Maximum queries from the group running at once
+    pub max_concurrency: usize,
+}
+
+impl ResourceGroup {
+    pub(crate) fn validate(&self) -> BlazeResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(BlazeError::Config("Resource group name must not be empty".to_string()));
+        }
+        for (setting, value) in [("cpu_share", self.cpu_share), ("memory_fraction", self.memory_fraction)] {
+            if !(value > 0.0 && value <= 1.0) {
+                return Err(BlazeError::Config(format!(
+                    "Resource group '{}': {} must be in (0, 1], got {}",
+                    self.name, setting, value
+                )));
+            }
+        }
+        if self.max_concurrency == 0 {
+            return Err(BlazeError::Config(format!(
+                "Resource group '{}': max_concurrency must be greater than 0",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A resource group's limits and activity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceGroupStats {
+    /// The group's configuration
+    pub group: ResourceGroup,
+    /// Memory the group may reserve, derived from the engine's limit
+    pub memory_limit_bytes: usize,
+    /// Bytes the group's running queries currently hold
+    pub reserved_memory_bytes: usize,
+    /// Queries currently running
+    pub running_queries: u64,
+    /// Queries waiting for a concurrency slot
+    pub queued_queries: u64,
+    /// Queries that finished successfully
+    pub completed_queries: u64,
+    /// Queries that returned an error
+    pub failed_queries: u64,
+    /// Average execution time of completed queries, excluding time queued
+    pub avg_execution_time_ms: f64,
+    /// Largest memory increase seen for one query
+    pub peak_memory_bytes: u64,
+}
+
+/// A registered group with its memory pool, concurrency slots and counters
+pub(crate) struct ResourceGroupState {
+    group: ResourceGroup,
+    memory_pool: Arc<ResizableMemoryPool>,
+    slots: Semaphore,
+    running: AtomicU64,
+    queued: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    total_time_ms: AtomicU64,
+    peak_memory_bytes: AtomicU64,
+}
+
+/// A concurrency slot, released when dropped
+pub(crate) struct GroupSlot<'a> {
+    group: &'a ResourceGroupState,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Drop for GroupSlot<'_> {
+    fn drop(&mut self) {
+        self.group.running.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ResourceGroupState {
+    /// Set up a group drawing from the engine's memory pool
+    pub(crate) fn new(group: ResourceGroup, engine_pool: Arc<ResizableMemoryPool>, engine_memory_limit: usize) -> Self {
+        let limit = Self::memory_limit(&group, engine_memory_limit);
+        Self {
+            memory_pool: Arc::new(ResizableMemoryPool::with_parent(limit, engine_pool)),
+            slots: Semaphore::new(group.max_concurrency),
+            group,
+            running: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            total_time_ms: AtomicU64::new(0),
+            peak_memory_bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn memory_limit(group: &ResourceGroup, engine_memory_limit: usize) -> usize {
+        (engine_memory_limit as f64 * group.memory_fraction) as usize
+    }
+
+    /// Follow a change to the engine's memory limit
+    pub(crate) fn resize(&self, engine_memory_limit: usize) {
+        self.memory_pool.set_limit(Self::memory_limit(&self.group, engine_memory_limit));
+    }
+
+    /// Wait for a concurrency slot
+    pub(crate) async fn admit(&self) -> BlazeResult<GroupSlot<'_>> {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let permit = self.slots.acquire().await;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        let permit = permit.map_err(|_| BlazeError::Internal(format!("Resource group '{}' closed", self.group.name)))?;
+
+        self.running.fetch_add(1, Ordering::Relaxed);
+        Ok(GroupSlot { group: self, _permit: permit })
+    }
+
+    /// Count a finished query
+    pub(crate) fn record(&self, succeeded: bool, execution_time_ms: u64, memory_used: u64) {
+        if succeeded {
+            self.completed.fetch_add(1, Ordering::Relaxed);
+            self.total_time_ms.fetch_add(execution_time_ms, Ordering::Relaxed);
+            self.peak_memory_bytes.fetch_max(memory_used, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Plan and run a query with the group's partitioning and memory pool
+    pub(crate) async fn collect(&self, df: DataFrame, cpu_cores: usize) -> BlazeResult<Vec<RecordBatch>> {
+        // The frame's own state carries any query hints
+        let (state, plan) = df.into_parts();
+        let engine_runtime = state.runtime_env().clone();
+        let runtime = RuntimeEnv {
+            memory_pool: self.memory_pool.clone(),
+            disk_manager: engine_runtime.disk_manager.clone(),
+            cache_manager: engine_runtime.cache_manager.clone(),
+            object_store_registry: engine_runtime.object_store_registry.clone(),
+        };
+        let mut state = SessionStateBuilder::new_from_existing(state)
+            .with_runtime_env(Arc::new(runtime))
+            .build();
+        state.config_mut().options_mut().execution.target_partitions =
+            ((cpu_cores as f64 * self.group.cpu_share).round() as usize).max(1);
+
+        let plan = state.create_physical_plan(&plan).await?;
+        Ok(collect(plan, state.task_ctx()).await?)
+    }
+
+    pub(crate) fn stats(&self) -> ResourceGroupStats {
+        let completed = self.completed.load(Ordering::Relaxed);
+        ResourceGroupStats {
+            group: self.group.clone(),
+            memory_limit_bytes: self.memory_pool.limit(),
+            reserved_memory_bytes: self.memory_pool.reserved(),
+            running_queries: self.running.load(Ordering::Relaxed),
+            queued_queries: self.queued.load(Ordering::Relaxed),
+            completed_queries: completed,
+            failed_queries: self.failed.load(Ordering::Relaxed),
+            avg_execution_time_ms: if completed > 0 {
+                self.total_time_ms.load(Ordering::Relaxed) as f64 / completed as f64
+            } else {
+                0.0
+            },
+            peak_memory_bytes: self.peak_memory_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,7 +1,11 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio;
 
-use bigquery_lite_engine::{BlazeQueryEngine, BlazeResult};
+use bigquery_lite_engine::{
+    parse_type_name, BadRowPolicy, BlazeError, BlazeQueryEngine, BlazeResult, ChecksumMode, Clock, CsvIngestOptions, CsvTableOptions, JsonTableOptions, DataFormat, FileListing, PayloadCompression, ProtobufFraming,
+    DecimalOverflow, DecimalRules, EngineConfig, EngineConfigUpdate, EngineRegistry, ParquetExportOptions, CsvExportOptions, JsonExportOptions, ParquetSinkOptions, PlanBaseline, PlanRegression, QueryHistoryFilter, QueryOptions, QueryPriority, QueryStatus,
+    ResourceGroup, ShutdownOptions,
+};
 
 #[tokio::test]
 async fn test_engine_creation() -> BlazeResult<()> {
@@ -55,8 +59,8 @@ async fn test_aggregation_queries() -> BlazeResult<()> {
     assert!(!result.data.is_empty());
     let first_row = &result.data[0];
     assert!(first_row.contains_key("category"));
-    assert!(first_row.contains_key("COUNT(*)"));
-    assert!(first_row.contains_key("AVG(categories.value)"));
+    assert!(first_row.contains_key("count(*)"));
+    assert!(first_row.contains_key("avg(categories.value)"));
     
     Ok(())
 }
@@ -212,7 +216,3880 @@ async fn test_error_handling() -> BlazeResult<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_register_table_replaces_existing() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+
+    engine.register_table("replaced", create_simple_test_data().await?).await?;
+    engine.register_table("replaced", create_categorized_test_data(100).await?).await?;
+
+    // The second registration wins and still counts as one table
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM replaced").await?;
+    assert_eq!(result.data[0]["n"], 100);
+    assert_eq!(engine.get_stats().await.registered_tables, 1);
+    assert_eq!(engine.list_tables().await?, vec!["replaced".to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_time_travel_snapshots() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+
+    engine.register_table("versioned", create_simple_test_data().await?).await?;
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    engine.register_table("versioned", create_categorized_test_data(100).await?).await?;
+
+    let snapshots = engine.list_snapshots("versioned").await?;
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0].num_rows, 5);
+    assert_eq!(snapshots[1].num_rows, 100);
+
+    // Reading as of the first commit sees the original 5 rows
+    let as_of = snapshots[0].committed_at.format("%Y-%m-%d %H:%M:%S%.6f");
+    let result = engine.execute_query(&format!(
+        "SELECT COUNT(*) AS n FROM versioned FOR SYSTEM_TIME AS OF TIMESTAMP '{}'", as_of
+    )).await?;
+    assert_eq!(result.data[0]["n"], 5);
+
+    // The current table is unaffected
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM versioned").await?;
+    assert_eq!(result.data[0]["n"], 100);
+
+    // Before the first snapshot there is nothing to read
+    let result = engine.execute_query(
+        "SELECT * FROM versioned FOR SYSTEM_TIME AS OF TIMESTAMP '2000-01-01 00:00:00'"
+    ).await;
+    assert!(result.is_err());
+
+    // Pruning never drops the current version
+    let pruned = engine.prune_snapshots(Some("versioned"), chrono::Utc::now()).await?;
+    assert_eq!(pruned, 1);
+    assert_eq!(engine.list_snapshots("versioned").await?.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_incremental_materialized_view() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(1000).await?).await?;
+
+    let info = engine.create_materialized_view(
+        "events_rollup",
+        "SELECT category, COUNT(*) AS n, SUM(id) AS id_sum, MAX(id) AS max_id FROM events GROUP BY category",
+    ).await?;
+    assert!(info.incremental);
+    assert_eq!(info.rows, 10);
+
+    let full_only = engine.create_materialized_view(
+        "events_avg",
+        "SELECT category, AVG(value) AS avg_value FROM events GROUP BY category",
+    ).await?;
+    assert!(!full_only.incremental);
+    assert!(full_only.unsupported_reason.is_some());
+
+    // Append rows 1000..1500 and check the rollup matches a fresh aggregation
+    let more: Vec<_> = create_categorized_test_data(1500).await?.into_iter().skip(1).collect();
+    engine.append_to_table("events", more).await?;
+
+    let rollup = engine.execute_query(
+        "SELECT SUM(n) AS n, SUM(id_sum) AS id_sum, MAX(max_id) AS max_id FROM events_rollup"
+    ).await?;
+    assert_eq!(rollup.data[0]["n"], 1500);
+    assert_eq!(rollup.data[0]["id_sum"], (0..1500).sum::<i64>());
+    assert_eq!(rollup.data[0]["max_id"], 1499);
+
+    let views = engine.list_materialized_views().await;
+    let rollup_info = views.iter().find(|v| v.name == "events_rollup").unwrap();
+    assert_eq!(rollup_info.incremental_refreshes, 1);
+    assert!(!rollup_info.stale);
+    let avg_info = views.iter().find(|v| v.name == "events_avg").unwrap();
+    assert_eq!(avg_info.full_refreshes, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_small_appends_are_coalesced() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::with_config(EngineConfig { batch_size: 100, ..Default::default() }).await?;
+    let batches = create_simple_test_data().await?;
+    engine.register_table("stream", batches.clone()).await?;
+    for _ in 0..49 {
+        engine.append_to_table("stream", batches.clone()).await?;
+    }
+
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(value) AS total FROM stream").await?;
+    assert_eq!(result.data[0]["n"], 250);
+    assert_eq!(result.data[0]["total"], 7500.0);
+    // 250 rows in batches of up to 100 rows rather than 50 batches of 5
+    let explain = engine.execute_query("EXPLAIN SELECT * FROM stream").await?;
+    let physical = explain.data.iter().find(|row| row["plan_type"] == "physical_plan").unwrap();
+    assert!(physical["plan"].as_str().unwrap().contains("partition_sizes=[3]"), "{}", physical["plan"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_append_schema_mismatch() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("narrow", create_simple_test_data().await?).await?;
+
+    let result = engine.append_to_table("narrow", create_categorized_test_data(10).await?).await;
+    assert!(result.is_err());
+
+    let result = engine.append_to_table("missing", create_simple_test_data().await?).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_change_data_capture() -> BlazeResult<()> {
+    use bigquery_lite_engine::ChangeType;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let mut subscription = engine.subscribe_changes(Some("cdc")).await;
+
+    engine.register_table("cdc", create_simple_test_data().await?).await?;
+    engine.append_to_table("cdc", create_simple_test_data().await?).await?;
+    let result = engine.execute_query("INSERT INTO cdc VALUES (6, 60.0), (7, 70.0)").await?;
+    assert_eq!(result.data[0]["count"], 2);
+
+    let events = engine.get_changes(Some("cdc"), 0).await;
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|e| e.change_type == ChangeType::Insert));
+    assert_eq!(events.iter().map(|e| e.num_rows()).collect::<Vec<_>>(), vec![5, 5, 2]);
+
+    // Replacing the table emits a delete of the old rows and an insert of the new ones
+    let before = engine.latest_change_sequence().await;
+    engine.register_table("cdc", create_simple_test_data().await?).await?;
+    let events = engine.get_changes(Some("cdc"), before).await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].change_type, ChangeType::Delete);
+    assert_eq!(events[0].num_rows(), 12);
+    assert_eq!(events[0].committed_at, events[1].committed_at);
+
+    // The live subscription saw every event in order
+    for expected in 1..=5 {
+        let event = subscription.recv().await.unwrap();
+        assert_eq!(event.sequence, expected);
+    }
+
+    let count = engine.execute_query("SELECT COUNT(*) AS n FROM cdc").await?;
+    assert_eq!(count.data[0]["n"], 5);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_model_and_predict() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("training", create_simple_test_data().await?).await?;
+
+    engine.execute_query(
+        "CREATE MODEL value_model OPTIONS(model_type='linear_reg', input_label_cols=['value']) AS \
+         SELECT id, value FROM training"
+    ).await?;
+    let model = engine.get_model("value_model").await.unwrap();
+    assert_eq!(model.features, vec!["id"]);
+    assert!((model.weights[0] - 10.0).abs() < 1e-6);
+
+    let result = engine.execute_query(
+        "SELECT id, predicted_value FROM ML.PREDICT(MODEL value_model, TABLE training) ORDER BY id"
+    ).await?;
+    assert_eq!(result.rows, 5);
+    let predicted = result.data[2]["predicted_value"].as_f64().unwrap();
+    assert!((predicted - 30.0).abs() < 1e-6);
+
+    engine.execute_query(
+        "CREATE MODEL high_model OPTIONS(model_type='logistic_reg', max_iterations=200) AS \
+         SELECT id, CASE WHEN id > 3 THEN 1 ELSE 0 END AS label FROM training"
+    ).await?;
+    let result = engine.execute_query(
+        "SELECT id, predicted_label FROM ML.PREDICT(MODEL high_model, (SELECT id FROM training)) ORDER BY id"
+    ).await?;
+    let labels: Vec<_> = result.data.iter().map(|row| row["predicted_label"].as_i64().unwrap()).collect();
+    assert_eq!(labels, vec![0, 0, 0, 1, 1]);
+
+    // The label column defaults to `label`, which this query lacks
+    let result = engine.execute_query(
+        "CREATE MODEL bad OPTIONS(model_type='linear_reg') AS SELECT id, value FROM training"
+    ).await;
+    assert!(result.is_err());
+
+    engine.execute_query("DROP MODEL value_model").await?;
+    assert_eq!(engine.list_models().await.len(), 1);
+    assert!(engine.execute_query("SELECT * FROM ML.PREDICT(MODEL value_model, TABLE training)").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_full_text_search() -> BlazeResult<()> {
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("message", DataType::Utf8, true),
+    ]));
+    let logs = |ids: Vec<i64>, messages: Vec<Option<&str>>| {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from(ids)), Arc::new(StringArray::from(messages))],
+        )
+    };
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("logs", vec![logs(
+        vec![1, 2, 3, 4],
+        vec![Some("Upstream TIMEOUT after 30s"), None, Some("connection reset"), Some("timeout: upstream=api-2")],
+    )?]).await?;
+
+    let query = "SELECT id FROM logs WHERE SEARCH(message, 'timeout upstream') ORDER BY id";
+    let ids = |result: &bigquery_lite_engine::QueryResult| {
+        result.data.iter().map(|row| row["id"].as_i64().unwrap()).collect::<Vec<_>>()
+    };
+    let unindexed = engine.execute_query(query).await?;
+    assert_eq!(ids(&unindexed), vec![1, 4]);
+
+    let substr = engine.execute_query("SELECT id FROM logs WHERE CONTAINS_SUBSTR(message, 'RESET')").await?;
+    assert_eq!(ids(&substr), vec![3]);
+
+    // The index gives the same answers and follows appends
+    engine.execute_query("CREATE SEARCH INDEX logs_idx ON logs(ALL COLUMNS)").await?;
+    assert_eq!(engine.list_search_indexes().await[0].columns, vec!["message"]);
+    assert_eq!(ids(&engine.execute_query(query).await?), vec![1, 4]);
+
+    engine.append_to_table("logs", vec![logs(vec![5], vec![Some("upstream timeout again")])?]).await?;
+    assert_eq!(ids(&engine.execute_query(query).await?), vec![1, 4, 5]);
+    let combined = engine.execute_query(
+        "SELECT id FROM logs WHERE SEARCH(message, 'timeout') AND SEARCH(message, 'again')"
+    ).await?;
+    assert_eq!(ids(&combined), vec![5]);
+
+    engine.execute_query("DROP SEARCH INDEX logs_idx ON logs").await?;
+    assert!(engine.list_search_indexes().await.is_empty());
+    assert_eq!(ids(&engine.execute_query(query).await?), vec![1, 4, 5]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vector_search() -> BlazeResult<()> {
+    use datafusion::arrow::array::{Array, FixedSizeListArray, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Float32Type, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let embeddings = vec![
+        Some(vec![Some(1.0f32), Some(0.0), Some(0.0)]),
+        Some(vec![Some(0.9), Some(0.1), Some(0.0)]),
+        Some(vec![Some(0.0), Some(1.0), Some(0.0)]),
+        None,
+        Some(vec![Some(0.0), Some(0.0), Some(1.0)]),
+    ];
+    let embedding = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(embeddings, 3);
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("embedding", embedding.data_type().clone(), true),
+    ]));
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])), Arc::new(embedding)])?;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("docs", vec![batch]).await?;
+
+    let distances = engine.execute_query(
+        "SELECT id, COSINE_DISTANCE(embedding, [1.0, 0.0, 0.0]) AS d FROM docs WHERE id = 3"
+    ).await?;
+    assert!((distances.data[0]["d"].as_f64().unwrap() - 1.0).abs() < 1e-9);
+
+    let query = "SELECT id, distance FROM VECTOR_SEARCH(TABLE docs, 'embedding', [1.0, 0.05, 0.0], \
+                 top_k => 2, distance_type => 'COSINE') ORDER BY distance";
+    let ids = |result: &bigquery_lite_engine::QueryResult| {
+        result.data.iter().map(|row| row["id"].as_i64().unwrap()).collect::<Vec<_>>()
+    };
+    let scanned = engine.execute_query(query).await?;
+    assert_eq!(ids(&scanned), vec![1, 2]);
+
+    engine.execute_query(
+        "CREATE VECTOR INDEX docs_idx ON docs(embedding) OPTIONS(index_type='HNSW', distance_type='COSINE')"
+    ).await?;
+    assert_eq!(engine.list_vector_indexes().await[0].indexed_rows, 4);
+    assert_eq!(ids(&engine.execute_query(query).await?), vec![1, 2]);
+
+    // Several query vectors, tagged by query_index
+    let multi = engine.execute_query(
+        "SELECT query_index, id FROM VECTOR_SEARCH(TABLE docs, 'embedding', \
+         (SELECT embedding FROM docs WHERE id IN (3, 5)), top_k => 1, distance_type => 'COSINE') \
+         ORDER BY query_index"
+    ).await?;
+    assert_eq!(ids(&multi), vec![3, 5]);
+
+    engine.execute_query("DROP VECTOR INDEX docs_idx ON docs").await?;
+    assert!(engine.list_vector_indexes().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sessionize() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.execute_query("CREATE TABLE events (user_id BIGINT, event_time TIMESTAMP)").await?;
+    engine.execute_query(
+        "INSERT INTO events VALUES \
+         (1, TIMESTAMP '2024-01-01 10:00:00'), (1, TIMESTAMP '2024-01-01 10:20:00'), \
+         (1, TIMESTAMP '2024-01-01 11:00:00'), (2, TIMESTAMP '2024-01-01 10:05:00'), \
+         (2, TIMESTAMP '2024-01-01 10:30:00')"
+    ).await?;
+
+    let result = engine.execute_query(
+        "SELECT user_id, session_number, COUNT(*) AS events \
+         FROM SESSIONIZE(TABLE events, 'user_id', 'event_time', INTERVAL 30 MINUTE) \
+         GROUP BY user_id, session_number ORDER BY user_id, session_number"
+    ).await?;
+    let sessions: Vec<(i64, i64, i64)> = result
+        .data
+        .iter()
+        .map(|row| (row["user_id"].as_i64().unwrap(), row["session_number"].as_i64().unwrap(), row["events"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(sessions, vec![(1, 1, 2), (1, 2, 1), (2, 1, 2)]);
+
+    let total = engine.execute_query(
+        "SELECT COUNT(DISTINCT session_id) AS n \
+         FROM SESSIONIZE((SELECT * FROM events WHERE user_id = 1), 'user_id', 'event_time', 600)"
+    ).await?;
+    assert_eq!(total.data[0]["n"], 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_assertions() -> BlazeResult<()> {
+    use bigquery_lite_engine::{Assertion, BlazeError};
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("checked", create_simple_test_data().await?).await?;
+
+    engine.execute_query("ASSERT (SELECT COUNT(*) FROM checked) = 5 AS 'expected five rows'").await?;
+    let failed = engine.execute_query("ASSERT (SELECT MAX(value) FROM checked) < 50 AS 'value too large'").await;
+    match failed {
+        Err(BlazeError::AssertionFailed(message)) => assert_eq!(message, "value too large"),
+        other => panic!("expected assertion failure, got {:?}", other.map(|r| r.rows)),
+    }
+
+    let results = engine.run_assertions(&[
+        Assertion::for_table("checked", "value > 0"),
+        Assertion::for_table("checked", "value < 30"),
+        Assertion::new("(SELECT COUNT(DISTINCT id) FROM checked) = 5"),
+        Assertion::for_table("missing_table", "id > 0"),
+    ]).await;
+
+    assert!(results[0].passed);
+    assert_eq!(results[0].failing_rows, Some(0));
+    assert!(!results[1].passed);
+    assert_eq!(results[1].failing_rows, Some(3));
+    assert!(results[2].passed);
+    assert!(!results[3].passed);
+    assert!(results[3].error.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_approx_quantiles() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("categorized", create_categorized_test_data(1000).await?).await?;
+
+    let result = engine.execute_query(
+        "SELECT CAST(array_length(q) AS BIGINT) AS n, array_element(q, 1) AS min_id, \
+                array_element(q, 3) AS median_id, array_element(q, 5) AS max_id \
+         FROM (SELECT APPROX_QUANTILES(id, 4) AS q FROM categorized)"
+    ).await?;
+    let row = &result.data[0];
+    assert_eq!(row["n"], 5);
+    assert_eq!(row["min_id"], 0);
+    assert_eq!(row["max_id"], 999);
+    assert!((row["median_id"].as_i64().unwrap() - 500).abs() <= 10);
+
+    let grouped = engine.execute_query(
+        "SELECT category, array_element(APPROX_QUANTILES(value, 2), 2) AS median \
+         FROM categorized GROUP BY category ORDER BY category"
+    ).await?;
+    assert_eq!(grouped.rows, 10);
+    assert!(grouped.data.iter().all(|row| row["median"].as_f64().is_some()));
+
+    assert!(engine.execute_query("SELECT APPROX_QUANTILES(value, 0) FROM categorized").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_hll_count_sketches() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("visits", create_categorized_test_data(5000).await?).await?;
+
+    // Roll up per category, then re-aggregate the stored sketches
+    engine.execute_query(
+        "CREATE TABLE visit_rollup AS \
+         SELECT category, HLL_COUNT.INIT(id) AS ids, HLL_COUNT.INIT(id % 100, 12) AS buckets \
+         FROM visits GROUP BY category"
+    ).await?;
+
+    let per_category = engine.execute_query(
+        "SELECT category, HLL_COUNT.EXTRACT(ids) AS n FROM visit_rollup ORDER BY category"
+    ).await?;
+    assert_eq!(per_category.rows, 10);
+    for row in &per_category.data {
+        assert!((row["n"].as_i64().unwrap() - 500).abs() <= 25);
+    }
+
+    let merged = engine.execute_query(
+        "SELECT HLL_COUNT.MERGE(ids) AS ids, hll_count.merge(buckets) AS buckets, \
+                HLL_COUNT.EXTRACT(HLL_COUNT.MERGE_PARTIAL(ids)) AS partial \
+         FROM visit_rollup"
+    ).await?;
+    let row = &merged.data[0];
+    assert!((row["ids"].as_i64().unwrap() - 5000).abs() <= 150);
+    assert_eq!(row["ids"], row["partial"]);
+    assert!((row["buckets"].as_i64().unwrap() - 100).abs() <= 3);
+
+    assert!(engine.execute_query("SELECT HLL_COUNT.INIT(value) FROM visits").await.is_err());
+    assert!(engine.execute_query("SELECT HLL_COUNT.INIT(id, 30) FROM visits").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_date_spine_and_gap_fill() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+
+    let spine = engine.execute_query(
+        "SELECT CAST(value AS VARCHAR) AS day \
+         FROM GENERATE_DATE_ARRAY(DATE '2024-01-30', DATE '2024-02-02') ORDER BY value"
+    ).await?;
+    let days: Vec<&str> = spine.data.iter().map(|row| row["day"].as_str().unwrap()).collect();
+    assert_eq!(days, vec!["2024-01-30", "2024-01-31", "2024-02-01", "2024-02-02"]);
+
+    let hours = engine.execute_query(
+        "SELECT COUNT(*) AS n FROM UNNEST(generate_timestamp_array(\
+         TIMESTAMP '2024-01-01 00:00:00', TIMESTAMP '2024-01-01 12:00:00', INTERVAL 2 HOUR))"
+    ).await?;
+    assert_eq!(hours.data[0]["n"], 7);
+
+    engine.execute_query("CREATE TABLE readings (device VARCHAR, time TIMESTAMP, temperature DOUBLE)").await?;
+    engine.execute_query(
+        "INSERT INTO readings VALUES \
+         ('a', TIMESTAMP '2024-01-01 10:00:00', 20.0), ('a', TIMESTAMP '2024-01-01 10:03:00', 23.0), \
+         ('b', TIMESTAMP '2024-01-01 10:01:00', 30.0), ('b', TIMESTAMP '2024-01-01 10:02:00', 31.0)"
+    ).await?;
+
+    let filled = engine.execute_query(
+        "SELECT device, CAST(time AS VARCHAR) AS minute, temperature \
+         FROM GAP_FILL(TABLE readings, ts_column => 'time', bucket_width => INTERVAL 1 MINUTE, \
+                       partitioning_columns => ['device'], value_columns => [('temperature', 'locf')]) \
+         ORDER BY device, time"
+    ).await?;
+    let rows: Vec<(String, String, f64)> = filled
+        .data
+        .iter()
+        .map(|row| (
+            row["device"].as_str().unwrap().to_string(),
+            row["minute"].as_str().unwrap().to_string(),
+            row["temperature"].as_f64().unwrap(),
+        ))
+        .collect();
+    assert_eq!(rows.len(), 6);
+    assert_eq!(rows[1], ("a".to_string(), "2024-01-01T10:01:00".to_string(), 20.0));
+    assert_eq!(rows[3].2, 23.0);
+    assert_eq!(rows[4].0, "b");
+
+    let interpolated = engine.execute_query(
+        "SELECT temperature FROM GAP_FILL((SELECT * FROM readings WHERE device = 'a'), 'time', INTERVAL 1 MINUTE, \
+                                          value_columns => [('temperature', 'linear')]) \
+         ORDER BY time"
+    ).await?;
+    let values: Vec<f64> = interpolated.data.iter().map(|row| row["temperature"].as_f64().unwrap()).collect();
+    assert_eq!(values, vec![20.0, 21.0, 22.0, 23.0]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_synthetic_dataset() -> BlazeResult<()> {
+    use bigquery_lite_engine::datagen::generate;
+    use bigquery_lite_engine::{Bound, ColumnSpec, ColumnType, DatasetSpec, Distribution};
+
+    let engine = BlazeQueryEngine::new().await?;
+    let spec = DatasetSpec::new(
+        25_000,
+        vec![
+            ColumnSpec::new("order_id", ColumnType::Sequence).with_range(Bound::Number(1.0), Bound::Number(1.0)),
+            "customer:string:cardinality=200,distribution=zipf,skew=1.1".parse()?,
+            ColumnSpec::new("amount", ColumnType::Float)
+                .with_distribution(Distribution::Normal)
+                .with_range(Bound::Number(5.0), Bound::Number(95.0))
+                .with_null_fraction(0.1),
+            "ordered_on:date:min=2024-03-01,max=2024-03-31".parse()?,
+            ColumnSpec::new("express", ColumnType::Bool),
+        ],
+    )
+    .with_seed(7);
+    engine.register_table("synthetic_orders", generate(&spec)?).await?;
+
+    let summary = engine.execute_query(
+        "SELECT COUNT(*) AS n, MIN(order_id) AS first_id, MAX(order_id) AS last_id, \
+                COUNT(DISTINCT customer) AS customers, COUNT(amount) AS amounts, \
+                CAST(COUNT(DISTINCT ordered_on) AS BIGINT) AS days, \
+                CAST(MIN(ordered_on) AS VARCHAR) AS first_day, AVG(amount) AS avg_amount \
+         FROM synthetic_orders"
+    ).await?;
+    let row = &summary.data[0];
+    assert_eq!(row["n"], 25_000);
+    assert_eq!(row["first_id"], 1);
+    assert_eq!(row["last_id"], 25_000);
+    assert!(row["customers"].as_i64().unwrap() <= 200);
+    let amounts = row["amounts"].as_i64().unwrap();
+    assert!((22_000..23_000).contains(&amounts), "{} non-null amounts", amounts);
+    assert_eq!(row["days"], 31);
+    assert_eq!(row["first_day"], "2024-03-01");
+    assert!((row["avg_amount"].as_f64().unwrap() - 50.0).abs() < 1.0);
+
+    // Zipf skew puts the first customer far ahead of the median one
+    let top = engine.execute_query(
+        "SELECT customer, COUNT(*) AS n FROM synthetic_orders GROUP BY customer ORDER BY n DESC LIMIT 1"
+    ).await?;
+    assert_eq!(top.data[0]["customer"], "customer_0");
+    assert!(top.data[0]["n"].as_i64().unwrap() > 3_000);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_config_keeps_tables() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(10_000).await?).await?;
+    let aggregate = "SELECT category, COUNT(*) FROM events GROUP BY category ORDER BY category";
+
+    // A tiny memory limit makes aggregations fail without dropping the table
+    engine.update_config(EngineConfigUpdate { memory_limit_bytes: Some(1024), ..Default::default() }).await?;
+    let error = engine.execute_query(aggregate).await.unwrap_err();
+    assert!(error.to_string().contains("Resources exhausted"), "{}", error);
+
+    let config = engine.update_config(EngineConfigUpdate {
+        memory_limit_bytes: Some(512 * 1024 * 1024),
+        cpu_cores: Some(2),
+        max_result_rows: Some(100),
+        ..Default::default()
+    }).await?;
+    assert_eq!(config.cpu_cores, 2);
+    assert_eq!(engine.config().await.memory_limit_bytes, 512 * 1024 * 1024);
+    assert_eq!(engine.execute_query(aggregate).await?.rows, 10);
+
+    // Results larger than max_result_rows are rejected
+    assert!(matches!(engine.execute_query("SELECT * FROM events").await, Err(BlazeError::InvalidInput(_))));
+    assert_eq!(engine.execute_query("SELECT * FROM events LIMIT 100").await?.rows, 100);
+
+    let invalid = engine.update_config(EngineConfigUpdate { batch_size: Some(0), ..Default::default() }).await;
+    assert!(matches!(invalid, Err(BlazeError::Config(_))));
+    assert!(engine.list_tables().await?.contains(&"events".to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_engine_registry_shares_memory_budget() -> BlazeResult<()> {
+    let registry = EngineRegistry::new(1024);
+    let etl = registry.get_or_create("etl").await?;
+    let interactive = registry.create("interactive", EngineConfig::default()).await?;
+    assert!(Arc::ptr_eq(&etl, &registry.get_or_create("etl").await?));
+    assert!(registry.create("etl", EngineConfig::default()).await.is_err());
+
+    // Each engine has its own catalog
+    etl.register_table("events", create_categorized_test_data(10_000).await?).await?;
+    assert!(interactive.execute_query("SELECT * FROM events").await.is_err());
+
+    // The engine's own limit is 2GB, but the shared budget is exhausted
+    let aggregate = "SELECT category, COUNT(*) FROM events GROUP BY category";
+    let error = etl.execute_query(aggregate).await.unwrap_err();
+    assert!(error.to_string().contains("Resources exhausted"), "{}", error);
+
+    registry.set_memory_limit(512 * 1024 * 1024);
+    assert_eq!(etl.execute_query(aggregate).await?.rows, 10);
+    assert_eq!(registry.reserved_memory_bytes(), 0);
+
+    let infos = registry.list().await;
+    assert_eq!(infos.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["etl", "interactive"]);
+    assert_eq!(infos[0].stats.total_queries, 1);
+
+    registry.remove("interactive").await?;
+    assert_eq!(registry.names().await, vec!["etl".to_string()]);
+    assert!(registry.remove("interactive").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resource_groups() -> BlazeResult<()> {
+    let engine = Arc::new(BlazeQueryEngine::new().await?);
+    engine.register_table("events", create_categorized_test_data(10_000).await?).await?;
+    engine.create_resource_group(ResourceGroup {
+        name: "interactive".to_string(),
+        cpu_share: 0.5,
+        memory_fraction: 0.5,
+        max_concurrency: 1,
+    }).await?;
+    engine.create_resource_group(ResourceGroup {
+        name: "etl".to_string(),
+        cpu_share: 1.0,
+        memory_fraction: 1e-9,
+        max_concurrency: 4,
+    }).await?;
+
+    // Queries beyond the concurrency limit wait their turn
+    let mut handles = vec![];
+    for i in 0..4 {
+        let engine = engine.clone();
+        handles.push(tokio::spawn(async move {
+            let sql = format!("SELECT category, SUM(value) FROM events WHERE id >= {} GROUP BY category", i);
+            engine.execute_query_with_options(&sql, &QueryOptions::in_group("interactive")).await
+        }));
+    }
+    for handle in handles {
+        assert_eq!(handle.await.unwrap()?.rows, 10);
+    }
+
+    // The group's memory share is tiny, so aggregations fail only inside it
+    let aggregate = "SELECT category, COUNT(*) FROM events GROUP BY category";
+    let by_label = QueryOptions {
+        labels: [("resource_group".to_string(), "etl".to_string())].into(),
+        ..Default::default()
+    };
+    let error = engine.execute_query_with_options(aggregate, &by_label).await.unwrap_err();
+    assert!(error.to_string().contains("Resources exhausted"), "{}", error);
+    assert_eq!(engine.execute_query(aggregate).await?.rows, 10);
+
+    let groups = engine.list_resource_groups().await;
+    assert_eq!((groups[0].group.name.as_str(), groups[0].failed_queries), ("etl", 1));
+    assert_eq!((groups[1].group.name.as_str(), groups[1].completed_queries), ("interactive", 4));
+    assert_eq!((groups[1].running_queries, groups[1].queued_queries), (0, 0));
+
+    let unknown = engine.execute_query_with_options(aggregate, &QueryOptions::in_group("adhoc")).await;
+    assert!(matches!(unknown, Err(BlazeError::InvalidInput(_))));
+    let invalid = engine.create_resource_group(ResourceGroup {
+        name: "adhoc".to_string(),
+        cpu_share: 1.5,
+        memory_fraction: 0.5,
+        max_concurrency: 1,
+    }).await;
+    assert!(matches!(invalid, Err(BlazeError::Config(_))));
+
+    engine.drop_resource_group("etl").await?;
+    assert_eq!(engine.list_resource_groups().await.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_execute_stream() -> BlazeResult<()> {
+    use futures::StreamExt;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(10_000).await?).await?;
+
+    let mut stream = engine.execute_stream("SELECT id, value FROM events WHERE category = 'category_3'").await?;
+    let mut rows = 0;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        assert_eq!(batch.num_columns(), 2);
+        rows += batch.num_rows();
+    }
+    assert_eq!(rows, 1_000);
+
+    // Other statements run eagerly and stream their result
+    let mut inserted = engine.execute_stream("INSERT INTO events VALUES (10000, 1.0, 'category_3')").await?;
+    assert_eq!(inserted.next().await.unwrap()?.num_rows(), 1);
+    assert!(engine.execute_stream("SELECT * FROM missing").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_execute_stream_with_options() -> BlazeResult<()> {
+    use futures::StreamExt;
+
+    let engine = BlazeQueryEngine::with_config(EngineConfig { batch_size: 1_000, ..EngineConfig::default() }).await?;
+    engine.register_table("events", create_categorized_test_data(10_000).await?).await?;
+
+    let options = QueryOptions { query_id: Some("export".to_string()), timeout_ms: Some(100), ..Default::default() };
+    let mut stream = engine.execute_stream_with_options("SELECT id FROM events", &options).await?;
+    assert_eq!(engine.running_queries().await[0].query_id, "export");
+    assert!(stream.next().await.unwrap().is_ok());
+
+    // A consumer still reading at the deadline gets a timeout
+    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+    let error = stream.next().await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("timed out after 100ms"), "{}", error);
+    assert!(stream.next().await.is_none());
+    drop(stream);
+    assert!(engine.running_queries().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_query_stats_by_fingerprint() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+
+    engine.execute_query("SELECT * FROM test_table WHERE id = 1").await?;
+    engine.execute_query("select *  from TEST_TABLE where ID = 4 -- again").await?;
+    engine.execute_query("SELECT COUNT(*) FROM test_table").await?;
+
+    let stats = engine.query_stats().await;
+    assert_eq!(stats.len(), 2);
+    let lookup = stats
+        .iter()
+        .find(|s| s.normalized_sql == "select * from test_table where id = ?")
+        .expect("point lookups share a fingerprint");
+    assert_eq!(lookup.executions, 2);
+    assert_eq!(lookup.fingerprint, bigquery_lite_engine::utils::fingerprint_sql("SELECT * FROM test_table WHERE id = 5"));
+    assert_eq!(stats.iter().map(|s| s.executions).sum::<u64>(), engine.get_stats().await.total_queries);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_referenced_tables() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let names = |names: &[&str]| names.iter().map(|n| format!("datafusion.public.{}", n)).collect::<Vec<_>>();
+
+    let refs = engine
+        .get_referenced_tables(
+            "WITH recent AS (SELECT * FROM test_table WHERE id > 2) \
+             SELECT * FROM recent JOIN Other o ON recent.id = o.id, generate_series(1, 3)",
+        )
+        .await?;
+    assert_eq!(refs.reads, names(&["other", "test_table"]));
+    assert!(refs.writes.is_empty());
+
+    let refs = engine.get_referenced_tables("INSERT INTO archive.events SELECT * FROM test_table").await?;
+    assert_eq!(refs.reads, names(&["test_table"]));
+    assert_eq!(refs.writes, vec!["datafusion.archive.events".to_string()]);
+
+    let refs = engine.get_referenced_tables("CREATE VIEW v AS SELECT id FROM test_table").await?;
+    assert_eq!((refs.reads, refs.writes), (names(&["test_table"]), names(&["v"])));
+
+    let refs = engine.get_referenced_tables("DELETE FROM test_table WHERE id IN (SELECT id FROM stale)").await?;
+    assert_eq!((refs.reads, refs.writes), (names(&["stale", "test_table"]), names(&["test_table"])));
+
+    let refs = engine.get_referenced_tables("ASSERT (SELECT COUNT(*) FROM test_table) > 0").await?;
+    assert_eq!(refs.reads, names(&["test_table"]));
+
+    // Nothing is executed
+    assert!(engine.execute_query("SELECT * FROM v").await.is_err());
+    assert!(engine.get_referenced_tables("SELEC * FROM").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_copy_to_and_from() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    let parquet = dir.path().join("all.parquet").display().to_string();
+    let csv = dir.path().join("big.txt").display().to_string();
+
+    let written = engine.execute_query(&format!("COPY test_table TO '{}' (FORMAT PARQUET)", parquet)).await?;
+    assert_eq!(written.data[0]["count"], 5);
+    assert_eq!(written.rows_written, Some(5));
+    assert_eq!(written.bytes_written, Some(std::fs::metadata(&parquet)?.len()));
+    let written = engine
+        .execute_query(&format!(
+            "COPY (SELECT id, value FROM test_table WHERE id > 3) TO '{}' WITH (FORMAT CSV, DELIMITER '|')",
+            csv
+        ))
+        .await?;
+    assert_eq!(written.data[0]["count"], 2);
+
+    // A missing table is created from the file, an existing one appended to
+    let copied = engine.execute_query(&format!("COPY restored FROM '{}'", parquet)).await?;
+    assert_eq!(copied.data[0]["count"], 5);
+    assert_eq!(copied.rows_written, None);
+    engine.execute_query(&format!("COPY restored FROM '{}' (FORMAT CSV, DELIMITER '|')", csv)).await?;
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(value) AS total FROM restored").await?;
+    assert_eq!(result.data[0]["n"], 7);
+    assert_eq!(result.data[0]["total"], 240.0);
+
+    let error = engine.execute_query(&format!("COPY restored FROM '{}' (FORMAT CSV, HEADER false)", csv)).await;
+    assert!(error.is_err());
+    let refs = engine.get_referenced_tables(&format!("COPY restored FROM '{}'", parquet)).await?;
+    assert_eq!(refs.writes, vec!["datafusion.public.restored".to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_files_from_glob() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    for (day, ids) in [("2024-01", "id <= 2"), ("2024-02", "id > 2"), ("2023-12", "id = 1")] {
+        std::fs::create_dir(dir.path().join(day))?;
+        let path = dir.path().join(day).join("part-0.parquet");
+        engine
+            .execute_query(&format!("COPY (SELECT * FROM test_table WHERE {}) TO '{}'", ids, path.display()))
+            .await?;
+    }
+    std::fs::write(dir.path().join("2024-01").join("notes.txt"), "not data")?;
+
+    let pattern = format!("{}/2024-*/part-*.parquet", dir.path().display());
+    let info = engine.register_files("parts", &pattern, None).await?;
+    assert_eq!((info.file_count, info.format), (2, DataFormat::Parquet));
+    assert!(info.total_bytes > 0);
+    assert!(info.files[0].ends_with("2024-01/part-0.parquet"));
+
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(value) AS total FROM parts").await?;
+    assert_eq!(result.data[0]["n"], 5);
+    assert_eq!(result.data[0]["total"], 150.0);
+    assert_eq!(engine.list_file_tables().await.len(), 1);
+
+    // Files that disagree on columns are rejected, naming the file
+    engine
+        .execute_query(&format!("COPY (SELECT id FROM test_table) TO '{}/2024-03/part-0.parquet'", dir.path().display()))
+        .await?;
+    let error = engine.register_files("parts", &pattern, None).await.unwrap_err().to_string();
+    assert!(error.contains("2024-03/part-0.parquet") && error.contains("columns"), "{}", error);
+    let missing = engine.register_files("none", &format!("{}/1999-*/*.parquet", dir.path().display()), None).await;
+    assert!(missing.unwrap_err().to_string().contains("No files match"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_external_table_is_scanned_in_place() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("trips.parquet").display().to_string();
+    engine.execute_query(&format!("COPY test_table TO '{}'", path)).await?;
+
+    engine.execute_query(&format!("CREATE EXTERNAL TABLE trips STORED AS PARQUET LOCATION '{}'", path)).await?;
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM trips WHERE id > 2").await?;
+    assert_eq!(result.data[0]["total"], 120.0);
+    let tables = engine.list_file_tables().await;
+    assert_eq!((tables[0].table_name.as_str(), tables[0].file_count), ("trips", 1));
+
+    let error = engine.execute_query(&format!("CREATE EXTERNAL TABLE trips LOCATION '{}'", path)).await.unwrap_err();
+    assert!(error.to_string().contains("already exists"), "{}", error);
+    engine.execute_query(&format!("CREATE EXTERNAL TABLE IF NOT EXISTS trips LOCATION '{}'", path)).await?;
+    engine.execute_query(&format!("CREATE OR REPLACE EXTERNAL TABLE trips LOCATION '{}'", path)).await?;
+    assert_eq!(engine.list_file_tables().await.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_table_from_glob_merges_columns() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    let exports = [
+        ("2024-01", "SELECT id, value FROM test_table WHERE id <= 2"),
+        ("2024-02", "SELECT value, id, 'web' AS source FROM test_table WHERE id > 2"),
+    ];
+    for (month, query) in exports {
+        std::fs::create_dir(dir.path().join(month))?;
+        let path = dir.path().join(month).join("part-0.parquet");
+        engine.execute_query(&format!("COPY ({}) TO '{}'", query, path.display())).await?;
+    }
+
+    let pattern = format!("{}/2024-*/*.parquet", dir.path().display());
+    assert!(engine.register_files("events", &pattern, None).await.is_err());
+    let info = engine.register_table_from_glob("events", &pattern).await?;
+    assert_eq!((info.file_count, info.listing), (2, FileListing::MergedGlob));
+
+    let result = engine.execute_query("SELECT id, source FROM events ORDER BY id").await?;
+    let rows: Vec<_> = result.data.iter().map(|row| (row["id"].as_i64(), row["source"].as_str())).collect();
+    assert_eq!(rows, vec![(Some(1), None), (Some(2), None), (Some(3), Some("web")), (Some(4), Some("web")), (Some(5), Some("web"))]);
+
+    // A column must keep its type across the files that have it
+    engine
+        .execute_query(&format!("COPY (SELECT 'x' AS id) TO '{}/2024-03/part-0.parquet'", dir.path().display()))
+        .await?;
+    let error = engine.register_table_from_glob("events", &pattern).await.unwrap_err().to_string();
+    assert!(error.contains("column 'id'") && error.contains("2024-03/part-0.parquet"), "{}", error);
+    let csv = engine.register_table_from_glob("events", &format!("{}/*.csv", dir.path().display())).await;
+    assert!(csv.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_partitioned_directory() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    for (partition, ids) in [("dt=2024-01-01/region=eu", "id <= 2"), ("dt=2024-01-01/region=us", "id = 3"), ("dt=2024-01-02/region=eu", "id > 3")] {
+        let path = dir.path().join(partition).join("part-0.parquet");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        engine
+            .execute_query(&format!("COPY (SELECT * FROM test_table WHERE {}) TO '{}'", ids, path.display()))
+            .await?;
+    }
+    std::fs::write(dir.path().join("_SUCCESS"), "")?;
+
+    let root = dir.path().display().to_string();
+    let info = engine.register_partitioned("exports", &root, None).await?;
+    assert_eq!((info.file_count, info.partitions, info.format), (3, 3, DataFormat::Parquet));
+    assert_eq!(
+        info.partition_columns,
+        vec![("dt".to_string(), "Date32".to_string()), ("region".to_string(), "Utf8".to_string())]
+    );
+    let result = engine
+        .execute_query("SELECT region, COUNT(*) AS n FROM exports WHERE dt = DATE '2024-01-01' GROUP BY region ORDER BY region")
+        .await?;
+    assert_eq!((result.data[0]["region"].as_str(), result.data[0]["n"].as_i64()), (Some("eu"), Some(2)));
+    assert_eq!(result.data[1]["n"], 1);
+    let info = engine.register_partitioned("exports_by_root", &format!("{}/", root), None).await?;
+    assert_eq!(info.partitions, 3);
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM exports_by_root WHERE region = 'eu'").await?;
+    assert_eq!(result.data[0]["n"], 4);
+
+    // Filters on partition columns never open the files they rule out
+    std::fs::write(dir.path().join("dt=2024-01-02/region=eu/part-0.parquet"), "corrupt")?;
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM exports WHERE dt < DATE '2024-01-02'").await?;
+    assert_eq!(result.data[0]["total"], 60.0);
+    assert!(engine.execute_query("SELECT SUM(value) FROM exports WHERE region = 'eu'").await.is_err());
+
+    std::fs::create_dir(dir.path().join("misc"))?;
+    std::fs::copy(dir.path().join("dt=2024-01-01/region=us/part-0.parquet"), dir.path().join("misc/part-0.parquet"))?;
+    let error = engine.register_partitioned("exports", &root, None).await.unwrap_err().to_string();
+    assert!(error.contains("not a column=value directory"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_load_csv_with_rejects() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("orders.csv");
+    std::fs::write(&path, "id,zip,amount\n1,02134,9.5\n2,n/a,7\n3,10001\nx,94105,1.25\n")?;
+
+    let options = CsvIngestOptions {
+        column_types: [("id".to_string(), parse_type_name("INT64")?), ("zip".to_string(), parse_type_name("STRING")?)].into(),
+        bad_rows: BadRowPolicy::Reject("orders_rejects".to_string()),
+        ..Default::default()
+    };
+    let report = engine.load_csv("orders", &path, &options).await?;
+    assert_eq!((report.rows_loaded, report.rows_rejected), (2, 2));
+    assert_eq!(report.rejects_table.as_deref(), Some("orders_rejects"));
+    assert_eq!(report.columns[2], ("amount".to_string(), "Float64".to_string()));
+
+    let result = engine.execute_query("SELECT zip, amount FROM orders ORDER BY id").await?;
+    assert_eq!(result.data[0]["zip"], "02134");
+    assert_eq!(result.data[1]["amount"], 7.0);
+    let rejects = engine.execute_query("SELECT line, error FROM orders_rejects ORDER BY line").await?;
+    assert_eq!(rejects.data[0]["line"], 4);
+    assert!(rejects.data[1]["error"].as_str().unwrap().contains("cannot parse 'x' as Int64"));
+
+    let strict = engine.load_csv("strict", &path, &CsvIngestOptions::default()).await;
+    assert!(strict.unwrap_err().to_string().contains("line 4: expected 3 fields, found 2"));
+
+    Ok(())
+}
+
 // Helper functions to create test data
+#[tokio::test]
+async fn test_execute_query_to_parquet() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("exports").join("big.parquet");
+
+    let options = ParquetExportOptions { compression: "zstd(3)".to_string(), row_group_size: 2, ..Default::default() };
+    let report = engine.execute_query_to_parquet("SELECT * FROM test_table WHERE id > 1", &path, &options).await?;
+    assert_eq!((report.rows_written, report.format), (4, DataFormat::Parquet));
+    assert_eq!(report.bytes_written, std::fs::metadata(&path)?.len());
+
+    let file = std::fs::File::open(&path)?;
+    let reader = datafusion::parquet::file::reader::SerializedFileReader::new(file).unwrap();
+    let metadata = datafusion::parquet::file::reader::FileReader::metadata(&reader);
+    assert_eq!(metadata.num_row_groups(), 2);
+    // Files record the codec but not its level
+    assert!(matches!(metadata.row_group(0).column(0).compression(), datafusion::parquet::basic::Compression::ZSTD(_)));
+
+    engine.register_files("exported", path.to_str().unwrap(), None).await?;
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM exported").await?;
+    assert_eq!(result.data[0]["total"], 140.0);
+
+    let error = engine.execute_query_to_parquet("SELECT 1 AS x", &path, &ParquetExportOptions::default()).await.unwrap_err();
+    assert!(error.to_string().contains("already exists"), "{}", error);
+    assert!(engine.execute_query_to_parquet("SELECT * FROM missing", dir.path().join("x.parquet"), &options).await.is_err());
+    assert!(!dir.path().join("x.parquet").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_execute_query_to_csv_and_json() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+
+    let path = dir.path().join("values.tsv");
+    let options = CsvExportOptions { delimiter: b'\t', ..Default::default() };
+    let report = engine.execute_query_to_csv("SELECT id, value FROM test_table WHERE id <= 2 ORDER BY id", &path, &options).await?;
+    assert_eq!((report.rows_written, report.format), (2, DataFormat::Csv));
+    assert_eq!(std::fs::read_to_string(&path)?, "id\tvalue\n1\t10.0\n2\t20.0\n");
+
+    let path = dir.path().join("values.csv.gz");
+    let options = CsvExportOptions { has_header: false, gzip: true, ..Default::default() };
+    engine.execute_query_to_csv("SELECT id FROM test_table", &path, &options).await?;
+    let mut text = String::new();
+    std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(std::fs::File::open(&path)?), &mut text)?;
+    assert_eq!(text.lines().count(), 5);
+
+    let path = dir.path().join("values.jsonl");
+    let report = engine.execute_query_to_json("SELECT id FROM test_table WHERE id > 3 ORDER BY id", &path, &JsonExportOptions::default()).await?;
+    assert_eq!(report.rows_written, 2);
+    assert_eq!(std::fs::read_to_string(&path)?, "{\"id\":4}\n{\"id\":5}\n");
+    let existing = engine.execute_query_to_json("SELECT 1", &path, &JsonExportOptions::default()).await;
+    assert!(matches!(existing, Err(BlazeError::InvalidInput(_))), "{:?}", existing);
+    let options = CsvExportOptions { overwrite: true, ..Default::default() };
+    assert!(matches!(
+        engine.execute_query_to_csv("SELECT 1", dir.path(), &options).await,
+        Err(BlazeError::InvalidInput(_))
+    ));
+    // A failed export leaves no partial file behind
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_partitioned_parquet() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine
+        .execute_query(
+            "CREATE TABLE sales AS SELECT * FROM (VALUES (2024, 'Paris', 1.0), (2024, 'Paris', 2.0), \
+             (2024, 'a/b', 3.0), (2023, NULL, 4.0)) AS t(year, city, amount)",
+        )
+        .await?;
+    let dir = tempfile::tempdir()?;
+    let out = dir.path().join("sales");
+
+    let options = ParquetSinkOptions {
+        partition_by: vec!["year".to_string(), "city".to_string()],
+        ..Default::default()
+    };
+    let report = engine.write_table_to_parquet("sales", &out, &options).await?;
+    assert_eq!((report.rows_written, report.files.len()), (4, 3));
+    let partitions: Vec<_> = report.files.iter().map(|f| f.partition.as_str()).collect();
+    assert_eq!(
+        partitions,
+        vec!["year=2023/city=__HIVE_DEFAULT_PARTITION__", "year=2024/city=Paris", "year=2024/city=a%2Fb"]
+    );
+    assert!(out.join("year=2024/city=Paris/part-00000.parquet").exists());
+
+    // Partition columns live in the directory names, not the files. Globs
+    // skip `key=value` directories, as DataFusion does.
+    let pattern = format!("{}/*.parquet", out.display());
+    engine.register_files("sales_back", &pattern, None).await?;
+    let result = engine.execute_query("SELECT * FROM sales_back").await?;
+    assert_eq!(result.rows, 4);
+    assert_eq!(result.data[0].keys().collect::<Vec<_>>(), vec!["amount"]);
+
+    let error = engine.write_table_to_parquet("sales", &out, &options).await.unwrap_err().to_string();
+    assert!(error.contains("is not empty"), "{}", error);
+    let missing = ParquetSinkOptions {
+        partition_by: vec!["region".to_string()],
+        overwrite: true,
+        ..Default::default()
+    };
+    let error = engine.write_table_to_parquet("sales", &out, &missing).await.unwrap_err().to_string();
+    assert!(error.contains("Partition column 'region'"), "{}", error);
+
+    // Small size targets roll over to new files
+    let rolling = ParquetSinkOptions {
+        target_file_size_bytes: 1,
+        overwrite: true,
+        compression: "zstd(3)".to_string(),
+        ..Default::default()
+    };
+    let report = engine
+        .write_table_to_parquet("SELECT value AS n FROM generate_series(1, 20000)", &out, &rolling)
+        .await?;
+    assert!(report.files.len() >= 3, "{:?}", report.files);
+    assert_eq!(report.rows_written, 20000);
+    assert!(report.files[1].path.ends_with("part-00001.parquet"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unknown_names_suggest_closest() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.execute_query("CREATE TABLE events AS SELECT 1 AS user_id, 'click' AS kind").await?;
+    engine.execute_query("CREATE TABLE users AS SELECT 1 AS id").await?;
+
+    let error = engine.execute_query("SELECT * FROM evnts").await.unwrap_err().to_string();
+    assert!(error.ends_with("table 'datafusion.public.evnts' not found; did you mean 'events'?"), "{}", error);
+    let error = engine.execute_query("SELECT usr_id FROM events").await.unwrap_err().to_string();
+    assert!(error.ends_with("No field named usr_id; did you mean 'user_id'?"), "{}", error);
+    let error = engine.execute_query("SELECT e.knd FROM events e").await.unwrap_err().to_string();
+    assert!(error.ends_with("No field named e.knd; did you mean 'e.kind'?"), "{}", error);
+
+    // Without a close name the error is DataFusion's own
+    let error = engine.execute_query("SELECT * FROM payments").await.unwrap_err().to_string();
+    assert!(error.ends_with("table 'datafusion.public.payments' not found"), "{}", error);
+    let error = engine.execute_query("SELECT amount FROM events").await.unwrap_err().to_string();
+    assert!(error.contains("Valid fields are"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_time_zone() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    assert_eq!(engine.config().await.time_zone, "UTC");
+
+    let config = engine
+        .update_config(EngineConfigUpdate { time_zone: Some("America/New_York".to_string()), ..Default::default() })
+        .await?;
+    assert_eq!(config.time_zone, "America/New_York");
+    let result = engine
+        .execute_query("SELECT CAST('2024-03-01 09:00:00' AS TIMESTAMPTZ) AS ts, CAST(EXTRACT(HOUR FROM CAST('2024-03-01 09:00:00' AS TIMESTAMPTZ)) AS BIGINT) AS h")
+        .await?;
+    assert_eq!(result.data[0]["ts"], "2024-03-01T09:00:00-05:00");
+    assert_eq!(result.data[0]["h"], 9);
+
+    // Zoned values are shown in the session zone
+    engine.execute_query("SET TIME ZONE '+05:30'").await?;
+    assert_eq!(engine.config().await.time_zone, "+05:30");
+    let result = engine
+        .execute_query("SELECT CAST('2024-03-01T00:00:00Z' AS TIMESTAMPTZ) AS ts, current_date AS d, now() AS n")
+        .await?;
+    assert_eq!(result.data[0]["ts"], "2024-03-01T05:30:00+05:30");
+    let date = result.data[0]["d"].as_str().unwrap();
+    assert_eq!(date.len(), 10, "{}", date);
+    assert!(result.data[0]["n"].as_str().unwrap().ends_with("+05:30"));
+
+    let invalid = engine.execute_query("SET TIME ZONE 'Mars/Olympus'").await;
+    assert!(matches!(invalid, Err(BlazeError::Config(_))), "{:?}", invalid);
+    assert_eq!(engine.config().await.time_zone, "+05:30");
+    assert!(EngineConfig::from_toml("time_zone = 'Mars/Olympus'").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sessions() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let session = engine.create_session().await;
+    let in_session = QueryOptions { session_id: Some(session.session_id.clone()), ..Default::default() };
+    let zoned = "SELECT CAST('2024-03-01T00:00:00Z' AS TIMESTAMPTZ) AS ts";
+
+    engine.execute_query_with_options("SET TIME ZONE 'Asia/Tokyo'", &in_session).await?;
+    engine.execute_query_with_options("SET max_result_rows = 2", &in_session).await?;
+    engine.execute_query_with_options("set batch_size to 1", &in_session).await?;
+    let settings = engine.get_session(&session.session_id).await.unwrap().settings;
+    assert_eq!((settings.time_zone.as_deref(), settings.max_result_rows, settings.batch_size), (Some("Asia/Tokyo"), Some(2), Some(1)));
+
+    // Settings apply to the session's statements only
+    let result = engine.execute_query_with_options(zoned, &in_session).await?;
+    assert_eq!(result.data[0]["ts"], "2024-03-01T09:00:00+09:00");
+    assert_eq!(engine.execute_query(zoned).await?.data[0]["ts"], "2024-03-01T00:00:00Z");
+    assert_eq!(engine.config().await.time_zone, "UTC");
+    let limited = engine.execute_query_with_options("SELECT * FROM test_table", &in_session).await;
+    assert!(matches!(limited, Err(BlazeError::InvalidInput(_))), "{:?}", limited);
+    assert_eq!(engine.execute_query("SELECT * FROM test_table").await?.rows, 5);
+
+    // Tables are shared, and DEFAULT goes back to the engine's setting
+    engine.execute_query_with_options("CREATE TABLE made_in_session AS SELECT 1 AS n", &in_session).await?;
+    assert_eq!(engine.execute_query("SELECT n FROM made_in_session").await?.rows, 1);
+    engine.execute_query_with_options("SET max_result_rows = DEFAULT", &in_session).await?;
+    assert_eq!(engine.execute_query_with_options("SELECT * FROM test_table", &in_session).await?.rows, 5);
+
+    let global = engine.execute_query_with_options("SET datafusion.execution.batch_size = 1", &in_session).await;
+    assert!(matches!(global, Err(BlazeError::InvalidInput(_))), "{:?}", global);
+    assert_eq!(engine.list_sessions().await.len(), 1);
+    assert_eq!(engine.get_session(&session.session_id).await.unwrap().statements, 9);
+
+    assert!(engine.close_session(&session.session_id).await);
+    assert!(engine.execute_query_with_options("SELECT 1", &in_session).await.is_err());
+    assert!(!engine.close_session(&session.session_id).await);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_decimal_semantics() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine
+        .execute_query("CREATE TABLE prices AS SELECT CAST(price AS DECIMAL(10, 2)) AS price FROM (VALUES ('19.99'), ('0.01'), ('80.00')) AS t(price)")
+        .await?;
+
+    // Derived rules keep DataFusion's result types and exact values
+    let result = engine.execute_query("SELECT SUM(price) AS total, MAX(price) * 3 AS tripled FROM prices").await?;
+    assert_eq!(result.data[0]["total"], "100.00");
+    assert_eq!(result.data[0]["tripled"], "240.00");
+    let result = engine
+        .execute_query("SELECT price > 10 AS high, SUM(price) AS total FROM prices GROUP BY price > 10 HAVING SUM(price) > 1 ORDER BY total")
+        .await?;
+    assert_eq!(result.rows, 1);
+    assert_eq!(result.data[0]["total"], "99.99");
+
+    // Values too wide for their type fail instead of overflowing silently
+    let big = "CAST('99999999999999999999999999999.999999999' AS DECIMAL(38, 9))";
+    let overflow = engine.execute_query(&format!("SELECT {big} * 10 AS x")).await;
+    assert!(overflow.unwrap_err().to_string().contains("Decimal overflow"));
+
+    let config = engine
+        .update_config(EngineConfigUpdate { decimal_overflow: Some(DecimalOverflow::Saturate), ..Default::default() })
+        .await?;
+    assert_eq!(config.decimal_overflow, DecimalOverflow::Saturate);
+    let result = engine.execute_query(&format!("SELECT {big} * 10 AS x, CAST(123.456 AS DECIMAL(4, 2)) AS y")).await?;
+    assert_eq!(result.data[0]["x"], "99999999999999999999999999999.999999999");
+    assert_eq!(result.data[0]["y"], "99.99");
+
+    engine
+        .update_config(EngineConfigUpdate { decimal_overflow: Some(DecimalOverflow::Promote), ..Default::default() })
+        .await?;
+    let result = engine.execute_query(&format!("SELECT {big} * 10 AS x, arrow_typeof({big} * 10) AS t")).await?;
+    assert_eq!(result.data[0]["x"], "999999999999999999999999999999.999999990");
+    assert_eq!(result.data[0]["t"], "Decimal256(59, 9)");
+
+    // BigQuery rules: NUMERIC is DECIMAL(38, 9) and results are rounded to it
+    engine
+        .update_config(EngineConfigUpdate {
+            decimal_rules: Some(DecimalRules::BigQuery),
+            decimal_overflow: Some(DecimalOverflow::Error),
+            ..Default::default()
+        })
+        .await?;
+    let result = engine
+        .execute_query("SELECT NUMERIC '1' / 3 AS third, AVG(price) AS mean, arrow_typeof(AVG(price)) AS t, CAST(1 AS BIGNUMERIC) AS big FROM prices")
+        .await?;
+    assert_eq!(result.data[0]["third"], "0.333333333");
+    assert_eq!(result.data[0]["mean"], "33.333333333");
+    assert_eq!(result.data[0]["t"], "Decimal128(38, 9)");
+    assert_eq!(result.data[0]["big"], "1.00000000000000000000000000000000000000");
+
+    let invalid = EngineConfig::from_toml("decimal_overflow = 'wrap'").unwrap_err().to_string();
+    assert!(invalid.contains("unknown variant `wrap`"), "{}", invalid);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_explain_graph() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+
+    let graph = engine
+        .explain_graph("SELECT value > 20 AS high, COUNT(*) AS n FROM test_table WHERE id > 1 GROUP BY value > 20 ORDER BY n")
+        .await?;
+    assert!(graph.node_count() >= 3, "{:#?}", graph.root);
+    assert_eq!(graph.root.id, 0);
+    assert!(graph.dot.starts_with("digraph plan {"));
+    assert!(graph.dot.contains("n1 -> n0;"), "{}", graph.dot);
+
+    // The scan of an in-memory table knows its row count exactly
+    let mut leaf = &graph.root;
+    while let Some(child) = leaf.children.first() {
+        leaf = child;
+    }
+    assert_eq!(leaf.name, "MemoryExec");
+    assert!(leaf.stats_exact);
+    assert_eq!(leaf.estimated_rows, Some(5));
+
+    let json = serde_json::to_value(&graph)?;
+    assert!(json["root"]["children"].is_array());
+
+    // Planning only: statements are refused and nothing runs
+    assert!(engine.explain_graph("CREATE TABLE other AS SELECT 1").await.is_err());
+    assert!(!engine.list_tables().await?.contains(&"other".to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_explain_analyze() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let sql = "SELECT id, value FROM test_table WHERE id > 1 ORDER BY value DESC";
+
+    let planned = engine.explain(sql, false).await?;
+    assert!(planned.root.metrics.is_none());
+    assert_eq!(planned.execution_time_ms, None);
+
+    // Running the query records what every operator did
+    let analyzed = engine.explain(sql, true).await?;
+    assert!(analyzed.execution_time_ms.is_some());
+    let root = analyzed.root.metrics.as_ref().expect("the root ran");
+    assert_eq!(root.output_rows, Some(4));
+    assert!(root.elapsed_compute_ns.is_some());
+    assert!(analyzed.dot.contains("actual rows: 4"), "{}", analyzed.dot);
+
+    let json = serde_json::to_value(&analyzed)?;
+    assert_eq!(json["root"]["metrics"]["output_rows"], 4);
+
+    assert!(engine.explain("DROP TABLE test_table", true).await.is_err());
+    assert!(engine.list_tables().await?.contains(&"test_table".to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_query_cpu_profile() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(200_000).await?).await?;
+    let dir = tempfile::tempdir()?;
+    let options = QueryOptions { profile: true, profile_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+    let sql = "SELECT category, COUNT(*) AS n, SUM(value) AS total FROM events GROUP BY category";
+
+    let result = engine.execute_query_with_options(sql, &options).await;
+    if cfg!(not(feature = "profiling")) {
+        assert!(result.unwrap_err().to_string().contains("`profiling` feature"));
+        return Ok(());
+    }
+
+    let profile = result?.profile.expect("a profile was requested");
+    assert!(profile.pprof_path.starts_with(dir.path()));
+    assert!(std::fs::metadata(&profile.pprof_path)?.len() > 0);
+    assert!(std::fs::read_to_string(&profile.flamegraph_path)?.contains("<svg"));
+
+    // Queries without the option are not profiled
+    assert!(engine.execute_query(sql).await?.profile.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_result_pages() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(250).await?).await?;
+    let options = QueryOptions { max_rows: Some(100), ..Default::default() };
+
+    let result = engine.execute_query_with_options("SELECT id FROM events ORDER BY id", &options).await?;
+    assert_eq!((result.rows, result.data.len()), (250, 100));
+    let mut ids: Vec<_> = result.data.iter().map(|row| row["id"].as_i64().unwrap()).collect();
+
+    // Pages are read from the kept result, not by running the query again
+    engine.execute_query("DROP TABLE events").await?;
+    let mut page_token = result.page_token;
+    let mut starts = Vec::new();
+    while let Some(token) = page_token {
+        let page = engine.fetch_results(&result.query_id, Some(&token), 100).await?;
+        assert_eq!(page.total_rows, 250);
+        starts.push(page.start_row);
+        ids.extend(page.data.iter().map(|row| row["id"].as_i64().unwrap()));
+        page_token = page.page_token;
+    }
+    assert_eq!(starts, vec![100, 200]);
+    assert_eq!(ids, (0..250).collect::<Vec<i64>>());
+
+    // Without a token the first page is read again
+    let first = engine.fetch_results(&result.query_id, None, 10).await?;
+    assert_eq!((first.start_row, first.data.len()), (0, 10));
+    assert!(first.page_token.is_some());
+
+    assert!(matches!(engine.fetch_results(&result.query_id, Some("job_0:10"), 10).await, Err(BlazeError::InvalidInput(_))));
+    assert!(matches!(engine.fetch_results("missing", None, 10).await, Err(BlazeError::InvalidInput(_))));
+
+    // A result fitting in one page has no token
+    let small = engine.execute_query_with_options("SELECT 1 AS one", &options).await?;
+    assert_eq!((small.data.len(), small.page_token), (1, None));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prepared_statements() -> BlazeResult<()> {
+    use serde_json::json;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(100).await?).await?;
+
+    let statement = engine.prepare("SELECT id FROM events WHERE id >= $1 AND category <> $2 ORDER BY id").await?;
+    assert!(statement.handle.starts_with("stmt_"), "{}", statement.handle);
+    let types: Vec<_> = statement.parameters.iter().map(|p| (p.name.as_str(), p.data_type.as_deref())).collect();
+    assert_eq!(types, vec![("$1", Some("Int64")), ("$2", Some("Utf8"))]);
+
+    let result = engine.execute_prepared(&statement.handle, &[json!(95), json!("none")]).await?;
+    let ids: Vec<_> = result.data.iter().map(|row| row["id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![95, 96, 97, 98, 99]);
+
+    // Values are cast to the parameter's type and bound as literals
+    let result = engine.execute_prepared(&statement.handle, &[json!("98"), json!("x' OR '1'='1")]).await?;
+    assert_eq!(result.rows, 2);
+    let wrong_type = engine.execute_prepared(&statement.handle, &[json!("ninety"), json!("none")]).await;
+    assert!(matches!(wrong_type, Err(BlazeError::InvalidInput(_))));
+    let too_few = engine.execute_prepared(&statement.handle, &[json!(1)]).await;
+    assert!(matches!(too_few, Err(BlazeError::InvalidInput(_))));
+
+    // Executions read the latest rows of the table
+    engine.execute_query("INSERT INTO events VALUES (100, 1.0, 'new')").await?;
+    let result = engine.execute_prepared(&statement.handle, &[json!(99), json!("none")]).await?;
+    assert_eq!(result.rows, 2);
+
+    assert_eq!(engine.list_prepared().await[0].executions, 3);
+    assert!(matches!(engine.prepare("DROP TABLE events").await, Err(BlazeError::InvalidInput(_))));
+    assert!(engine.deallocate_prepared(&statement.handle).await);
+    assert!(engine.list_prepared().await.is_empty());
+    assert!(matches!(engine.execute_prepared(&statement.handle, &[]).await, Err(BlazeError::InvalidInput(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_plan_cache() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(100).await?).await?;
+    let sql = "SELECT category, MAX(id) AS last FROM events GROUP BY category ORDER BY category";
+
+    let first = engine.execute_query(sql).await?;
+    let second = engine.execute_query(sql).await?;
+    assert_eq!(second.data, first.data);
+    let stats = engine.get_stats().await.plan_cache;
+    assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+
+    // Cached plans read the latest rows of their tables
+    engine.execute_query("INSERT INTO events VALUES (100, 1.0, 'new')").await?;
+    let third = engine.execute_query(sql).await?;
+    assert_eq!(third.rows, first.rows + 1);
+    assert_eq!(engine.get_stats().await.plan_cache.hits, 2);
+
+    // A table replaced by one with other columns is planned again
+    engine.execute_query("DROP TABLE events").await?;
+    engine.execute_query("CREATE TABLE events AS SELECT 'x' AS category, 7 AS id").await?;
+    let replaced = engine.execute_query(sql).await?;
+    assert_eq!(replaced.data.len(), 1);
+    assert_eq!(replaced.data[0]["last"], 7);
+    assert_eq!(engine.get_stats().await.plan_cache.misses, 2);
+
+    // Changing settings clears the cache
+    engine.execute_query("SET datafusion.execution.batch_size = 1024").await?;
+    assert_eq!(engine.get_stats().await.plan_cache.entries, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_result_cache() -> BlazeResult<()> {
+    let config = EngineConfig { result_cache_max_bytes: 64 * 1024 * 1024, ..Default::default() };
+    let engine = BlazeQueryEngine::with_config(config).await?;
+    engine.register_table("events", create_categorized_test_data(500).await?).await?;
+    let sql = "SELECT category, COUNT(*) AS n FROM events GROUP BY category ORDER BY category";
+
+    let first = engine.execute_query(sql).await?;
+    assert!(!first.cache_hit);
+    let second = engine.execute_query("select category, count(*) as n\nfrom events group by category order by category").await?;
+    assert!(second.cache_hit);
+    assert_eq!(second.data, first.data);
+
+    // A write to the table makes the cached result stale
+    engine.execute_query("INSERT INTO events VALUES (500, 1.0, 'new')").await?;
+    let third = engine.execute_query(sql).await?;
+    assert!(!third.cache_hit);
+    assert_eq!(third.rows, first.rows + 1);
+    assert!(engine.execute_query(sql).await?.cache_hit);
+
+    // So does registering the table again
+    engine.register_table("events", create_categorized_test_data(10).await?).await?;
+    assert!(!engine.execute_query(sql).await?.cache_hit);
+
+    // Volatile functions and views are never served from the cache
+    engine.execute_query("CREATE VIEW recent AS SELECT * FROM events WHERE id > 5").await?;
+    for sql in ["SELECT id, random() AS r FROM events", "SELECT COUNT(*) AS n FROM recent"] {
+        engine.execute_query(sql).await?;
+        assert!(!engine.execute_query(sql).await?.cache_hit, "{}", sql);
+    }
+
+    let stats = engine.get_stats().await.result_cache;
+    assert_eq!((stats.hits, stats.misses), (2, 3));
+    assert!(stats.entries >= 1 && stats.cached_bytes > 0);
+
+    // Changing the configuration clears the cache
+    engine.update_config(EngineConfigUpdate { result_cache_ttl_secs: Some(60), ..Default::default() }).await?;
+    assert_eq!(engine.get_stats().await.result_cache.entries, 0);
+    assert!(!engine.execute_query(sql).await?.cache_hit);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cached_result_tables() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(1_000).await?).await?;
+    let options = QueryOptions { cache_result: true, ..Default::default() };
+
+    let result = engine
+        .execute_query_with_options("SELECT category, COUNT(*) AS n FROM events GROUP BY category", &options)
+        .await?;
+    let table = result.result_table.expect("the result was cached");
+    assert!(table.starts_with("_results.job_"), "{}", table);
+
+    // The cached rows are read back without the source table
+    engine.execute_query("DROP TABLE events").await?;
+    let page = engine.execute_query(&format!("SELECT * FROM {} ORDER BY category LIMIT 2 OFFSET 1", table)).await?;
+    assert_eq!(page.rows, 2);
+    let mut categories: Vec<_> = result.data.iter().map(|row| row["category"].clone()).collect();
+    categories.sort_by_key(|category| category.to_string());
+    assert_eq!(page.data[0]["category"], categories[1]);
+    let total = engine.execute_query(&format!("SELECT SUM(n) AS total FROM {}", table)).await?;
+    assert_eq!(total.data[0]["total"], 1_000);
+
+    // Hidden from the table list, listed on their own, and not kept for
+    // statements or queries without the option
+    assert!(!engine.list_tables().await?.iter().any(|name| name.starts_with("job_")));
+    let tables = engine.list_result_tables().await;
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].reference, table);
+    assert_eq!(tables[0].rows, result.rows);
+    assert_eq!((tables[0].expires_at - tables[0].created_at).num_hours(), 24);
+    assert!(engine.execute_query("SELECT 1 AS one").await?.result_table.is_none());
+    assert!(engine.execute_query_with_options("CREATE TABLE t AS SELECT 1 AS one", &options).await?.result_table.is_none());
+
+    engine.drop_result_table(&table).await?;
+    assert!(engine.list_result_tables().await.is_empty());
+    assert!(engine.execute_query(&format!("SELECT * FROM {}", table)).await.is_err());
+    assert!(engine.drop_result_table(&table).await.is_err());
+
+    // Expired tables are dropped before the next query runs
+    let short = BlazeQueryEngine::with_config(EngineConfig { result_table_ttl_secs: 1, ..Default::default() }).await?;
+    let table = short.execute_query_with_options("SELECT 1 AS one", &options).await?.result_table.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1_100)).await;
+    assert!(short.execute_query(&format!("SELECT * FROM {}", table)).await.is_err());
+    assert!(short.list_result_tables().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_engine_state_snapshot_and_restore() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("training", create_simple_test_data().await?).await?;
+    engine.register_table("events", create_categorized_test_data(2_500).await?).await?;
+    engine.execute_query("CREATE TABLE empty_ids AS SELECT id FROM training WHERE id < 0").await?;
+    engine.execute_query("CREATE VIEW big_values AS SELECT id, value FROM training WHERE value > 20").await?;
+    engine.execute_query("CREATE VIEW top_value AS SELECT MAX(value) AS highest FROM big_values").await?;
+    engine.create_materialized_view("per_category", "SELECT category, COUNT(*) AS n FROM events GROUP BY category").await?;
+    engine.create_search_index("category_index", "events", Some(vec!["category".to_string()])).await?;
+    engine.execute_query(
+        "CREATE MODEL value_model OPTIONS(model_type='linear_reg', input_label_cols=['value']) AS \
+         SELECT id, value FROM training"
+    ).await?;
+    engine.create_resource_group(ResourceGroup {
+        name: "etl".to_string(),
+        cpu_share: 0.5,
+        memory_fraction: 0.5,
+        max_concurrency: 2,
+    }).await?;
+    let queries = [
+        "SELECT id, value FROM training ORDER BY id",
+        "SELECT highest FROM top_value",
+        "SELECT category, n FROM per_category ORDER BY category",
+        "SELECT id, predicted_value FROM ML.PREDICT(MODEL value_model, TABLE training) ORDER BY id",
+        "SELECT COUNT(*) AS n FROM empty_ids",
+    ];
+    let mut expected = Vec::new();
+    for sql in queries {
+        expected.push(engine.execute_query(sql).await?.data);
+    }
+
+    let dir = tempfile::tempdir()?;
+    let saved = engine.snapshot_to(dir.path()).await?;
+    assert_eq!(saved.tables, 3);
+    assert_eq!(saved.total_rows, 2_505);
+    assert_eq!(saved.views, 3);
+    assert_eq!(saved.indexes, 1);
+    assert_eq!(saved.models, 1);
+    assert!(dir.path().join("manifest.json").is_file());
+
+    let restored = BlazeQueryEngine::new().await?;
+    let info = restored.restore_from(dir.path()).await?;
+    assert_eq!(info.saved_at, saved.saved_at);
+    for (sql, expected) in queries.iter().zip(&expected) {
+        assert_eq!(&restored.execute_query(sql).await?.data, expected, "{}", sql);
+    }
+    assert_eq!(restored.list_search_indexes().await[0].name, "category_index");
+    assert_eq!(restored.list_resource_groups().await[0].group.name, "etl");
+    let before = engine.get_stats().await;
+    let after = restored.get_stats().await;
+    assert_eq!(after.total_queries, before.total_queries + queries.len() as u64);
+    assert_eq!(after.registered_tables, saved.tables);
+    assert_eq!(restored.query_stats().await.len(), engine.query_stats().await.len());
+
+    // Materialized views keep being maintained after a restore
+    restored.append_to_table("events", create_categorized_test_data(10).await?).await?;
+    let total = restored.execute_query("SELECT SUM(n) AS total FROM per_category").await?;
+    assert_eq!(total.data[0]["total"], 2_510);
+
+    // Saving again replaces the earlier state; other directories and
+    // engines that already have tables are refused
+    restored.snapshot_to(dir.path()).await?;
+    assert!(engine.restore_from(dir.path()).await.unwrap_err().to_string().contains("without tables"));
+    let other = tempfile::tempdir()?;
+    std::fs::write(other.path().join("notes.txt"), "keep")?;
+    assert!(engine.snapshot_to(other.path()).await.is_err());
+    assert!(BlazeQueryEngine::new().await?.restore_from(other.path()).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_then_cancels() -> BlazeResult<()> {
+    use futures::StreamExt;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("training", create_simple_test_data().await?).await?;
+
+    // An open stream counts as a running query until it is dropped
+    let mut stuck = engine.execute_stream("SELECT id FROM training ORDER BY id").await?;
+    let finishing = engine.execute_stream("SELECT value FROM training").await?;
+    let finisher = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        drop(finishing);
+    });
+
+    let dir = tempfile::tempdir()?;
+    let report = engine
+        .shutdown(&ShutdownOptions { drain_timeout_ms: 500, state_dir: Some(dir.path().to_path_buf()) })
+        .await?;
+    finisher.await.unwrap();
+    assert_eq!(report.drained_queries, 1);
+    assert_eq!(report.interrupted.len(), 1);
+    assert_eq!(report.interrupted[0].sql, "SELECT id FROM training ORDER BY id");
+    assert!(report.interrupted[0].running_ms >= 500);
+    assert_eq!(report.state.as_ref().map(|state| state.tables), Some(1));
+
+    let error = stuck.next().await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("cancelled"), "{}", error);
+    assert!(matches!(engine.execute_query("SELECT 1").await, Err(BlazeError::ShuttingDown)));
+    assert!(engine.shutdown(&ShutdownOptions::default()).await.is_err());
+
+    // The saved state brings the tables back in a new engine
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(dir.path()).await?;
+    assert_eq!(restored.execute_query("SELECT COUNT(*) AS n FROM training").await?.data[0]["n"], 5);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_cancel_query() -> BlazeResult<()> {
+    use futures::StreamExt;
+
+    let config = EngineConfig { cpu_cores: 2, ..EngineConfig::default() };
+    let engine = Arc::new(BlazeQueryEngine::with_config(config).await?);
+    engine.register_table("events", create_categorized_test_data(2_000).await?).await?;
+
+    let quick = engine.execute_query("SELECT COUNT(*) AS n FROM events").await?;
+    assert!(quick.query_id.starts_with("query_"));
+    let named = QueryOptions { query_id: Some("daily".to_string()), ..Default::default() };
+    assert_eq!(engine.execute_query_with_options("SELECT 1", &named).await?.query_id, "daily");
+
+    // Eight billion rows would take minutes; the filter keeps DataFusion
+    // from answering the count from statistics
+    let runaway = "SELECT COUNT(*) AS n FROM events a, events b, events c WHERE a.id + b.id + c.id >= 0";
+    let options = QueryOptions { query_id: Some("runaway".to_string()), ..Default::default() };
+    let running = {
+        let engine = engine.clone();
+        tokio::spawn(async move { engine.execute_query_with_options(runaway, &options).await })
+    };
+    while engine.running_queries().await.is_empty() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let queries = engine.running_queries().await;
+    assert_eq!((queries[0].query_id.as_str(), queries[0].sql.as_str()), ("runaway", runaway));
+    let duplicate = QueryOptions { query_id: Some("runaway".to_string()), ..Default::default() };
+    assert!(matches!(engine.execute_query_with_options("SELECT 1", &duplicate).await, Err(BlazeError::InvalidInput(_))));
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    engine.cancel_query("runaway").await?;
+    let outcome = tokio::time::timeout(std::time::Duration::from_secs(10), running).await.expect("query kept running");
+    match outcome.unwrap() {
+        Err(BlazeError::Cancelled(reason)) => assert_eq!(reason, "query runaway was cancelled"),
+        other => panic!("expected a cancellation, got {:?}", other.map(|result| result.rows)),
+    }
+    assert!(engine.running_queries().await.is_empty());
+    assert!(matches!(engine.cancel_query("runaway").await, Err(BlazeError::InvalidInput(_))));
+
+    // A cancelled stream ends with the error
+    let mut stream = engine.execute_stream("SELECT id FROM events").await?;
+    let query_id = engine.running_queries().await[0].query_id.clone();
+    engine.cancel_query(&query_id).await?;
+    let error = stream.next().await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("cancelled"), "{}", error);
+    drop(stream);
+
+    // Other queries are unaffected
+    assert_eq!(engine.execute_query("SELECT COUNT(*) AS n FROM events").await?.data[0]["n"], 2_000);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_query_timeout() -> BlazeResult<()> {
+    let config = EngineConfig { cpu_cores: 2, query_timeout_ms: 200, ..EngineConfig::default() };
+    let engine = BlazeQueryEngine::with_config(config).await?;
+    engine.register_table("events", create_categorized_test_data(2_000).await?).await?;
+    let runaway = "SELECT COUNT(*) AS n FROM events a, events b, events c WHERE a.id + b.id + c.id >= 0";
+
+    let started = std::time::Instant::now();
+    let error = engine.execute_query(runaway).await.unwrap_err();
+    assert!(matches!(error, BlazeError::Timeout { timeout_ms: 200 }), "{}", error);
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(engine.running_queries().await.is_empty());
+    assert!(matches!(engine.execute_query_batches(runaway).await, Err(BlazeError::Timeout { timeout_ms: 200 })));
+
+    // A query's own timeout overrides the engine's
+    let options = QueryOptions { timeout_ms: Some(50), ..Default::default() };
+    assert!(matches!(
+        engine.execute_query_with_options(runaway, &options).await,
+        Err(BlazeError::Timeout { timeout_ms: 50 })
+    ));
+    assert_eq!(engine.execute_query("SELECT COUNT(*) AS n FROM events").await?.data[0]["n"], 2_000);
+
+    let config = engine.update_config(EngineConfigUpdate { query_timeout_ms: Some(0), ..Default::default() }).await?;
+    assert_eq!(config.query_timeout_ms, 0);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_admission_control() -> BlazeResult<()> {
+    let config = EngineConfig {
+        cpu_cores: 2,
+        max_concurrent_queries: 1,
+        max_queued_queries: 1,
+        queue_timeout_ms: 100,
+        ..EngineConfig::default()
+    };
+    let engine = Arc::new(BlazeQueryEngine::with_config(config).await?);
+    engine.register_table("events", create_categorized_test_data(2_000).await?).await?;
+    let runaway = "SELECT COUNT(*) AS n FROM events a, events b, events c WHERE a.id + b.id + c.id >= 0";
+    let wait_for = |running: usize, queued: usize| {
+        let engine = engine.clone();
+        async move {
+            loop {
+                let admission = engine.get_stats().await.admission;
+                if (admission.running_queries, admission.queued_queries) == (running, queued) {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+    };
+
+    let options = QueryOptions { query_id: Some("blocker".to_string()), ..Default::default() };
+    let blocker = {
+        let engine = engine.clone();
+        tokio::spawn(async move { engine.execute_query_with_options(runaway, &options).await })
+    };
+    wait_for(1, 0).await;
+
+    // A query waits for the running one, then gives up
+    let timed_out = engine.execute_query("SELECT 1").await;
+    assert!(matches!(timed_out, Err(BlazeError::Overloaded(_))), "{:?}", timed_out.map(|r| r.rows));
+
+    // Without a queue timeout it waits until admitted; beyond the queue's
+    // capacity queries are rejected at once
+    engine.update_config(EngineConfigUpdate { queue_timeout_ms: Some(0), ..Default::default() }).await?;
+    let queued = {
+        let engine = engine.clone();
+        tokio::spawn(async move { engine.execute_query("SELECT COUNT(*) AS n FROM events").await })
+    };
+    wait_for(1, 1).await;
+    let rejected = engine.execute_query("SELECT 1").await;
+    assert!(matches!(rejected, Err(BlazeError::Overloaded(_))), "{:?}", rejected.map(|r| r.rows));
+
+    engine.cancel_query("blocker").await?;
+    assert!(matches!(blocker.await.unwrap(), Err(BlazeError::Cancelled(_))));
+    assert_eq!(queued.await.unwrap()?.data[0]["n"], 2_000);
+
+    let admission = engine.get_stats().await.admission;
+    assert_eq!((admission.running_queries, admission.queued_queries), (0, 0));
+    assert_eq!((admission.admitted_queries, admission.delayed_queries), (2, 1));
+    assert_eq!((admission.rejected_queries, admission.timed_out_queries), (1, 1));
+
+    // Lifting the limit admits every query at once
+    engine.update_config(EngineConfigUpdate { max_concurrent_queries: Some(0), ..Default::default() }).await?;
+    assert_eq!(engine.execute_query("SELECT 1 AS one").await?.rows, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_query_priorities() -> BlazeResult<()> {
+    let config = EngineConfig { cpu_cores: 2, max_concurrent_queries: 1, queue_timeout_ms: 0, ..EngineConfig::default() };
+    let engine = Arc::new(BlazeQueryEngine::with_config(config).await?);
+    engine.register_table("events", create_categorized_test_data(2_000).await?).await?;
+    let runaway = "SELECT COUNT(*) AS n FROM events a, events b, events c WHERE a.id + b.id + c.id >= 0";
+    let spawn = |sql: &'static str, query_id: &str, priority: QueryPriority| {
+        let engine = engine.clone();
+        let options = QueryOptions { query_id: Some(query_id.to_string()), priority, ..Default::default() };
+        tokio::spawn(async move { engine.execute_query_with_options(sql, &options).await })
+    };
+    let wait_for_queued = |queued: usize| {
+        let engine = engine.clone();
+        async move {
+            while engine.get_stats().await.admission.queued_queries != queued {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+    };
+
+    let blocker = spawn(runaway, "blocker", QueryPriority::Batch);
+    while engine.get_stats().await.admission.running_queries == 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let batch = spawn(runaway, "batch", QueryPriority::Batch);
+    wait_for_queued(1).await;
+    let interactive = spawn("SELECT COUNT(*) AS n FROM events", "interactive", QueryPriority::Interactive);
+    wait_for_queued(2).await;
+    assert_eq!(engine.get_stats().await.admission.queued_batch_queries, 1);
+
+    // The interactive query arrived last but runs before the batch query,
+    // which would otherwise hold the only slot
+    engine.cancel_query("blocker").await?;
+    assert!(matches!(blocker.await.unwrap(), Err(BlazeError::Cancelled(_))));
+    let interactive = tokio::time::timeout(std::time::Duration::from_secs(10), interactive)
+        .await
+        .expect("the interactive query waited behind the batch query");
+    assert_eq!(interactive.unwrap()?.data[0]["n"], 2_000);
+
+    engine.cancel_query("batch").await?;
+    assert!(matches!(batch.await.unwrap(), Err(BlazeError::Cancelled(_))));
+    assert!(QueryPriority::parse("urgent").is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_slot_scheduling() -> BlazeResult<()> {
+    let engine = Arc::new(BlazeQueryEngine::with_config(EngineConfig { slots: 4, ..EngineConfig::default() }).await?);
+    engine.register_table("events", create_categorized_test_data(2_000).await?).await?;
+    let count = "SELECT COUNT(*) AS n FROM events WHERE value > 0";
+
+    // A query alone gets the whole pool
+    assert_eq!(engine.execute_query(count).await?.data[0]["n"], 2_000);
+    let slots = engine.get_stats().await.slots;
+    assert_eq!((slots.total_slots, slots.slots_in_use, slots.scheduled_queries), (4, 0, 1));
+    assert_eq!(slots.avg_slots_per_query, 4.0);
+
+    // Queries starting meanwhile get what the running ones leave
+    let runaway = "SELECT COUNT(*) AS n FROM events a, events b, events c WHERE a.id + b.id + c.id >= 0";
+    let options = QueryOptions { query_id: Some("runaway".to_string()), ..Default::default() };
+    let running = {
+        let engine = engine.clone();
+        tokio::spawn(async move { engine.execute_query_with_options(runaway, &options).await })
+    };
+    while engine.get_stats().await.slots.slots_in_use == 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(engine.execute_query(count).await?.data[0]["n"], 2_000);
+    let slots = engine.get_stats().await.slots;
+    assert_eq!((slots.slots_in_use, slots.peak_slots_in_use, slots.scheduled_queries), (4, 5, 3));
+    engine.cancel_query("runaway").await?;
+    assert!(running.await.unwrap().is_err());
+    assert_eq!(engine.get_stats().await.slots.slots_in_use, 0);
+
+    let config = engine.update_config(EngineConfigUpdate { slots: Some(0), ..Default::default() }).await?;
+    assert_eq!(config.slots, 0);
+    engine.execute_query(count).await?;
+    assert_eq!(engine.get_stats().await.slots.scheduled_queries, 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_query_history() -> BlazeResult<()> {
+    let dir = tempfile::tempdir()?;
+    let config = EngineConfig { cpu_cores: 2, query_history_path: Some(dir.path().join("history.jsonl")), ..EngineConfig::default() };
+    let engine = BlazeQueryEngine::with_config(config.clone()).await?;
+    engine.register_table("events", create_categorized_test_data(2_000).await?).await?;
+
+    let session_id = engine.create_session().await.session_id;
+    let options = QueryOptions { session_id: Some(session_id.clone()), query_id: Some("counted".to_string()), ..Default::default() };
+    engine.execute_query_with_options("SELECT COUNT(*) AS n FROM events", &options).await?;
+    engine.execute_query("SELECT id FROM events WHERE value > 0").await?;
+    assert!(engine.execute_query("SELECT missing FROM events").await.is_err());
+    let runaway = "SELECT COUNT(*) AS n FROM events a, events b, events c WHERE a.id + b.id + c.id >= 0";
+    let options = QueryOptions { timeout_ms: Some(50), ..Default::default() };
+    assert!(engine.execute_query_with_options(runaway, &options).await.is_err());
+
+    let history = engine.get_query_history(&QueryHistoryFilter::default());
+    let statuses: Vec<_> = history.iter().map(|entry| entry.status).collect();
+    assert_eq!(statuses, [QueryStatus::TimedOut, QueryStatus::Failed, QueryStatus::Succeeded, QueryStatus::Succeeded]);
+    let counted = &history[3];
+    assert_eq!((counted.query_id.as_str(), counted.rows), ("counted", 1));
+    assert_eq!(counted.session_id.as_deref(), Some(session_id.as_str()));
+    assert!(counted.result_bytes > 0);
+    assert!(history[1].error.as_deref().unwrap().contains("missing"));
+    assert!(history[0].duration_ms >= 50);
+
+    let filter = QueryHistoryFilter { status: Some(QueryStatus::Succeeded), sql_contains: Some("where".to_string()), ..Default::default() };
+    let found = engine.get_query_history(&filter);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].sql, "SELECT id FROM events WHERE value > 0");
+    let filter = QueryHistoryFilter { session_id: Some(session_id), ..Default::default() };
+    assert_eq!(engine.get_query_history(&filter).len(), 1);
+    let filter = QueryHistoryFilter { since: Some(chrono::Utc::now()), ..Default::default() };
+    assert!(engine.get_query_history(&filter).is_empty());
+
+    // A new engine over the same file starts with the history
+    let restarted = BlazeQueryEngine::with_config(config).await?;
+    let filter = QueryHistoryFilter { limit: Some(2), ..Default::default() };
+    assert_eq!(restarted.get_query_history(&filter), history[..2]);
+
+    let config = engine.update_config(EngineConfigUpdate { query_history_size: Some(0), ..Default::default() }).await?;
+    assert_eq!(config.query_history_size, 0);
+    engine.execute_query("SELECT 1").await?;
+    assert!(engine.get_query_history(&QueryHistoryFilter::default()).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_estimate_cardinality() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(3_000).await?).await?;
+    engine.register_table("training", create_simple_test_data().await?).await?;
+
+    let scan = engine.estimate_cardinality("SELECT id, category FROM events").await?;
+    assert_eq!(scan.estimated_rows, Some(3_000));
+    assert!(scan.exact);
+
+    // Aggregates are bounded by their input; joins can multiply rows
+    let grouped = engine.estimate_cardinality("SELECT category, COUNT(*) AS n FROM events GROUP BY category").await?;
+    let rows = grouped.estimated_rows.expect("bounded by the scan");
+    assert!(rows <= 3_000);
+    assert!(!grouped.exact);
+    assert_eq!(grouped.max_stage_rows, Some(3_000));
+    assert_eq!(grouped.stages[0].parent, None);
+    assert!(grouped.stages.iter().skip(1).all(|stage| stage.parent.is_some()));
+
+    let joined = engine
+        .estimate_cardinality("SELECT e.category, t.value FROM events e CROSS JOIN training t")
+        .await?;
+    assert!(joined.max_stage_rows.unwrap() >= 3_000, "{:#?}", joined.stages);
+
+    // Only queries are estimated, and nothing runs
+    assert!(engine.estimate_cardinality("CREATE TABLE other AS SELECT 1").await.is_err());
+    assert_eq!(engine.get_stats().await.total_queries, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dry_run() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(3_000).await?).await?;
+
+    let narrow = engine.dry_run("SELECT id FROM events WHERE id > 10").await?;
+    assert!(narrow.bytes_exact);
+    assert!(narrow.total_bytes_processed >= 3_000 * 8, "{}", narrow.total_bytes_processed);
+    assert_eq!(narrow.tables.len(), 1);
+    assert_eq!((narrow.tables[0].table.as_str(), narrow.tables[0].columns.clone()), ("events", vec!["id".to_string()]));
+    assert!(narrow.optimized_plan.contains("TableScan: events"), "{}", narrow.optimized_plan);
+
+    // Only the columns read count
+    let wide = engine.dry_run("SELECT * FROM events").await?;
+    assert!(wide.total_bytes_processed > narrow.total_bytes_processed);
+    assert_eq!(wide.tables[0].columns, vec!["category", "id", "value"]);
+
+    // Nothing runs, and only queries are dry-run
+    assert!(engine.dry_run("DROP TABLE events").await.is_err());
+    assert_eq!(engine.get_stats().await.total_queries, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_result_serializers() -> BlazeResult<()> {
+    use bigquery_lite_engine::{ResultSerializer, SerializeContext};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    /// Tab-separated values, as an embedder would add them
+    struct TsvSerializer;
+
+    impl ResultSerializer for TsvSerializer {
+        fn name(&self) -> &str {
+            "tsv"
+        }
+
+        fn content_type(&self) -> &str {
+            "text/tab-separated-values"
+        }
+
+        fn serialize(&self, batches: &[RecordBatch], _context: &SerializeContext) -> BlazeResult<Vec<u8>> {
+            let mut output = String::new();
+            for batch in batches {
+                let ids = batch.column(0).as_any().downcast_ref::<datafusion::arrow::array::Int64Array>().unwrap();
+                for id in ids.iter().flatten() {
+                    output.push_str(&format!("{}\n", id));
+                }
+            }
+            Ok(output.into_bytes())
+        }
+    }
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("training", create_simple_test_data().await?).await?;
+    let sql = "SELECT id, value FROM training WHERE id <= 2 ORDER BY id";
+
+    let json = engine.execute_query_as(sql, "json").await?;
+    let rows: serde_json::Value = serde_json::from_slice(&json.data)?;
+    assert_eq!(rows, serde_json::to_value(engine.execute_query(sql).await?.data)?);
+    assert_eq!(json.rows, 2);
+
+    let csv = engine.execute_query_as(sql, "csv").await?;
+    assert_eq!(csv.content_type, "text/csv");
+    assert_eq!(String::from_utf8(csv.data).unwrap(), "id,value\n1,10.0\n2,20.0\n");
+
+    assert!(engine.execute_query_as(sql, "tsv").await.is_err());
+    engine.register_serializer(Arc::new(TsvSerializer)).await;
+    assert!(engine.list_serializers().await.contains(&"tsv".to_string()));
+    let tsv = engine.execute_query_as(sql, "TSV").await?;
+    assert_eq!(tsv.format, "tsv");
+    assert_eq!(tsv.data, b"1\n2\n");
+
+    Ok(())
+}
+
+/// A Flight service serving the path `orders` as two endpoints, each of
+/// which is also readable by its ticket
+mod flight_service {
+    use std::sync::Arc;
+
+    use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+    use arrow_flight::{
+        Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest,
+        HandshakeResponse, PollInfo, PutResult, SchemaResult, Ticket,
+    };
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+    use datafusion::arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions, StreamWriter};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use futures::stream::{self, BoxStream};
+    use futures::StreamExt;
+    use tonic::{Request, Response, Status, Streaming};
+
+    type FlightResult<T> = Result<Response<T>, Status>;
+
+    pub fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("order_id", DataType::Int64, false),
+            Field::new("customer", DataType::Utf8, false),
+        ]))
+    }
+
+    /// Rows of the part named by a ticket, 3 per part in batches of 2 and 1
+    fn part(ticket: &[u8]) -> Option<Vec<RecordBatch>> {
+        let first: i64 = match ticket {
+            b"part-0" => 1,
+            b"part-1" => 4,
+            _ => return None,
+        };
+        let batch = |ids: Vec<i64>| {
+            let customers: Vec<_> = ids.iter().map(|id| format!("customer-{}", id % 2)).collect();
+            RecordBatch::try_new(schema(), vec![Arc::new(Int64Array::from(ids)), Arc::new(StringArray::from(customers))]).unwrap()
+        };
+        Some(vec![batch(vec![first, first + 1]), batch(vec![first + 2])])
+    }
+
+    pub struct OrdersService;
+
+    #[tonic::async_trait]
+    impl FlightService for OrdersService {
+        type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+        type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+        type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+        type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+        type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+        type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+        type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+
+        async fn get_flight_info(&self, request: Request<FlightDescriptor>) -> FlightResult<FlightInfo> {
+            if request.into_inner().path != ["orders"] {
+                return Err(Status::not_found("no such flight"));
+            }
+            // The schema as an encapsulated IPC message
+            let mut schema_bytes = Vec::new();
+            StreamWriter::try_new(&mut schema_bytes, &schema()).unwrap();
+            let endpoints = ["part-0", "part-1"].map(|ticket| FlightEndpoint {
+                ticket: Some(Ticket::new(ticket)),
+                ..Default::default()
+            });
+            Ok(Response::new(FlightInfo {
+                schema: schema_bytes.into(),
+                endpoint: endpoints.to_vec(),
+                total_records: 6,
+                total_bytes: -1,
+                ..Default::default()
+            }))
+        }
+
+        async fn do_get(&self, request: Request<Ticket>) -> FlightResult<Self::DoGetStream> {
+            let batches = part(&request.into_inner().ticket).ok_or_else(|| Status::not_found("no such ticket"))?;
+            let generator = IpcDataGenerator::default();
+            let options = IpcWriteOptions::default();
+            let mut tracker = DictionaryTracker::new(false);
+            let mut encoded = vec![generator.schema_to_bytes_with_dictionary_tracker(&schema(), &mut tracker, &options)];
+            for batch in &batches {
+                encoded.push(generator.encoded_batch(batch, &mut tracker, &options).unwrap().1);
+            }
+            let messages: Vec<_> = encoded
+                .into_iter()
+                .map(|data| FlightData {
+                    data_header: data.ipc_message.into(),
+                    data_body: data.arrow_data.into(),
+                    ..Default::default()
+                })
+                .collect();
+            Ok(Response::new(Box::pin(stream::iter(messages).map(Ok))))
+        }
+
+        async fn handshake(&self, _: Request<Streaming<HandshakeRequest>>) -> FlightResult<Self::HandshakeStream> {
+            Err(Status::unimplemented("handshake"))
+        }
+
+        async fn list_flights(&self, _: Request<Criteria>) -> FlightResult<Self::ListFlightsStream> {
+            Err(Status::unimplemented("list_flights"))
+        }
+
+        async fn poll_flight_info(&self, _: Request<FlightDescriptor>) -> FlightResult<PollInfo> {
+            Err(Status::unimplemented("poll_flight_info"))
+        }
+
+        async fn get_schema(&self, _: Request<FlightDescriptor>) -> FlightResult<SchemaResult> {
+            Err(Status::unimplemented("get_schema"))
+        }
+
+        async fn do_put(&self, _: Request<Streaming<FlightData>>) -> FlightResult<Self::DoPutStream> {
+            Err(Status::unimplemented("do_put"))
+        }
+
+        async fn do_exchange(&self, _: Request<Streaming<FlightData>>) -> FlightResult<Self::DoExchangeStream> {
+            Err(Status::unimplemented("do_exchange"))
+        }
+
+        async fn do_action(&self, _: Request<Action>) -> FlightResult<Self::DoActionStream> {
+            Err(Status::unimplemented("do_action"))
+        }
+
+        async fn list_actions(&self, _: Request<Empty>) -> FlightResult<Self::ListActionsStream> {
+            Err(Status::unimplemented("list_actions"))
+        }
+    }
+
+    /// Serve on a free local port, returning the endpoint URI
+    pub async fn start() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(socket, _)| socket);
+            Some((connection, listener))
+        });
+        let server = tonic::transport::Server::builder().add_service(FlightServiceServer::new(OrdersService));
+        tokio::spawn(server.serve_with_incoming(incoming));
+        format!("grpc://{}", address)
+    }
+}
+
+#[tokio::test]
+async fn test_register_flight() -> BlazeResult<()> {
+    use bigquery_lite_engine::FlightSource;
+    use datafusion::arrow::array::StringArray;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let endpoint = flight_service::start().await;
+    let engine = BlazeQueryEngine::new().await?;
+
+    // A path descriptor is read from every endpoint of the flight
+    let info = engine.register_flight("orders", &endpoint, FlightSource::Path(vec!["orders".to_string()])).await?;
+    assert_eq!(info.total_records, Some(6));
+    assert_eq!(info.columns, vec![("order_id".to_string(), "Int64".to_string()), ("customer".to_string(), "Utf8".to_string())]);
+
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(order_id) AS total FROM orders").await?;
+    assert_eq!(result.data[0]["n"], 6);
+    assert_eq!(result.data[0]["total"], 21);
+
+    // Remote rows join with local tables
+    let customers = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("customer", DataType::Utf8, false), Field::new("tier", DataType::Utf8, false)])),
+        vec![
+            Arc::new(StringArray::from(vec!["customer-0", "customer-1"])),
+            Arc::new(StringArray::from(vec!["gold", "silver"])),
+        ],
+    )?;
+    engine.register_table("customers", vec![customers]).await?;
+    let joined = engine
+        .execute_query(
+            "SELECT c.tier, COUNT(*) AS n FROM orders o JOIN customers c ON o.customer = c.customer GROUP BY c.tier ORDER BY c.tier",
+        )
+        .await?;
+    assert_eq!(joined.data.len(), 2);
+    assert_eq!(joined.data[0]["tier"], "gold");
+    assert_eq!(joined.data[0]["n"], 3);
+
+    let limited = engine.execute_query("SELECT order_id FROM orders LIMIT 2").await?;
+    assert_eq!(limited.rows, 2);
+
+    // A ticket is read as one stream
+    engine.register_flight("second_part", &endpoint, FlightSource::Ticket(b"part-1".to_vec())).await?;
+    let part = engine.execute_query("SELECT MIN(order_id) AS lo, MAX(order_id) AS hi FROM second_part").await?;
+    assert_eq!(part.data[0]["lo"], 4);
+    assert_eq!(part.data[0]["hi"], 6);
+    let tables: Vec<_> = engine.list_flight_tables().await.into_iter().map(|t| t.table_name).collect();
+    assert_eq!(tables, vec!["orders", "second_part"]);
+
+    // Flights the service does not know are refused at registration
+    let missing = engine.register_flight("missing", &endpoint, FlightSource::Path(vec!["returns".to_string()])).await;
+    assert!(matches!(missing, Err(BlazeError::External(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flight_server_uploads() -> BlazeResult<()> {
+    use arrow_flight::flight_service_client::FlightServiceClient;
+    use arrow_flight::{FlightData, FlightDescriptor};
+    use bigquery_lite_engine::FlightServer;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let engine = Arc::new(BlazeQueryEngine::new().await?);
+    let server = FlightServer::start(engine.clone(), "127.0.0.1:0").await?;
+    let mut client = FlightServiceClient::connect(format!("http://{}", server.address())).await.unwrap();
+
+    // The schema message carries the descriptor, then one message per batch
+    let upload = |path: &[&str], ids: Vec<Vec<i64>>| {
+        let schema = flight_service::schema();
+        let generator = IpcDataGenerator::default();
+        let options = IpcWriteOptions::default();
+        let mut tracker = DictionaryTracker::new(false);
+        let mut encoded = vec![generator.schema_to_bytes_with_dictionary_tracker(&schema, &mut tracker, &options)];
+        for ids in ids {
+            let customers: Vec<_> = ids.iter().map(|id| format!("customer-{}", id)).collect();
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int64Array::from(ids)), Arc::new(datafusion::arrow::array::StringArray::from(customers))],
+            )
+            .unwrap();
+            encoded.push(generator.encoded_batch(&batch, &mut tracker, &options).unwrap().1);
+        }
+        let mut messages: Vec<_> = encoded
+            .into_iter()
+            .map(|data| FlightData {
+                data_header: data.ipc_message.into(),
+                data_body: data.arrow_data.into(),
+                ..Default::default()
+            })
+            .collect();
+        messages[0].flight_descriptor = Some(FlightDescriptor::new_path(path.iter().map(|p| p.to_string()).collect()));
+        futures::stream::iter(messages)
+    };
+
+    let mut results = client.do_put(upload(&["uploads"], vec![vec![1, 2], vec![3]])).await.unwrap().into_inner();
+    let metadata = results.message().await.unwrap().unwrap().app_metadata;
+    let metadata: serde_json::Value = serde_json::from_slice(&metadata).unwrap();
+    assert_eq!(metadata, serde_json::json!({"table": "uploads", "rows": 3}));
+
+    client.do_put(upload(&["uploads"], vec![vec![4]])).await.unwrap();
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(order_id) AS total FROM uploads").await?;
+    assert_eq!((result.data[0]["n"].clone(), result.data[0]["total"].clone()), (4.into(), 10.into()));
+
+    client.do_put(upload(&["uploads", "replace"], vec![vec![7]])).await.unwrap();
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(order_id) AS total FROM uploads").await?;
+    assert_eq!((result.data[0]["n"].clone(), result.data[0]["total"].clone()), (1.into(), 7.into()));
+
+    let refused = client.do_put(upload(&[], vec![vec![8]])).await;
+    assert_eq!(refused.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+    let stats = server.stop().await?;
+    assert_eq!((stats.uploads, stats.rows_uploaded, stats.failed_uploads), (3, 5, 1));
+    assert!(stats.last_error.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_time_partitioning_on_ingest() -> BlazeResult<()> {
+    use datafusion::arrow::array::{Int64Array, TimestampMicrosecondArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    const DAY_US: i64 = 86_400_000_000;
+    // 2024-03-01T00:00:00Z
+    const MARCH_1: i64 = 1_709_251_200_000_000;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("event_time", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+    ]));
+    let batch = |ids: Vec<i64>, times: Vec<Option<i64>>| {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from(ids)), Arc::new(TimestampMicrosecondArray::from(times))],
+        )
+    };
+
+    let engine = BlazeQueryEngine::new().await?;
+    // Designated before the table exists; the caller passes unsplit batches
+    engine.set_time_partitioning("events", "event_time").await?;
+    engine
+        .register_table(
+            "events",
+            vec![batch(
+                vec![1, 2, 3, 4, 5],
+                vec![Some(MARCH_1 + 10), Some(MARCH_1 + DAY_US), Some(MARCH_1 + 2 * DAY_US + 1), None, Some(MARCH_1 + 20)],
+            )?],
+        )
+        .await?;
+    engine.append_to_table("events", vec![batch(vec![6, 7], vec![Some(MARCH_1 + 2 * DAY_US), Some(MARCH_1 + 3 * DAY_US)])?]).await?;
+
+    let info = engine.time_partitions("events").await?;
+    let days: Vec<_> = info.partitions.iter().map(|p| (p.day.map(|d| d.to_string()), p.rows)).collect();
+    assert_eq!(days, vec![
+        (None, 1),
+        (Some("2024-03-01".to_string()), 2),
+        (Some("2024-03-02".to_string()), 1),
+        (Some("2024-03-03".to_string()), 2),
+        (Some("2024-03-04".to_string()), 1),
+    ]);
+
+    let result = engine
+        .execute_query(
+            "SELECT COUNT(*) AS n, SUM(id) AS ids FROM events \
+             WHERE event_time >= TIMESTAMP '2024-03-02' AND event_time < TIMESTAMP '2024-03-04'",
+        )
+        .await?;
+    assert_eq!(result.data[0]["n"], 3);
+    assert_eq!(result.data[0]["ids"], 11);
+    let nulls = engine.execute_query("SELECT id FROM events WHERE event_time IS NULL").await?;
+    assert_eq!(nulls.data[0]["id"], 4);
+
+    // Only temporal columns partition a table, and the designation is saved
+    // with the engine state
+    assert!(engine.set_time_partitioning("events", "id").await.is_err());
+    let dir = tempfile::tempdir()?;
+    engine.snapshot_to(dir.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(dir.path()).await?;
+    assert_eq!(restored.time_partitions("events").await?.partitions.len(), 5);
+
+    engine.clear_time_partitioning("events").await?;
+    assert!(engine.time_partitions("events").await.is_err());
+    assert_eq!(engine.execute_query("SELECT COUNT(*) AS n FROM events").await?.data[0]["n"], 7);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lru_table_eviction() -> BlazeResult<()> {
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    let batch = |start: i64| RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from_iter_values(start..start + 10_000))]);
+    let table_bytes = batch(0)?.get_array_memory_size();
+
+    let dir = tempfile::tempdir()?;
+    let engine = BlazeQueryEngine::with_config(EngineConfig {
+        memory_limit_bytes: table_bytes * 5 / 2,
+        evict_tables: true,
+        table_memory_percent: 100,
+        table_spill_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    })
+    .await?;
+
+    engine.register_table("a", vec![batch(0)?]).await?;
+    engine.register_table("b", vec![batch(10_000)?]).await?;
+    // Reading `a` leaves `b` least recently used
+    engine.execute_query("SELECT COUNT(*) AS n FROM a").await?;
+    engine.register_table("c", vec![batch(20_000)?]).await?;
+
+    let events = engine.list_table_evictions().await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].table_name, "b");
+    assert_eq!(events[0].evicted_for, "c");
+    assert!(events[0].spill_path.is_some());
+    let memory: Vec<_> = engine.list_table_memory().await.into_iter().map(|t| t.table_name).collect();
+    assert_eq!(memory, vec!["c", "a"]);
+
+    // The spilled table is still queryable from disk
+    let spilled = engine.execute_query("SELECT MIN(id) AS lo, COUNT(*) AS n FROM b").await?;
+    assert_eq!(spilled.data[0]["lo"], 10_000);
+    assert_eq!(spilled.data[0]["n"], 10_000);
+
+    // Pinned tables are skipped even when least recently used
+    engine.pin_table("a").await?;
+    engine.register_table("d", vec![batch(30_000)?]).await?;
+    assert_eq!(engine.list_table_evictions().await[1].table_name, "c");
+    assert!(engine.pin_table("missing").await.is_err());
+
+    // With everything else pinned there is nothing left to evict
+    engine.pin_table("d").await?;
+    assert!(engine.register_table("e", vec![batch(40_000)?]).await.is_err());
+    engine.unpin_table("d").await;
+    engine.register_table("e", vec![batch(40_000)?]).await?;
+
+    let stats = engine.get_stats().await;
+    assert_eq!(stats.evicted_tables, 3);
+    assert_eq!(stats.evicted_bytes, 3 * table_bytes as u64);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshot_isolated_appends() -> BlazeResult<()> {
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    let batch = move |start: i64| RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from_iter_values(start..start + 100))]);
+
+    let engine = Arc::new(BlazeQueryEngine::new().await?);
+    engine.register_table("events", vec![batch(0)?]).await?;
+
+    let writer = {
+        let engine = engine.clone();
+        let batch = batch.clone();
+        tokio::spawn(async move {
+            for i in 1..=20 {
+                engine.append_to_table("events", vec![batch(i * 100)?]).await?;
+            }
+            Ok::<_, BlazeError>(())
+        })
+    };
+
+    // Both scans of a statement read the same version, whole appends only
+    for _ in 0..20 {
+        let result = engine
+            .execute_query("SELECT (SELECT COUNT(*) FROM events) AS a, (SELECT MAX(id) + 1 FROM events) AS b")
+            .await?;
+        let (a, b) = (result.data[0]["a"].as_i64().unwrap(), result.data[0]["b"].as_i64().unwrap());
+        assert_eq!(a, b);
+        assert_eq!(a % 100, 0);
+    }
+    writer.await.unwrap()?;
+
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM events").await?;
+    assert_eq!(result.data[0]["n"], 2100);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transactions() -> BlazeResult<()> {
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2, 3]))])?;
+    const COUNT: &str = "SELECT COUNT(*) AS n FROM orders";
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("orders", vec![batch.clone()]).await?;
+
+    // Rolled back writes leave tables as they were and drop created tables
+    engine.execute_query("BEGIN").await?;
+    engine.execute_query("INSERT INTO orders VALUES (4), (5)").await?;
+    engine.register_table("staging", vec![batch.clone()]).await?;
+    assert_eq!(engine.current_transaction().await.unwrap().tables, vec!["orders", "staging"]);
+    assert_eq!(engine.execute_query(COUNT).await?.data[0]["n"], 5);
+    assert!(engine.execute_query("BEGIN TRANSACTION").await.is_err());
+    engine.execute_query("ROLLBACK").await?;
+    assert!(engine.current_transaction().await.is_none());
+    assert_eq!(engine.execute_query(COUNT).await?.data[0]["n"], 3);
+    assert!(engine.execute_query("SELECT * FROM staging").await.is_err());
+
+    // A failing statement rolls back the whole transaction
+    engine.execute_query("START TRANSACTION").await?;
+    engine.execute_query("INSERT INTO orders VALUES (4)").await?;
+    assert!(engine.execute_query("INSERT INTO orders SELECT id FROM missing").await.is_err());
+    assert!(engine.current_transaction().await.is_none());
+    assert_eq!(engine.execute_query(COUNT).await?.data[0]["n"], 3);
+
+    // Change events are published on commit
+    let since = engine.latest_change_sequence().await;
+    engine.begin_transaction().await?;
+    engine.execute_query("INSERT INTO orders VALUES (4)").await?;
+    assert!(engine.get_changes(Some("orders"), since).await.is_empty());
+    let committed = engine.commit_transaction().await?;
+    assert_eq!(committed.tables, vec!["orders"]);
+    assert_eq!(engine.get_changes(Some("orders"), since).await.len(), 1);
+    assert_eq!(engine.execute_query(COUNT).await?.data[0]["n"], 4);
+    assert!(engine.execute_query("COMMIT").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_transactions() -> BlazeResult<()> {
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use tokio::sync::Barrier;
+
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2, 3]))])?;
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("orders", vec![batch.clone()]).await?;
+    engine.register_table("returns", vec![batch]).await?;
+    let count = |table: &str| format!("SELECT COUNT(*) AS n FROM {}", table);
+
+    let (a, b) = (engine.create_session().await, engine.create_session().await);
+    let in_a = QueryOptions { session_id: Some(a.session_id.clone()), ..Default::default() };
+    let in_b = QueryOptions { session_id: Some(b.session_id.clone()), ..Default::default() };
+
+    // Both sessions hold a transaction open while one of them fails; only
+    // the failing session's writes are rolled back
+    let (opened, failed) = (Barrier::new(2), Barrier::new(2));
+    let session_a = async {
+        engine.execute_query_with_options("BEGIN", &in_a).await?;
+        engine.execute_query_with_options("INSERT INTO orders VALUES (4), (5)", &in_a).await?;
+        opened.wait().await;
+        failed.wait().await;
+        assert!(engine.execute_query("SELECT * FROM missing").await.is_err());
+        assert_eq!(engine.execute_query(&count("orders")).await?.data[0]["n"], 5);
+        engine.execute_query_with_options("COMMIT", &in_a).await
+    };
+    let session_b = async {
+        engine.execute_query_with_options("BEGIN", &in_b).await?;
+        engine.execute_query_with_options("INSERT INTO returns VALUES (4)", &in_b).await?;
+        opened.wait().await;
+        let result = engine.execute_query_with_options("INSERT INTO returns SELECT id FROM missing", &in_b).await;
+        assert!(result.is_err());
+        failed.wait().await;
+        Ok::<_, BlazeError>(())
+    };
+    tokio::try_join!(session_a, session_b)?;
+    assert!(engine.current_transaction().await.is_none());
+    assert_eq!(engine.execute_query(&count("orders")).await?.data[0]["n"], 5);
+    assert_eq!(engine.execute_query(&count("returns")).await?.data[0]["n"], 3);
+    assert!(engine.execute_query_with_options("COMMIT", &in_b).await.is_err());
+
+    // A table saved by one transaction cannot be written outside it, and
+    // closing the session rolls its transaction back
+    engine.execute_query_with_options("BEGIN", &in_a).await?;
+    engine.execute_query_with_options("DELETE FROM orders WHERE id > 3", &in_a).await?;
+    let result = engine.execute_query("INSERT INTO orders VALUES (6)").await;
+    assert!(matches!(result, Err(BlazeError::InvalidInput(_))), "{:?}", result);
+    assert!(engine.close_session(&a.session_id).await);
+    assert_eq!(engine.execute_query(&count("orders")).await?.data[0]["n"], 5);
+    engine.execute_query("INSERT INTO orders VALUES (6)").await?;
+    assert_eq!(engine.execute_query(&count("orders")).await?.data[0]["n"], 6);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dbt_relation_hooks() -> BlazeResult<()> {
+    use bigquery_lite_engine::RelationType;
+    use datafusion::arrow::array::{Int64Array, TimestampMicrosecondArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("loaded_at", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+    ]));
+    // 2024-03-01T00:00:00Z and an hour later
+    let loaded = vec![Some(1_709_251_200_000_000), Some(1_709_254_800_000_000)];
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![Arc::new(Int64Array::from(vec![1, 2])), Arc::new(TimestampMicrosecondArray::from(loaded))],
+    )?;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("raw_orders", vec![batch]).await?;
+    engine.execute_query("CREATE VIEW recent_orders AS SELECT id FROM raw_orders WHERE id > 1").await?;
+
+    // Models are built under a temporary name and swapped in
+    assert_eq!(engine.create_table_as("orders__dbt_tmp", "SELECT id, id * 10 AS amount FROM raw_orders", false).await?, 2);
+    assert!(engine.create_table_as("orders__dbt_tmp", "SELECT 1 AS id", false).await.is_err());
+    assert_eq!(engine.create_table_as("empty", "SELECT id FROM raw_orders WHERE id > 5", true).await?, 0);
+    engine.rename_relation("orders__dbt_tmp", "orders").await?;
+    engine.rename_relation("recent_orders", "latest_orders").await?;
+    assert!(engine.rename_relation("orders", "raw_orders").await.is_err());
+
+    let relations: Vec<_> = engine.list_relations().await?.into_iter().map(|r| (r.name, r.relation_type)).collect();
+    assert_eq!(relations, vec![
+        ("empty".to_string(), RelationType::Table),
+        ("latest_orders".to_string(), RelationType::View),
+        ("orders".to_string(), RelationType::Table),
+        ("raw_orders".to_string(), RelationType::Table),
+    ]);
+    let columns: Vec<_> = engine.get_columns("orders").await?.into_iter().map(|c| (c.name, c.data_type)).collect();
+    assert_eq!(columns, vec![("id".to_string(), "INT64".to_string()), ("amount".to_string(), "INT64".to_string())]);
+    assert_eq!(engine.get_columns("empty").await?.len(), 1);
+    let result = engine.execute_query("SELECT SUM(amount) AS total FROM orders").await?;
+    assert_eq!(result.data[0]["total"], 30);
+    assert_eq!(engine.execute_query("SELECT id FROM latest_orders").await?.data[0]["id"], 2);
+
+    // A view can be replaced by a table
+    assert_eq!(engine.create_table_as("latest_orders", "SELECT 7 AS id", true).await?, 1);
+    assert_eq!(engine.get_columns("latest_orders").await?[0].data_type, "INT64");
+
+    let freshness = engine.relation_freshness("raw_orders", "loaded_at").await?;
+    assert_eq!(freshness.max_loaded_at.unwrap().to_rfc3339(), "2024-03-01T01:00:00+00:00");
+    assert!(freshness.snapshotted_at > freshness.max_loaded_at.unwrap());
+    assert!(engine.relation_freshness("empty", "id").await?.max_loaded_at.is_none());
+
+    assert!(engine.drop_relation("orders").await?);
+    assert!(!engine.drop_relation("orders").await?);
+    assert!(engine.get_columns("orders").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_geojson_and_shapefile_ingest() -> BlazeResult<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("places.geojson");
+    std::fs::write(
+        &path,
+        r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"name": "pier", "visitors": 120},
+             "geometry": {"type": "Point", "coordinates": [-122.41941550000001, 37.7749295]}},
+            {"type": "Feature", "properties": {"name": "park", "visitors": 4500},
+             "geometry": {"type": "Polygon", "coordinates": [[[-122.5, 37.7], [-122.4, 37.7], [-122.4, 37.8], [-122.5, 37.7]]]}},
+            {"type": "Feature", "properties": {"name": "unmapped", "visitors": null}, "geometry": null}
+        ]}"#,
+    )?;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let report = engine.load_geojson("places", &path).await?;
+    assert_eq!(report.rows_loaded, 3);
+    assert_eq!(report.bounds, Some([-122.5, 37.7, -122.4, 37.8]));
+
+    let result = engine
+        .execute_query("SELECT name, visitors FROM places WHERE bbox_xmax <= -122.4 AND bbox_ymin >= 37.75")
+        .await?;
+    assert_eq!(result.data.len(), 1);
+    assert_eq!(result.data[0]["name"], "pier");
+    assert_eq!(result.data[0]["visitors"], 120);
+
+    // The point's WKB holds the coordinates exactly as written
+    let batches = engine.execute_query_batches("SELECT geometry FROM places WHERE name = 'pier'").await?;
+    let wkb = batches[0].column(0).as_any().downcast_ref::<datafusion::arrow::array::BinaryArray>().unwrap().value(0);
+    assert_eq!(&wkb[..5], &[1, 1, 0, 0, 0]);
+    assert_eq!(f64::from_le_bytes(wkb[5..13].try_into().unwrap()), -122.41941550000001);
+    assert_eq!(f64::from_le_bytes(wkb[13..21].try_into().unwrap()), 37.7749295);
+
+    assert!(engine.load_shapefile("missing", dir.path().join("missing.shp")).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_query_hints() -> BlazeResult<()> {
+    use bigquery_lite_engine::PlanNode;
+
+    fn find_join(node: &PlanNode) -> Option<&PlanNode> {
+        if node.name == "HashJoinExec" {
+            return Some(node);
+        }
+        node.children.iter().find_map(find_join)
+    }
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine
+        .execute_query("CREATE TABLE dim AS SELECT * FROM (VALUES (1, 'a'), (2, 'b'), (3, 'c')) AS t(id, name)")
+        .await?;
+    engine.execute_query("CREATE TABLE fact AS SELECT value % 3 + 1 AS dim_id, value AS amount FROM generate_series(1, 999)").await?;
+
+    // The planner would build from the small table; the hint makes it
+    // collect the large one
+    let sql = "SELECT /*+ BROADCAST(f) */ d.name, SUM(f.amount) AS total \
+               FROM dim d JOIN fact f ON f.dim_id = d.id GROUP BY d.name ORDER BY d.name";
+    let result = engine.execute_query(sql).await?;
+    assert_eq!(result.rows, 3);
+    assert_eq!(result.data[0]["total"], 166833);
+    let graph = engine.explain_graph(sql).await?;
+    assert_eq!(graph.hints, vec!["BROADCAST(f)".to_string()]);
+    let join = find_join(&graph.root).unwrap();
+    assert!(join.description.contains("mode=CollectLeft"), "{}", join.description);
+    assert_eq!(join.children[0].estimated_rows, Some(999));
+
+    let explain = engine.execute_query(&format!("EXPLAIN {}", sql)).await?;
+    let hints_row = explain.data.iter().find(|row| row["plan_type"] == "query_hints").unwrap();
+    assert_eq!(hints_row["plan"], "BROADCAST(f)");
+
+    let sql = "SELECT /*+ SHUFFLE_HASH(dim) REPARTITION(4) */ COUNT(*) AS n FROM fact JOIN dim ON fact.dim_id = dim.id";
+    assert_eq!(engine.execute_query(sql).await?.data[0]["n"], 999);
+    let graph = engine.explain_graph(sql).await?;
+    let join = find_join(&graph.root).unwrap();
+    assert!(join.description.contains("mode=Partitioned"), "{}", join.description);
+    assert!(graph.dot.contains("RoundRobinBatch(4)"), "{}", graph.dot);
+    assert_eq!(join.children[0].estimated_rows, Some(3));
+
+    // Hints must name tables the query reads and be well formed; hints on
+    // other statements are ignored
+    assert!(engine.execute_query("SELECT /*+ BROADCAST(other) */ COUNT(*) FROM fact").await.is_err());
+    assert!(engine.execute_query("SELECT /*+ NESTED_LOOP(fact) */ COUNT(*) FROM fact").await.is_err());
+    engine.execute_query("CREATE TABLE fact_copy AS SELECT /*+ BROADCAST(fact) */ * FROM fact").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_join_reordering() -> BlazeResult<()> {
+    use bigquery_lite_engine::EngineConfigUpdate;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine.execute_query("CREATE TABLE events AS SELECT value AS id, value % 2000 + 1 AS user_id FROM generate_series(1, 4000)").await?;
+    engine.execute_query("CREATE TABLE sessions AS SELECT value AS id, value % 2000 + 1 AS user_id FROM generate_series(1, 4000)").await?;
+    engine.execute_query("CREATE TABLE vip AS SELECT value AS user_id FROM generate_series(1, 2)").await?;
+
+    engine.execute_query("ANALYZE TABLE events").await?;
+    let stats = engine.analyze_table("sessions").await?;
+    assert_eq!(stats.row_count, 4000);
+    assert_eq!(stats.columns[1].distinct_count, Some(2000));
+    assert_eq!(stats.columns[1].null_count, 0);
+    assert!(engine.table_statistics("vip").await.is_none());
+
+    // As written, the first join pairs every event with every session of
+    // the same user before the two VIPs are picked out
+    let sql = "SELECT COUNT(*) AS n FROM events e JOIN sessions s ON e.user_id = s.user_id \
+               JOIN vip v ON s.user_id = v.user_id";
+    let before = engine.join_diagnostics(sql).await?;
+    assert!(!before.reorder_joins);
+    assert_eq!(before.join_order, vec!["events", "sessions", "vip"]);
+    assert_eq!(before.joins[0].actual_rows, 8000);
+    assert_eq!(before.joins[0].estimated_rows, Some(8000));
+    assert_eq!(before.joins[1].estimated_rows, None);
+
+    // Without statistics for every table the order is kept
+    engine.update_config(EngineConfigUpdate { reorder_joins: Some(true), ..Default::default() }).await?;
+    assert_eq!(engine.join_diagnostics(sql).await?.join_order, before.join_order);
+
+    engine.analyze_table("vip").await?;
+    let after = engine.join_diagnostics(sql).await?;
+    assert!(after.reorder_joins);
+    assert_eq!(after.join_order, vec!["sessions", "vip", "events"]);
+    assert_eq!((after.joins[0].estimated_rows, after.joins[0].actual_rows), (Some(4), 4));
+    assert_eq!((after.joins[1].estimated_rows, after.joins[1].actual_rows), (Some(8), 8));
+    assert_eq!(engine.execute_query(sql).await?.data[0]["n"], 8);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_injected_clock() -> BlazeResult<()> {
+    use chrono::TimeZone;
+
+    let engine = BlazeQueryEngine::with_config(EngineConfig { result_table_ttl_secs: 60, ..Default::default() }).await?;
+    assert_eq!(engine.clock(), Clock::System);
+    let fixed = chrono::Utc.with_ymd_and_hms(2024, 2, 29, 23, 30, 0).unwrap();
+    engine.set_clock(Clock::Fixed(fixed));
+
+    let sql = "SELECT CURRENT_TIMESTAMP AS ts, CURRENT_DATE AS d, current_time() AS t";
+    let first = engine.execute_query(sql).await?;
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let second = engine.execute_query(sql).await?;
+    assert_eq!(first.data, second.data);
+    assert_eq!(first.data[0]["ts"], "2024-02-29T23:30:00Z");
+    assert_eq!(first.data[0]["d"], "2024-02-29");
+    assert_eq!(first.data[0]["t"], "23:30:00");
+
+    // The fixed instant is shown in the session zone
+    engine.execute_query("SET TIME ZONE 'Asia/Tokyo'").await?;
+    let result = engine.execute_query(sql).await?;
+    assert_eq!(result.data[0]["ts"], "2024-03-01T08:30:00+09:00");
+    assert_eq!(result.data[0]["d"], "2024-03-01");
+
+    // Result tables expire by the engine clock
+    let options = QueryOptions { cache_result: true, ..Default::default() };
+    let table = engine.execute_query_with_options("SELECT 1 AS one", &options).await?.result_table.unwrap();
+    assert_eq!(engine.list_result_tables().await[0].created_at, fixed);
+    engine.set_clock(Clock::Fixed(fixed + chrono::Duration::seconds(61)));
+    assert!(engine.list_result_tables().await.is_empty());
+    assert!(engine.execute_query(&format!("SELECT * FROM {}", table)).await.is_err());
+
+    // An offset clock moves with the system clock
+    engine.execute_query("SET TIME ZONE 'UTC'").await?;
+    engine.set_clock(Clock::Offset(chrono::Duration::days(-365)));
+    let result = engine.execute_query("SELECT CAST(EXTRACT(YEAR FROM CURRENT_TIMESTAMP) AS BIGINT) AS y").await?;
+    let expected = Clock::Offset(chrono::Duration::days(-365)).now();
+    assert_eq!(result.data[0]["y"], chrono::Datelike::year(&expected));
+
+    engine.set_clock(Clock::System);
+    let result = engine.execute_query("SELECT CAST(EXTRACT(YEAR FROM CURRENT_TIMESTAMP) AS BIGINT) AS y").await?;
+    assert_eq!(result.data[0]["y"], chrono::Datelike::year(&chrono::Utc::now()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_time_travel_with_fixed_clock() -> BlazeResult<()> {
+    use chrono::TimeZone;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let start = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    engine.set_clock(Clock::Fixed(start));
+    engine.register_table("versioned", create_simple_test_data().await?).await?;
+    let view = engine.create_materialized_view("versioned_count", "SELECT COUNT(*) AS n FROM versioned").await?;
+    assert_eq!(view.last_refreshed_at, start);
+
+    engine.set_clock(Clock::Fixed(start + chrono::Duration::days(1)));
+    engine.execute_query("INSERT INTO versioned SELECT * FROM versioned").await?;
+
+    // Snapshots and view refreshes are stamped by the engine clock
+    let snapshots = engine.list_snapshots("versioned").await?;
+    let committed: Vec<_> = snapshots.iter().map(|s| s.committed_at).collect();
+    assert_eq!(committed, vec![start, start + chrono::Duration::days(1)]);
+    let views = engine.list_materialized_views().await;
+    assert_eq!(views[0].last_refreshed_at, start + chrono::Duration::days(1));
+
+    // AS OF expressions are evaluated by the same clock
+    let sql = "SELECT COUNT(*) AS n FROM versioned FOR SYSTEM_TIME AS OF CURRENT_TIMESTAMP() - INTERVAL 12 HOUR";
+    assert_eq!(engine.execute_query(sql).await?.data[0]["n"], 5);
+    let sql = "SELECT COUNT(*) AS n FROM versioned FOR SYSTEM_TIME AS OF CURRENT_TIMESTAMP()";
+    assert_eq!(engine.execute_query(sql).await?.data[0]["n"], 10);
+
+    // Transactions start at the engine clock too
+    engine.begin_transaction().await?;
+    assert_eq!(engine.current_transaction().await.unwrap().started_at, start + chrono::Duration::days(1));
+    engine.rollback_transaction().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stats_by_label() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(100).await?).await?;
+
+    let labeled = |pairs: &[(&str, &str)]| QueryOptions {
+        labels: pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        ..Default::default()
+    };
+    let growth = labeled(&[("team", "growth"), ("dashboard", "funnel")]);
+    engine.execute_query_with_options("SELECT * FROM events LIMIT 10", &growth).await?;
+    engine.execute_query_with_options("SELECT * FROM events LIMIT 5", &growth).await?;
+    engine.execute_query_with_options("SELECT COUNT(*) AS n FROM events", &labeled(&[("team", "finance")])).await?;
+    engine.execute_query("SELECT * FROM events").await?;
+    assert!(engine.execute_query_with_options("SELECT * FROM missing", &growth).await.is_err());
+
+    let teams = engine.get_stats_by_label("team").await;
+    let mut usage: Vec<_> = teams.iter().map(|s| (s.value.as_str(), s.queries, s.rows_returned)).collect();
+    usage.sort();
+    assert_eq!(usage, vec![("finance", 1, 1), ("growth", 2, 15)]);
+    let growth_stats = teams.iter().find(|s| s.value == "growth").unwrap();
+    assert_eq!(growth_stats.label, "team");
+    assert_eq!(
+        growth_stats.avg_execution_time_ms,
+        growth_stats.total_execution_time_ms as f64 / 2.0
+    );
+
+    let dashboards = engine.get_stats_by_label("dashboard").await;
+    assert_eq!(dashboards.len(), 1);
+    assert_eq!((dashboards[0].value.as_str(), dashboards[0].queries), ("funnel", 2));
+    assert!(engine.get_stats_by_label("owner").await.is_empty());
+    assert_eq!(engine.get_stats().await.total_queries, 4);
+
+    // Label usage is kept with the engine state
+    let dir = tempfile::tempdir()?;
+    engine.snapshot_to(dir.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(dir.path()).await?;
+    assert_eq!(restored.get_stats_by_label("team").await.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_result_checksums() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("events", create_categorized_test_data(200).await?).await?;
+    let ordered = QueryOptions { checksum: Some(ChecksumMode::Ordered), ..Default::default() };
+    let unordered = QueryOptions { checksum: Some(ChecksumMode::Unordered), ..Default::default() };
+
+    let ascending = "SELECT id, category FROM events ORDER BY id";
+    let descending = "SELECT category, id FROM events ORDER BY id DESC";
+    let a = engine.execute_query_with_options(ascending, &ordered).await?.checksum.unwrap();
+    let b = engine.execute_query_with_options(descending, &ordered).await?.checksum.unwrap();
+    assert_ne!(a, b);
+    assert_eq!(a, engine.execute_query_with_options(ascending, &ordered).await?.checksum.unwrap());
+    let a = engine.execute_query_with_options(ascending, &unordered).await?.checksum.unwrap();
+    let b = engine.execute_query_with_options(descending, &unordered).await?.checksum.unwrap();
+    assert_eq!(a, b);
+    assert_eq!(a.len(), 32);
+
+    // Values are compared as rendered, not by type
+    let narrowed = "SELECT CAST(id AS INT) AS id, category FROM events";
+    assert_eq!(a, engine.execute_query_with_options(narrowed, &unordered).await?.checksum.unwrap());
+    let changed = "SELECT id + 1 AS id, category FROM events";
+    assert_ne!(a, engine.execute_query_with_options(changed, &unordered).await?.checksum.unwrap());
+
+    assert!(engine.execute_query(ascending).await?.checksum.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_and_delete() -> BlazeResult<()> {
+    use bigquery_lite_engine::ChangeType;
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine
+        .execute_query(
+            "CREATE TABLE users AS SELECT * FROM (VALUES (1, 'ada', 'uk'), (2, 'bob', 'us'), (3, 'cy', 'us'), (4, 'di', NULL)) \
+             AS t(id, name, country)",
+        )
+        .await?;
+    engine.execute_query("CREATE TABLE erasure_requests AS SELECT 3 AS user_id").await?;
+    let since = engine.latest_change_sequence().await;
+
+    let updated = engine.execute_query("UPDATE users SET name = upper(name) WHERE country = 'us'").await?;
+    assert_eq!(updated.data[0]["count"], 2);
+    let result = engine.execute_query("SELECT id, name FROM users ORDER BY id").await?;
+    let names: Vec<_> = result.data.iter().map(|row| row["name"].as_str().unwrap().to_string()).collect();
+    assert_eq!(names, vec!["ada", "BOB", "CY", "di"]);
+
+    // Rows where the condition is NULL are left alone
+    let deleted = engine.execute_query("DELETE FROM users WHERE country <> 'uk'").await?;
+    assert_eq!(deleted.data[0]["count"], 2);
+    let deleted = engine.execute_query("DELETE FROM users WHERE id IN (SELECT user_id FROM erasure_requests)").await?;
+    assert_eq!(deleted.data[0]["count"], 0);
+    let result = engine.execute_query("SELECT id FROM users ORDER BY id").await?;
+    assert_eq!(result.data.iter().map(|row| row["id"].as_i64().unwrap()).collect::<Vec<_>>(), vec![1, 4]);
+
+    let events = engine.get_changes(Some("users"), since).await;
+    let changes: Vec<_> = events.iter().map(|e| (e.change_type, e.num_rows())).collect();
+    assert_eq!(
+        changes,
+        vec![(ChangeType::Delete, 2), (ChangeType::Insert, 2), (ChangeType::Delete, 2)]
+    );
+
+    // A subquery picks the rows, and a transaction can undo the change
+    engine.execute_query("BEGIN").await?;
+    engine.execute_query("CREATE OR REPLACE TABLE erasure_requests AS SELECT 4 AS user_id").await?;
+    let deleted = engine.execute_query("DELETE FROM users WHERE id IN (SELECT user_id FROM erasure_requests)").await?;
+    assert_eq!(deleted.data[0]["count"], 1);
+    assert_eq!(engine.execute_query("SELECT * FROM users").await?.rows, 1);
+    engine.execute_query("ROLLBACK").await?;
+    assert_eq!(engine.execute_query("SELECT * FROM users").await?.rows, 2);
+
+    let all = engine.execute_query("DELETE FROM users").await?;
+    assert_eq!(all.data[0]["count"], 2);
+    assert_eq!(engine.execute_query("SELECT * FROM users").await?.rows, 0);
+    engine.execute_query("INSERT INTO users VALUES (5, 'ed', 'fr')").await?;
+    assert_eq!(engine.execute_query("SELECT * FROM users").await?.rows, 1);
+
+    assert!(engine.execute_query("UPDATE users SET missing = 1").await.is_err());
+    assert!(engine.execute_query("DELETE FROM missing_table").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_data() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    let dir = tempfile::tempdir()?;
+    let uri = dir.path().join("exports").join("part-*.parquet").display().to_string();
+    let export = format!(
+        "EXPORT DATA OPTIONS (uri = '{}', format = 'PARQUET', compression = 'ZSTD') AS SELECT id, value FROM test_table WHERE id > 1",
+        uri
+    );
+
+    let report = engine.execute_query(&export).await?;
+    let file = dir.path().join("exports").join("part-000000000000.parquet");
+    assert_eq!(report.data[0]["file"], file.display().to_string());
+    assert_eq!(report.data[0]["rows"], 4);
+    assert_eq!(report.data[0]["bytes"], std::fs::metadata(&file)?.len());
+    assert_eq!((report.rows_written, report.bytes_written), (Some(4), Some(std::fs::metadata(&file)?.len())));
+    let copied = engine.execute_query(&format!("COPY exported FROM '{}'", file.display())).await?;
+    assert_eq!(copied.data[0]["count"], 4);
+
+    // Existing files are only replaced with overwrite = true
+    let error = engine.execute_query(&export).await.unwrap_err();
+    assert!(error.to_string().contains("overwrite = true"), "{}", error);
+    let overwrite = export.replace("compression = 'ZSTD'", "overwrite = true");
+    assert_eq!(engine.execute_query(&overwrite).await?.data[0]["rows"], 4);
+
+    let csv = dir.path().join("csv-*.csv.gz").display().to_string();
+    let report = engine
+        .execute_query(&format!(
+            "EXPORT DATA OPTIONS (uri = '{}', format = 'CSV', compression = 'GZIP', header = false, field_delimiter = ';') \
+             AS SELECT id, value FROM test_table",
+            csv
+        ))
+        .await?;
+    assert_eq!(report.data[0]["rows"], 5);
+    let written = std::fs::read(csv.replace('*', "000000000000"))?;
+    assert_eq!(written[..2], [0x1f, 0x8b]);
+
+    let error = engine
+        .execute_query(&format!("EXPORT DATA OPTIONS (uri = '{}', format = 'CSV', compression = 'ZSTD') AS SELECT 1", csv))
+        .await;
+    assert!(error.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_plan_baseline() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+    engine.execute_query("CREATE TABLE other AS SELECT id, value * 2 AS doubled FROM test_table").await?;
+    let queries = BTreeMap::from([
+        ("filtered".to_string(), "SELECT id FROM test_table WHERE value > 10 AND to_timestamp(id) < now()".to_string()),
+        (
+            "joined".to_string(),
+            "SELECT t.id, o.doubled FROM test_table t JOIN other o ON t.id = o.id ORDER BY o.doubled".to_string(),
+        ),
+    ]);
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("plans.json");
+    engine.record_plan_baseline(&queries).await?.save(&path)?;
+    let mut baseline = PlanBaseline::load(&path)?;
+    assert_eq!(baseline.queries.len(), 2);
+    assert!(baseline.queries["joined"].plan.contains("Inner Join"));
+    assert!(baseline.queries["filtered"].plan.contains("(<now>"), "{}", baseline.queries["filtered"].plan);
+
+    // Plans are stable across runs, including folded timestamps
+    let report = engine.check_plan_baseline(&baseline).await?;
+    assert_eq!((report.unchanged, report.has_changes()), (2, false));
+
+    // A baseline that read fewer columns flags lost pruning
+    let joined = baseline.queries.get_mut("joined").unwrap();
+    joined.plan = joined.plan.replace("TableScan: other projection=[id, doubled]", "TableScan: other projection=[id]");
+    let report = engine.check_plan_baseline(&baseline).await?;
+    assert_eq!((report.unchanged, report.changes.len()), (1, 1));
+    assert_eq!(report.changes[0].regressions, vec![PlanRegression::PruningLost]);
+    assert!(report.changes[0].diff.ends_with("-         TableScan: other projection=[id]\n+         TableScan: other projection=[id, doubled]"), "{}", report.changes[0].diff);
+
+    // Queries that no longer plan are reported with the error
+    engine.execute_query("DROP TABLE test_table").await?;
+    let report = engine.check_plan_baseline(&baseline).await?;
+    assert!(report.has_regressions());
+    let failed = report.changes.iter().find(|change| change.query == "filtered").unwrap();
+    assert!(failed.current_plan.is_none() && failed.error.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shared_results() -> BlazeResult<()> {
+    use datafusion::arrow::ipc::reader::FileReader;
+
+    let dir = tempfile::tempdir()?;
+    let config = EngineConfig { shared_result_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+    let engine = BlazeQueryEngine::with_config(config).await?;
+    engine.register_table("test_table", create_simple_test_data().await?).await?;
+
+    let shared = engine.execute_query_shared("SELECT id, value FROM test_table WHERE id > 2 ORDER BY id").await?;
+    assert_eq!(shared.rows, 3);
+    assert!(shared.path.starts_with(&dir.path().display().to_string()));
+    assert_eq!(shared.bytes, std::fs::metadata(&shared.path)?.len());
+    let batches = FileReader::try_new(std::fs::File::open(&shared.path)?, None)?.collect::<Result<Vec<_>, _>>()?;
+    let ids = batches[0].column(0).as_any().downcast_ref::<datafusion::arrow::array::Int64Array>().unwrap();
+    assert_eq!(ids.values(), &[3, 4, 5]);
+
+    let kept = engine.execute_query_shared("SELECT * FROM test_table").await?;
+    assert_eq!(engine.list_shared_results().await.len(), 2);
+    engine.release_shared_result(&shared.name).await?;
+    assert!(!std::path::Path::new(&shared.path).exists());
+    assert!(engine.release_shared_result(&shared.name).await.is_err());
+    assert!(engine.execute_query_shared("SELECT * FROM missing_table").await.is_err());
+
+    // Unreleased results go with the engine; failed queries leave no file
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+    drop(engine);
+    assert!(!std::path::Path::new(&kept.path).exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_csv() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("trips.csv");
+    std::fs::write(&path, "# exported 2024-05-01\nid;city;fare\n1;'Oslo; Norway';12.5\n2;Bergen;\n3;'Trond''heim';7\n")?;
+    let path = path.display().to_string();
+
+    let options = CsvTableOptions {
+        delimiter: b';',
+        quote: b'\'',
+        comment: Some(b'#'),
+        ..Default::default()
+    };
+    let info = engine.register_csv("trips", &path, &options).await?;
+    assert_eq!((info.file_count, info.format), (1, DataFormat::Csv));
+    let result = engine.execute_query("SELECT city, fare FROM trips ORDER BY id").await?;
+    assert_eq!(result.data[0]["city"], "Oslo; Norway");
+    assert_eq!(result.data[0]["fare"], 12.5);
+    assert!(result.data[1]["fare"].is_null());
+    assert_eq!(result.data[2]["city"], "Trond'heim");
+
+    // Without type inference every column is a string
+    engine.register_csv("raw_trips", &path, &CsvTableOptions { infer_types: false, ..options.clone() }).await?;
+    let result = engine.execute_query("SELECT arrow_typeof(id) AS t FROM raw_trips LIMIT 1").await?;
+    assert_eq!(result.data[0]["t"], "Utf8");
+
+    // An explicit schema replaces the header's names and inferred types
+    let schema = vec![
+        ("trip_id".to_string(), parse_type_name("STRING")?),
+        ("town".to_string(), parse_type_name("STRING")?),
+        ("amount".to_string(), parse_type_name("FLOAT64")?),
+    ];
+    let typed = CsvTableOptions { schema: Some(schema), ..options.clone() };
+    engine.register_csv("typed_trips", &path, &typed).await?;
+    let result = engine.execute_query("SELECT arrow_typeof(trip_id) AS t, SUM(amount) AS total FROM typed_trips GROUP BY 1").await?;
+    assert_eq!(result.data[0]["t"], "Utf8");
+    assert_eq!(result.data[0]["total"], 19.5);
+
+    // The options are kept across snapshots
+    let state = tempfile::tempdir()?;
+    engine.snapshot_to(state.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(state.path()).await?;
+    let result = restored.execute_query("SELECT COUNT(*) AS n, MAX(town) AS town FROM typed_trips").await?;
+    assert_eq!(result.data[0]["n"], 3);
+    assert_eq!(result.data[0]["town"], "Trond'heim");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_compressed_files() -> BlazeResult<()> {
+    use std::io::Write;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(b"id,fare\n1,12.5\n2,7.5\n")?;
+    std::fs::write(dir.path().join("trips.csv.gz"), gzip.finish()?)?;
+    let json = zstd::encode_all(&b"{\"level\": \"info\"}\n{\"level\": \"error\"}\n"[..], 3)?;
+    std::fs::write(dir.path().join("logs.json.zst"), json)?;
+
+    let info = engine.register_files("trips", &format!("{}/*.csv.gz", dir.path().display()), None).await?;
+    assert_eq!((info.format, info.compression), (DataFormat::Csv, Some(PayloadCompression::Gzip)));
+    let result = engine.execute_query("SELECT SUM(fare) AS total FROM trips").await?;
+    assert_eq!(result.data[0]["total"], 20.0);
+    let logs = format!("{}/logs.json.zst", dir.path().display());
+    engine.register_json("logs", &logs, &JsonTableOptions::default()).await?;
+    assert_eq!(engine.execute_query("SELECT COUNT(*) AS n FROM logs").await?.data[0]["n"], 2);
+
+    // COPY compresses and decompresses by suffix too
+    let copy = format!("{}/copy.csv.gz", dir.path().display());
+    engine.execute_query(&format!("COPY trips TO '{}'", copy)).await?;
+    assert_eq!(&std::fs::read(&copy)?[..2], &[0x1f, 0x8b]);
+    engine.execute_query(&format!("COPY copied FROM '{}'", copy)).await?;
+    assert_eq!(engine.execute_query("SELECT COUNT(*) AS n FROM copied").await?.data[0]["n"], 2);
+
+    // The files of one table share a compression
+    std::fs::write(dir.path().join("more.csv"), "id,fare\n3,1.0\n")?;
+    let error = engine.register_files("mixed", &format!("{}/*", dir.path().display()), Some(DataFormat::Csv)).await;
+    assert!(error.unwrap_err().to_string().contains("compressed differently"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_json() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    std::fs::write(
+        dir.path().join("app-1.json"),
+        "{\"level\": \"info\", \"ms\": 12}\n{\"level\": \"error\", \"ms\": 340}\n",
+    )?;
+    // Later logs gained a field
+    std::fs::write(
+        dir.path().join("app-2.json"),
+        "{\"level\": \"warn\", \"ms\": 80, \"account\": \"ada\"}\n",
+    )?;
+    let pattern = format!("{}/app-*.json", dir.path().display());
+
+    let info = engine.register_json("logs", &pattern, &JsonTableOptions::default()).await?;
+    assert_eq!((info.file_count, info.format), (2, DataFormat::Json));
+    let result = engine.execute_query("SELECT level, ms, account FROM logs ORDER BY ms").await?;
+    assert_eq!(result.rows, 3);
+    assert!(result.data[0]["account"].is_null());
+    assert_eq!(result.data[1]["account"], "ada");
+    assert_eq!(result.data[2]["level"], "error");
+
+    // Fields past the sample are not part of the schema
+    engine.register_json("sampled", &pattern, &JsonTableOptions { infer_rows: 2 }).await?;
+    let error = engine.execute_query("SELECT account FROM sampled").await.unwrap_err().to_string();
+    assert!(error.contains("account"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_avro() -> BlazeResult<()> {
+    use apache_avro::types::{Record, Value};
+
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let schema = apache_avro::Schema::parse_str(
+        r#"{"type": "record", "name": "trip", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "city", "type": {"type": "enum", "name": "city", "symbols": ["Oslo", "Bergen"]}},
+            {"name": "fare", "type": ["null", "double"]},
+            {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+            {"name": "stops", "type": {"type": "array", "items": "string"}},
+            {"name": "driver", "type": ["null", {"type": "record", "name": "driver", "fields": [{"name": "name", "type": "string"}]}]}
+        ]}"#,
+    )
+    .unwrap();
+    let trips = [(1, "Oslo", Some(12.5), vec!["a", "b"], Some("Ada")), (2, "Bergen", None, vec![], None), (3, "Oslo", Some(7.0), vec!["c"], None)];
+    for (part, trips) in [(0, &trips[..2]), (1, &trips[2..])] {
+        let mut writer = apache_avro::Writer::with_codec(&schema, Vec::new(), apache_avro::Codec::Deflate);
+        for (id, city, fare, stops, driver) in trips {
+            let mut record = Record::new(&schema).unwrap();
+            record.put("id", *id as i64);
+            record.put("city", Value::Enum(if *city == "Oslo" { 0 } else { 1 }, city.to_string()));
+            record.put("fare", fare.map_or(Value::Union(0, Box::new(Value::Null)), |fare| Value::Union(1, Box::new(Value::Double(fare)))));
+            record.put("at", Value::TimestampMillis(1_714_521_600_000 + *id as i64));
+            record.put("stops", Value::Array(stops.iter().map(|stop| Value::String(stop.to_string())).collect()));
+            let driver = driver.map_or(Value::Union(0, Box::new(Value::Null)), |name| {
+                Value::Union(1, Box::new(Value::Record(vec![("name".to_string(), Value::String(name.to_string()))])))
+            });
+            record.put("driver", driver);
+            writer.append(record).unwrap();
+        }
+        std::fs::write(dir.path().join(format!("trips-{}.avro", part)), writer.into_inner().unwrap())?;
+    }
+
+    let report = engine.register_avro("trips", dir.path()).await?;
+    assert_eq!((report.files.len(), report.rows_loaded), (2, 3));
+    assert!(report.columns.contains(&("at".to_string(), "Timestamp(Millisecond, Some(\"UTC\"))".to_string())));
+    let result = engine
+        .execute_query(
+            "SELECT city, SUM(fare) AS total, CAST(SUM(array_length(stops)) AS BIGINT) AS stops, MAX(driver['name']) AS driver
+             FROM trips GROUP BY city ORDER BY city",
+        )
+        .await?;
+    assert!(result.data[0]["total"].is_null());
+    assert_eq!(result.data[1]["city"], "Oslo");
+    assert_eq!(result.data[1]["total"], 19.5);
+    assert_eq!(result.data[1]["stops"], 3);
+    assert_eq!(result.data[1]["driver"], "Ada");
+
+    let single = engine.register_avro("first_trips", dir.path().join("trips-0.avro")).await?;
+    assert_eq!(single.rows_loaded, 2);
+    std::fs::write(dir.path().join("broken.avro"), "not avro")?;
+    let error = engine.register_avro("broken", dir.path().join("broken.avro")).await.unwrap_err().to_string();
+    assert!(error.contains("broken.avro"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_protobuf() -> BlazeResult<()> {
+    use prost::Message;
+    use prost_reflect::{DescriptorPool, DynamicMessage, Value};
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let field = |name: &str, number: i32, kind: Type, label: Label| FieldDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(number),
+        label: Some(label as i32),
+        r#type: Some(kind as i32),
+        ..Default::default()
+    };
+    let descriptor_set = FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("orders.proto".to_string()),
+            package: Some("shop".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Order".to_string()),
+                field: vec![
+                    field("id", 1, Type::Int64, Label::Optional),
+                    field("customer", 2, Type::String, Label::Optional),
+                    field("amount", 3, Type::Double, Label::Optional),
+                    field("items", 4, Type::String, Label::Repeated),
+                ],
+                ..Default::default()
+            }],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }],
+    };
+    let descriptor_path = dir.path().join("orders.desc");
+    std::fs::write(&descriptor_path, descriptor_set.encode_to_vec())?;
+
+    let order_type = DescriptorPool::from_file_descriptor_set(descriptor_set).unwrap().get_message_by_name("shop.Order").unwrap();
+    let messages = dir.path().join("messages");
+    std::fs::create_dir(&messages)?;
+    let orders = [(1, "ada", 12.5, vec!["pen", "ink"]), (2, "bob", 3.0, vec![]), (3, "ada", 7.5, vec!["pad"])];
+    for (part, orders) in [(0, &orders[..2]), (1, &orders[2..])] {
+        let mut data = Vec::new();
+        for (id, customer, amount, items) in orders {
+            let mut order = DynamicMessage::new(order_type.clone());
+            order.set_field_by_name("id", Value::I64(*id));
+            order.set_field_by_name("customer", Value::String(customer.to_string()));
+            order.set_field_by_name("amount", Value::F64(*amount));
+            order.set_field_by_name("items", Value::List(items.iter().map(|item| Value::String(item.to_string())).collect()));
+            order.encode_length_delimited(&mut data).unwrap();
+        }
+        std::fs::write(messages.join(format!("orders-{}.bin", part)), data)?;
+    }
+
+    let report = engine
+        .register_protobuf("orders", &descriptor_path, "shop.Order", &messages, ProtobufFraming::Delimited)
+        .await?;
+    assert_eq!((report.files.len(), report.rows_loaded), (2, 3));
+    assert_eq!(report.columns[0], ("id".to_string(), "Int64".to_string()));
+    let result = engine
+        .execute_query(
+            "SELECT customer, SUM(amount) AS total, CAST(SUM(array_length(items)) AS BIGINT) AS items
+             FROM orders GROUP BY customer ORDER BY customer",
+        )
+        .await?;
+    assert_eq!(result.data[0]["customer"], "ada");
+    assert_eq!(result.data[0]["total"], 20.0);
+    assert_eq!(result.data[0]["items"], 3);
+
+    let error = engine
+        .register_protobuf("orders", &descriptor_path, "shop.Refund", &messages, ProtobufFraming::Delimited)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("shop.Order"), "{}", error);
+    std::fs::write(dir.path().join("broken.bin"), [0x05, 0x08])?;
+    let error = engine
+        .register_protobuf("broken", &descriptor_path, "shop.Order", dir.path().join("broken.bin"), ProtobufFraming::Delimited)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("broken.bin") && error.contains("truncated"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_orc() -> BlazeResult<()> {
+    use datafusion::arrow::array::{Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // orc-rust can't write dates; their statistics are covered by the
+    // orc_tables unit tests
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, true),
+        Field::new("region", DataType::Utf8, true),
+        Field::new("amount", DataType::Float64, true),
+    ]));
+    for part in 0..2i64 {
+        let file = std::fs::File::create(dir.path().join(format!("sales-{}.orc", part)))?;
+        let mut writer = orc_rust::ArrowWriterBuilder::new(file, schema.clone()).try_build().unwrap();
+        let ids: Vec<i64> = (part * 3..part * 3 + 3).collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(ids)),
+                Arc::new(StringArray::from(vec!["north", "south", "north"])),
+                Arc::new(Float64Array::from(vec![Some(10.0), None, Some(2.5)])),
+            ],
+        )?;
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    let info = engine.register_orc("sales", dir.path()).await?;
+    assert_eq!((info.files.len(), info.rows), (2, 6));
+    assert!(info.columns.contains(&("amount".to_string(), "Float64".to_string())));
+    let result = engine
+        .execute_query("SELECT region, SUM(amount) AS total, COUNT(*) AS n FROM sales WHERE id >= 2 GROUP BY region ORDER BY region")
+        .await?;
+    assert_eq!(result.data[0]["region"], "north");
+    assert_eq!(result.data[0]["total"], 15.0);
+    assert_eq!(result.data[0]["n"], 3);
+    assert_eq!(result.data[1]["n"], 1);
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM sales WHERE id > 0").await?;
+    assert_eq!(result.data[0]["n"], 5);
+
+    let state = tempfile::tempdir()?;
+    engine.snapshot_to(state.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(state.path()).await?;
+    assert_eq!(restored.list_orc_tables().await.len(), 1);
+    let result = restored.execute_query("SELECT MAX(id) AS id FROM sales").await?;
+    assert_eq!(result.data[0]["id"], 5);
+
+    std::fs::write(dir.path().join("broken.orc"), "not orc")?;
+    let error = engine.register_orc("broken", dir.path()).await.unwrap_err().to_string();
+    assert!(error.contains("broken.orc"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_arrow_ipc() -> BlazeResult<()> {
+    use datafusion::arrow::ipc::writer::FileWriter;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let dir = tempfile::tempdir()?;
+    let batches = create_simple_test_data().await?;
+    for (part, extension) in ["arrow", "feather"].iter().enumerate() {
+        let file = std::fs::File::create(dir.path().join(format!("part-{}.{}", part, extension)))?;
+        let mut writer = FileWriter::try_new(file, &batches[0].schema())?;
+        for batch in &batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+    }
+
+    let info = engine.register_arrow_ipc("mapped", dir.path()).await?;
+    let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+    assert_eq!((info.files.len(), info.batches, info.rows), (2, 2 * batches.len(), 2 * rows));
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM mapped").await?;
+    assert_eq!(result.data[0]["n"], 2 * rows);
+    assert!(engine.list_table_memory().await.iter().all(|table| table.table_name != "mapped"));
+    let error = engine.append_to_table("mapped", batches.clone()).await.unwrap_err();
+    assert!(error.to_string().contains("does not support appends"), "{}", error);
+
+    let state = tempfile::tempdir()?;
+    engine.snapshot_to(state.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(state.path()).await?;
+    assert_eq!(restored.list_arrow_ipc_tables().await[0].rows, 2 * rows);
+
+    std::fs::write(dir.path().join("notes.arrow"), "not arrow")?;
+    let error = engine.register_arrow_ipc("broken", dir.path()).await.unwrap_err().to_string();
+    assert!(error.contains("notes.arrow"), "{}", error);
+
+    Ok(())
+}
+
+#[cfg(feature = "object_store")]
+#[tokio::test]
+async fn test_register_gcs_bucket() -> BlazeResult<()> {
+    use bigquery_lite_engine::GcsCredentials;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let error = engine.register_files("trips", "gs://lake/trips/*.parquet", None).await.unwrap_err();
+    assert!(error.to_string().contains("gs://lake"), "{}", error);
+
+    let info = engine.register_gcs("lake", GcsCredentials::ApplicationDefault).await?;
+    assert_eq!(info.url, "gs://lake");
+    let key = GcsCredentials::ServiceAccountFile("/nonexistent/key.json".to_string());
+    assert!(matches!(engine.register_gcs("archive", key).await, Err(BlazeError::Config(_))));
+    let stores = engine.list_object_stores().await;
+    assert_eq!(stores.iter().map(|store| store.url.as_str()).collect::<Vec<_>>(), vec!["gs://lake"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "object_store")]
+#[tokio::test]
+async fn test_register_azure_container() -> BlazeResult<()> {
+    let config = EngineConfig {
+        azure_storage_account: Some("devstore".to_string()),
+        azure_sas_token: Some("sv=2022-11-02&ss=b&sig=abc".to_string()),
+        ..EngineConfig::default()
+    };
+    let engine = BlazeQueryEngine::with_config(config.clone()).await?;
+    let info = engine.register_azure("az://lake").await?;
+    assert_eq!((info.url.as_str(), info.credentials.as_str()), ("az://lake", "SAS token"));
+    let info = engine.register_azure("abfss://archive@devstore.dfs.core.windows.net/2024").await?;
+    assert_eq!(info.url, "abfss://archive@devstore.dfs.core.windows.net");
+    assert_eq!(engine.list_object_stores().await.len(), 2);
+
+    let both = EngineConfig { azure_access_key: Some("c2VjcmV0LWtleQ==".to_string()), ..config };
+    assert!(matches!(BlazeQueryEngine::with_config(both).await, Err(BlazeError::Config(_))));
+
+    Ok(())
+}
+
+/// A static file server answering HEAD and byte-range requests, for tables
+/// registered over HTTP
+#[cfg(feature = "object_store")]
+mod http_files {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serve `files` to requests carrying `Authorization: Bearer token`,
+    /// returning the base URL and the `Range` headers seen
+    pub async fn start(files: HashMap<String, Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match socket.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let mut lines = request.lines();
+                let mut parts = lines.next().unwrap_or_default().split(' ');
+                let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+                let headers: HashMap<String, String> = lines
+                    .filter_map(|line| line.split_once(": "))
+                    .map(|(name, value)| (name.to_lowercase(), value.to_string()))
+                    .collect();
+
+                let (status, extra, body) = match files.get(path) {
+                    _ if headers.get("authorization").map(String::as_str) != Some("Bearer token") => ("401 Unauthorized", String::new(), Vec::new()),
+                    None => ("404 Not Found", String::new(), Vec::new()),
+                    Some(file) => match headers.get("range").and_then(|r| r.strip_prefix("bytes=")) {
+                        Some(range) => {
+                            seen.lock().unwrap().push(range.to_string());
+                            let (start, end) = range.split_once('-').unwrap();
+                            let start: usize = start.parse().unwrap();
+                            let end = end.parse::<usize>().map_or(file.len() - 1, |end| end.min(file.len() - 1));
+                            let extra = format!("Content-Range: bytes {}-{}/{}\r\n", start, end, file.len());
+                            ("206 Partial Content", extra, file[start..=end].to_vec())
+                        }
+                        None => ("200 OK", String::new(), file.clone()),
+                    },
+                };
+                let body = if method == "HEAD" { Vec::new() } else { body };
+                let length = match (method, files.get(path)) {
+                    ("HEAD", Some(file)) => file.len(),
+                    _ => body.len(),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nLast-Modified: Tue, 01 Oct 2024 00:00:00 GMT\r\n{}Connection: close\r\n\r\n",
+                    status, length, extra
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+        (format!("http://{}", address), ranges)
+    }
+}
+
+#[cfg(feature = "object_store")]
+#[tokio::test]
+async fn test_register_http_files() -> BlazeResult<()> {
+    use datafusion::parquet::arrow::ArrowWriter;
+    use std::collections::HashMap;
+
+    let batches = create_simple_test_data().await?;
+    let mut parquet = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut parquet, batches[0].schema(), None).unwrap();
+    for batch in &batches {
+        writer.write(batch).unwrap();
+    }
+    writer.close().unwrap();
+    let files = HashMap::from([
+        ("/data/values.parquet".to_string(), parquet),
+        ("/data/values.csv".to_string(), b"id,value\n1,10.5\n2,20.5\n".to_vec()),
+    ]);
+    let (base, ranges) = http_files::start(files).await;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let headers = vec![("Authorization".to_string(), "Bearer token".to_string())];
+    let info = engine.register_http("remote", &format!("{}/data/values.parquet", base), None, &headers).await?;
+    assert_eq!((info.format, info.file_count), (DataFormat::Parquet, 1));
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(value) AS total FROM remote").await?;
+    assert_eq!(result.data[0]["n"], 5);
+    assert_eq!(result.data[0]["total"], 150.0);
+    assert!(!ranges.lock().unwrap().is_empty());
+
+    engine.register_http("remote_csv", &format!("{}/data/values.csv", base), None, &headers).await?;
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM remote_csv").await?;
+    assert_eq!(result.data[0]["total"], 31.0);
+    let stores = engine.list_object_stores().await;
+    assert_eq!((stores[0].url.as_str(), stores[0].credentials.as_str()), (base.as_str(), "headers Authorization"));
+
+    let other = BlazeQueryEngine::new().await?;
+    assert!(other.register_http("denied", &format!("{}/data/values.csv", base), None, &[]).await.is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "object_store")]
+#[tokio::test]
+async fn test_object_cache_serves_repeated_reads() -> BlazeResult<()> {
+    use datafusion::parquet::arrow::ArrowWriter;
+    use std::collections::HashMap;
+
+    let batches = create_simple_test_data().await?;
+    let mut parquet = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut parquet, batches[0].schema(), None).unwrap();
+    for batch in &batches {
+        writer.write(batch).unwrap();
+    }
+    writer.close().unwrap();
+    let size = parquet.len() as u64;
+    let (base, ranges) = http_files::start(HashMap::from([("/data/values.parquet".to_string(), parquet)])).await;
+
+    let cache_dir = tempfile::tempdir().unwrap();
+    let config = EngineConfig { object_cache_dir: Some(cache_dir.path().to_path_buf()), ..EngineConfig::default() };
+    let engine = BlazeQueryEngine::with_config(config).await?;
+    let headers = vec![("Authorization".to_string(), "Bearer token".to_string())];
+    engine.register_http("remote", &format!("{}/data/values.parquet", base), None, &headers).await?;
+
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM remote").await?;
+    assert_eq!(result.data[0]["total"], 150.0);
+    let downloads = ranges.lock().unwrap().len();
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM remote").await?;
+    assert_eq!(result.data[0]["total"], 150.0);
+    assert_eq!(ranges.lock().unwrap().len(), downloads);
+
+    let cache = engine.get_stats().await.object_cache.unwrap();
+    assert_eq!((cache.misses, cache.bytes_downloaded, cache.cached_bytes), (1, size, size));
+    assert!(cache.hits > 0);
+    assert_eq!(cache.evicted_blocks, 0);
+    assert!(BlazeQueryEngine::new().await?.get_stats().await.object_cache.is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "delta")]
+#[tokio::test]
+async fn test_register_delta_table() -> BlazeResult<()> {
+    use deltalake::protocol::SaveMode;
+    use deltalake::DeltaOps;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().to_str().unwrap();
+    let batches = create_simple_test_data().await?;
+    let table = DeltaOps::try_from_uri(path).await.unwrap().write(batches.clone()).await.unwrap();
+    DeltaOps(table).write(batches).with_save_mode(SaveMode::Append).await.unwrap();
+
+    let engine = BlazeQueryEngine::new().await?;
+    let info = engine.register_delta("values_latest", path, None).await?;
+    assert_eq!(info.version, 1);
+    engine.register_delta("values_v0", path, Some(0)).await?;
+    let result = engine
+        .execute_query("SELECT (SELECT COUNT(*) FROM values_latest) AS latest, (SELECT COUNT(*) FROM values_v0) AS first")
+        .await?;
+    assert_eq!((result.data[0]["latest"].as_i64(), result.data[0]["first"].as_i64()), (Some(10), Some(5)));
+    let result = engine.execute_query("SELECT SUM(value) AS total FROM values_latest WHERE id > 3").await?;
+    assert_eq!(result.data[0]["total"], 180.0);
+
+    let state = tempfile::tempdir()?;
+    engine.snapshot_to(state.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(state.path()).await?;
+    let versions: Vec<_> = restored.list_delta_tables().await.iter().map(|t| (t.version, t.pinned_version)).collect();
+    assert_eq!(versions, vec![(1, None), (0, Some(0))]);
+
+    Ok(())
+}
+
+#[cfg(feature = "iceberg")]
+#[tokio::test]
+async fn test_register_iceberg_missing_metadata() -> BlazeResult<()> {
+    let dir = tempfile::tempdir()?;
+    let metadata = dir.path().join("metadata").join("v1.metadata.json");
+
+    let engine = BlazeQueryEngine::new().await?;
+    let error = engine.register_iceberg("lake", metadata.to_str().unwrap(), None).await.unwrap_err();
+    assert!(matches!(error, BlazeError::External(_)), "{}", error);
+    assert!(engine.list_iceberg_tables().await.is_empty());
+    assert!(engine.execute_query("SELECT * FROM lake").await.is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "iceberg")]
+#[tokio::test]
+async fn test_register_iceberg_table() -> BlazeResult<()> {
+    use bigquery_lite_engine::IcebergVersion;
+
+    let dir = tempfile::tempdir()?;
+    let metadata = copy_iceberg_fixture("orders", dir.path());
+    let metadata = metadata.to_str().unwrap();
+
+    // Two appends: orders 1-3 on 2024-06-01, then orders 4-5 on 2024-07-01
+    let engine = BlazeQueryEngine::new().await?;
+    let info = engine.register_iceberg("orders", metadata, None).await?;
+    assert_eq!((info.snapshot_id, info.pinned), (Some(1002), false));
+    let snapshots: Vec<_> = info.snapshots.iter().map(|s| (s.snapshot_id, s.operation.as_str())).collect();
+    assert_eq!(snapshots, vec![(1001, "append"), (1002, "append")]);
+    let columns: Vec<_> = info.columns.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(columns, vec!["id", "customer", "amount"]);
+
+    let result = engine.execute_query("SELECT COUNT(*) AS n, SUM(amount) AS total FROM orders").await?;
+    assert_eq!((result.data[0]["n"].as_i64(), result.data[0]["total"].as_f64()), (Some(5), Some(138.25)));
+
+    // Reading as of June sees only the first snapshot
+    let result = engine
+        .execute_query("SELECT id FROM orders FOR SYSTEM_TIME AS OF TIMESTAMP '2024-06-15 00:00:00' ORDER BY id")
+        .await?;
+    let ids: Vec<_> = result.data.iter().map(|row| row["id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert!(engine
+        .execute_query("SELECT id FROM orders FOR SYSTEM_TIME AS OF TIMESTAMP '2024-05-01 00:00:00'")
+        .await
+        .is_err());
+
+    // So does a table pinned to that snapshot at registration
+    let info = engine.register_iceberg("orders_june", metadata, Some(IcebergVersion::SnapshotId(1001))).await?;
+    assert_eq!((info.snapshot_id, info.pinned), (Some(1001), true));
+    let result = engine.execute_query("SELECT customer FROM orders_june WHERE amount > 10 ORDER BY customer").await?;
+    let customers: Vec<_> = result.data.iter().map(|row| row["customer"].as_str().unwrap()).collect();
+    assert_eq!(customers, vec!["ada", "grace"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "duckdb")]
+#[tokio::test]
+async fn test_attach_duckdb_database() -> BlazeResult<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("warehouse.duckdb");
+    {
+        let connection = duckdb::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE users (id INTEGER, plan VARCHAR);
+                 INSERT INTO users VALUES (1, 'pro'), (2, 'free'), (3, 'pro');
+                 CREATE SCHEMA staging;
+                 CREATE TABLE staging.orders (user_id INTEGER, amount DOUBLE);
+                 INSERT INTO staging.orders VALUES (1, 9.5), (1, 20.5), (2, 3.0);",
+            )
+            .unwrap();
+    }
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine
+        .execute_query("CREATE TABLE events AS SELECT * FROM (VALUES (1, 'login'), (3, 'login'), (3, 'logout')) AS t(user_id, kind)")
+        .await?;
+    engine.execute_query(&format!("ATTACH '{}' AS wh (READ_ONLY)", path.display())).await?;
+
+    let attached = engine.list_attached_databases().await;
+    assert_eq!(attached.len(), 1);
+    assert_eq!(attached[0].tables, vec!["wh.main.users", "wh.staging.orders"]);
+
+    // Engine tables join with the attached ones, filters run in DuckDB
+    let result = engine
+        .execute_query(
+            "SELECT e.user_id, COUNT(*) AS n FROM events e JOIN wh.users u ON e.user_id = u.id \
+             WHERE u.plan = 'pro' GROUP BY e.user_id ORDER BY e.user_id",
+        )
+        .await?;
+    assert_eq!(result.rows, 2);
+    assert_eq!(result.data[1]["n"], 2);
+    let result = engine.execute_query("SELECT SUM(amount) AS total FROM wh.staging.orders WHERE user_id = 1").await?;
+    assert_eq!(result.data[0]["total"], 30.0);
+    let result = engine.execute_query("SELECT COUNT(*) AS n FROM wh.main.users").await?;
+    assert_eq!(result.data[0]["n"], 3);
+
+    let error = engine.attach_duckdb(&path, "wh").await.unwrap_err().to_string();
+    assert!(error.contains("already attached"), "{}", error);
+    assert!(engine.attach_duckdb(&path, "public").await.is_err());
+
+    engine.execute_query("DETACH wh").await?;
+    assert!(engine.execute_query("SELECT * FROM wh.users").await.is_err());
+    assert!(engine.list_attached_databases().await.is_empty());
+    engine.execute_query("DETACH IF EXISTS wh").await?;
+
+    // The alias can be reused once detached
+    engine.attach_duckdb(&path, "wh").await?;
+    assert_eq!(engine.execute_query("SELECT * FROM wh.users").await?.rows, 3);
+
+    // Without AS the file name is the alias
+    engine.execute_query(&format!("ATTACH '{}'", path.display())).await?;
+    assert_eq!(engine.execute_query("SELECT * FROM warehouse.staging.orders").await?.rows, 3);
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_register_postgres_unreachable_server() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    let error = engine
+        .register_postgres("accounts", "host=127.0.0.1 port=1 user=reader password=s3cret connect_timeout=2", "billing.accounts")
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("PostgreSQL"), "{}", error);
+    assert!(engine.list_postgres_tables().await.is_empty());
+    let error = engine.register_postgres("accounts", "host=127.0.0.1", "billing.").await.unwrap_err().to_string();
+    assert!(error.contains("schema.table"), "{}", error);
+
+    Ok(())
+}
+
+#[cfg(feature = "bigquery")]
+#[tokio::test]
+async fn test_register_bigquery_unreachable_endpoint() -> BlazeResult<()> {
+    use bigquery_lite_engine::BigQueryReadOptions;
+
+    let engine = BlazeQueryEngine::new().await?;
+    let options = BigQueryReadOptions {
+        columns: vec!["id".to_string()],
+        row_restriction: Some("id > 10".to_string()),
+        access_token: Some("token".to_string()),
+        endpoint: Some("http://127.0.0.1:1".to_string()),
+        ..Default::default()
+    };
+    let error = engine.register_bigquery("trips", "my-project.taxi.trips", &options).await.unwrap_err().to_string();
+    assert!(error.contains("BigQuery Storage"), "{}", error);
+    assert!(engine.execute_query("SELECT * FROM trips").await.is_err());
+    let error = engine.register_bigquery("trips", "taxi.trips", &options).await.unwrap_err().to_string();
+    assert!(error.contains("project.dataset.table"), "{}", error);
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_attach_sqlite_database() -> BlazeResult<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("app.db");
+    {
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, plan TEXT);
+                 INSERT INTO users VALUES (1, 'pro'), (2, 'free'), (3, 'pro');",
+            )
+            .unwrap();
+    }
+
+    let engine = BlazeQueryEngine::new().await?;
+    engine
+        .execute_query("CREATE TABLE events AS SELECT * FROM (VALUES (1, 'login'), (3, 'login'), (3, 'logout')) AS t(user_id, kind)")
+        .await?;
+    let info = engine.attach_sqlite(&path, "app").await?;
+    assert_eq!(info.tables, vec!["app.users"]);
+
+    let result = engine
+        .execute_query(
+            "SELECT e.user_id, COUNT(*) AS n FROM events e JOIN app.users u ON e.user_id = u.id \
+             WHERE u.plan = 'pro' GROUP BY e.user_id ORDER BY e.user_id",
+        )
+        .await?;
+    assert_eq!(result.rows, 2);
+    assert_eq!(result.data[1]["n"], 2);
+    assert_eq!(engine.execute_query("SELECT COUNT(*) AS n FROM app.users").await?.data[0]["n"], 3);
+    assert!(engine.attach_sqlite(&path, "app").await.unwrap_err().to_string().contains("already attached"));
+
+    // Attachments are kept across snapshots
+    let state = tempfile::tempdir()?;
+    engine.snapshot_to(state.path()).await?;
+    let restored = BlazeQueryEngine::new().await?;
+    restored.restore_from(state.path()).await?;
+    assert_eq!(restored.list_sqlite_databases().await.len(), 1);
+
+    engine.detach_sqlite("app").await?;
+    assert!(engine.execute_query("SELECT * FROM app.users").await.is_err());
+    engine.attach_sqlite(&path, "app").await?;
+
+    Ok(())
+}
+
 async fn create_simple_test_data() -> BlazeResult<Vec<datafusion::arrow::record_batch::RecordBatch>> {
     use datafusion::arrow::array::*;
     use datafusion::arrow::datatypes::{Schema, Field, DataType};
@@ -281,4 +4158,54 @@ async fn create_categorized_test_data(
     }
 
     Ok(batches)
-}
\ No newline at end of file
+}
+
+/// Copy the Iceberg table `table` checked in under tests/fixtures/iceberg
+/// into `dir`, returning its metadata file. Iceberg metadata and manifests
+/// hold absolute paths, written in the fixture under `file:///fixtures`,
+/// which are rewritten to point into the copy.
+#[cfg(feature = "iceberg")]
+fn copy_iceberg_fixture(table: &str, dir: &std::path::Path) -> std::path::PathBuf {
+    use apache_avro::types::Value;
+
+    fn relocate(value: Value, from: &str, to: &str) -> Value {
+        match value {
+            Value::String(s) => Value::String(s.replace(from, to)),
+            Value::Union(index, inner) => Value::Union(index, Box::new(relocate(*inner, from, to))),
+            Value::Record(fields) => Value::Record(fields.into_iter().map(|(name, v)| (name, relocate(v, from, to))).collect()),
+            Value::Array(items) => Value::Array(items.into_iter().map(|v| relocate(v, from, to)).collect()),
+            Value::Map(entries) => Value::Map(entries.into_iter().map(|(key, v)| (key, relocate(v, from, to))).collect()),
+            other => other,
+        }
+    }
+
+    let (from, to) = ("file:///fixtures", format!("file://{}", dir.display()));
+    let source = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/iceberg").join(table);
+    for subdir in ["metadata", "data"] {
+        std::fs::create_dir_all(dir.join(table).join(subdir)).unwrap();
+        for entry in std::fs::read_dir(source.join(subdir)).unwrap() {
+            let path = entry.unwrap().path();
+            let target = dir.join(table).join(subdir).join(path.file_name().unwrap());
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => std::fs::write(target, std::fs::read_to_string(&path).unwrap().replace(from, &to)).unwrap(),
+                Some("avro") => {
+                    let reader = apache_avro::Reader::new(std::fs::File::open(&path).unwrap()).unwrap();
+                    let schema = reader.writer_schema().clone();
+                    let metadata = reader.user_metadata().clone();
+                    let mut writer = apache_avro::Writer::new(&schema, Vec::new());
+                    for (key, value) in metadata {
+                        writer.add_user_metadata(key, value).unwrap();
+                    }
+                    for value in reader {
+                        writer.append(relocate(value.unwrap(), from, &to)).unwrap();
+                    }
+                    std::fs::write(target, writer.into_inner().unwrap()).unwrap();
+                }
+                _ => {
+                    std::fs::copy(&path, target).unwrap();
+                }
+            }
+        }
+    }
+    dir.join(table).join("metadata").join("v2.metadata.json")
+}
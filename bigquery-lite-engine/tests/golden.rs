@@ -0,0 +1,209 @@
+//! Golden-file result tests
+//!
+//! Every `tests/golden/queries/*.sql` file runs against a fresh engine loaded
+//! with the CSV files in `tests/golden/fixtures/` (one table per file, named
+//! after it). Each statement's result, or its error, is rendered as text and
+//! compared with the matching `.out` file.
+//!
+//! Statements are separated by `;`. Comment lines directly before a
+//! statement can adjust the comparison:
+//!
+//! - `-- rowsort` sorts the rendered rows, for queries without a defined order
+//! - `-- precision: N` rounds floats to `N` significant digits (default 10)
+//!
+//! To accept new results, regenerate the expected files and review the diff:
+//!
+//! ```text
+//! GOLDEN_UPDATE=1 cargo test --test golden
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bigquery_lite_engine::utils::split_top_level;
+use bigquery_lite_engine::{BlazeQueryEngine, BlazeResult};
+use datafusion::arrow::array::{Array, Float32Array, Float64Array};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use datafusion::prelude::{CsvReadOptions, SessionContext};
+
+const DEFAULT_PRECISION: usize = 10;
+
+#[tokio::test]
+async fn test_golden_queries() -> BlazeResult<()> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = std::env::var("GOLDEN_UPDATE").is_ok_and(|v| v != "0");
+
+    let mut queries: Vec<PathBuf> = fs::read_dir(root.join("queries"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    queries.retain(|path| path.extension().is_some_and(|ext| ext == "sql"));
+    queries.sort();
+    assert!(!queries.is_empty(), "no golden queries found");
+
+    let mut mismatches = Vec::new();
+    for query_file in &queries {
+        let actual = run_file(&root.join("fixtures"), query_file).await?;
+        let expected_file = query_file.with_extension("out");
+
+        if update {
+            fs::write(&expected_file, &actual)?;
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_file).unwrap_or_default();
+        if expected != actual {
+            mismatches.push(format!(
+                "{} differs from {}\n{}",
+                query_file.display(),
+                expected_file.display(),
+                diff(&expected, &actual)
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\n\nRun with GOLDEN_UPDATE=1 to accept the new results.",
+        mismatches.join("\n\n")
+    );
+    Ok(())
+}
+
+/// Run every statement of a query file, rendering the results
+async fn run_file(fixtures: &Path, query_file: &Path) -> BlazeResult<String> {
+    let engine = BlazeQueryEngine::new().await?;
+    load_fixtures(&engine, fixtures).await?;
+
+    let text = fs::read_to_string(query_file)?;
+    let mut output = String::new();
+    for statement in split_top_level(&text, ';') {
+        let mut rowsort = false;
+        let mut precision = DEFAULT_PRECISION;
+        let mut sql_lines = Vec::new();
+        for line in statement.lines() {
+            let trimmed = line.trim();
+            if trimmed == "-- rowsort" {
+                rowsort = true;
+            } else if let Some(digits) = trimmed.strip_prefix("-- precision:") {
+                precision = digits.trim().parse().expect("precision must be a number");
+            } else if !(sql_lines.is_empty() && (trimmed.is_empty() || trimmed.starts_with("--"))) {
+                sql_lines.push(line);
+            }
+        }
+        let sql = sql_lines.join("\n").trim().to_string();
+        if sql.is_empty() {
+            continue;
+        }
+
+        output.push_str(&sql);
+        output.push_str(";\n");
+        match engine.execute_query_batches(&sql).await {
+            Ok(batches) => output.push_str(&render(&batches, rowsort, precision)),
+            Err(e) => output.push_str(&format!("ERROR: {}\n", e)),
+        }
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+async fn load_fixtures(engine: &BlazeQueryEngine, fixtures: &Path) -> BlazeResult<()> {
+    let ctx = SessionContext::new();
+    for entry in fs::read_dir(fixtures)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "csv") {
+            continue;
+        }
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let batches = ctx
+            .read_csv(path.to_string_lossy().as_ref(), CsvReadOptions::new())
+            .await?
+            .collect()
+            .await?;
+        engine.register_table(&name, batches).await?;
+    }
+    Ok(())
+}
+
+/// Header line plus one ` | `-separated line per row
+fn render(batches: &[RecordBatch], rowsort: bool, precision: usize) -> String {
+    let Some(schema) = batches.first().map(|b| b.schema()) else {
+        return "(no rows)\n".to_string();
+    };
+
+    let options = FormatOptions::new().with_null("NULL");
+    let mut rows = Vec::new();
+    for batch in batches {
+        let columns: Vec<Box<dyn Fn(usize) -> String + '_>> = batch
+            .columns()
+            .iter()
+            .map(|column| -> Box<dyn Fn(usize) -> String + '_> {
+                match column.data_type() {
+                    DataType::Float64 => {
+                        let values = column.as_any().downcast_ref::<Float64Array>().unwrap();
+                        Box::new(move |row| float(values.is_valid(row).then(|| values.value(row)), precision))
+                    }
+                    DataType::Float32 => {
+                        let values = column.as_any().downcast_ref::<Float32Array>().unwrap();
+                        Box::new(move |row| float(values.is_valid(row).then(|| values.value(row) as f64), precision))
+                    }
+                    _ => {
+                        let formatter = ArrayFormatter::try_new(column.as_ref(), &options).unwrap();
+                        Box::new(move |row| formatter.value(row).to_string())
+                    }
+                }
+            })
+            .collect();
+        for row in 0..batch.num_rows() {
+            rows.push(columns.iter().map(|value| value(row)).collect::<Vec<_>>().join(" | "));
+        }
+    }
+    if rowsort {
+        rows.sort();
+    }
+
+    let header: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    let mut text = header.join(" | ");
+    text.push('\n');
+    for row in rows {
+        text.push_str(&row);
+        text.push('\n');
+    }
+    text
+}
+
+/// Round to `precision` significant digits so results don't depend on
+/// summation order or formatting details
+fn float(value: Option<f64>, precision: usize) -> String {
+    match value {
+        None => "NULL".to_string(),
+        Some(v) if v.is_nan() => "NaN".to_string(),
+        Some(v) if v.is_infinite() => if v > 0.0 { "Infinity" } else { "-Infinity" }.to_string(),
+        Some(v) => {
+            let rounded: f64 = format!("{:.*e}", precision.saturating_sub(1), v).parse().unwrap();
+            // Avoid printing -0
+            format!("{}", rounded + 0.0)
+        }
+    }
+}
+
+/// Line-by-line comparison listing the differing lines
+fn diff(expected: &str, actual: &str) -> String {
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let mut lines = Vec::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {}
+            (e, a) => {
+                lines.push(format!("line {}:", i + 1));
+                if let Some(e) = e {
+                    lines.push(format!("  - {}", e));
+                }
+                if let Some(a) = a {
+                    lines.push(format!("  + {}", a));
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
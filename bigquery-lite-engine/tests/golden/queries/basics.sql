@@ -0,0 +1,20 @@
+-- Filtering, ordering and NULL handling over the fixtures
+SELECT order_id, amount, status FROM orders WHERE amount > 10 ORDER BY order_id;
+
+-- rowsort
+SELECT status, COUNT(*) AS orders, SUM(amount) AS total FROM orders GROUP BY status;
+
+SELECT c.name, COUNT(o.order_id) AS orders, COALESCE(SUM(o.amount), 0) AS spent
+FROM customers c LEFT JOIN orders o ON o.customer_id = c.customer_id
+GROUP BY c.name
+ORDER BY spent DESC, c.name;
+
+-- Float normalization: 0.1 + 0.2 is rendered as 0.3
+SELECT 0.1 + 0.2 AS sum, 1.0 / 3 AS third, CAST(NULL AS DOUBLE) AS missing;
+
+SELECT name, country IS NULL AS no_country FROM customers WHERE signup_date >= DATE '2023-03-01' ORDER BY name;
+
+SELECT * FROM no_such_table;
+
+-- precision: 4
+SELECT AVG(amount) AS avg_amount, STDDEV(amount) AS stddev_amount FROM orders;
@@ -0,0 +1,22 @@
+-- BigQuery extensions: sketches, date spines, gap filling and sessions
+SELECT array_element(APPROX_QUANTILES(order_id, 4), 3) AS median_order FROM orders;
+
+SELECT HLL_COUNT.EXTRACT(HLL_COUNT.INIT(customer_id)) AS customers FROM orders;
+
+SELECT value AS day FROM GENERATE_DATE_ARRAY(DATE '2024-01-01', DATE '2024-01-07', INTERVAL 2 DAY);
+
+SELECT CAST(ordered_at AS DATE) AS day, amount
+FROM GAP_FILL(
+  (SELECT date_trunc('day', ordered_at) AS ordered_at, SUM(amount) AS amount FROM orders GROUP BY 1),
+  ts_column => 'ordered_at',
+  bucket_width => INTERVAL 1 DAY,
+  value_columns => [('amount', 'locf')]
+)
+ORDER BY day;
+
+SELECT customer_id, session_number, COUNT(*) AS orders
+FROM SESSIONIZE(TABLE orders, 'customer_id', 'ordered_at', INTERVAL 1 HOUR)
+GROUP BY customer_id, session_number
+ORDER BY customer_id, session_number;
+
+ASSERT (SELECT COUNT(*) FROM orders) = 10 AS 'orders fixture changed';
@@ -0,0 +1,60 @@
+//! Soak tests for slow leaks
+//!
+//! The short run checks that the soak harness itself works. The long run is
+//! ignored by default; run it in release mode for as long as needed:
+//!
+//! ```text
+//! SOAK_DURATION_SECS=14400 cargo test --release --test soak -- --ignored --nocapture
+//! ```
+
+use bigquery_lite_engine::benchmarks::{SoakConfig, SoakTest};
+use bigquery_lite_engine::BlazeResult;
+
+#[tokio::test]
+async fn test_short_soak_run() -> BlazeResult<()> {
+    let config = SoakConfig {
+        duration_ms: 3_000,
+        sample_interval_ms: 100,
+        dataset_rows: 5_000,
+        warmup_samples: 5,
+        ..Default::default()
+    };
+    let report = SoakTest::new(config).await?.run().await?;
+
+    assert!(report.samples.len() >= 20, "{} samples", report.samples.len());
+    assert!(report.statements > report.samples.len() as u64);
+    assert_eq!(report.failed_statements, 0);
+    // Scratch tables are dropped and no reservation outlives its statement;
+    // RSS is too noisy to judge in a few seconds
+    assert!(report.samples.iter().all(|s| s.reserved_memory_bytes == 0));
+    assert!(!report.leaks.iter().any(|leak| leak.starts_with("Catalog")), "{:?}", report.leaks);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_soak_config_validation() {
+    let config = SoakConfig {
+        duration_ms: 1_000,
+        sample_interval_ms: 500,
+        ..Default::default()
+    };
+    assert!(SoakTest::new(config).await.is_err());
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_long_soak_run() -> BlazeResult<()> {
+    let seconds: u64 = std::env::var("SOAK_DURATION_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(600);
+    let config = SoakConfig {
+        duration_ms: seconds * 1000,
+        ..Default::default()
+    };
+    let report = SoakTest::new(config).await?.run().await?;
+
+    assert!(report.passed, "leaks: {:?}, failed statements: {}", report.leaks, report.failed_statements);
+    Ok(())
+}
@@ -0,0 +1,392 @@
+//! Randomized SQL robustness tests
+//!
+//! Generates valid-ish SQL over a fixed set of registered tables, including
+//! the engine's own statements and table functions, and checks that every
+//! query either succeeds or fails with a `BlazeError` rather than panicking
+//! or hitting an internal error.
+//! Some generated queries are deliberately mangled (truncated, tokens
+//! swapped) to exercise the error paths.
+//!
+//! The run is deterministic for a given seed. `FUZZ_SEED` and
+//! `FUZZ_ITERATIONS` override the defaults, e.g.
+//!
+//! ```text
+//! FUZZ_SEED=7 FUZZ_ITERATIONS=5000 cargo test --test sql_fuzz
+//! ```
+
+use std::sync::Arc;
+
+use bigquery_lite_engine::{BlazeError, BlazeQueryEngine, BlazeResult};
+use datafusion::arrow::array::{Float64Array, Int64Array, StringArray, TimestampNanosecondArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const DEFAULT_SEED: u64 = 0x5eed;
+const DEFAULT_ITERATIONS: usize = 300;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Int,
+    Float,
+    Text,
+    Time,
+}
+
+struct Table {
+    name: &'static str,
+    columns: &'static [(&'static str, Kind)],
+}
+
+const TABLES: &[Table] = &[
+    Table {
+        name: "events",
+        columns: &[("id", Kind::Int), ("user_id", Kind::Int), ("amount", Kind::Float), ("kind", Kind::Text), ("ts", Kind::Time)],
+    },
+    Table {
+        name: "users",
+        columns: &[("user_id", Kind::Int), ("name", Kind::Text), ("score", Kind::Float)],
+    },
+    Table {
+        name: "empty_table",
+        columns: &[("id", Kind::Int), ("label", Kind::Text)],
+    },
+];
+
+#[tokio::test]
+async fn test_generated_sql_never_panics() -> BlazeResult<()> {
+    let seed = env_or("FUZZ_SEED", DEFAULT_SEED);
+    let iterations = env_or("FUZZ_ITERATIONS", DEFAULT_ITERATIONS as u64) as usize;
+
+    let engine = Arc::new(BlazeQueryEngine::new().await?);
+    register_fixtures(&engine).await?;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut generator = Generator { rng: &mut rng };
+    let (mut succeeded, mut failed) = (0, 0);
+
+    for iteration in 0..iterations {
+        let sql = generator.statement();
+        let task_engine = engine.clone();
+        let task_sql = sql.clone();
+        match tokio::spawn(async move { task_engine.execute_query(&task_sql).await }).await {
+            Ok(Ok(_)) => succeeded += 1,
+            Ok(Err(BlazeError::Internal(e))) => panic!(
+                "query hit an internal error (FUZZ_SEED={} iteration {}):\n{}\n{}",
+                seed, iteration, sql, e
+            ),
+            Ok(Err(_)) => failed += 1,
+            Err(e) if e.is_panic() => panic!(
+                "query panicked (FUZZ_SEED={} iteration {}):\n{}\n{:?}",
+                seed, iteration, sql, e
+            ),
+            Err(e) => panic!("query task failed: {}", e),
+        }
+    }
+
+    // A generator producing only errors would not exercise much
+    assert!(succeeded > iterations / 5, "only {} of {} queries succeeded ({} failed)", succeeded, iterations, failed);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_substr_overflow_returns_error() -> BlazeResult<()> {
+    let engine = BlazeQueryEngine::new().await?;
+    register_fixtures(&engine).await?;
+
+    // Would overflow inside DataFusion's SUBSTR kernel (found by the generator above)
+    for sql in [
+        "SELECT SUBSTR('', 9223372036854775807, 2147483648) AS s FROM users",
+        "SELECT SUBSTRING(name, 1, 9223372036854775807) AS s FROM users",
+        "SELECT SUBSTR(name, user_id, 9223372036854775807 - user_id) AS s FROM users",
+    ] {
+        let result = engine.execute_query(sql).await;
+        assert!(matches!(result, Err(BlazeError::QueryExecution(_))), "{}: {:?}", sql, result);
+    }
+
+    // The engine stays usable afterwards
+    let count = engine.execute_query("SELECT COUNT(*) AS n FROM users").await?;
+    assert_eq!(count.data[0]["n"], 12);
+    Ok(())
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+async fn register_fixtures(engine: &BlazeQueryEngine) -> BlazeResult<()> {
+    let mut rng = StdRng::seed_from_u64(1);
+    let rows = 200;
+    let kinds = ["click", "view", "buy", ""];
+
+    let events = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("user_id", DataType::Int64, true),
+            Field::new("amount", DataType::Float64, true),
+            Field::new("kind", DataType::Utf8, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Nanosecond, None), true),
+        ])),
+        vec![
+            Arc::new(Int64Array::from_iter_values(0..rows)),
+            Arc::new(Int64Array::from_iter((0..rows).map(|i| (i % 7 != 3).then_some(i % 10)))),
+            Arc::new(Float64Array::from_iter((0..rows).map(|_| rng.gen_bool(0.9).then(|| rng.gen_range(-50.0..500.0))))),
+            Arc::new(StringArray::from_iter((0..rows).map(|i| (i % 11 != 0).then(|| kinds[i as usize % kinds.len()])))),
+            Arc::new(TimestampNanosecondArray::from_iter(
+                (0..rows).map(|i| (i % 13 != 0).then_some(1_704_067_200_000_000_000 + i * 97_000_000_000)),
+            )),
+        ],
+    )?;
+    engine.register_table("events", vec![events]).await?;
+
+    let users = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("user_id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("score", DataType::Float64, true),
+        ])),
+        vec![
+            Arc::new(Int64Array::from_iter_values(0..12)),
+            Arc::new(StringArray::from_iter((0..12).map(|i| (i != 5).then(|| format!("user_{}", i))))),
+            Arc::new(Float64Array::from_iter((0..12).map(|i| (i % 4 != 0).then_some(i as f64 * 1.5)))),
+        ],
+    )?;
+    engine.register_table("users", vec![users]).await?;
+
+    engine.execute_query("CREATE TABLE empty_table (id BIGINT, label VARCHAR)").await?;
+    Ok(())
+}
+
+struct Generator<'a> {
+    rng: &'a mut StdRng,
+}
+
+impl Generator<'_> {
+    fn statement(&mut self) -> String {
+        match self.rng.gen_range(0..100) {
+            0..=54 => self.select(),
+            55..=64 => self.aggregate_functions(),
+            65..=74 => self.table_function(),
+            75..=79 => self.engine_statement(),
+            80..=84 => format!("EXPLAIN {}", self.select()),
+            _ => {
+                let sql = self.select();
+                self.mangle(sql)
+            }
+        }
+    }
+
+    fn table(&mut self) -> &'static Table {
+        TABLES.choose(self.rng).unwrap()
+    }
+
+    fn column(&mut self, table: &Table, kind: Option<Kind>) -> Option<&'static str> {
+        let candidates: Vec<_> = table
+            .columns
+            .iter()
+            .filter(|(_, k)| kind.is_none_or(|kind| *k == kind))
+            .collect();
+        candidates.choose(self.rng).map(|(name, _)| *name)
+    }
+
+    fn literal(&mut self, kind: Kind) -> String {
+        match kind {
+            Kind::Int => ["0", "1", "-1", "7", "2147483648", "9223372036854775807", "NULL"].choose(self.rng).unwrap().to_string(),
+            Kind::Float => ["0.0", "1.5", "-2.25", "1e300", "'NaN'", "NULL"].choose(self.rng).unwrap().to_string(),
+            Kind::Text => ["'click'", "''", "'%'", "'it''s'", "'ü'", "NULL"].choose(self.rng).unwrap().to_string(),
+            Kind::Time => ["TIMESTAMP '2024-01-01 00:00:00'", "TIMESTAMP '1970-01-01 00:00:00'", "NULL"]
+                .choose(self.rng)
+                .unwrap()
+                .to_string(),
+        }
+    }
+
+    fn expr(&mut self, table: &Table, kind: Kind, depth: usize) -> String {
+        if depth == 0 || self.rng.gen_bool(0.4) {
+            return match self.column(table, Some(kind)) {
+                Some(column) if self.rng.gen_bool(0.7) => column.to_string(),
+                _ => self.literal(kind),
+            };
+        }
+
+        let depth = depth - 1;
+        match kind {
+            Kind::Int => match self.rng.gen_range(0..6) {
+                0 => format!("({} {} {})", self.expr(table, Kind::Int, depth), ["+", "-", "*", "/", "%"].choose(self.rng).unwrap(), self.expr(table, Kind::Int, depth)),
+                1 => format!("LENGTH({})", self.expr(table, Kind::Text, depth)),
+                2 => format!("CAST({} AS BIGINT)", self.expr(table, Kind::Float, depth)),
+                3 => format!("ABS({})", self.expr(table, Kind::Int, depth)),
+                4 => format!("COALESCE({}, {})", self.expr(table, Kind::Int, depth), self.literal(Kind::Int)),
+                _ => format!("CASE WHEN {} THEN {} ELSE {} END", self.predicate(table, depth), self.expr(table, Kind::Int, depth), self.expr(table, Kind::Int, depth)),
+            },
+            Kind::Float => match self.rng.gen_range(0..4) {
+                0 => format!("({} {} {})", self.expr(table, Kind::Float, depth), ["+", "-", "*", "/"].choose(self.rng).unwrap(), self.expr(table, Kind::Float, depth)),
+                1 => format!("ROUND({}, {})", self.expr(table, Kind::Float, depth), self.rng.gen_range(-2..4)),
+                2 => format!("SQRT({})", self.expr(table, Kind::Float, depth)),
+                _ => format!("CAST({} AS DOUBLE)", self.expr(table, Kind::Int, depth)),
+            },
+            Kind::Text => match self.rng.gen_range(0..5) {
+                0 => format!("UPPER({})", self.expr(table, Kind::Text, depth)),
+                1 => format!("CONCAT({}, {})", self.expr(table, Kind::Text, depth), self.expr(table, Kind::Text, depth)),
+                2 => format!("SUBSTR({}, {}, {})", self.expr(table, Kind::Text, depth), self.literal(Kind::Int), self.literal(Kind::Int)),
+                3 => format!("CAST({} AS VARCHAR)", self.expr(table, Kind::Int, depth)),
+                _ => format!("REPLACE({}, 'c', 'k')", self.expr(table, Kind::Text, depth)),
+            },
+            Kind::Time => format!("date_trunc('{}', {})", ["hour", "day", "month"].choose(self.rng).unwrap(), self.expr(table, Kind::Time, depth)),
+        }
+    }
+
+    fn predicate(&mut self, table: &Table, depth: usize) -> String {
+        let kind = *[Kind::Int, Kind::Float, Kind::Text].choose(self.rng).unwrap();
+        match self.rng.gen_range(0..6) {
+            0 => format!("{} IS NULL", self.expr(table, kind, depth)),
+            1 => format!("{} IN ({}, {})", self.expr(table, kind, depth), self.literal(kind), self.literal(kind)),
+            2 if kind == Kind::Text => format!("{} LIKE {}", self.expr(table, kind, depth), self.literal(kind)),
+            3 if depth > 0 => format!("({} {} {})", self.predicate(table, depth - 1), ["AND", "OR"].choose(self.rng).unwrap(), self.predicate(table, depth - 1)),
+            4 => format!("NOT ({} BETWEEN {} AND {})", self.expr(table, kind, depth), self.literal(kind), self.literal(kind)),
+            _ => format!("{} {} {}", self.expr(table, kind, depth), ["=", "<>", "<", ">=", "!="].choose(self.rng).unwrap(), self.expr(table, kind, depth)),
+        }
+    }
+
+    fn select(&mut self) -> String {
+        let table = self.table();
+        match self.rng.gen_range(0..10) {
+            0..=4 => {
+                let columns = (0..self.rng.gen_range(1..4))
+                    .map(|i| {
+                        let kind = *[Kind::Int, Kind::Float, Kind::Text].choose(self.rng).unwrap();
+                        format!("{} AS c{}", self.expr(table, kind, 2), i)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let mut sql = format!("SELECT {} FROM {}", columns, table.name);
+                if self.rng.gen_bool(0.6) {
+                    sql += &format!(" WHERE {}", self.predicate(table, 2));
+                }
+                if self.rng.gen_bool(0.4) {
+                    sql += " ORDER BY 1";
+                }
+                if self.rng.gen_bool(0.4) {
+                    sql += &format!(" LIMIT {}", self.rng.gen_range(0..20));
+                }
+                sql
+            }
+            5..=7 => {
+                let key = self.column(table, None).unwrap();
+                let measure = self.column(table, Some(Kind::Int)).unwrap();
+                let aggregate = ["COUNT(*)", "COUNT(DISTINCT {})", "SUM({})", "AVG({})", "MIN({})", "MAX({})", "STDDEV({})"]
+                    .choose(self.rng)
+                    .unwrap()
+                    .replace("{}", measure);
+                let mut sql = format!("SELECT {}, {} AS m FROM {} GROUP BY {}", key, aggregate, table.name, key);
+                if self.rng.gen_bool(0.3) {
+                    sql += " HAVING COUNT(*) > 1";
+                }
+                sql
+            }
+            8 => format!(
+                "SELECT e.id, u.name FROM events e {} JOIN users u ON e.user_id = u.user_id WHERE {} LIMIT 50",
+                ["", "LEFT", "FULL", "INNER"].choose(self.rng).unwrap(),
+                self.predicate(&TABLES[0], 1),
+            ),
+            _ => format!(
+                "WITH t AS (SELECT {} AS v FROM {}) SELECT v, ROW_NUMBER() OVER (ORDER BY v) AS rn FROM t",
+                self.expr(table, Kind::Int, 2),
+                table.name
+            ),
+        }
+    }
+
+    fn aggregate_functions(&mut self) -> String {
+        let table = self.table();
+        let int = self.column(table, Some(Kind::Int)).unwrap();
+        let any = self.column(table, None).unwrap();
+        let calls = [
+            format!("array_element(APPROX_QUANTILES({}, {}), 1)", int, self.rng.gen_range(-1..6)),
+            format!("HLL_COUNT.EXTRACT(HLL_COUNT.INIT({}))", int),
+            format!("HLL_COUNT.MERGE(HLL_COUNT.INIT({}, {}))", any, self.rng.gen_range(8..26)),
+            format!("APPROX_QUANTILES({}, 2)", any),
+            format!("HLL_COUNT.EXTRACT({})", self.literal(Kind::Text)),
+        ];
+        format!("SELECT {} AS v FROM {}", calls.choose(self.rng).unwrap(), table.name)
+    }
+
+    fn table_function(&mut self) -> String {
+        let source = ["TABLE events", "TABLE empty_table", "(SELECT * FROM events WHERE amount > 100)", "TABLE missing"]
+            .choose(self.rng)
+            .unwrap();
+        match self.rng.gen_range(0..4) {
+            0 => format!(
+                "SELECT COUNT(*) AS n FROM SESSIONIZE({}, '{}', '{}', {})",
+                source,
+                ["user_id", "kind", "user_id,kind", "nope"].choose(self.rng).unwrap(),
+                ["ts", "id", "kind"].choose(self.rng).unwrap(),
+                ["INTERVAL 10 MINUTE", "30", "-1", "'x'"].choose(self.rng).unwrap(),
+            ),
+            1 => format!(
+                "SELECT COUNT(*) AS n FROM GAP_FILL({}, ts_column => '{}', bucket_width => {}{})",
+                source,
+                ["ts", "id"].choose(self.rng).unwrap(),
+                ["INTERVAL 5 MINUTE", "INTERVAL 1 DAY", "INTERVAL 0 SECOND", "INTERVAL 1 MONTH"].choose(self.rng).unwrap(),
+                [
+                    "",
+                    ", partitioning_columns => ['user_id']",
+                    ", value_columns => [('amount', 'linear'), ('kind', 'locf')]",
+                    ", value_columns => [('kind', 'linear')]",
+                ]
+                .choose(self.rng)
+                .unwrap(),
+            ),
+            2 => format!(
+                "SELECT COUNT(*) AS n FROM GENERATE_DATE_ARRAY(DATE '2024-01-01', {}{})",
+                ["DATE '2024-03-01'", "DATE '2023-01-01'", "NULL", "'2024-01-10'"].choose(self.rng).unwrap(),
+                ["", ", INTERVAL 1 WEEK", ", INTERVAL -1 DAY", ", INTERVAL 0 DAY", ", INTERVAL 1 HOUR"].choose(self.rng).unwrap(),
+            ),
+            _ => format!(
+                "SELECT COUNT(*) AS n FROM UNNEST(GENERATE_TIMESTAMP_ARRAY(TIMESTAMP '2024-01-01 00:00:00', {}, {}))",
+                ["TIMESTAMP '2024-01-02 00:00:00'", "NULL", "TIMESTAMP '2023-12-31 00:00:00'"].choose(self.rng).unwrap(),
+                ["INTERVAL 1 HOUR", "INTERVAL 1 MONTH", "INTERVAL 0 SECOND", "INTERVAL -30 MINUTE"].choose(self.rng).unwrap(),
+            ),
+        }
+    }
+
+    fn engine_statement(&mut self) -> String {
+        let statements = [
+            "ASSERT (SELECT COUNT(*) FROM events) > 0 AS 'events present'",
+            "ASSERT NULL",
+            "ASSERT (SELECT kind FROM events LIMIT 1)",
+            "CREATE MODEL fuzz_model OPTIONS(model_type='linear_reg', input_label_cols=['score']) AS SELECT user_id, score FROM users WHERE score IS NOT NULL",
+            "CREATE MODEL fuzz_model OPTIONS(model_type='logistic_reg') AS SELECT name FROM users",
+            "SELECT * FROM ML.PREDICT(MODEL fuzz_model, TABLE users)",
+            "DROP MODEL IF EXISTS fuzz_model",
+            "CREATE SEARCH INDEX IF NOT EXISTS fuzz_idx ON events(kind)",
+            "SELECT COUNT(*) AS n FROM events WHERE SEARCH(kind, 'click')",
+            "DROP SEARCH INDEX IF EXISTS fuzz_idx ON events",
+            "SELECT COUNT(*) AS n FROM events FOR SYSTEM_TIME AS OF TIMESTAMP '2000-01-01 00:00:00'",
+            "INSERT INTO empty_table VALUES (1, 'x')",
+        ];
+        statements.choose(self.rng).unwrap().to_string()
+    }
+
+    /// Damage a valid query so it exercises parser and planner errors
+    fn mangle(&mut self, sql: String) -> String {
+        let tokens: Vec<&str> = sql.split(' ').collect();
+        match self.rng.gen_range(0..4) {
+            0 => {
+                let cut = self.rng.gen_range(0..=sql.len());
+                let cut = (0..=cut).rev().find(|i| sql.is_char_boundary(*i)).unwrap_or(0);
+                sql[..cut].to_string()
+            }
+            1 if tokens.len() > 1 => {
+                let mut tokens = tokens;
+                let (a, b) = (self.rng.gen_range(0..tokens.len()), self.rng.gen_range(0..tokens.len()));
+                tokens.swap(a, b);
+                tokens.join(" ")
+            }
+            2 => sql.replacen('(', "", 1),
+            _ => format!("{} {}", sql, ["UNION", "JOIN", ")", "'", "--", ";SELECT 1"].choose(self.rng).unwrap()),
+        }
+    }
+}
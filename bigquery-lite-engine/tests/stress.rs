@@ -0,0 +1,24 @@
+use bigquery_lite_engine::benchmarks::{BenchmarkConfig, BenchmarkSuite, StressConfig};
+use bigquery_lite_engine::BlazeResult;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_engine_survives_stress() -> BlazeResult<()> {
+    let config = BenchmarkConfig {
+        stress: StressConfig {
+            concurrency: 3,
+            rounds: 2,
+            cancellations: 5,
+            registrations: 5,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let report = BenchmarkSuite::new(config).await?.run_stress_test().await?;
+
+    assert!(report.passed, "{:?}", report.problems);
+    assert!(report.memory_limit_errors >= 1);
+    assert_eq!(report.registered_tables, 5);
+    assert!(report.probes > 0);
+
+    Ok(())
+}
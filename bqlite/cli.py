@@ -339,5 +339,36 @@ def list_schemas(
         raise typer.Exit(1)
 
 
+@app.command("generate-data")
+def generate_data(
+    output_path: str = typer.Argument(..., help="Path of the CSV file to write"),
+    rows: int = typer.Option(10000, "--rows", help="Number of rows to generate"),
+    columns: List[str] = typer.Option(
+        ...,
+        "--column",
+        help="Column spec name:type[:key=value,...], e.g. user:string:cardinality=500,distribution=zipf (repeatable)",
+    ),
+    seed: Optional[int] = typer.Option(None, "--seed", help="Seed for reproducible data"),
+):
+    """Generate a synthetic dataset with the Rust engine and write it as CSV.
+
+    Types: sequence, int, float, string, bool, date, timestamp. Options: distribution
+    (uniform, normal, zipf, cyclic), min, max, cardinality, skew, null_fraction, prefix.
+    """
+    try:
+        import bigquery_lite_engine
+    except ImportError:
+        rprint("[red]Error: The bigquery_lite_engine module is not installed (build it with maturin)[/red]")
+        raise typer.Exit(1)
+
+    try:
+        written = bigquery_lite_engine.generate_csv(output_path, rows, columns, seed)
+    except (ValueError, TypeError, OSError, RuntimeError) as e:
+        rprint(f"[red]Error: {e}[/red]")
+        raise typer.Exit(1)
+
+    rprint(f"[green]✅ Wrote {written} rows to {output_path}[/green]")
+
+
 if __name__ == "__main__":
     app()
\ No newline at end of file